@@ -33,7 +33,7 @@ use runtime_primitives::traits::{
 	Block as BlockT, Hash as HashT, Header as HeaderT, ProvideRuntimeApi, AuthorityIdFor
 };
 use runtime_primitives::generic::BlockId;
-use transaction_pool::txpool::{self, Pool as TransactionPool};
+use txpool_api::{TransactionPool, InPoolTransaction};
 use inherents::InherentData;
 
 // block size limit.
@@ -110,17 +110,17 @@ impl<B, E, Block, RA> AuthoringApi for SubstrateClient<B, E, Block, RA> where
 }
 
 /// Proposer factory.
-pub struct ProposerFactory<C, A> where A: txpool::ChainApi {
+pub struct ProposerFactory<C, A> where A: TransactionPool {
 	/// The client instance.
 	pub client: Arc<C>,
 	/// The transaction pool.
-	pub transaction_pool: Arc<TransactionPool<A>>,
+	pub transaction_pool: Arc<A>,
 }
 
 impl<C, A> consensus_common::Environment<<C as AuthoringApi>::Block> for ProposerFactory<C, A> where
 	C: AuthoringApi,
 	<C as ProvideRuntimeApi>::Api: BlockBuilderApi<<C as AuthoringApi>::Block>,
-	A: txpool::ChainApi<Block=<C as AuthoringApi>::Block>,
+	A: TransactionPool<Block=<C as AuthoringApi>::Block>,
 	client::error::Error: From<<C as AuthoringApi>::Error>,
 	Proposer<<C as AuthoringApi>::Block, C, A>: consensus_common::Proposer<<C as AuthoringApi>::Block>,
 {
@@ -151,19 +151,19 @@ impl<C, A> consensus_common::Environment<<C as AuthoringApi>::Block> for Propose
 }
 
 /// The proposer logic.
-pub struct Proposer<Block: BlockT, C, A: txpool::ChainApi> {
+pub struct Proposer<Block: BlockT, C, A: TransactionPool> {
 	client: Arc<C>,
 	parent_hash: <Block as BlockT>::Hash,
 	parent_id: BlockId<Block>,
 	parent_number: <<Block as BlockT>::Header as HeaderT>::Number,
-	transaction_pool: Arc<TransactionPool<A>>,
+	transaction_pool: Arc<A>,
 }
 
 impl<Block, C, A> consensus_common::Proposer<<C as AuthoringApi>::Block> for Proposer<Block, C, A> where
 	Block: BlockT,
 	C: AuthoringApi<Block=Block>,
 	<C as ProvideRuntimeApi>::Api: BlockBuilderApi<Block>,
-	A: txpool::ChainApi<Block=Block>,
+	A: TransactionPool<Block=Block>,
 	client::error::Error: From<<C as AuthoringApi>::Error>
 {
 	type Create = Result<<C as AuthoringApi>::Block, error::Error>;
@@ -180,7 +180,7 @@ impl<Block, C, A> Proposer<Block, C, A>	where
 	Block: BlockT,
 	C: AuthoringApi<Block=Block>,
 	<C as ProvideRuntimeApi>::Api: BlockBuilderApi<Block>,
-	A: txpool::ChainApi<Block=Block>,
+	A: TransactionPool<Block=Block>,
 	client::error::Error: From<<C as AuthoringApi>::Error>,
 {
 	fn propose_with(&self, inherent_data: InherentData)
@@ -197,16 +197,16 @@ impl<Block, C, A> Proposer<Block, C, A>	where
 				let pending_iterator = self.transaction_pool.ready();
 
 				for pending in pending_iterator {
-					let encoded_size = pending.data.encode().len();
+					let encoded_size = pending.data().encode().len();
 					if pending_size + encoded_size >= MAX_TRANSACTIONS_SIZE { break }
 
-					match block_builder.push_extrinsic(pending.data.clone()) {
+					match block_builder.push_extrinsic(pending.data().clone()) {
 						Ok(()) => {
 							pending_size += encoded_size;
 						}
 						Err(e) => {
 							trace!(target: "transaction-pool", "Invalid transaction: {}", e);
-							unqueue_invalid.push(pending.hash.clone());
+							unqueue_invalid.push(pending.hash().clone());
 						}
 					}
 				}
@@ -44,6 +44,8 @@ pub fn start<C>(service: &Service<C>, exit: ::exit_future::Exit, handle: TaskExe
 	let client = service.client();
 	let txpool = service.transaction_pool();
 	let mut last_number = None;
+	let mut last_txpool_metrics = txpool.metrics();
+	let mut last_chain_freeze_rotations = 0;
 
 	let mut sys = System::new();
 	let self_pid = get_current_pid();
@@ -61,6 +63,7 @@ pub fn start<C>(service: &Service<C>, exit: ::exit_future::Exit, handle: TaskExe
 				(SyncState::Downloading, None) => (format!("Syncing{}", speed()), "".into()),
 				(SyncState::Downloading, Some(n)) => (format!("Syncing{}", speed()), format!(", target=#{}", n)),
 			};
+			let stalled = sync_status.sync.is_major_syncing() && last_number == Some(best_number);
 			last_number = Some(best_number);
 			let txpool_status = txpool.status();
 			let finalized_number: u64 = info.chain.finalized_number.as_();
@@ -80,6 +83,22 @@ pub fn start<C>(service: &Service<C>, exit: ::exit_future::Exit, handle: TaskExe
 				TransferRateFormat(bandwidth_upload),
 			);
 
+			// explain what the sync is waiting for when no progress is made.
+			if stalled {
+				let download = network.download_stats();
+				let requested_from = download.downloading.iter()
+					.find(|(range, _)| range.start <= download.first_missing && download.first_missing < range.end)
+					.map_or(0, |(_, peers)| peers.len());
+				info!(
+					target: "substrate",
+					"Waiting for block #{} (requested from {} peers), {} blocks queued, {} ranges downloading",
+					download.first_missing,
+					requested_from,
+					download.queued_blocks,
+					download.downloading.len(),
+				);
+			}
+
 			// get cpu usage and memory usage of this process
 			let (cpu_usage, memory) = if sys.refresh_process(self_pid) {
 				let proc = sys.get_process(self_pid).expect("Above refresh_process succeeds, this should be Some(), qed");
@@ -100,6 +119,46 @@ pub fn start<C>(service: &Service<C>, exit: ::exit_future::Exit, handle: TaskExe
 				"bandwidth_download" => bandwidth_download,
 				"bandwidth_upload" => bandwidth_upload,
 			);
+
+			let txpool_metrics = txpool.metrics();
+			let rate = |now: u64, last: u64| now.saturating_sub(last) as f64 * 1000.0 / TIMER_INTERVAL_MS as f64;
+			telemetry!(
+				"txpool.metrics";
+				"height" => best_number,
+				"ready" => txpool_status.ready,
+				"ready_bytes" => txpool_status.ready_bytes,
+				"future" => txpool_status.future,
+				"future_bytes" => txpool_status.future_bytes,
+				"submitted_per_sec" => rate(txpool_metrics.submitted, last_txpool_metrics.submitted),
+				"validated_per_sec" => rate(txpool_metrics.validated, last_txpool_metrics.validated),
+				"invalid_per_sec" => rate(txpool_metrics.invalid, last_txpool_metrics.invalid),
+				"pruned_per_sec" => rate(txpool_metrics.pruned, last_txpool_metrics.pruned),
+				"banned" => txpool_metrics.banned,
+			);
+			last_txpool_metrics = txpool_metrics;
+
+			if sync_status.chain_freeze_rotations > last_chain_freeze_rotations {
+				telemetry!(
+					"sync.chain_freeze";
+					"height" => best_number,
+					"best_seen" => ?sync_status.sync.best_seen_block,
+					"peers" => num_peers,
+					"rotated_peers" => sync_status.chain_freeze_rotations - last_chain_freeze_rotations,
+				);
+			}
+			last_chain_freeze_rotations = sync_status.chain_freeze_rotations;
+
+			let connections = network.connection_metrics();
+			telemetry!(
+				"network.connections";
+				"open" => connections.open_connections,
+				"pending_dials" => connections.pending_dials,
+				"dial_failures" => ?connections.dial_failures,
+				"established" => connections.established,
+				"failed" => connections.failed,
+				"handshake_avg_ms" => millis(connections.handshake_time_average()),
+				"handshake_max_ms" => millis(connections.handshake_time_max),
+			);
 		} else {
 			warn!("Error getting best block information");
 		}
@@ -153,6 +212,10 @@ pub fn start<C>(service: &Service<C>, exit: ::exit_future::Exit, handle: TaskExe
 	handle.spawn(exit.until(informant_work).map(|_| ()));
 }
 
+fn millis(duration: Duration) -> u64 {
+	duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
 fn speed(best_number: u64, last_number: Option<u64>) -> String {
 	let speed = match last_number {
 		Some(num) => (best_number.saturating_sub(num) * 10_000 / TIMER_INTERVAL_MS) as f64,
@@ -32,14 +32,14 @@ use service::{
 	FactoryGenesis, PruningMode, ChainSpec,
 };
 use network::{
-	Protocol, config::{NetworkConfiguration, NonReservedPeerMode, Secret},
+	Protocol, config::{NetworkConfiguration, NonReservedPeerMode, Secret, TlsCertificate},
 	multiaddr,
 };
 use primitives::H256;
 
 use std::{
-	io::{Write, Read, stdin, stdout}, iter, fs::{self, File}, net::{Ipv4Addr, SocketAddr},
-	path::{Path, PathBuf}, str::FromStr,
+	cmp, io::{Write, Read, stdin, stdout}, iter, fs::{self, File}, net::{Ipv4Addr, SocketAddr},
+	path::{Path, PathBuf}, str::FromStr, time::Duration,
 };
 
 use names::{Generator, Name};
@@ -48,7 +48,7 @@ use structopt::{StructOpt, clap::AppSettings};
 #[doc(hidden)]
 pub use structopt::clap::App;
 use params::{
-	RunCmd, PurgeChainCmd, RevertCmd, ImportBlocksCmd, ExportBlocksCmd, BuildSpecCmd,
+	RunCmd, PurgeChainCmd, RevertCmd, ImportBlocksCmd, ExportBlocksCmd, BuildSpecCmd, VerifyDbCmd,
 	NetworkConfigurationParams, SharedParams, MergeParameters
 };
 pub use params::{NoCustom, CoreParams};
@@ -61,6 +61,10 @@ use lazy_static::lazy_static;
 use futures::Future;
 
 const MAX_NODE_NAME_LENGTH: usize = 32;
+/// Environment variable the password of the archive given with `--wss-pkcs12` is read from,
+/// unless a password file is given. Passwords aren't taken as arguments, which other users
+/// of the host can see.
+const WSS_PKCS12_PASSWORD_ENV: &str = "WSS_PKCS12_PASSWORD";
 
 /// Executable version. Used to pass version information from the root crate.
 pub struct VersionInfo {
@@ -224,10 +228,22 @@ where
 			purge_chain::<F, _>(params, spec_factory, version).map(|_| None),
 		params::CoreParams::Revert(params) =>
 			revert_chain::<F, _>(params, spec_factory, version).map(|_| None),
+		params::CoreParams::VerifyDb(params) =>
+			verify_db::<F, _, _>(params, spec_factory, exit, version).map(|_| None),
 		params::CoreParams::Custom(params) => Ok(Some(params)),
 	}
 }
 
+fn parse_pruning_mode(pruning: Option<String>) -> error::Result<PruningMode> {
+	Ok(match pruning {
+		Some(ref s) if s == "archive" => PruningMode::ArchiveAll,
+		None => PruningMode::default(),
+		Some(s) => PruningMode::keep_blocks(
+			s.parse().map_err(|_| create_input_err("Invalid pruning mode specified"))?
+		),
+	})
+}
+
 fn parse_node_key(key: Option<String>) -> error::Result<Option<Secret>> {
 	match key.map(|k| H256::from_str(&k)) {
 		Some(Ok(secret)) => Ok(Some(secret.into())),
@@ -236,17 +252,28 @@ fn parse_node_key(key: Option<String>) -> error::Result<Option<Secret>> {
 	}
 }
 
+/// Reads the password of the TLS certificate archive from `file`, or else from the environment.
+/// The password is empty if neither is set.
+fn wss_pkcs12_password(file: Option<String>) -> error::Result<String> {
+	match file {
+		Some(path) => fs::read_to_string(&path)
+			.map(|password| password.trim_end_matches(&['\r', '\n'][..]).to_owned())
+			.map_err(|err| create_input_err(format!("Error reading the password file {}: {}", path, err))),
+		None => Ok(std::env::var(WSS_PKCS12_PASSWORD_ENV).unwrap_or_default()),
+	}
+}
+
 /// Fill the given `NetworkConfiguration` by looking at the cli parameters.
 fn fill_network_configuration(
 	cli: NetworkConfigurationParams,
-	base_path: &Path,
+	base_path: Option<&Path>,
 	chain_spec_id: &str,
 	config: &mut NetworkConfiguration,
 	client_id: String,
 ) -> error::Result<()> {
 	config.boot_nodes.extend(cli.bootnodes.into_iter());
-	config.config_path = Some(
-		network_path(&base_path, chain_spec_id).to_string_lossy().into()
+	config.config_path = base_path.map(|base_path|
+		network_path(base_path, chain_spec_id).to_string_lossy().into()
 	);
 	config.net_config_path = config.config_path.clone();
 	config.reserved_nodes.extend(cli.reserved_nodes.into_iter());
@@ -260,9 +287,11 @@ fn fill_network_configuration(
 	}
 
 	if config.listen_addresses.is_empty() {
-		let port = match cli.port {
-			Some(port) => port,
-			None => 30333,
+		let port = match (cli.port, base_path) {
+			(Some(port), _) => port,
+			(None, Some(_)) => 30333,
+			// temporary nodes let the OS pick a free port
+			(None, None) => 0,
 		};
 
 		config.listen_addresses = vec![
@@ -272,14 +301,46 @@ fn fill_network_configuration(
 		];
 	}
 
+	if let Some(pkcs12_path) = cli.wss_pkcs12 {
+		config.wss_certificate = Some(TlsCertificate {
+			pkcs12_path,
+			password: wss_pkcs12_password(cli.wss_pkcs12_password_file)?,
+		});
+	}
+
+	if let Some(ws_port) = cli.ws_port {
+		// pages served over HTTPS can only open WebSockets over TLS.
+		let ws = if config.wss_certificate.is_some() { Protocol::Wss } else { Protocol::Ws };
+		config.listen_addresses.push(
+			iter::once(Protocol::Ip4(Ipv4Addr::new(0, 0, 0, 0)))
+				.chain(iter::once(Protocol::Tcp(ws_port)))
+				.chain(iter::once(ws))
+				.collect()
+		);
+	}
+
+	config.pre_shared_key_path = cli.psk_file;
+
 	config.public_addresses = Vec::new();
+	config.enable_port_mapping = cli.enable_port_mapping;
+	config.enable_mdns = cli.enable_mdns;
 
 	config.client_version = client_id;
 	config.use_secret = parse_node_key(cli.node_key)?;
+	config.node_key_type = cli.node_key_type.into();
 
 	config.in_peers = cli.in_peers;
 	config.out_peers = cli.out_peers;
 
+	config.multiplexer.multiplexer = cli.multiplexer.into();
+	if let Some(window) = cli.yamux_receive_window {
+		config.multiplexer.yamux_receive_window = window;
+	}
+	config.max_connections_per_ip = cli.max_peers_per_ip;
+	config.max_connections_per_subnet = cli.max_peers_per_subnet;
+	config.allowed_ips.extend(cli.allow_ip.into_iter());
+	config.denied_ips.extend(cli.deny_ip.into_iter());
+
 	Ok(())
 }
 
@@ -323,13 +384,8 @@ where
 	config.database_path =
 		db_path(&base_path, config.chain_spec.id()).to_string_lossy().into();
 	config.database_cache_size = cli.database_cache_size;
-	config.pruning = match cli.pruning {
-		Some(ref s) if s == "archive" => PruningMode::ArchiveAll,
-		None => PruningMode::default(),
-		Some(s) => PruningMode::keep_blocks(
-			s.parse().map_err(|_| create_input_err("Invalid pruning mode specified"))?
-		),
-	};
+	config.in_memory = cli.tmp;
+	config.pruning = parse_pruning_mode(cli.pruning)?;
 
 	let role =
 		if cli.light {
@@ -349,7 +405,7 @@ where
 	let client_id = config.client_id();
 	fill_network_configuration(
 		cli.network_config,
-		&base_path,
+		if cli.tmp { None } else { Some(&base_path) },
 		spec.id(),
 		&mut config.network,
 		client_id,
@@ -372,6 +428,27 @@ where
 	config.rpc_ws = Some(
 		parse_address(&format!("{}:{}", ws_interface, 9944), cli.ws_port)?
 	);
+	config.rpc_unsafe = cli.rpc_unsafe;
+	config.chain_freeze_timeout = cli.chain_freeze_timeout.map(Duration::from_secs);
+	config.justifications_only = cli.justifications_only;
+	config.light_announcement = cli.light_announcement.into();
+	config.cross_check_blocks = cli.cross_check_blocks;
+	if let Some(max_ranges) = cli.max_download_ranges {
+		config.download_window.max_ranges = max_ranges;
+		config.download_window.min_ranges = cmp::min(config.download_window.min_ranges, max_ranges);
+	}
+	if let Some(max_requests) = cli.block_request_quota {
+		config.block_request_quota.max_requests = max_requests;
+	}
+	if let Some(max_messages) = cli.gossip_quota {
+		config.gossip_quota.max_messages = max_messages;
+	}
+	if let Some(max_kib) = cli.transaction_quota {
+		config.transaction_quota.max_bytes = max_kib * 1024;
+	}
+	if let Some(workers) = cli.verification_workers {
+		config.verification_workers = workers;
+	}
 
 	// Override telemetry
 	if cli.no_telemetry {
@@ -425,7 +502,7 @@ where
 		let network_key = parse_node_key(cli.node_key.clone())?;
 
 		let network_keys =
-			network::obtain_private_key(&network_key, &network_path)
+			network::obtain_private_key(&network_key, &network_path, cli.node_key_type.into())
 				.map_err(|err| format!("Error obtaining network key: {}", err))?;
 
 		let peer_id = network_keys.to_peer_id();
@@ -540,6 +617,26 @@ where
 	Ok(service::chain_ops::revert_chain::<F>(config, As::sa(blocks))?)
 }
 
+fn verify_db<F, E, S>(
+	cli: VerifyDbCmd,
+	spec_factory: S,
+	exit: E,
+	version: &VersionInfo,
+) -> error::Result<()>
+where
+	F: ServiceFactory,
+	E: IntoExit,
+	S: FnOnce(&str) -> Result<Option<ChainSpec<FactoryGenesis<F>>>, String>,
+{
+	let mut config = create_config_with_db_path::<F, _>(spec_factory, &cli.shared_params, version)?;
+	config.pruning = parse_pruning_mode(cli.pruning)?;
+
+	info!("DB path: {}", config.database_path);
+	service::chain_ops::verify_chain::<F, _>(
+		config, exit.into_exit(), cli.last.map(As::sa), cli.repair
+	).map_err(Into::into)
+}
+
 fn purge_chain<F, S>(
 	cli: PurgeChainCmd,
 	spec_factory: S,
@@ -686,4 +783,20 @@ mod tests {
 		assert!(is_node_name_valid("www.visit.me").is_err());
 		assert!(is_node_name_valid("email@domain").is_err());
 	}
+
+	#[test]
+	fn wss_pkcs12_password_is_read_from_a_file_or_the_environment() {
+		let path = std::env::temp_dir().join(format!("wss-password-{}", std::process::id()));
+		fs::write(&path, "secret\n").unwrap();
+		let password = wss_pkcs12_password(Some(path.to_string_lossy().into()));
+		fs::remove_file(&path).unwrap();
+		assert_eq!(password.unwrap(), "secret");
+
+		assert!(wss_pkcs12_password(Some(path.to_string_lossy().into())).is_err());
+
+		std::env::set_var(WSS_PKCS12_PASSWORD_ENV, "from env");
+		assert_eq!(wss_pkcs12_password(None).unwrap(), "from env");
+		std::env::remove_var(WSS_PKCS12_PASSWORD_ENV);
+		assert_eq!(wss_pkcs12_password(None).unwrap(), "");
+	}
 }
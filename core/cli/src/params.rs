@@ -51,6 +51,64 @@ impl Into<client::ExecutionStrategy> for ExecutionStrategy {
 	}
 }
 
+arg_enum! {
+	/// Blocks announced to light peers
+	#[derive(Debug, Clone, Copy)]
+	pub enum LightAnnouncement {
+		All,
+		Best,
+		Finalized,
+	}
+}
+
+impl Into<network::config::LightAnnouncement> for LightAnnouncement {
+	fn into(self) -> network::config::LightAnnouncement {
+		match self {
+			LightAnnouncement::All => network::config::LightAnnouncement::All,
+			LightAnnouncement::Best => network::config::LightAnnouncement::Best,
+			LightAnnouncement::Finalized => network::config::LightAnnouncement::Finalized,
+		}
+	}
+}
+
+arg_enum! {
+	/// Type of the node key
+	#[derive(Debug, Clone, Copy)]
+	pub enum NodeKeyType {
+		Secp256k1,
+		Ed25519,
+	}
+}
+
+impl Into<network::config::NodeKeyType> for NodeKeyType {
+	fn into(self) -> network::config::NodeKeyType {
+		match self {
+			NodeKeyType::Secp256k1 => network::config::NodeKeyType::Secp256k1,
+			NodeKeyType::Ed25519 => network::config::NodeKeyType::Ed25519,
+		}
+	}
+}
+
+arg_enum! {
+	/// Stream multiplexers to negotiate
+	#[derive(Debug, Clone, Copy)]
+	pub enum Multiplexer {
+		YamuxPreferred,
+		Yamux,
+		Mplex,
+	}
+}
+
+impl Into<network::config::Multiplexer> for Multiplexer {
+	fn into(self) -> network::config::Multiplexer {
+		match self {
+			Multiplexer::YamuxPreferred => network::config::Multiplexer::YamuxPreferred,
+			Multiplexer::Yamux => network::config::Multiplexer::Yamux,
+			Multiplexer::Mplex => network::config::Multiplexer::Mplex,
+		}
+	}
+}
+
 /// Shared parameters used by all `CoreParams`.
 #[derive(Debug, StructOpt, Clone)]
 pub struct SharedParams {
@@ -100,6 +158,43 @@ pub struct NetworkConfigurationParams {
 	#[structopt(long = "node-key", value_name = "KEY")]
 	pub node_key: Option<String>,
 
+	/// Type of the node secret key, whether specified or generated. Switching types changes
+	/// the identity of the node; the previous key is kept and restored when switching back.
+	#[structopt(
+		long = "node-key-type",
+		value_name = "TYPE",
+		raw(
+			possible_values = "&NodeKeyType::variants()",
+			case_insensitive = "true",
+			default_value = r#""Secp256k1""#
+		)
+	)]
+	pub node_key_type: NodeKeyType,
+
+	/// Also listen for WebSocket connections on this port, e.g. from in-browser light clients
+	#[structopt(long = "ws-port", value_name = "PORT")]
+	pub ws_port: Option<u16>,
+
+	/// Accept WebSocket connections over TLS with this certificate and private key, bundled in a PKCS #12 archive
+	#[structopt(long = "wss-pkcs12", value_name = "PATH")]
+	pub wss_pkcs12: Option<String>,
+
+	/// Read the password of the archive given with --wss-pkcs12 from this file. Otherwise it is read from the WSS_PKCS12_PASSWORD environment variable, if set
+	#[structopt(long = "wss-pkcs12-password-file", value_name = "PATH")]
+	pub wss_pkcs12_password_file: Option<String>,
+
+	/// Only connect to the nodes of the private network whose pre-shared key is in this file
+	#[structopt(long = "psk-file", value_name = "PATH")]
+	pub psk_file: Option<String>,
+
+	/// Map the p2p port on the local router through UPnP or NAT-PMP, to accept incoming connections from behind a NAT
+	#[structopt(long = "enable-port-mapping")]
+	pub enable_port_mapping: bool,
+
+	/// Discover the nodes of the local network through mDNS, to form local test networks without bootnodes
+	#[structopt(long = "enable-mdns")]
+	pub enable_mdns: bool,
+
 	/// Specify the number of outgoing connections we're trying to maintain
 	#[structopt(long = "out-peers", value_name = "OUT_PEERS", default_value = "25")]
 	pub out_peers: u32,
@@ -107,6 +202,38 @@ pub struct NetworkConfigurationParams {
 	/// Specify the maximum number of incoming connections we're accepting
 	#[structopt(long = "in-peers", value_name = "IN_PEERS", default_value = "25")]
 	pub in_peers: u32,
+
+	/// Stream multiplexers to negotiate with other nodes. Can be either yamuxpreferred, yamux or mplex.
+	#[structopt(
+		long = "multiplexer",
+		value_name = "MULTIPLEXER",
+		raw(
+			possible_values = "&Multiplexer::variants()",
+			case_insensitive = "true",
+			default_value = r#""YamuxPreferred""#
+		)
+	)]
+	pub multiplexer: Multiplexer,
+
+	/// Size in bytes of the yamux receive window of each substream. Larger windows speed up the sync over links with a high latency
+	#[structopt(long = "yamux-receive-window", value_name = "BYTES")]
+	pub yamux_receive_window: Option<u32>,
+
+	/// Specify the maximum number of connections with the same IP address
+	#[structopt(long = "max-peers-per-ip", value_name = "COUNT", default_value = "4")]
+	pub max_peers_per_ip: u32,
+
+	/// Specify the maximum number of connections with the same /24 (IPv4) or /48 (IPv6) subnet. Private networks aren't limited
+	#[structopt(long = "max-peers-per-subnet", value_name = "COUNT", default_value = "8")]
+	pub max_peers_per_subnet: u32,
+
+	/// Only allow connections with this IP network, in CIDR notation (e.g. 10.0.0.0/8)
+	#[structopt(long = "allow-ip", value_name = "CIDR")]
+	pub allow_ip: Vec<network::config::IpNetwork>,
+
+	/// Refuse connections with this IP network, in CIDR notation (e.g. 10.0.0.0/8)
+	#[structopt(long = "deny-ip", value_name = "CIDR")]
+	pub deny_ip: Vec<network::config::IpNetwork>,
 }
 
 /// The `run` command used to run a node.
@@ -128,6 +255,11 @@ pub struct RunCmd {
 	#[structopt(long = "light")]
 	pub light: bool,
 
+	/// Run a temporary node: the database and keystore are kept in memory and the p2p port
+	/// is chosen by the OS unless `--port` is given. Nothing is written to disk.
+	#[structopt(long = "tmp")]
+	pub tmp: bool,
+
 	/// Limit the memory the database cache can use
 	#[structopt(long = "db-cache", value_name = "MiB")]
 	pub database_cache_size: Option<u32>,
@@ -140,6 +272,10 @@ pub struct RunCmd {
 	#[structopt(long = "ws-external")]
 	pub ws_external: bool,
 
+	/// Enable RPC methods that are unsafe to expose publicly, like `author_submitBlock`
+	#[structopt(long = "rpc-unsafe")]
+	pub rpc_unsafe: bool,
+
 	/// Specify HTTP RPC server TCP port
 	#[structopt(long = "rpc-port", value_name = "PORT")]
 	pub rpc_port: Option<u16>,
@@ -148,6 +284,50 @@ pub struct RunCmd {
 	#[structopt(long = "ws-port", value_name = "PORT")]
 	pub ws_port: Option<u16>,
 
+	/// Replace a quarter of the peers, the least reputable first, when the chain doesn't progress for this many seconds
+	#[structopt(long = "chain-freeze-timeout", value_name = "SECONDS")]
+	pub chain_freeze_timeout: Option<u64>,
+
+	/// Which imported blocks are announced to light peers. Can be either all, best or finalized.
+	#[structopt(
+		long = "light-announcement",
+		value_name = "BLOCKS",
+		raw(
+			possible_values = "&LightAnnouncement::variants()",
+			case_insensitive = "true",
+			default_value = r#""All""#
+		)
+	)]
+	pub light_announcement: LightAnnouncement,
+
+	/// Never download blocks, only fetch the missing justifications of the blocks in the database
+	#[structopt(long = "justifications-only")]
+	pub justifications_only: bool,
+
+	/// Download each block range from two peers and compare the blocks before importing them
+	#[structopt(long = "cross-check-blocks")]
+	pub cross_check_blocks: bool,
+
+	/// Maximum number of block ranges downloaded ahead of the imported chain
+	#[structopt(long = "max-download-ranges", value_name = "COUNT")]
+	pub max_download_ranges: Option<usize>,
+
+	/// Maximum number of block requests served to a single peer every 10 seconds
+	#[structopt(long = "block-request-quota", value_name = "COUNT")]
+	pub block_request_quota: Option<u32>,
+
+	/// Maximum number of gossip messages handled from a single peer every second
+	#[structopt(long = "gossip-quota", value_name = "COUNT")]
+	pub gossip_quota: Option<u32>,
+
+	/// Maximum size of the extrinsics sent to a single peer every 5 seconds
+	#[structopt(long = "transaction-quota", value_name = "KiB")]
+	pub transaction_quota: Option<usize>,
+
+	/// Number of threads verifying blocks ahead of their import. Blocks are verified on the import thread if below 2
+	#[structopt(long = "verification-workers", value_name = "COUNT")]
+	pub verification_workers: Option<usize>,
+
 	/// Specify the pruning mode, a number of blocks to keep or 'archive'. Default is 256.
 	#[structopt(long = "pruning", value_name = "PRUNING_MODE")]
 	pub pruning: Option<String>,
@@ -202,6 +382,19 @@ pub struct BuildSpecCmd {
 	/// Specify node secret key (64-character hex string)
 	#[structopt(long = "node-key", value_name = "KEY")]
 	pub node_key: Option<String>,
+
+	/// Type of the node secret key, whether specified or generated. Switching types changes
+	/// the identity of the node; the previous key is kept and restored when switching back.
+	#[structopt(
+		long = "node-key-type",
+		value_name = "TYPE",
+		raw(
+			possible_values = "&NodeKeyType::variants()",
+			case_insensitive = "true",
+			default_value = r#""Secp256k1""#
+		)
+	)]
+	pub node_key_type: NodeKeyType,
 }
 
 impl_get_log_filter!(BuildSpecCmd);
@@ -298,6 +491,29 @@ pub struct PurgeChainCmd {
 
 impl_get_log_filter!(PurgeChainCmd);
 
+/// The `verify-db` command used to check the consistency of the stored chain.
+#[derive(Debug, StructOpt, Clone)]
+pub struct VerifyDbCmd {
+	/// Only verify the last given number of blocks. The whole chain by default.
+	#[structopt(long = "last", value_name = "COUNT")]
+	pub last: Option<u64>,
+
+	/// Revert unfinalized blocks from the first inconsistent block onwards.
+	#[structopt(long = "repair")]
+	pub repair: bool,
+
+	/// The pruning mode the database was created with, a number of blocks to keep or 'archive'.
+	/// Default is 256.
+	#[structopt(long = "pruning", value_name = "PRUNING_MODE")]
+	pub pruning: Option<String>,
+
+	#[allow(missing_docs)]
+	#[structopt(flatten)]
+	pub shared_params: SharedParams,
+}
+
+impl_get_log_filter!(VerifyDbCmd);
+
 /// All core commands that are provided by default.
 ///
 /// The core commands are split into multiple subcommands and `Run` is the default subcommand. From
@@ -323,6 +539,9 @@ pub enum CoreParams<CC, RP> {
 	/// Remove the whole chain data.
 	PurgeChain(PurgeChainCmd),
 
+	/// Verify the consistency of the chain data.
+	VerifyDb(VerifyDbCmd),
+
 	/// Further custom subcommands.
 	Custom(CC),
 }
@@ -356,6 +575,10 @@ impl<CC, RP> StructOpt for CoreParams<CC, RP> where
 			PurgeChainCmd::augment_clap(SubCommand::with_name("purge-chain"))
 				.about("Remove the whole chain data.")
 		)
+		.subcommand(
+			VerifyDbCmd::augment_clap(SubCommand::with_name("verify-db"))
+				.about("Verify the consistency of the chain data.")
+		)
 	}
 
 	fn from_clap(matches: &::structopt::clap::ArgMatches) -> Self {
@@ -369,6 +592,7 @@ impl<CC, RP> StructOpt for CoreParams<CC, RP> where
 			("revert", Some(matches)) => CoreParams::Revert(RevertCmd::from_clap(matches)),
 			("purge-chain", Some(matches)) =>
 				CoreParams::PurgeChain(PurgeChainCmd::from_clap(matches)),
+			("verify-db", Some(matches)) => CoreParams::VerifyDb(VerifyDbCmd::from_clap(matches)),
 			(_, None) => CoreParams::Run(MergeParameters::from_clap(matches)),
 			_ => CoreParams::Custom(CC::from_clap(matches)),
 		}
@@ -384,6 +608,7 @@ impl<CC, RP> GetLogFilter for CoreParams<CC, RP> where CC: GetLogFilter {
 			CoreParams::ImportBlocks(c) => c.get_log_filter(),
 			CoreParams::PurgeChain(c) => c.get_log_filter(),
 			CoreParams::Revert(c) => c.get_log_filter(),
+			CoreParams::VerifyDb(c) => c.get_log_filter(),
 			CoreParams::Custom(c) => c.get_log_filter(),
 		}
 	}
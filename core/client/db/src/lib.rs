@@ -67,8 +67,8 @@ pub type DbState = state_machine::TrieBackend<Arc<state_machine::Storage<Blake2H
 pub struct DatabaseSettings {
 	/// Cache size in bytes. If `None` default is used.
 	pub cache_size: Option<usize>,
-	/// Path to the database.
-	pub path: PathBuf,
+	/// Path to the database. `None` keeps the database purely in memory.
+	pub path: Option<PathBuf>,
 	/// Pruning mode.
 	pub pruning: PruningMode,
 }
@@ -170,6 +170,35 @@ impl<Block: BlockT> client::blockchain::HeaderBackend<Block> for BlockchainDb<Bl
 		utils::read_header(&*self.db, columns::KEY_LOOKUP, columns::HEADER, id)
 	}
 
+	fn header_ancestry(&self, hash: Block::Hash, max: usize) -> Result<Vec<Block::Header>, client::error::Error> {
+		let mut headers = Vec::new();
+		// only the first header is looked up by hash, the lookup keys of its
+		// ancestors are derived from the child's number and parent hash.
+		let mut lookup_key = match block_id_to_lookup_key::<Block>(&*self.db, columns::KEY_LOOKUP, BlockId::Hash(hash))? {
+			Some(key) => key,
+			None => return Ok(headers),
+		};
+		while headers.len() < max {
+			let header = match self.db.get(columns::HEADER, &lookup_key).map_err(db_err)? {
+				Some(header) => match Block::Header::decode(&mut &header[..]) {
+					Some(header) => header,
+					None => return Err(
+						client::error::ErrorKind::Backend("Error decoding header".into()).into()
+					),
+				},
+				None => break,
+			};
+			let number = *header.number();
+			if number.is_zero() {
+				headers.push(header);
+				break;
+			}
+			lookup_key = utils::number_and_hash_to_lookup_key(number - As::sa(1), header.parent_hash());
+			headers.push(header);
+		}
+		Ok(headers)
+	}
+
 	fn info(&self) -> Result<client::blockchain::Info<Block>, client::error::Error> {
 		let meta = self.meta.read();
 		Ok(client::blockchain::Info {
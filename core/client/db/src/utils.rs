@@ -188,12 +188,17 @@ pub fn db_err(err: io::Error) -> client::error::Error {
 	client::error::ErrorKind::Backend(err.description().into()).into()
 }
 
-/// Open RocksDB database.
+/// Open RocksDB database, or an in-memory one if no path is configured.
 pub fn open_database(config: &DatabaseSettings, col_meta: Option<u32>, db_type: &str) -> client::error::Result<Arc<KeyValueDB>> {
-	let mut db_config = DatabaseConfig::with_columns(Some(NUM_COLUMNS));
-	db_config.memory_budget = config.cache_size;
-	let path = config.path.to_str().ok_or_else(|| client::error::ErrorKind::Backend("Invalid database path".into()))?;
-	let db = Database::open(&db_config, &path).map_err(db_err)?;
+	let db: Arc<KeyValueDB> = match config.path {
+		Some(ref path) => {
+			let mut db_config = DatabaseConfig::with_columns(Some(NUM_COLUMNS));
+			db_config.memory_budget = config.cache_size;
+			let path = path.to_str().ok_or_else(|| client::error::ErrorKind::Backend("Invalid database path".into()))?;
+			Arc::new(Database::open(&db_config, &path).map_err(db_err)?)
+		},
+		None => Arc::new(kvdb_memorydb::create(NUM_COLUMNS)),
+	};
 
 	// check database type
 	match db.get(col_meta, meta_keys::TYPE).map_err(db_err)? {
@@ -210,7 +215,7 @@ pub fn open_database(config: &DatabaseSettings, col_meta: Option<u32>, db_type:
 		},
 	}
 
-	Ok(Arc::new(db))
+	Ok(db)
 }
 
 /// Read database column entry for the given block.
@@ -16,7 +16,7 @@
 
 //! Substrate blockchain trait
 
-use runtime_primitives::traits::{AuthorityIdFor, Block as BlockT, Header as HeaderT, NumberFor};
+use runtime_primitives::traits::{AuthorityIdFor, Block as BlockT, Header as HeaderT, NumberFor, Zero};
 use runtime_primitives::generic::BlockId;
 use runtime_primitives::Justification;
 
@@ -51,6 +51,27 @@ pub trait HeaderBackend<Block: BlockT>: Send + Sync {
 		}
 	}
 
+	/// Get up to `max` headers starting with the block of the given hash and
+	/// following parent hashes, i.e. in descending order. Stops at genesis or at
+	/// the first header that is not found.
+	fn header_ancestry(&self, hash: Block::Hash, max: usize) -> Result<Vec<Block::Header>> {
+		let mut headers = Vec::new();
+		let mut id = BlockId::Hash(hash);
+		while headers.len() < max {
+			let header = match self.header(id)? {
+				Some(header) => header,
+				None => break,
+			};
+			let is_genesis = header.number().is_zero();
+			id = BlockId::Hash(*header.parent_hash());
+			headers.push(header);
+			if is_genesis {
+				break;
+			}
+		}
+		Ok(headers)
+	}
+
 	/// Get block header. Returns `UnknownBlock` error if block is not found.
 	fn expect_header(&self, id: BlockId<Block>) -> Result<Block::Header> {
 		self.header(id)?.ok_or_else(|| ErrorKind::UnknownBlock(format!("{}", id)).into())
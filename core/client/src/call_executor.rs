@@ -19,7 +19,7 @@ use codec::{Encode, Decode};
 use runtime_primitives::generic::BlockId;
 use runtime_primitives::traits::Block as BlockT;
 use state_machine::{
-	self, OverlayedChanges, Ext, CodeExecutor, ExecutionManager, native_when_possible
+	self, OverlayedChanges, Ext, CodeExecutor, ExecutionManager, OffchainKeystore, native_when_possible
 };
 use executor::{RuntimeVersion, RuntimeInfo, NativeVersion};
 use hash_db::Hasher;
@@ -152,6 +152,35 @@ impl<B, E> Clone for LocalCallExecutor<B, E> where E: Clone {
 	}
 }
 
+impl<B, E> LocalCallExecutor<B, E> {
+	/// Execute a call in an offchain context on top of the state of the given block. The code
+	/// can use the keys of the given keystore to sign. No changes are made.
+	pub fn call_offchain<Block>(
+		&self,
+		id: &BlockId<Block>,
+		method: &str,
+		call_data: &[u8],
+		keystore: &OffchainKeystore,
+	) -> error::Result<Vec<u8>> where
+		B: backend::LocalBackend<Block, Blake2Hasher>,
+		E: CodeExecutor<Blake2Hasher>,
+		Block: BlockT<Hash=H256>,
+	{
+		let state = self.backend.state_at(*id)?;
+		let return_data = state_machine::execute_offchain(
+			&state,
+			self.backend.changes_trie_storage(),
+			&self.executor,
+			method,
+			call_data,
+			true,
+			keystore,
+		)?;
+		self.backend.destroy_state(state)?;
+		Ok(return_data)
+	}
+}
+
 impl<B, E, Block> CallExecutor<Block, Blake2Hasher> for LocalCallExecutor<B, E>
 where
 	B: backend::LocalBackend<Block, Blake2Hasher>,
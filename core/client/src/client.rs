@@ -88,13 +88,14 @@ pub struct Client<B, E, Block, RA> where Block: BlockT {
 	importing_block: RwLock<Option<Block::Hash>>,
 	block_execution_strategy: ExecutionStrategy,
 	api_execution_strategy: ExecutionStrategy,
+	genesis_state_root: Block::Hash,
 	_phantom: PhantomData<RA>,
 }
 
 /// Client import operation, a wrapper for the backend.
 pub struct ClientImportOperation<Block: BlockT, H: Hasher<Out=Block::Hash>, B: backend::Backend<Block, H>> {
 	op: B::BlockImportOperation,
-	notify_imported: Option<(Block::Hash, BlockOrigin, Block::Header, bool, Option<Vec<(Vec<u8>, Option<Vec<u8>>)>>)>,
+	notify_imported: Option<(Block::Hash, BlockOrigin, Block::Header, bool, Vec<Block::Hash>, ImportedBlockStats, Option<Vec<(Vec<u8>, Option<Vec<u8>>)>>)>,
 	notify_finalized: Vec<Block::Hash>,
 }
 
@@ -138,6 +139,8 @@ pub struct ClientInfo<Block: BlockT> {
 	pub best_queued_number: Option<<<Block as BlockT>::Header as HeaderT>::Number>,
 	/// Best queued block hash.
 	pub best_queued_hash: Option<Block::Hash>,
+	/// State root of the genesis block.
+	pub genesis_state_root: Block::Hash,
 }
 
 /// Block status.
@@ -164,6 +167,20 @@ pub struct BlockImportNotification<Block: BlockT> {
 	pub header: Block::Header,
 	/// Is this the new best block.
 	pub is_new_best: bool,
+	/// Blocks removed from the best chain by this import, newest first.
+	/// Empty unless the new best block is on a different fork.
+	pub retracted: Vec<Block::Hash>,
+	/// Size and contents statistics of the imported block.
+	pub stats: ImportedBlockStats,
+}
+
+/// Statistics of an imported block, recorded while importing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportedBlockStats {
+	/// Number of extrinsics in the block body.
+	pub extrinsics_count: usize,
+	/// Size of the SCALE-encoded block (header and body) in bytes.
+	pub encoded_size: usize,
 }
 
 /// Summary of a finalized block.
@@ -269,6 +286,10 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 			backend.commit_operation(op)?;
 		}
 
+		let genesis_state_root = backend.blockchain().header(BlockId::Number(Zero::zero()))?
+			.map(|header| *header.state_root())
+			.ok_or_else(|| error::ErrorKind::UnknownBlock("genesis".into()))?;
+
 		Ok(Client {
 			backend,
 			executor,
@@ -279,6 +300,7 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 			importing_block: Default::default(),
 			block_execution_strategy,
 			api_execution_strategy,
+			genesis_state_root,
 			_phantom: Default::default(),
 		})
 	}
@@ -667,6 +689,12 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 			PrePostHeader::Different(header, post_header)
 		};
 
+		let stats = ImportedBlockStats {
+			extrinsics_count: body.as_ref().map_or(0, |body| body.len()),
+			encoded_size: import_headers.pre().using_encoded(|e| e.len())
+				+ body.as_ref().map_or(0, |body| body.using_encoded(|e| e.len())),
+		};
+
 		let hash = import_headers.post().hash();
 		let height: u64 = import_headers.post().number().as_();
 
@@ -683,12 +711,15 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 			finalized,
 			auxiliary,
 			fork_choice,
+			stats,
 		);
 
 		telemetry!("block.import";
 			"height" => height,
 			"best" => ?hash,
-			"origin" => ?origin
+			"origin" => ?origin,
+			"extrinsics" => stats.extrinsics_count,
+			"size" => stats.encoded_size
 		);
 
 		result
@@ -706,6 +737,7 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 		finalized: bool,
 		aux: Vec<(Vec<u8>, Option<Vec<u8>>)>,
 		fork_choice: ForkChoiceStrategy,
+		stats: ImportedBlockStats,
 	) -> error::Result<ImportResult> where
 		E: CallExecutor<Block, Blake2Hasher> + Send + Sync + Clone,
 	{
@@ -780,7 +812,18 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 				operation.notify_finalized.push(hash);
 			}
 
-			operation.notify_imported = Some((hash, origin, import_headers.into_post(), is_new_best, storage_changes));
+			let retracted = if is_new_best && parent_hash != last_best {
+				let route_from_best = crate::blockchain::tree_route(
+					self.backend.blockchain(),
+					BlockId::Hash(last_best),
+					BlockId::Hash(parent_hash),
+				)?;
+				route_from_best.retracted().iter().map(|entry| entry.hash).collect()
+			} else {
+				Vec::new()
+			};
+
+			operation.notify_imported = Some((hash, origin, import_headers.into_post(), is_new_best, retracted, stats, storage_changes));
 		}
 
 		Ok(ImportResult::Queued)
@@ -926,9 +969,9 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 
 	fn notify_imported(
 		&self,
-		notify_import: (Block::Hash, BlockOrigin, Block::Header, bool, Option<Vec<(Vec<u8>, Option<Vec<u8>>)>>),
+		notify_import: (Block::Hash, BlockOrigin, Block::Header, bool, Vec<Block::Hash>, ImportedBlockStats, Option<Vec<(Vec<u8>, Option<Vec<u8>>)>>),
 	) -> error::Result<()> {
-		let (hash, origin, header, is_new_best, storage_changes) = notify_import;
+		let (hash, origin, header, is_new_best, retracted, stats, storage_changes) = notify_import;
 
 		if let Some(storage_changes) = storage_changes {
 			// TODO [ToDr] How to handle re-orgs? Should we re-emit all storage changes?
@@ -941,6 +984,8 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 			origin,
 			header,
 			is_new_best,
+			retracted,
+			stats,
 		};
 
 		self.import_notification_sinks.lock()
@@ -1011,6 +1056,7 @@ impl<B, E, Block, RA> Client<B, E, Block, RA> where
 			chain: info,
 			best_queued_hash: None,
 			best_queued_number: None,
+			genesis_state_root: self.genesis_state_root,
 		})
 	}
 
@@ -1209,6 +1255,10 @@ impl<B, E, Block, RA> ChainHeaderBackend<Block> for Client<B, E, Block, RA> wher
 		self.backend.blockchain().header(id)
 	}
 
+	fn header_ancestry(&self, hash: Block::Hash, max: usize) -> error::Result<Vec<Block::Header>> {
+		self.backend.blockchain().header_ancestry(hash, max)
+	}
+
 	fn info(&self) -> error::Result<blockchain::Info<Block>> {
 		self.backend.blockchain().info()
 	}
@@ -1588,6 +1638,33 @@ pub(crate) mod tests {
 		);
 	}
 
+	#[test]
+	fn import_notifications_carry_block_stats() {
+		use futures::Stream;
+
+		let client = test_client::new();
+		let notifications = client.import_notification_stream();
+
+		let mut builder = client.new_block().unwrap();
+
+		builder.push_transfer(Transfer {
+			from: Keyring::Alice.to_raw_public().into(),
+			to: Keyring::Ferdie.to_raw_public().into(),
+			amount: 42,
+			nonce: 0,
+		}).unwrap();
+
+		let block = builder.bake().unwrap();
+		let encoded_size = block.encode().len();
+		client.import(BlockOrigin::Own, block).unwrap();
+
+		let notification = notifications.wait().next().unwrap().unwrap();
+		assert_eq!(notification.stats, ImportedBlockStats {
+			extrinsics_count: 1,
+			encoded_size,
+		});
+	}
+
 	#[test]
 	fn client_uses_authorities_from_blockchain_cache() {
 		let client = test_client::new();
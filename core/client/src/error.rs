@@ -98,6 +98,12 @@ error_chain! {
 			display("Remote node has responded with invalid header proof"),
 		}
 
+		/// Remote block body doesn't match the extrinsics root of its header.
+		InvalidBodyProof {
+			description("invalid block body"),
+			display("Remote node has responded with a block body that doesn't match the header"),
+		}
+
 		/// Remote fetch has been cancelled.
 		RemoteFetchCancelled {
 			description("remote fetch cancelled"),
@@ -651,7 +651,8 @@ fn register_aura_inherent_data_provider(
 	}
 }
 
-/// Start an import queue for the Aura consensus algorithm.
+/// Start an import queue for the Aura consensus algorithm, verifying blocks whose parent
+/// is imported on `verification_workers` threads.
 pub fn import_queue<B, C, E>(
 	slot_duration: SlotDuration,
 	block_import: SharedBlockImport<B>,
@@ -659,6 +660,7 @@ pub fn import_queue<B, C, E>(
 	client: Arc<C>,
 	extra: E,
 	inherent_data_providers: InherentDataProviders,
+	verification_workers: usize,
 ) -> Result<AuraImportQueue<B, C, E>, consensus_common::Error> where
 	B: Block,
 	C: Authorities<B> + ProvideRuntimeApi + Send + Sync,
@@ -671,7 +673,7 @@ pub fn import_queue<B, C, E>(
 	let verifier = Arc::new(
 		AuraVerifier { client: client.clone(), extra, inherent_data_providers }
 	);
-	Ok(BasicQueue::new(verifier, block_import, justification_import))
+	Ok(BasicQueue::with_verification_workers(verifier, block_import, justification_import, verification_workers))
 }
 
 #[cfg(test)]
@@ -166,4 +166,8 @@ pub trait JustificationImport<B: BlockT> {
 		number: NumberFor<B>,
 		justification: Justification,
 	) -> Result<(), Self::Error>;
+
+	/// Called when a justification requested through `Link::request_justification`
+	/// could not be obtained from the network and is no longer requested.
+	fn on_justification_unavailable(&self, _hash: B::Hash, _number: NumberFor<B>) { }
 }
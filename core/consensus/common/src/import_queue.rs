@@ -25,11 +25,12 @@
 //! instantiated simply.
 
 use crate::block_import::{ImportBlock, BlockImport, JustificationImport, ImportResult, BlockOrigin};
-use std::collections::{HashSet, VecDeque};
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::sync::{mpsc, Arc};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use parking_lot::{Condvar, Mutex, RwLock};
-use log::{trace, debug};
+use log::{trace, debug, warn};
 
 use runtime_primitives::Justification;
 use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, NumberFor, Zero, AuthorityIdFor};
@@ -45,6 +46,12 @@ pub type SharedJustificationImport<B> = Arc<dyn JustificationImport<B, Error=Con
 /// Maps to the Origin used by the network.
 pub type Origin = usize;
 
+/// Estimated size of the queued blocks above which the queue asks to stop feeding it.
+pub const QUEUED_BYTES_HIGH_WATERMARK: usize = 256 * 1024 * 1024;
+
+/// Estimated size of the queued blocks below which the queue accepts blocks again.
+pub const QUEUED_BYTES_LOW_WATERMARK: usize = 128 * 1024 * 1024;
+
 /// Block data used by the queue.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct IncomingBlock<B: BlockT> {
@@ -58,6 +65,9 @@ pub struct IncomingBlock<B: BlockT> {
 	pub justification: Option<Justification>,
 	/// The peer, we received this from
 	pub origin: Option<Origin>,
+	/// Encoded size of the block as received, used to estimate the memory used by the
+	/// queue. Zero if unknown.
+	pub size: usize,
 }
 
 /// Verify a justification of a block
@@ -72,6 +82,20 @@ pub trait Verifier<B: BlockT>: Send + Sync + Sized {
 		justification: Option<Justification>,
 		body: Option<Vec<B::Extrinsic>>
 	) -> Result<(ImportBlock<B>, Option<Vec<AuthorityIdFor<B>>>), String>;
+
+	/// Rank the fork ending with the given announced header against other competing forks.
+	///
+	/// Forks with a higher weight are downloaded first, since they are more likely to
+	/// become the best chain. Returns `None` if the weight can not be told from the header.
+	fn fork_weight(&self, _header: &B::Header) -> Option<u64> {
+		None
+	}
+
+	/// Whether a block can be verified before its parent is imported. Verifiers reading the
+	/// state or the authorities at the parent of a block must keep the default.
+	fn is_parent_independent(&self) -> bool {
+		false
+	}
 }
 
 /// Blocks import queue API.
@@ -96,8 +120,23 @@ pub trait ImportQueue<B: BlockT>: Send + Sync {
 	fn is_importing(&self, hash: &B::Hash) -> bool;
 	/// Import bunch of blocks.
 	fn import_blocks(&self, origin: BlockOrigin, blocks: Vec<IncomingBlock<B>>);
-	/// Import a block justification.
-	fn import_justification(&self, hash: B::Hash, number: NumberFor<B>, justification: Justification) -> bool;
+	/// Verify and import a single block ahead of the queued blocks of lower priority,
+	/// and wait for the result. Sync is notified of the import like for the other blocks.
+	fn import_block(
+		&self,
+		origin: BlockOrigin,
+		block: IncomingBlock<B>,
+	) -> Result<BlockImportResult<B::Hash, NumberFor<B>>, BlockImportError>;
+	/// Import a block justification provided by the given peer. The result is
+	/// reported through `Link::justification_imported`.
+	fn import_justification(&self, who: Origin, hash: B::Hash, number: NumberFor<B>, justification: Justification);
+	/// Notify that a requested justification could not be obtained from the network.
+	/// Forwarded to `JustificationImport::on_justification_unavailable`.
+	fn justification_unavailable(&self, _hash: B::Hash, _number: NumberFor<B>) { }
+	/// Rank the fork ending with the given announced header. See `Verifier::fork_weight`.
+	fn fork_weight(&self, _header: &B::Header) -> Option<u64> {
+		None
+	}
 }
 
 /// Import queue status. It isn't completely accurate.
@@ -106,36 +145,105 @@ pub struct ImportQueueStatus<B: BlockT> {
 	pub importing_count: usize,
 	/// The number of the best block that was ever in the queue since start/last failure.
 	pub best_importing_number: <<B as BlockT>::Header as HeaderT>::Number,
+	/// Estimated encoded size of the blocks in the queue.
+	pub queued_bytes: usize,
+	/// Number of queued blocks waiting to be verified.
+	pub verification_backlog: usize,
+	/// Whether no more blocks should be fed to the queue. Set once the queued blocks exceed
+	/// the high watermark, until they drain below the low watermark.
+	pub backpressure: bool,
 }
 
 /// Basic block import queue that is importing blocks sequentially in a separate thread,
-/// with pluggable verification.
+/// with pluggable verification. Blocks can be verified by a pool of worker threads
+/// ahead of their import.
 pub struct BasicQueue<B: BlockT, V: 'static + Verifier<B>> {
 	handle: Mutex<Option<::std::thread::JoinHandle<()>>>,
 	data: Arc<AsyncImportQueueData<B>>,
 	verifier: Arc<V>,
 	block_import: SharedBlockImport<B>,
 	justification_import: Option<SharedJustificationImport<B>>,
+	verification_workers: usize,
+}
+
+/// Work item of the import thread.
+enum BlockImportMsg<B: BlockT> {
+	/// Blocks to import, with their estimated encoded size.
+	ImportBlocks(BlockOrigin, Vec<IncomingBlock<B>>, usize),
+	/// A block to import, and where to send the result.
+	ImportBlock(BlockOrigin, IncomingBlock<B>, mpsc::Sender<BlockImportOutcome<B>>),
+	ImportJustification(Origin, B::Hash, NumberFor<B>, Justification),
+	JustificationUnavailable(B::Hash, NumberFor<B>),
 }
 
-/// Locks order: queue, queue_blocks, best_importing_number
+/// Result of the import of a block.
+type BlockImportOutcome<B> = Result<BlockImportResult<<B as BlockT>::Hash, NumberFor<B>>, BlockImportError>;
+
+/// Locks order: queue, queue_blocks, best_importing_number, usage
 pub struct AsyncImportQueueData<B: BlockT> {
 	signal: Condvar,
-	queue: Mutex<VecDeque<(BlockOrigin, Vec<IncomingBlock<B>>)>>,
+	queue: Mutex<VecDeque<BlockImportMsg<B>>>,
 	queue_blocks: RwLock<HashSet<B::Hash>>,
 	best_importing_number: RwLock<<<B as BlockT>::Header as HeaderT>::Number>,
+	usage: Mutex<QueueUsage>,
+	high_watermark: usize,
+	low_watermark: usize,
 	is_stopping: AtomicBool,
 }
 
+/// Estimated resources used by the queued blocks.
+#[derive(Default)]
+struct QueueUsage {
+	bytes: usize,
+	verification_backlog: usize,
+	backpressure: bool,
+}
+
 impl<B: BlockT, V: Verifier<B>> BasicQueue<B, V> {
 	/// Instantiate a new basic queue, with given verifier and justification import.
 	pub fn new(verifier: Arc<V>, block_import: SharedBlockImport<B>, justification_import: Option<SharedJustificationImport<B>>) -> Self {
+		Self::with_verification_workers(verifier, block_import, justification_import, 0)
+	}
+
+	/// Instantiate a new basic queue verifying blocks on `workers` threads, while importing
+	/// them in order. Blocks are verified on the import thread if `workers` is below 2.
+	///
+	/// Unless the verifier is parent independent, only the blocks whose parent is already
+	/// imported are verified on the worker threads. See `Verifier::is_parent_independent`.
+	pub fn with_verification_workers(
+		verifier: Arc<V>,
+		block_import: SharedBlockImport<B>,
+		justification_import: Option<SharedJustificationImport<B>>,
+		workers: usize,
+	) -> Self {
+		Self::with_queued_bytes_watermarks(
+			verifier,
+			block_import,
+			justification_import,
+			workers,
+			QUEUED_BYTES_HIGH_WATERMARK,
+			QUEUED_BYTES_LOW_WATERMARK,
+		)
+	}
+
+	/// Instantiate a new basic queue verifying blocks on `workers` threads, which asks to
+	/// stop feeding it once the queued blocks exceed `high_watermark` bytes, until they
+	/// drain below `low_watermark` bytes.
+	pub fn with_queued_bytes_watermarks(
+		verifier: Arc<V>,
+		block_import: SharedBlockImport<B>,
+		justification_import: Option<SharedJustificationImport<B>>,
+		workers: usize,
+		high_watermark: usize,
+		low_watermark: usize,
+	) -> Self {
 		Self {
 			handle: Mutex::new(None),
-			data: Arc::new(AsyncImportQueueData::new()),
+			data: Arc::new(AsyncImportQueueData::with_watermarks(high_watermark, low_watermark)),
 			verifier,
 			block_import,
 			justification_import,
+			verification_workers: workers,
 		}
 	}
 }
@@ -143,11 +251,20 @@ impl<B: BlockT, V: Verifier<B>> BasicQueue<B, V> {
 impl<B: BlockT> AsyncImportQueueData<B> {
 	/// Instantiate a new async import queue data.
 	pub fn new() -> Self {
+		Self::with_watermarks(QUEUED_BYTES_HIGH_WATERMARK, QUEUED_BYTES_LOW_WATERMARK)
+	}
+
+	/// Instantiate a new async import queue data, with the given bounds in bytes of the
+	/// queued blocks between which backpressure is applied.
+	pub fn with_watermarks(high_watermark: usize, low_watermark: usize) -> Self {
 		Self {
 			signal: Default::default(),
 			queue: Mutex::new(VecDeque::new()),
 			queue_blocks: RwLock::new(HashSet::new()),
 			best_importing_number: RwLock::new(Zero::zero()),
+			usage: Mutex::new(QueueUsage::default()),
+			high_watermark,
+			low_watermark,
 			is_stopping: Default::default(),
 		}
 	}
@@ -156,6 +273,31 @@ impl<B: BlockT> AsyncImportQueueData<B> {
 	pub fn stop(&self) {
 		self.is_stopping.store(true, Ordering::SeqCst);
 	}
+
+	// Notes blocks of `bytes` in total entering the queue.
+	fn blocks_queued(&self, count: usize, bytes: usize) {
+		let mut usage = self.usage.lock();
+		usage.bytes += bytes;
+		usage.verification_backlog += count;
+		if usage.bytes > self.high_watermark {
+			usage.backpressure = true;
+		}
+	}
+
+	// Notes queued blocks that are either verified or won't be.
+	fn verification_done(&self, count: usize) {
+		let mut usage = self.usage.lock();
+		usage.verification_backlog = usage.verification_backlog.saturating_sub(count);
+	}
+
+	// Notes blocks of `bytes` in total leaving the queue.
+	fn blocks_dequeued(&self, bytes: usize) {
+		let mut usage = self.usage.lock();
+		usage.bytes = usage.bytes.saturating_sub(bytes);
+		if usage.bytes < self.low_watermark {
+			usage.backpressure = false;
+		}
+	}
 }
 
 impl<B: BlockT, V: 'static + Verifier<B>> ImportQueue<B> for BasicQueue<B, V> {
@@ -169,11 +311,17 @@ impl<B: BlockT, V: 'static + Verifier<B>> ImportQueue<B> for BasicQueue<B, V> {
 		let verifier = self.verifier.clone();
 		let block_import = self.block_import.clone();
 		let justification_import = self.justification_import.clone();
+		let verification_workers = self.verification_workers;
 		*self.handle.lock() = Some(::std::thread::Builder::new().name("ImportQueue".into()).spawn(move || {
 			if let Some(justification_import) = justification_import.as_ref() {
 				justification_import.on_start(&link);
 			}
-			import_thread(block_import, link, qdata, verifier)
+			let pool = if verification_workers > 1 {
+				Some(VerificationPool::new(verification_workers, verifier.clone(), qdata.clone()))
+			} else {
+				None
+			};
+			import_thread(block_import, justification_import, link, qdata, verifier, pool)
 		})?);
 		Ok(())
 	}
@@ -183,7 +331,21 @@ impl<B: BlockT, V: 'static + Verifier<B>> ImportQueue<B> for BasicQueue<B, V> {
 		let mut queue_blocks = self.data.queue_blocks.write();
 		let mut best_importing_number = self.data.best_importing_number.write();
 		queue_blocks.clear();
-		queue.clear();
+		// sync waits for the result of every justification import, and callers of
+		// `import_block` for theirs, keep them.
+		let data = &self.data;
+		queue.retain(|msg| match msg {
+			BlockImportMsg::ImportJustification(..) | BlockImportMsg::JustificationUnavailable(..) => true,
+			BlockImportMsg::ImportBlock(_, block, _) => {
+				queue_blocks.insert(block.hash.clone());
+				true
+			},
+			BlockImportMsg::ImportBlocks(_, blocks, bytes) => {
+				data.verification_done(blocks.len());
+				data.blocks_dequeued(*bytes);
+				false
+			},
+		});
 		*best_importing_number = Zero::zero();
 	}
 
@@ -198,13 +360,21 @@ impl<B: BlockT, V: 'static + Verifier<B>> ImportQueue<B> for BasicQueue<B, V> {
 			}
 
 			let _ = handle.join();
+			// the blocks left are never imported, let their senders know.
+			self.data.queue.lock().clear();
 		}
 	}
 
 	fn status(&self) -> ImportQueueStatus<B> {
+		let importing_count = self.data.queue_blocks.read().len();
+		let best_importing_number = *self.data.best_importing_number.read();
+		let usage = self.data.usage.lock();
 		ImportQueueStatus {
-			importing_count: self.data.queue_blocks.read().len(),
-			best_importing_number: *self.data.best_importing_number.read(),
+			importing_count,
+			best_importing_number,
+			queued_bytes: usage.bytes,
+			verification_backlog: usage.verification_backlog,
+			backpressure: usage.backpressure,
 		}
 	}
 
@@ -223,18 +393,57 @@ impl<B: BlockT, V: 'static + Verifier<B>> ImportQueue<B> for BasicQueue<B, V> {
 		let mut queue_blocks = self.data.queue_blocks.write();
 		let mut best_importing_number = self.data.best_importing_number.write();
 		let new_best_importing_number = blocks.last().and_then(|b| b.header.as_ref().map(|h| h.number().clone())).unwrap_or_else(|| Zero::zero());
+
+		let position = queue_position(&queue, &queue_blocks, &origin, blocks.first());
 		queue_blocks.extend(blocks.iter().map(|b| b.hash.clone()));
 		if new_best_importing_number > *best_importing_number {
 			*best_importing_number = new_best_importing_number;
 		}
-		queue.push_back((origin, blocks));
+		let bytes = blocks.iter().map(|block| block.size).sum();
+		self.data.blocks_queued(blocks.len(), bytes);
+		queue.insert(position, BlockImportMsg::ImportBlocks(origin, blocks, bytes));
 		self.data.signal.notify_one();
 	}
 
-	fn import_justification(&self, hash: B::Hash, number: NumberFor<B>, justification: Justification) -> bool {
-		self.justification_import.as_ref().map(|justification_import| {
-			justification_import.import_justification(hash, number, justification).is_ok()
-		}).unwrap_or(false)
+	fn import_block(
+		&self,
+		origin: BlockOrigin,
+		block: IncomingBlock<B>,
+	) -> Result<BlockImportResult<B::Hash, NumberFor<B>>, BlockImportError> {
+		let (result, receiver) = mpsc::channel();
+		{
+			let handle = self.handle.lock();
+			if handle.is_none() {
+				// without the import thread, there is no sync to notify.
+				return import_single_block(&*self.block_import, origin, block, self.verifier.clone());
+			}
+
+			trace!(target:"sync", "Scheduling block {} for import", block.hash);
+			let mut queue = self.data.queue.lock();
+			let mut queue_blocks = self.data.queue_blocks.write();
+			let position = queue_position(&queue, &queue_blocks, &origin, Some(&block));
+			queue_blocks.insert(block.hash.clone());
+			queue.insert(position, BlockImportMsg::ImportBlock(origin, block, result));
+			self.data.signal.notify_one();
+		}
+		// the sender is dropped if the queue stops before importing the block.
+		receiver.recv().unwrap_or(Err(BlockImportError::Error))
+	}
+
+	fn import_justification(&self, who: Origin, hash: B::Hash, number: NumberFor<B>, justification: Justification) {
+		let mut queue = self.data.queue.lock();
+		queue.push_back(BlockImportMsg::ImportJustification(who, hash, number, justification));
+		self.data.signal.notify_one();
+	}
+
+	fn justification_unavailable(&self, hash: B::Hash, number: NumberFor<B>) {
+		let mut queue = self.data.queue.lock();
+		queue.push_back(BlockImportMsg::JustificationUnavailable(hash, number));
+		self.data.signal.notify_one();
+	}
+
+	fn fork_weight(&self, header: &B::Header) -> Option<u64> {
+		self.verifier.fork_weight(header)
 	}
 }
 
@@ -244,16 +453,63 @@ impl<B: BlockT, V: 'static + Verifier<B>> Drop for BasicQueue<B, V> {
 	}
 }
 
+/// Index in the queue at which blocks of the given origin are inserted, given the first one.
+///
+/// Blocks at the head of the chain skip the queued blocks of lower priority. If they
+/// build on queued blocks, they are imported right after them instead.
+fn queue_position<B: BlockT>(
+	queue: &VecDeque<BlockImportMsg<B>>,
+	queue_blocks: &HashSet<B::Hash>,
+	origin: &BlockOrigin,
+	first: Option<&IncomingBlock<B>>,
+) -> usize {
+	if !is_priority_origin(origin) {
+		return queue.len();
+	}
+
+	let queued_parent = first
+		.and_then(|b| b.header.as_ref())
+		.map(|h| h.parent_hash().clone())
+		.filter(|parent| queue_blocks.contains(parent));
+	match queued_parent {
+		Some(parent) => queue.iter()
+			.position(|msg| match msg {
+				BlockImportMsg::ImportBlocks(_, blocks, _) => blocks.iter().any(|b| b.hash == parent),
+				BlockImportMsg::ImportBlock(_, block, _) => block.hash == parent,
+				_ => false,
+			})
+			// the parent is being imported already.
+			.map_or(0, |index| index + 1),
+		None => queue.iter()
+			.position(|msg| match msg {
+				BlockImportMsg::ImportBlocks(origin, _, _) => !is_priority_origin(origin),
+				_ => false,
+			})
+			.unwrap_or(queue.len()),
+	}
+}
+
+/// Whether blocks of the given origin are imported ahead of the other queued blocks,
+/// so that the node keeps up with the head of the chain while syncing older blocks.
+fn is_priority_origin(origin: &BlockOrigin) -> bool {
+	match origin {
+		BlockOrigin::NetworkBroadcast | BlockOrigin::ConsensusBroadcast | BlockOrigin::Own => true,
+		_ => false,
+	}
+}
+
 /// Blocks import thread.
 fn import_thread<B: BlockT, L: Link<B>, V: Verifier<B>>(
 	block_import: SharedBlockImport<B>,
+	justification_import: Option<SharedJustificationImport<B>>,
 	link: L,
 	qdata: Arc<AsyncImportQueueData<B>>,
-	verifier: Arc<V>
+	verifier: Arc<V>,
+	pool: Option<VerificationPool<B>>,
 ) {
 	trace!(target: "sync", "Starting import thread");
 	loop {
-		let new_blocks = {
+		let msg = {
 			let mut queue_lock = qdata.queue.lock();
 
 			// We are holding the same lock that `stop` takes so here we either see that stop flag
@@ -267,21 +523,50 @@ fn import_thread<B: BlockT, L: Link<B>, V: Verifier<B>>(
 			}
 
 			match queue_lock.pop_front() {
-				Some(new_blocks) => new_blocks,
+				Some(msg) => msg,
 				None => break,
 			}
 		};
 
+		let (new_blocks, bytes) = match msg {
+			BlockImportMsg::ImportBlocks(origin, blocks, bytes) => ((origin, blocks), bytes),
+			BlockImportMsg::ImportJustification(who, hash, number, justification) => {
+				let success = import_justification(justification_import.as_ref(), hash, number, justification);
+				link.justification_imported(who, &hash, number, success);
+				continue;
+			},
+			BlockImportMsg::JustificationUnavailable(hash, number) => {
+				if let Some(justification_import) = justification_import.as_ref() {
+					justification_import.on_justification_unavailable(hash, number);
+				}
+				continue;
+			},
+			BlockImportMsg::ImportBlock(origin, block, result) => {
+				let hash = block.hash.clone();
+				let import_result = import_single_block(&*block_import, origin, block, verifier.clone());
+				// failures are the caller's to handle, sync only learns about imported blocks.
+				if let Ok(ref imported) = import_result {
+					process_import_result(&link, Ok(imported.clone()));
+					link.maintain_sync();
+				}
+				qdata.queue_blocks.write().remove(&hash);
+				let _ = result.send(import_result);
+				continue;
+			},
+		};
+
 		let blocks_hashes: Vec<B::Hash> = new_blocks.1.iter().map(|b| b.hash.clone()).collect();
-		if !import_many_blocks(
+		if !import_blocks_verified_ahead(
 			&*block_import,
 			&link,
 			Some(&*qdata),
 			new_blocks,
 			verifier.clone(),
+			pool.as_ref(),
 		) {
 			break;
 		}
+		qdata.blocks_dequeued(bytes);
 
 		let mut queue_blocks = qdata.queue_blocks.write();
 		for blocks_hash in blocks_hashes {
@@ -292,13 +577,116 @@ fn import_thread<B: BlockT, L: Link<B>, V: Verifier<B>>(
 	trace!(target: "sync", "Stopping import thread");
 }
 
+/// Result of the verification of the block at the given index of a batch.
+type Verification<B> = (usize, Result<VerifiedBlock<B>, BlockImportError>);
+
+/// A verification job: the index of the block in its batch, the block and where to send the result.
+type VerificationJob<B> = (usize, BlockOrigin, IncomingBlock<B>, mpsc::Sender<Verification<B>>);
+
+/// Threads verifying the blocks of a batch ahead of their import.
+struct VerificationPool<B: BlockT> {
+	jobs: Option<mpsc::Sender<VerificationJob<B>>>,
+	workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl<B: BlockT> VerificationPool<B> {
+	fn new<V: 'static + Verifier<B>>(workers: usize, verifier: Arc<V>, qdata: Arc<AsyncImportQueueData<B>>) -> Self {
+		let (jobs, receiver) = mpsc::channel::<VerificationJob<B>>();
+		let receiver = Arc::new(Mutex::new(receiver));
+		let workers = (0..workers)
+			.filter_map(|index| {
+				let receiver = receiver.clone();
+				let verifier = verifier.clone();
+				let qdata = qdata.clone();
+				thread::Builder::new().name(format!("ImportQueueVerifier{}", index)).spawn(move || loop {
+					let job = receiver.lock().recv();
+					let (index, origin, block, results) = match job {
+						Ok(job) => job,
+						Err(_) => break,
+					};
+					let verification = verify_block(origin, block, &*verifier);
+					qdata.verification_done(1);
+					let _ = results.send((index, verification));
+				})
+					.map_err(|e| warn!(target: "sync", "Failed to start block verification thread: {:?}", e))
+					.ok()
+			})
+			.collect::<Vec<_>>();
+
+		VerificationPool {
+			jobs: if workers.is_empty() { None } else { Some(jobs) },
+			workers,
+		}
+	}
+
+	/// Hands the blocks of a batch that can be verified ahead over to the workers: all of
+	/// them if the verifier is parent independent, otherwise those whose parent isn't in the
+	/// batch, and so is imported already. Returns the receiver of the results, each with the
+	/// index of its block, and the batch with the blocks left to verify on the import thread.
+	fn verify(
+		&self,
+		origin: BlockOrigin,
+		blocks: Vec<IncomingBlock<B>>,
+		parent_independent: bool,
+	) -> (mpsc::Receiver<Verification<B>>, Vec<Option<IncomingBlock<B>>>) {
+		let (results, receiver) = mpsc::channel();
+		let batch: HashSet<B::Hash> = if parent_independent {
+			HashSet::new()
+		} else {
+			blocks.iter().map(|block| block.hash).collect()
+		};
+		let blocks = blocks.into_iter().enumerate().map(|(index, block)| {
+			let parent_in_batch = block.header.as_ref().map_or(false, |header| batch.contains(header.parent_hash()));
+			match self.jobs.as_ref() {
+				Some(jobs) if !parent_in_batch => match jobs.send((index, origin.clone(), block, results.clone())) {
+					Ok(()) => None,
+					Err(mpsc::SendError((_, _, block, _))) => Some(block),
+				},
+				_ => Some(block),
+			}
+		}).collect();
+		(receiver, blocks)
+	}
+}
+
+impl<B: BlockT> Drop for VerificationPool<B> {
+	fn drop(&mut self) {
+		// the workers stop once the jobs channel is closed.
+		self.jobs.take();
+		for worker in self.workers.drain(..) {
+			let _ = worker.join();
+		}
+	}
+}
+
+/// Import a justification with the given justification import, if any.
+/// Returns whether the justification was imported.
+pub fn import_justification<B: BlockT>(
+	justification_import: Option<&SharedJustificationImport<B>>,
+	hash: B::Hash,
+	number: NumberFor<B>,
+	justification: Justification,
+) -> bool {
+	let justification_import = match justification_import {
+		Some(justification_import) => justification_import,
+		None => return false,
+	};
+
+	justification_import.import_justification(hash, number, justification)
+		.map_err(|e| debug!(target: "sync", "Justification import of block {} ({}) failed: {:?}", number, hash, e))
+		.is_ok()
+}
+
 /// Hooks that the verification queue can use to influence the synchronization
 /// algorithm.
 pub trait Link<B: BlockT>: Send {
 	/// Block imported.
 	fn block_imported(&self, _hash: &B::Hash, _number: NumberFor<B>) { }
-	/// Request a justification for the given block.
-	fn request_justification(&self, _hash: &B::Hash, _number: NumberFor<B>) { }
+	/// Justification import result.
+	fn justification_imported(&self, _who: Origin, _hash: &B::Hash, _number: NumberFor<B>, _success: bool) { }
+	/// Request a justification for the given block. Mandatory requests are needed
+	/// to make finality progress and take precedence over optional ones.
+	fn request_justification(&self, _hash: &B::Hash, _number: NumberFor<B>, _mandatory: bool) { }
 	/// Maintain sync.
 	fn maintain_sync(&self) { }
 	/// Disconnect from peer.
@@ -310,7 +698,7 @@ pub trait Link<B: BlockT>: Send {
 }
 
 /// Block import successful result.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum BlockImportResult<H: ::std::fmt::Debug + PartialEq, N: ::std::fmt::Debug + PartialEq> {
 	/// Imported known block.
 	ImportedKnown(H, N),
@@ -343,8 +731,23 @@ pub fn import_many_blocks<'a, B: BlockT, V: Verifier<B>>(
 	blocks: (BlockOrigin, Vec<IncomingBlock<B>>),
 	verifier: Arc<V>
 ) -> bool
+{
+	import_blocks_verified_ahead(import_handle, link, qdata, blocks, verifier, None)
+}
+
+/// Import a bunch of blocks in order, verified ahead by the pool if any.
+fn import_blocks_verified_ahead<B: BlockT, V: Verifier<B>>(
+	import_handle: &BlockImport<B, Error=ConsensusError>,
+	link: &Link<B>,
+	qdata: Option<&AsyncImportQueueData<B>>,
+	blocks: (BlockOrigin, Vec<IncomingBlock<B>>),
+	verifier: Arc<V>,
+	pool: Option<&VerificationPool<B>>,
+) -> bool
 {
 	let (blocks_origin, blocks) = blocks;
+	// verifications received ahead of the block being imported.
+	let mut verified = BTreeMap::new();
 	let count = blocks.len();
 	let mut imported = 0;
 
@@ -358,22 +761,57 @@ pub fn import_many_blocks<'a, B: BlockT, V: Verifier<B>>(
 		};
 	trace!(target:"sync", "Starting import of {} blocks {}", count, blocks_range);
 
+	// blocks handed over to the pool are `None` here.
+	let (verifications, blocks) = match pool {
+		Some(pool) => {
+			let (verifications, blocks) = pool.verify(blocks_origin.clone(), blocks, verifier.is_parent_independent());
+			(Some(verifications), blocks)
+		},
+		None => (None, blocks.into_iter().map(Some).collect::<Vec<_>>()),
+	};
+	// blocks verified ahead are accounted for by the workers.
+	let mut verifications_left = blocks.iter().filter(|block| block.is_some()).count();
+	let verification_done = |done: usize| {
+		qdata.map(|qdata| qdata.verification_done(done));
+	};
+
 	// Blocks in the response/drain should be in ascending order.
-	for block in blocks {
-		let import_result = import_single_block(
-			import_handle,
-			blocks_origin.clone(),
-			block,
-			verifier.clone(),
-		);
+	for (index, block) in blocks.into_iter().enumerate() {
+		let import_result = match block {
+			Some(block) => {
+				verifications_left -= 1;
+				let result = import_single_block(import_handle, blocks_origin.clone(), block, verifier.clone());
+				verification_done(1);
+				result
+			},
+			None => {
+				if let Some(verifications) = verifications.as_ref() {
+					while !verified.contains_key(&index) {
+						match verifications.recv() {
+							Ok((verified_index, verification)) => { verified.insert(verified_index, verification); },
+							Err(_) => break,
+						}
+					}
+				}
+				match verified.remove(&index) {
+					Some(verification) => verification.and_then(|verified_block| import_verified_block(import_handle, verified_block)),
+					None => {
+						warn!(target: "sync", "Block verification thread stopped before verifying block {}", index);
+						Err(BlockImportError::Error)
+					},
+				}
+			},
+		};
 		let is_import_failed = import_result.is_err();
 		imported += process_import_result(link, import_result);
 		if is_import_failed {
 			qdata.map(|qdata| *qdata.best_importing_number.write() = Zero::zero());
+			verification_done(verifications_left);
 			return true;
 		}
 
 		if qdata.map(|qdata| qdata.is_stopping.load(Ordering::SeqCst)).unwrap_or_default() {
+			verification_done(verifications_left);
 			return false;
 		}
 	}
@@ -390,6 +828,27 @@ pub fn import_single_block<B: BlockT, V: Verifier<B>>(
 	block: IncomingBlock<B>,
 	verifier: Arc<V>
 ) -> Result<BlockImportResult<B::Hash, <<B as BlockT>::Header as HeaderT>::Number>, BlockImportError>
+{
+	let verified_block = verify_block(block_origin, block, &*verifier)?;
+	import_verified_block(import_handle, verified_block)
+}
+
+/// A block checked by the verifier, ready to be imported.
+struct VerifiedBlock<B: BlockT> {
+	peer: Option<Origin>,
+	hash: B::Hash,
+	number: NumberFor<B>,
+	parent: B::Hash,
+	import_block: ImportBlock<B>,
+	new_authorities: Option<Vec<AuthorityIdFor<B>>>,
+}
+
+/// Check a block with the verifier.
+fn verify_block<B: BlockT, V: Verifier<B>>(
+	block_origin: BlockOrigin,
+	block: IncomingBlock<B>,
+	verifier: &V,
+) -> Result<VerifiedBlock<B>, BlockImportError>
 {
 	let peer = block.origin;
 
@@ -418,6 +877,16 @@ pub fn import_single_block<B: BlockT, V: Verifier<B>>(
 			BlockImportError::VerificationFailed(peer, msg)
 		})?;
 
+	Ok(VerifiedBlock { peer, hash, number, parent, import_block, new_authorities })
+}
+
+/// Import a block checked by the verifier.
+fn import_verified_block<B: BlockT>(
+	import_handle: &BlockImport<B, Error=ConsensusError>,
+	verified_block: VerifiedBlock<B>,
+) -> Result<BlockImportResult<B::Hash, <<B as BlockT>::Header as HeaderT>::Number>, BlockImportError>
+{
+	let VerifiedBlock { peer, hash, number, parent, import_block, new_authorities } = verified_block;
 	match import_handle.import_block(import_block, new_authorities) {
 		Ok(ImportResult::AlreadyInChain) => {
 			trace!(target: "sync", "Block already in chain {}: {:?}", number, hash);
@@ -467,7 +936,7 @@ pub fn process_import_result<B: BlockT>(
 		},
 		Ok(BlockImportResult::ImportedUnjustified(hash, number)) => {
 			link.block_imported(&hash, number);
-			link.request_justification(&hash, number);
+			link.request_justification(&hash, number, true);
 			1
 		},
 		Err(BlockImportError::IncompleteHeader(who)) => {
@@ -494,3 +963,38 @@ pub fn process_import_result<B: BlockT>(
 		},
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_client::runtime::Block;
+
+	#[test]
+	fn backpressure_is_applied_between_watermarks() {
+		let qdata = AsyncImportQueueData::<Block>::with_watermarks(250, 150);
+		let backpressure = || qdata.usage.lock().backpressure;
+
+		qdata.blocks_queued(2, 200);
+		assert!(!backpressure());
+
+		// crossing the high watermark.
+		qdata.blocks_queued(1, 100);
+		assert_eq!(qdata.usage.lock().bytes, 300);
+		assert!(backpressure());
+
+		// kept until the queue drains below the low watermark.
+		qdata.blocks_dequeued(100);
+		assert!(backpressure());
+		qdata.blocks_dequeued(50);
+		assert!(backpressure());
+		qdata.blocks_dequeued(10);
+		assert_eq!(qdata.usage.lock().bytes, 140);
+		assert!(!backpressure());
+
+		// and applied again once the high watermark is crossed again.
+		qdata.blocks_queued(1, 100);
+		assert!(!backpressure());
+		qdata.blocks_queued(1, 20);
+		assert!(backpressure());
+	}
+}
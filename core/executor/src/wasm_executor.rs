@@ -505,6 +505,41 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 
 		Ok(0)
 	},
+	// return 0 and place u32::max_value() into written_out if no keystore is available.
+	ext_ed25519_public_keys(written_out: *mut u32) -> *mut u8 => {
+		let maybe_keys = this.ext.offchain_keystore().map(|keystore| keystore.ed25519_public_keys());
+
+		if let Some(keys) = maybe_keys {
+			let value = keys.concat();
+			let offset = this.heap.allocate(value.len() as u32) as u32;
+			this.memory.set(offset, &value).map_err(|_| UserError("Invalid attempt to set memory in ext_ed25519_public_keys"))?;
+			this.memory.write_primitive(written_out, value.len() as u32)
+				.map_err(|_| UserError("Invalid attempt to write written_out in ext_ed25519_public_keys"))?;
+			Ok(offset)
+		} else {
+			this.memory.write_primitive(written_out, u32::max_value())
+				.map_err(|_| UserError("Invalid attempt to write failed written_out in ext_ed25519_public_keys"))?;
+			Ok(0)
+		}
+	},
+	// return 0 if the signature was written, 1 if no keystore is available and 2 if the key is unknown.
+	ext_ed25519_sign(pubkey_data: *const u8, msg_data: *const u8, msg_len: u32, sig_data: *mut u8) -> u32 => {
+		let mut pubkey = [0u8; 32];
+		this.memory.get_into(pubkey_data, &mut pubkey[..]).map_err(|_| UserError("Invalid attempt to get pubkey in ext_ed25519_sign"))?;
+		let msg = this.memory.get(msg_data, msg_len as usize).map_err(|_| UserError("Invalid attempt to get message in ext_ed25519_sign"))?;
+
+		let sig = match this.ext.offchain_keystore() {
+			Some(keystore) => match keystore.ed25519_sign(&pubkey, &msg) {
+				Some(sig) => sig,
+				None => return Ok(2),
+			},
+			None => return Ok(1),
+		};
+
+		this.memory.set(sig_data, &sig[..]).map_err(|_| UserError("Invalid attempt to set signature in ext_ed25519_sign"))?;
+
+		Ok(0)
+	},
 	ext_sandbox_instantiate(
 		dispatch_thunk_idx: usize,
 		wasm_ptr: *const u8,
@@ -884,6 +919,68 @@ mod tests {
 		);
 	}
 
+	struct SingleKeystore(ed25519::Pair);
+
+	impl state_machine::OffchainKeystore for SingleKeystore {
+		fn ed25519_public_keys(&self) -> Vec<[u8; 32]> {
+			vec![self.0.public().0]
+		}
+
+		fn ed25519_sign(&self, public: &[u8; 32], msg: &[u8]) -> Option<[u8; 64]> {
+			if public == &self.0.public().0 {
+				Some(self.0.sign(msg).to_fixed_bytes())
+			} else {
+				None
+			}
+		}
+	}
+
+	#[test]
+	fn ed25519_public_keys_should_work() {
+		let mut ext = TestExternalities::<Blake2Hasher>::default();
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+		assert_eq!(
+			WasmExecutor::new().call(&mut ext, 8, &test_code[..], "test_ed25519_public_keys", &[]).unwrap(),
+			b"no keystore".to_vec()
+		);
+
+		let key = ed25519::Pair::from_seed(&blake2_256(b"test"));
+		let public = key.public();
+		ext.set_offchain_keystore(::std::sync::Arc::new(SingleKeystore(key)));
+		assert_eq!(
+			WasmExecutor::new().call(&mut ext, 8, &test_code[..], "test_ed25519_public_keys", &[]).unwrap(),
+			public.0.to_vec()
+		);
+	}
+
+	#[test]
+	fn ed25519_sign_should_work() {
+		let mut ext = TestExternalities::<Blake2Hasher>::default();
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+		let key = ed25519::Pair::from_seed(&blake2_256(b"test"));
+		let public = key.public();
+		let mut calldata = vec![];
+		calldata.extend_from_slice(public.as_ref());
+		calldata.extend_from_slice(b"all ok!");
+
+		assert_eq!(
+			WasmExecutor::new().call(&mut ext, 8, &test_code[..], "test_ed25519_sign", &calldata).unwrap(),
+			Vec::<u8>::new()
+		);
+
+		ext.set_offchain_keystore(::std::sync::Arc::new(SingleKeystore(key)));
+		let sig = WasmExecutor::new().call(&mut ext, 8, &test_code[..], "test_ed25519_sign", &calldata).unwrap();
+		assert!(ed25519::verify(&sig, b"all ok!", &public));
+
+		// keys the keystore doesn't hold can't be used.
+		let mut calldata = vec![0; 32];
+		calldata.extend_from_slice(b"all ok!");
+		assert_eq!(
+			WasmExecutor::new().call(&mut ext, 8, &test_code[..], "test_ed25519_sign", &calldata).unwrap(),
+			Vec::<u8>::new()
+		);
+	}
+
 	#[test]
 	fn enumerated_trie_root_should_work() {
 		let mut ext = TestExternalities::<Blake2Hasher>::default();
@@ -8,7 +8,7 @@ use alloc::slice;
 
 use runtime_io::{
 	set_storage, storage, clear_prefix, print, blake2_256,
-	twox_128, twox_256, ed25519_verify, enumerated_trie_root
+	twox_128, twox_256, ed25519_verify, ed25519_public_keys, ed25519_sign, enumerated_trie_root
 };
 
 macro_rules! impl_stubs {
@@ -79,6 +79,21 @@ impl_stubs!(
 		let msg = b"all ok!";
 		[ed25519_verify(&sig, &msg[..], &pubkey) as u8].to_vec()
 	},
+	test_ed25519_public_keys => |_| {
+		match ed25519_public_keys() {
+			Some(keys) => keys.iter().flat_map(|key| key.iter().cloned()).collect(),
+			None => b"no keystore".to_vec(),
+		}
+	},
+	test_ed25519_sign => |input: &[u8]| {
+		let mut pubkey = [0; 32];
+		pubkey.copy_from_slice(&input[0..32]);
+
+		match ed25519_sign(&pubkey, &input[32..]) {
+			Some(sig) => sig.to_vec(),
+			None => Vec::new(),
+		}
+	},
 	test_enumerated_trie_root => |_| {
 		enumerated_trie_root::<substrate_primitives::Blake2Hasher>(&[&b"zero"[..], &b"one"[..], &b"two"[..]]).to_vec()
 	},
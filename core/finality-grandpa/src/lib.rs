@@ -121,6 +121,7 @@ mod authorities;
 mod communication;
 mod finality_proof;
 mod until_imported;
+mod voting_rule;
 
 #[cfg(feature="service-integration")]
 mod service_integration;
@@ -128,6 +129,7 @@ mod service_integration;
 pub use service_integration::{LinkHalfForService, BlockImportForService};
 
 pub use finality_proof::{prove_finality, check_finality_proof};
+pub use voting_rule::{VotingRule, BeforeBestBlockBy};
 
 #[cfg(test)]
 mod tests;
@@ -384,6 +386,7 @@ struct Environment<B, E, Block: BlockT, N: Network<Block>, RA> {
 	consensus_changes: SharedConsensusChanges<Block::Hash, NumberFor<Block>>,
 	network: N,
 	set_id: u64,
+	voting_rule: Arc<VotingRule<Block>>,
 }
 
 impl<Block: BlockT<Hash=H256>, B, E, N, RA> grandpa::Chain<Block::Hash, NumberFor<Block>> for Environment<B, E, Block, N, RA> where
@@ -455,7 +458,7 @@ impl<Block: BlockT<Hash=H256>, B, E, N, RA> grandpa::Chain<Block::Hash, NumberFo
 					}
 				}
 
-				let mut best_header = self.inner.header(&BlockId::Hash(best_hash)).ok()?
+				let best_header = self.inner.header(&BlockId::Hash(best_hash)).ok()?
 					.expect("Header known to exist after `best_containing` call; qed");
 
 				// we target a vote towards 3/4 of the unfinalized chain (rounding up)
@@ -474,16 +477,34 @@ impl<Block: BlockT<Hash=H256>, B, E, N, RA> grandpa::Chain<Block::Hash, NumberFo
 				let target = limit.map(|limit| limit.min(target)).unwrap_or(target);
 
 				// walk backwards until we find the target block
+				let mut target_header = best_header.clone();
 				loop {
-					if *best_header.number() < target { unreachable!(); }
-					if *best_header.number() == target {
-						return Some((best_hash, *best_header.number()));
+					if *target_header.number() < target { unreachable!(); }
+					if *target_header.number() == target {
+						break;
 					}
 
-					best_hash = *best_header.parent_hash();
-					best_header = self.inner.header(&BlockId::Hash(best_hash)).ok()?
+					best_hash = *target_header.parent_hash();
+					target_header = self.inner.header(&BlockId::Hash(best_hash)).ok()?
 						.expect("Header known to exist after `best_containing` call; qed");
 				}
+
+				// restrict vote according to the given voting rule, restrictions
+				// beyond the base or the computed target are ignored.
+				let restricted_target = self.voting_rule.restrict_vote(
+					self.inner.backend().blockchain(),
+					&base_header,
+					&best_header,
+					&target_header,
+				).filter(|(_, number)| *number >= *base_header.number() && *number < target);
+
+				match restricted_target {
+					Some((hash, number)) => {
+						debug!(target: "afg", "Vote target restricted by voting rule from #{} to #{}", target, number);
+						Some((hash, number))
+					},
+					None => Some((best_hash, target)),
+				}
 			},
 			Ok(None) => {
 				debug!(target: "afg", "Encountered error finding best chain containing {:?}: couldn't find target block", block);
@@ -506,6 +527,17 @@ struct NewAuthoritySet<H, N> {
 	authorities: Vec<(Ed25519AuthorityId, u64)>,
 }
 
+/// Commands issued to the background voter.
+#[derive(Debug)]
+enum VoterCommand<H, N> {
+	/// Restart the voter with a new authority set.
+	ChangeAuthorities(NewAuthoritySet<H, N>),
+	/// Pause the voter for the given reason.
+	Pause(String),
+	/// Resume a paused voter.
+	Resume,
+}
+
 /// Signals either an early exit of a voter or an error.
 #[derive(Debug)]
 enum ExitOrError<H, N> {
@@ -1045,7 +1077,7 @@ fn finalize_block<B, Block: BlockT<Hash=H256>, E, RA>(
 pub struct GrandpaBlockImport<B, E, Block: BlockT<Hash=H256>, RA, PRA> {
 	inner: Arc<Client<B, E, Block, RA>>,
 	authority_set: SharedAuthoritySet<Block::Hash, NumberFor<Block>>,
-	authority_set_change: mpsc::UnboundedSender<NewAuthoritySet<Block::Hash, NumberFor<Block>>>,
+	send_voter_commands: mpsc::UnboundedSender<VoterCommand<Block::Hash, NumberFor<Block>>>,
 	consensus_changes: SharedConsensusChanges<Block::Hash, NumberFor<Block>>,
 	api: Arc<PRA>,
 }
@@ -1082,7 +1114,7 @@ impl<B, E, Block: BlockT<Hash=H256>, RA, PRA> JustificationImport<Block>
 				if let Ok(Some(hash)) = effective_block_hash {
 					if let Ok(Some(header)) = self.inner.header(&BlockId::Hash(hash)) {
 						if *header.number() == pending_change.effective_number() {
-							link.request_justification(&header.hash(), *header.number());
+							link.request_justification(&header.hash(), *header.number(), true);
 						}
 					}
 				}
@@ -1287,7 +1319,7 @@ impl<B, E, Block: BlockT<Hash=H256>, RA, PRA>
 		match result {
 			Err(ExitOrError::AuthoritiesChanged(new)) => {
 				info!(target: "finality", "Imported justification for block #{} that enacts authority set change, signalling voter.", number);
-				if let Err(e) = self.authority_set_change.unbounded_send(new) {
+				if let Err(e) = self.send_voter_commands.unbounded_send(VoterCommand::ChangeAuthorities(new)) {
 					return Err(ConsensusErrorKind::ClientImport(e.to_string()).into());
 				}
 			},
@@ -1353,10 +1385,68 @@ fn canonical_at_height<B, E, Block: BlockT<Hash=H256>, RA>(
 pub struct LinkHalf<B, E, Block: BlockT<Hash=H256>, RA> {
 	client: Arc<Client<B, E, Block, RA>>,
 	authority_set: SharedAuthoritySet<Block::Hash, NumberFor<Block>>,
-	authority_set_change: mpsc::UnboundedReceiver<NewAuthoritySet<Block::Hash, NumberFor<Block>>>,
+	voter_commands_rx: mpsc::UnboundedReceiver<VoterCommand<Block::Hash, NumberFor<Block>>>,
+	voter_handle: VoterHandle<Block::Hash, NumberFor<Block>>,
 	consensus_changes: SharedConsensusChanges<Block::Hash, NumberFor<Block>>,
 }
 
+impl<B, E, Block: BlockT<Hash=H256>, RA> LinkHalf<B, E, Block, RA> {
+	/// Get a handle that can be used to pause and resume the voter.
+	pub fn voter_handle(&self) -> VoterHandle<Block::Hash, NumberFor<Block>> {
+		self.voter_handle.clone()
+	}
+}
+
+/// A handle to pause and resume a running GRANDPA voter, e.g. to hold back
+/// finality during incidents without stopping block production.
+///
+/// While paused the voter doesn't participate in any rounds, but authority set
+/// changes are still followed.
+pub struct VoterHandle<H, N> {
+	commands: mpsc::UnboundedSender<VoterCommand<H, N>>,
+	paused: Arc<parking_lot::Mutex<bool>>,
+}
+
+impl<H, N> Clone for VoterHandle<H, N> {
+	fn clone(&self) -> Self {
+		VoterHandle {
+			commands: self.commands.clone(),
+			paused: self.paused.clone(),
+		}
+	}
+}
+
+impl<H, N> VoterHandle<H, N> {
+	/// Pause the voter. Does nothing if the voter is already paused.
+	pub fn pause(&self, reason: String) {
+		let mut paused = self.paused.lock();
+		if *paused {
+			return;
+		}
+
+		if self.commands.unbounded_send(VoterCommand::Pause(reason)).is_ok() {
+			*paused = true;
+		}
+	}
+
+	/// Resume the voter. Does nothing if the voter isn't paused.
+	pub fn resume(&self) {
+		let mut paused = self.paused.lock();
+		if !*paused {
+			return;
+		}
+
+		if self.commands.unbounded_send(VoterCommand::Resume).is_ok() {
+			*paused = false;
+		}
+	}
+
+	/// Whether the voter is currently paused.
+	pub fn is_paused(&self) -> bool {
+		*self.paused.lock()
+	}
+}
+
 struct AncestryChain<Block: BlockT> {
 	ancestry: HashMap<Block::Hash, Block::Header>,
 }
@@ -1446,20 +1536,25 @@ pub fn block_import<B, E, Block: BlockT<Hash=H256>, RA, PRA>(
 		None => ConsensusChanges::empty(),
 	}));
 
-	let (authority_set_change_tx, authority_set_change_rx) = mpsc::unbounded();
+	let (voter_commands_tx, voter_commands_rx) = mpsc::unbounded();
+	let voter_handle = VoterHandle {
+		commands: voter_commands_tx.clone(),
+		paused: Arc::new(parking_lot::Mutex::new(false)),
+	};
 
 	Ok((
 		GrandpaBlockImport {
 			inner: client.clone(),
 			authority_set: authority_set.clone(),
-			authority_set_change: authority_set_change_tx,
+			send_voter_commands: voter_commands_tx,
 			consensus_changes: consensus_changes.clone(),
 			api
 		},
 		LinkHalf {
 			client,
 			authority_set,
-			authority_set_change: authority_set_change_rx,
+			voter_commands_rx,
+			voter_handle,
 			consensus_changes,
 		},
 	))
@@ -1518,6 +1613,26 @@ fn committer_communication<Block: BlockT<Hash=H256>, B, E, N, RA>(
 	(commit_in, commit_out)
 }
 
+/// Load the last completed round of the current authority set from the backend,
+/// defaulting to the genesis round if no round has completed yet.
+fn load_last_completed<B, E, Block: BlockT<Hash=H256>, RA>(
+	client: &Client<B, E, Block, RA>,
+	genesis_hash: Block::Hash,
+) -> ::client::error::Result<LastCompleted<Block::Hash, NumberFor<Block>>> where
+	B: Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher>,
+{
+	use runtime_primitives::traits::Zero;
+
+	match Backend::get_aux(&**client.backend(), LAST_COMPLETED_KEY)? {
+		None => Ok((0, RoundState::genesis((genesis_hash, <NumberFor<Block>>::zero())))),
+		Some(raw) => LastCompleted::decode(&mut &raw[..])
+			.ok_or_else(|| ::client::error::ErrorKind::Backend(
+				format!("Last GRANDPA round state kept in invalid format")
+			).into())
+	}
+}
+
 /// Run a GRANDPA voter as a task. Provide configuration and a link to a
 /// block import worker that has already been instantiated with `block_import`.
 pub fn run_grandpa<B, E, Block: BlockT<Hash=H256>, N, RA>(
@@ -1535,14 +1650,38 @@ pub fn run_grandpa<B, E, Block: BlockT<Hash=H256>, N, RA>(
 	DigestFor<Block>: Encode,
 	DigestItemFor<Block>: DigestItem<AuthorityId=Ed25519AuthorityId>,
 	RA: Send + Sync + 'static,
+{
+	run_grandpa_voter(config, link, network, (), on_exit)
+}
+
+/// Run a GRANDPA voter as a task, restricting the blocks it votes for with the
+/// given voting rule. Provide configuration and a link to a block import worker
+/// that has already been instantiated with `block_import`.
+pub fn run_grandpa_voter<B, E, Block: BlockT<Hash=H256>, N, RA, VR>(
+	config: Config,
+	link: LinkHalf<B, E, Block, RA>,
+	network: N,
+	voting_rule: VR,
+	on_exit: impl Future<Item=(),Error=()> + Send + 'static,
+) -> ::client::error::Result<impl Future<Item=(),Error=()> + Send + 'static> where
+	Block::Hash: Ord,
+	B: Backend<Block, Blake2Hasher> + 'static,
+	E: CallExecutor<Block, Blake2Hasher> + Send + Sync + 'static,
+	N: Network<Block> + Send + Sync + 'static,
+	N::In: Send + 'static,
+	NumberFor<Block>: BlockNumberOps,
+	DigestFor<Block>: Encode,
+	DigestItemFor<Block>: DigestItem<AuthorityId=Ed25519AuthorityId>,
+	RA: Send + Sync + 'static,
+	VR: VotingRule<Block> + 'static,
 {
 	use futures::future::{self, Loop as FutureLoop};
-	use runtime_primitives::traits::Zero;
 
 	let LinkHalf {
 		client,
 		authority_set,
-		authority_set_change,
+		voter_commands_rx,
+		voter_handle,
 		consensus_changes,
 	} = link;
 
@@ -1553,15 +1692,10 @@ pub fn run_grandpa<B, E, Block: BlockT<Hash=H256>, N, RA>(
 	// accidental reuse.
 	let (broadcast_worker, network) = communication::rebroadcasting_network(network);
 
-	let (last_round_number, last_state) = match Backend::get_aux(&**client.backend(), LAST_COMPLETED_KEY)? {
-		None => (0, RoundState::genesis((genesis_hash, <NumberFor<Block>>::zero()))),
-		Some(raw) => LastCompleted::decode(&mut &raw[..])
-			.ok_or_else(|| ::client::error::ErrorKind::Backend(
-				format!("Last GRANDPA round state kept in invalid format")
-			))?
-	};
+	let (last_round_number, last_state) = load_last_completed(&*client, genesis_hash)?;
 
 	let voters = authority_set.current_authorities();
+	let voting_rule: Arc<VotingRule<Block>> = Arc::new(voting_rule);
 
 	let initial_environment = Arc::new(Environment {
 		inner: client.clone(),
@@ -1571,48 +1705,63 @@ pub fn run_grandpa<B, E, Block: BlockT<Hash=H256>, N, RA>(
 		set_id: authority_set.set_id(),
 		authority_set: authority_set.clone(),
 		consensus_changes: consensus_changes.clone(),
+		voting_rule: voting_rule.clone(),
 	});
 
-	let initial_state = (initial_environment, last_round_number, last_state, authority_set_change.into_future());
+	let initial_state = (
+		initial_environment,
+		last_round_number,
+		last_state,
+		voter_commands_rx.into_future(),
+		voter_handle.is_paused(),
+	);
 	let voter_work = future::loop_fn(initial_state, move |params| {
-		let (env, last_round_number, last_state, authority_set_change) = params;
-		debug!(target: "afg", "{}: Starting new voter with set ID {}", config.name(), env.set_id);
+		let (env, last_round_number, last_state, voter_commands_rx, paused) = params;
 
-		let chain_info = match client.info() {
-			Ok(i) => i,
-			Err(e) => return future::Either::B(future::err(Error::Client(e))),
-		};
+		let voter = if paused {
+			debug!(target: "afg", "{}: Voter with set ID {} is paused", config.name(), env.set_id);
+			future::Either::B(future::empty())
+		} else {
+			debug!(target: "afg", "{}: Starting new voter with set ID {}", config.name(), env.set_id);
 
-		let last_finalized = (
-			chain_info.chain.finalized_hash,
-			chain_info.chain.finalized_number,
-		);
+			let chain_info = match client.info() {
+				Ok(i) => i,
+				Err(e) => return future::Either::B(future::err(Error::Client(e))),
+			};
 
-		let committer_data = committer_communication(
-			config.local_key.clone(),
-			env.set_id,
-			&env.voters,
-			&client,
-			&network,
-		);
+			let last_finalized = (
+				chain_info.chain.finalized_hash,
+				chain_info.chain.finalized_number,
+			);
 
-		let voters = (*env.voters).clone();
+			let committer_data = committer_communication(
+				config.local_key.clone(),
+				env.set_id,
+				&env.voters,
+				&client,
+				&network,
+			);
 
-		let voter = voter::Voter::new(
-			env,
-			voters,
-			committer_data,
-			last_round_number,
-			last_state,
-			last_finalized,
-		);
+			let voters = (*env.voters).clone();
+
+			future::Either::A(voter::Voter::new(
+				env.clone(),
+				voters,
+				committer_data,
+				last_round_number,
+				last_state,
+				last_finalized,
+			))
+		};
 		let client = client.clone();
 		let config = config.clone();
 		let network = network.clone();
 		let authority_set = authority_set.clone();
 		let consensus_changes = consensus_changes.clone();
+		let voting_rule = voting_rule.clone();
+		let resume_client = client.clone();
 
-		let trigger_authority_set_change = |new: NewAuthoritySet<_, _>, authority_set_change| {
+		let trigger_authority_set_change = |new: NewAuthoritySet<_, _>, voter_commands_rx, paused| {
 			let env = Arc::new(Environment {
 				inner: client,
 				config,
@@ -1621,6 +1770,7 @@ pub fn run_grandpa<B, E, Block: BlockT<Hash=H256>, N, RA>(
 				network,
 				authority_set,
 				consensus_changes,
+				voting_rule,
 			});
 
 			// start the new authority set using the block where the
@@ -1629,34 +1779,62 @@ pub fn run_grandpa<B, E, Block: BlockT<Hash=H256>, N, RA>(
 				env,
 				0, // always start at round 0 when changing sets.
 				RoundState::genesis((new.canon_hash, new.canon_number)),
-				authority_set_change,
+				voter_commands_rx,
+				paused,
 			)))
 		};
 
-		future::Either::A(voter.select2(authority_set_change).then(move |res| match res {
+		future::Either::A(voter.select2(voter_commands_rx).then(move |res| match res {
 			Ok(future::Either::A(((), _))) => {
 				// voters don't conclude naturally; this could reasonably be an error.
 				Ok(FutureLoop::Break(()))
 			},
 			Err(future::Either::B(_)) => {
-				// the `authority_set_change` stream should not fail.
+				// the `voter_commands_rx` stream should not fail.
 				Ok(FutureLoop::Break(()))
 			},
 			Ok(future::Either::B(((None, _), _))) => {
-				// the `authority_set_change` stream should never conclude since it's never closed.
+				// the `voter_commands_rx` stream should never conclude since it's never closed.
 				Ok(FutureLoop::Break(()))
 			},
 			Err(future::Either::A((ExitOrError::Error(e), _))) => {
 				// return inner voter error
 				Err(e)
 			}
-			Ok(future::Either::B(((Some(new), authority_set_change), _))) => {
+			Ok(future::Either::B(((Some(VoterCommand::ChangeAuthorities(new)), voter_commands_rx), _))) => {
 				// authority set change triggered externally through the channel
-				trigger_authority_set_change(new, authority_set_change.into_future())
+				trigger_authority_set_change(new, voter_commands_rx.into_future(), paused)
+			}
+			Ok(future::Either::B(((Some(VoterCommand::Pause(reason)), voter_commands_rx), _))) => {
+				info!(target: "afg", "Pausing GRANDPA voter: {}", reason);
+
+				// the round state is reloaded from the backend once resumed.
+				let (last_round_number, last_state) = load_last_completed(&*resume_client, genesis_hash)?;
+				Ok(FutureLoop::Continue((
+					env,
+					last_round_number,
+					last_state,
+					voter_commands_rx.into_future(),
+					true,
+				)))
+			}
+			Ok(future::Either::B(((Some(VoterCommand::Resume), voter_commands_rx), _))) => {
+				info!(target: "afg", "Resuming GRANDPA voter");
+
+				// resume from the last completed round that was persisted,
+				// rounds might have been completed before pausing.
+				let (last_round_number, last_state) = load_last_completed(&*resume_client, genesis_hash)?;
+				Ok(FutureLoop::Continue((
+					env,
+					last_round_number,
+					last_state,
+					voter_commands_rx.into_future(),
+					false,
+				)))
 			}
-			Err(future::Either::A((ExitOrError::AuthoritiesChanged(new), authority_set_change))) => {
+			Err(future::Either::A((ExitOrError::AuthoritiesChanged(new), voter_commands_rx))) => {
 				// authority set change triggered internally by finalizing a change block
-				trigger_authority_set_change(new, authority_set_change)
+				trigger_authority_set_change(new, voter_commands_rx, paused)
 			},
 		}))
 	});
@@ -37,4 +37,14 @@ pub type LinkHalfForService<F> = ::LinkHalf<
 	FullExecutor<F>,
 	<F as ServiceFactory>::Block,
 	<F as ServiceFactory>::RuntimeApi
->;
\ No newline at end of file
+>;
+
+impl<H: Send, N: Send> service::FinalityVoterControl for ::VoterHandle<H, N> {
+	fn pause(&self, reason: String) {
+		::VoterHandle::pause(self, reason)
+	}
+
+	fn resume(&self) {
+		::VoterHandle::resume(self)
+	}
+}
@@ -91,6 +91,7 @@ impl TestNetFactory for GrandpaTestNet {
 		// the authority role ensures gossip hits all nodes here.
 		ProtocolConfig {
 			roles: Roles::AUTHORITY,
+			..Default::default()
 		}
 	}
 
@@ -772,3 +773,37 @@ fn doesnt_vote_on_the_tip_of_the_chain() {
 	// the highest block to be finalized will be 3/4 deep in the unfinalized chain
 	assert_eq!(highest, 75);
 }
+
+#[test]
+fn voting_rules_restrict_vote_target() {
+	let peers = &[Keyring::Alice];
+	let voters = make_ids(peers);
+
+	let mut net = GrandpaTestNet::new(TestApi::new(voters), 1);
+	net.peer(0).push_blocks(20, false);
+
+	let client = net.peer(0).client().clone();
+	let backend = client.backend().blockchain();
+	let header = |n: u64| client.header(&BlockId::Number(n)).unwrap().unwrap();
+
+	// the vote is kept at least 5 blocks behind the best block
+	let rule = BeforeBestBlockBy(5u64);
+	assert_eq!(
+		rule.restrict_vote(backend, &header(0), &header(20), &header(18)),
+		Some((header(15).hash(), 15)),
+	);
+
+	// targets that are already far enough behind aren't restricted
+	assert_eq!(rule.restrict_vote(backend, &header(0), &header(20), &header(15)), None);
+
+	// composed rules apply the strictest restriction
+	let rules: Vec<Box<VotingRule<Block>>> = vec![
+		Box::new(BeforeBestBlockBy(2u64)),
+		Box::new(BeforeBestBlockBy(5u64)),
+		Box::new(()),
+	];
+	assert_eq!(
+		rules.restrict_vote(backend, &header(0), &header(20), &header(20)),
+		Some((header(15).hash(), 15)),
+	);
+}
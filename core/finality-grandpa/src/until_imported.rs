@@ -449,6 +449,8 @@ mod tests {
 				origin: BlockOrigin::File,
 				header,
 				is_new_best: false,
+				retracted: Vec::new(),
+				stats: Default::default(),
 			}).unwrap();
 		}
 	}
@@ -0,0 +1,145 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Handling custom voting rules for GRANDPA.
+//!
+//! This exposes the `VotingRule` trait used to implement arbitrary voting
+//! restrictions that are taken into account by the GRANDPA environment when
+//! selecting a finality target to vote on.
+
+use client::blockchain::HeaderBackend;
+use runtime_primitives::generic::BlockId;
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, NumberFor, Zero};
+
+/// A trait for custom voting rules in GRANDPA.
+pub trait VotingRule<Block: BlockT>: Send + Sync {
+	/// Restrict the given `current_target` vote, returning the block hash and
+	/// number of the block to vote on, and `None` in case the vote should not
+	/// be restricted. `base` is the block that we're basing our votes on in
+	/// order to pick our target (e.g. last round estimate), and `best_target`
+	/// is the initial best vote target before any vote rules were applied.
+	///
+	/// Returned targets that are not between `base` and `current_target` are
+	/// ignored.
+	fn restrict_vote(
+		&self,
+		backend: &HeaderBackend<Block>,
+		base: &Block::Header,
+		best_target: &Block::Header,
+		current_target: &Block::Header,
+	) -> Option<(Block::Hash, NumberFor<Block>)>;
+}
+
+impl<Block: BlockT> VotingRule<Block> for () {
+	fn restrict_vote(
+		&self,
+		_backend: &HeaderBackend<Block>,
+		_base: &Block::Header,
+		_best_target: &Block::Header,
+		_current_target: &Block::Header,
+	) -> Option<(Block::Hash, NumberFor<Block>)> {
+		None
+	}
+}
+
+/// A custom voting rule that guarantees that our vote is always behind the best
+/// block by at least N blocks. In the best case our vote is exactly N blocks
+/// behind the best block.
+#[derive(Clone, Debug)]
+pub struct BeforeBestBlockBy<N>(pub N);
+
+impl<Block: BlockT> VotingRule<Block> for BeforeBestBlockBy<NumberFor<Block>> {
+	fn restrict_vote(
+		&self,
+		backend: &HeaderBackend<Block>,
+		_base: &Block::Header,
+		best_target: &Block::Header,
+		current_target: &Block::Header,
+	) -> Option<(Block::Hash, NumberFor<Block>)> {
+		if self.0.is_zero() {
+			return None;
+		}
+
+		// find the target number restricted by this rule
+		let target_number = if *best_target.number() > self.0 {
+			*best_target.number() - self.0
+		} else {
+			Zero::zero()
+		};
+
+		// our current target is already lower than this rule would restrict
+		if target_number >= *current_target.number() {
+			return None;
+		}
+
+		find_target(backend, target_number, current_target)
+	}
+}
+
+/// A set of voting rules that are applied in order, each one restricting the
+/// target picked by the previous ones.
+impl<Block: BlockT> VotingRule<Block> for Vec<Box<VotingRule<Block>>> {
+	fn restrict_vote(
+		&self,
+		backend: &HeaderBackend<Block>,
+		base: &Block::Header,
+		best_target: &Block::Header,
+		current_target: &Block::Header,
+	) -> Option<(Block::Hash, NumberFor<Block>)> {
+		let mut restricted_target = current_target.clone();
+
+		for rule in self {
+			let restricted = rule.restrict_vote(backend, base, best_target, &restricted_target)
+				.filter(|(_, number)| *number < *restricted_target.number())
+				.and_then(|(hash, _)| backend.header(BlockId::Hash(hash)).ok().and_then(|h| h));
+
+			if let Some(header) = restricted {
+				restricted_target = header;
+			}
+		}
+
+		if restricted_target.hash() == current_target.hash() {
+			None
+		} else {
+			Some((restricted_target.hash(), *restricted_target.number()))
+		}
+	}
+}
+
+/// Walk backwards from `current_header` until the block with the given
+/// `target_number` is found.
+fn find_target<Block: BlockT>(
+	backend: &HeaderBackend<Block>,
+	target_number: NumberFor<Block>,
+	current_header: &Block::Header,
+) -> Option<(Block::Hash, NumberFor<Block>)> {
+	let mut target_hash = current_header.hash();
+	let mut target_header = current_header.clone();
+
+	loop {
+		if *target_header.number() < target_number {
+			return None;
+		}
+
+		if *target_header.number() == target_number {
+			return Some((target_hash, target_number));
+		}
+
+		target_hash = *target_header.parent_hash();
+		target_header = backend.header(BlockId::Hash(target_hash)).ok()?
+			.expect("Header known to exist due to being the ancestor of a known block; qed");
+	}
+}
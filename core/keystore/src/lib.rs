@@ -22,6 +22,7 @@
 
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Mutex;
 use std::fs::{self, File};
 use std::io::{self, Write};
 
@@ -30,6 +31,7 @@ use error_chain::{error_chain, error_chain_processing, impl_error_chain_processe
 	impl_extract_backtrace, impl_error_chain_kind};
 
 use substrate_primitives::{hashing::blake2_256, ed25519::{Pair, Public, PKCS_LEN}};
+use substrate_state_machine::OffchainKeystore;
 
 pub use crypto::KEY_ITERATIONS;
 
@@ -117,8 +119,11 @@ type Seed = [u8; 32];
 
 /// Key store.
 pub struct Store {
-	path: PathBuf,
+	path: Option<PathBuf>,
 	additional: HashMap<Public, Seed>,
+	/// Keys tried with the empty password for offchain signing, `None` for the ones locked
+	/// with another password. Decrypting a key file is slow, so each key is tried once.
+	offchain_keys: Mutex<HashMap<Public, Option<Pair>>>,
 }
 
 pub fn pad_seed(seed:  &str) -> Seed {
@@ -143,11 +148,23 @@ impl Store {
 	/// Create a new store at the given path.
 	pub fn open(path: PathBuf) -> Result<Self> {
 		fs::create_dir_all(&path)?;
-		Ok(Store { path, additional: HashMap::new() })
+		Ok(Store { path: Some(path), additional: HashMap::new(), offchain_keys: Default::default() })
+	}
+
+	/// Create a new store that keeps all keys in memory. Nothing is written to disk.
+	pub fn new_in_memory() -> Self {
+		Store { path: None, additional: HashMap::new(), offchain_keys: Default::default() }
 	}
 
 	/// Generate a new key, placing it into the store.
-	pub fn generate(&self, password: &str) -> Result<Pair> {
+	pub fn generate(&mut self, password: &str) -> Result<Pair> {
+		if self.path.is_none() {
+			let seed: Seed = rand::random();
+			let pair = Pair::from_seed(&seed);
+			self.additional.insert(pair.public(), seed);
+			return Ok(pair);
+		}
+
 		let (pair, pkcs_bytes) = Pair::generate_with_pkcs8();
 		let key_file = EncryptedKey::encrypt(&pkcs_bytes, password, KEY_ITERATIONS as u32);
 
@@ -175,7 +192,10 @@ impl Store {
 			let pair = Pair::from_seed(seed);
 			return Ok(pair);
 		}
-		let path = self.key_file_path(public);
+		let path = match self.path {
+			Some(_) => self.key_file_path(public),
+			None => return Err(io::Error::from(io::ErrorKind::NotFound).into()),
+		};
 		let file = File::open(path)?;
 
 		let encrypted_key: EncryptedKey = ::serde_json::from_reader(&file)?;
@@ -187,7 +207,11 @@ impl Store {
 	/// Get public keys of all stored keys.
 	pub fn contents(&self) -> Result<Vec<Public>> {
 		let mut public_keys: Vec<Public> = self.additional.keys().cloned().collect();
-		for entry in fs::read_dir(&self.path)? {
+		let path = match self.path {
+			Some(ref path) => path,
+			None => return Ok(public_keys),
+		};
+		for entry in fs::read_dir(path)? {
 			let entry = entry?;
 			let path = entry.path();
 
@@ -210,13 +234,46 @@ impl Store {
 		Ok(public_keys)
 	}
 
+	/// Calls `f` with the key unlocked with the empty password, if any.
+	fn with_offchain_key<R>(&self, public: &Public, f: impl FnOnce(&Pair) -> R) -> Option<R> {
+		let mut keys = self.offchain_keys.lock().expect("offchain keys lock is never poisoned; qed");
+		if !keys.contains_key(public) {
+			let pair = match self.load(public, "") {
+				Ok(pair) => Some(pair),
+				Err(Error(ErrorKind::InvalidPassword, _)) => None,
+				// unknown keys aren't remembered.
+				Err(_) => return None,
+			};
+			keys.insert(public.clone(), pair);
+		}
+		keys[public].as_ref().map(f)
+	}
+
 	fn key_file_path(&self, public: &Public) -> PathBuf {
-		let mut buf = self.path.clone();
+		let mut buf = self.path.clone().expect("only called for stores backed by a directory; qed");
 		buf.push(hex::encode(public.as_slice()));
 		buf
 	}
 }
 
+/// Offchain execution contexts can only see and sign with the keys of the store that are
+/// unlocked with the empty password, like the ones the service generates.
+impl OffchainKeystore for Store {
+	fn ed25519_public_keys(&self) -> Vec<[u8; 32]> {
+		self.contents()
+			.map(|keys| keys.into_iter()
+				.filter(|key| self.with_offchain_key(key, |_| ()).is_some())
+				.map(|key| key.0)
+				.collect()
+			)
+			.unwrap_or_default()
+	}
+
+	fn ed25519_sign(&self, public: &[u8; 32], msg: &[u8]) -> Option<[u8; 64]> {
+		self.with_offchain_key(&Public(*public), |pair| pair.sign(msg).to_fixed_bytes())
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -253,7 +310,7 @@ mod tests {
 	#[test]
 	fn basic_store() {
 		let temp_dir = TempDir::new("keystore").unwrap();
-		let store = Store::open(temp_dir.path().to_owned()).unwrap();
+		let mut store = Store::open(temp_dir.path().to_owned()).unwrap();
 
 		assert!(store.contents().unwrap().is_empty());
 
@@ -267,6 +324,41 @@ mod tests {
 		assert_eq!(store.contents().unwrap()[0], key.public());
 	}
 
+	#[test]
+	fn in_memory_store() {
+		let mut store = Store::new_in_memory();
+
+		assert!(store.contents().unwrap().is_empty());
+
+		let key = store.generate("").unwrap();
+		let key2 = store.load(&key.public(), "").unwrap();
+
+		assert_eq!(key.public(), key2.public());
+		assert_eq!(store.contents().unwrap(), vec![key.public()]);
+	}
+
+	#[test]
+	fn offchain_signing() {
+		let temp_dir = TempDir::new("keystore").unwrap();
+		let mut store = Store::open(temp_dir.path().to_owned()).unwrap();
+		let key = store.generate("").unwrap();
+		let locked = store.generate("thepassword").unwrap();
+
+		// keys locked with a password can't be used, so they aren't listed.
+		assert_eq!(store.ed25519_public_keys(), vec![key.public().0]);
+
+		let sig = store.ed25519_sign(&key.public().0, b"all ok!").unwrap();
+		assert!(substrate_primitives::ed25519::verify(&sig[..], b"all ok!", key.public()));
+		assert!(store.ed25519_sign(&locked.public().0, b"all ok!").is_none());
+		assert!(store.ed25519_sign(&[0; 32], b"all ok!").is_none());
+
+		// keys are decrypted once, unknown ones aren't remembered.
+		let offchain_keys = store.offchain_keys.lock().unwrap();
+		assert_eq!(offchain_keys.len(), 2);
+		assert!(offchain_keys[&key.public()].is_some());
+		assert!(offchain_keys[&locked.public()].is_none());
+	}
+
 	#[test]
 	fn test_generate_from_seed() {
 		let temp_dir = TempDir::new("keystore").unwrap();
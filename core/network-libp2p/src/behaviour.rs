@@ -18,12 +18,13 @@ use crate::custom_proto::{CustomProtos, CustomProtosOut, RegisteredProtocols};
 use crate::{NetworkConfiguration, ProtocolId};
 use bytes::Bytes;
 use futures::prelude::*;
-use libp2p::NetworkBehaviour;
-use libp2p::core::{PeerId, ProtocolsHandler};
+use libp2p::{Multiaddr, NetworkBehaviour};
+use libp2p::core::{PeerId, ProtocolsHandler, protocols_handler::DummyProtocolsHandler};
 use libp2p::core::swarm::{ConnectedPoint, NetworkBehaviour, NetworkBehaviourAction};
 use libp2p::core::swarm::{NetworkBehaviourEventProcess, PollParameters};
 use libp2p::identify::{Identify, IdentifyEvent, protocol::IdentifyInfo};
 use libp2p::kad::{Kademlia, KademliaOut, KademliaTopology};
+use libp2p::mdns::{Mdns, MdnsTopology};
 use libp2p::ping::{Ping, PingEvent};
 use log::{debug, trace, warn};
 use std::{cmp, io, time::Duration, time::Instant};
@@ -43,6 +44,8 @@ pub struct Behaviour<TSubstream> {
 	discovery: DiscoveryBehaviour<TSubstream>,
 	/// Periodically identifies the remote and responds to incoming requests.
 	identify: Identify<TSubstream>,
+	/// Discovers nodes of the local network. Defined below.
+	mdns: MdnsBehaviour<TSubstream>,
 
 	/// Queue of events to produce for the outside.
 	#[behaviour(ignore)]
@@ -64,6 +67,7 @@ impl<TSubstream> Behaviour<TSubstream> {
 			custom_protocols: CustomProtos::new(config, protocols),
 			discovery: DiscoveryBehaviour::new(local_peer_id),
 			identify,
+			mdns: MdnsBehaviour::new(config.enable_mdns),
 			events: Vec::new(),
 		}
 	}
@@ -92,6 +96,16 @@ impl<TSubstream> Behaviour<TSubstream> {
 		self.custom_protocols.remove_reserved_peer(peer_id)
 	}
 
+	/// Returns true if the peer is reserved.
+	pub fn is_reserved_peer(&self, peer_id: &PeerId) -> bool {
+		self.custom_protocols.is_reserved_peer(peer_id)
+	}
+
+	/// Returns true if we only accept reserved nodes.
+	pub fn is_reserved_only(&self) -> bool {
+		self.custom_protocols.is_reserved_only()
+	}
+
 	/// Start accepting all peers again if we weren't.
 	pub fn accept_unreserved_peers(&mut self) {
 		self.custom_protocols.accept_unreserved_peers()
@@ -102,6 +116,32 @@ impl<TSubstream> Behaviour<TSubstream> {
 		self.custom_protocols.deny_unreserved_peers()
 	}
 
+	/// Returns the maximum number of incoming and outgoing non-reserved connections.
+	pub fn peer_slots(&self) -> (usize, usize) {
+		self.custom_protocols.peer_slots()
+	}
+
+	/// Changes the maximum number of incoming and outgoing non-reserved connections. The
+	/// excess peers with the lowest `reputation` are disconnected.
+	pub fn set_peer_slots(&mut self, in_peers: usize, out_peers: usize, reputation: impl Fn(&PeerId) -> i32) {
+		self.custom_protocols.set_peer_slots(in_peers, out_peers, reputation)
+	}
+
+	/// Returns true if we discover the nodes of the local network through mDNS.
+	pub fn is_mdns_enabled(&self) -> bool {
+		self.mdns.is_enabled()
+	}
+
+	/// Starts discovering the nodes of the local network through mDNS.
+	pub fn enable_mdns(&mut self) -> Result<(), io::Error> {
+		self.mdns.enable()
+	}
+
+	/// Stops discovering the nodes of the local network through mDNS.
+	pub fn disable_mdns(&mut self) {
+		self.mdns.disable()
+	}
+
 	/// Disconnects a peer and bans it for a little while.
 	///
 	/// Same as `drop_node`, except that the same peer will not be able to reconnect later.
@@ -176,6 +216,8 @@ pub enum BehaviourOut {
 		peer_id: PeerId,
 		/// Information about the peer.
 		info: IdentifyInfo,
+		/// Address the peer sees us at.
+		observed_addr: Multiaddr,
 	},
 }
 
@@ -213,12 +255,13 @@ impl<TSubstream> NetworkBehaviourEventProcess<CustomProtosOut> for Behaviour<TSu
 impl<TSubstream> NetworkBehaviourEventProcess<IdentifyEvent> for Behaviour<TSubstream> {
 	fn inject_event(&mut self, event: IdentifyEvent) {
 		match event {
-			IdentifyEvent::Identified { peer_id, info, .. } => {
-				trace!(target: "sub-libp2p", "Identified {:?} => {:?}", peer_id, info);
+			IdentifyEvent::Identified { peer_id, info, observed_addr } => {
+				trace!(target: "sub-libp2p", "Identified {:?} => {:?}, observed us at {}",
+					peer_id, info, observed_addr);
 				// TODO: ideally we would delay the first identification to when we open the custom
 				//	protocol, so that we only report id info to the service about the nodes we
 				//	care about (https://github.com/libp2p/rust-libp2p/issues/876)
-				self.events.push(BehaviourOut::Identified { peer_id, info });
+				self.events.push(BehaviourOut::Identified { peer_id, info, observed_addr });
 			}
 			IdentifyEvent::Error { .. } => {}
 		}
@@ -353,3 +396,76 @@ where
 	}
 }
 
+/// Implementation of `NetworkBehaviour` that discovers the nodes on the local network through
+/// mDNS. Can be enabled and disabled at runtime.
+pub struct MdnsBehaviour<TSubstream> {
+	/// The mDNS service, or `None` if disabled.
+	mdns: Option<Mdns<TSubstream>>,
+}
+
+impl<TSubstream> MdnsBehaviour<TSubstream> {
+	fn new(enabled: bool) -> Self {
+		let mut behaviour = MdnsBehaviour { mdns: None };
+		if enabled {
+			if let Err(err) = behaviour.enable() {
+				warn!(target: "sub-libp2p", "Failed to start mDNS discovery: {:?}", err);
+			}
+		}
+		behaviour
+	}
+
+	fn is_enabled(&self) -> bool {
+		self.mdns.is_some()
+	}
+
+	fn enable(&mut self) -> Result<(), io::Error> {
+		if self.mdns.is_none() {
+			self.mdns = Some(Mdns::new()?);
+		}
+		Ok(())
+	}
+
+	fn disable(&mut self) {
+		self.mdns = None;
+	}
+}
+
+impl<TSubstream, TTopology> NetworkBehaviour<TTopology> for MdnsBehaviour<TSubstream>
+where
+	TSubstream: AsyncRead + AsyncWrite,
+	TTopology: MdnsTopology,
+{
+	type ProtocolsHandler = DummyProtocolsHandler<TSubstream>;
+	type OutEvent = void::Void;
+
+	fn new_handler(&mut self) -> Self::ProtocolsHandler {
+		DummyProtocolsHandler::default()
+	}
+
+	fn inject_connected(&mut self, _: PeerId, _: ConnectedPoint) {}
+
+	fn inject_disconnected(&mut self, _: &PeerId, _: ConnectedPoint) {}
+
+	fn inject_node_event(
+		&mut self,
+		_: PeerId,
+		event: <Self::ProtocolsHandler as ProtocolsHandler>::OutEvent,
+	) {
+		void::unreachable(event)
+	}
+
+	fn poll(
+		&mut self,
+		params: &mut PollParameters<TTopology>,
+	) -> Async<
+		NetworkBehaviourAction<
+			<Self::ProtocolsHandler as ProtocolsHandler>::InEvent,
+			Self::OutEvent,
+		>,
+	> {
+		match self.mdns {
+			Some(ref mut mdns) => NetworkBehaviour::<TTopology>::poll(mdns, params),
+			None => Async::NotReady,
+		}
+	}
+}
@@ -0,0 +1,136 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Periodic resolution of the DNS names of the bootstrap nodes.
+//!
+//! The DNS transport only uses the first address a name resolves to, and a
+//! bootstrap address that fails for long enough ends up removed from the
+//! topology. Instead, the names are resolved again from time to time by a
+//! background thread, and all their addresses are reported, so that we keep
+//! up with address changes and can fall back to another address if one fails.
+
+use futures::sync::mpsc;
+use libp2p::{Multiaddr, PeerId, multiaddr::Protocol};
+use log::{debug, trace, warn};
+use std::net::{IpAddr, ToSocketAddrs};
+use std::time::Duration;
+use std::{iter, thread};
+
+/// Interval between two resolutions of the names.
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Returns true if the address starts with a DNS name.
+pub fn is_dns(addr: &Multiaddr) -> bool {
+	match addr.iter().next() {
+		Some(Protocol::Dns4(_)) | Some(Protocol::Dns6(_)) => true,
+		_ => false,
+	}
+}
+
+/// Starts resolving the names of the given bootstrap nodes in the background. The
+/// addresses they resolve to are reported on the returned stream after each resolution.
+pub fn start(boot_nodes: Vec<(PeerId, Multiaddr)>) -> mpsc::UnboundedReceiver<(PeerId, Vec<Multiaddr>)> {
+	let (tx, rx) = mpsc::unbounded();
+	let spawned = thread::Builder::new()
+		.name("bootnodes-dns".into())
+		.spawn(move || {
+			loop {
+				for (peer_id, addr) in &boot_nodes {
+					match resolve(addr) {
+						Ok(addrs) => {
+							trace!(target: "sub-libp2p", "Bootnode address {} resolved to {:?}", addr, addrs);
+							if tx.unbounded_send((peer_id.clone(), addrs)).is_err() {
+								return
+							}
+						},
+						Err(err) => debug!(target: "sub-libp2p", "Failed to resolve bootnode address {}: {}", addr, err),
+					}
+				}
+				thread::sleep(RESOLVE_INTERVAL);
+				if tx.is_closed() {
+					return
+				}
+			}
+		});
+	if let Err(err) = spawned {
+		warn!(target: "sub-libp2p", "Failed to start the bootnodes resolution thread: {:?}", err);
+	}
+	rx
+}
+
+/// Resolves the DNS name at the start of the address into all the IP addresses it has.
+fn resolve(addr: &Multiaddr) -> Result<Vec<Multiaddr>, String> {
+	let mut protocols = addr.iter();
+	let (host, ipv6) = match protocols.next() {
+		Some(Protocol::Dns4(host)) => (host.to_string(), false),
+		Some(Protocol::Dns6(host)) => (host.to_string(), true),
+		_ => return Err("not a DNS address".into()),
+	};
+	let port = match protocols.next() {
+		Some(Protocol::Tcp(port)) => port,
+		_ => return Err("not a TCP address".into()),
+	};
+	let rest = protocols.collect::<Vec<_>>();
+
+	let mut addrs = Vec::new();
+	for socket in (host.as_str(), port).to_socket_addrs().map_err(|err| err.to_string())? {
+		let ip = match socket.ip() {
+			IpAddr::V4(ip) if !ipv6 => Protocol::Ip4(ip),
+			IpAddr::V6(ip) if ipv6 => Protocol::Ip6(ip),
+			_ => continue,
+		};
+		let resolved = iter::once(ip)
+			.chain(iter::once(Protocol::Tcp(port)))
+			.chain(rest.iter().cloned())
+			.collect::<Multiaddr>();
+		if !addrs.contains(&resolved) {
+			addrs.push(resolved);
+		}
+	}
+	Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_dns, resolve};
+	use libp2p::Multiaddr;
+
+	fn addr(addr: &str) -> Multiaddr {
+		addr.parse().unwrap()
+	}
+
+	#[test]
+	fn dns_addresses_are_recognized() {
+		assert!(is_dns(&addr("/dns4/example.com/tcp/30333")));
+		assert!(is_dns(&addr("/dns6/example.com/tcp/30333")));
+		assert!(!is_dns(&addr("/ip4/127.0.0.1/tcp/30333")));
+		assert!(!is_dns(&addr("/ip4/127.0.0.1/tcp/30333/dns4/example.com")));
+	}
+
+	#[test]
+	fn names_resolve_to_addresses_of_their_ip_version() {
+		let resolved = resolve(&addr("/dns4/localhost/tcp/30333/ws")).unwrap();
+		assert!(resolved.contains(&addr("/ip4/127.0.0.1/tcp/30333/ws")));
+		assert!(resolved.iter().all(|a| a.to_string().starts_with("/ip4/") && a.to_string().ends_with("/tcp/30333/ws")));
+	}
+
+	#[test]
+	fn unsupported_addresses_are_not_resolved() {
+		assert!(resolve(&addr("/ip4/127.0.0.1/tcp/30333")).is_err());
+		assert!(resolve(&addr("/dns4/localhost/udp/30333")).is_err());
+		assert!(resolve(&addr("/dns4/localhost")).is_err());
+	}
+}
@@ -16,7 +16,7 @@
 
 use crate::custom_proto::handler::{CustomProtosHandler, CustomProtosHandlerOut, CustomProtosHandlerIn};
 use crate::custom_proto::upgrade::RegisteredProtocols;
-use crate::{NetworkConfiguration, NonReservedPeerMode, ProtocolId, topology::NetTopology};
+use crate::{NetworkConfiguration, NonReservedPeerMode, ProtocolId, ip_filter, topology::NetTopology};
 use bytes::Bytes;
 use fnv::{FnvHashMap, FnvHashSet};
 use futures::prelude::*;
@@ -24,13 +24,17 @@ use libp2p::core::swarm::{ConnectedPoint, NetworkBehaviour, NetworkBehaviourActi
 use libp2p::core::{protocols_handler::ProtocolsHandler, PeerId};
 use log::{debug, trace, warn};
 use smallvec::SmallVec;
-use std::{io, marker::PhantomData, time::Duration, time::Instant};
+use std::{cmp, io, marker::PhantomData, net::IpAddr, time::Duration, time::Instant};
 use tokio_io::{AsyncRead, AsyncWrite};
 use tokio_timer::Delay;
 
 // Duration during which a peer is disabled.
 const PEER_DISABLE_DURATION: Duration = Duration::from_secs(5 * 60);
 
+// Duration during which we don't dial again a peer we refused because of the limits on the
+// connections with the same host or subnet.
+const LIMITED_PEER_BACKOFF: Duration = Duration::from_secs(60);
+
 /// Network behaviour that handles opening substreams for custom protocols with other nodes.
 pub struct CustomProtos<TSubstream> {
 	/// List of protocols to open with peers. Never modified.
@@ -47,12 +51,20 @@ pub struct CustomProtos<TSubstream> {
 	/// list but not in `open_protocols`.
 	enabled_peers: FnvHashMap<PeerId, ConnectedPoint>,
 
-	/// Maximum number of incoming non-reserved connections, taken from the config. Never modified.
+	/// Maximum number of incoming non-reserved connections, taken from the config. Can be
+	/// modified with `set_peer_slots`.
 	max_incoming_connections: usize,
 
-	/// Maximum number of outgoing non-reserved connections, taken from the config. Never modified.
+	/// Maximum number of outgoing non-reserved connections, taken from the config. Can be
+	/// modified with `set_peer_slots`.
 	max_outgoing_connections: usize,
 
+	/// Maximum number of non-reserved connections with the same IP address. Never modified.
+	max_connections_per_ip: usize,
+
+	/// Maximum number of non-reserved connections with the same subnet. Never modified.
+	max_connections_per_subnet: usize,
+
 	/// If true, only reserved peers can connect.
 	reserved_only: bool,
 
@@ -62,6 +74,10 @@ pub struct CustomProtos<TSubstream> {
 	/// List of the IDs of peers that are forbidden, and the moment their ban expires.
 	banned_peers: Vec<(PeerId, Instant)>,
 
+	/// List of the IDs of peers we dialed but refused because of the limits on the connections
+	/// with the same host or subnet, and the moment we can dial them again.
+	limited_peers: Vec<(PeerId, Instant)>,
+
 	/// When this delay expires, we need to synchronize our active connectons with the
 	/// network topology.
 	next_connect_to_nodes: Delay,
@@ -138,9 +154,12 @@ impl<TSubstream> CustomProtos<TSubstream> {
 			registered_protocols,
 			max_incoming_connections,
 			max_outgoing_connections,
+			max_connections_per_ip: config.max_connections_per_ip as usize,
+			max_connections_per_subnet: config.max_connections_per_subnet as usize,
 			reserved_only: config.non_reserved_mode == NonReservedPeerMode::Deny,
 			reserved_peers: Default::default(),
 			banned_peers: Vec::new(),
+			limited_peers: Vec::new(),
 			open_protocols: Vec::with_capacity(open_protos_cap),
 			enabled_peers: FnvHashMap::with_capacity_and_hasher(connec_cap, Default::default()),
 			next_connect_to_nodes: Delay::new(Instant::now()),
@@ -165,6 +184,16 @@ impl<TSubstream> CustomProtos<TSubstream> {
 		self.reserved_peers.remove(&peer_id);
 	}
 
+	/// Returns true if the peer is reserved.
+	pub fn is_reserved_peer(&self, peer_id: &PeerId) -> bool {
+		self.reserved_peers.contains(peer_id)
+	}
+
+	/// Returns true if we only accept reserved nodes.
+	pub fn is_reserved_only(&self) -> bool {
+		self.reserved_only
+	}
+
 	/// Start accepting all peers again if we weren't.
 	pub fn accept_unreserved_peers(&mut self) {
 		if !self.reserved_only {
@@ -199,6 +228,46 @@ impl<TSubstream> CustomProtos<TSubstream> {
 		})
 	}
 
+	/// Returns the maximum number of incoming and outgoing non-reserved connections.
+	pub fn peer_slots(&self) -> (usize, usize) {
+		(self.max_incoming_connections, self.max_outgoing_connections)
+	}
+
+	/// Changes the maximum number of incoming and outgoing non-reserved connections.
+	///
+	/// If we have more connections than allowed, the excess non-reserved peers are disabled,
+	/// the least reputable first, which closes the connections. If we're allowed more outgoing
+	/// connections, we try to open them.
+	pub fn set_peer_slots(&mut self, in_peers: usize, out_peers: usize, reputation: impl Fn(&PeerId) -> i32) {
+		self.max_incoming_connections = in_peers;
+		self.max_outgoing_connections = out_peers;
+
+		let mut peers = self.enabled_peers.iter()
+			.filter(|(peer_id, _)| !self.reserved_peers.contains(peer_id))
+			.map(|(peer_id, endpoint)| (reputation(peer_id), peer_id.clone(), endpoint.is_dialer()))
+			.collect::<Vec<_>>();
+		peers.sort_by_key(|(reputation, _, _)| cmp::Reverse(*reputation));
+
+		let mut num_ingoing = 0;
+		let mut num_outgoing = 0;
+		for (_, peer_id, is_dialer) in peers {
+			let (num, max) = if is_dialer {
+				(&mut num_outgoing, out_peers)
+			} else {
+				(&mut num_ingoing, in_peers)
+			};
+			if *num < max {
+				*num += 1;
+				continue
+			}
+			debug!(target: "sub-libp2p", "Disconnecting {:?} because we have fewer slots", peer_id);
+			self.disconnect_peer(&peer_id);
+		}
+
+		// Trigger a `connect_to_nodes` round.
+		self.next_connect_to_nodes = Delay::new(Instant::now());
+	}
+
 	/// Disconnects the given peer if we are connected to it.
 	pub fn disconnect_peer(&mut self, peer: &PeerId) {
 		if self.enabled_peers.remove(peer).is_some() {
@@ -240,6 +309,21 @@ impl<TSubstream> CustomProtos<TSubstream> {
 		});
 	}
 
+	/// Stops dialing for a while a peer refused because of the limits on the connections with
+	/// the same host or subnet; otherwise we would keep dialing it in every round only to
+	/// refuse it again. Peers that dialed us aren't tracked, as we don't choose them.
+	fn back_off_limited_peer(&mut self, peer_id: PeerId, endpoint: &ConnectedPoint) {
+		if !endpoint.is_dialer() {
+			return
+		}
+
+		let backoff_end = Instant::now() + LIMITED_PEER_BACKOFF;
+		match self.limited_peers.iter_mut().find(|(p, _)| p == &peer_id) {
+			Some((_, end)) => *end = backoff_end,
+			None => self.limited_peers.push((peer_id, backoff_end)),
+		}
+	}
+
 	/// Updates the attempted connections to nodes.
 	///
 	/// Also updates `next_connect_to_nodes` with the earliest known moment when we need to
@@ -265,7 +349,7 @@ impl<TSubstream> CustomProtos<TSubstream> {
 				.filter(|(_, endpoint)| endpoint.is_dialer())
 				.filter(|(p, _)| !self.reserved_peers.contains(p))
 				.count();
-			self.max_outgoing_connections - num_outgoing_connections
+			self.max_outgoing_connections.saturating_sub(num_outgoing_connections)
 		};
 
 		trace!(target: "sub-libp2p", "Connect-to-nodes round; attempting to fill {:?} slots",
@@ -288,6 +372,12 @@ impl<TSubstream> CustomProtos<TSubstream> {
 				}
 			}
 
+			if let Some((_, backoff_end)) = self.limited_peers.iter().find(|(p, _)| p == peer_id) {
+				if *backoff_end > Instant::now() {
+					continue
+				}
+			}
+
 			num_to_open -= 1;
 			self.events.push(NetworkBehaviourAction::DialPeer { peer_id: peer_id.clone() });
 		}
@@ -336,8 +426,7 @@ where
 					.filter(|(p, _)| !self.reserved_peers.contains(p))
 					.count();
 
-				debug_assert!(num_outgoing <= self.max_outgoing_connections);
-				if num_outgoing == self.max_outgoing_connections {
+				if num_outgoing >= self.max_outgoing_connections {
 					return
 				}
 			}
@@ -347,8 +436,7 @@ where
 					.filter(|(p, _)| !self.reserved_peers.contains(p))
 					.count();
 
-				debug_assert!(num_ingoing <= self.max_incoming_connections);
-				if num_ingoing == self.max_incoming_connections {
+				if num_ingoing >= self.max_incoming_connections {
 					debug!(target: "sub-libp2p", "Ignoring incoming connection from {:?} because \
 						we're full", peer_id);
 					return
@@ -356,6 +444,39 @@ where
 			}
 		}
 
+		// Check the limits on the connections with the same host or subnet, so that a single
+		// host can't take all our slots. Local networks aren't limited, as they are usually
+		// test networks whose nodes all share a subnet.
+		if !is_reserved {
+			if let Some(ip) = remote_ip(&endpoint).filter(|ip| !is_local(ip)) {
+				let other_ips = self.enabled_peers.iter()
+					.filter(|(p, _)| !self.reserved_peers.contains(p))
+					.filter_map(|(_, e)| remote_ip(e));
+				let (mut num_same_ip, mut num_same_subnet) = (0, 0);
+				for other_ip in other_ips {
+					if other_ip == ip {
+						num_same_ip += 1;
+					}
+					if same_subnet(&other_ip, &ip) {
+						num_same_subnet += 1;
+					}
+				}
+
+				if num_same_ip >= self.max_connections_per_ip {
+					debug!(target: "sub-libp2p", "Ignoring {:?} because we have too many \
+						connections with {}", peer_id, ip);
+					self.back_off_limited_peer(peer_id, &endpoint);
+					return
+				}
+				if num_same_subnet >= self.max_connections_per_subnet {
+					debug!(target: "sub-libp2p", "Ignoring {:?} because we have too many \
+						connections with the subnet of {}", peer_id, ip);
+					self.back_off_limited_peer(peer_id, &endpoint);
+					return
+				}
+			}
+		}
+
 		// If everything is fine, enable the node.
 		debug_assert!(!self.enabled_peers.contains_key(&peer_id));
 		// We ask the handler to actively open substreams only if we are the dialer; otherwise
@@ -482,6 +603,7 @@ where
 		// Clean up `banned_peers`
 		self.banned_peers.retain(|(_, end)| *end < Instant::now());
 		self.banned_peers.shrink_to_fit();
+		self.limited_peers.retain(|(_, end)| *end > Instant::now());
 
 		if !self.events.is_empty() {
 			return Async::Ready(self.events.remove(0))
@@ -490,3 +612,176 @@ where
 		Async::NotReady
 	}
 }
+
+/// Returns the IP address of the remote of a connection, if it is known.
+fn remote_ip(endpoint: &ConnectedPoint) -> Option<IpAddr> {
+	let addr = match endpoint {
+		ConnectedPoint::Dialer { address } => address,
+		ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+	};
+	ip_filter::ip_of(addr)
+}
+
+/// Returns true if the address is a loopback, private or link-local one.
+fn is_local(ip: &IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+		IpAddr::V6(ip) => ip.is_loopback()
+			// Unique local addresses, fc00::/7.
+			|| ip.segments()[0] & 0xfe00 == 0xfc00
+			// Link-local addresses, fe80::/10.
+			|| ip.segments()[0] & 0xffc0 == 0xfe80,
+	}
+}
+
+/// Returns true if both addresses are in the same /24 (IPv4) or /48 (IPv6) subnet.
+fn same_subnet(a: &IpAddr, b: &IpAddr) -> bool {
+	match (a, b) {
+		(IpAddr::V4(a), IpAddr::V4(b)) => a.octets()[..3] == b.octets()[..3],
+		(IpAddr::V6(a), IpAddr::V6(b)) => a.segments()[..3] == b.segments()[..3],
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use libp2p::Multiaddr;
+
+	fn protos() -> CustomProtos<()> {
+		CustomProtos::new(&NetworkConfiguration::default(), RegisteredProtocols::default())
+	}
+
+	fn enable(protos: &mut CustomProtos<()>, dialer: bool) -> PeerId {
+		let peer_id = PeerId::random();
+		let address: Multiaddr = "/ip4/10.0.0.1/tcp/30333".parse().unwrap();
+		let endpoint = if dialer {
+			ConnectedPoint::Dialer { address }
+		} else {
+			ConnectedPoint::Listener { listen_addr: address.clone(), send_back_addr: address }
+		};
+		protos.enabled_peers.insert(peer_id.clone(), endpoint);
+		peer_id
+	}
+
+	#[test]
+	fn least_reputable_peers_are_disconnected_when_shrinking_slots() {
+		let mut protos = protos();
+		let mut reputations = FnvHashMap::default();
+		let mut add = |protos: &mut CustomProtos<()>, dialer, reputation| {
+			let peer_id = enable(protos, dialer);
+			reputations.insert(peer_id.clone(), reputation);
+			peer_id
+		};
+		let good_in = add(&mut protos, false, 100);
+		let bad_in = add(&mut protos, false, -50);
+		let neutral_in = add(&mut protos, false, 0);
+		let good_out = add(&mut protos, true, 10);
+		let bad_out = add(&mut protos, true, -10);
+		let neutral_out = add(&mut protos, true, 0);
+		// reserved peers are kept whatever their reputation.
+		let reserved = add(&mut protos, false, -90);
+		protos.add_reserved_peer(reserved.clone());
+
+		protos.set_peer_slots(1, 2, |peer_id| reputations[peer_id]);
+
+		assert_eq!(protos.peer_slots(), (1, 2));
+		let mut kept = protos.enabled_peers.keys().cloned().collect::<Vec<_>>();
+		kept.sort_by_key(|peer_id| peer_id.to_base58());
+		let mut expected = vec![good_in, good_out, neutral_out, reserved];
+		expected.sort_by_key(|peer_id| peer_id.to_base58());
+		assert_eq!(kept, expected);
+
+		let mut disabled = protos.events.iter().filter_map(|event| match event {
+			NetworkBehaviourAction::SendEvent { peer_id, event: CustomProtosHandlerIn::Disable } => Some(peer_id.clone()),
+			_ => None,
+		}).collect::<Vec<_>>();
+		disabled.sort_by_key(|peer_id| peer_id.to_base58());
+		let mut expected = vec![bad_in, neutral_in, bad_out];
+		expected.sort_by_key(|peer_id| peer_id.to_base58());
+		assert_eq!(disabled, expected);
+	}
+
+	#[test]
+	fn no_peer_is_disconnected_when_growing_slots() {
+		let mut protos = protos();
+		enable(&mut protos, false);
+		enable(&mut protos, true);
+		protos.set_peer_slots(1, 1, |_| 0);
+		assert_eq!(protos.enabled_peers.len(), 2);
+
+		protos.set_peer_slots(8, 16, |_| 0);
+
+		assert_eq!(protos.peer_slots(), (8, 16));
+		assert_eq!(protos.enabled_peers.len(), 2);
+		assert!(protos.events.is_empty());
+	}
+
+	#[test]
+	fn remote_ips_of_endpoints() {
+		let dialer = ConnectedPoint::Dialer { address: "/ip4/1.2.3.4/tcp/30333".parse().unwrap() };
+		assert_eq!(remote_ip(&dialer), Some("1.2.3.4".parse().unwrap()));
+
+		let listener = ConnectedPoint::Listener {
+			listen_addr: "/ip4/0.0.0.0/tcp/30333".parse().unwrap(),
+			send_back_addr: "/ip6/::ffff:1.2.3.5/tcp/4000".parse().unwrap(),
+		};
+		assert_eq!(remote_ip(&listener), Some("1.2.3.5".parse().unwrap()));
+
+		let dns = ConnectedPoint::Dialer { address: "/dns4/example.com/tcp/30333".parse().unwrap() };
+		assert_eq!(remote_ip(&dns), None);
+	}
+
+	#[test]
+	fn subnets_of_addresses_are_compared() {
+		let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+		assert!(same_subnet(&ip("1.2.3.4"), &ip("1.2.3.200")));
+		assert!(!same_subnet(&ip("1.2.3.4"), &ip("1.2.4.4")));
+		assert!(same_subnet(&ip("2001:db8:1::1"), &ip("2001:db8:1:ffff::1")));
+		assert!(!same_subnet(&ip("2001:db8:1::1"), &ip("2001:db8:2::1")));
+		assert!(!same_subnet(&ip("1.2.3.4"), &ip("::1")));
+
+		assert!(is_local(&ip("127.0.0.1")));
+		assert!(is_local(&ip("192.168.1.1")));
+		assert!(is_local(&ip("169.254.1.1")));
+		assert!(is_local(&ip("fd00::1")));
+		assert!(is_local(&ip("fe80::1")));
+		assert!(!is_local(&ip("1.2.3.4")));
+		assert!(!is_local(&ip("2001:db8::1")));
+	}
+
+	#[test]
+	fn dialed_peers_refused_by_the_subnet_limit_are_backed_off() {
+		let mut config = NetworkConfiguration::default();
+		config.max_connections_per_subnet = 1;
+		let mut protos = CustomProtos::<()>::new(&config, RegisteredProtocols::default());
+		let dialer = |addr: &str| ConnectedPoint::Dialer { address: addr.parse().unwrap() };
+
+		let first = PeerId::random();
+		protos.inject_connected(first.clone(), dialer("/ip4/1.2.3.4/tcp/30333"));
+		assert!(protos.enabled_peers.contains_key(&first));
+
+		// a second peer of the same subnet is refused, and not dialed again for a while.
+		let dialed = PeerId::random();
+		protos.inject_connected(dialed.clone(), dialer("/ip4/1.2.3.5/tcp/30333"));
+		assert!(!protos.enabled_peers.contains_key(&dialed));
+		assert!(protos.limited_peers.iter().any(|(p, end)| p == &dialed && *end > Instant::now()));
+
+		// peers dialing us are refused too, but we don't choose them.
+		let listened = PeerId::random();
+		protos.inject_connected(listened.clone(), ConnectedPoint::Listener {
+			listen_addr: "/ip4/0.0.0.0/tcp/30333".parse().unwrap(),
+			send_back_addr: "/ip4/1.2.3.6/tcp/4000".parse().unwrap(),
+		});
+		assert!(!protos.enabled_peers.contains_key(&listened));
+		assert!(!protos.limited_peers.iter().any(|(p, _)| p == &listened));
+
+		// private networks aren't limited.
+		for n in 1..4 {
+			let local = PeerId::random();
+			protos.inject_connected(local.clone(), dialer(&format!("/ip4/10.0.0.{}/tcp/30333", n)));
+			assert!(protos.enabled_peers.contains_key(&local));
+		}
+		assert_eq!(protos.limited_peers.len(), 1);
+	}
+}
@@ -0,0 +1,261 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Lists of IP networks that connections are allowed with or denied, checked
+//! when connections are established.
+
+use libp2p::{Multiaddr, multiaddr::Protocol};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// IP network in CIDR notation, such as `10.0.0.0/8` or `2001:db8::/32`. An address without
+/// prefix length designates a single host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+	addr: IpAddr,
+	prefix_len: u8,
+}
+
+impl IpNetwork {
+	/// Creates a network from an address and a prefix length. Returns `None` if the prefix
+	/// length is too large for the address.
+	pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+		if prefix_len > max_prefix_len(&addr) {
+			return None
+		}
+		Some(IpNetwork { addr: mask(&addr, prefix_len), prefix_len })
+	}
+
+	/// Returns the /24 (IPv4) or /48 (IPv6) subnet of the address, usually operated by a
+	/// single party.
+	pub fn subnet_of(addr: &IpAddr) -> Self {
+		let addr = &to_canonical(addr);
+		let prefix_len = match addr {
+			IpAddr::V4(_) => 24,
+			IpAddr::V6(_) => 48,
+		};
+		IpNetwork { addr: mask(addr, prefix_len), prefix_len }
+	}
+
+	/// Returns true if the address is part of the network. IPv4-mapped IPv6 addresses,
+	/// such as `::ffff:10.0.0.1`, are part of the IPv4 networks.
+	pub fn contains(&self, addr: &IpAddr) -> bool {
+		let addr = &to_canonical(addr);
+		match (self.addr, addr) {
+			(IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) =>
+				mask(addr, self.prefix_len) == self.addr,
+			_ => false,
+		}
+	}
+}
+
+impl FromStr for IpNetwork {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (addr, prefix_len) = match s.find('/') {
+			Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+			None => (s, None),
+		};
+		let addr = addr.parse::<IpAddr>()
+			.map_err(|_| format!("Invalid IP address in {}", s))?;
+		let prefix_len = match prefix_len {
+			Some(len) => len.parse::<u8>().map_err(|_| format!("Invalid prefix length in {}", s))?,
+			None => max_prefix_len(&addr),
+		};
+		IpNetwork::new(addr, prefix_len).ok_or_else(|| format!("Prefix length too large in {}", s))
+	}
+}
+
+impl fmt::Display for IpNetwork {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}/{}", self.addr, self.prefix_len)
+	}
+}
+
+fn max_prefix_len(addr: &IpAddr) -> u8 {
+	match addr {
+		IpAddr::V4(_) => 32,
+		IpAddr::V6(_) => 128,
+	}
+}
+
+/// Turns IPv4-mapped IPv6 addresses into the IPv4 address they designate, which is what
+/// dual-stack sockets report for IPv4 connections.
+fn to_canonical(addr: &IpAddr) -> IpAddr {
+	match addr {
+		IpAddr::V6(v6) => match v6.segments() {
+			// `to_ipv4` also converts IPv4-compatible addresses, such as `::1`, which aren't IPv4.
+			[0, 0, 0, 0, 0, 0xffff, _, _] => v6.to_ipv4().map_or(*addr, IpAddr::V4),
+			_ => *addr,
+		},
+		IpAddr::V4(_) => *addr,
+	}
+}
+
+/// Keeps the first `prefix_len` bits of the address.
+fn mask(addr: &IpAddr, prefix_len: u8) -> IpAddr {
+	match addr {
+		IpAddr::V4(addr) => {
+			let bits = u32::from(*addr);
+			let mask = u32::max_value().checked_shl(32 - u32::from(prefix_len)).unwrap_or(0);
+			IpAddr::V4((bits & mask).into())
+		},
+		IpAddr::V6(addr) => {
+			let bits = u128::from(*addr);
+			let mask = u128::max_value().checked_shl(128 - u32::from(prefix_len)).unwrap_or(0);
+			IpAddr::V6((bits & mask).into())
+		},
+	}
+}
+
+/// Which of the lists of an `IpFilter` to act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFilterList {
+	/// Networks connections are allowed with. If not empty, connections with any other address
+	/// are refused.
+	Allow,
+	/// Networks connections are refused with, even if they are part of the allowed ones.
+	Deny,
+}
+
+/// Allow and deny lists of IP networks.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+	allowed: Vec<IpNetwork>,
+	denied: Vec<IpNetwork>,
+}
+
+impl IpFilter {
+	/// Creates a filter from the allowed and denied networks.
+	pub fn new(allowed: Vec<IpNetwork>, denied: Vec<IpNetwork>) -> Self {
+		IpFilter { allowed, denied }
+	}
+
+	/// Returns true if connections with the address are allowed.
+	pub fn is_allowed(&self, addr: &IpAddr) -> bool {
+		if self.denied.iter().any(|network| network.contains(addr)) {
+			return false
+		}
+		self.allowed.is_empty() || self.allowed.iter().any(|network| network.contains(addr))
+	}
+
+	/// Returns true if connections with the host of the multiaddress are allowed. Addresses
+	/// that don't start with an IP address, such as DNS names, are only checked once resolved.
+	pub fn is_addr_allowed(&self, addr: &Multiaddr) -> bool {
+		match ip_of(addr) {
+			Some(ip) => self.is_allowed(&ip),
+			None => true,
+		}
+	}
+
+	/// Returns the networks of a list.
+	pub fn list(&self, list: IpFilterList) -> &[IpNetwork] {
+		match list {
+			IpFilterList::Allow => &self.allowed,
+			IpFilterList::Deny => &self.denied,
+		}
+	}
+
+	/// Adds a network to a list, if it isn't part of it already.
+	pub fn add(&mut self, list: IpFilterList, network: IpNetwork) {
+		let networks = self.list_mut(list);
+		if !networks.contains(&network) {
+			networks.push(network);
+		}
+	}
+
+	/// Removes a network from a list. Returns false if it wasn't part of it.
+	pub fn remove(&mut self, list: IpFilterList, network: &IpNetwork) -> bool {
+		let networks = self.list_mut(list);
+		let len = networks.len();
+		networks.retain(|n| n != network);
+		networks.len() != len
+	}
+
+	fn list_mut(&mut self, list: IpFilterList) -> &mut Vec<IpNetwork> {
+		match list {
+			IpFilterList::Allow => &mut self.allowed,
+			IpFilterList::Deny => &mut self.denied,
+		}
+	}
+}
+
+/// Returns the IP address a multiaddress starts with, if any. IPv4-mapped IPv6 addresses
+/// are returned as IPv4 addresses.
+pub fn ip_of(addr: &Multiaddr) -> Option<IpAddr> {
+	match addr.iter().next() {
+		Some(Protocol::Ip4(ip)) => Some(IpAddr::V4(ip)),
+		Some(Protocol::Ip6(ip)) => Some(to_canonical(&IpAddr::V6(ip))),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_networks() {
+		let network: IpNetwork = "10.1.2.3/8".parse().unwrap();
+		assert_eq!(network.to_string(), "10.0.0.0/8");
+		let host: IpNetwork = "192.168.0.1".parse().unwrap();
+		assert_eq!(host.to_string(), "192.168.0.1/32");
+		let v6: IpNetwork = "2001:db8::1/32".parse().unwrap();
+		assert_eq!(v6.to_string(), "2001:db8::/32");
+		assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+		assert!("10.0.0/8".parse::<IpNetwork>().is_err());
+		assert!("10.0.0.0/x".parse::<IpNetwork>().is_err());
+	}
+
+	#[test]
+	fn matches_addresses() {
+		let network: IpNetwork = "10.0.0.0/8".parse().unwrap();
+		assert!(network.contains(&"10.200.3.4".parse().unwrap()));
+		assert!(!network.contains(&"11.0.0.1".parse().unwrap()));
+		// IPv4-mapped IPv6 addresses are matched as IPv4, IPv4-compatible ones aren't.
+		assert!(network.contains(&"::ffff:10.0.0.1".parse().unwrap()));
+		assert!(!network.contains(&"::ffff:11.0.0.1".parse().unwrap()));
+		assert!(!network.contains(&"::10.0.0.1".parse().unwrap()));
+		let v6: IpNetwork = "::/0".parse().unwrap();
+		assert!(!v6.contains(&"::ffff:10.0.0.1".parse().unwrap()));
+		assert!(v6.contains(&"::1".parse().unwrap()));
+		let everything: IpNetwork = "0.0.0.0/0".parse().unwrap();
+		assert!(everything.contains(&"1.2.3.4".parse().unwrap()));
+	}
+
+	#[test]
+	fn deny_takes_precedence() {
+		let mut filter = IpFilter::default();
+		assert!(filter.is_allowed(&"1.2.3.4".parse().unwrap()));
+
+		filter.add(IpFilterList::Allow, "10.0.0.0/8".parse().unwrap());
+		filter.add(IpFilterList::Deny, "10.0.0.0/16".parse().unwrap());
+		assert!(!filter.is_allowed(&"1.2.3.4".parse().unwrap()));
+		assert!(!filter.is_allowed(&"10.0.1.1".parse().unwrap()));
+		assert!(filter.is_allowed(&"10.1.1.1".parse().unwrap()));
+
+		assert!(filter.remove(IpFilterList::Deny, &"10.0.0.0/16".parse().unwrap()));
+		assert!(filter.is_allowed(&"10.0.1.1".parse().unwrap()));
+		assert!(filter.is_addr_allowed(&"/dns4/example.com/tcp/30333".parse().unwrap()));
+		assert!(!filter.is_addr_allowed(&"/ip4/1.2.3.4/tcp/30333".parse().unwrap()));
+	}
+
+	#[test]
+	fn subnets_of_addresses() {
+		assert_eq!(IpNetwork::subnet_of(&"10.1.2.3".parse().unwrap()).to_string(), "10.1.2.0/24");
+		assert_eq!(IpNetwork::subnet_of(&"2001:db8:1:2::1".parse().unwrap()).to_string(), "2001:db8:1::/48");
+	}
+}
@@ -17,8 +17,12 @@
 //! Networking layer of Substrate.
 
 mod behaviour;
+mod bootnodes;
 mod custom_proto;
 mod error;
+mod ip_filter;
+mod port_mapping;
+mod reputation;
 mod secret;
 mod service_task;
 mod topology;
@@ -27,10 +31,13 @@ mod transport;
 
 pub use crate::custom_proto::RegisteredProtocol;
 pub use crate::error::{Error, ErrorKind, DisconnectReason};
+pub use crate::ip_filter::{IpFilterList, IpNetwork};
+pub use crate::reputation::{ReputationChange, BAN_THRESHOLD, DISCONNECT_THRESHOLD};
 pub use crate::secret::obtain_private_key;
 pub use crate::service_task::{start_service, Service, ServiceEvent};
-pub use crate::traits::{NetworkConfiguration, NodeIndex, NodeId, NonReservedPeerMode};
-pub use crate::traits::{ProtocolId, Secret, Severity};
+pub use crate::transport::ConnectionMetrics;
+pub use crate::traits::{NetworkConfiguration, NodeIndex, NodeId, NodeKeyType, NonReservedPeerMode};
+pub use crate::traits::{Multiplexer, MultiplexerConfig, ProtocolId, Secret, Severity, TlsCertificate};
 pub use libp2p::{Multiaddr, multiaddr::{Protocol}, multiaddr, PeerId, core::PublicKey};
 
 /// Check if node url is valid
@@ -0,0 +1,424 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Mapping of the listening ports on the local router, so that nodes behind a
+//! NAT can accept inbound connections.
+//!
+//! The router is the default gateway of the host. UPnP is tried first, then
+//! NAT-PMP. Mappings are leased and renewed by a background thread, which removes
+//! them and stops once the returned `PortMapping` is dropped.
+
+use futures::{prelude::*, sync::mpsc};
+use libp2p::{Multiaddr, multiaddr};
+use log::{debug, trace, warn};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::time::Duration;
+use std::thread;
+
+/// How long to wait for a router to answer.
+const GATEWAY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Lifetime of the mappings we request.
+const LEASE_DURATION: Duration = Duration::from_secs(20 * 60);
+/// Mappings are renewed well before their lease expires.
+const RENEW_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Port of the UPnP discovery (SSDP) server on the router.
+const SSDP_PORT: u16 = 1900;
+/// Port of the NAT-PMP server on the router.
+const NAT_PMP_PORT: u16 = 5351;
+/// Description of the mappings, shown by routers.
+const MAPPING_DESCRIPTION: &str = "substrate";
+
+/// Ports being mapped in the background. Produces the external addresses as they are
+/// discovered.
+///
+/// Dropping it removes the mappings from the router, and waits for the background thread
+/// to finish.
+pub struct PortMapping {
+	/// External addresses reported by the background thread.
+	addrs: mpsc::UnboundedReceiver<Multiaddr>,
+	/// Tells the background thread to remove the mappings and stop.
+	stop: Sender<()>,
+	/// The background thread, if it could be started.
+	thread: Option<thread::JoinHandle<()>>,
+}
+
+/// Starts mapping the given TCP ports in the background.
+pub fn start(ports: Vec<u16>) -> PortMapping {
+	start_with(ports, map_ports)
+}
+
+/// Starts mapping the given TCP ports in the background with `map_ports`, which is called
+/// again whenever the mappings are due to be renewed.
+fn start_with<F>(ports: Vec<u16>, mut map_ports: F) -> PortMapping
+	where F: FnMut(&[u16]) -> Option<Mappings> + Send + 'static
+{
+	let (tx, rx) = mpsc::unbounded();
+	let (stop, stopped) = channel();
+	let spawned = thread::Builder::new()
+		.name("port-mapping".into())
+		.spawn(move || {
+			let mut reported = Vec::new();
+			let mut mappings;
+			loop {
+				mappings = map_ports(&ports);
+				for (_, addr) in mappings.iter().flat_map(|mappings| mappings.ports.iter()) {
+					if !reported.contains(addr) {
+						reported.push(addr.clone());
+						let _ = tx.unbounded_send(addr.clone());
+					}
+				}
+				match stopped.recv_timeout(RENEW_INTERVAL) {
+					Err(RecvTimeoutError::Timeout) => {},
+					Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+				}
+			}
+			if let Some(mappings) = mappings {
+				mappings.remove();
+			}
+		});
+	let thread = match spawned {
+		Ok(thread) => Some(thread),
+		Err(err) => {
+			warn!(target: "sub-libp2p", "Failed to start the port mapping thread: {:?}", err);
+			None
+		}
+	};
+	PortMapping { addrs: rx, stop, thread }
+}
+
+impl Stream for PortMapping {
+	type Item = Multiaddr;
+	type Error = ();
+
+	fn poll(&mut self) -> Poll<Option<Multiaddr>, ()> {
+		self.addrs.poll()
+	}
+}
+
+impl Drop for PortMapping {
+	fn drop(&mut self) {
+		let _ = self.stop.send(());
+		if let Some(thread) = self.thread.take() {
+			if thread.join().is_err() {
+				warn!(target: "sub-libp2p", "The port mapping thread panicked");
+			}
+		}
+	}
+}
+
+/// Router our ports are mapped on.
+enum Router {
+	Upnp(igd::Gateway),
+	NatPmp(UdpSocket),
+}
+
+/// Ports mapped on a router, with the external addresses they are reachable at.
+struct Mappings {
+	router: Router,
+	ports: Vec<(u16, Multiaddr)>,
+}
+
+impl Mappings {
+	/// Removes the mappings from the router.
+	fn remove(&self) {
+		for (port, addr) in &self.ports {
+			let result = match &self.router {
+				Router::Upnp(gateway) => gateway.remove_port(igd::PortMappingProtocol::TCP, *port)
+					.map_err(|err| format!("{:?}", err)),
+				// a NAT-PMP mapping is removed by requesting it with no lifetime.
+				Router::NatPmp(socket) => nat_pmp_request(socket, &nat_pmp_mapping_request(*port, 0, 0), 16)
+					.map(|_| ()),
+			};
+			match result {
+				Ok(()) => debug!(target: "sub-libp2p", "Removed the mapping of port {} to {}", port, addr),
+				Err(err) => debug!(target: "sub-libp2p", "Failed to remove the mapping of port {}: {}", port, err),
+			}
+		}
+	}
+}
+
+/// Maps the ports on the default gateway.
+fn map_ports(ports: &[u16]) -> Option<Mappings> {
+	let gateway = match default_gateway() {
+		Ok(gateway) => gateway,
+		Err(err) => {
+			debug!(target: "sub-libp2p", "Can't map ports, no default gateway: {}", err);
+			return None
+		}
+	};
+	let local_ip = match local_ip(SocketAddr::from((gateway, NAT_PMP_PORT))) {
+		Ok(ip) => ip,
+		Err(err) => {
+			debug!(target: "sub-libp2p", "Can't map ports, no local address: {}", err);
+			return None
+		}
+	};
+	if !local_ip.is_private() {
+		trace!(target: "sub-libp2p", "Not mapping ports, {} is reachable already", local_ip);
+		return None
+	}
+
+	match map_upnp(gateway, local_ip, ports) {
+		Ok(mappings) => return Some(mappings),
+		Err(err) => debug!(target: "sub-libp2p", "UPnP port mapping failed: {}", err),
+	}
+	match map_nat_pmp(gateway, local_ip, ports) {
+		Ok(mappings) => Some(mappings),
+		Err(err) => {
+			debug!(target: "sub-libp2p", "NAT-PMP port mapping failed: {}", err);
+			None
+		}
+	}
+}
+
+fn map_upnp(gateway: Ipv4Addr, local_ip: Ipv4Addr, ports: &[u16]) -> Result<Mappings, String> {
+	// The discovery is sent to the gateway only, rather than to any UPnP device of the network.
+	let gateway = igd::search_gateway(igd::SearchOptions {
+		bind_addr: SocketAddr::from((local_ip, 0)),
+		broadcast_address: SocketAddr::from((gateway, SSDP_PORT)),
+		timeout: Some(GATEWAY_TIMEOUT),
+	}).map_err(|err| format!("{:?}", err))?;
+	let external_ip = gateway.get_external_ip()
+		.map_err(|err| format!("{:?}", err))?;
+
+	let mut mapped = Vec::new();
+	for &port in ports {
+		let local_addr = SocketAddrV4::new(local_ip, port);
+		let lease = LEASE_DURATION.as_secs() as u32;
+		match gateway.add_port(igd::PortMappingProtocol::TCP, port, local_addr, lease, MAPPING_DESCRIPTION) {
+			Ok(()) => mapped.push((port, tcp_addr(external_ip, port))),
+			Err(err) => debug!(target: "sub-libp2p", "UPnP failed to map port {}: {:?}", port, err),
+		}
+	}
+	Ok(Mappings { router: Router::Upnp(gateway), ports: mapped })
+}
+
+fn map_nat_pmp(gateway: Ipv4Addr, local_ip: Ipv4Addr, ports: &[u16]) -> Result<Mappings, String> {
+	let socket = UdpSocket::bind((local_ip, 0)).map_err(|err| err.to_string())?;
+	socket.set_read_timeout(Some(GATEWAY_TIMEOUT)).map_err(|err| err.to_string())?;
+	socket.connect((gateway, NAT_PMP_PORT)).map_err(|err| err.to_string())?;
+	map_nat_pmp_with(socket, ports)
+}
+
+/// Maps the ports with the NAT-PMP server `socket` is connected to.
+fn map_nat_pmp_with(socket: UdpSocket, ports: &[u16]) -> Result<Mappings, String> {
+	let response = nat_pmp_request(&socket, &[0, 0], 12)?;
+	let external_ip = Ipv4Addr::new(response[8], response[9], response[10], response[11]);
+
+	let mut mapped = Vec::new();
+	let lease = LEASE_DURATION.as_secs() as u32;
+	for &port in ports {
+		match nat_pmp_request(&socket, &nat_pmp_mapping_request(port, port, lease), 16) {
+			// the router may pick another external port than the one we suggested.
+			Ok(response) => mapped.push((port, tcp_addr(external_ip, u16::from(response[10]) << 8 | u16::from(response[11])))),
+			Err(err) => debug!(target: "sub-libp2p", "NAT-PMP failed to map port {}: {}", port, err),
+		}
+	}
+	Ok(Mappings { router: Router::NatPmp(socket), ports: mapped })
+}
+
+/// Builds a NAT-PMP request mapping the TCP port `internal` to `external` for `lease` seconds.
+fn nat_pmp_mapping_request(internal: u16, external: u16, lease: u32) -> [u8; 12] {
+	[
+		0, 2, 0, 0,
+		(internal >> 8) as u8, internal as u8,
+		(external >> 8) as u8, external as u8,
+		(lease >> 24) as u8, (lease >> 16) as u8, (lease >> 8) as u8, lease as u8,
+	]
+}
+
+/// Sends a NAT-PMP request and returns the response, once checked to be successful.
+fn nat_pmp_request(socket: &UdpSocket, request: &[u8], response_len: usize) -> Result<Vec<u8>, String> {
+	socket.send(request).map_err(|err| err.to_string())?;
+	let mut response = [0; 16];
+	let len = socket.recv(&mut response).map_err(|err| err.to_string())?;
+	if len < response_len || response[0] != 0 || response[1] != request[1] + 128 {
+		return Err("invalid response".into())
+	}
+	let result = u16::from(response[2]) << 8 | u16::from(response[3]);
+	if result != 0 {
+		return Err(format!("result code {}", result))
+	}
+	Ok(response[..len].to_vec())
+}
+
+/// Returns the default gateway of the host.
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Result<Ipv4Addr, String> {
+	let routes = std::fs::read_to_string("/proc/net/route").map_err(|err| err.to_string())?;
+	parse_default_gateway(&routes).ok_or_else(|| "no default route".into())
+}
+
+/// Returns the default gateway of the host.
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Result<Ipv4Addr, String> {
+	Err("finding the default gateway isn't supported on this platform".into())
+}
+
+/// Extracts the gateway of the default route from the content of `/proc/net/route`.
+fn parse_default_gateway(routes: &str) -> Option<Ipv4Addr> {
+	// Flag of the routes that go through a gateway.
+	const RTF_GATEWAY: u16 = 0x2;
+
+	routes.lines().skip(1).find_map(|line| {
+		let fields = line.split_whitespace().collect::<Vec<_>>();
+		if fields.len() < 4 || fields[1] != "00000000" {
+			return None
+		}
+		let flags = u16::from_str_radix(fields[3], 16).ok()?;
+		if flags & RTF_GATEWAY == 0 {
+			return None
+		}
+		// the address is written as an integer in the byte order of the host.
+		let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+		Some(Ipv4Addr::from(u32::from_be(gateway)))
+	})
+}
+
+/// Returns the local address used to reach `remote`. Nothing is sent.
+fn local_ip(remote: SocketAddr) -> Result<Ipv4Addr, String> {
+	let socket = UdpSocket::bind("0.0.0.0:0").map_err(|err| err.to_string())?;
+	socket.connect(remote).map_err(|err| err.to_string())?;
+	match socket.local_addr().map_err(|err| err.to_string())? {
+		SocketAddr::V4(addr) => Ok(*addr.ip()),
+		SocketAddr::V6(_) => Err("no IPv4 address".into()),
+	}
+}
+
+fn tcp_addr(ip: Ipv4Addr, port: u16) -> Multiaddr {
+	multiaddr![Ip4(ip), Tcp(port)]
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Formats an address the way `/proc/net/route` does.
+	fn route_addr(ip: [u8; 4]) -> String {
+		format!("{:08X}", u32::from_ne_bytes(ip))
+	}
+
+	#[test]
+	fn default_gateway_is_parsed() {
+		let routes = format!(
+			"Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+			eth0\t{}\t{}\t0001\t0\t0\t0\t{}\n\
+			eth0\t{}\t{}\t0003\t0\t0\t100\t{}\n",
+			route_addr([192, 168, 1, 0]), route_addr([0, 0, 0, 0]), route_addr([255, 255, 255, 0]),
+			route_addr([0, 0, 0, 0]), route_addr([192, 168, 1, 254]), route_addr([0, 0, 0, 0]),
+		);
+		assert_eq!(parse_default_gateway(&routes), Some(Ipv4Addr::new(192, 168, 1, 254)));
+
+		// a route to the local network only isn't a default route.
+		let routes = routes.lines().take(2).collect::<Vec<_>>().join("\n");
+		assert_eq!(parse_default_gateway(&routes), None);
+	}
+
+	/// Answers the NAT-PMP requests received on `router` as a router with the external address
+	/// 1.2.3.4 would, mapping every port to the port above it, and returns the requests.
+	fn nat_pmp_router(router: UdpSocket, requests: usize) -> thread::JoinHandle<Vec<Vec<u8>>> {
+		thread::spawn(move || {
+			let mut received = Vec::new();
+			for _ in 0..requests {
+				let mut request = [0; 12];
+				let (len, from) = router.recv_from(&mut request).unwrap();
+				let mut response = vec![0, request[1] + 128, 0, 0, 0, 0, 0, 0];
+				if request[1] == 0 {
+					response.extend_from_slice(&[1, 2, 3, 4]);
+				} else {
+					let external = (u16::from(request[4]) << 8 | u16::from(request[5])) + 1;
+					response.extend_from_slice(&request[4..6]);
+					response.extend_from_slice(&[(external >> 8) as u8, external as u8]);
+					response.extend_from_slice(&request[8..12]);
+				}
+				router.send_to(&response, from).unwrap();
+				received.push(request[..len].to_vec());
+			}
+			received
+		})
+	}
+
+	#[test]
+	fn nat_pmp_mappings_are_created_and_removed() {
+		let router = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+		socket.set_read_timeout(Some(GATEWAY_TIMEOUT)).unwrap();
+		socket.connect(router.local_addr().unwrap()).unwrap();
+		// the external address, the mappings of both ports, then their removal.
+		let router = nat_pmp_router(router, 5);
+
+		let mappings = map_nat_pmp_with(socket, &[30333, 30334]).unwrap();
+		assert_eq!(
+			mappings.ports.iter().map(|(_, addr)| addr.clone()).collect::<Vec<_>>(),
+			vec![tcp_addr(Ipv4Addr::new(1, 2, 3, 4), 30334), tcp_addr(Ipv4Addr::new(1, 2, 3, 4), 30335)],
+		);
+		mappings.remove();
+
+		let lease = LEASE_DURATION.as_secs() as u32;
+		assert_eq!(router.join().unwrap(), vec![
+			vec![0, 0],
+			nat_pmp_mapping_request(30333, 30333, lease).to_vec(),
+			nat_pmp_mapping_request(30334, 30334, lease).to_vec(),
+			nat_pmp_mapping_request(30333, 0, 0).to_vec(),
+			nat_pmp_mapping_request(30334, 0, 0).to_vec(),
+		]);
+	}
+
+	#[test]
+	fn failed_nat_pmp_requests_are_reported() {
+		let router = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+		socket.connect(router.local_addr().unwrap()).unwrap();
+		let router = thread::spawn(move || {
+			let mut request = [0; 12];
+			let (_, from) = router.recv_from(&mut request).unwrap();
+			// result code 3: network failure.
+			router.send_to(&[0, 128, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0], from).unwrap();
+		});
+
+		assert_eq!(nat_pmp_request(&socket, &[0, 0], 12), Err("result code 3".to_string()));
+		router.join().unwrap();
+	}
+
+	#[test]
+	fn dropping_the_port_mapping_removes_the_mappings_and_stops_the_thread() {
+		let router = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+		socket.set_read_timeout(Some(GATEWAY_TIMEOUT)).unwrap();
+		socket.connect(router.local_addr().unwrap()).unwrap();
+		// the external address and the mapping of the port, then its removal.
+		let router = nat_pmp_router(router, 3);
+
+		let mut mapping = start_with(vec![30333], move |ports| {
+			map_nat_pmp_with(socket.try_clone().unwrap(), ports).ok()
+		});
+		assert!(mapping.thread.is_some());
+		assert_eq!(
+			(&mut mapping).take(1).collect().wait(),
+			Ok(vec![tcp_addr(Ipv4Addr::new(1, 2, 3, 4), 30334)]),
+		);
+		// returns once the thread is done, rather than after the renew interval.
+		drop(mapping);
+
+		let lease = LEASE_DURATION.as_secs() as u32;
+		assert_eq!(router.join().unwrap(), vec![
+			vec![0, 0],
+			nat_pmp_mapping_request(30333, 30333, lease).to_vec(),
+			nat_pmp_mapping_request(30333, 0, 0).to_vec(),
+		]);
+	}
+}
@@ -0,0 +1,223 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reputation of the peers, raised or lowered by reports of their behaviour
+//! and decaying back towards zero over time.
+//!
+//! A peer whose reputation drops to `DISCONNECT_THRESHOLD` is disconnected, and
+//! banned for a while once it drops to `BAN_THRESHOLD`. Only a change as large as
+//! `BAN_THRESHOLD` on its own, such as a `Severity::Bad` report, can take a peer
+//! there: smaller ones, however many, stop just above it. Reputations are kept by
+//! `PeerId`, so that reconnecting doesn't clear them, and are saved along with
+//! the topology so that restarting doesn't either.
+
+use crate::traits::Severity;
+use fnv::FnvHashMap;
+use libp2p::PeerId;
+use std::time::{Duration, Instant};
+
+/// Reputation at or below which a peer is disconnected.
+pub const DISCONNECT_THRESHOLD: i32 = -100;
+/// Reputation at or below which a peer is banned for a while.
+pub const BAN_THRESHOLD: i32 = -1000;
+/// Bounds of the reputation, so that a peer can't build up credit or debt forever.
+const MAX_REPUTATION: i32 = 1000;
+const MIN_REPUTATION: i32 = 2 * BAN_THRESHOLD;
+/// Every second, a reputation moves towards zero by this fraction of itself, and at least by one.
+const DECAY_DIVISOR: i32 = 50;
+
+/// Change of the reputation of a peer, with the reason for it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ReputationChange<'a> {
+	/// Value added to the reputation. Negative for misbehaviour.
+	pub value: i32,
+	/// Reason of the change, for diagnostic.
+	pub reason: &'a str,
+}
+
+impl<'a> ReputationChange<'a> {
+	/// Create a new change.
+	pub fn new(value: i32, reason: &'a str) -> Self {
+		ReputationChange { value, reason }
+	}
+}
+
+impl<'a> From<Severity<'a>> for ReputationChange<'a> {
+	fn from(severity: Severity<'a>) -> Self {
+		match severity {
+			Severity::Timeout => ReputationChange::new(DISCONNECT_THRESHOLD, "Timeout"),
+			Severity::Useless(reason) => ReputationChange::new(2 * DISCONNECT_THRESHOLD, reason),
+			Severity::Bad(reason) => ReputationChange::new(BAN_THRESHOLD, reason),
+		}
+	}
+}
+
+/// What to do with a peer after its reputation changed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum ReputationAction {
+	/// Nothing, the peer stays connected.
+	Keep,
+	/// Disconnect the peer.
+	Disconnect,
+	/// Disconnect the peer and ban it for a while.
+	Ban,
+}
+
+/// Reputations of the peers we heard about.
+#[derive(Default)]
+pub(crate) struct Reputations {
+	peers: FnvHashMap<PeerId, (i32, Instant)>,
+}
+
+impl Reputations {
+	/// Restore the reputation of a peer, e.g. as loaded from disk.
+	pub(crate) fn set(&mut self, peer_id: PeerId, reputation: i32) {
+		let reputation = reputation.max(MIN_REPUTATION).min(MAX_REPUTATION);
+		self.peers.insert(peer_id, (reputation, Instant::now()));
+	}
+
+	/// Current reputations of the peers we have a record of.
+	pub(crate) fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a PeerId, i32)> + 'a {
+		let now = Instant::now();
+		self.peers.iter().map(move |(peer_id, &(reputation, updated))| {
+			(peer_id, decay(reputation, elapsed_secs(updated, now)))
+		})
+	}
+
+	/// Current reputation of a peer.
+	pub(crate) fn get(&self, peer_id: &PeerId) -> i32 {
+		self.peers.get(peer_id)
+			.map_or(0, |&(reputation, updated)| decay(reputation, elapsed_secs(updated, Instant::now())))
+	}
+
+	/// Apply a change to the reputation of a peer and return what to do with the peer.
+	pub(crate) fn report(&mut self, peer_id: &PeerId, change: i32) -> ReputationAction {
+		self.report_at(peer_id, change, Instant::now())
+	}
+
+	fn report_at(&mut self, peer_id: &PeerId, change: i32, now: Instant) -> ReputationAction {
+		let entry = self.peers.entry(peer_id.clone()).or_insert((0, now));
+		let secs = elapsed_secs(entry.1, now);
+		// only whole seconds are accounted for, the remainder is kept for the next update.
+		entry.0 = decay(entry.0, secs);
+		entry.1 += Duration::from_secs(secs);
+		let floor = if change <= BAN_THRESHOLD {
+			MIN_REPUTATION
+		} else {
+			entry.0.min(BAN_THRESHOLD + 1)
+		};
+		entry.0 = entry.0.saturating_add(change).max(floor).min(MAX_REPUTATION);
+
+		if change >= 0 {
+			ReputationAction::Keep
+		} else if entry.0 <= BAN_THRESHOLD {
+			ReputationAction::Ban
+		} else if entry.0 <= DISCONNECT_THRESHOLD {
+			ReputationAction::Disconnect
+		} else {
+			ReputationAction::Keep
+		}
+	}
+
+	/// Forget the peers whose reputation decayed to zero.
+	pub(crate) fn cleanup(&mut self) {
+		let now = Instant::now();
+		self.peers.retain(|_, &mut (reputation, updated)| decay(reputation, elapsed_secs(updated, now)) != 0);
+		self.peers.shrink_to_fit();
+	}
+}
+
+fn elapsed_secs(since: Instant, now: Instant) -> u64 {
+	if now > since { (now - since).as_secs() } else { 0 }
+}
+
+fn decay(reputation: i32, secs: u64) -> i32 {
+	// Down to `DECAY_DIVISOR`, the magnitude shrinks geometrically. From there it goes down
+	// by one every second.
+	let magnitude = f64::from(reputation.abs());
+	let linear_start = f64::from(DECAY_DIVISOR);
+	let ratio = 1.0 - 1.0 / linear_start;
+	let secs = secs as f64;
+	let geometric_secs = if magnitude > linear_start {
+		(linear_start / magnitude).ln() / ratio.ln()
+	} else {
+		0.0
+	};
+	let magnitude = if secs <= geometric_secs {
+		magnitude * ratio.powf(secs)
+	} else {
+		magnitude.min(linear_start) - (secs - geometric_secs)
+	};
+	reputation.signum() * magnitude.max(0.0).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn offences_lead_to_a_disconnection_then_a_ban() {
+		let mut reputations = Reputations::default();
+		let peer = PeerId::random();
+		let now = Instant::now();
+
+		assert_eq!(reputations.report_at(&peer, 50, now), ReputationAction::Keep);
+		assert_eq!(reputations.report_at(&peer, -100, now), ReputationAction::Keep);
+		assert_eq!(reputations.report_at(&peer, -100, now), ReputationAction::Disconnect);
+		assert_eq!(reputations.report_at(&peer, BAN_THRESHOLD, now), ReputationAction::Ban);
+		assert_eq!(reputations.report_at(&peer, 10, now), ReputationAction::Keep);
+		assert_eq!(reputations.get(&PeerId::random()), 0);
+	}
+
+	#[test]
+	fn repeated_useless_reports_do_not_lead_to_a_ban() {
+		let mut reputations = Reputations::default();
+		let peer = PeerId::random();
+		let now = Instant::now();
+		let useless = ReputationChange::from(Severity::Useless("test")).value;
+
+		for _ in 0..20 {
+			assert_eq!(reputations.report_at(&peer, useless, now), ReputationAction::Disconnect);
+		}
+		assert_eq!(reputations.get(&peer), BAN_THRESHOLD + 1);
+		assert_eq!(reputations.report_at(&peer, -1, now), ReputationAction::Disconnect);
+		assert_eq!(reputations.report_at(&peer, BAN_THRESHOLD, now), ReputationAction::Ban);
+		// a banned peer stays at the ban level when it's reported again.
+		assert_eq!(reputations.report_at(&peer, useless, now), ReputationAction::Ban);
+	}
+
+	#[test]
+	fn reputation_decays_towards_zero() {
+		assert_eq!(decay(1000, 1), 980);
+		assert_eq!(decay(-1000, 1), -980);
+		assert_eq!(decay(-30, 1), -29);
+		assert_eq!(decay(3, 10), 0);
+		assert_eq!(decay(49, 1), 48);
+		assert_eq!(decay(-2000, 3600), 0);
+		assert_eq!(decay(0, 100), 0);
+		assert_eq!(decay(1000, u64::max_value()), 0);
+		// the closed form doesn't depend on how the time is split.
+		assert_eq!(decay(decay(1000, 50), 50), decay(1000, 100));
+
+		let mut reputations = Reputations::default();
+		let peer = PeerId::random();
+		let now = Instant::now();
+		reputations.report_at(&peer, BAN_THRESHOLD, now);
+		// a banned peer that behaves after its ban isn't dropped straight away.
+		let later = now + Duration::from_secs(300);
+		assert_eq!(reputations.report_at(&peer, -50, later), ReputationAction::Keep);
+	}
+}
@@ -14,38 +14,64 @@
 // You should have received a copy of the GNU General Public License
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::NetworkConfiguration;
+use crate::{NetworkConfiguration, NodeKeyType};
 use libp2p::secio;
-use log::{trace, warn};
+use log::{info, trace, warn};
 use rand::Rng;
 use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Write};
-use std::{fs, path::Path};
+use std::{fs, path::Path, path::PathBuf};
 
 // File where the private key is stored.
 const SECRET_FILE: &str = "secret";
 
+// Secp256k1 keys are stored as is, like before key types were introduced, so that the files
+// stay readable by older versions. Other keys are preceded by a byte telling their type.
+const ED25519_TAG: u8 = 1;
+
 /// Obtains or generates the local private key using the configuration.
 pub fn obtain_private_key_from_config(
 	config: &NetworkConfiguration
 ) -> Result<secio::SecioKeyPair, IoError> {
-	obtain_private_key(&config.use_secret, &config.net_config_path)
+	obtain_private_key(&config.use_secret, &config.net_config_path, config.node_key_type)
 }
 
 /// Obtains or generates the local private key using the configuration.
+///
+/// If the key stored in `net_config_path` isn't of the requested type, it is moved aside
+/// and a key of the requested type is used instead, restored from a previous move if any.
 pub fn obtain_private_key(
 	secret: &Option<[u8; 32]>,
 	net_config_path: &Option<String>,
+	key_type: NodeKeyType,
 ) -> Result<secio::SecioKeyPair, IoError> {
 	if let Some(ref secret) = secret {
 		// Key was specified in the configuration.
-		secio::SecioKeyPair::secp256k1_raw_key(&secret[..])
-			.map_err(|err| IoError::new(IoErrorKind::InvalidData, err))
+		key_pair(key_type, &secret[..])
 	} else {
 		if let Some(ref path) = net_config_path {
 			// Try fetch the key from a the file containing the secret.
 			let secret_path = Path::new(path).join(SECRET_FILE);
 			match load_private_key_from_file(&secret_path) {
-				Ok(s) => Ok(s),
+				Ok((stored_type, raw_key)) if stored_type == key_type => key_pair(key_type, &raw_key),
+				Ok((stored_type, _)) => {
+					let previous_path = backup_path(&secret_path, stored_type);
+					info!(target: "sub-libp2p", "Switching node key from {} to {}, which changes \
+						the identity of the node. The previous key is kept in {:?}",
+						stored_type.name(), key_type.name(), previous_path);
+					fs::rename(&secret_path, &previous_path)?;
+
+					// Switching back to a key type we used before restores the identity we had.
+					let restore_path = backup_path(&secret_path, key_type);
+					if let Ok((restored_type, raw_key)) = load_private_key_from_file(&restore_path) {
+						if restored_type == key_type {
+							info!(target: "sub-libp2p", "Restoring the {} node key from {:?}",
+								key_type.name(), restore_path);
+							fs::rename(&restore_path, &secret_path)?;
+							return key_pair(key_type, &raw_key)
+						}
+					}
+					Ok(gen_key_and_try_write_to_file(&secret_path, key_type))
+				},
 				Err(err) => {
 					// Failed to fetch existing file ; generate a new key
 					trace!(target: "sub-libp2p",
@@ -53,7 +79,7 @@ pub fn obtain_private_key(
 						secret_path,
 						err
 					);
-					Ok(gen_key_and_try_write_to_file(&secret_path))
+					Ok(gen_key_and_try_write_to_file(&secret_path, key_type))
 				}
 			}
 
@@ -62,42 +88,75 @@ pub fn obtain_private_key(
 			// a new key.
 			let mut key: [u8; 32] = [0; 32];
 			rand::rngs::EntropyRng::new().fill(&mut key);
-			Ok(secio::SecioKeyPair::secp256k1_raw_key(&key)
+			Ok(key_pair(key_type, &key)
 				.expect("randomly-generated key with correct len should always be valid"))
 		}
 	}
 }
 
-/// Tries to load a private key from a file located at the given path.
+/// Builds a key pair of the given type from a raw private key.
+fn key_pair(key_type: NodeKeyType, raw_key: &[u8]) -> Result<secio::SecioKeyPair, IoError> {
+	match key_type {
+		NodeKeyType::Secp256k1 => secio::SecioKeyPair::secp256k1_raw_key(raw_key),
+		NodeKeyType::Ed25519 => secio::SecioKeyPair::ed25519_raw_key(raw_key),
+	}.map_err(|err| IoError::new(IoErrorKind::InvalidData, err))
+}
+
+/// Path where a key of the given type is kept while another type is used.
+fn backup_path(secret_path: &Path, key_type: NodeKeyType) -> PathBuf {
+	secret_path.with_extension(key_type.name())
+}
+
+/// Tries to load a private key from a file located at the given path. Returns the type
+/// of the key and the key.
 fn load_private_key_from_file<P>(path: P)
-	-> Result<secio::SecioKeyPair, IoError>
+	-> Result<(NodeKeyType, Vec<u8>), IoError>
 	where P: AsRef<Path> {
-	fs::File::open(path)
+	let content = fs::File::open(path)
 		.and_then(|mut file| {
 			// We are in 2018 and there is still no method on `std::io::Read`
 			// that directly returns a `Vec`.
 			let mut buf = Vec::new();
 			file.read_to_end(&mut buf).map(|_| buf)
-		})
-		.and_then(|content|
-			secio::SecioKeyPair::secp256k1_raw_key(&content)
-				.map_err(|err| IoError::new(IoErrorKind::InvalidData, err))
-		)
+		})?;
+
+	let tag = content.first().cloned();
+	let (key_type, raw_key) = match (content.len(), tag) {
+		(32, _) => (NodeKeyType::Secp256k1, content),
+		(33, Some(ED25519_TAG)) => (NodeKeyType::Ed25519, content[1..].to_vec()),
+		_ => return Err(IoError::new(IoErrorKind::InvalidData, "Unknown secret key format")),
+	};
+	// Make sure that the key is valid before using it.
+	key_pair(key_type, &raw_key)?;
+	Ok((key_type, raw_key))
+}
+
+/// Returns the content of a key file for the given key.
+fn key_file_content(key_type: NodeKeyType, raw_key: &[u8]) -> Vec<u8> {
+	match key_type {
+		NodeKeyType::Secp256k1 => raw_key.to_vec(),
+		NodeKeyType::Ed25519 => {
+			let mut content = Vec::with_capacity(1 + raw_key.len());
+			content.push(ED25519_TAG);
+			content.extend_from_slice(raw_key);
+			content
+		},
+	}
 }
 
 /// Generates a new secret key and tries to write it to the given file.
 /// Doesn't error if we couldn't open or write to the file.
-fn gen_key_and_try_write_to_file<P>(path: P) -> secio::SecioKeyPair
+fn gen_key_and_try_write_to_file<P>(path: P, key_type: NodeKeyType) -> secio::SecioKeyPair
 	where P: AsRef<Path> {
 	let raw_key: [u8; 32] = rand::rngs::EntropyRng::new().gen();
-	let secio_key = secio::SecioKeyPair::secp256k1_raw_key(&raw_key)
+	let secio_key = key_pair(key_type, &raw_key)
 		.expect("randomly-generated key with correct len should always be valid");
 
 	// And store the newly-generated key in the file if possible.
 	// Errors that happen while doing so are ignored.
 	match open_priv_key_file(&path) {
 		Ok(mut file) =>
-			match file.write_all(&raw_key) {
+			match file.write_all(&key_file_content(key_type, &raw_key)) {
 				Ok(()) => (),
 				Err(err) => warn!(target: "sub-libp2p",
 					"Failed to write secret key in file {:?} ; err = {:?}",
@@ -135,3 +194,63 @@ fn open_priv_key_file<P>(path: P) -> Result<fs::File, IoError>
 		.create_new(true)
 		.open(path)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("substrate-secret-{}-{}", name, rand::random::<u64>()));
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	fn obtain(dir: &Path, key_type: NodeKeyType) -> secio::SecioKeyPair {
+		obtain_private_key(&None, &Some(dir.to_string_lossy().into_owned()), key_type).unwrap()
+	}
+
+	#[test]
+	fn secp256k1_keys_stay_in_the_legacy_format() {
+		let dir = temp_dir("legacy");
+		let secret_path = dir.join(SECRET_FILE);
+		fs::write(&secret_path, &[1u8; 32]).unwrap();
+
+		let key = obtain(&dir, NodeKeyType::Secp256k1);
+		assert_eq!(key.to_peer_id(), key_pair(NodeKeyType::Secp256k1, &[1u8; 32]).unwrap().to_peer_id());
+		assert_eq!(fs::read(&secret_path).unwrap(), vec![1u8; 32]);
+
+		// new secp256k1 keys are written in the same format.
+		fs::remove_file(&secret_path).unwrap();
+		let key = obtain(&dir, NodeKeyType::Secp256k1);
+		let content = fs::read(&secret_path).unwrap();
+		assert_eq!(content.len(), 32);
+		assert_eq!(key.to_peer_id(), key_pair(NodeKeyType::Secp256k1, &content).unwrap().to_peer_id());
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn switching_key_types_restores_previous_identity() {
+		let dir = temp_dir("switch");
+		let secret_path = dir.join(SECRET_FILE);
+
+		let secp256k1 = obtain(&dir, NodeKeyType::Secp256k1).to_peer_id();
+		let ed25519 = obtain(&dir, NodeKeyType::Ed25519).to_peer_id();
+		assert_ne!(secp256k1, ed25519);
+		let content = fs::read(&secret_path).unwrap();
+		assert_eq!((content.len(), content[0]), (33, ED25519_TAG));
+		assert!(backup_path(&secret_path, NodeKeyType::Secp256k1).exists());
+
+		assert_eq!(obtain(&dir, NodeKeyType::Secp256k1).to_peer_id(), secp256k1);
+		assert_eq!(obtain(&dir, NodeKeyType::Ed25519).to_peer_id(), ed25519);
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn unknown_key_formats_are_rejected() {
+		let dir = temp_dir("unknown");
+		let secret_path = dir.join(SECRET_FILE);
+		fs::write(&secret_path, &[7u8; 33]).unwrap();
+		assert!(load_private_key_from_file(&secret_path).is_err());
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
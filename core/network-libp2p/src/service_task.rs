@@ -18,27 +18,36 @@ use crate::{
 	behaviour::Behaviour, behaviour::BehaviourOut, secret::obtain_private_key_from_config,
 	transport
 };
+use crate::bootnodes;
+use crate::ip_filter::{self, IpFilter, IpFilterList, IpNetwork};
 use crate::custom_proto::{RegisteredProtocol, RegisteredProtocols};
+use crate::port_mapping;
+use crate::reputation::{Reputations, ReputationAction, ReputationChange};
 use crate::topology::NetTopology;
 use crate::{Error, NetworkConfiguration, NodeIndex, ProtocolId, parse_str_addr};
 use bytes::Bytes;
 use fnv::FnvHashMap;
-use futures::{prelude::*, Stream};
-use libp2p::{Multiaddr, PeerId, multiaddr};
+use futures::{prelude::*, Stream, sync::mpsc};
+use libp2p::{Multiaddr, PeerId, multiaddr, multiaddr::Protocol};
 use libp2p::core::{Swarm, nodes::Substream, transport::boxed::Boxed, muxing::StreamMuxerBox};
 use libp2p::core::nodes::ConnectedPoint;
 use log::{debug, info, warn};
+use parking_lot::RwLock;
 use std::collections::hash_map::Entry;
-use std::fs;
+use std::{cmp, fs, iter};
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio_timer::Interval;
 
 // File where the network topology is stored.
 const NODES_FILE: &str = "nodes.json";
+/// Delay before trying to connect again to a bootstrap node we aren't connected to. Doubled
+/// after every attempt, up to `BOOTNODE_MAX_RETRY`.
+const BOOTNODE_FIRST_RETRY: Duration = Duration::from_secs(60);
+const BOOTNODE_MAX_RETRY: Duration = Duration::from_secs(30 * 60);
 
 /// Starts the substrate libp2p service.
 ///
@@ -71,28 +80,76 @@ where TProtos: IntoIterator<Item = RegisteredProtocol> {
 	// Register the external addresses provided by the user as our own.
 	topology.add_external_addrs(config.public_addresses.clone().into_iter());
 
+	// Restore the reputations saved along with the topology.
+	let mut reputations = Reputations::default();
+	for (peer_id, reputation) in topology.reputations() {
+		reputations.set(peer_id.clone(), reputation);
+	}
+
+	let ip_filter = Arc::new(RwLock::new(IpFilter::new(
+		config.allowed_ips.clone(),
+		config.denied_ips.clone(),
+	)));
+
 	// Build the swarm.
-	let (mut swarm, bandwidth) = {
+	let (mut swarm, bandwidth, connection_metrics) = {
 		let registered_custom = RegisteredProtocols(registered_custom.into_iter().collect());
 		let behaviour = Behaviour::new(&config, local_peer_id.clone(), registered_custom);
-		let (transport, bandwidth) = transport::build_transport(local_private_key);
-		(Swarm::new(transport, behaviour, topology), bandwidth)
+		let tls_acceptor = match config.wss_certificate {
+			Some(ref certificate) => Some(transport::tls_acceptor(certificate)?),
+			None => None,
+		};
+		let pre_shared_key = match config.pre_shared_key_path {
+			Some(ref path) => {
+				info!(target: "sub-libp2p", "Only connecting to nodes of the private network of {}", path);
+				Some(transport::PreSharedKey::from_file(path)?)
+			},
+			None => None,
+		};
+		let (transport, bandwidth, connection_metrics) = transport::build_transport(
+			local_private_key,
+			tls_acceptor,
+			pre_shared_key,
+			&config.multiplexer,
+			ip_filter.clone(),
+		);
+		(Swarm::new(transport, behaviour, topology), bandwidth, connection_metrics)
 	};
 
 	// Listen on multiaddresses.
+	let mut listen_ports = Vec::new();
 	for addr in &config.listen_addresses {
 		match Swarm::listen_on(&mut swarm, addr.clone()) {
-			Ok(new_addr) => debug!(target: "sub-libp2p", "Libp2p listening on {}", new_addr),
+			Ok(new_addr) => {
+				debug!(target: "sub-libp2p", "Libp2p listening on {}", new_addr);
+				let mut protocols = new_addr.iter();
+				if let (Some(Protocol::Ip4(_)), Some(Protocol::Tcp(port))) = (protocols.next(), protocols.next()) {
+					listen_ports.push(port);
+				}
+			},
 			Err(err) => warn!(target: "sub-libp2p", "Can't listen on {} because: {:?}", addr, err)
 		}
 	}
 
+	// Map the listening ports on the router, if enabled.
+	let port_mapping = if config.enable_port_mapping && !listen_ports.is_empty() {
+		Some(port_mapping::start(listen_ports))
+	} else {
+		None
+	};
+
 	// Add the bootstrap nodes to the topology and connect to them.
+	let mut boot_nodes = FnvHashMap::default();
+	let mut dns_boot_nodes = Vec::new();
 	for bootnode in config.boot_nodes.iter() {
 		match parse_str_addr(bootnode) {
 			Ok((peer_id, addr)) => {
 				Swarm::topology_mut(&mut swarm).add_bootstrap_addr(&peer_id, addr.clone());
-				Swarm::dial(&mut swarm, peer_id);
+				Swarm::dial(&mut swarm, peer_id.clone());
+				if bootnodes::is_dns(&addr) {
+					dns_boot_nodes.push((peer_id.clone(), addr));
+				}
+				boot_nodes.insert(peer_id, (Instant::now() + BOOTNODE_FIRST_RETRY, BOOTNODE_FIRST_RETRY * 2));
 			},
 			Err(_) => {
 				// If the format of the bootstrap node is not a multiaddr, try to parse it as
@@ -115,6 +172,13 @@ where TProtos: IntoIterator<Item = RegisteredProtocol> {
 		}
 	}
 
+	// Resolve the names of the bootstrap nodes again from time to time.
+	let boot_nodes_resolution = if !dns_boot_nodes.is_empty() {
+		Some(bootnodes::start(dns_boot_nodes))
+	} else {
+		None
+	};
+
 	// Initialize the reserved peers.
 	for reserved in config.reserved_nodes.iter() {
 		if let Ok((peer_id, addr)) = parse_str_addr(reserved) {
@@ -132,9 +196,15 @@ where TProtos: IntoIterator<Item = RegisteredProtocol> {
 	Ok(Service {
 		swarm,
 		bandwidth,
+		connection_metrics,
+		ip_filter,
 		nodes_info: Default::default(),
 		index_by_id: Default::default(),
 		next_node_id: 1,
+		reputations,
+		port_mapping,
+		boot_nodes,
+		boot_nodes_resolution,
 		cleanup: Interval::new_interval(Duration::from_secs(60)),
 		injected_events: Vec::new(),
 	})
@@ -200,6 +270,12 @@ pub struct Service {
 	/// Bandwidth logging system. Can be queried to know the average bandwidth consumed.
 	bandwidth: Arc<transport::BandwidthSinks>,
 
+	/// Counters of the dials and handshakes of the transport.
+	connection_metrics: Arc<transport::ConnectionMetricsSinks>,
+
+	/// IP networks connections are allowed with or denied. Shared with the transport.
+	ip_filter: Arc<RwLock<IpFilter>>,
+
 	/// Information about all the nodes we're connected to.
 	nodes_info: FnvHashMap<NodeIndex, NodeInfo>,
 
@@ -209,6 +285,19 @@ pub struct Service {
 	/// Next index to assign to a node.
 	next_node_id: NodeIndex,
 
+	/// Reputation of the peers, by `PeerId` so that it survives reconnections.
+	reputations: Reputations,
+
+	/// External addresses discovered by mapping our ports on the router, if enabled.
+	port_mapping: Option<port_mapping::PortMapping>,
+
+	/// Bootstrap nodes, with when to try connecting to them again if we aren't connected, and
+	/// the delay before the attempt after that.
+	boot_nodes: FnvHashMap<PeerId, (Instant, Duration)>,
+
+	/// Addresses the DNS names of the bootstrap nodes resolve to, if there are any names.
+	boot_nodes_resolution: Option<mpsc::UnboundedReceiver<(PeerId, Vec<Multiaddr>)>>,
+
 	/// Stream that fires when we need to cleanup and flush the topology, and cleanup the disabled
 	/// peers.
 	cleanup: Interval,
@@ -235,6 +324,12 @@ impl Service {
 		Swarm::listeners(&self.swarm)
 	}
 
+	/// Returns the addresses of the local node that we report to the network. Addresses
+	/// observed by other peers are only part of it once enough peers confirmed them.
+	pub fn external_addresses(&self) -> Vec<Multiaddr> {
+		Swarm::topology(&self.swarm).advertised_addrs()
+	}
+
 	/// Returns the downloaded bytes per second averaged over the past few seconds.
 	#[inline]
 	pub fn average_download_per_sec(&self) -> u64 {
@@ -247,6 +342,13 @@ impl Service {
 		self.bandwidth.average_upload_per_sec()
 	}
 
+	/// Returns the counters of the connections, to diagnose connectivity problems.
+	pub fn connection_metrics(&self) -> transport::ConnectionMetrics {
+		let mut metrics = self.connection_metrics.snapshot();
+		metrics.open_connections = Swarm::topology(&self.swarm).open_connections();
+		metrics
+	}
+
 	/// Returns the peer id of the local node.
 	#[inline]
 	pub fn peer_id(&self) -> &PeerId {
@@ -259,6 +361,30 @@ impl Service {
 		self.nodes_info.keys().cloned()
 	}
 
+	/// Adds an address we know a peer is reachable at, which is tried before the discovered ones.
+	pub fn add_known_address(&mut self, peer_id: PeerId, addr: Multiaddr) {
+		Swarm::topology_mut(&mut self.swarm).add_bootstrap_addr(&peer_id, addr);
+	}
+
+	/// Starts connecting to a peer, through the addresses the topology knows for it.
+	///
+	/// Has no effect if we're already connected or connecting to it.
+	pub fn dial_peer(&mut self, peer_id: PeerId) {
+		if &peer_id == Swarm::local_peer_id(&self.swarm) {
+			return
+		}
+		debug!(target: "sub-libp2p", "Dialing {:?} on purpose", peer_id);
+		Swarm::dial(&mut self.swarm, peer_id);
+	}
+
+	/// Disconnects a peer, by `PeerId` rather than by `NodeIndex`.
+	///
+	/// This is asynchronous and will not immediately close the peer.
+	pub fn disconnect_peer(&mut self, peer_id: &PeerId, reason: &str) {
+		debug!(target: "sub-libp2p", "Disconnecting {:?} on purpose: {}", peer_id, reason);
+		self.swarm.drop_node(peer_id);
+	}
+
 	/// Try to add a reserved peer.
 	pub fn add_reserved_peer(&mut self, peer_id: PeerId, addr: Multiaddr) {
 		Swarm::topology_mut(&mut self.swarm).add_bootstrap_addr(&peer_id, addr);
@@ -273,6 +399,34 @@ impl Service {
 		self.swarm.remove_reserved_peer(peer_id);
 	}
 
+	/// Returns true if the node is one of the reserved peers.
+	pub fn is_reserved_node(&self, node_index: NodeIndex) -> bool {
+		self.nodes_info.get(&node_index)
+			.map_or(false, |info| self.swarm.is_reserved_peer(&info.peer_id))
+	}
+
+	/// Returns true if we only accept reserved nodes.
+	#[inline]
+	pub fn is_reserved_only(&self) -> bool {
+		self.swarm.is_reserved_only()
+	}
+
+	/// Returns true if we discover the nodes of the local network through mDNS.
+	#[inline]
+	pub fn is_mdns_enabled(&self) -> bool {
+		self.swarm.is_mdns_enabled()
+	}
+
+	/// Starts or stops discovering the nodes of the local network through mDNS.
+	pub fn set_mdns_enabled(&mut self, enabled: bool) -> Result<(), IoError> {
+		if enabled {
+			self.swarm.enable_mdns()
+		} else {
+			self.swarm.disable_mdns();
+			Ok(())
+		}
+	}
+
 	/// Start accepting all peers again if we weren't.
 	#[inline]
 	pub fn accept_unreserved_peers(&mut self) {
@@ -285,6 +439,65 @@ impl Service {
 		self.swarm.deny_unreserved_peers();
 	}
 
+	/// Returns the maximum number of incoming and outgoing non-reserved connections.
+	#[inline]
+	pub fn peer_slots(&self) -> (u32, u32) {
+		let (in_peers, out_peers) = self.swarm.peer_slots();
+		(in_peers as u32, out_peers as u32)
+	}
+
+	/// Changes the maximum number of incoming and outgoing non-reserved connections, similar to
+	/// `in_peers` and `out_peers` in the configuration. Disconnects the least reputable excess
+	/// nodes if we have fewer slots than connected nodes, and opens connections if we have more
+	/// outgoing slots.
+	pub fn set_peer_slots(&mut self, in_peers: u32, out_peers: u32) {
+		info!(target: "sub-libp2p", "Changing peer slots to {} in and {} out", in_peers, out_peers);
+		let reputations = &self.reputations;
+		self.swarm.set_peer_slots(in_peers as usize, out_peers as usize, |peer_id| reputations.get(peer_id));
+	}
+
+	/// Returns the networks of the allow or deny list.
+	pub fn ip_filter(&self, list: IpFilterList) -> Vec<IpNetwork> {
+		self.ip_filter.read().list(list).to_vec()
+	}
+
+	/// Adds a network to the allow or deny list, and disconnects the nodes whose address is no
+	/// longer allowed.
+	pub fn add_ip_filter(&mut self, list: IpFilterList, network: IpNetwork) {
+		info!(target: "sub-libp2p", "Adding {} to the {:?} list", network, list);
+		self.ip_filter.write().add(list, network);
+		self.disconnect_filtered_nodes();
+	}
+
+	/// Removes a network from the allow or deny list, and disconnects the nodes whose address
+	/// is no longer allowed. Returns false if the network wasn't part of the list.
+	pub fn remove_ip_filter(&mut self, list: IpFilterList, network: &IpNetwork) -> bool {
+		info!(target: "sub-libp2p", "Removing {} from the {:?} list", network, list);
+		let removed = self.ip_filter.write().remove(list, network);
+		self.disconnect_filtered_nodes();
+		removed
+	}
+
+	/// Disconnects the nodes we're connected to whose address isn't allowed by the filter.
+	fn disconnect_filtered_nodes(&mut self) {
+		let to_disconnect = {
+			let filter = self.ip_filter.read();
+			self.nodes_info.values()
+				.filter(|info| {
+					let addr = match info.endpoint {
+						ConnectedPoint::Dialer { ref address } => address,
+						ConnectedPoint::Listener { ref send_back_addr, .. } => send_back_addr,
+					};
+					ip_filter::ip_of(addr).map_or(false, |ip| !filter.is_allowed(&ip))
+				})
+				.map(|info| info.peer_id.clone())
+				.collect::<Vec<_>>()
+		};
+		for peer_id in to_disconnect {
+			self.disconnect_peer(&peer_id, "address denied by the IP filter");
+		}
+	}
+
 	/// Returns the `PeerId` of a node.
 	#[inline]
 	pub fn peer_id_of_node(&self, node_index: NodeIndex) -> Option<&PeerId> {
@@ -332,6 +545,31 @@ impl Service {
 		}
 	}
 
+	/// Changes the reputation of a peer, disconnecting or banning it if it drops too low.
+	pub fn report_peer(&mut self, node_index: NodeIndex, change: ReputationChange) {
+		let action = match self.nodes_info.get(&node_index) {
+			Some(info) => {
+				let action = self.reputations.report(&info.peer_id, change.value);
+				let reputation = self.reputations.get(&info.peer_id);
+				debug!(target: "sub-libp2p", "Reputation of {:?} (#{:?}) changed by {} ({}), now {}",
+					info.peer_id, node_index, change.value, change.reason, reputation);
+				Swarm::topology_mut(&mut self.swarm).set_reputation(&info.peer_id, reputation);
+				action
+			},
+			None => return,
+		};
+		match action {
+			ReputationAction::Keep => {},
+			ReputationAction::Disconnect => self.drop_node(node_index),
+			ReputationAction::Ban => self.ban_node(node_index),
+		}
+	}
+
+	/// Returns the current reputation of a peer, or `None` if we're not connected to it.
+	pub fn peer_reputation(&self, node_index: NodeIndex) -> Option<i32> {
+		self.nodes_info.get(&node_index).map(|info| self.reputations.get(&info.peer_id))
+	}
+
 	/// Disconnects a peer.
 	///
 	/// This is asynchronous and will not immediately close the peer.
@@ -408,7 +646,8 @@ impl Service {
 						messages,
 					})))
 				}
-				Ok(Async::Ready(Some(BehaviourOut::Identified { peer_id, info }))) => {
+				Ok(Async::Ready(Some(BehaviourOut::Identified { peer_id, info, observed_addr }))) => {
+					self.add_observed_addr(&peer_id, &observed_addr);
 					// Contrary to the other events, this one can happen even on nodes which don't
 					// have any open custom protocol slot. Therefore it is not necessarily in the
 					// list.
@@ -425,6 +664,77 @@ impl Service {
 		}
 	}
 
+	/// Records that a peer observed us at the given address.
+	fn add_observed_addr(&mut self, reporter: &PeerId, observed: &Multiaddr) {
+		// the port the peer sees is the one of the connection, which is only our listening
+		// port if the peer dialed us. Use the ports we listen on instead.
+		let addrs = Swarm::listeners(&self.swarm)
+			.filter_map(|listen| address_translation(listen, observed))
+			.collect::<Vec<_>>();
+		for addr in addrs {
+			if Swarm::topology_mut(&mut self.swarm).add_observed_addr(reporter, addr.clone()) {
+				info!(target: "sub-libp2p", "Confirmed external address {}", addr);
+			}
+		}
+	}
+
+	/// Polls the external addresses discovered by the port mapping.
+	fn poll_port_mapping(&mut self) {
+		loop {
+			let addr = match self.port_mapping.as_mut().map(|stream| stream.poll()) {
+				Some(Ok(Async::Ready(Some(addr)))) => addr,
+				Some(Ok(Async::NotReady)) | None => return,
+				Some(Ok(Async::Ready(None))) | Some(Err(())) => {
+					self.port_mapping = None;
+					return
+				},
+			};
+			info!(target: "sub-libp2p", "Mapped port on the router, now reachable at {}", addr);
+			Swarm::topology_mut(&mut self.swarm).add_external_addrs(iter::once(addr));
+		}
+	}
+
+	/// Polls the addresses the bootstrap nodes were resolved to.
+	fn poll_boot_nodes_resolution(&mut self) {
+		loop {
+			let (peer_id, addrs) = match self.boot_nodes_resolution.as_mut().map(|stream| stream.poll()) {
+				Some(Ok(Async::Ready(Some(resolved)))) => resolved,
+				Some(Ok(Async::NotReady)) | None => return,
+				Some(Ok(Async::Ready(None))) | Some(Err(())) => {
+					self.boot_nodes_resolution = None;
+					return
+				},
+			};
+			// The addresses that failed are backed off by the topology, so that we rotate
+			// through the other ones. Those the name doesn't resolve to anymore expire.
+			for addr in addrs {
+				Swarm::topology_mut(&mut self.swarm).add_bootstrap_addr(&peer_id, addr);
+			}
+			if !self.index_by_id.contains_key(&peer_id) {
+				Swarm::dial(&mut self.swarm, peer_id);
+			}
+		}
+	}
+
+	/// Tries again to connect to the bootstrap nodes we aren't connected to, with an
+	/// increasing delay between attempts.
+	fn connect_to_boot_nodes(&mut self) {
+		let now = Instant::now();
+		for (peer_id, (next_attempt, back_off)) in self.boot_nodes.iter_mut() {
+			if self.index_by_id.contains_key(peer_id) {
+				*back_off = BOOTNODE_FIRST_RETRY;
+				continue
+			}
+			if *next_attempt > now {
+				continue
+			}
+			debug!(target: "sub-libp2p", "Trying to connect to bootnode {:?} again", peer_id);
+			Swarm::dial(&mut self.swarm, peer_id.clone());
+			*next_attempt = now + *back_off;
+			*back_off = cmp::min(*back_off * 2, BOOTNODE_MAX_RETRY);
+		}
+	}
+
 	/// Polls the stream that fires when we need to cleanup and flush the topology.
 	fn poll_cleanup(&mut self) -> Poll<Option<ServiceEvent>, IoError> {
 		loop {
@@ -433,6 +743,11 @@ impl Service {
 				Ok(Async::Ready(Some(_))) => {
 					debug!(target: "sub-libp2p", "Cleaning and flushing topology");
 					Swarm::topology_mut(&mut self.swarm).cleanup();
+					for (peer_id, reputation) in self.reputations.iter() {
+						Swarm::topology_mut(&mut self.swarm).set_reputation(peer_id, reputation);
+					}
+					self.reputations.cleanup();
+					self.connect_to_boot_nodes();
 					if let Err(err) = Swarm::topology_mut(&mut self.swarm).flush_to_disk() {
 						warn!(target: "sub-libp2p", "Failed to flush topology: {:?}", err);
 					}
@@ -474,6 +789,9 @@ impl Stream for Service {
 			Async::NotReady => (),
 		}
 
+		self.poll_port_mapping();
+		self.poll_boot_nodes_resolution();
+
 		match self.poll_cleanup()? {
 			Async::Ready(value) => return Ok(Async::Ready(value)),
 			Async::NotReady => (),
@@ -484,3 +802,63 @@ impl Stream for Service {
 		Ok(Async::NotReady)
 	}
 }
+
+/// Replaces the IP address of a TCP listening address with the one observed by a remote.
+fn address_translation(listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+	let mut observed = observed.iter();
+	let ip = observed.next()?;
+	match observed.next()? {
+		Protocol::Tcp(_) => {},
+		_ => return None,
+	}
+
+	let mut listen = listen.iter();
+	match (listen.next()?, &ip) {
+		(Protocol::Ip4(_), Protocol::Ip4(_)) | (Protocol::Ip6(_), Protocol::Ip6(_)) => {},
+		_ => return None,
+	}
+	let port = match listen.next()? {
+		Protocol::Tcp(port) => port,
+		_ => return None,
+	};
+
+	Some(iter::once(ip).chain(iter::once(Protocol::Tcp(port))).chain(listen).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::address_translation;
+	use libp2p::Multiaddr;
+
+	fn addr(addr: &str) -> Multiaddr {
+		addr.parse().unwrap()
+	}
+
+	#[test]
+	fn observed_addresses_are_translated_to_listening_ports() {
+		assert_eq!(
+			address_translation(&addr("/ip4/0.0.0.0/tcp/30333"), &addr("/ip4/1.2.3.4/tcp/49152")),
+			Some(addr("/ip4/1.2.3.4/tcp/30333"))
+		);
+		assert_eq!(
+			address_translation(&addr("/ip6/::/tcp/30333"), &addr("/ip6/2001:db8::1/tcp/49152")),
+			Some(addr("/ip6/2001:db8::1/tcp/30333"))
+		);
+		// the protocols above TCP are kept.
+		assert_eq!(
+			address_translation(&addr("/ip4/0.0.0.0/tcp/30334/ws"), &addr("/ip4/1.2.3.4/tcp/49152")),
+			Some(addr("/ip4/1.2.3.4/tcp/30334/ws"))
+		);
+	}
+
+	#[test]
+	fn mismatching_addresses_are_not_translated() {
+		// the IP versions differ.
+		assert_eq!(address_translation(&addr("/ip6/::/tcp/30333"), &addr("/ip4/1.2.3.4/tcp/49152")), None);
+		// only TCP ports are translated.
+		assert_eq!(address_translation(&addr("/ip4/0.0.0.0/tcp/30333"), &addr("/ip4/1.2.3.4/udp/49152")), None);
+		assert_eq!(address_translation(&addr("/ip4/0.0.0.0/udp/30333"), &addr("/ip4/1.2.3.4/tcp/49152")), None);
+		// only IP addresses are observed.
+		assert_eq!(address_translation(&addr("/ip4/0.0.0.0/tcp/30333"), &addr("/dns4/example.com/tcp/49152")), None);
+	}
+}
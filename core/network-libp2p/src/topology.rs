@@ -14,13 +14,17 @@
 // You should have received a copy of the GNU General Public License
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.?
 
+use crate::ip_filter::{IpNetwork, ip_of};
+use crate::reputation::DISCONNECT_THRESHOLD;
 use fnv::FnvHashMap;
 use libp2p::{Multiaddr, PeerId, identify::IdentifyTopology, multihash::Multihash};
 use libp2p::core::{PublicKey, swarm::ConnectedPoint, topology::DisconnectReason, topology::Topology};
 use libp2p::kad::{KBucketsPeerId, KadConnectionType, KademliaTopology};
+use libp2p::mdns::MdnsTopology;
 use log::{debug, info, trace, warn};
 use serde_derive::{Serialize, Deserialize};
 use std::{cmp, fs, iter, vec};
+use std::net::IpAddr;
 use std::io::{Read, Cursor, Error as IoError, ErrorKind as IoErrorKind, Write, BufReader, BufWriter};
 use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant, SystemTime};
@@ -57,6 +61,14 @@ const FIRST_CONNECT_FAIL_BACKOFF: Duration = Duration::from_secs(2);
 const FAIL_BACKOFF_MULTIPLIER: u32 = 2;
 /// We need a maximum value for the backoff, overwise we risk an overflow.
 const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+/// Number of distinct subnets peers must observe us from at an address before we advertise it.
+/// Counting subnets rather than peers prevents a single party running many peers from
+/// making us advertise an address of its choice.
+const OBSERVATIONS_TO_CONFIRM: usize = 3;
+/// Maximum number of addresses peers observed us at that we keep track of.
+const MAX_OBSERVED_ADDRESSES: usize = 32;
+/// Observations of our addresses are forgotten after this duration.
+const OBSERVATION_EXPIRATION: Duration = Duration::from_secs(3600);
 
 // TODO: should be merged with the Kademlia k-buckets
 
@@ -73,6 +85,12 @@ pub struct NetTopology {
 	local_peer_id: PeerId,
 	/// Known addresses for the local node to report to the network.
 	external_addresses: Vec<Multiaddr>,
+	/// Addresses peers observed us at, with when peers of each subnet last reported them.
+	observed_addresses: FnvHashMap<Multiaddr, FnvHashMap<IpNetwork, Instant>>,
+	/// IP addresses of the peers we are connected to.
+	connected_ips: FnvHashMap<PeerId, IpAddr>,
+	/// Number of connections currently open.
+	open_connections: usize,
 }
 
 impl NetTopology {
@@ -88,6 +106,9 @@ impl NetTopology {
 			local_peer_id,
 			local_public_key,
 			external_addresses: Vec::new(),
+			observed_addresses: Default::default(),
+			connected_ips: Default::default(),
+			open_connections: 0,
 		}
 	}
 
@@ -108,6 +129,9 @@ impl NetTopology {
 			local_peer_id,
 			local_public_key,
 			external_addresses: Vec::new(),
+			observed_addresses: Default::default(),
+			connected_ips: Default::default(),
+			open_connections: 0,
 		}
 	}
 
@@ -142,18 +166,104 @@ impl NetTopology {
 				.filter(|a| a.expires > now_systime || a.is_connected())
 				.collect();
 			peer.addrs = new_addrs;
-			!peer.addrs.is_empty()
+			peer.is_worth_keeping()
 		});
+
+		let now = Instant::now();
+		self.observed_addresses.retain(|_, reporters| {
+			reporters.retain(|_, reported| *reported + OBSERVATION_EXPIRATION > now);
+			!reporters.is_empty()
+		});
+	}
+
+	/// Records the reputation of a peer, so that it is kept across restarts.
+	///
+	/// Peers we don't know any address of are kept as long as their reputation isn't neutral.
+	pub fn set_reputation(&mut self, peer: &PeerId, reputation: i32) {
+		if let Some(info) = self.store.get_mut(peer) {
+			info.reputation = reputation;
+		} else if reputation != 0 {
+			peer_access(&mut self.store, peer).reputation = reputation;
+		}
+	}
+
+	/// Returns the reputations recorded for the peers, excluding neutral ones.
+	pub fn reputations(&self) -> impl Iterator<Item = (&PeerId, i32)> {
+		self.store.iter()
+			.filter(|(_, info)| info.reputation != 0)
+			.map(|(peer, info)| (peer, info.reputation))
 	}
 
 	/// Add the external addresses that are known for the local node.
 	pub fn add_external_addrs<TIter>(&mut self, addrs: TIter)
 	where TIter: Iterator<Item = Multiaddr> {
-		self.external_addresses.extend(addrs);
+		for addr in addrs {
+			if !self.external_addresses.contains(&addr) {
+				self.external_addresses.push(addr);
+			}
+		}
+	}
+
+	/// Records that a peer observed us at the given address. The address is
+	/// advertised once peers of enough distinct subnets observed it. Observations
+	/// of peers we aren't connected to, whose IP address we don't know, are ignored.
+	///
+	/// Returns true if this observation confirmed the address.
+	pub fn add_observed_addr(&mut self, reporter: &PeerId, addr: Multiaddr) -> bool {
+		if self.external_addresses.contains(&addr) {
+			return false
+		}
+		let subnet = match self.connected_ips.get(reporter) {
+			Some(ip) => IpNetwork::subnet_of(ip),
+			None => return false,
+		};
+
+		if !self.observed_addresses.contains_key(&addr) &&
+			self.observed_addresses.len() >= MAX_OBSERVED_ADDRESSES
+		{
+			// make room by forgetting the address observed from the fewest subnets.
+			let least_observed = self.observed_addresses.iter()
+				.min_by_key(|(_, reporters)| reporters.len())
+				.map(|(addr, _)| addr.clone());
+			if let Some(least_observed) = least_observed {
+				self.observed_addresses.remove(&least_observed);
+			}
+		}
+
+		let reporters = self.observed_addresses.entry(addr).or_insert_with(Default::default);
+		let was_confirmed = reporters.len() >= OBSERVATIONS_TO_CONFIRM;
+		reporters.insert(subnet, Instant::now());
+		!was_confirmed && reporters.len() >= OBSERVATIONS_TO_CONFIRM
+	}
+
+	/// Returns the number of connections currently open.
+	#[inline]
+	pub fn open_connections(&self) -> usize {
+		self.open_connections
+	}
+
+	/// Returns the addresses that peers of enough subnets observed us at, the most observed first.
+	pub fn confirmed_external_addrs(&self) -> Vec<Multiaddr> {
+		let mut confirmed = self.observed_addresses.iter()
+			.filter(|(_, reporters)| reporters.len() >= OBSERVATIONS_TO_CONFIRM)
+			.map(|(addr, reporters)| (reporters.len(), addr.clone()))
+			.collect::<Vec<_>>();
+		confirmed.sort_by(|a, b| b.0.cmp(&a.0));
+		confirmed.into_iter().map(|(_, addr)| addr).collect()
+	}
+
+	/// Returns the addresses of the local node to report to the network: the
+	/// ones we know of, then the confirmed observed ones.
+	pub fn advertised_addrs(&self) -> Vec<Multiaddr> {
+		let mut addrs = self.external_addresses.clone();
+		addrs.extend(self.confirmed_external_addrs());
+		addrs
 	}
 
 	/// Returns a list of all the known addresses of peers, ordered by the
-	/// order in which we should attempt to connect to them.
+	/// order in which we should attempt to connect to them. Addresses with the
+	/// same score are ordered by when we last saw the peer, most recent first.
+	/// Peers with a bad reputation are skipped.
 	///
 	/// Because of expiration and back-off mechanisms, this list can grow
 	/// by itself over time. The `Instant` that is returned corresponds to
@@ -171,6 +281,10 @@ impl NetTopology {
 		'peer_loop: for (peer, info) in &mut self.store {
 			peer_addrs.clear();
 
+			if info.reputation <= DISCONNECT_THRESHOLD {
+				continue
+			}
+
 			for addr in &mut info.addrs {
 				let (score, is_connected) = addr.score_and_is_connected();
 				if is_connected {
@@ -184,7 +298,7 @@ impl NetTopology {
 					continue
 				}
 
-				peer_addrs.push(((peer, &addr.addr), score));
+				peer_addrs.push(((peer, &addr.addr), (score, info.last_seen)));
 			}
 
 			for val in peer_addrs.drain(..) {
@@ -208,11 +322,13 @@ impl NetTopology {
 		let mut found = false;
 		let new_addrs = peer.addrs
 			.drain(..)
-			.filter_map(|a| {
+			.filter_map(|mut a| {
 				if a.expires < now_systime && !a.is_connected() {
 					return None
 				}
 				if a.addr == addr {
+					// Adding the address again pushes back its expiration.
+					a.expires = cmp::max(a.expires, now_systime + BOOTSTRAP_NODE_EXPIRATION);
 					found = true;
 				}
 				Some(a)
@@ -353,11 +469,19 @@ impl IdentifyTopology for NetTopology {
 	}
 }
 
+impl MdnsTopology for NetTopology {
+	#[inline]
+	fn add_mdns_discovered_address(&mut self, peer: PeerId, addr: Multiaddr) {
+		// The peer answered from the local network, so it is reachable at this address.
+		self.add_discovered_addrs(&peer, iter::once((addr, true)));
+	}
+}
+
 impl Topology for NetTopology {
 	#[inline]
 	fn addresses_of_peer(&mut self, peer: &PeerId) -> Vec<Multiaddr> {
 		if peer == &self.local_peer_id {
-			return self.external_addresses.clone()
+			return self.advertised_addrs()
 		}
 
 		let peer = if let Some(peer) = self.store.get_mut(peer) {
@@ -384,7 +508,11 @@ impl Topology for NetTopology {
 
 	fn add_local_external_addrs<TIter>(&mut self, addrs: TIter)
 	where TIter: Iterator<Item = Multiaddr> {
-		self.add_external_addrs(addrs)
+		// these come from any peer, and we can't tell which one. Observed
+		// addresses are only advertised once confirmed through `add_observed_addr`.
+		for addr in addrs {
+			trace!(target: "sub-libp2p", "Ignoring unconfirmed external address {}", addr);
+		}
 	}
 
 	fn local_peer_id(&self) -> &PeerId {
@@ -396,6 +524,17 @@ impl Topology for NetTopology {
 	}
 
 	fn set_connected(&mut self, peer: &PeerId, endpoint: &ConnectedPoint) {
+		self.open_connections += 1;
+		peer_access(&mut self.store, peer).last_seen = Some(SystemTime::now());
+
+		let remote_addr = match endpoint {
+			ConnectedPoint::Dialer { address } => address,
+			ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
+		};
+		if let Some(ip) = ip_of(remote_addr) {
+			self.connected_ips.insert(peer.clone(), ip);
+		}
+
 		let addr = match endpoint {
 			ConnectedPoint::Dialer { address } => address,
 			ConnectedPoint::Listener { .. } => return
@@ -403,9 +542,6 @@ impl Topology for NetTopology {
 
 		let now = Instant::now();
 
-		// Just making sure that we have an entry for this peer in `store`, but don't use it.
-		let _ = peer_access(&mut self.store, peer);
-
 		for (peer_in_store, info_in_store) in self.store.iter_mut() {
 			if peer == peer_in_store {
 				if let Some(addr) = info_in_store.addrs.iter_mut().find(|a| &a.addr == addr) {
@@ -438,7 +574,13 @@ impl Topology for NetTopology {
 		}
 	}
 
-	fn set_disconnected(&mut self, _: &PeerId, endpoint: &ConnectedPoint, reason: DisconnectReason) {
+	fn set_disconnected(&mut self, peer: &PeerId, endpoint: &ConnectedPoint, reason: DisconnectReason) {
+		self.open_connections = self.open_connections.saturating_sub(1);
+		self.connected_ips.remove(peer);
+		if let Some(info) = self.store.get_mut(peer) {
+			info.last_seen = Some(SystemTime::now());
+		}
+
 		let addr = match endpoint {
 			ConnectedPoint::Dialer { address } => address,
 			ConnectedPoint::Listener { .. } => return
@@ -491,6 +633,18 @@ fn peer_access<'a>(store: &'a mut FnvHashMap<PeerId, PeerInfo>, peer: &PeerId) -
 struct PeerInfo {
 	/// Addresses of that peer.
 	addrs: Vec<Addr>,
+	/// When we were last connected to that peer, if ever.
+	last_seen: Option<SystemTime>,
+	/// Reputation of that peer, as last reported by the service.
+	reputation: i32,
+}
+
+impl PeerInfo {
+	/// Returns true if we know an address of the peer, or if its reputation is worth
+	/// remembering.
+	fn is_worth_keeping(&self) -> bool {
+		!self.addrs.is_empty() || self.reputation != 0
+	}
 }
 
 #[derive(Debug)]
@@ -605,6 +759,11 @@ fn div_dur_with_dur(a: Duration, b: Duration) -> u32 {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SerializedPeerInfo {
 	addrs: Vec<SerializedAddr>,
+	/// Missing in files written by older versions.
+	#[serde(default)]
+	last_seen: Option<SystemTime>,
+	#[serde(default)]
+	reputation: i32,
 }
 
 /// Serialized version of an `Addr`. Suitable for storage in the cache file.
@@ -734,35 +893,41 @@ fn deserialize_tolerant(
 			});
 		}
 
-		if addrs.is_empty() {
-			continue
+		let info = PeerInfo {
+			addrs,
+			last_seen: info.last_seen,
+			reputation: info.reputation,
+		};
+		if info.is_worth_keeping() {
+			out.insert(peer, info);
 		}
-
-		out.insert(peer, PeerInfo { addrs });
 	}
 
 	out
 }
 
-/// Attempts to turn a deserialized version of the storage into the final version.
+/// Turns the storage into its serialized version and writes it to `out`.
 ///
-/// Skips entries that are invalid or expired.
+/// Skips expired addresses, and entries left without an address and with a neutral reputation.
 fn serialize<W: Write>(out: W, map: &mut FnvHashMap<PeerId, PeerInfo>) -> Result<(), IoError> {
 	let now = SystemTime::now();
 	let array: FnvHashMap<_, _> = map.iter_mut().filter_map(|(peer, info)| {
-		if info.addrs.is_empty() {
+		let addrs: Vec<SerializedAddr> = info.addrs.iter_mut()
+			.filter_map(|a| if a.expires > now || a.is_connected() {
+				Some(a.into())
+			} else {
+				None
+			})
+			.collect();
+		if addrs.is_empty() && info.reputation == 0 {
 			return None
 		}
 
 		let peer = peer.to_base58();
 		let info = SerializedPeerInfo {
-			addrs: info.addrs.iter_mut()
-				.filter_map(|a| if a.expires > now || a.is_connected() {
-					Some(a.into())
-				} else {
-					None
-				})
-				.collect(),
+			addrs,
+			last_seen: info.last_seen,
+			reputation: info.reputation,
 		};
 
 		Some((peer, info))
@@ -771,3 +936,133 @@ fn serialize<W: Write>(out: W, map: &mut FnvHashMap<PeerId, PeerInfo>) -> Result
 	serde_json::to_writer_pretty(out, &array)
 		.map_err(|err| IoError::new(IoErrorKind::Other, err))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use libp2p::secio::SecioKeyPair;
+
+	fn topology() -> NetTopology {
+		NetTopology::memory(SecioKeyPair::ed25519_generated().unwrap().to_public_key())
+	}
+
+	fn connect(topology: &mut NetTopology, ip: &str) -> PeerId {
+		let peer = PeerId::random();
+		let address = format!("/ip4/{}/tcp/30333", ip).parse().unwrap();
+		topology.set_connected(&peer, &ConnectedPoint::Dialer { address });
+		peer
+	}
+
+	#[test]
+	fn observed_addresses_are_confirmed_by_distinct_subnets() {
+		let mut topology = topology();
+		let observed: Multiaddr = "/ip4/1.2.3.4/tcp/30333".parse().unwrap();
+
+		// peers of a single subnet, however many, don't confirm the address.
+		for i in 1..6 {
+			let peer = connect(&mut topology, &format!("10.0.0.{}", i));
+			assert!(!topology.add_observed_addr(&peer, observed.clone()));
+		}
+		assert!(topology.confirmed_external_addrs().is_empty());
+
+		let peer = connect(&mut topology, "10.0.1.1");
+		assert!(!topology.add_observed_addr(&peer, observed.clone()));
+		let peer = connect(&mut topology, "10.0.2.1");
+		assert!(topology.add_observed_addr(&peer, observed.clone()));
+		assert_eq!(topology.confirmed_external_addrs(), vec![observed.clone()]);
+
+		// the address is only reported as confirmed once.
+		let peer = connect(&mut topology, "10.0.3.1");
+		assert!(!topology.add_observed_addr(&peer, observed));
+	}
+
+	#[test]
+	fn observations_of_peers_we_are_not_connected_to_are_ignored() {
+		let mut topology = topology();
+		let observed: Multiaddr = "/ip4/1.2.3.4/tcp/30333".parse().unwrap();
+
+		assert!(!topology.add_observed_addr(&PeerId::random(), observed.clone()));
+
+		let address: Multiaddr = "/ip4/10.0.0.1/tcp/30333".parse().unwrap();
+		let peer = connect(&mut topology, "10.0.0.1");
+		topology.set_disconnected(&peer, &ConnectedPoint::Dialer { address }, DisconnectReason::Graceful);
+		assert!(!topology.add_observed_addr(&peer, observed));
+		assert!(topology.observed_addresses.is_empty());
+	}
+
+	#[test]
+	fn known_external_addresses_are_not_observed() {
+		let mut topology = topology();
+		let external: Multiaddr = "/ip4/1.2.3.4/tcp/30333".parse().unwrap();
+		topology.add_external_addrs(iter::once(external.clone()));
+
+		let peer = connect(&mut topology, "10.0.0.1");
+		assert!(!topology.add_observed_addr(&peer, external.clone()));
+		assert!(topology.observed_addresses.is_empty());
+		assert_eq!(topology.advertised_addrs(), vec![external]);
+	}
+
+	fn round_trip(map: &mut FnvHashMap<PeerId, PeerInfo>) -> FnvHashMap<PeerId, PeerInfo> {
+		let mut data = Vec::new();
+		serialize(&mut data, map).unwrap();
+		match serde_json::from_slice(&data).unwrap() {
+			serde_json::Value::Object(map) => deserialize_tolerant(map.into_iter(), &PeerId::random()),
+			_ => panic!("the storage is serialized as a map"),
+		}
+	}
+
+	#[test]
+	fn peers_are_stored_with_their_last_seen_time_and_reputation() {
+		let mut topology = topology();
+		let address: Multiaddr = "/ip4/10.0.0.1/tcp/30333".parse().unwrap();
+		let peer = PeerId::random();
+		topology.add_discovered_addrs(&peer, iter::once((address.clone(), true)));
+		topology.set_reputation(&peer, -100);
+		let last_seen = SystemTime::now();
+		topology.store.get_mut(&peer).unwrap().last_seen = Some(last_seen);
+
+		let loaded = round_trip(&mut topology.store);
+
+		assert_eq!(loaded.len(), 1);
+		let info = &loaded[&peer];
+		assert_eq!(info.addrs.iter().map(|a| a.addr.clone()).collect::<Vec<_>>(), vec![address]);
+		assert_eq!(info.last_seen, Some(last_seen));
+		assert_eq!(info.reputation, -100);
+	}
+
+	#[test]
+	fn reputations_of_peers_without_addresses_are_kept() {
+		let mut topology = topology();
+		let bad = PeerId::random();
+		let neutral = PeerId::random();
+		topology.set_reputation(&bad, -100);
+		topology.set_reputation(&neutral, 0);
+
+		topology.cleanup();
+		let loaded = round_trip(&mut topology.store);
+
+		assert_eq!(loaded.len(), 1);
+		assert!(loaded[&bad].addrs.is_empty());
+		assert_eq!(loaded[&bad].reputation, -100);
+	}
+
+	#[test]
+	fn storage_written_by_older_versions_is_loaded() {
+		let peer = PeerId::random();
+		let expires = SystemTime::now() + Duration::from_secs(3600);
+		// no `last_seen` nor `reputation`.
+		let old = serde_json::json!({
+			"addrs": [{ "addr": "/ip4/10.0.0.1/tcp/30333", "expires": expires, "score": 5 }],
+		});
+		let mut map = serde_json::Map::new();
+		map.insert(peer.to_base58(), old);
+
+		let loaded = deserialize_tolerant(map.into_iter(), &PeerId::random());
+
+		let info = &loaded[&peer];
+		assert_eq!(info.addrs.len(), 1);
+		assert_eq!(info.addrs[0].score.score, 5);
+		assert_eq!(info.last_seen, None);
+		assert_eq!(info.reputation, 0);
+	}
+}
@@ -16,6 +16,7 @@
 
 use std::{fmt, iter, net::Ipv4Addr, str};
 use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use crate::ip_filter::IpNetwork;
 
 /// Protocol / handler id
 pub type ProtocolId = [u8; 3];
@@ -40,18 +41,45 @@ pub struct NetworkConfiguration {
 	pub listen_addresses: Vec<Multiaddr>,
 	/// Multiaddresses to advertise. Detected automatically if empty.
 	pub public_addresses: Vec<Multiaddr>,
+	/// Map the listening ports on the local router through UPnP or NAT-PMP, and advertise
+	/// the resulting external addresses.
+	pub enable_port_mapping: bool,
+	/// Discover the nodes of the local network through mDNS. Can be changed at runtime.
+	pub enable_mdns: bool,
 	/// List of initial node addresses
 	pub boot_nodes: Vec<String>,
 	/// Use provided node key instead of default
 	pub use_secret: Option<Secret>,
+	/// Type of the node key, whether provided or generated.
+	pub node_key_type: NodeKeyType,
 	/// Maximum allowed number of incoming connections
 	pub in_peers: u32,
 	/// Number of outgoing connections we're trying to maintain
 	pub out_peers: u32,
+	/// Maximum number of non-reserved connections with the same IP address. Loopback,
+	/// private and link-local addresses aren't limited.
+	pub max_connections_per_ip: u32,
+	/// Maximum number of non-reserved connections with the same /24 (IPv4) or /48 (IPv6)
+	/// subnet. Kept well below `in_peers`, so that a single hosting provider can't fill our
+	/// incoming slots. Loopback, private and link-local addresses aren't limited.
+	pub max_connections_per_subnet: u32,
+	/// Networks connections are allowed with. If not empty, connections with any other
+	/// address are refused. Can be changed at runtime.
+	pub allowed_ips: Vec<IpNetwork>,
+	/// Networks connections are refused with, even if they are allowed by `allowed_ips`. Can
+	/// be changed at runtime.
+	pub denied_ips: Vec<IpNetwork>,
 	/// List of reserved node addresses.
 	pub reserved_nodes: Vec<String>,
 	/// The non-reserved peer mode.
 	pub non_reserved_mode: NonReservedPeerMode,
+	/// Certificate to accept WebSocket connections over TLS with, on `/wss` listen addresses.
+	pub wss_certificate: Option<TlsCertificate>,
+	/// Path of the file containing the pre-shared key of a private network. Connections of
+	/// nodes that don't know the key are rejected.
+	pub pre_shared_key_path: Option<String>,
+	/// Stream multiplexers to negotiate, and their parameters.
+	pub multiplexer: MultiplexerConfig,
 	/// Client identifier. Sent over the wire for debugging purposes.
 	pub client_version: String,
 	/// Name of the node. Sent over the wire for debugging purposes.
@@ -72,12 +100,22 @@ impl NetworkConfiguration {
 			net_config_path: None,
 			listen_addresses: Vec::new(),
 			public_addresses: Vec::new(),
+			enable_port_mapping: false,
+			enable_mdns: false,
 			boot_nodes: Vec::new(),
 			use_secret: None,
+			node_key_type: NodeKeyType::Secp256k1,
 			in_peers: 25,
 			out_peers: 75,
+			max_connections_per_ip: 4,
+			max_connections_per_subnet: 8,
+			allowed_ips: Vec::new(),
+			denied_ips: Vec::new(),
 			reserved_nodes: Vec::new(),
 			non_reserved_mode: NonReservedPeerMode::Accept,
+			wss_certificate: None,
+			pre_shared_key_path: None,
+			multiplexer: MultiplexerConfig::default(),
 			client_version: "unknown".into(),
 			node_name: "unknown".into(),
 		}
@@ -95,6 +133,23 @@ impl NetworkConfiguration {
 	}
 }
 
+/// TLS certificate and private key, bundled in a PKCS #12 archive.
+#[derive(PartialEq, Eq, Clone)]
+pub struct TlsCertificate {
+	/// Path of the archive.
+	pub pkcs12_path: String,
+	/// Password the archive is protected with.
+	pub password: String,
+}
+
+impl fmt::Debug for TlsCertificate {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("TlsCertificate")
+			.field("pkcs12_path", &self.pkcs12_path)
+			.finish()
+	}
+}
+
 /// The severity of misbehaviour of a peer that is reported.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Severity<'a> {
@@ -118,6 +173,62 @@ impl<'a> fmt::Display for Severity<'a> {
 	}
 }
 
+/// Stream multiplexers to negotiate with the nodes we connect to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Multiplexer {
+	/// Yamux if the remote supports it, mplex otherwise. This is the default.
+	YamuxPreferred,
+	/// Only yamux.
+	Yamux,
+	/// Only mplex.
+	Mplex,
+}
+
+/// Configuration of the stream multiplexers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiplexerConfig {
+	/// Multiplexers to negotiate.
+	pub multiplexer: Multiplexer,
+	/// Number of bytes a yamux substream can receive before the remote waits for us to read
+	/// them. Bounds the throughput to this window per round-trip, so links with a large
+	/// bandwidth-delay product need a large window.
+	pub yamux_receive_window: u32,
+	/// Maximum number of bytes a yamux substream buffers. Can't be less than the receive window.
+	pub yamux_max_buffer_size: usize,
+	/// Maximum number of simultaneous mplex substreams.
+	pub mplex_max_substreams: usize,
+}
+
+impl Default for MultiplexerConfig {
+	fn default() -> Self {
+		MultiplexerConfig {
+			multiplexer: Multiplexer::YamuxPreferred,
+			yamux_receive_window: 1024 * 1024,
+			yamux_max_buffer_size: 16 * 1024 * 1024,
+			mplex_max_substreams: 128,
+		}
+	}
+}
+
+/// Types of node keys, from which the identity of the node derives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKeyType {
+	/// Secp256k1 key. This is the default.
+	Secp256k1,
+	/// Ed25519 key.
+	Ed25519,
+}
+
+impl NodeKeyType {
+	/// Name of the key type, as accepted on the command line.
+	pub fn name(&self) -> &'static str {
+		match self {
+			NodeKeyType::Secp256k1 => "secp256k1",
+			NodeKeyType::Ed25519 => "ed25519",
+		}
+	}
+}
+
 /// Non-reserved peer modes.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum NonReservedPeerMode {
@@ -14,47 +14,101 @@
 // You should have received a copy of the GNU General Public License
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
-use futures::prelude::*;
-use libp2p::{InboundUpgradeExt, OutboundUpgradeExt, PeerId, Transport, mplex, secio, yamux, tcp, dns, websocket};
+use crate::{Multiplexer, MultiplexerConfig, ip_filter::IpFilter};
+use futures::{future, prelude::*};
+use libp2p::{PeerId, Transport, mplex, secio, yamux, tcp, dns, websocket};
 use libp2p::core::{self, transport::boxed::Boxed, muxing::StreamMuxerBox};
-use std::{io, sync::Arc, time::Duration, usize};
+use parking_lot::RwLock;
+use std::{cmp, io, sync::Arc, time::Duration, usize};
 
 pub use self::bandwidth::BandwidthSinks;
+pub use self::metrics::{ConnectionMetrics, ConnectionMetricsSinks};
+pub use self::pnet::PreSharedKey;
+pub use self::wss::tls_acceptor;
 
 mod bandwidth;
+mod filter;
+mod metrics;
+mod pnet;
+mod wss;
 
 /// Builds the transport that serves as a common ground for all connections.
 ///
 /// Returns a `BandwidthSinks` object that allows querying the average bandwidth produced by all
-/// the connections spawned with this transport.
+/// the connections spawned with this transport, and a `ConnectionMetricsSinks` object that counts
+/// the dials, their failures, and the time taken to establish the connections.
+///
+/// `/wss` addresses can only be listened on if a TLS acceptor is passed. If a pre-shared key
+/// is passed, only the nodes knowing it can connect. Connections with the addresses refused by
+/// `ip_filter` are closed before any handshake.
 pub fn build_transport(
-	local_private_key: secio::SecioKeyPair
-) -> (Boxed<(PeerId, StreamMuxerBox), io::Error>, Arc<bandwidth::BandwidthSinks>) {
+	local_private_key: secio::SecioKeyPair,
+	tls_acceptor: Option<tokio_tls::TlsAcceptor>,
+	pre_shared_key: Option<PreSharedKey>,
+	multiplexer_config: &MultiplexerConfig,
+	ip_filter: Arc<RwLock<IpFilter>>,
+) -> (Boxed<(PeerId, StreamMuxerBox), io::Error>, Arc<bandwidth::BandwidthSinks>, Arc<metrics::ConnectionMetricsSinks>) {
 	let mut mplex_config = mplex::MplexConfig::new();
 	mplex_config.max_buffer_len_behaviour(mplex::MaxBufferBehaviour::Block);
 	mplex_config.max_buffer_len(usize::MAX);
+	mplex_config.max_substreams(multiplexer_config.mplex_max_substreams);
+
+	let mut yamux_config = ::yamux::Config::default();
+	yamux_config.set_receive_window(multiplexer_config.yamux_receive_window);
+	yamux_config.set_max_buffer_size(cmp::max(
+		multiplexer_config.yamux_max_buffer_size,
+		multiplexer_config.yamux_receive_window as usize
+	));
+	let yamux_config = yamux::Config::new(yamux_config);
+	let multiplexer = multiplexer_config.multiplexer;
+
+	let connection_metrics = Arc::new(metrics::ConnectionMetricsSinks::default());
 
 	let transport = tcp::TcpConfig::new();
+	// Only listened on, so not measured by `DialMetrics`.
+	let wss = wss::WssListenConfig::new(transport.clone(), tls_acceptor);
+	let wss = filter::IpFiltering::new(wss, ip_filter.clone());
+	let transport = filter::IpFiltering::new(transport, ip_filter);
+	let transport = metrics::DialMetrics::new(transport, connection_metrics.clone());
 	let transport = websocket::WsConfig::new(transport.clone()).or_transport(transport);
+	let transport = wss.or_transport(transport);
 	let transport = dns::DnsConfig::new(transport);
+	let transport = metrics::DnsDialMetrics::new(transport, connection_metrics.clone());
 	let (transport, sinks) = bandwidth::BandwidthLogging::new(transport, 5);
+	let transport = transport.and_then(move |socket, _| pnet::handshake(socket, pre_shared_key));
 
 	// TODO: rework the transport creation (https://github.com/libp2p/rust-libp2p/issues/783)
 	let transport = transport
 		.with_upgrade(secio::SecioConfig::new(local_private_key))
 		.and_then(move |out, endpoint| {
 			let peer_id = out.remote_key.into_peer_id();
-			let peer_id2 = peer_id.clone();
-			let upgrade = core::upgrade::SelectUpgrade::new(yamux::Config::default(), mplex_config)
-				.map_inbound(move |muxer| (peer_id, muxer))
-				.map_outbound(move |muxer| (peer_id2, muxer));
-
-			core::upgrade::apply(out.stream, upgrade, endpoint)
-				.map(|(id, muxer)| (id, core::muxing::StreamMuxerBox::new(muxer)))
+			// The muxers are of different types, so each choice is boxed on its own.
+			match multiplexer {
+				Multiplexer::YamuxPreferred => {
+					let upgrade = core::upgrade::SelectUpgrade::new(yamux_config, mplex_config);
+					future::Either::A(core::upgrade::apply(out.stream, upgrade, endpoint)
+						.map(move |muxer| (peer_id, core::muxing::StreamMuxerBox::new(muxer)))
+						.map_err(other_error))
+				},
+				Multiplexer::Yamux => {
+					future::Either::B(future::Either::A(core::upgrade::apply(out.stream, yamux_config, endpoint)
+						.map(move |muxer| (peer_id, core::muxing::StreamMuxerBox::new(muxer)))
+						.map_err(other_error)))
+				},
+				Multiplexer::Mplex => {
+					future::Either::B(future::Either::B(core::upgrade::apply(out.stream, mplex_config, endpoint)
+						.map(move |muxer| (peer_id, core::muxing::StreamMuxerBox::new(muxer)))
+						.map_err(other_error)))
+				},
+			}
 		})
 		.with_timeout(Duration::from_secs(20))
-		.map_err(|err| io::Error::new(io::ErrorKind::Other, err))
-		.boxed();
+		.map_err(|err| io::Error::new(io::ErrorKind::Other, err));
+	let transport = metrics::HandshakeMetrics::new(transport, connection_metrics.clone()).boxed();
+
+	(transport, sinks, connection_metrics)
+}
 
-	(transport, sinks)
+fn other_error<E: ToString>(err: E) -> io::Error {
+	io::Error::new(io::ErrorKind::Other, err.to_string())
 }
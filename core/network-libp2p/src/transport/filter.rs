@@ -0,0 +1,80 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Refuses the connections with the addresses denied by the `IpFilter`, before
+//! any handshake.
+
+use crate::ip_filter::IpFilter;
+use futures::prelude::*;
+use libp2p::{Multiaddr, core::Transport, core::transport::TransportError};
+use log::debug;
+use parking_lot::RwLock;
+use std::{io, sync::Arc};
+
+/// Wraps around a `Transport` and checks the addresses of the connections against an
+/// `IpFilter`, which can be modified at any time. Must be placed below the resolution of DNS
+/// names.
+#[derive(Clone)]
+pub struct IpFiltering<TInner> {
+	inner: TInner,
+	filter: Arc<RwLock<IpFilter>>,
+}
+
+impl<TInner> IpFiltering<TInner> {
+	/// Creates a new `IpFiltering` around the transport.
+	pub fn new(inner: TInner, filter: Arc<RwLock<IpFilter>>) -> Self {
+		IpFiltering { inner, filter }
+	}
+}
+
+impl<TInner> Transport for IpFiltering<TInner>
+where
+	TInner: Transport<Error = io::Error>,
+	TInner::Listener: Send + 'static,
+{
+	type Output = TInner::Output;
+	type Error = io::Error;
+	type Listener = Box<dyn Stream<Item = (Self::ListenerUpgrade, Multiaddr), Error = io::Error> + Send>;
+	type ListenerUpgrade = TInner::ListenerUpgrade;
+	type Dial = TInner::Dial;
+
+	fn listen_on(self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>> {
+		let filter = self.filter;
+		let (listener, new_addr) = self.inner.listen_on(addr)?;
+		// Dropping the upgrade closes the connection.
+		let listener = listener.filter(move |(_, client_addr)| {
+			let allowed = filter.read().is_addr_allowed(client_addr);
+			if !allowed {
+				debug!(target: "sub-libp2p", "Refusing incoming connection from {}", client_addr);
+			}
+			allowed
+		});
+		Ok((Box::new(listener), new_addr))
+	}
+
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		if !self.filter.read().is_addr_allowed(&addr) {
+			debug!(target: "sub-libp2p", "Refusing to dial {}", addr);
+			let err = io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is denied", addr));
+			return Err(TransportError::Other(err))
+		}
+		self.inner.dial(addr)
+	}
+
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.inner.nat_traversal(server, observed)
+	}
+}
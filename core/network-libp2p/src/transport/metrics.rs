@@ -0,0 +1,410 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Counters of the connections opened through the transport, to diagnose
+//! connectivity problems.
+//!
+//! `DialMetrics` wraps around the transport that opens the raw connections, so
+//! that the errors of the dials are known precisely. `DnsDialMetrics` wraps
+//! around the DNS transport, and counts the dials whose name couldn't be resolved,
+//! which never reach the transport below. `HandshakeMetrics` wraps around the
+//! complete transport, and measures how long establishing the connections takes,
+//! encryption and multiplexing included.
+
+use fnv::FnvHashMap;
+use futures::prelude::*;
+use libp2p::{Multiaddr, core::Transport, core::transport::TransportError, dns::DnsErr};
+use parking_lot::Mutex;
+use std::{cmp, io, sync::Arc, time::Duration, time::Instant};
+
+/// Counters of the connections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionMetrics {
+	/// Number of nodes we're connected to.
+	pub open_connections: usize,
+	/// Number of outgoing connections being opened.
+	pub pending_dials: usize,
+	/// Number of outgoing connections that couldn't be opened, by kind of error, the most
+	/// frequent first. Addresses whose name couldn't be resolved are counted as `NotFound`.
+	pub dial_failures: Vec<(io::ErrorKind, u64)>,
+	/// Number of connections, incoming or outgoing, that were established.
+	pub established: u64,
+	/// Number of connections, incoming or outgoing, that failed before being established,
+	/// during the handshake or before.
+	pub failed: u64,
+	/// Total time taken to establish the connections that were, from the start of the dial or
+	/// the acceptance of the connection to the negotiation of the multiplexer.
+	pub handshake_time_total: Duration,
+	/// Longest time taken to establish a connection.
+	pub handshake_time_max: Duration,
+}
+
+impl ConnectionMetrics {
+	/// Returns the average time taken to establish a connection.
+	pub fn handshake_time_average(&self) -> Duration {
+		if self.established == 0 {
+			return Duration::from_secs(0)
+		}
+		let nanos = self.handshake_time_total.as_secs() as u128 * 1_000_000_000 +
+			self.handshake_time_total.subsec_nanos() as u128;
+		let average = nanos / self.established as u128;
+		Duration::new((average / 1_000_000_000) as u64, (average % 1_000_000_000) as u32)
+	}
+}
+
+/// Counters shared by the transports of `DialMetrics` and `HandshakeMetrics`.
+#[derive(Default)]
+pub struct ConnectionMetricsSinks {
+	counters: Mutex<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+	pending_dials: usize,
+	dial_failures: FnvHashMap<io::ErrorKind, u64>,
+	established: u64,
+	failed: u64,
+	handshake_time_total: Duration,
+	handshake_time_max: Duration,
+}
+
+impl ConnectionMetricsSinks {
+	/// Returns the current value of the counters. `open_connections` isn't known to the
+	/// transport, and is left to zero.
+	pub fn snapshot(&self) -> ConnectionMetrics {
+		let counters = self.counters.lock();
+		let mut dial_failures = counters.dial_failures.iter()
+			.map(|(kind, count)| (*kind, *count))
+			.collect::<Vec<_>>();
+		dial_failures.sort_by(|a, b| b.1.cmp(&a.1));
+		ConnectionMetrics {
+			open_connections: 0,
+			pending_dials: counters.pending_dials,
+			dial_failures,
+			established: counters.established,
+			failed: counters.failed,
+			handshake_time_total: counters.handshake_time_total,
+			handshake_time_max: counters.handshake_time_max,
+		}
+	}
+}
+
+/// Wraps around a `Transport` and counts the dials in progress and the errors of the failed ones.
+#[derive(Clone)]
+pub struct DialMetrics<TInner> {
+	inner: TInner,
+	sinks: Arc<ConnectionMetricsSinks>,
+}
+
+impl<TInner> DialMetrics<TInner> {
+	/// Creates a new `DialMetrics` around the transport.
+	pub fn new(inner: TInner, sinks: Arc<ConnectionMetricsSinks>) -> Self {
+		DialMetrics { inner, sinks }
+	}
+}
+
+impl<TInner> Transport for DialMetrics<TInner>
+where
+	TInner: Transport<Error = io::Error>,
+	TInner::Dial: Send + 'static,
+	TInner::Output: Send + 'static,
+{
+	type Output = TInner::Output;
+	type Error = io::Error;
+	type Listener = TInner::Listener;
+	type ListenerUpgrade = TInner::ListenerUpgrade;
+	type Dial = Box<dyn Future<Item = Self::Output, Error = io::Error> + Send>;
+
+	fn listen_on(self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>> {
+		self.inner.listen_on(addr)
+	}
+
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let dial = self.inner.dial(addr)?;
+		let pending = PendingDial::new(self.sinks.clone());
+		let sinks = self.sinks;
+		Ok(Box::new(dial.then(move |result| {
+			drop(pending);
+			if let Err(ref err) = result {
+				*sinks.counters.lock().dial_failures.entry(err.kind()).or_insert(0) += 1;
+			}
+			result
+		})))
+	}
+
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.inner.nat_traversal(server, observed)
+	}
+}
+
+/// Wraps around the DNS `Transport` and counts the dials that failed to resolve their name.
+/// The dials to the resolved addresses are counted by the `DialMetrics` below it.
+#[derive(Clone)]
+pub struct DnsDialMetrics<TInner> {
+	inner: TInner,
+	sinks: Arc<ConnectionMetricsSinks>,
+}
+
+impl<TInner> DnsDialMetrics<TInner> {
+	/// Creates a new `DnsDialMetrics` around the transport.
+	pub fn new(inner: TInner, sinks: Arc<ConnectionMetricsSinks>) -> Self {
+		DnsDialMetrics { inner, sinks }
+	}
+}
+
+impl<TInner, TErr> Transport for DnsDialMetrics<TInner>
+where
+	TInner: Transport<Error = DnsErr<TErr>>,
+	TInner::Dial: Send + 'static,
+	TInner::Output: Send + 'static,
+	TErr: Send + 'static,
+{
+	type Output = TInner::Output;
+	type Error = TInner::Error;
+	type Listener = TInner::Listener;
+	type ListenerUpgrade = TInner::ListenerUpgrade;
+	type Dial = Box<dyn Future<Item = Self::Output, Error = Self::Error> + Send>;
+
+	fn listen_on(self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>> {
+		self.inner.listen_on(addr)
+	}
+
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let dial = self.inner.dial(addr)?;
+		let sinks = self.sinks;
+		Ok(Box::new(dial.map_err(move |err| {
+			match err {
+				DnsErr::Underlying(_) => {},
+				_ => *sinks.counters.lock().dial_failures.entry(io::ErrorKind::NotFound).or_insert(0) += 1,
+			}
+			err
+		})))
+	}
+
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.inner.nat_traversal(server, observed)
+	}
+}
+
+/// Counts a dial as pending until dropped, which happens when it finishes or is aborted.
+struct PendingDial(Arc<ConnectionMetricsSinks>);
+
+impl PendingDial {
+	fn new(sinks: Arc<ConnectionMetricsSinks>) -> Self {
+		sinks.counters.lock().pending_dials += 1;
+		PendingDial(sinks)
+	}
+}
+
+impl Drop for PendingDial {
+	fn drop(&mut self) {
+		self.0.counters.lock().pending_dials -= 1;
+	}
+}
+
+/// Wraps around a `Transport` and measures how long establishing the connections takes.
+#[derive(Clone)]
+pub struct HandshakeMetrics<TInner> {
+	inner: TInner,
+	sinks: Arc<ConnectionMetricsSinks>,
+}
+
+impl<TInner> HandshakeMetrics<TInner> {
+	/// Creates a new `HandshakeMetrics` around the transport.
+	pub fn new(inner: TInner, sinks: Arc<ConnectionMetricsSinks>) -> Self {
+		HandshakeMetrics { inner, sinks }
+	}
+}
+
+impl<TInner> Transport for HandshakeMetrics<TInner>
+where
+	TInner: Transport<Error = io::Error>,
+	TInner::Listener: Send + 'static,
+	TInner::ListenerUpgrade: Send + 'static,
+	TInner::Dial: Send + 'static,
+	TInner::Output: Send + 'static,
+{
+	type Output = TInner::Output;
+	type Error = io::Error;
+	type Listener = Box<dyn Stream<Item = (Self::ListenerUpgrade, Multiaddr), Error = io::Error> + Send>;
+	type ListenerUpgrade = Box<dyn Future<Item = Self::Output, Error = io::Error> + Send>;
+	type Dial = Box<dyn Future<Item = Self::Output, Error = io::Error> + Send>;
+
+	fn listen_on(self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>> {
+		let sinks = self.sinks;
+		let (listener, new_addr) = self.inner.listen_on(addr)?;
+		let listener = listener.map(move |(upgrade, client_addr)| {
+			(Box::new(measure(upgrade, sinks.clone())) as Self::ListenerUpgrade, client_addr)
+		});
+		Ok((Box::new(listener), new_addr))
+	}
+
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		let dial = self.inner.dial(addr)?;
+		Ok(Box::new(measure(dial, self.sinks)))
+	}
+
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		self.inner.nat_traversal(server, observed)
+	}
+}
+
+/// Counts the outcome of establishing a connection, and how long it took.
+fn measure<TFut>(future: TFut, sinks: Arc<ConnectionMetricsSinks>) -> impl Future<Item = TFut::Item, Error = io::Error>
+where TFut: Future<Error = io::Error>
+{
+	let started = Instant::now();
+	future.then(move |result| {
+		let mut counters = sinks.counters.lock();
+		match result {
+			Ok(_) => {
+				let elapsed = started.elapsed();
+				counters.established += 1;
+				counters.handshake_time_total += elapsed;
+				counters.handshake_time_max = cmp::max(counters.handshake_time_max, elapsed);
+			},
+			Err(_) => counters.failed += 1,
+		}
+		result
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::{future, stream};
+
+	/// Transport whose dials succeed or fail immediately with the given error.
+	#[derive(Clone)]
+	struct MockTransport(Option<io::ErrorKind>);
+
+	impl Transport for MockTransport {
+		type Output = ();
+		type Error = io::Error;
+		type Listener = stream::Empty<(Self::ListenerUpgrade, Multiaddr), io::Error>;
+		type ListenerUpgrade = future::FutureResult<(), io::Error>;
+		type Dial = future::FutureResult<(), io::Error>;
+
+		fn listen_on(self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>> {
+			Err(TransportError::MultiaddrNotSupported(addr))
+		}
+
+		fn dial(self, _addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+			Ok(future::result(self.0.map_or(Ok(()), |kind| Err(kind.into()))))
+		}
+
+		fn nat_traversal(&self, _server: &Multiaddr, _observed: &Multiaddr) -> Option<Multiaddr> {
+			None
+		}
+	}
+
+	/// DNS transport whose dials fail immediately with the given error.
+	#[derive(Clone)]
+	struct MockDns(fn() -> DnsErr<io::Error>);
+
+	impl Transport for MockDns {
+		type Output = ();
+		type Error = DnsErr<io::Error>;
+		type Listener = stream::Empty<(Self::ListenerUpgrade, Multiaddr), Self::Error>;
+		type ListenerUpgrade = future::FutureResult<(), Self::Error>;
+		type Dial = future::FutureResult<(), Self::Error>;
+
+		fn listen_on(self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>> {
+			Err(TransportError::MultiaddrNotSupported(addr))
+		}
+
+		fn dial(self, _addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+			Ok(future::err((self.0)()))
+		}
+
+		fn nat_traversal(&self, _server: &Multiaddr, _observed: &Multiaddr) -> Option<Multiaddr> {
+			None
+		}
+	}
+
+	fn addr() -> Multiaddr {
+		"/ip4/127.0.0.1/tcp/30333".parse().unwrap()
+	}
+
+	#[test]
+	fn dials_are_pending_until_finished_or_dropped() {
+		let sinks = Arc::new(ConnectionMetricsSinks::default());
+		let transport = DialMetrics::new(MockTransport(None), sinks.clone());
+
+		let finished = transport.clone().dial(addr()).unwrap();
+		let dropped = transport.dial(addr()).unwrap();
+		assert_eq!(sinks.snapshot().pending_dials, 2);
+
+		finished.wait().unwrap();
+		assert_eq!(sinks.snapshot().pending_dials, 1);
+		drop(dropped);
+		assert_eq!(sinks.snapshot().pending_dials, 0);
+		assert!(sinks.snapshot().dial_failures.is_empty());
+	}
+
+	#[test]
+	fn dial_failures_are_counted_by_kind() {
+		let sinks = Arc::new(ConnectionMetricsSinks::default());
+		let dial = |error| DialMetrics::new(MockTransport(Some(error)), sinks.clone()).dial(addr()).unwrap().wait();
+
+		assert!(dial(io::ErrorKind::TimedOut).is_err());
+		assert!(dial(io::ErrorKind::ConnectionRefused).is_err());
+		assert!(dial(io::ErrorKind::ConnectionRefused).is_err());
+
+		assert_eq!(sinks.snapshot().dial_failures, vec![
+			(io::ErrorKind::ConnectionRefused, 2),
+			(io::ErrorKind::TimedOut, 1),
+		]);
+		assert_eq!(sinks.snapshot().pending_dials, 0);
+	}
+
+	#[test]
+	fn dns_resolution_failures_are_counted() {
+		let sinks = Arc::new(ConnectionMetricsSinks::default());
+		let dial = |error: fn() -> DnsErr<io::Error>| DnsDialMetrics::new(MockDns(error), sinks.clone()).dial(addr()).unwrap().wait();
+
+		assert!(dial(|| DnsErr::ResolveFail("example.com".into())).is_err());
+		// failures of the transport below are counted by its own `DialMetrics`.
+		assert!(dial(|| DnsErr::Underlying(io::ErrorKind::ConnectionRefused.into())).is_err());
+
+		assert_eq!(sinks.snapshot().dial_failures, vec![(io::ErrorKind::NotFound, 1)]);
+	}
+
+	#[test]
+	fn handshakes_are_measured() {
+		let sinks = Arc::new(ConnectionMetricsSinks::default());
+		let dial = |error| HandshakeMetrics::new(MockTransport(error), sinks.clone()).dial(addr()).unwrap().wait();
+
+		assert!(dial(None).is_ok());
+		assert!(dial(None).is_ok());
+		assert!(dial(Some(io::ErrorKind::Other)).is_err());
+
+		let metrics = sinks.snapshot();
+		assert_eq!((metrics.established, metrics.failed), (2, 1));
+		assert!(metrics.handshake_time_max <= metrics.handshake_time_total);
+		assert!(metrics.handshake_time_average() <= metrics.handshake_time_max);
+	}
+
+	#[test]
+	fn handshake_time_average_is_computed() {
+		let mut metrics = ConnectionMetrics::default();
+		assert_eq!(metrics.handshake_time_average(), Duration::from_secs(0));
+
+		metrics.established = 4;
+		metrics.handshake_time_total = Duration::from_millis(2_002);
+		assert_eq!(metrics.handshake_time_average(), Duration::from_micros(500_500));
+	}
+}
@@ -0,0 +1,309 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Private networks: connections are encrypted with a pre-shared key before
+//! anything else is negotiated, so that nodes without the key can't connect.
+//!
+//! Each side sends a random nonce and encrypts what it writes with XSalsa20,
+//! keyed by the pre-shared key and its own nonce. This is the libp2p `pnet`
+//! scheme, and the key file uses the same format as other libp2p implementations.
+
+use futures::{future, prelude::*};
+use salsa20::XSalsa20;
+use salsa20::stream_cipher::{NewStreamCipher, SyncStreamCipher, generic_array::GenericArray};
+use std::{fmt, fs, io, io::Read, io::Write, path::Path};
+
+const KEY_FILE_HEADER: &str = "/key/swarm/psk/1.0.0/";
+const KEY_FILE_ENCODING: &str = "/base16/";
+const NONCE_LEN: usize = 24;
+
+/// Key shared by all the nodes of a private network.
+#[derive(Clone, Copy)]
+pub struct PreSharedKey([u8; 32]);
+
+impl PreSharedKey {
+	/// Loads a key from a file.
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+		let mut content = String::new();
+		fs::File::open(path)?.read_to_string(&mut content)?;
+		let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid pre-shared key file: {}", msg));
+
+		let mut lines = content.lines().map(str::trim);
+		if lines.next() != Some(KEY_FILE_HEADER) {
+			return Err(invalid("unknown format"))
+		}
+		if lines.next() != Some(KEY_FILE_ENCODING) {
+			return Err(invalid("only base16 keys are supported"))
+		}
+		let hex = lines.next().ok_or_else(|| invalid("missing key"))?.as_bytes();
+		if hex.len() != 64 {
+			return Err(invalid("the key must be 32 bytes long"))
+		}
+
+		let mut key = [0; 32];
+		for (byte, digits) in key.iter_mut().zip(hex.chunks(2)) {
+			let high = hex_digit(digits[0]).ok_or_else(|| invalid("the key isn't hexadecimal"))?;
+			let low = hex_digit(digits[1]).ok_or_else(|| invalid("the key isn't hexadecimal"))?;
+			*byte = high << 4 | low;
+		}
+		Ok(PreSharedKey(key))
+	}
+}
+
+impl fmt::Debug for PreSharedKey {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		write!(fmt, "PreSharedKey")
+	}
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+	match digit {
+		b'0' ..= b'9' => Some(digit - b'0'),
+		b'a' ..= b'f' => Some(digit - b'a' + 10),
+		b'A' ..= b'F' => Some(digit - b'A' + 10),
+		_ => None,
+	}
+}
+
+/// Exchanges nonces with the remote and returns the encrypted connection, or the
+/// connection as it is if no key is given.
+pub fn handshake<TSocket>(
+	socket: TSocket,
+	key: Option<PreSharedKey>,
+) -> impl Future<Item = PnetOutput<TSocket>, Error = io::Error>
+where TSocket: tokio_io::AsyncRead + tokio_io::AsyncWrite
+{
+	let key = match key {
+		Some(key) => key,
+		None => return future::Either::A(future::ok(PnetOutput { inner: socket, ciphers: None, pending: Vec::new() })),
+	};
+
+	let local_nonce: [u8; NONCE_LEN] = rand::random();
+	let exchange = tokio_io::io::write_all(socket, local_nonce)
+		.and_then(|(socket, _)| tokio_io::io::flush(socket))
+		.and_then(|socket| tokio_io::io::read_exact(socket, [0; NONCE_LEN]))
+		.map(move |(socket, remote_nonce)| PnetOutput {
+			inner: socket,
+			ciphers: Some(Ciphers {
+				read: cipher(&key, &remote_nonce),
+				write: cipher(&key, &local_nonce),
+			}),
+			pending: Vec::new(),
+		});
+	future::Either::B(exchange)
+}
+
+fn cipher(key: &PreSharedKey, nonce: &[u8; NONCE_LEN]) -> XSalsa20 {
+	XSalsa20::new(GenericArray::from_slice(&key.0), GenericArray::from_slice(nonce))
+}
+
+struct Ciphers {
+	read: XSalsa20,
+	write: XSalsa20,
+}
+
+/// Connection encrypted with the pre-shared key, if any.
+pub struct PnetOutput<TSocket> {
+	inner: TSocket,
+	ciphers: Option<Ciphers>,
+	/// Encrypted data accepted by `write` but not written to `inner` yet.
+	pending: Vec<u8>,
+}
+
+impl<TSocket: Write> PnetOutput<TSocket> {
+	/// Writes the pending data. Fails with `WouldBlock` if it can't be fully written.
+	fn write_pending(&mut self) -> io::Result<()> {
+		while !self.pending.is_empty() {
+			let written = self.inner.write(&self.pending)?;
+			if written == 0 {
+				return Err(io::ErrorKind::WriteZero.into())
+			}
+			self.pending.drain(..written);
+		}
+		Ok(())
+	}
+}
+
+impl<TSocket: Read> Read for PnetOutput<TSocket> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		if let Some(ref mut ciphers) = self.ciphers {
+			ciphers.read.apply_keystream(&mut buf[..read]);
+		}
+		Ok(read)
+	}
+}
+
+impl<TSocket: tokio_io::AsyncRead> tokio_io::AsyncRead for PnetOutput<TSocket> {}
+
+impl<TSocket: Write> Write for PnetOutput<TSocket> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if self.ciphers.is_none() {
+			return self.inner.write(buf)
+		}
+		// encrypted data can't be taken back, so it is buffered until it is fully written.
+		self.write_pending()?;
+		let mut encrypted = buf.to_vec();
+		if let Some(ref mut ciphers) = self.ciphers {
+			ciphers.write.apply_keystream(&mut encrypted);
+		}
+		self.pending = encrypted;
+		match self.write_pending() {
+			Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {},
+			Err(err) => return Err(err),
+			Ok(()) => {},
+		}
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.write_pending()?;
+		self.inner.flush()
+	}
+}
+
+impl<TSocket: tokio_io::AsyncWrite> tokio_io::AsyncWrite for PnetOutput<TSocket> {
+	fn shutdown(&mut self) -> Poll<(), io::Error> {
+		match self.write_pending() {
+			Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+			Err(err) => return Err(err),
+			Ok(()) => {},
+		}
+		self.inner.shutdown()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191A1B1C1D1E1F";
+
+	fn key_from(content: &str) -> Result<PreSharedKey, io::Error> {
+		let path = std::env::temp_dir().join(format!("substrate-psk-{}", rand::random::<u64>()));
+		fs::write(&path, content).unwrap();
+		let key = PreSharedKey::from_file(&path);
+		let _ = fs::remove_file(&path);
+		key
+	}
+
+	fn encrypted<TSocket>(inner: TSocket, key: &PreSharedKey, nonce: &[u8; NONCE_LEN]) -> PnetOutput<TSocket> {
+		let ciphers = Ciphers { read: cipher(key, nonce), write: cipher(key, nonce) };
+		PnetOutput { inner, ciphers: Some(ciphers), pending: Vec::new() }
+	}
+
+	/// Writer accepting at most `capacity` bytes, then failing with `WouldBlock`.
+	struct Congested {
+		written: Vec<u8>,
+		capacity: usize,
+	}
+
+	impl Write for Congested {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			let len = buf.len().min(self.capacity - self.written.len());
+			if len == 0 {
+				return Err(io::ErrorKind::WouldBlock.into())
+			}
+			self.written.extend_from_slice(&buf[..len]);
+			Ok(len)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn key_files_are_parsed() {
+		let key = key_from(&format!("{}\n{}\n{}\n", KEY_FILE_HEADER, KEY_FILE_ENCODING, KEY)).unwrap();
+		let expected = (0..32).collect::<Vec<u8>>();
+		assert_eq!(&key.0[..], &expected[..]);
+
+		// surrounding whitespace and a missing trailing newline are accepted.
+		let key = key_from(&format!("  {}\r\n{}  \n{}", KEY_FILE_HEADER, KEY_FILE_ENCODING, KEY)).unwrap();
+		assert_eq!(&key.0[..], &expected[..]);
+	}
+
+	#[test]
+	fn invalid_key_files_are_rejected() {
+		let invalid = |content: String| key_from(&content).unwrap_err().kind();
+		assert_eq!(invalid(format!("/key/swarm/psk/2.0.0/\n{}\n{}", KEY_FILE_ENCODING, KEY)), io::ErrorKind::InvalidData);
+		assert_eq!(invalid(format!("{}\n/base64/\n{}", KEY_FILE_HEADER, KEY)), io::ErrorKind::InvalidData);
+		assert_eq!(invalid(format!("{}\n{}\n", KEY_FILE_HEADER, KEY_FILE_ENCODING)), io::ErrorKind::InvalidData);
+		assert_eq!(invalid(format!("{}\n{}\n{}", KEY_FILE_HEADER, KEY_FILE_ENCODING, &KEY[2..])), io::ErrorKind::InvalidData);
+		assert_eq!(invalid(format!("{}\n{}\n{}g", KEY_FILE_HEADER, KEY_FILE_ENCODING, &KEY[1..])), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn missing_key_files_are_reported() {
+		let path = std::env::temp_dir().join(format!("substrate-psk-missing-{}", rand::random::<u64>()));
+		assert_eq!(PreSharedKey::from_file(&path).unwrap_err().kind(), io::ErrorKind::NotFound);
+	}
+
+	#[test]
+	fn data_is_decrypted_with_the_same_key_and_nonce() {
+		let key = PreSharedKey([7; 32]);
+		let nonce = [1; NONCE_LEN];
+		let data = b"private network data, split over several writes".to_vec();
+
+		let mut writer = encrypted(Vec::new(), &key, &nonce);
+		writer.write_all(&data[..10]).unwrap();
+		writer.write_all(&data[10..]).unwrap();
+		let ciphertext = writer.inner;
+		assert_eq!(ciphertext.len(), data.len());
+		assert_ne!(ciphertext, data);
+
+		let mut decrypted = Vec::new();
+		encrypted(Cursor::new(ciphertext.clone()), &key, &nonce).read_to_end(&mut decrypted).unwrap();
+		assert_eq!(decrypted, data);
+
+		// another key or nonce doesn't decrypt the data.
+		let mut garbage = Vec::new();
+		encrypted(Cursor::new(ciphertext.clone()), &PreSharedKey([8; 32]), &nonce).read_to_end(&mut garbage).unwrap();
+		assert_ne!(garbage, data);
+		garbage.clear();
+		encrypted(Cursor::new(ciphertext), &key, &[2; NONCE_LEN]).read_to_end(&mut garbage).unwrap();
+		assert_ne!(garbage, data);
+	}
+
+	#[test]
+	fn data_is_sent_in_clear_without_key() {
+		let mut output = PnetOutput { inner: Vec::new(), ciphers: None, pending: Vec::new() };
+		output.write_all(b"public").unwrap();
+		assert_eq!(output.inner, b"public".to_vec());
+	}
+
+	#[test]
+	fn encrypted_data_is_buffered_until_written() {
+		let key = PreSharedKey([7; 32]);
+		let nonce = [1; NONCE_LEN];
+		let data = b"more than the socket accepts".to_vec();
+
+		let mut writer = encrypted(Congested { written: Vec::new(), capacity: 8 }, &key, &nonce);
+		assert_eq!(writer.write(&data).unwrap(), data.len());
+		assert_eq!(writer.flush().unwrap_err().kind(), io::ErrorKind::WouldBlock);
+		// nothing more is accepted before the pending data is written.
+		assert_eq!(writer.write(b"next").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+
+		writer.inner.capacity = data.len();
+		writer.flush().unwrap();
+
+		let mut decrypted = Vec::new();
+		encrypted(Cursor::new(writer.inner.written), &key, &nonce).read_to_end(&mut decrypted).unwrap();
+		assert_eq!(decrypted, data);
+	}
+}
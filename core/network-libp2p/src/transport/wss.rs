@@ -0,0 +1,280 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Listening transport for WebSockets over TLS, so that browsers can connect to
+//! us from pages served over HTTPS.
+//!
+//! The WebSocket transport of libp2p only listens on plain `/ws` addresses.
+//! This one handles `/wss` listen addresses; dialing them is left to libp2p.
+
+use crate::traits::TlsCertificate;
+use futures::{future, prelude::*, try_ready};
+use libp2p::{Multiaddr, multiaddr::Protocol, tcp::TcpConfig};
+use libp2p::core::{Transport, transport::TransportError};
+use log::debug;
+use rw_stream_sink::RwStreamSink;
+use std::{fs, io, io::Read};
+use tokio_io::{AsyncRead, AsyncWrite};
+use websocket::{OwnedMessage, server::upgrade::r#async::IntoWs};
+
+/// Connection produced by the transport.
+pub trait AsyncStream: AsyncRead + AsyncWrite {}
+impl<T: AsyncRead + AsyncWrite> AsyncStream for T {}
+
+/// Loads the TLS certificate to accept connections with.
+pub fn tls_acceptor(certificate: &TlsCertificate) -> Result<tokio_tls::TlsAcceptor, io::Error> {
+	let mut pkcs12 = Vec::new();
+	fs::File::open(&certificate.pkcs12_path)?.read_to_end(&mut pkcs12)?;
+	let identity = native_tls::Identity::from_pkcs12(&pkcs12, &certificate.password)
+		.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+	let acceptor = native_tls::TlsAcceptor::new(identity)
+		.map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+	Ok(acceptor.into())
+}
+
+/// Listens on `/wss` addresses over TCP. Refuses every address if no TLS
+/// certificate is configured.
+#[derive(Clone)]
+pub struct WssListenConfig {
+	inner: TcpConfig,
+	acceptor: Option<tokio_tls::TlsAcceptor>,
+}
+
+impl WssListenConfig {
+	/// Creates a new transport accepting connections with the given certificate.
+	pub fn new(inner: TcpConfig, acceptor: Option<tokio_tls::TlsAcceptor>) -> Self {
+		WssListenConfig { inner, acceptor }
+	}
+}
+
+impl Transport for WssListenConfig {
+	type Output = Box<dyn AsyncStream + Send>;
+	type Error = io::Error;
+	type Listener = Box<dyn Stream<Item = (Self::ListenerUpgrade, Multiaddr), Error = io::Error> + Send>;
+	type ListenerUpgrade = Box<dyn Future<Item = Self::Output, Error = io::Error> + Send>;
+	type Dial = future::Empty<Self::Output, io::Error>;
+
+	fn listen_on(self, addr: Multiaddr) -> Result<(Self::Listener, Multiaddr), TransportError<Self::Error>> {
+		let mut inner_addr = addr.clone();
+		match inner_addr.pop() {
+			Some(Protocol::Wss) => {},
+			_ => return Err(TransportError::MultiaddrNotSupported(addr)),
+		}
+		let acceptor = match self.acceptor {
+			Some(acceptor) => acceptor,
+			None => {
+				debug!(target: "sub-libp2p", "Can't listen on {} without a TLS certificate", addr);
+				return Err(TransportError::MultiaddrNotSupported(addr))
+			},
+		};
+
+		let (listener, mut new_addr) = self.inner.listen_on(inner_addr)?;
+		new_addr.append(Protocol::Wss);
+
+		let listener = listener.map(move |(upgrade, mut client_addr)| {
+			client_addr.append(Protocol::Wss);
+			let acceptor = acceptor.clone();
+			let upgrade = upgrade
+				.and_then(move |stream| acceptor.accept(stream).map_err(other_error))
+				.and_then(|stream| stream.into_ws().map_err(|(_, _, _, err)| other_error(err)))
+				.and_then(|upgrade| upgrade.accept().map_err(other_error))
+				.map(|(client, _headers)| {
+					let messages = BinaryMessages::new(client.map_err(other_error).sink_map_err(other_error));
+					Box::new(RwStreamSink::new(messages)) as Box<dyn AsyncStream + Send>
+				});
+			(Box::new(upgrade) as Self::ListenerUpgrade, client_addr)
+		});
+
+		Ok((Box::new(listener), new_addr))
+	}
+
+	fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+		Err(TransportError::MultiaddrNotSupported(addr))
+	}
+
+	fn nat_traversal(&self, server: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+		let mut server = server.clone();
+		match server.pop() {
+			Some(Protocol::Wss) => {},
+			_ => return None,
+		}
+		let mut observed = observed.clone();
+		if let Some(Protocol::Wss) = observed.iter().last() {
+			observed.pop();
+		}
+		let mut result = self.inner.nat_traversal(&server, &observed)?;
+		result.append(Protocol::Wss);
+		Some(result)
+	}
+}
+
+/// Data of the messages of a WebSocket connection. Answers the pings of the remote, and ends
+/// once the remote closes the connection.
+struct BinaryMessages<S> {
+	inner: S,
+	/// Pong we have to send before anything else.
+	pending_pong: Option<OwnedMessage>,
+	/// True if a pong was sent but not flushed yet.
+	flushing_pong: bool,
+}
+
+impl<S> BinaryMessages<S>
+where
+	S: Stream<Item = OwnedMessage, Error = io::Error> + Sink<SinkItem = OwnedMessage, SinkError = io::Error>,
+{
+	fn new(inner: S) -> Self {
+		BinaryMessages { inner, pending_pong: None, flushing_pong: false }
+	}
+
+	/// Sends and flushes the pending pong, if any.
+	fn send_pong(&mut self) -> Poll<(), io::Error> {
+		if let Some(pong) = self.pending_pong.take() {
+			if let AsyncSink::NotReady(pong) = self.inner.start_send(pong)? {
+				self.pending_pong = Some(pong);
+				return Ok(Async::NotReady)
+			}
+			self.flushing_pong = true;
+		}
+		if self.flushing_pong {
+			try_ready!(self.inner.poll_complete());
+			self.flushing_pong = false;
+		}
+		Ok(Async::Ready(()))
+	}
+}
+
+impl<S> Stream for BinaryMessages<S>
+where
+	S: Stream<Item = OwnedMessage, Error = io::Error> + Sink<SinkItem = OwnedMessage, SinkError = io::Error>,
+{
+	type Item = Vec<u8>;
+	type Error = io::Error;
+
+	fn poll(&mut self) -> Poll<Option<Vec<u8>>, io::Error> {
+		loop {
+			// We don't read further until the last ping is answered, so that pongs don't pile up.
+			try_ready!(self.send_pong());
+			match try_ready!(self.inner.poll()) {
+				Some(OwnedMessage::Binary(data)) => return Ok(Async::Ready(Some(data))),
+				Some(OwnedMessage::Text(data)) => return Ok(Async::Ready(Some(data.into_bytes()))),
+				Some(OwnedMessage::Ping(data)) => self.pending_pong = Some(OwnedMessage::Pong(data)),
+				Some(OwnedMessage::Pong(_)) => {},
+				Some(OwnedMessage::Close(_)) | None => return Ok(Async::Ready(None)),
+			}
+		}
+	}
+}
+
+impl<S> Sink for BinaryMessages<S>
+where
+	S: Stream<Item = OwnedMessage, Error = io::Error> + Sink<SinkItem = OwnedMessage, SinkError = io::Error>,
+{
+	type SinkItem = Vec<u8>;
+	type SinkError = io::Error;
+
+	fn start_send(&mut self, data: Vec<u8>) -> StartSend<Vec<u8>, io::Error> {
+		if self.pending_pong.is_some() {
+			if let Async::NotReady = self.send_pong()? {
+				return Ok(AsyncSink::NotReady(data))
+			}
+		}
+		match self.inner.start_send(OwnedMessage::Binary(data))? {
+			AsyncSink::Ready => Ok(AsyncSink::Ready),
+			AsyncSink::NotReady(OwnedMessage::Binary(data)) => Ok(AsyncSink::NotReady(data)),
+			AsyncSink::NotReady(_) => unreachable!("the sink gives back the message it was given; qed"),
+		}
+	}
+
+	fn poll_complete(&mut self) -> Poll<(), io::Error> {
+		try_ready!(self.send_pong());
+		self.inner.poll_complete()
+	}
+
+	fn close(&mut self) -> Poll<(), io::Error> {
+		try_ready!(self.send_pong());
+		self.inner.close()
+	}
+}
+
+fn other_error<E: ToString>(err: E) -> io::Error {
+	io::Error::new(io::ErrorKind::Other, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::VecDeque;
+
+	/// WebSocket connection receiving the given messages, and keeping the messages sent.
+	#[derive(Default)]
+	struct MockConnection {
+		received: VecDeque<OwnedMessage>,
+		sent: Vec<OwnedMessage>,
+	}
+
+	impl Stream for MockConnection {
+		type Item = OwnedMessage;
+		type Error = io::Error;
+
+		fn poll(&mut self) -> Poll<Option<OwnedMessage>, io::Error> {
+			Ok(Async::Ready(self.received.pop_front()))
+		}
+	}
+
+	impl Sink for MockConnection {
+		type SinkItem = OwnedMessage;
+		type SinkError = io::Error;
+
+		fn start_send(&mut self, message: OwnedMessage) -> StartSend<OwnedMessage, io::Error> {
+			self.sent.push(message);
+			Ok(AsyncSink::Ready)
+		}
+
+		fn poll_complete(&mut self) -> Poll<(), io::Error> {
+			Ok(Async::Ready(()))
+		}
+	}
+
+	#[test]
+	fn pings_are_answered_and_data_is_read_until_closed() {
+		let mut messages = BinaryMessages::new(MockConnection {
+			received: vec![
+				OwnedMessage::Ping(vec![1]),
+				OwnedMessage::Binary(vec![2]),
+				OwnedMessage::Pong(vec![3]),
+				OwnedMessage::Text("a".into()),
+				OwnedMessage::Close(None),
+				OwnedMessage::Binary(vec![4]),
+			].into(),
+			sent: Vec::new(),
+		});
+
+		let data = (&mut messages).wait().collect::<Result<Vec<_>, _>>().unwrap();
+
+		assert_eq!(data, vec![vec![2], b"a".to_vec()]);
+		assert_eq!(messages.inner.sent, vec![OwnedMessage::Pong(vec![1])]);
+	}
+
+	#[test]
+	fn data_is_sent_as_binary_messages_after_pending_pongs() {
+		let mut messages = BinaryMessages::new(MockConnection::default());
+		messages.pending_pong = Some(OwnedMessage::Pong(vec![1]));
+
+		(&mut messages).send(vec![2]).wait().unwrap();
+
+		assert_eq!(messages.inner.sent, vec![OwnedMessage::Pong(vec![1]), OwnedMessage::Binary(vec![2])]);
+	}
+}
@@ -0,0 +1,213 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Queue of block requests waiting to be served by worker threads, so that
+//! reading blocks from the database doesn't stall the network thread.
+
+use std::collections::{HashMap, VecDeque};
+use parking_lot::{Condvar, Mutex};
+use network_libp2p::NodeIndex;
+use runtime_primitives::traits::Block as BlockT;
+use message;
+
+struct Inner<B: BlockT> {
+	// Queued requests, per peer.
+	requests: HashMap<NodeIndex, VecDeque<message::BlockRequest<B>>>,
+	// Peers with queued requests, in the order they are served.
+	peers: VecDeque<NodeIndex>,
+	// Total number of queued requests.
+	len: usize,
+	// Whether worker threads are serving the queue.
+	started: bool,
+	// Whether the worker threads must stop.
+	stopped: bool,
+}
+
+/// Bounded queue of block requests, served in a round-robin fashion across peers.
+pub(crate) struct BlockRequestQueue<B: BlockT> {
+	inner: Mutex<Inner<B>>,
+	available: Condvar,
+	max_len: usize,
+}
+
+impl<B: BlockT> BlockRequestQueue<B> {
+	/// Create a new queue holding at most `max_len` requests.
+	pub(crate) fn new(max_len: usize) -> Self {
+		BlockRequestQueue {
+			inner: Mutex::new(Inner {
+				requests: HashMap::new(),
+				peers: VecDeque::new(),
+				len: 0,
+				started: false,
+				stopped: false,
+			}),
+			available: Condvar::new(),
+			max_len,
+		}
+	}
+
+	/// Mark the queue as served by worker threads. Until then, requests are
+	/// expected to be served by the caller.
+	pub(crate) fn start(&self) {
+		self.inner.lock().started = true;
+	}
+
+	/// Whether worker threads serve the queue.
+	pub(crate) fn is_started(&self) -> bool {
+		let inner = self.inner.lock();
+		inner.started && !inner.stopped
+	}
+
+	/// Queue a request. Returns the request back if the queue is full.
+	pub(crate) fn push(&self, who: NodeIndex, request: message::BlockRequest<B>) -> Result<(), message::BlockRequest<B>> {
+		let mut inner = self.inner.lock();
+		if inner.len >= self.max_len {
+			return Err(request);
+		}
+		let became_active = {
+			let queued = inner.requests.entry(who).or_insert_with(VecDeque::new);
+			queued.push_back(request);
+			queued.len() == 1
+		};
+		if became_active {
+			inner.peers.push_back(who);
+		}
+		inner.len += 1;
+		self.available.notify_one();
+		Ok(())
+	}
+
+	/// Take the next request to serve, waiting for one to be queued. Returns
+	/// `None` once the queue is stopped.
+	pub(crate) fn pop(&self) -> Option<(NodeIndex, message::BlockRequest<B>)> {
+		let mut inner = self.inner.lock();
+		loop {
+			if inner.stopped {
+				return None;
+			}
+			if let Some(next) = Self::take_next(&mut inner) {
+				return Some(next);
+			}
+			self.available.wait(&mut inner);
+		}
+	}
+
+	fn take_next(inner: &mut Inner<B>) -> Option<(NodeIndex, message::BlockRequest<B>)> {
+		let who = inner.peers.pop_front()?;
+		let (request, more) = {
+			let queued = inner.requests.get_mut(&who).expect("peers only lists peers with queued requests; qed");
+			let request = queued.pop_front().expect("queues of listed peers are never empty; qed");
+			(request, !queued.is_empty())
+		};
+		if more {
+			inner.peers.push_back(who);
+		} else {
+			inner.requests.remove(&who);
+		}
+		inner.len -= 1;
+		Some((who, request))
+	}
+
+	/// Number of queued requests of a peer.
+	pub(crate) fn queued(&self, who: NodeIndex) -> usize {
+		self.inner.lock().requests.get(&who).map_or(0, |queued| queued.len())
+	}
+
+	/// Drop the queued requests of a peer.
+	pub(crate) fn remove_peer(&self, who: NodeIndex) {
+		let mut inner = self.inner.lock();
+		if let Some(queued) = inner.requests.remove(&who) {
+			inner.len -= queued.len();
+			inner.peers.retain(|peer| *peer != who);
+		}
+	}
+
+	/// Drop a queued request the peer no longer needs.
+	pub(crate) fn cancel(&self, who: NodeIndex, id: message::RequestId) {
+		let mut inner = self.inner.lock();
+		let (removed, empty) = match inner.requests.get_mut(&who) {
+			Some(queued) => {
+				let len = queued.len();
+				queued.retain(|request| request.id != id);
+				(len - queued.len(), queued.is_empty())
+			},
+			None => return,
+		};
+		inner.len -= removed;
+		if empty {
+			inner.requests.remove(&who);
+			inner.peers.retain(|peer| *peer != who);
+		}
+	}
+
+	/// Stop the worker threads.
+	pub(crate) fn stop(&self) {
+		self.inner.lock().stopped = true;
+		self.available.notify_all();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::BlockRequestQueue;
+	use message;
+	use runtime_primitives::testing::{Block as RawBlock, ExtrinsicWrapper};
+
+	type Block = RawBlock<ExtrinsicWrapper<u64>>;
+
+	fn request(id: message::RequestId) -> message::BlockRequest<Block> {
+		message::generic::BlockRequest {
+			id,
+			fields: message::BlockAttributes::HEADER,
+			from: message::FromBlock::Number(1),
+			to: None,
+			direction: message::Direction::Ascending,
+			max: None,
+		}
+	}
+
+	#[test]
+	fn peers_are_served_in_turn() {
+		let queue = BlockRequestQueue::new(10);
+		queue.push(0, request(1)).unwrap();
+		queue.push(0, request(2)).unwrap();
+		queue.push(0, request(3)).unwrap();
+		queue.push(1, request(4)).unwrap();
+		queue.push(2, request(5)).unwrap();
+		queue.cancel(0, 2);
+		assert_eq!(queue.queued(0), 2);
+		assert_eq!(queue.queued(3), 0);
+
+		let served = (0 .. 4).map(|_| queue.pop().map(|(who, r)| (who, r.id)).unwrap()).collect::<Vec<_>>();
+		assert_eq!(served, vec![(0, 1), (1, 4), (2, 5), (0, 3)]);
+	}
+
+	#[test]
+	fn queue_is_bounded() {
+		let queue = BlockRequestQueue::new(2);
+		queue.push(0, request(1)).unwrap();
+		queue.push(1, request(2)).unwrap();
+		assert_eq!(queue.push(2, request(3)).map_err(|r| r.id), Err(3));
+
+		queue.remove_peer(0);
+		queue.push(2, request(3)).unwrap();
+		assert_eq!(queue.pop().map(|(who, _)| who), Some(1));
+		assert_eq!(queue.pop().map(|(who, _)| who), Some(2));
+
+		queue.stop();
+		assert!(queue.pop().is_none());
+	}
+}
@@ -17,13 +17,23 @@
 use std::mem;
 use std::cmp;
 use std::ops::Range;
+use std::time::{Duration, Instant};
 use std::collections::{HashMap, BTreeMap};
 use std::collections::hash_map::Entry;
 use network_libp2p::NodeIndex;
-use runtime_primitives::traits::{Block as BlockT, NumberFor, As};
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, NumberFor, As};
+use codec::Encode;
+use consensus::import_queue::IncomingBlock;
 use message;
+use simulation;
 
 const MAX_PARALLEL_DOWNLOADS: u32 = 1;
+// Number of peers a range is downloaded from when cross-checking block data.
+const CROSS_CHECK_PARALLEL_DOWNLOADS: u32 = 2;
+// Time after which a range still being downloaded can be assigned to another peer.
+const RANGE_REASSIGN_AGE: Duration = Duration::from_secs(15);
+// Maximum number of downloaded blocks of unknown forks kept, from all peers.
+const MAX_FORK_BLOCKS: usize = 4096;
 
 /// Block data with origin.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -32,6 +42,28 @@ pub struct BlockData<B: BlockT> {
 	pub block: message::BlockData<B>,
 	/// The peer, we received this from
 	pub origin: Option<NodeIndex>,
+	/// Encoded size of the block
+	pub size: usize,
+}
+
+impl<B: BlockT> BlockData<B> {
+	/// Wrap a block received from `origin`.
+	pub fn new(block: message::BlockData<B>, origin: Option<NodeIndex>) -> Self {
+		let size = block.using_encoded(|data| data.len());
+		BlockData { block, origin, size }
+	}
+
+	/// Turn into a block to hand over to the import queue.
+	pub fn into_incoming(self) -> IncomingBlock<B> {
+		IncomingBlock {
+			hash: self.block.hash,
+			header: self.block.header,
+			body: self.block.body,
+			justification: self.block.justification,
+			origin: self.origin,
+			size: self.size,
+		}
+	}
 }
 
 #[derive(Debug)]
@@ -39,6 +71,8 @@ enum BlockRangeState<B: BlockT> {
 	Downloading {
 		len: NumberFor<B>,
 		downloading: u32,
+		// Time of the last assignment to a peer.
+		since: Instant,
 	},
 	Complete(Vec<BlockData<B>>),
 }
@@ -52,65 +86,357 @@ impl<B: BlockT> BlockRangeState<B> {
 	}
 }
 
+/// Occupancy of the download window of a `BlockCollection`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DownloadStats<N> {
+	/// Number of downloaded blocks waiting to be drained.
+	pub queued_blocks: usize,
+	/// Ranges being downloaded, in ascending order, with the peers downloading them.
+	pub downloading: Vec<(Range<N>, Vec<NodeIndex>)>,
+	/// First block that has to be downloaded before more blocks can be drained.
+	pub first_missing: N,
+}
+
+/// Different block data provided by peers for the same block numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence<N> {
+	/// First block number with different data.
+	pub number: N,
+	/// Peers that provided the previously downloaded data.
+	pub peers: Vec<NodeIndex>,
+}
+
+/// Outcome of comparing block data provided by several peers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CrossCheck<N> {
+	/// The blocks differ from the ones downloaded before. The divergent range is
+	/// downloaded again from peers that didn't take part in the divergence.
+	Diverged(Divergence<N>),
+	/// The blocks of an earlier divergence were downloaded from another peer,
+	/// which disagrees with these peers.
+	Resolved {
+		/// First block number of the divergence.
+		number: N,
+		/// Peers that provided blocks the other peer disagrees with.
+		wrong: Vec<NodeIndex>,
+	},
+}
+
+/// Blocks peers disagree on, until another peer provides them.
+struct Dispute<B: BlockT> {
+	/// End of the divergent range (excluded).
+	end: NumberFor<B>,
+	/// Peers involved, with the hash each of them provided for the first divergent block.
+	claims: Vec<(NodeIndex, B::Hash)>,
+}
+
+/// Encoded size of the given downloaded blocks.
+fn encoded_size<B: BlockT>(blocks: &[BlockData<B>]) -> usize {
+	blocks.iter().map(|b| b.size).sum()
+}
+
 /// A collection of blocks being downloaded.
-#[derive(Default)]
 pub struct BlockCollection<B: BlockT> {
 	/// Downloaded blocks.
 	blocks: BTreeMap<NumberFor<B>, BlockRangeState<B>>,
 	peer_requests: HashMap<NodeIndex, NumberFor<B>>,
+	/// Encoded size of the downloaded blocks waiting to be drained.
+	queued_bytes: usize,
+	/// No new ranges are scheduled while `queued_bytes` exceeds this budget.
+	max_queued_bytes: usize,
+	/// Missing ranges inside the downloaded region, by start (end excluded).
+	/// These are scheduled before any other range.
+	gaps: BTreeMap<NumberFor<B>, NumberFor<B>>,
+	/// Whether ranges are downloaded from several peers and their data compared.
+	cross_check: bool,
+	/// Divergent ranges by first divergent block number, waiting for another peer.
+	disputes: BTreeMap<NumberFor<B>, Dispute<B>>,
+	/// Maximum number of ranges being downloaded or waiting to be drained.
+	max_ranges: usize,
+}
+
+impl<B: BlockT> Default for BlockCollection<B> {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl<B: BlockT> BlockCollection<B> {
-	/// Create a new instance.
+	/// Create a new instance without limit on the size of downloaded blocks.
 	pub fn new() -> Self {
+		Self::with_max_queued_bytes(usize::max_value())
+	}
+
+	/// Create a new instance which stops scheduling downloads once the given
+	/// number of bytes of downloaded blocks is waiting to be drained.
+	pub fn with_max_queued_bytes(max_queued_bytes: usize) -> Self {
 		BlockCollection {
 			blocks: BTreeMap::new(),
 			peer_requests: HashMap::new(),
+			queued_bytes: 0,
+			max_queued_bytes,
+			gaps: BTreeMap::new(),
+			cross_check: false,
+			disputes: BTreeMap::new(),
+			max_ranges: usize::max_value(),
 		}
 	}
 
+	/// Limit the number of ranges being downloaded or waiting to be drained.
+	/// Ranges needed to drain the collection are still scheduled when the limit is reached.
+	pub fn set_max_ranges(&mut self, max_ranges: usize) {
+		self.max_ranges = max_ranges;
+	}
+
+	/// Download each range from several peers and compare the provided blocks
+	/// before draining them. Divergent data and the peers found to provide wrong
+	/// data are reported by `insert`.
+	pub fn set_cross_check(&mut self, cross_check: bool) {
+		self.cross_check = cross_check;
+	}
+
+	/// Encoded size of the downloaded blocks waiting to be drained.
+	pub fn queued_bytes(&self) -> usize {
+		self.queued_bytes
+	}
+
+	/// Returns the occupancy of the collection, for blocks to be drained from
+	/// the given block number.
+	pub fn stats(&self, from: NumberFor<B>) -> DownloadStats<NumberFor<B>> {
+		let mut queued_blocks = 0;
+		let mut downloading = Vec::new();
+		let mut first_missing = from;
+		for (start, range) in &self.blocks {
+			match *range {
+				BlockRangeState::Complete(ref blocks) => {
+					queued_blocks += blocks.len();
+					if *start <= first_missing {
+						first_missing = cmp::max(first_missing, *start + As::sa(blocks.len() as u64));
+					}
+				},
+				BlockRangeState::Downloading { len, .. } => {
+					let mut peers = self.peer_requests.iter()
+						.filter(|&(_, peer_start)| peer_start == start)
+						.map(|(peer, _)| *peer)
+						.collect::<Vec<_>>();
+					peers.sort();
+					downloading.push((*start .. *start + len, peers));
+				},
+			}
+		}
+
+		DownloadStats {
+			queued_blocks,
+			downloading,
+			first_missing,
+		}
+	}
+
+	/// Missing ranges inside the downloaded region that must be downloaded
+	/// again, in ascending order.
+	pub fn gaps(&self) -> Vec<Range<NumberFor<B>>> {
+		self.gaps.iter().map(|(start, end)| *start .. *end).collect()
+	}
+
 	/// Clear everything.
 	pub fn clear(&mut self) {
 		self.blocks.clear();
 		self.peer_requests.clear();
+		self.queued_bytes = 0;
+		self.gaps.clear();
+		self.disputes.clear();
 	}
 
-	/// Insert a set of blocks into collection.
-	pub fn insert(&mut self, start: NumberFor<B>, blocks: Vec<message::BlockData<B>>, who: NodeIndex) {
+	/// Insert a set of blocks into collection. When cross-checking, returns the
+	/// peers that provided different blocks for the same numbers, if any. The
+	/// divergent range is then downloaded again from other peers, and the peers
+	/// they disagree with are returned once they answered.
+	pub fn insert(&mut self, start: NumberFor<B>, blocks: Vec<message::BlockData<B>>, who: NodeIndex) -> Option<CrossCheck<NumberFor<B>>> {
 		if blocks.is_empty() {
-			return;
+			return None;
 		}
 
-		match self.blocks.get(&start) {
-			Some(&BlockRangeState::Downloading { .. }) => {
-				trace!(target: "sync", "Ignored block data still marked as being downloaded: {}", start);
-				debug_assert!(false);
-				return;
+		let divergence = match self.blocks.get(&start) {
+			Some(&BlockRangeState::Downloading { downloading, .. }) => {
+				// the range was reassigned, other peers may still be downloading it.
+				trace!(target: "sync", "Accepted block data still downloaded by {} other peers: {}", downloading, start);
+				None
 			},
-			Some(&BlockRangeState::Complete(ref existing)) if existing.len() >= blocks.len() => {
-				trace!(target: "sync", "Ignored block data already downloaded: {}", start);
-				return;
+			Some(&BlockRangeState::Complete(ref existing)) => {
+				let divergent = existing.iter()
+					.zip(blocks.iter())
+					.position(|(known, new)| known.block.hash != new.hash);
+				match divergent {
+					Some(index) if self.cross_check => {
+						let mut peers = existing[index..].iter()
+							.filter_map(|b| b.origin)
+							.collect::<Vec<_>>();
+						peers.sort();
+						peers.dedup();
+						let number = start + As::sa(index as u64);
+						let claims = existing[index].origin.into_iter()
+							.map(|origin| (origin, existing[index].block.hash.clone()))
+							.chain(Some((who, blocks[index].hash.clone())))
+							.collect();
+						let end = start + As::sa(cmp::max(existing.len(), blocks.len()) as u64);
+						Some((Divergence { number, peers }, Dispute { end, claims }))
+					},
+					_ if existing.len() >= blocks.len() => {
+						trace!(target: "sync", "Ignored block data already downloaded: {}", start);
+						return None;
+					},
+					_ => None,
+				}
 			},
-			_ => (),
+			None => None,
+		};
+
+		if let Some((divergence, dispute)) = divergence {
+			self.mark_missing(divergence.number .. dispute.end);
+			self.disputes.insert(divergence.number, dispute);
+			return Some(CrossCheck::Diverged(divergence));
+		}
+
+		let end = start + As::sa(blocks.len() as u64);
+		let resolved = self.resolve_dispute(start, &blocks, who);
+
+		let blocks: Vec<_> = blocks.into_iter().map(|b| BlockData::new(b, Some(who))).collect();
+		self.queued_bytes += encoded_size(&blocks);
+		if let Some(BlockRangeState::Complete(replaced)) = self.blocks.insert(start, BlockRangeState::Complete(blocks)) {
+			self.queued_bytes -= encoded_size(&replaced);
+		}
+
+		// a truncated response only fills the beginning of a gap.
+		if let Some(gap_end) = self.gaps.remove(&start) {
+			if end < gap_end {
+				self.gaps.insert(end, gap_end);
+			}
+		}
+
+		resolved
+	}
+
+	/// Settle the first dispute the given blocks cover, if `who` didn't take part in it.
+	fn resolve_dispute(&mut self, start: NumberFor<B>, blocks: &[message::BlockData<B>], who: NodeIndex) -> Option<CrossCheck<NumberFor<B>>> {
+		let end = start + As::sa(blocks.len() as u64);
+		let number = self.disputes.range(start .. end)
+			.filter(|(_, dispute)| dispute.claims.iter().all(|(peer, _)| *peer != who))
+			.map(|(number, _)| *number)
+			.next()?;
+		let dispute = self.disputes.remove(&number).expect("number is taken from the disputes; qed");
+		let hash = &blocks[(number - start).as_() as usize].hash;
+		// if the peer agrees with none of the others, there is no telling who is wrong.
+		if dispute.claims.iter().all(|(_, claim)| claim != hash) {
+			trace!(target: "sync", "Block #{} from {} differs from all the diverging blocks", number, who);
+			return None;
 		}
+		let mut wrong = dispute.claims.into_iter()
+			.filter(|(_, claim)| claim != hash)
+			.map(|(peer, _)| peer)
+			.collect::<Vec<_>>();
+		wrong.sort();
+		wrong.dedup();
+		Some(CrossCheck::Resolved { number, wrong })
+	}
 
-		self.blocks.insert(start, BlockRangeState::Complete(blocks.into_iter()
-			.map(|b| BlockData { origin: Some(who), block: b }).collect()));
+	/// Whether `who` took part in a divergence overlapping the range, and so mustn't download it again.
+	fn is_disputed_by(&self, who: NodeIndex, range: &Range<NumberFor<B>>) -> bool {
+		self.disputes.range(.. range.end)
+			.any(|(_, dispute)| dispute.end > range.start && dispute.claims.iter().any(|(peer, _)| *peer == who))
+	}
+
+	/// Discard the downloaded blocks in the given range and track it as a gap,
+	/// so that exactly these blocks are downloaded again.
+	pub fn mark_missing(&mut self, missing: Range<NumberFor<B>>) {
+		if missing.end <= missing.start {
+			return;
+		}
+
+		let overlapping = self.blocks.iter()
+			.filter_map(|(start, r)| match *r {
+				BlockRangeState::Complete(_) if *start < missing.end && *start + r.len() > missing.start => Some(*start),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+
+		for start in overlapping {
+			let mut blocks = match self.blocks.remove(&start) {
+				Some(BlockRangeState::Complete(blocks)) => blocks,
+				_ => continue,
+			};
+			let end = start + As::sa(blocks.len() as u64);
+			let tail = if end > missing.end {
+				blocks.split_off((missing.end - start).as_() as usize)
+			} else {
+				Vec::new()
+			};
+			let discarded = if start < missing.start {
+				blocks.split_off((missing.start - start).as_() as usize)
+			} else {
+				mem::replace(&mut blocks, Vec::new())
+			};
+			self.queued_bytes -= encoded_size(&discarded);
+
+			if !blocks.is_empty() {
+				self.blocks.insert(start, BlockRangeState::Complete(blocks));
+			}
+			if !tail.is_empty() {
+				self.blocks.insert(missing.end, BlockRangeState::Complete(tail));
+			}
+		}
+
+		// merge with overlapping or adjacent gaps.
+		let (mut gap_start, mut gap_end) = (missing.start, missing.end);
+		let merged = self.gaps.iter()
+			.filter(|&(start, end)| *start <= missing.end && *end >= missing.start)
+			.map(|(start, end)| (*start, *end))
+			.collect::<Vec<_>>();
+		for (start, end) in merged {
+			self.gaps.remove(&start);
+			gap_start = cmp::min(gap_start, start);
+			gap_end = cmp::max(gap_end, end);
+		}
+		trace!(target: "sync", "Blocks {} to {} are missing", gap_start, gap_end);
+		self.gaps.insert(gap_start, gap_end);
+	}
+
+	/// First gap that can be downloaded from the given peer at the given best block,
+	/// limited to `count` blocks.
+	fn next_gap(&self, who: NodeIndex, count: NumberFor<B>, peer_best: NumberFor<B>) -> Option<Range<NumberFor<B>>> {
+		self.gaps.iter()
+			.filter(|&(start, _)| *start <= peer_best && !self.blocks.contains_key(start))
+			.map(|(start, end)| *start .. cmp::min(*end, *start + count))
+			.filter(|range| !self.is_disputed_by(who, range))
+			.next()
 	}
 
 	/// Returns a set of block hashes that require a header download. The returned set is marked as being downloaded.
+	/// Ranges assigned to other peers for longer than `RANGE_REASSIGN_AGE` may be returned again.
 	pub fn needed_blocks(&mut self, who: NodeIndex, count: usize, peer_best: NumberFor<B>, common: NumberFor<B>) -> Option<Range<NumberFor<B>>> {
+		let now = simulation::now();
+		let max_parallel = if self.cross_check { CROSS_CHECK_PARALLEL_DOWNLOADS } else { MAX_PARALLEL_DOWNLOADS };
 		// First block number that we need to download
 		let first_different = common + As::sa(1);
 		let count = As::sa(count as u64);
-		let (mut range, downloading) = {
+		let gap = self.next_gap(who, count, peer_best);
+		let (mut range, downloading) = if let Some(gap) = gap.clone() {
+			(gap, 0)
+		} else {
 			let mut downloading_iter = self.blocks.iter().peekable();
 			let mut prev: Option<(&NumberFor<B>, &BlockRangeState<B>)> = None;
 			loop {
 				let next = downloading_iter.next();
 				break match &(prev, next) {
-					&(Some((start, &BlockRangeState::Downloading { ref len, downloading })), _) if downloading < MAX_PARALLEL_DOWNLOADS =>
+					&(Some((start, &BlockRangeState::Downloading { ref len, downloading, .. })), _)
+						if downloading < max_parallel && *start + *len <= peer_best + As::sa(1) =>
 						(*start .. *start + *len, downloading),
+					// the whole range is taken over from a peer that is too slow to provide it
+					&(Some((start, &BlockRangeState::Downloading { ref len, downloading, since })), _)
+						if now.duration_since(since) >= RANGE_REASSIGN_AGE && *start + *len <= peer_best + As::sa(1) =>
+					{
+						trace!(target: "sync", "Reassigning blocks {} to {} to {}", start, *start + *len, who);
+						(*start .. *start + *len, downloading)
+					},
 					&(Some((start, r)), Some((next_start, _))) if *start + r.len() < *next_start =>
 						(*start + r.len() .. cmp::min(*next_start, *start + r.len() + count), 0), // gap
 					&(Some((start, r)), None) =>
@@ -131,9 +457,55 @@ impl<B: BlockT> BlockCollection<B> {
 			trace!(target: "sync", "Out of range for peer {} ({} vs {})", who, range.start, peer_best);
 			return None;
 		}
+		if self.is_disputed_by(who, &range) {
+			trace!(target: "sync", "Not downloading {:?} again from {}, which provided divergent blocks", range, who);
+			return None;
+		}
+		// over budget, only schedule gaps and ranges that precede all the
+		// downloaded blocks, since these are needed to drain the collection
+		if gap.is_none() && self.queued_bytes >= self.max_queued_bytes {
+			let first_complete = self.blocks.iter()
+				.filter_map(|(start, r)| match *r {
+					BlockRangeState::Complete(_) => Some(*start),
+					BlockRangeState::Downloading { .. } => None,
+				})
+				.next();
+			if first_complete.map_or(false, |first| range.start > first) {
+				trace!(target: "sync", "Too much block data queued ({} bytes), not downloading {:?} from {}",
+					self.queued_bytes, range, who);
+				return None;
+			}
+		}
+		// with a full window, new ranges are only scheduled to fill holes
+		// before downloaded blocks.
+		if gap.is_none() && downloading == 0 && self.blocks.len() >= self.max_ranges {
+			let precedes_downloaded = self.blocks.range(range.start ..)
+				.any(|(_, r)| match *r {
+					BlockRangeState::Complete(_) => true,
+					BlockRangeState::Downloading { .. } => false,
+				});
+			if !precedes_downloaded {
+				trace!(target: "sync", "Download window of {} ranges is full, not downloading {:?} from {}",
+					self.max_ranges, range, who);
+				return None;
+			}
+		}
 		range.end = cmp::min(peer_best + As::sa(1), range.end);
+		if gap.is_some() {
+			// only the scheduled part of the gap is marked as being downloaded.
+			let gap_end = self.gaps.remove(&range.start)
+				.expect("scheduled gap is taken from the gaps; qed");
+			self.gaps.insert(range.start, range.end);
+			if range.end < gap_end {
+				self.gaps.insert(range.end, gap_end);
+			}
+		}
 		self.peer_requests.insert(who, range.start);
-		self.blocks.insert(range.start, BlockRangeState::Downloading { len: range.end - range.start, downloading: downloading + 1 });
+		self.blocks.insert(range.start, BlockRangeState::Downloading {
+			len: range.end - range.start,
+			downloading: downloading + 1,
+			since: now,
+		});
 		if range.end <= range.start {
 			panic!("Empty range {:?}, count={}, peer_best={}, common={}, blocks={:?}", range, count, peer_best, common, self.blocks);
 		}
@@ -146,9 +518,13 @@ impl<B: BlockT> BlockCollection<B> {
 		let mut ranges = Vec::new();
 		{
 			let mut prev = from;
+			// when cross-checking, ranges are only drained once all peers answered.
+			let cross_check = self.cross_check;
+			let peer_requests = &self.peer_requests;
 			for (start, range_data) in &mut self.blocks {
 				match range_data {
-					&mut BlockRangeState::Complete(ref mut blocks) if *start <= prev => {
+					&mut BlockRangeState::Complete(ref mut blocks) if *start <= prev &&
+						!(cross_check && peer_requests.values().any(|s| s == start)) => {
 							prev = *start + As::sa(blocks.len() as u64);
 							let mut blocks = mem::replace(blocks, Vec::new());
 							drained.append(&mut blocks);
@@ -161,6 +537,7 @@ impl<B: BlockT> BlockCollection<B> {
 		for r in ranges {
 			self.blocks.remove(&r);
 		}
+		self.queued_bytes -= encoded_size(&drained);
 		trace!(target: "sync", "Drained {} blocks", drained.len());
 		drained
 	}
@@ -177,10 +554,8 @@ impl<B: BlockT> BlockCollection<B> {
 					Some(&mut BlockRangeState::Downloading { .. }) => {
 						true
 					},
-					_ => {
-						debug_assert!(false);
-						false
-					}
+					// the range was reassigned and provided by another peer.
+					_ => false,
 				};
 				if remove {
 					self.blocks.remove(&start);
@@ -191,11 +566,131 @@ impl<B: BlockT> BlockCollection<B> {
 	}
 }
 
+/// Blocks of unknown forks being downloaded, keyed by hash and linked to their parent.
+///
+/// Unlike `BlockCollection`, blocks at the same height on competing forks are kept
+/// side by side, and a fork partially downloaded from one peer can be continued
+/// from another.
+pub struct ForkBlocks<B: BlockT> {
+	/// Downloaded blocks by peer and hash, with their parent hash and number.
+	blocks: HashMap<(NodeIndex, B::Hash), (B::Hash, NumberFor<B>, BlockData<B>)>,
+	max_blocks: usize,
+}
+
+/// Reason downloaded fork blocks were not kept.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ForkInsertError<H> {
+	/// The block with the given hash doesn't match its header.
+	HashMismatch(H),
+	/// Keeping the blocks would exceed the limit of blocks kept for all peers.
+	Full,
+}
+
+impl<B: BlockT> Default for ForkBlocks<B> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<B: BlockT> ForkBlocks<B> {
+	/// Create a new empty instance.
+	pub fn new() -> Self {
+		Self::with_max_blocks(MAX_FORK_BLOCKS)
+	}
+
+	/// Create a new empty instance keeping at most `max_blocks` blocks.
+	pub fn with_max_blocks(max_blocks: usize) -> Self {
+		ForkBlocks {
+			blocks: HashMap::new(),
+			max_blocks,
+		}
+	}
+
+	/// Clear everything.
+	pub fn clear(&mut self) {
+		self.blocks.clear();
+	}
+
+	/// Number of downloaded blocks.
+	pub fn len(&self) -> usize {
+		self.blocks.len()
+	}
+
+	/// Whether no blocks are stored.
+	pub fn is_empty(&self) -> bool {
+		self.blocks.is_empty()
+	}
+
+	/// Add blocks downloaded from `who`. Blocks without a header can't be linked to
+	/// their parent and are ignored. Nothing is kept if a block doesn't match its
+	/// hash or if the limit of blocks would be exceeded.
+	pub fn insert(&mut self, blocks: Vec<message::BlockData<B>>, who: NodeIndex) -> Result<(), ForkInsertError<B::Hash>> {
+		if let Some(block) = blocks.iter().find(|block| block.header.as_ref().map_or(false, |h| h.hash() != block.hash)) {
+			return Err(ForkInsertError::HashMismatch(block.hash));
+		}
+		let new_blocks = blocks.iter()
+			.filter(|block| block.header.is_some() && !self.blocks.contains_key(&(who, block.hash)))
+			.count();
+		if self.blocks.len() + new_blocks > self.max_blocks {
+			return Err(ForkInsertError::Full);
+		}
+
+		for block in blocks {
+			let (parent_hash, number) = match block.header {
+				Some(ref header) => (*header.parent_hash(), *header.number()),
+				None => continue,
+			};
+			self.blocks.entry((who, block.hash))
+				.or_insert_with(|| (parent_hash, number, BlockData::new(block, Some(who))));
+		}
+		Ok(())
+	}
+
+	/// Number and parent hash of the lowest block downloaded from `who` on the chain
+	/// ending at `head`. `None` if `head` itself has not been downloaded from `who`.
+	pub fn lowest_ancestor(&self, who: NodeIndex, head: &B::Hash) -> Option<(NumberFor<B>, B::Hash)> {
+		let mut lowest = None;
+		let mut hash = *head;
+		while let Some(&(parent_hash, number, _)) = self.blocks.get(&(who, hash)) {
+			lowest = Some((number, parent_hash));
+			hash = parent_hash;
+		}
+		lowest
+	}
+
+	/// Remove the chain of blocks downloaded from `who` ending at `head` and return it
+	/// in ascending order.
+	pub fn drain(&mut self, who: NodeIndex, head: &B::Hash) -> Vec<BlockData<B>> {
+		let mut drained = Vec::new();
+		let mut hash = *head;
+		while let Some((parent_hash, _, block)) = self.blocks.remove(&(who, hash)) {
+			drained.push(block);
+			hash = parent_hash;
+		}
+		drained.reverse();
+		drained
+	}
+
+	/// Discard the blocks downloaded from `who`.
+	pub fn remove_peer(&mut self, who: NodeIndex) {
+		self.blocks.retain(|&(peer, _), _| peer != who);
+	}
+
+	/// Discard blocks at or below the given number, which can't be imported anymore.
+	pub fn prune(&mut self, number: NumberFor<B>) {
+		self.blocks.retain(|_, &mut (_, n, _)| n > number);
+	}
+}
+
 #[cfg(test)]
 mod test {
-	use super::{BlockCollection, BlockData, BlockRangeState};
+	use super::{BlockCollection, BlockData, BlockRangeState, CrossCheck, Divergence, DownloadStats, ForkBlocks, ForkInsertError};
+	use codec::Encode;
 	use message;
-	use runtime_primitives::testing::{Block as RawBlock, ExtrinsicWrapper};
+	use simulation::Simulation;
+	use std::time::{Duration, Instant};
+	use runtime_primitives::testing::{Block as RawBlock, ExtrinsicWrapper, Header, Digest};
+	use runtime_primitives::traits::Header as HeaderT;
 	use primitives::H256;
 
 	type Block = RawBlock<ExtrinsicWrapper<u64>>;
@@ -247,14 +742,14 @@ mod test {
 		bc.insert(1, blocks[1..11].to_vec(), peer0);
 
 		assert_eq!(bc.needed_blocks(peer0, 40, 150, 0), Some(11 .. 41));
-		assert_eq!(bc.drain(1), blocks[1..11].iter().map(|b| BlockData { block: b.clone(), origin: Some(0) }).collect::<Vec<_>>());
+		assert_eq!(bc.drain(1), blocks[1..11].iter().map(|b| BlockData::new(b.clone(), Some(0))).collect::<Vec<_>>());
 
 		bc.clear_peer_download(peer0);
 		bc.insert(11, blocks[11..41].to_vec(), peer0);
 
 		let drained = bc.drain(12);
-		assert_eq!(drained[..30], blocks[11..41].iter().map(|b| BlockData { block: b.clone(), origin: Some(0) }).collect::<Vec<_>>()[..]);
-		assert_eq!(drained[30..], blocks[41..81].iter().map(|b| BlockData { block: b.clone(), origin: Some(1) }).collect::<Vec<_>>()[..]);
+		assert_eq!(drained[..30], blocks[11..41].iter().map(|b| BlockData::new(b.clone(), Some(0))).collect::<Vec<_>>()[..]);
+		assert_eq!(drained[30..], blocks[41..81].iter().map(|b| BlockData::new(b.clone(), Some(1))).collect::<Vec<_>>()[..]);
 
 		bc.clear_peer_download(peer2);
 		assert_eq!(bc.needed_blocks(peer2, 40, 150, 80), Some(81 .. 121));
@@ -265,8 +760,22 @@ mod test {
 
 		assert_eq!(bc.drain(80), vec![]);
 		let drained = bc.drain(81);
-		assert_eq!(drained[..40], blocks[81..121].iter().map(|b| BlockData { block: b.clone(), origin: Some(2) }).collect::<Vec<_>>()[..]);
-		assert_eq!(drained[40..], blocks[121..150].iter().map(|b| BlockData { block: b.clone(), origin: Some(1) }).collect::<Vec<_>>()[..]);
+		assert_eq!(drained[..40], blocks[81..121].iter().map(|b| BlockData::new(b.clone(), Some(2))).collect::<Vec<_>>()[..]);
+		assert_eq!(drained[40..], blocks[121..150].iter().map(|b| BlockData::new(b.clone(), Some(1))).collect::<Vec<_>>()[..]);
+	}
+
+	#[test]
+	fn assemble_descending_ranges() {
+		let mut bc: BlockCollection<Block> = BlockCollection::new();
+		let blocks = generate_blocks(64);
+
+		// ranges arrive from the top of the fork downwards
+		bc.insert(33, blocks[33..64].to_vec(), 0);
+		bc.insert(1, blocks[1..33].to_vec(), 0);
+
+		let drained = bc.drain(1);
+		assert_eq!(drained, blocks[1..64].iter().map(|b| BlockData::new(b.clone(), Some(0))).collect::<Vec<_>>());
+		assert!(is_empty(&bc));
 	}
 
 	#[test]
@@ -275,11 +784,265 @@ mod test {
 		bc.blocks.insert(100, BlockRangeState::Downloading {
 			len: 128,
 			downloading: 1,
+			since: Instant::now(),
 		});
-		let blocks = generate_blocks(10).into_iter().map(|b| BlockData { block: b, origin: None }).collect();
+		let blocks = generate_blocks(10).into_iter().map(|b| BlockData::new(b, None)).collect();
 		bc.blocks.insert(114305, BlockRangeState::Complete(blocks));
 
 		assert_eq!(bc.needed_blocks(0, 128, 10000, 000), Some(1 .. 100));
 		assert_eq!(bc.needed_blocks(0, 128, 10000, 600), Some(100 + 128 .. 100 + 128 + 128));
 	}
+
+	#[test]
+	fn byte_budget_limits_scheduled_ranges() {
+		let mut bc: BlockCollection<Block> = BlockCollection::with_max_queued_bytes(1);
+		let blocks = generate_blocks(150);
+		let peer0 = 0;
+		let peer1 = 1;
+
+		assert_eq!(bc.needed_blocks(peer0, 40, 150, 0), Some(1 .. 41));
+		assert_eq!(bc.needed_blocks(peer1, 40, 150, 0), Some(41 .. 81));
+		bc.clear_peer_download(peer1);
+		bc.insert(41, blocks[41..81].to_vec(), peer1);
+		assert_eq!(bc.queued_bytes(), blocks[41..81].iter().map(|b| b.encode().len()).sum::<usize>());
+
+		// over budget, only the range needed to drain the collection is scheduled.
+		assert_eq!(bc.needed_blocks(peer1, 40, 150, 0), None);
+		bc.clear_peer_download(peer0);
+		assert_eq!(bc.needed_blocks(peer0, 40, 150, 0), Some(1 .. 41));
+		bc.clear_peer_download(peer0);
+		bc.insert(1, blocks[1..41].to_vec(), peer0);
+
+		assert_eq!(bc.drain(1).len(), 80);
+		assert_eq!(bc.queued_bytes(), 0);
+		assert_eq!(bc.needed_blocks(peer1, 40, 150, 80), Some(81 .. 121));
+	}
+
+	#[test]
+	fn missing_blocks_are_downloaded_again() {
+		let mut bc: BlockCollection<Block> = BlockCollection::new();
+		let blocks = generate_blocks(100);
+		let (peer0, peer1, peer2) = (0, 1, 2);
+
+		assert_eq!(bc.needed_blocks(peer0, 40, 100, 0), Some(1 .. 41));
+		bc.clear_peer_download(peer0);
+		bc.insert(1, blocks[1..41].to_vec(), peer0);
+
+		bc.mark_missing(10 .. 15);
+		assert_eq!(bc.gaps(), vec![10 .. 15]);
+		assert_eq!(
+			bc.queued_bytes(),
+			blocks[1..10].iter().chain(blocks[15..41].iter()).map(|b| b.encode().len()).sum::<usize>(),
+		);
+
+		// gaps are scheduled first, only the requested part is marked as downloading.
+		assert_eq!(bc.needed_blocks(peer1, 3, 100, 0), Some(10 .. 13));
+		assert_eq!(bc.needed_blocks(peer2, 40, 100, 0), Some(13 .. 15));
+		assert_eq!(bc.drain(1).len(), 9);
+
+		// a truncated response leaves the rest of the gap missing.
+		bc.clear_peer_download(peer1);
+		bc.insert(10, blocks[10..12].to_vec(), peer1);
+		assert_eq!(bc.gaps(), vec![12 .. 13, 13 .. 15]);
+		assert_eq!(bc.needed_blocks(peer1, 40, 100, 0), Some(12 .. 13));
+		bc.clear_peer_download(peer1);
+		bc.insert(12, blocks[12..13].to_vec(), peer1);
+		bc.clear_peer_download(peer2);
+		bc.insert(13, blocks[13..15].to_vec(), peer2);
+
+		assert!(bc.gaps().is_empty());
+		let drained = bc.drain(10);
+		assert_eq!(drained.iter().map(|b| b.block.clone()).collect::<Vec<_>>(), blocks[10..41].to_vec());
+	}
+
+	#[test]
+	fn stats_describe_download_window() {
+		let mut bc: BlockCollection<Block> = BlockCollection::new();
+		let blocks = generate_blocks(100);
+		let (peer0, peer1, peer2) = (0, 1, 2);
+
+		assert_eq!(bc.needed_blocks(peer0, 10, 100, 0), Some(1 .. 11));
+		assert_eq!(bc.needed_blocks(peer1, 10, 100, 0), Some(11 .. 21));
+		assert_eq!(bc.needed_blocks(peer2, 10, 100, 0), Some(21 .. 31));
+		bc.clear_peer_download(peer1);
+		bc.insert(11, blocks[11..21].to_vec(), peer1);
+
+		assert_eq!(bc.stats(1), DownloadStats {
+			queued_blocks: 10,
+			downloading: vec![(1 .. 11, vec![peer0]), (21 .. 31, vec![peer2])],
+			first_missing: 1,
+		});
+
+		bc.clear_peer_download(peer0);
+		bc.insert(1, blocks[1..11].to_vec(), peer0);
+		assert_eq!(bc.stats(1).first_missing, 21);
+		assert_eq!(bc.stats(1).queued_blocks, 20);
+	}
+
+	#[test]
+	fn ranges_of_slow_peers_are_reassigned() {
+		let sim = Simulation::enter(0);
+		let mut bc: BlockCollection<Block> = BlockCollection::new();
+		let blocks = generate_blocks(100);
+		let (peer0, peer1) = (0, 1);
+
+		assert_eq!(bc.needed_blocks(peer0, 40, 100, 0), Some(1 .. 41));
+		assert_eq!(bc.needed_blocks(peer1, 40, 100, 0), Some(41 .. 81));
+
+		// the range of the silent peer is taken over once it is too old.
+		bc.clear_peer_download(peer1);
+		sim.advance(Duration::from_secs(10));
+		assert_eq!(bc.needed_blocks(peer1, 40, 100, 0), Some(41 .. 81));
+		bc.clear_peer_download(peer1);
+		sim.advance(Duration::from_secs(10));
+		assert_eq!(bc.needed_blocks(peer1, 40, 100, 0), Some(1 .. 41));
+
+		// whichever peer answers first provides the blocks.
+		bc.clear_peer_download(peer1);
+		bc.insert(1, blocks[1..41].to_vec(), peer1);
+		bc.clear_peer_download(peer0);
+		assert_eq!(bc.drain(1).len(), 40);
+	}
+
+	#[test]
+	fn cross_checked_ranges_are_compared() {
+		let mut bc: BlockCollection<Block> = BlockCollection::new();
+		bc.set_cross_check(true);
+		let blocks = generate_blocks(20);
+		let divergent = generate_blocks(20);
+		let (peer0, peer1, peer2) = (0, 1, 2);
+
+		// each range is downloaded from two peers and drained once both answered.
+		assert_eq!(bc.needed_blocks(peer0, 10, 20, 0), Some(1 .. 11));
+		assert_eq!(bc.needed_blocks(peer1, 10, 20, 0), Some(1 .. 11));
+		bc.clear_peer_download(peer0);
+		assert_eq!(bc.insert(1, blocks[1..11].to_vec(), peer0), None);
+		assert!(bc.drain(1).is_empty());
+		bc.clear_peer_download(peer1);
+		assert_eq!(bc.insert(1, blocks[1..11].to_vec(), peer1), None);
+		assert_eq!(bc.drain(1).len(), 10);
+
+		// divergent data is reported and downloaded again.
+		assert_eq!(bc.needed_blocks(peer0, 10, 20, 10), Some(11 .. 21));
+		assert_eq!(bc.needed_blocks(peer2, 10, 20, 10), Some(11 .. 21));
+		bc.clear_peer_download(peer0);
+		bc.insert(11, blocks[11..21].to_vec(), peer0);
+		let mut response = blocks[11..21].to_vec();
+		response[5] = divergent[16].clone();
+		bc.clear_peer_download(peer2);
+		assert_eq!(bc.insert(11, response, peer2), Some(CrossCheck::Diverged(Divergence { number: 16, peers: vec![peer0] })));
+		assert_eq!(bc.gaps(), vec![16 .. 21]);
+		assert_eq!(bc.drain(11).len(), 5);
+
+		// the diverging peers don't download the blocks again, another peer settles it.
+		assert_eq!(bc.needed_blocks(peer0, 10, 20, 15), None);
+		assert_eq!(bc.needed_blocks(peer2, 10, 20, 15), None);
+		assert_eq!(bc.needed_blocks(peer1, 10, 20, 15), Some(16 .. 21));
+		bc.clear_peer_download(peer1);
+		assert_eq!(bc.insert(16, blocks[16..21].to_vec(), peer1), Some(CrossCheck::Resolved { number: 16, wrong: vec![peer2] }));
+		assert_eq!(bc.drain(16).len(), 5);
+		assert_eq!(bc.needed_blocks(peer2, 10, 30, 20), Some(21 .. 31));
+	}
+
+	#[test]
+	fn download_window_limits_ranges() {
+		let mut bc = BlockCollection::new();
+		bc.set_max_ranges(2);
+		let blocks = generate_blocks(150);
+		let (peer0, peer1, peer2) = (0, 1, 2);
+
+		assert_eq!(bc.needed_blocks(peer0, 40, 150, 0), Some(1 .. 41));
+		assert_eq!(bc.needed_blocks(peer1, 40, 150, 0), Some(41 .. 81));
+		assert_eq!(bc.needed_blocks(peer2, 40, 150, 0), None);
+
+		bc.clear_peer_download(peer1);
+		bc.insert(41, blocks[40 .. 80].to_vec(), peer1);
+		assert_eq!(bc.needed_blocks(peer2, 40, 150, 0), None);
+
+		// the range preceding the downloaded blocks is still scheduled.
+		bc.clear_peer_download(peer0);
+		assert_eq!(bc.needed_blocks(peer2, 40, 150, 0), Some(1 .. 41));
+		assert_eq!(bc.needed_blocks(peer0, 40, 150, 0), None);
+
+		bc.clear_peer_download(peer2);
+		bc.insert(1, blocks[0 .. 40].to_vec(), peer2);
+		assert_eq!(bc.drain(1).len(), 80);
+		assert_eq!(bc.needed_blocks(peer0, 40, 150, 80), Some(81 .. 121));
+	}
+
+	fn generate_fork(parent_hash: H256, first: u64, n: usize) -> Vec<message::BlockData<Block>> {
+		let mut parent_hash = parent_hash;
+		(0 .. n as u64).map(|i| {
+			let header = Header {
+				parent_hash,
+				number: first + i,
+				state_root: H256::random(),
+				extrinsics_root: Default::default(),
+				digest: Digest::default(),
+			};
+			parent_hash = header.hash();
+			message::generic::BlockData {
+				hash: parent_hash,
+				header: Some(header),
+				body: None,
+				message_queue: None,
+				receipt: None,
+				justification: None,
+			}
+		}).collect()
+	}
+
+	#[test]
+	fn competing_forks_are_kept_apart() {
+		let mut forks = ForkBlocks::<Block>::new();
+		let common = H256::random();
+		let fork_a = generate_fork(common, 10, 6);
+		let fork_b = generate_fork(common, 10, 6);
+		let head_a = fork_a[5].hash;
+		let head_b = fork_b[5].hash;
+
+		// top halves of both forks, from different peers.
+		forks.insert(fork_a[3..].to_vec(), 0).unwrap();
+		forks.insert(fork_b[3..].to_vec(), 1).unwrap();
+		assert_eq!(forks.len(), 6);
+		assert_eq!(forks.lowest_ancestor(0, &head_a), Some((13, fork_a[2].hash)));
+		assert_eq!(forks.lowest_ancestor(1, &head_b), Some((13, fork_b[2].hash)));
+
+		// the blocks of one peer don't extend the chain downloaded from another.
+		forks.insert(fork_a[..3].to_vec(), 2).unwrap();
+		assert_eq!(forks.lowest_ancestor(0, &head_a), Some((13, fork_a[2].hash)));
+		forks.insert(fork_a[..3].to_vec(), 0).unwrap();
+		assert_eq!(forks.lowest_ancestor(0, &head_a), Some((10, common)));
+		assert_eq!(forks.lowest_ancestor(1, &head_b), Some((13, fork_b[2].hash)));
+
+		let drained = forks.drain(0, &head_a);
+		assert_eq!(drained.iter().map(|b| b.block.hash).collect::<Vec<_>>(),
+			fork_a.iter().map(|b| b.hash).collect::<Vec<_>>());
+		assert!(drained.iter().all(|b| b.origin == Some(0)));
+		assert_eq!(forks.len(), 6);
+		assert_eq!(forks.lowest_ancestor(0, &head_a), None);
+
+		forks.remove_peer(2);
+		assert_eq!(forks.len(), 3);
+		forks.prune(14);
+		assert_eq!(forks.lowest_ancestor(1, &head_b), Some((15, fork_b[4].hash)));
+	}
+
+	#[test]
+	fn fork_blocks_are_checked_and_bounded() {
+		let mut forks = ForkBlocks::<Block>::with_max_blocks(8);
+		let common = H256::random();
+		let fork = generate_fork(common, 10, 6);
+
+		let mut forged = fork.clone();
+		forged[2].hash = H256::random();
+		assert_eq!(forks.insert(forged.clone(), 0), Err(ForkInsertError::HashMismatch(forged[2].hash)));
+		assert!(forks.is_empty());
+
+		forks.insert(fork.clone(), 0).unwrap();
+		assert_eq!(forks.insert(fork.clone(), 1), Err(ForkInsertError::Full));
+		assert_eq!(forks.len(), 6);
+		// blocks already kept don't count against the limit again.
+		assert_eq!(forks.insert(fork, 0), Ok(()));
+	}
 }
@@ -17,6 +17,7 @@
 //! Blockchain access trait
 
 use client::{self, Client as SubstrateClient, ClientInfo, BlockStatus, CallExecutor};
+use client::blockchain::HeaderBackend as ChainHeaderBackend;
 use client::error::Error;
 use client::light::fetcher::ChangesProof;
 use consensus::{BlockImport, Error as ConsensusError};
@@ -44,9 +45,16 @@ pub trait Client<Block: BlockT>: Send + Sync {
 	/// Get block header.
 	fn header(&self, id: &BlockId<Block>) -> Result<Option<Block::Header>, Error>;
 
+	/// Get up to `max` headers starting at the given block and following
+	/// parent hashes, in descending order.
+	fn header_ancestry(&self, hash: Block::Hash, max: usize) -> Result<Vec<Block::Header>, Error>;
+
 	/// Get block body.
 	fn body(&self, id: &BlockId<Block>) -> Result<Option<Vec<Block::Extrinsic>>, Error>;
 
+	/// Get the set of authorities at a given block.
+	fn authorities(&self, id: &BlockId<Block>) -> Result<Vec<AuthorityIdFor<Block>>, Error>;
+
 	/// Get block justification.
 	fn justification(&self, id: &BlockId<Block>) -> Result<Option<Justification>, Error>;
 
@@ -59,6 +67,12 @@ pub trait Client<Block: BlockT>: Send + Sync {
 	/// Get method execution proof.
 	fn execution_proof(&self, block: &Block::Hash, method: &str, data: &[u8]) -> Result<(Vec<u8>, Vec<Vec<u8>>), Error>;
 
+	/// Insert auxiliary data into the key-value store and delete the given keys.
+	fn insert_aux(&self, insert: &[(&[u8], &[u8])], delete: &[&[u8]]) -> Result<(), Error>;
+
+	/// Query auxiliary data from the key-value store.
+	fn get_aux(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
 	/// Get key changes proof.
 	fn key_changes_proof(
 		&self,
@@ -99,10 +113,18 @@ impl<B, E, Block, RA> Client<Block> for SubstrateClient<B, E, Block, RA> where
 		(self as &SubstrateClient<B, E, Block, RA>).header(id)
 	}
 
+	fn header_ancestry(&self, hash: Block::Hash, max: usize) -> Result<Vec<Block::Header>, Error> {
+		ChainHeaderBackend::header_ancestry(self as &SubstrateClient<B, E, Block, RA>, hash, max)
+	}
+
 	fn body(&self, id: &BlockId<Block>) -> Result<Option<Vec<Block::Extrinsic>>, Error> {
 		(self as &SubstrateClient<B, E, Block, RA>).body(id)
 	}
 
+	fn authorities(&self, id: &BlockId<Block>) -> Result<Vec<AuthorityIdFor<Block>>, Error> {
+		(self as &SubstrateClient<B, E, Block, RA>).authorities_at(id)
+	}
+
 	fn justification(&self, id: &BlockId<Block>) -> Result<Option<Justification>, Error> {
 		(self as &SubstrateClient<B, E, Block, RA>).justification(id)
 	}
@@ -119,6 +141,14 @@ impl<B, E, Block, RA> Client<Block> for SubstrateClient<B, E, Block, RA> where
 		(self as &SubstrateClient<B, E, Block, RA>).execution_proof(&BlockId::Hash(block.clone()), method, data)
 	}
 
+	fn insert_aux(&self, insert: &[(&[u8], &[u8])], delete: &[&[u8]]) -> Result<(), Error> {
+		client::backend::AuxStore::insert_aux(self, insert.iter(), delete.iter())
+	}
+
+	fn get_aux(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		client::backend::AuxStore::get_aux(self, key)
+	}
+
 	fn key_changes_proof(
 		&self,
 		first: Block::Hash,
@@ -16,14 +16,19 @@
 
 //! Configuration for the networking layer of Substrate.
 
-pub use network_libp2p::{NonReservedPeerMode, NetworkConfiguration, Secret};
+pub use network_libp2p::{
+	IpNetwork, Multiplexer, MultiplexerConfig, NonReservedPeerMode, NetworkConfiguration,
+	NodeKeyType, Secret, TlsCertificate,
+};
 
 use chain::Client;
 use codec;
 use on_demand::OnDemandService;
 use runtime_primitives::traits::{Block as BlockT};
 use service::{ExHashT, TransactionPool};
+use std::cmp;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Service initialization parameters.
 pub struct Params<B: BlockT, S, H: ExHashT> {
@@ -46,16 +51,250 @@ pub struct Params<B: BlockT, S, H: ExHashT> {
 pub struct ProtocolConfig {
 	/// Assigned roles.
 	pub roles: Roles,
+	/// Never download blocks, only fetch the missing justifications of blocks we
+	/// already have (e.g. after restoring a database copy).
+	pub justifications_only: bool,
+	/// Which imported blocks are announced to light peers. Full peers are
+	/// always announced all blocks immediately. Light peers too by default.
+	pub light_announcement: LightAnnouncement,
+	/// If the best block seen from peers doesn't advance and no block announcements
+	/// are received for this long while peers are connected, the chain is considered
+	/// frozen and some of the peers are replaced. `None`, the default, disables the detection.
+	pub chain_freeze_timeout: Option<Duration>,
+	/// How failed justification requests are retried.
+	pub justification_retry: RetryPolicy,
+	/// Maximum number of justification requests tracked at once. Requests for
+	/// higher blocks are dropped first, explicit requests are dropped last.
+	pub max_pending_justifications: usize,
+	/// Maximum encoded size of downloaded blocks waiting to be imported. No
+	/// new block ranges are requested from peers while this is exceeded.
+	pub max_queued_block_bytes: usize,
+	/// Download each block range from two peers and compare the blocks before
+	/// importing them, to detect peers serving divergent data early. Diverging
+	/// blocks are downloaded from a third peer, and the peers it disagrees with are dropped.
+	pub cross_check_blocks: bool,
+	/// Number of block ranges that may be downloaded ahead of the imported chain.
+	pub download_window: DownloadWindow,
+	/// Maximum encoded size of the block responses we serve. Responses are
+	/// truncated on a block boundary, but always contain at least one block.
+	pub max_block_response_bytes: usize,
+	/// Block responses of more than one block larger than this are rejected.
+	pub max_accepted_block_response_bytes: usize,
+	/// Block requests served to a single peer within a time window. Requests
+	/// beyond the quota are delayed to the next window.
+	pub block_request_quota: RequestQuota,
+	/// Gossip and announcement messages handled from a single peer within a
+	/// time window. Peers flooding us beyond it are penalized.
+	pub gossip_quota: GossipQuota,
+	/// Maximum number of gossip messages remembered as seen. Messages seen
+	/// again are neither validated nor propagated again.
+	pub max_seen_gossip_messages: usize,
+	/// Time gossip messages are remembered as seen for.
+	pub seen_gossip_messages_ttl: Duration,
+	/// Time after which ready transactions that aren't included in a block yet
+	/// are sent again to peers, plus a random delay of up to half of it. `None`
+	/// sends transactions to each peer only once.
+	pub transaction_rebroadcast: Option<Duration>,
+	/// Extrinsics sent to a single peer within a time window, and the size of the
+	/// messages they are batched in.
+	pub transaction_quota: TransactionQuota,
+	/// Number of threads serving block requests. `0` serves them on the network thread.
+	pub block_request_workers: usize,
+	/// Maximum number of block requests waiting for a worker thread. Requests
+	/// beyond this are delayed to a later tick like requests over the quota,
+	/// or dropped once `block_request_quota.max_delayed` requests of the peer
+	/// are delayed already.
+	pub max_queued_block_requests: usize,
+	/// Identifier of the hard fork of the chain we follow, if any. Peers
+	/// announcing another fork are disconnected during the handshake.
+	pub fork_id: Option<Vec<u8>>,
+	/// Maximum number of our block requests a peer has to answer at the same
+	/// time. Further requests are sent once it answered the previous ones.
+	pub max_requests_in_flight: usize,
+	/// Maximum number of block requests of a single peer waiting to be served.
+	/// Further requests of the peer are answered with an empty response, so
+	/// that it can ask another peer without waiting for a timeout.
+	pub max_queued_requests_per_peer: usize,
+	/// Name and version of our implementation, told to peers in the handshake.
+	pub client_version: Option<String>,
+	/// Maximum number of transactions messages held back for a clogged peer.
+	/// Further ones are dropped. Sync messages are never dropped.
+	pub max_queued_outbound_messages: usize,
 }
 
 impl Default for ProtocolConfig {
 	fn default() -> ProtocolConfig {
 		ProtocolConfig {
 			roles: Roles::FULL,
+			justifications_only: false,
+			light_announcement: LightAnnouncement::All,
+			chain_freeze_timeout: None,
+			justification_retry: RetryPolicy::default(),
+			max_pending_justifications: 1024,
+			max_queued_block_bytes: 256 * 1024 * 1024,
+			cross_check_blocks: false,
+			download_window: DownloadWindow::default(),
+			max_block_response_bytes: 16 * 1024 * 1024,
+			max_accepted_block_response_bytes: 64 * 1024 * 1024,
+			block_request_quota: RequestQuota::default(),
+			gossip_quota: GossipQuota::default(),
+			max_seen_gossip_messages: ::consensus_gossip::SEEN_MESSAGES_CAPACITY,
+			seen_gossip_messages_ttl: ::consensus_gossip::SEEN_MESSAGES_TTL,
+			transaction_rebroadcast: Some(Duration::from_secs(60)),
+			transaction_quota: TransactionQuota::default(),
+			block_request_workers: 2,
+			max_queued_block_requests: 256,
+			fork_id: None,
+			max_requests_in_flight: 8,
+			max_queued_requests_per_peer: 8,
+			client_version: None,
+			max_queued_outbound_messages: 1024,
 		}
 	}
 }
 
+/// Retry policy for requests of data that is fetched from peers separately
+/// from the blocks themselves (e.g. justifications).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+	/// Time to wait before asking a peer again after it failed to provide the
+	/// data. Doubled with every further failure of the same peer.
+	pub retry_wait: Duration,
+	/// Upper bound of the time to wait before asking the same peer again.
+	pub max_retry_wait: Duration,
+	/// Number of failed attempts after which the data is reported as unavailable
+	/// and no longer requested. `None` keeps requesting it until the block is finalized.
+	pub max_attempts: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> RetryPolicy {
+		RetryPolicy {
+			retry_wait: Duration::from_secs(10),
+			max_retry_wait: Duration::from_secs(300),
+			max_attempts: None,
+		}
+	}
+}
+
+/// Amount of requests served to a single peer within a time window.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestQuota {
+	/// Length of the window.
+	pub window: Duration,
+	/// Maximum number of requests served within a window.
+	pub max_requests: u32,
+	/// Maximum encoded size of the responses served within a window.
+	pub max_bytes: usize,
+	/// Maximum number of requests waiting for the next window. A peer sending
+	/// more requests is disconnected.
+	pub max_delayed: usize,
+}
+
+impl Default for RequestQuota {
+	fn default() -> RequestQuota {
+		RequestQuota {
+			window: Duration::from_secs(10),
+			max_requests: 256,
+			max_bytes: 128 * 1024 * 1024,
+			max_delayed: 8,
+		}
+	}
+}
+
+/// Amount of gossip and announcement messages handled from a single peer within a
+/// time window. Messages beyond the quota are dropped; a peer exceeding it again in
+/// the next windows loses reputation, then is disconnected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GossipQuota {
+	/// Length of the window.
+	pub window: Duration,
+	/// Maximum number of messages handled within a window.
+	pub max_messages: u32,
+	/// Maximum encoded size of the messages handled within a window.
+	pub max_bytes: usize,
+	/// Reputation change of a peer for each window it exceeds the quota in, after the first one.
+	pub reputation_change: i32,
+	/// Number of consecutive windows a peer may exceed the quota in before being disconnected.
+	pub max_exceeded_windows: u32,
+}
+
+impl Default for GossipQuota {
+	fn default() -> GossipQuota {
+		GossipQuota {
+			window: Duration::from_secs(1),
+			max_messages: 1024,
+			max_bytes: 32 * 1024 * 1024,
+			reputation_change: -10,
+			max_exceeded_windows: 8,
+		}
+	}
+}
+
+/// Amount of extrinsics sent to a single peer within a time window. Extrinsics beyond
+/// the quota are sent in a later window.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionQuota {
+	/// Length of the window.
+	pub window: Duration,
+	/// Maximum encoded size of the extrinsics sent within a window.
+	pub max_bytes: usize,
+	/// Maximum encoded size of the extrinsics batched in a single message. Larger
+	/// batches are split; a single larger extrinsic is sent alone.
+	pub max_message_bytes: usize,
+}
+
+impl Default for TransactionQuota {
+	fn default() -> TransactionQuota {
+		TransactionQuota {
+			window: Duration::from_secs(5),
+			max_bytes: 4 * 1024 * 1024,
+			max_message_bytes: 256 * 1024,
+		}
+	}
+}
+
+/// Bounds of the block download window, i.e. the number of block ranges that are
+/// requested or waiting to be imported at the same time. The window grows with
+/// the number of connected peers, within these bounds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DownloadWindow {
+	/// Number of ranges allowed regardless of the number of peers.
+	pub min_ranges: usize,
+	/// Upper bound of the number of ranges.
+	pub max_ranges: usize,
+	/// Number of ranges allowed for each connected peer.
+	pub ranges_per_peer: usize,
+}
+
+impl DownloadWindow {
+	/// Number of ranges allowed with the given number of connected peers.
+	pub fn ranges(&self, peers: usize) -> usize {
+		cmp::max(self.min_ranges, cmp::min(self.max_ranges, peers.saturating_mul(self.ranges_per_peer)))
+	}
+}
+
+impl Default for DownloadWindow {
+	fn default() -> DownloadWindow {
+		DownloadWindow {
+			min_ranges: 8,
+			max_ranges: 128,
+			ranges_per_peer: 4,
+		}
+	}
+}
+
+/// Blocks that are announced to light peers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightAnnouncement {
+	/// Announce every block, as soon as it is imported.
+	All,
+	/// Announce a block when it becomes our best block.
+	Best,
+	/// Announce a block when it is finalized.
+	Finalized,
+}
+
 bitflags! {
 	/// Bitmask of the roles that a node fulfills.
 	pub struct Roles: u8 {
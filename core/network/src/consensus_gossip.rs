@@ -16,24 +16,139 @@
 
 //! Utility for gossip of network messages between authorities.
 //! Handles chain-specific and standard BFT messages.
-
-use std::collections::{HashMap, HashSet};
+//!
+//! Consensus engines can also register a `Validator`, which decides whether
+//! each of their messages is kept, propagated or discarded, and when it
+//! expires. Their messages are tagged with the `ConsensusEngineId` of the
+//! engine and only exchanged with peers supporting `Features::GOSSIP_ENGINES`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash as StdHash;
+use std::sync::Arc;
+use linked_hash_map::LinkedHashMap;
 use futures::sync::mpsc;
 use std::time::{Instant, Duration};
-use rand::{self, seq::SliceRandom};
 use network_libp2p::NodeIndex;
 use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, Hash, HashFor};
 use runtime_primitives::generic::BlockId;
+use codec::Encode;
 pub use message::generic::{Message, ConsensusMessage};
+pub use message::{ConsensusEngineId, SignedConsensusMessage};
+use message::Features;
 use protocol::Context;
 use config::Roles;
+use simulation;
 
 // FIXME: Add additional spam/DoS attack protection: https://github.com/paritytech/substrate/issues/1115
 const MESSAGE_LIFETIME: Duration = Duration::from_secs(600);
+/// Default number of messages remembered as seen, so that they aren't handled again.
+pub const SEEN_MESSAGES_CAPACITY: usize = 65536;
+/// Default time messages are remembered as seen for.
+pub const SEEN_MESSAGES_TTL: Duration = Duration::from_secs(1200);
+
+// Number of topics metrics are kept for, forgetting the least recently active first.
+const METRICS_TOPICS: usize = 1024;
+
+/// Counters of the gossip messages of a topic.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct TopicMetrics {
+	/// Number of new messages received from peers.
+	pub received: u64,
+	/// Number of messages sent to peers.
+	pub sent: u64,
+	/// Number of messages pruned because they expired or their topic did.
+	pub expired: u64,
+	/// Number of messages received from peers that were ancient or discarded by a validator.
+	pub rejected: u64,
+}
+
+/// Messages seen recently, with when they were first seen. Bounded in number and in time,
+/// forgetting the oldest messages first.
+struct SeenMessages<K: StdHash + Eq> {
+	entries: LinkedHashMap<K, Instant>,
+	capacity: usize,
+	ttl: Duration,
+}
+
+impl<K: StdHash + Eq + Clone> SeenMessages<K> {
+	fn new(capacity: usize, ttl: Duration) -> Self {
+		SeenMessages {
+			entries: LinkedHashMap::new(),
+			capacity,
+			ttl,
+		}
+	}
+
+	/// Notes a message as seen at `now`. Returns false if it was already seen.
+	fn insert(&mut self, key: K, now: Instant) -> bool {
+		if self.entries.contains_key(&key) {
+			return false;
+		}
+		self.entries.insert(key, now);
+		while self.entries.len() > self.capacity {
+			self.entries.pop_front();
+		}
+		true
+	}
+
+	fn contains(&self, key: &K) -> bool {
+		self.entries.contains_key(key)
+	}
+
+	/// When the message was first seen, if it is still remembered.
+	fn seen_at(&self, key: &K) -> Option<Instant> {
+		self.entries.get(key).cloned()
+	}
+
+	/// Forgets the messages seen before the time to live, and those not matching the predicate.
+	fn retain<P: Fn(&K) -> bool>(&mut self, now: Instant, predicate: P) {
+		let ttl = self.ttl;
+		let to_remove = self.entries.iter()
+			.filter(|(key, seen)| **seen + ttl < now || !predicate(key))
+			.map(|(key, _)| key.clone())
+			.collect::<Vec<_>>();
+		for key in to_remove {
+			self.entries.remove(&key);
+		}
+	}
+
+	fn len(&self) -> usize {
+		self.entries.len()
+	}
+}
+
+/// Outcome of the validation of a gossip message by the validator of its engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationResult {
+	/// The message is valid: keep it and send it to the peers that don't know it yet.
+	Propagate,
+	/// The message is valid but of no use to other peers: keep it without sending it on.
+	Keep,
+	/// The message is stale or of no interest: drop it without holding it against the peer.
+	Ignore,
+	/// The message is invalid: drop it, and lower the reputation of the peer that sent it.
+	Discard,
+}
+
+/// Checks the gossip messages of a consensus engine.
+pub trait Validator<B: BlockT>: Send + Sync {
+	/// Validate a message received from a peer on a topic.
+	fn validate(&self, topic: &B::Hash, data: &[u8]) -> ValidationResult;
+
+	/// Whether a kept message is no longer relevant and can be dropped before the end of
+	/// its lifetime. Messages don't expire early by default.
+	fn message_expired(&self, _topic: &B::Hash, _data: &[u8]) -> bool {
+		false
+	}
+}
 
 struct PeerConsensus<H> {
 	known_messages: HashSet<H>,
 	is_authority: bool,
+	// Whether the peer understands messages signed by authorities.
+	authority_messages: bool,
+	// Whether the peer understands messages of the engines with a validator.
+	gossip_engines: bool,
 }
 
 struct MessageEntry<B: BlockT> {
@@ -41,57 +156,170 @@ struct MessageEntry<B: BlockT> {
 	message_hash: B::Hash,
 	message: ConsensusMessage,
 	broadcast: bool,
+	// Whether `message` is an encoded `SignedConsensusMessage`, verified against the authority set.
+	authenticated: bool,
+	// Engine whose validator checked the message, if any.
+	engine_id: Option<ConsensusEngineId>,
+	// Whether the message is sent to peers, or only kept for the local subscribers.
+	propagate: bool,
+}
+
+// Messages of both lanes are hashed apart, so that a message sent on one can't shadow
+// the same bytes sent on the other.
+fn message_hash<B: BlockT>(message: &[u8], authenticated: bool) -> B::Hash {
+	let mut data = Vec::with_capacity(message.len() + 1);
+	data.push(authenticated as u8);
+	data.extend_from_slice(message);
+	HashFor::<B>::hash(&data)
+}
+
+// Messages of engines are hashed apart from the other lanes and from other engines.
+fn engine_message_hash<B: BlockT>(engine_id: ConsensusEngineId, message: &[u8]) -> B::Hash {
+	let mut data = Vec::with_capacity(message.len() + 5);
+	data.push(2);
+	data.extend_from_slice(&engine_id);
+	data.extend_from_slice(message);
+	HashFor::<B>::hash(&data)
+}
+
+fn consensus_message<B: BlockT>(topic: B::Hash, message: ConsensusMessage, broadcast: bool, authenticated: bool) -> ::message::Message<B> {
+	if authenticated {
+		Message::AuthorityConsensus(topic, message, broadcast)
+	} else {
+		Message::Consensus(topic, message, broadcast)
+	}
 }
 
 /// Consensus network protocol handler. Manages statements and candidate requests.
 pub struct ConsensusGossip<B: BlockT> {
-	peers: HashMap<NodeIndex, PeerConsensus<(B::Hash, B::Hash)>>,
-	live_message_sinks: HashMap<B::Hash, Vec<mpsc::UnboundedSender<ConsensusMessage>>>,
+	peers: BTreeMap<NodeIndex, PeerConsensus<(B::Hash, B::Hash)>>,
+	live_message_sinks: HashMap<(B::Hash, bool), Vec<mpsc::UnboundedSender<ConsensusMessage>>>,
+	engine_message_sinks: HashMap<(ConsensusEngineId, B::Hash), Vec<mpsc::UnboundedSender<ConsensusMessage>>>,
+	validators: HashMap<ConsensusEngineId, Arc<dyn Validator<B>>>,
 	messages: Vec<MessageEntry<B>>,
-	known_messages: HashSet<(B::Hash, B::Hash)>,
-	message_times: HashMap<(B::Hash, B::Hash), Instant>,
+	known_messages: SeenMessages<(B::Hash, B::Hash)>,
 	session_start: Option<B::Hash>,
+	metrics: LinkedHashMap<B::Hash, TopicMetrics>,
 }
 
 impl<B: BlockT> ConsensusGossip<B> {
 	/// Create a new instance.
 	pub fn new() -> Self {
+		Self::with_seen_messages(SEEN_MESSAGES_CAPACITY, SEEN_MESSAGES_TTL)
+	}
+
+	/// Create a new instance remembering at most `capacity` seen messages, each for `ttl`.
+	/// Messages seen again within that time are neither validated nor propagated again.
+	pub fn with_seen_messages(capacity: usize, ttl: Duration) -> Self {
 		ConsensusGossip {
-			peers: HashMap::new(),
+			peers: BTreeMap::new(),
 			live_message_sinks: HashMap::new(),
+			engine_message_sinks: HashMap::new(),
+			validators: HashMap::new(),
 			messages: Default::default(),
-			known_messages: Default::default(),
-			message_times: Default::default(),
-			session_start: None
+			known_messages: SeenMessages::new(capacity, ttl),
+			session_start: None,
+			metrics: LinkedHashMap::new(),
 		}
 	}
 
+	/// Returns the message counters of the most recently active topics.
+	pub fn metrics(&self) -> Vec<(B::Hash, TopicMetrics)> {
+		self.metrics.iter().map(|(topic, metrics)| (*topic, metrics.clone())).collect()
+	}
+
 	/// Closes all notification streams.
 	pub fn abort(&mut self) {
 		self.live_message_sinks.clear();
+		self.engine_message_sinks.clear();
+	}
+
+	/// Register the validator of the messages of a consensus engine, replacing the previous
+	/// one. Messages of engines without a validator are ignored.
+	pub fn register_validator(&mut self, engine_id: ConsensusEngineId, validator: Arc<dyn Validator<B>>) {
+		self.validators.insert(engine_id, validator);
+	}
+
+	/// The validator registered for the messages of a consensus engine.
+	pub fn validator(&self, engine_id: ConsensusEngineId) -> Option<Arc<dyn Validator<B>>> {
+		self.validators.get(&engine_id).cloned()
+	}
+
+	/// Whether a message has been seen recently. `authenticated` tells the lane of
+	/// messages signed by authorities apart from the others.
+	pub fn is_known_message(&self, topic: B::Hash, message: &[u8], authenticated: bool) -> bool {
+		self.known_messages.contains(&(topic, message_hash::<B>(message, authenticated)))
+	}
+
+	/// Whether a message of a consensus engine has been seen recently.
+	pub fn is_known_engine_message(&self, engine_id: ConsensusEngineId, topic: B::Hash, message: &[u8]) -> bool {
+		self.known_messages.contains(&(topic, engine_message_hash::<B>(engine_id, message)))
 	}
 
 	/// Handle new connected peer.
 	pub fn new_peer(&mut self, protocol: &mut Context<B>, who: NodeIndex, roles: Roles) {
+		let authority_messages = protocol.peer_info(who)
+			.map_or(false, |info| info.features.contains(Features::AUTHORITY_MESSAGES));
+		let gossip_engines = protocol.peer_info(who)
+			.map_or(false, |info| info.features.contains(Features::GOSSIP_ENGINES));
 		if roles.intersects(Roles::AUTHORITY) {
 			trace!(target:"gossip", "Registering {:?} {}", roles, who);
 			// Send out all known messages to authorities.
 			let mut known_messages = HashSet::new();
-			for entry in self.messages.iter() {
+			for entry in self.messages.iter().filter(|entry| entry.engine_id.is_none() && (authority_messages || !entry.authenticated)) {
 				known_messages.insert((entry.topic, entry.message_hash));
-				protocol.send_message(who, Message::Consensus(entry.topic.clone(), entry.message.clone(), entry.broadcast));
+				topic_metrics(&mut self.metrics, entry.topic).sent += 1;
+				protocol.send_message(who, consensus_message::<B>(entry.topic.clone(), entry.message.clone(), entry.broadcast, entry.authenticated));
 			}
 			self.peers.insert(who, PeerConsensus {
 				known_messages,
 				is_authority: true,
+				authority_messages,
+				gossip_engines,
 			});
 		}
 		else if roles.intersects(Roles::FULL) {
 			self.peers.insert(who, PeerConsensus {
 				known_messages: HashSet::new(),
 				is_authority: false,
+				authority_messages,
+				gossip_engines,
 			});
 		}
+
+		// Messages of engines are sent to every peer understanding them, whatever its roles.
+		if let Some(peer) = self.peers.get_mut(&who).filter(|peer| peer.gossip_engines) {
+			for entry in self.messages.iter().filter(|entry| entry.propagate) {
+				if let Some(engine_id) = entry.engine_id {
+					if peer.known_messages.insert((entry.topic, entry.message_hash)) {
+						topic_metrics(&mut self.metrics, entry.topic).sent += 1;
+						protocol.send_message(who, Message::EngineConsensus(engine_id, entry.topic, entry.message.clone()));
+					}
+				}
+			}
+		}
+	}
+
+	fn propagate_engine(
+		&mut self,
+		protocol: &mut Context<B>,
+		engine_id: ConsensusEngineId,
+		message_hash: B::Hash,
+		topic: B::Hash,
+		message: &ConsensusMessage,
+	) {
+		let mut sent = 0;
+		for (id, ref mut peer) in self.peers.iter_mut() {
+			if !peer.gossip_engines {
+				continue;
+			}
+			if peer.known_messages.insert((topic, message_hash)) {
+				trace!(target:"gossip", "Propagating message of engine {:?} to {}", engine_id, id);
+				protocol.send_message(*id, Message::EngineConsensus(engine_id, topic, message.clone()));
+				sent += 1;
+			}
+		}
+		topic_metrics(&mut self.metrics, topic).sent += sent;
 	}
 
 	fn propagate<F>(
@@ -100,12 +328,17 @@ impl<B: BlockT> ConsensusGossip<B> {
 		message_hash: B::Hash,
 		topic: B::Hash,
 		broadcast: bool,
+		authenticated: bool,
 		get_message: F,
 	)
 		where F: Fn() -> ConsensusMessage,
 	{
+		let mut sent = 0;
 		if broadcast {
 			for (id, ref mut peer) in self.peers.iter_mut() {
+				if authenticated && !peer.authority_messages {
+					continue;
+				}
 				if peer.known_messages.insert((topic.clone(), message_hash.clone())) {
 					let message = get_message();
 					if peer.is_authority {
@@ -113,18 +346,21 @@ impl<B: BlockT> ConsensusGossip<B> {
 					} else {
 						trace!(target:"gossip", "Propagating to {}: {:?}", id, message);
 					}
-					protocol.send_message(*id, Message::Consensus(topic, message, broadcast));
+					protocol.send_message(*id, consensus_message::<B>(topic, message, broadcast, authenticated));
+					sent += 1;
 				}
 			}
 
+			topic_metrics(&mut self.metrics, topic).sent += sent;
 			return;
 		}
 
 		let mut non_authorities: Vec<_> = self.peers.iter()
+			.filter(|(_, ref peer)| !authenticated || peer.authority_messages)
 			.filter_map(|(id, ref peer)| if !peer.is_authority && !peer.known_messages.contains(&(topic, message_hash)) { Some(*id) } else { None })
 			.collect();
 
-		non_authorities.shuffle(&mut rand::thread_rng());
+		simulation::shuffle(&mut non_authorities);
 		let non_authorities: HashSet<_> = if non_authorities.is_empty() {
 			HashSet::new()
 		} else {
@@ -133,32 +369,60 @@ impl<B: BlockT> ConsensusGossip<B> {
 
 		for (id, ref mut peer) in self.peers.iter_mut() {
 			if peer.is_authority {
+				if authenticated && !peer.authority_messages {
+					continue;
+				}
 				if peer.known_messages.insert((topic.clone(), message_hash.clone())) {
 					let message = get_message();
 					trace!(target:"gossip", "Propagating to authority {}: {:?}", id, message);
-					protocol.send_message(*id, Message::Consensus(topic, message, broadcast));
+					protocol.send_message(*id, consensus_message::<B>(topic, message, broadcast, authenticated));
+					sent += 1;
 				}
 			} else if non_authorities.contains(&id) {
 				let message = get_message();
 				trace!(target:"gossip", "Propagating to {}: {:?}", id, message);
 				peer.known_messages.insert((topic.clone(), message_hash.clone()));
-				protocol.send_message(*id, Message::Consensus(topic, message, broadcast));
+				protocol.send_message(*id, consensus_message::<B>(topic, message, broadcast, authenticated));
+				sent += 1;
 			}
 		}
+		topic_metrics(&mut self.metrics, topic).sent += sent;
 	}
 
-	fn register_message<F>(&mut self, message_hash: B::Hash, topic: B::Hash, broadcast: bool, get_message: F)
+	fn register_message<F>(&mut self, message_hash: B::Hash, topic: B::Hash, broadcast: bool, authenticated: bool, get_message: F)
 		where F: Fn() -> ConsensusMessage
 	{
-		if self.known_messages.insert((topic, message_hash)) {
+		if self.known_messages.insert((topic, message_hash), simulation::now()) {
 			self.messages.push(MessageEntry {
 				topic,
 				message_hash,
 				broadcast,
 				message: get_message(),
+				authenticated,
+				engine_id: None,
+				propagate: true,
 			});
+		}
+	}
 
-			self.message_times.insert((topic, message_hash), Instant::now());
+	fn register_engine_message(
+		&mut self,
+		engine_id: ConsensusEngineId,
+		message_hash: B::Hash,
+		topic: B::Hash,
+		message: ConsensusMessage,
+		propagate: bool,
+	) {
+		if self.known_messages.insert((topic, message_hash), simulation::now()) {
+			self.messages.push(MessageEntry {
+				topic,
+				message_hash,
+				broadcast: true,
+				message,
+				authenticated: false,
+				engine_id: Some(engine_id),
+				propagate,
+			});
 		}
 	}
 
@@ -174,23 +438,31 @@ impl<B: BlockT> ConsensusGossip<B> {
 			sinks.retain(|sink| !sink.is_closed());
 			!sinks.is_empty()
 		});
+		self.engine_message_sinks.retain(|_, sinks| {
+			sinks.retain(|sink| !sink.is_closed());
+			!sinks.is_empty()
+		});
 
-		let message_times = &mut self.message_times;
+		let validators = &self.validators;
 		let known_messages = &mut self.known_messages;
+		let metrics = &mut self.metrics;
 		let before = self.messages.len();
-		let now = Instant::now();
+		let now = simulation::now();
 
 		self.messages.retain(|entry| {
-			message_times.get(&(entry.topic, entry.message_hash))
-				.map(|instant| *instant + MESSAGE_LIFETIME >= now && predicate(&entry.topic))
-				.unwrap_or(false)
+			let expired = entry.engine_id
+				.and_then(|engine_id| validators.get(&engine_id))
+				.map_or(false, |validator| validator.message_expired(&entry.topic, &entry.message));
+			let keep = !expired && known_messages.seen_at(&(entry.topic, entry.message_hash))
+				.map(|instant| instant + MESSAGE_LIFETIME >= now && predicate(&entry.topic))
+				.unwrap_or(false);
+			if !keep {
+				topic_metrics(metrics, entry.topic).expired += 1;
+			}
+			keep
 		});
 
-		known_messages.retain(|(topic, message_hash)| {
-			message_times.get(&(*topic, *message_hash))
-				.map(|instant| *instant + (2 * MESSAGE_LIFETIME) >= now && predicate(topic))
-				.unwrap_or(false)
-		});
+		known_messages.retain(now, |(topic, _)| predicate(topic));
 
 		trace!(target:"gossip", "Cleaned up {} stale messages, {} left ({} known)",
 			before - self.messages.len(),
@@ -198,8 +470,6 @@ impl<B: BlockT> ConsensusGossip<B> {
 			known_messages.len(),
 		);
 
-		message_times.retain(|h, _| known_messages.contains(h));
-
 		for (_, ref mut peer) in self.peers.iter_mut() {
 			peer.known_messages.retain(|h| known_messages.contains(h));
 		}
@@ -207,11 +477,32 @@ impl<B: BlockT> ConsensusGossip<B> {
 
 	/// Get all incoming messages for a topic.
 	pub fn messages_for(&mut self, topic: B::Hash) -> mpsc::UnboundedReceiver<ConsensusMessage> {
+		self.lane_messages_for(topic, false)
+	}
+
+	/// Get all incoming messages for a topic signed by a current authority. Messages
+	/// are encoded `SignedConsensusMessage`s, so that the signer can be told.
+	pub fn authority_messages_for(&mut self, topic: B::Hash) -> mpsc::UnboundedReceiver<ConsensusMessage> {
+		self.lane_messages_for(topic, true)
+	}
+
+	/// Get all incoming messages of a consensus engine for a topic, that its validator kept.
+	pub fn engine_messages_for(&mut self, engine_id: ConsensusEngineId, topic: B::Hash) -> mpsc::UnboundedReceiver<ConsensusMessage> {
+		let (tx, rx) = mpsc::unbounded();
+		for entry in self.messages.iter().filter(|e| e.topic == topic && e.engine_id == Some(engine_id)) {
+			tx.unbounded_send(entry.message.clone()).expect("receiver known to be live; qed");
+		}
+		self.engine_message_sinks.entry((engine_id, topic)).or_default().push(tx);
+
+		rx
+	}
+
+	fn lane_messages_for(&mut self, topic: B::Hash, authenticated: bool) -> mpsc::UnboundedReceiver<ConsensusMessage> {
 		let (tx, rx) = mpsc::unbounded();
-		for entry in self.messages.iter().filter(|e| e.topic == topic) {
+		for entry in self.messages.iter().filter(|e| e.topic == topic && e.authenticated == authenticated && e.engine_id.is_none()) {
 			tx.unbounded_send(entry.message.clone()).expect("receiver known to be live; qed");
 		}
-		self.live_message_sinks.entry(topic).or_default().push(tx);
+		self.live_message_sinks.entry((topic, authenticated)).or_default().push(tx);
 
 		rx
 	}
@@ -228,7 +519,33 @@ impl<B: BlockT> ConsensusGossip<B> {
 		message: ConsensusMessage,
 		broadcast: bool,
 	) -> Option<(B::Hash, ConsensusMessage)> {
-		let message_hash = HashFor::<B>::hash(&message[..]);
+		self.on_incoming_lane(protocol, who, topic, message, broadcast, false)
+	}
+
+	/// Handle an incoming message signed by an authority, whose signature the caller
+	/// checked. Same as `on_incoming` otherwise, `message` being the encoded
+	/// `SignedConsensusMessage`.
+	pub fn on_incoming_authenticated(
+		&mut self,
+		protocol: &mut Context<B>,
+		who: NodeIndex,
+		topic: B::Hash,
+		message: ConsensusMessage,
+		broadcast: bool,
+	) -> Option<(B::Hash, ConsensusMessage)> {
+		self.on_incoming_lane(protocol, who, topic, message, broadcast, true)
+	}
+
+	fn on_incoming_lane(
+		&mut self,
+		protocol: &mut Context<B>,
+		who: NodeIndex,
+		topic: B::Hash,
+		message: ConsensusMessage,
+		broadcast: bool,
+		authenticated: bool,
+	) -> Option<(B::Hash, ConsensusMessage)> {
+		let message_hash = message_hash::<B>(&message[..], authenticated);
 
 		if self.known_messages.contains(&(topic, message_hash)) {
 			trace!(target:"gossip", "Ignored already known message from {} in {}", who, topic);
@@ -243,6 +560,7 @@ impl<B: BlockT> ConsensusGossip<B> {
 			(Ok(info), Ok(Some(header))) => {
 				if header.number() < &info.chain.best_number {
 					trace!(target:"gossip", "Ignored ancient message from {}, hash={}", who, topic);
+					topic_metrics(&mut self.metrics, topic).rejected += 1;
 					return None;
 				}
 			},
@@ -253,7 +571,8 @@ impl<B: BlockT> ConsensusGossip<B> {
 		if let Some(ref mut peer) = self.peers.get_mut(&who) {
 			use std::collections::hash_map::Entry;
 			peer.known_messages.insert((topic, message_hash));
-			if let Entry::Occupied(mut entry) = self.live_message_sinks.entry(topic) {
+			topic_metrics(&mut self.metrics, topic).received += 1;
+			if let Entry::Occupied(mut entry) = self.live_message_sinks.entry((topic, authenticated)) {
 				debug!(target: "gossip", "Pushing consensus message to sinks for {}.", topic);
 				entry.get_mut().retain(|sink| {
 					if let Err(e) = sink.unbounded_send(message.clone()) {
@@ -270,10 +589,94 @@ impl<B: BlockT> ConsensusGossip<B> {
 			return None;
 		}
 
-		self.multicast_inner(protocol, message_hash, topic, broadcast, || message.clone());
+		self.multicast_inner(protocol, message_hash, topic, broadcast, authenticated, || message.clone());
 		Some((topic, message))
 	}
 
+	/// Handle an incoming message of a consensus engine, given the result of the validator the
+	/// engine registered. The validator is run by the caller, so that it doesn't run while the
+	/// gossip is locked. Already known messages and messages of unregistered peers are ignored.
+	/// Returns an error if the validator discarded the message, so that the peer can be penalized.
+	pub fn on_incoming_engine(
+		&mut self,
+		protocol: &mut Context<B>,
+		who: NodeIndex,
+		engine_id: ConsensusEngineId,
+		topic: B::Hash,
+		message: ConsensusMessage,
+		validation: ValidationResult,
+	) -> Result<(), &'static str> {
+		let message_hash = engine_message_hash::<B>(engine_id, &message[..]);
+
+		if self.known_messages.contains(&(topic, message_hash)) {
+			trace!(target:"gossip", "Ignored already known message from {} in {}", who, topic);
+			return Ok(());
+		}
+
+		if !self.peers.contains_key(&who) {
+			trace!(target:"gossip", "Ignored engine message from unregistered peer {}", who);
+			return Ok(());
+		}
+
+		let propagate = match validation {
+			ValidationResult::Propagate => true,
+			ValidationResult::Keep => false,
+			ValidationResult::Ignore => {
+				trace!(target:"gossip", "Ignored stale message of engine {:?} from {} in {}", engine_id, who, topic);
+				// so that the copies relayed by other peers aren't validated again.
+				self.known_messages.insert((topic, message_hash), simulation::now());
+				return Ok(());
+			},
+			ValidationResult::Discard => {
+				trace!(target:"gossip", "Discarded message of engine {:?} from {} in {}", engine_id, who, topic);
+				self.known_messages.insert((topic, message_hash), simulation::now());
+				topic_metrics(&mut self.metrics, topic).rejected += 1;
+				return Err("Peer sent a consensus message its engine discarded");
+			},
+		};
+
+		if let Some(peer) = self.peers.get_mut(&who) {
+			peer.known_messages.insert((topic, message_hash));
+		}
+		topic_metrics(&mut self.metrics, topic).received += 1;
+
+		{
+			use std::collections::hash_map::Entry;
+			if let Entry::Occupied(mut entry) = self.engine_message_sinks.entry((engine_id, topic)) {
+				debug!(target: "gossip", "Pushing message of engine {:?} to sinks for {}.", engine_id, topic);
+				entry.get_mut().retain(|sink| {
+					if let Err(e) = sink.unbounded_send(message.clone()) {
+						trace!(target:"gossip", "Error broadcasting message notification: {:?}", e);
+					}
+					!sink.is_closed()
+				});
+				if entry.get().is_empty() {
+					entry.remove_entry();
+				}
+			}
+		}
+
+		if propagate {
+			self.propagate_engine(protocol, engine_id, message_hash, topic, &message);
+		}
+		self.register_engine_message(engine_id, message_hash, topic, message, propagate);
+		Ok(())
+	}
+
+	/// Multicast a message of a consensus engine to all peers understanding engine messages.
+	/// The message isn't checked by the validator of the engine.
+	pub fn multicast_engine(
+		&mut self,
+		protocol: &mut Context<B>,
+		engine_id: ConsensusEngineId,
+		topic: B::Hash,
+		message: ConsensusMessage,
+	) {
+		let message_hash = engine_message_hash::<B>(engine_id, &message[..]);
+		self.propagate_engine(protocol, engine_id, message_hash, topic, &message);
+		self.register_engine_message(engine_id, message_hash, topic, message, true);
+	}
+
 	/// Multicast a message to all peers.
 	pub fn multicast(
 		&mut self,
@@ -282,8 +685,21 @@ impl<B: BlockT> ConsensusGossip<B> {
 		message: ConsensusMessage,
 		broadcast: bool,
 	) {
-		let message_hash = HashFor::<B>::hash(&message);
-		self.multicast_inner(protocol, message_hash, topic, broadcast, || message.clone());
+		let message_hash = message_hash::<B>(&message, false);
+		self.multicast_inner(protocol, message_hash, topic, broadcast, false, || message.clone());
+	}
+
+	/// Multicast a message signed by an authority to all peers able to check it.
+	pub fn multicast_authenticated(
+		&mut self,
+		protocol: &mut Context<B>,
+		topic: B::Hash,
+		message: SignedConsensusMessage,
+		broadcast: bool,
+	) {
+		let message = message.encode();
+		let message_hash = message_hash::<B>(&message, true);
+		self.multicast_inner(protocol, message_hash, topic, broadcast, true, || message.clone());
 	}
 
 	fn multicast_inner<F>(
@@ -292,12 +708,13 @@ impl<B: BlockT> ConsensusGossip<B> {
 		message_hash: B::Hash,
 		topic: B::Hash,
 		broadcast: bool,
+		authenticated: bool,
 		get_message: F,
 	)
 		where F: Fn() -> ConsensusMessage
 	{
-		self.register_message(message_hash, topic, broadcast, &get_message);
-		self.propagate(protocol, message_hash, topic, broadcast, get_message);
+		self.register_message(message_hash, topic, broadcast, authenticated, &get_message);
+		self.propagate(protocol, message_hash, topic, broadcast, authenticated, get_message);
 	}
 
 	/// Note new consensus session.
@@ -308,14 +725,65 @@ impl<B: BlockT> ConsensusGossip<B> {
 	}
 }
 
+// Counters of a topic, marked as the most recently active.
+fn topic_metrics<H: StdHash + Eq + Copy>(metrics: &mut LinkedHashMap<H, TopicMetrics>, topic: H) -> &mut TopicMetrics {
+	if metrics.get_refresh(&topic).is_none() {
+		metrics.insert(topic, TopicMetrics::default());
+		while metrics.len() > METRICS_TOPICS {
+			metrics.pop_front();
+		}
+	}
+	metrics.get_mut(&topic).expect("inserted above if missing; qed")
+}
+
 #[cfg(test)]
 mod tests {
+	use network_libp2p::{Severity, ReputationChange};
 	use runtime_primitives::testing::{H256, Block as RawBlock, ExtrinsicWrapper};
 	use std::time::Instant;
+	use protocol::PeerInfo;
+	use message;
 	use super::*;
 
 	type Block = RawBlock<ExtrinsicWrapper<u64>>;
 
+	#[derive(Default)]
+	struct TestContext {
+		sent: Vec<(NodeIndex, message::Message<Block>)>,
+	}
+
+	impl Context<Block> for TestContext {
+		fn client(&self) -> &::chain::Client<Block> {
+			unimplemented!()
+		}
+
+		fn report_peer(&mut self, _who: NodeIndex, _reason: Severity) {}
+
+		fn change_reputation(&mut self, _who: NodeIndex, _change: ReputationChange) {}
+
+		fn peer_info(&self, _peer: NodeIndex) -> Option<PeerInfo<Block>> {
+			Some(PeerInfo {
+				roles: Roles::FULL,
+				protocol_version: 0,
+				features: Features::GOSSIP_ENGINES,
+				client_version: None,
+				latency: None,
+				best_hash: Default::default(),
+				best_number: 0,
+			})
+		}
+
+		fn send_message(&mut self, who: NodeIndex, data: message::Message<Block>) {
+			self.sent.push((who, data));
+		}
+
+		fn cancel_requests(&mut self, _who: NodeIndex, _filter: &Fn(&message::BlockRequest<Block>) -> bool) {}
+
+		fn is_request_deferred(&self, _who: NodeIndex, _filter: &Fn(&message::BlockRequest<Block>) -> bool) -> bool {
+			false
+		}
+	}
+
 	#[test]
 	fn collects_garbage() {
 		let prev_hash = H256::random();
@@ -334,15 +802,16 @@ mod tests {
 					message_hash: $hash,
 					message: $m,
 					broadcast: false,
+					authenticated: false,
+					engine_id: None,
+					propagate: true,
 				});
-				consensus.message_times.insert(($topic, $hash), $now);
+				consensus.known_messages.entries.insert(($topic, $hash), $now);
 			}
 		}
 
 		push_msg!(prev_hash, m1_hash, now, m1);
 		push_msg!(best_hash, m2_hash, now, m2.clone());
-		consensus.known_messages.insert((prev_hash, m1_hash));
-		consensus.known_messages.insert((best_hash, m2_hash));
 
 		// nothing to collect
 		consensus.collect_garbage(|_t| true);
@@ -371,7 +840,7 @@ mod tests {
 		push_msg!(best_hash, m2_hash, now - (2 * MESSAGE_LIFETIME), m2);
 		consensus.collect_garbage(|_topic| true);
 		assert!(consensus.messages.is_empty());
-		assert!(consensus.known_messages.is_empty());
+		assert_eq!(consensus.known_messages.len(), 0);
 	}
 
 	#[test]
@@ -385,7 +854,7 @@ mod tests {
 		let message_hash = HashFor::<Block>::hash(&message);
 		let topic = HashFor::<Block>::hash(&[1,2,3]);
 
-		consensus.register_message(message_hash, topic, false, || message.clone());
+		consensus.register_message(message_hash, topic, false, false, || message.clone());
 		let stream = consensus.messages_for(topic);
 
 		assert_eq!(stream.wait().next(), Some(Ok(message)));
@@ -399,8 +868,8 @@ mod tests {
 		let msg_a = vec![1, 2, 3];
 		let msg_b = vec![4, 5, 6];
 
-		consensus.register_message(HashFor::<Block>::hash(&msg_a), topic, false, || msg_a.clone());
-		consensus.register_message(HashFor::<Block>::hash(&msg_b), topic, false, || msg_b.clone());
+		consensus.register_message(HashFor::<Block>::hash(&msg_a), topic, false, false, || msg_a.clone());
+		consensus.register_message(HashFor::<Block>::hash(&msg_b), topic, false, false, || msg_b.clone());
 
 		assert_eq!(consensus.messages.len(), 2);
 	}
@@ -416,7 +885,7 @@ mod tests {
 		let message_hash = HashFor::<Block>::hash(&message);
 		let topic = HashFor::<Block>::hash(&[1,2,3]);
 
-		consensus.register_message(message_hash, topic, false, || message.clone());
+		consensus.register_message(message_hash, topic, false, false, || message.clone());
 
 		let stream1 = consensus.messages_for(topic);
 		let stream2 = consensus.messages_for(topic);
@@ -424,4 +893,100 @@ mod tests {
 		assert_eq!(stream1.wait().next(), Some(Ok(message.clone())));
 		assert_eq!(stream2.wait().next(), Some(Ok(message)));
 	}
+
+	#[test]
+	fn engine_messages_are_kept_apart() {
+		use futures::Stream;
+
+		let mut consensus = ConsensusGossip::<Block>::new();
+
+		let message = vec![1, 2, 3];
+		let topic = HashFor::<Block>::hash(&[1,2,3]);
+
+		let message_hash = engine_message_hash::<Block>(*b"test", &message);
+		consensus.register_engine_message(*b"test", message_hash, topic, message.clone(), false);
+		consensus.register_message(HashFor::<Block>::hash(&[4, 5, 6]), topic, false, false, || vec![4, 5, 6]);
+		assert_eq!(consensus.messages.len(), 2);
+
+		let stream = consensus.engine_messages_for(*b"test", topic);
+		assert_eq!(stream.wait().next(), Some(Ok(message)));
+		consensus.abort();
+
+		let stream = consensus.messages_for(topic);
+		assert_eq!(stream.wait().next(), Some(Ok(vec![4, 5, 6])));
+	}
+
+	#[test]
+	fn validator_expires_engine_messages() {
+		struct ExpireOdd;
+		impl Validator<Block> for ExpireOdd {
+			fn validate(&self, _topic: &H256, _data: &[u8]) -> ValidationResult {
+				ValidationResult::Propagate
+			}
+
+			fn message_expired(&self, _topic: &H256, data: &[u8]) -> bool {
+				data[0] % 2 == 1
+			}
+		}
+
+		let mut consensus = ConsensusGossip::<Block>::new();
+		consensus.register_validator(*b"test", Arc::new(ExpireOdd));
+
+		let topic = HashFor::<Block>::hash(&[1,2,3]);
+		for data in vec![vec![1], vec![2], vec![3]] {
+			let message_hash = engine_message_hash::<Block>(*b"test", &data);
+			consensus.register_engine_message(*b"test", message_hash, topic, data, true);
+		}
+
+		consensus.collect_garbage(|_topic| true);
+		assert_eq!(consensus.messages.len(), 1);
+		assert_eq!(consensus.messages[0].message, vec![2]);
+		assert_eq!(consensus.metrics(), vec![(topic, TopicMetrics { expired: 2, ..Default::default() })]);
+	}
+
+	#[test]
+	fn incoming_engine_messages_follow_validation() {
+		let mut context = TestContext::default();
+		let mut consensus = ConsensusGossip::<Block>::new();
+		consensus.new_peer(&mut context, 1, Roles::FULL);
+		consensus.new_peer(&mut context, 2, Roles::FULL);
+		let topic = HashFor::<Block>::hash(&[1, 2, 3]);
+
+		// propagated messages are kept and sent to the peers that don't know them.
+		assert_eq!(consensus.on_incoming_engine(&mut context, 1, *b"test", topic, vec![1], ValidationResult::Propagate), Ok(()));
+		assert_eq!(context.sent, vec![(2, Message::EngineConsensus(*b"test", topic, vec![1]))]);
+		assert!(consensus.is_known_engine_message(*b"test", topic, &[1]));
+
+		// kept messages aren't sent on.
+		assert_eq!(consensus.on_incoming_engine(&mut context, 1, *b"test", topic, vec![2], ValidationResult::Keep), Ok(()));
+		assert_eq!(context.sent.len(), 1);
+		assert_eq!(consensus.messages.len(), 2);
+
+		// ignored messages are dropped without blaming the peer.
+		assert_eq!(consensus.on_incoming_engine(&mut context, 1, *b"test", topic, vec![3], ValidationResult::Ignore), Ok(()));
+		assert_eq!(consensus.messages.len(), 2);
+		assert!(consensus.is_known_engine_message(*b"test", topic, &[3]));
+
+		// discarded messages are dropped, and copies of them aren't validated again.
+		assert!(consensus.on_incoming_engine(&mut context, 1, *b"test", topic, vec![4], ValidationResult::Discard).is_err());
+		assert_eq!(consensus.on_incoming_engine(&mut context, 2, *b"test", topic, vec![4], ValidationResult::Discard), Ok(()));
+		assert_eq!(consensus.messages.len(), 2);
+		assert_eq!(context.sent.len(), 1);
+		assert_eq!(consensus.metrics(), vec![(topic, TopicMetrics { received: 2, sent: 1, rejected: 1, ..Default::default() })]);
+	}
+
+	#[test]
+	fn seen_messages_are_bounded() {
+		let now = Instant::now();
+		let mut seen = SeenMessages::new(2, Duration::from_secs(10));
+		assert!(seen.insert(1, now));
+		assert!(!seen.insert(1, now));
+		assert!(seen.insert(2, now));
+		assert!(seen.insert(3, now + Duration::from_secs(5)));
+		assert!(!seen.contains(&1));
+
+		seen.retain(now + Duration::from_secs(12), |_| true);
+		assert!(!seen.contains(&2));
+		assert!(seen.contains(&3));
+	}
 }
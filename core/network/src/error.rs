@@ -35,5 +35,10 @@ error_chain! {
 	}
 
 	errors {
+		/// A notifications protocol with the same name is already registered.
+		DuplicateNotificationsProtocol(name: Vec<u8>) {
+			description("Notifications protocol already registered"),
+			display("Notifications protocol {} already registered", String::from_utf8_lossy(name)),
+		}
 	}
 }
@@ -0,0 +1,823 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Requests for auxiliary per-block data (e.g. justifications) that is fetched
+//! from peers separately from the blocks themselves.
+
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+use std::time::{Duration, Instant};
+use config::{RetryPolicy, Roles};
+use protocol::Context;
+use network_libp2p::{Severity, NodeIndex};
+use runtime_primitives::Justification;
+use runtime_primitives::traits::{Block as BlockT, As, NumberFor};
+use message;
+use simulation;
+use sync::{Metrics, PeerSync, send_block_request, report_peer};
+
+// Max number of consecutive blocks to request data for in a single request.
+const MAX_EXTRAS_PER_REQUEST: usize = 128;
+// Time to wait for a peer to answer a request before asking another peer.
+const EXTRA_REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Max number of consecutive blocks to request data for from the given peer,
+/// depending on its capabilities.
+fn max_batch_len<B: BlockT>(peer: &PeerSync<B>) -> usize {
+	if peer.capabilities.extra_ranges {
+		cmp::min(MAX_EXTRAS_PER_REQUEST, peer.capabilities.max_blocks_per_request)
+	} else {
+		1
+	}
+}
+
+/// Rank of a peer when picking whom to ask for extra data, lower is better.
+/// Justifications are produced by authorities and kept by full nodes, while
+/// light peers are unlikely to have them, so they are only used as fallback.
+fn peer_preference(roles: Roles) -> u8 {
+	if roles.contains(Roles::AUTHORITY) {
+		0
+	} else if roles.contains(Roles::FULL) {
+		1
+	} else {
+		2
+	}
+}
+
+/// Pending request for the given block (hash and number).
+pub(crate) type ExtraRequest<B> = (<B as BlockT>::Hash, NumberFor<B>);
+
+/// State of a tracked request for extra block data, for diagnostics.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtraRequestInfo<B: BlockT> {
+	/// Hash of the block the data is requested for.
+	pub hash: B::Hash,
+	/// Number of the block the data is requested for.
+	pub number: NumberFor<B>,
+	/// Whether the request is needed to make finality progress.
+	pub mandatory: bool,
+	/// Whether data provided by a peer is currently being imported.
+	pub importing: bool,
+	/// Peer the request is currently in flight with and time elapsed since it was sent.
+	pub requested_from: Option<(NodeIndex, Duration)>,
+	/// Peers that failed to provide the data, with the number of failed attempts
+	/// and time elapsed since the last one.
+	pub failed_attempts: Vec<(NodeIndex, u32, Duration)>,
+}
+
+/// The kind of data requested by `ExtraRequests` and how to get it from responses.
+pub(crate) trait ExtraRequestsEssence<B: BlockT> {
+	/// Data provided by a peer for a single block.
+	type Response;
+
+	/// Name of the requested data, used in logs.
+	fn name(&self) -> &'static str;
+
+	/// Block attributes to request.
+	fn fields(&self) -> message::BlockAttributes;
+
+	/// Extract the requested data from a block of a response.
+	fn extract(&self, block: message::BlockData<B>) -> Option<Self::Response>;
+
+	/// Count requests that are repeated after a failed attempt in the sync metrics.
+	fn note_retries(&self, metrics: &mut Metrics, retries: u64);
+}
+
+/// Requests for block justifications.
+pub(crate) struct JustificationsEssence;
+
+impl<B: BlockT> ExtraRequestsEssence<B> for JustificationsEssence {
+	type Response = Justification;
+
+	fn name(&self) -> &'static str {
+		"justification"
+	}
+
+	fn fields(&self) -> message::BlockAttributes {
+		message::BlockAttributes::JUSTIFICATION
+	}
+
+	fn extract(&self, block: message::BlockData<B>) -> Option<Justification> {
+		block.justification
+	}
+
+	fn note_retries(&self, metrics: &mut Metrics, retries: u64) {
+		metrics.justification_retries += retries;
+	}
+}
+
+/// Manages pending requests for auxiliary block data of the kind described by
+/// the essence: peer selection, batching, timeouts and retries. Provided data
+/// is imported by the caller, which reports the result with `on_import_result`.
+pub(crate) struct ExtraRequests<B: BlockT, Essence> {
+	essence: Essence,
+	policy: RetryPolicy,
+	// Maximum number of tracked requests, including the ones in flight.
+	max_requests: usize,
+	requests: HashSet<ExtraRequest<B>>,
+	// Requests needed to make finality progress, which are prioritized over
+	// optional ones and never garbage collected.
+	mandatory_requests: HashSet<ExtraRequest<B>>,
+	pending_requests: VecDeque<ExtraRequest<B>>,
+	// Time of the request, first block requested and batch of consecutive
+	// blocks still requested from each peer, never empty. Garbage collection
+	// may remove blocks from the front of a batch that is in flight.
+	peer_requests: HashMap<NodeIndex, (Instant, ExtraRequest<B>, Vec<ExtraRequest<B>>)>,
+	// Number of failed attempts and time of the last one, per request and peer.
+	previous_requests: HashMap<ExtraRequest<B>, HashMap<NodeIndex, (u32, Instant)>>,
+	// Requests whose provided data is being imported.
+	importing_requests: HashSet<ExtraRequest<B>>,
+	// Whether the set of mandatory requests changed since the last call to
+	// `take_mandatory_changes`.
+	mandatory_changed: bool,
+	// Requests given up after exhausting the attempt budget or dropped because
+	// too many requests were pending, not yet reported.
+	unavailable: Vec<ExtraRequest<B>>,
+}
+
+impl<B: BlockT, Essence: ExtraRequestsEssence<B>> ExtraRequests<B, Essence> {
+	pub(crate) fn new(essence: Essence, policy: RetryPolicy, max_requests: usize) -> ExtraRequests<B, Essence> {
+		ExtraRequests {
+			essence,
+			policy,
+			max_requests,
+			requests: HashSet::new(),
+			mandatory_requests: HashSet::new(),
+			mandatory_changed: false,
+			pending_requests: VecDeque::new(),
+			peer_requests: HashMap::new(),
+			previous_requests: HashMap::new(),
+			importing_requests: HashSet::new(),
+			unavailable: Vec::new(),
+		}
+	}
+
+	/// The first block of the batch that was requested from the given peer,
+	/// even if it has been garbage collected since.
+	pub(crate) fn peer_request(&self, who: NodeIndex) -> Option<ExtraRequest<B>> {
+		self.peer_requests.get(&who).map(|(_, first, _)| *first)
+	}
+
+	/// Dispatches all possible pending requests to the given peers. Peers are
+	/// filtered according to the current known best block (i.e. we won't send a
+	/// request for block #10 to a peer at block #2), and we also back off from
+	/// requesting the same data from a peer that failed to provide it.
+	/// Requests for consecutive blocks at the front of the queue are batched
+	/// into a single request to the same peer. Authorities and full nodes are
+	/// asked before light peers.
+	pub(crate) fn dispatch(&mut self, peers: &HashMap<NodeIndex, PeerSync<B>>, metrics: &mut Metrics, protocol: &mut Context<B>) {
+		if self.pending_requests.is_empty() {
+			return;
+		}
+
+		// retried requests are put back at the front of the queue, make sure
+		// mandatory requests are still scheduled before optional ones.
+		if !self.mandatory_requests.is_empty() {
+			let mandatory_requests = &self.mandatory_requests;
+			let (mut mandatory, mut optional): (VecDeque<_>, VecDeque<_>) = self.pending_requests
+				.drain(..)
+				.partition(|request| mandatory_requests.contains(request));
+			mandatory.append(&mut optional);
+			self.pending_requests = mandatory;
+		}
+
+		let now = simulation::now();
+		let mut available_peers = peers.iter().filter_map(|(peer, sync)| {
+			// extra requests are tracked separately from block requests, so any
+			// peer can serve one as long as it has no other one of this kind in flight
+			if self.peer_requests.contains_key(&peer) {
+				None
+			} else {
+				Some((*peer, sync.best_number, max_batch_len(sync), (peer_preference(sync.roles), sync.latency_rank())))
+			}
+		}).collect::<Vec<_>>();
+
+		// spread requests over peers in random order, but offer them to the
+		// peers most likely to have the data first, and the closest among them
+		available_peers.sort_by_key(|p| p.0);
+		simulation::shuffle(&mut available_peers);
+		available_peers.sort_by_key(|p| p.3);
+		let mut available_peers = available_peers.into_iter()
+			.map(|(peer, best_number, max_batch, _)| (peer, best_number, max_batch))
+			.collect::<VecDeque<_>>();
+
+		let mut last_peer = available_peers.back().map(|p| p.0);
+		let mut unhandled_requests = VecDeque::new();
+
+		loop {
+			let (peer, peer_best_number, max_batch) = match available_peers.pop_front() {
+				Some(p) => p,
+				_ => break,
+			};
+
+			// only ask peers that have synced past the block number that we're
+			// asking the data for and to whom we haven't already made the same
+			// request recently
+			let peer_eligible = match self.pending_requests.front() {
+				Some(request) => self.is_eligible(request, peer, peer_best_number, now),
+				_ => break,
+			};
+
+			if !peer_eligible {
+				available_peers.push_back((peer, peer_best_number, max_batch));
+
+				// we tried all peers and none can answer this request
+				if Some(peer) == last_peer {
+					last_peer = available_peers.back().map(|p| p.0);
+
+					let request = self.pending_requests.pop_front()
+						.expect("verified to be Some in the beginning of the loop; qed");
+
+					unhandled_requests.push_back(request);
+				}
+
+				continue;
+			}
+
+			last_peer = available_peers.back().map(|p| p.0);
+
+			let first = self.pending_requests.pop_front()
+				.expect("verified to be Some in the beginning of the loop; qed");
+			let mut batch = vec![first];
+			while batch.len() < max_batch {
+				let last_number = batch[batch.len() - 1].1;
+				match self.pending_requests.front() {
+					Some(next) if next.1 == last_number + As::sa(1) &&
+						self.is_eligible(next, peer, peer_best_number, now) => {},
+					_ => break,
+				}
+				batch.extend(self.pending_requests.pop_front());
+			}
+
+			let retries = batch.iter()
+				.filter(|request| self.previous_requests.contains_key(*request))
+				.count() as u64;
+			self.essence.note_retries(metrics, retries);
+
+			let last = batch[batch.len() - 1];
+			trace!(target: "sync", "Requesting {}s for blocks {} to {} from {}", self.essence.name(), first.0, last.0, peer);
+			let request = message::generic::BlockRequest {
+				id: 0,
+				fields: self.essence.fields(),
+				from: message::FromBlock::Hash(first.0),
+				to: if batch.len() > 1 { Some(last.0) } else { None },
+				direction: message::Direction::Ascending,
+				max: Some(batch.len() as u32),
+			};
+			self.peer_requests.insert(peer, (now, first, batch));
+
+			send_block_request(protocol, metrics, peer, request);
+		}
+
+		self.pending_requests.append(&mut unhandled_requests);
+	}
+
+	/// Whether the data can be requested from the given peer: it must have
+	/// synced past the block and its backoff after failing to provide the data
+	/// must have expired.
+	fn is_eligible(&self, request: &ExtraRequest<B>, peer: NodeIndex, peer_best_number: NumberFor<B>, now: Instant) -> bool {
+		peer_best_number >= request.1 &&
+			self.previous_requests
+				.get(request)
+				.and_then(|peers| peers.get(&peer))
+				.map_or(true, |(failures, last_failure)| now - *last_failure >= self.retry_wait(*failures))
+	}
+
+	/// Time to wait before asking a peer again after the given number of failures.
+	fn retry_wait(&self, failures: u32) -> Duration {
+		let factor = 1u32 << cmp::min(failures.saturating_sub(1), 16);
+		self.policy.retry_wait.checked_mul(factor)
+			.map_or(self.policy.max_retry_wait, |wait| cmp::min(wait, self.policy.max_retry_wait))
+	}
+
+	/// Notes that the given peer failed to provide the data for a block.
+	/// Returns whether the request should be retried, otherwise its attempt
+	/// budget is exhausted and it is given up.
+	fn note_failure(&mut self, request: ExtraRequest<B>, who: NodeIndex, now: Instant) -> bool {
+		let attempts = {
+			let peers = self.previous_requests.entry(request).or_insert_with(HashMap::new);
+			{
+				let peer = peers.entry(who).or_insert((0, now));
+				peer.0 += 1;
+				peer.1 = now;
+			}
+			peers.values().map(|(failures, _)| *failures).sum::<u32>()
+		};
+
+		match self.policy.max_attempts {
+			Some(max_attempts) if attempts >= max_attempts => {
+				trace!(target: "sync", "Giving up {} request for {} after {} attempts", self.essence.name(), request.0, attempts);
+				self.forget(&request);
+				self.unavailable.push(request);
+				false
+			},
+			_ => true,
+		}
+	}
+
+	/// Takes the requests given up since the last call, after their attempt
+	/// budget was exhausted or because they were dropped from a full queue.
+	pub(crate) fn take_unavailable(&mut self) -> Vec<ExtraRequest<B>> {
+		mem::replace(&mut self.unavailable, Vec::new())
+	}
+
+	/// Puts requests back at the front of the queue, in their original order.
+	fn requeue(&mut self, requests: Vec<ExtraRequest<B>>) {
+		for request in requests.into_iter().rev() {
+			self.pending_requests.push_front(request);
+		}
+	}
+
+	/// Priority of a request, lower is more urgent: mandatory requests first, then
+	/// requests for lower blocks.
+	fn priority(&self, request: &ExtraRequest<B>) -> (bool, NumberFor<B>) {
+		(!self.mandatory_requests.contains(request), request.1)
+	}
+
+	/// Stops tracking a request that is no longer pending.
+	fn forget(&mut self, request: &ExtraRequest<B>) {
+		self.requests.remove(request);
+		if self.mandatory_requests.remove(request) {
+			self.mandatory_changed = true;
+		}
+		self.previous_requests.remove(request);
+	}
+
+	/// Returns all mandatory requests if they changed since the last call.
+	pub(crate) fn take_mandatory_changes(&mut self) -> Option<Vec<ExtraRequest<B>>> {
+		if !mem::replace(&mut self.mandatory_changed, false) {
+			return None;
+		}
+
+		let mut requests = self.mandatory_requests.iter().cloned().collect::<Vec<_>>();
+		requests.sort_by_key(|request| request.1);
+		Some(requests)
+	}
+
+	/// Returns the state of all tracked requests, ordered by block number.
+	pub(crate) fn requests_info(&self) -> Vec<ExtraRequestInfo<B>> {
+		let now = simulation::now();
+		let mut requests = self.requests.iter().map(|request| {
+			let requested_from = self.peer_requests.iter()
+				.find(|&(_, &(_, _, ref batch))| batch.contains(request))
+				.map(|(peer, &(at, _, _))| (*peer, now.duration_since(at)));
+			let mut failed_attempts = self.previous_requests.get(request)
+				.map(|peers| peers.iter()
+					.map(|(peer, &(attempts, at))| (*peer, attempts, now.duration_since(at)))
+					.collect::<Vec<_>>())
+				.unwrap_or_default();
+			failed_attempts.sort_by_key(|attempt| attempt.0);
+
+			ExtraRequestInfo {
+				hash: request.0,
+				number: request.1,
+				mandatory: self.mandatory_requests.contains(request),
+				importing: self.importing_requests.contains(request),
+				requested_from,
+				failed_attempts,
+			}
+		}).collect::<Vec<_>>();
+		requests.sort_by_key(|info| info.number);
+		requests
+	}
+
+	/// Queue a request (without dispatching it). Mandatory requests are needed
+	/// to make finality progress and take precedence over optional ones.
+	///
+	/// When the maximum number of requests is reached the pending request with
+	/// the lowest priority is dropped, which may be the new one. Dropped mandatory
+	/// requests are reported through `take_unavailable`.
+	pub(crate) fn queue_request(&mut self, request: &ExtraRequest<B>, mandatory: bool) {
+		if self.requests.contains(request) {
+			// a pending request becoming mandatory moves ahead in the queue.
+			if mandatory && self.mandatory_requests.insert(*request) {
+				self.mandatory_changed = true;
+				let index = self.pending_requests.iter().position(|r| r == request);
+				if let Some(index) = index {
+					self.pending_requests.remove(index);
+					self.insert_pending(*request);
+				}
+			}
+			return;
+		}
+
+		let priority = (!mandatory, request.1);
+		if self.requests.len() >= self.max_requests {
+			let lowest = self.pending_requests.iter()
+				.enumerate()
+				.max_by_key(|(_, r)| self.priority(r))
+				.map(|(index, r)| (index, *r));
+
+			match lowest {
+				Some((index, lowest)) if self.priority(&lowest) > priority => {
+					trace!(target: "sync", "Too many {} requests, dropping request for {}", self.essence.name(), lowest.0);
+					self.pending_requests.remove(index);
+					if self.mandatory_requests.contains(&lowest) {
+						self.unavailable.push(lowest);
+					}
+					self.forget(&lowest);
+				},
+				_ => {
+					trace!(target: "sync", "Too many {} requests, dropping request for {}", self.essence.name(), request.0);
+					if mandatory {
+						self.unavailable.push(*request);
+					}
+					return;
+				},
+			}
+		}
+
+		self.requests.insert(*request);
+		if mandatory {
+			self.mandatory_requests.insert(*request);
+			self.mandatory_changed = true;
+		}
+		self.insert_pending(*request);
+	}
+
+	/// Inserts a request into the queue before the first pending request with
+	/// a lower priority.
+	fn insert_pending(&mut self, request: ExtraRequest<B>) {
+		let priority = self.priority(&request);
+		let index = self.pending_requests.iter()
+			.position(|r| self.priority(r) > priority)
+			.unwrap_or(self.pending_requests.len());
+		self.pending_requests.insert(index, request);
+	}
+
+	/// Retry any pending request if a peer disconnected.
+	pub(crate) fn peer_disconnected(&mut self, who: NodeIndex) {
+		if let Some((_, _, requests)) = self.peer_requests.remove(&who) {
+			self.requeue(requests);
+		}
+	}
+
+	/// Requeues the requests that peers failed to answer in time, so that they
+	/// can be dispatched to other peers, and penalizes those peers.
+	pub(crate) fn on_timeouts(&mut self, protocol: &mut Context<B>, metrics: &mut Metrics) {
+		let now = simulation::now();
+		let fields = self.essence.fields();
+		let mut timed_out = Vec::new();
+		for (who, (requested_at, first, _)) in self.peer_requests.iter_mut() {
+			// the timeout starts once the request is sent, not while it waits
+			// for the peer to answer its previous requests
+			let from = message::FromBlock::Hash(first.0);
+			if protocol.is_request_deferred(*who, &|request| request.fields == fields && request.from == from) {
+				*requested_at = now;
+			} else if now - *requested_at >= EXTRA_REQUEST_TIMEOUT {
+				timed_out.push(*who);
+			}
+		}
+		timed_out.sort();
+
+		for who in timed_out {
+			let (_, _, requests) = self.peer_requests.remove(&who)
+				.expect("timed out peers are collected from peer_requests above; qed");
+			trace!(target: "sync", "{} request to {} timed out", self.essence.name(), who);
+			// don't ask the same peer again before its backoff expires
+			let retry = requests.into_iter()
+				.filter(|request| self.note_failure(*request, who, now))
+				.collect();
+			self.requeue(retry);
+			report_peer(protocol, metrics, who, Severity::Timeout);
+		}
+	}
+
+	/// Processes the response for the batch previously requested from the given
+	/// peer. Returns the data to import for the blocks of the batch, and queues a
+	/// retry for every block whose data is missing from the response. Blocks past
+	/// the end of a truncated response are retried without counting as a failure.
+	pub(crate) fn on_response(
+		&mut self,
+		who: NodeIndex,
+		blocks: Vec<message::BlockData<B>>,
+	) -> Vec<(ExtraRequest<B>, Essence::Response)> {
+		// we assume that the request maps to the given response, this is
+		// currently enforced by the outer network protocol before passing on
+		// messages to chain sync.
+		let (first, requests) = match self.peer_requests.remove(&who) {
+			Some((_, first, requests)) => (first, requests),
+			None => return Vec::new(),
+		};
+
+		// the batch covers consecutive blocks from the first one requested, peers
+		// may stop early to limit the response size.
+		let answered_end = first.1 + As::sa(blocks.len() as u64);
+		let mut responses: HashMap<B::Hash, Essence::Response> = {
+			let essence = &self.essence;
+			blocks.into_iter()
+				.filter_map(|block| {
+					let hash = block.hash;
+					essence.extract(block).map(|response| (hash, response))
+				})
+				.collect()
+		};
+		let now = simulation::now();
+		let mut import = Vec::new();
+		let mut retry = Vec::new();
+		for request in requests {
+			match responses.remove(&request.0) {
+				Some(response) => {
+					self.importing_requests.insert(request);
+					import.push((request, response));
+				},
+				None if request.1 >= answered_end => {
+					trace!(target: "sync", "{} request for {} not answered by {} in a truncated response",
+						self.essence.name(), request.1, who);
+					retry.push(request);
+				},
+				None => if self.note_failure(request, who, now) {
+					retry.push(request);
+				},
+			}
+		}
+
+		self.requeue(retry);
+		import
+	}
+
+	/// Processes the result of importing the data the given peer provided for
+	/// a block. If the import failed the peer is penalized and the request is
+	/// retried, unless its attempt budget is exhausted.
+	pub(crate) fn on_import_result(
+		&mut self,
+		who: NodeIndex,
+		request: &ExtraRequest<B>,
+		success: bool,
+		protocol: &mut Context<B>,
+		metrics: &mut Metrics,
+	) {
+		// the request may have been collected while the data was being imported.
+		if !self.importing_requests.remove(request) {
+			return;
+		}
+
+		if success {
+			self.forget(request);
+			return;
+		}
+
+		report_peer(
+			protocol,
+			metrics,
+			who,
+			Severity::Bad(&format!("Invalid {} provided for #{}", self.essence.name(), request.0)),
+		);
+		if self.note_failure(*request, who, simulation::now()) {
+			self.pending_requests.push_front(*request);
+		}
+	}
+
+	/// Abandons the import of the data provided for a block without blaming
+	/// the peer that provided it, e.g. because the block itself was dropped.
+	/// The request is retried.
+	pub(crate) fn cancel_import(&mut self, request: &ExtraRequest<B>) {
+		if self.importing_requests.remove(request) {
+			self.pending_requests.push_front(*request);
+		}
+	}
+
+	/// Removes any optional requests for blocks at or below the given best
+	/// finalized, and any request for a block that isn't on the finalized chain
+	/// according to `is_on_finalized_chain`, as those can never be fulfilled.
+	/// Mandatory requests on the finalized chain are kept, e.g. justifications of
+	/// blocks enacting authority set changes are still needed once finalized.
+	pub(crate) fn collect_garbage<F>(&mut self, best_finalized: NumberFor<B>, is_on_finalized_chain: F)
+		where F: Fn(&ExtraRequest<B>) -> bool
+	{
+		let mandatory_requests = &self.mandatory_requests;
+		let collected: HashSet<_> = self.requests.iter()
+			.filter(|request| {
+				let needed = request.1 > best_finalized || mandatory_requests.contains(request);
+				!needed || !is_on_finalized_chain(request)
+			})
+			.cloned()
+			.collect();
+		if collected.is_empty() {
+			return;
+		}
+
+		for request in &collected {
+			self.forget(request);
+		}
+		let keep = |request: &ExtraRequest<B>| !collected.contains(request);
+		self.pending_requests.retain(|request| keep(request));
+		for (_, _, requests) in self.peer_requests.values_mut() {
+			requests.retain(|request| keep(request));
+		}
+		self.peer_requests.retain(|_, (_, _, requests)| !requests.is_empty());
+		self.importing_requests.retain(|request| keep(request));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{ExtraRequests, JustificationsEssence, MAX_EXTRAS_PER_REQUEST, max_batch_len, peer_preference};
+	use config::{RetryPolicy, Roles};
+	use protocol::{CURRENT_VERSION, MIN_VERSION};
+	use sync::{PeerCapabilities, PeerSync, PeerSyncState};
+	use message::{self, Features};
+	use std::time::Instant;
+	use runtime_primitives::testing::{Block as RawBlock, ExtrinsicWrapper};
+	use primitives::H256;
+
+	type Block = RawBlock<ExtrinsicWrapper<u64>>;
+
+	fn requests(max_requests: usize) -> ExtraRequests<Block, JustificationsEssence> {
+		ExtraRequests::new(JustificationsEssence, RetryPolicy::default(), max_requests)
+	}
+
+	#[test]
+	fn requests_are_queued_by_priority() {
+		let mut extra = requests(10);
+		let (a, b, c) = ((H256::random(), 3), (H256::random(), 1), (H256::random(), 5));
+
+		extra.queue_request(&a, false);
+		extra.queue_request(&b, false);
+		extra.queue_request(&c, true);
+
+		assert_eq!(extra.pending_requests.iter().cloned().collect::<Vec<_>>(), vec![c, b, a]);
+
+		// forcing an already pending request moves it ahead.
+		extra.queue_request(&a, true);
+		assert_eq!(extra.pending_requests.iter().cloned().collect::<Vec<_>>(), vec![a, c, b]);
+	}
+
+	#[test]
+	fn lowest_priority_requests_are_dropped_when_full() {
+		let mut extra = requests(2);
+		let (a, b, c, d) = (
+			(H256::random(), 1),
+			(H256::random(), 2),
+			(H256::random(), 3),
+			(H256::random(), 4),
+		);
+
+		extra.queue_request(&a, false);
+		extra.queue_request(&c, true);
+
+		// lower priority than anything pending: the new request is dropped.
+		extra.queue_request(&b, false);
+		assert!(!extra.requests.contains(&b));
+		assert_eq!(extra.pending_requests.iter().cloned().collect::<Vec<_>>(), vec![c, a]);
+
+		// a mandatory request evicts the optional one.
+		extra.queue_request(&d, true);
+		assert!(!extra.requests.contains(&a));
+		assert_eq!(extra.pending_requests.iter().cloned().collect::<Vec<_>>(), vec![c, d]);
+
+		// only dropped mandatory requests are reported.
+		assert!(extra.take_unavailable().is_empty());
+		extra.queue_request(&(H256::random(), 5), true);
+		assert_eq!(extra.take_unavailable().len(), 1);
+		assert_eq!(extra.pending_requests.len(), 2);
+	}
+
+	#[test]
+	fn mandatory_requests_are_not_garbage_collected() {
+		let mut extra = requests(10);
+		let (a, b, c) = ((H256::random(), 3), (H256::random(), 4), (H256::random(), 8));
+
+		extra.queue_request(&a, false);
+		extra.queue_request(&b, true);
+		extra.queue_request(&c, false);
+		extra.collect_garbage(5, |_| true);
+
+		assert!(!extra.requests.contains(&a));
+		assert_eq!(extra.pending_requests.iter().cloned().collect::<Vec<_>>(), vec![b, c]);
+	}
+
+	#[test]
+	fn requests_off_the_finalized_chain_are_garbage_collected() {
+		let mut extra = requests(10);
+		let (a, b, c, d) = ((H256::random(), 3), (H256::random(), 4), (H256::random(), 8), (H256::random(), 9));
+
+		extra.queue_request(&a, true);
+		extra.queue_request(&b, true);
+		extra.queue_request(&c, false);
+		extra.queue_request(&d, false);
+		assert!(extra.take_mandatory_changes().is_some());
+
+		// `b` and `d` are on forks abandoned by the finalized chain.
+		extra.collect_garbage(5, |request| request != &b && request != &d);
+
+		assert_eq!(extra.pending_requests.iter().cloned().collect::<Vec<_>>(), vec![a, c]);
+		assert_eq!(extra.requests.len(), 2);
+		assert_eq!(extra.take_mandatory_changes(), Some(vec![a]));
+	}
+
+	#[test]
+	fn truncated_responses_are_not_failures() {
+		let mut extra = requests(10);
+		let (a, b, c) = ((H256::random(), 1), (H256::random(), 2), (H256::random(), 3));
+		for request in &[a, b, c] {
+			extra.queue_request(request, false);
+		}
+		extra.pending_requests.clear();
+		extra.peer_requests.insert(0, (Instant::now(), a, vec![a, b, c]));
+
+		let block = |hash, justification| message::generic::BlockData {
+			hash,
+			header: None,
+			body: None,
+			receipt: None,
+			message_queue: None,
+			justification,
+		};
+		// the response stops after `b`, which the peer doesn't have a justification for.
+		let import = extra.on_response(0, vec![block(a.0, Some(vec![1])), block(b.0, None)]);
+		assert_eq!(import, vec![(a, vec![1])]);
+		assert!(extra.previous_requests.contains_key(&b));
+		assert!(!extra.previous_requests.contains_key(&c));
+		assert!(extra.pending_requests.contains(&b));
+		assert!(extra.pending_requests.contains(&c));
+	}
+
+	#[test]
+	fn responses_are_matched_against_the_batch_sent() {
+		let mut extra = requests(10);
+		let (a, b, c) = ((H256::random(), 1), (H256::random(), 2), (H256::random(), 3));
+		for request in &[a, b, c] {
+			extra.queue_request(request, false);
+		}
+		extra.pending_requests.clear();
+		extra.peer_requests.insert(0, (Instant::now(), a, vec![a, b, c]));
+
+		// `a` is finalized while the request is in flight.
+		extra.collect_garbage(1, |_| true);
+		assert_eq!(extra.peer_request(0), Some(a));
+
+		// the response only covers `a`, the blocks past it weren't answered.
+		let import = extra.on_response(0, vec![message::generic::BlockData {
+			hash: a.0,
+			header: None,
+			body: None,
+			receipt: None,
+			message_queue: None,
+			justification: Some(vec![1]),
+		}]);
+		assert!(import.is_empty());
+		assert!(!extra.previous_requests.contains_key(&b));
+		assert!(!extra.previous_requests.contains_key(&c));
+		assert!(extra.pending_requests.contains(&b));
+		assert!(extra.pending_requests.contains(&c));
+	}
+
+	#[test]
+	fn batches_depend_on_peer_capabilities() {
+		let peer = |version, features| PeerSync::<Block> {
+			common_number: 0,
+			best_hash: H256::random(),
+			best_number: 100,
+			state: PeerSyncState::Available,
+			recently_announced: Default::default(),
+			finalized_number: 0,
+			latency: None,
+			roles: Roles::FULL,
+			capabilities: PeerCapabilities::negotiate(version, features),
+		};
+
+		assert_eq!(max_batch_len(&peer(CURRENT_VERSION, Features::BATCHED_JUSTIFICATIONS)), MAX_EXTRAS_PER_REQUEST);
+		assert!(max_batch_len(&peer(MIN_VERSION, Features::BATCHED_JUSTIFICATIONS)) > 1);
+		assert_eq!(max_batch_len(&peer(CURRENT_VERSION, Features::empty())), 1);
+		assert_eq!(max_batch_len(&peer(MIN_VERSION, Features::empty())), 1);
+	}
+
+	#[test]
+	fn authorities_and_full_nodes_are_preferred() {
+		assert!(peer_preference(Roles::AUTHORITY) < peer_preference(Roles::FULL));
+		assert!(peer_preference(Roles::FULL | Roles::AUTHORITY) < peer_preference(Roles::FULL));
+		assert!(peer_preference(Roles::FULL) < peer_preference(Roles::LIGHT));
+		assert_eq!(peer_preference(Roles::LIGHT), peer_preference(Roles::NONE));
+	}
+
+	#[test]
+	fn requests_info_reports_tracked_requests() {
+		let mut extra = requests(10);
+		let (a, b) = ((H256::random(), 7), (H256::random(), 2));
+
+		extra.queue_request(&a, true);
+		extra.queue_request(&b, false);
+
+		let info = extra.requests_info();
+		assert_eq!(info.iter().map(|i| (i.hash, i.number, i.mandatory)).collect::<Vec<_>>(), vec![
+			(b.0, b.1, false),
+			(a.0, a.1, true),
+		]);
+		assert!(info.iter().all(|i| i.requested_from.is_none() && i.failed_attempts.is_empty()));
+	}
+}
@@ -15,14 +15,24 @@
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
 use parking_lot::Mutex;
-use network_libp2p::{Service, Severity, NodeIndex, PeerId, ProtocolId};
+use network_libp2p::{Service, Severity, ReputationChange, NodeIndex, PeerId, ProtocolId};
 use std::sync::Arc;
 
 /// IO interface for the syncing handler.
 /// Provides peer connection management and an interface to the blockchain client.
 pub trait SyncIo {
 	/// Report a peer for misbehaviour.
-	fn report_peer(&mut self, who: NodeIndex, reason: Severity);
+	fn report_peer(&mut self, who: NodeIndex, reason: Severity) {
+		self.change_reputation(who, reason.into())
+	}
+	/// Raise or lower the reputation of a peer.
+	fn change_reputation(&mut self, who: NodeIndex, change: ReputationChange);
+	/// Disconnect a peer without changing its reputation.
+	fn disconnect_peer(&mut self, who: NodeIndex);
+	/// Returns the reputation of a peer, or `None` if it isn't known.
+	fn peer_reputation(&self, _who: NodeIndex) -> Option<i32> {
+		None
+	}
 	/// Send a packet to a peer.
 	fn send(&mut self, who: NodeIndex, data: Vec<u8>);
 	/// Returns peer identifier string
@@ -31,6 +41,10 @@ pub trait SyncIo {
 	}
 	/// Returns information on p2p session
 	fn peer_id(&self, who: NodeIndex) -> Option<PeerId>;
+	/// Returns true if the peer is one of the reserved peers
+	fn is_reserved_peer(&self, _who: NodeIndex) -> bool {
+		false
+	}
 }
 
 /// Wraps the network service.
@@ -52,11 +66,24 @@ impl<'s> NetSyncIo<'s> {
 impl<'s> SyncIo for NetSyncIo<'s> {
 	fn report_peer(&mut self, who: NodeIndex, reason: Severity) {
 		info!("Purposefully dropping {} ; reason: {:?}", who, reason);
-		match reason {
-			Severity::Bad(_) => self.network.lock().ban_node(who),
-			Severity::Useless(_) => self.network.lock().drop_node(who),
-			Severity::Timeout => self.network.lock().drop_node(who),
-		}
+		let mut network = self.network.lock();
+		// the reputation decides whether the peer is banned, but it's dropped either way.
+		network.report_peer(who, reason.into());
+		network.drop_node(who)
+	}
+
+	fn change_reputation(&mut self, who: NodeIndex, change: ReputationChange) {
+		trace!(target: "sync", "Changing reputation of {} by {} ; reason: {}", who, change.value, change.reason);
+		self.network.lock().report_peer(who, change)
+	}
+
+	fn disconnect_peer(&mut self, who: NodeIndex) {
+		trace!(target: "sync", "Disconnecting {}", who);
+		self.network.lock().drop_node(who)
+	}
+
+	fn peer_reputation(&self, who: NodeIndex) -> Option<i32> {
+		self.network.lock().peer_reputation(who)
 	}
 
 	fn send(&mut self, who: NodeIndex, data: Vec<u8>) {
@@ -68,6 +95,10 @@ impl<'s> SyncIo for NetSyncIo<'s> {
 		net.peer_id_of_node(who).cloned()
 	}
 
+	fn is_reserved_peer(&self, who: NodeIndex) -> bool {
+		self.network.lock().is_reserved_node(who)
+	}
+
 	fn peer_debug_info(&self, who: NodeIndex) -> String {
 		let net = self.network.lock();
 		if let (Some(peer_id), Some(addr)) = (net.peer_id_of_node(who), net.node_endpoint(who)) {
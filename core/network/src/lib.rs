@@ -31,6 +31,7 @@ extern crate parity_codec as codec;
 extern crate futures;
 extern crate rustc_hex;
 extern crate rand;
+extern crate snap;
 extern crate tokio;
 #[macro_use] extern crate log;
 #[macro_use] extern crate bitflags;
@@ -48,31 +49,42 @@ extern crate substrate_test_client as test_client;
 
 mod service;
 mod sync;
+mod extra_requests;
 #[macro_use]
 mod protocol;
 mod io;
 mod chain;
 mod blocks;
+mod block_requests;
 mod on_demand;
+mod outbound;
+mod lru_set;
 pub mod config;
 pub mod consensus_gossip;
 pub mod error;
 pub mod message;
+pub mod notifications;
+pub mod simulation;
 pub mod specialization;
 
 #[cfg(any(test, feature = "test-helpers"))]
 pub mod test;
 
 pub use chain::Client as ClientHandle;
-pub use service::{Service, FetchFuture, TransactionPool, ManageNetwork, SyncProvider, ExHashT};
-pub use protocol::{ProtocolStatus, PeerInfo, Context};
-pub use sync::{Status as SyncStatus, SyncState};
+pub use service::{Service, FetchFuture, BlockFetchFuture, TransactionPool, ManageNetwork, SyncProvider, ExHashT};
+pub use protocol::{ProtocolStatus, PeerInfo, Context, MessageCounters, MessageStats, TrafficStats, PropagationMetrics};
+pub use consensus_gossip::TopicMetrics;
+pub use sync::{Metrics as SyncMetrics, Status as SyncStatus, SyncState};
+pub use extra_requests::ExtraRequestInfo;
+pub use blocks::DownloadStats;
 pub use network_libp2p::{
-    NodeIndex, ProtocolId, Severity, Protocol, Multiaddr,
-    obtain_private_key, multiaddr, PeerId, PublicKey
+    NodeIndex, ProtocolId, Severity, Protocol, Multiaddr, ReputationChange,
+    BAN_THRESHOLD, DISCONNECT_THRESHOLD, obtain_private_key, multiaddr, parse_str_addr, PeerId,
+    PublicKey, ConnectionMetrics, IpFilterList, IpNetwork
 };
 pub use message::{generic as generic_message, RequestId, Status as StatusMessage};
 pub use error::Error;
 pub use on_demand::{OnDemand, OnDemandService, RemoteResponse};
+pub use notifications::{NotificationEvent, ProtocolName};
 #[doc(hidden)]
 pub use runtime_primitives::traits::Block as BlockT;
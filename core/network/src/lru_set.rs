@@ -0,0 +1,78 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Set of bounded size, forgetting the least recently inserted items first.
+
+use linked_hash_map::LinkedHashMap;
+use std::hash::Hash;
+
+/// Set holding at most `capacity` items. Inserting an item already present makes it the most
+/// recent one.
+#[derive(Debug)]
+pub(crate) struct LruHashSet<T: Hash + Eq> {
+	items: LinkedHashMap<T, ()>,
+	capacity: usize,
+}
+
+impl<T: Hash + Eq> LruHashSet<T> {
+	/// Creates an empty set holding at most `capacity` items.
+	pub(crate) fn new(capacity: usize) -> Self {
+		LruHashSet {
+			items: LinkedHashMap::new(),
+			capacity,
+		}
+	}
+
+	/// Inserts an item, forgetting the least recent one if the set is full. Returns true if
+	/// the item wasn't present.
+	pub(crate) fn insert(&mut self, item: T) -> bool {
+		if self.items.get_refresh(&item).is_some() {
+			return false;
+		}
+		self.items.insert(item, ());
+		while self.items.len() > self.capacity {
+			self.items.pop_front();
+		}
+		true
+	}
+
+	/// Removes an item. Returns true if it was present.
+	pub(crate) fn remove(&mut self, item: &T) -> bool {
+		self.items.remove(item).is_some()
+	}
+
+	/// Returns true if the item is present.
+	pub(crate) fn contains(&self, item: &T) -> bool {
+		self.items.contains_key(item)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn forgets_least_recent_items() {
+		let mut set = LruHashSet::new(2);
+		assert!(set.insert(1));
+		assert!(set.insert(2));
+		assert!(!set.insert(1));
+		assert!(set.insert(3));
+		assert!(set.contains(&1));
+		assert!(!set.contains(&2));
+		assert!(set.contains(&3));
+	}
+}
@@ -17,12 +17,13 @@
 //! Network packet message types. These get serialized and put into the lower level protocol payload.
 
 use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
+use primitives::ed25519;
 use codec::{Encode, Decode, Input, Output};
 pub use self::generic::{
 	BlockAnnounce, RemoteCallRequest, RemoteReadRequest,
 	RemoteHeaderRequest, RemoteHeaderResponse,
 	RemoteChangesRequest, RemoteChangesResponse,
-	FromBlock
+	RemoteBodyRequest, FromBlock
 };
 
 /// A unique ID of a request.
@@ -42,6 +43,12 @@ pub type Status<B> = generic::Status<
 	<<B as BlockT>::Header as HeaderT>::Number,
 >;
 
+/// Type alias for using the finalized block announcement type using block type parameters.
+pub type FinalizedAnnounce<B> = generic::FinalizedAnnounce<
+	<B as BlockT>::Hash,
+	<<B as BlockT>::Header as HeaderT>::Number,
+>;
+
 /// Type alias for using the block request type using block type parameters.
 pub type BlockRequest<B> = generic::BlockRequest<
 	<B as BlockT>::Hash,
@@ -62,6 +69,11 @@ pub type BlockResponse<B> = generic::BlockResponse<
 	<B as BlockT>::Extrinsic,
 >;
 
+/// Type alias for using the RemoteBodyResponse type using block type parameters.
+pub type RemoteBodyResponse<B> = generic::RemoteBodyResponse<
+	<B as BlockT>::Extrinsic,
+>;
+
 /// A set of transactions.
 pub type Transactions<E> = Vec<E>;
 
@@ -94,6 +106,41 @@ impl Decode for BlockAttributes {
 	}
 }
 
+bitflags! {
+	/// Optional protocol features, exchanged in the `Status` message. A feature is only
+	/// used with a peer when both sides advertise it, so features can be rolled out
+	/// without bumping the protocol version.
+	pub struct Features: u32 {
+		/// Block responses may be sent compressed.
+		const COMPRESSED_RESPONSES = 0b00000001;
+		/// Justifications of consecutive blocks can be requested in a single packet.
+		const BATCHED_JUSTIFICATIONS = 0b00000010;
+		/// Notifications protocols can be opened.
+		const NOTIFICATIONS = 0b00001000;
+		/// Finalized blocks are announced.
+		const FINALIZED_ANNOUNCEMENTS = 0b00010000;
+		/// Consensus messages signed by authorities are gossiped.
+		const AUTHORITY_MESSAGES = 0b00100000;
+		/// Pings are answered, to measure latency and detect dead connections.
+		const PING = 0b01000000;
+		/// Consensus messages of the engines registering a gossip validator are gossiped.
+		const GOSSIP_ENGINES = 0b10000000;
+	}
+}
+
+impl Encode for Features {
+	fn encode_to<T: Output>(&self, dest: &mut T) {
+		self.bits().encode_to(dest)
+	}
+}
+
+impl Decode for Features {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		// bits of features unknown to us are ignored.
+		u32::decode(input).map(Self::from_bits_truncate)
+	}
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Encode, Decode)]
 /// Block enumeration direction.
 pub enum Direction {
@@ -112,6 +159,80 @@ pub struct RemoteCallResponse {
 	pub proof: Vec<Vec<u8>>,
 }
 
+/// Request to stop serving a request that is no longer needed.
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct CancelRequest {
+	/// Id of the request to cancel.
+	pub id: RequestId,
+}
+
+/// Block response compressed with snappy, sent to peers supporting
+/// `Features::COMPRESSED_RESPONSES` instead of a `BlockResponse`.
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct CompressedBlockResponse {
+	/// Id of the request this response was made for.
+	pub id: RequestId,
+	/// Compressed encoding of the `BlockResponse`.
+	pub data: Vec<u8>,
+}
+
+/// Opens a notifications protocol with a peer supporting `Features::NOTIFICATIONS`.
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct NotificationsOpen {
+	/// Name of the protocol.
+	pub protocol: Vec<u8>,
+	/// Protocol-specific handshake.
+	pub handshake: Vec<u8>,
+}
+
+/// Identifier of a consensus engine, tagging its gossip messages so that the
+/// validator it registered can be found.
+pub type ConsensusEngineId = [u8; 4];
+
+/// Consensus message signed by an authority with its session key. Peers check the
+/// signature and that the signer is a current authority before gossiping it further.
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct SignedConsensusMessage {
+	/// The message.
+	pub message: Vec<u8>,
+	/// Session key of the authority.
+	pub signer: ed25519::Public,
+	/// Signature of the topic and message.
+	pub signature: ed25519::Signature,
+}
+
+impl SignedConsensusMessage {
+	/// Sign a message of the given topic.
+	pub fn sign<Hash: Encode>(topic: &Hash, message: Vec<u8>, key: &ed25519::Pair) -> Self {
+		let signature = key.sign(&Self::payload(topic, &message));
+		SignedConsensusMessage {
+			message,
+			signer: key.public(),
+			signature,
+		}
+	}
+
+	/// Check the signature of the message of the given topic.
+	pub fn verify<Hash: Encode>(&self, topic: &Hash) -> bool {
+		ed25519::verify_strong(&self.signature, &Self::payload(topic, &self.message), &self.signer)
+	}
+
+	fn payload<Hash: Encode>(topic: &Hash, message: &[u8]) -> Vec<u8> {
+		let mut payload = topic.encode();
+		payload.extend_from_slice(message);
+		payload
+	}
+}
+
+/// Notification of a protocol opened by both sides.
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct Notification {
+	/// Name of the protocol.
+	pub protocol: Vec<u8>,
+	/// Protocol-specific payload.
+	pub data: Vec<u8>,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
 /// Remote read response.
 pub struct RemoteReadResponse {
@@ -123,11 +244,13 @@ pub struct RemoteReadResponse {
 
 /// Generic types.
 pub mod generic {
+	use codec::{Decode, Input};
 	use runtime_primitives::Justification;
 	use config::Roles;
 	use super::{
 		BlockAttributes, RemoteCallResponse, RemoteReadResponse,
-		RequestId, Transactions, Direction
+		RequestId, Transactions, Direction, CancelRequest, Features,
+		CompressedBlockResponse, NotificationsOpen, Notification, ConsensusEngineId,
 	};
 	/// Consensus is opaque to us
 	pub type ConsensusMessage = Vec<u8>;
@@ -189,13 +312,69 @@ pub mod generic {
 		RemoteChangesRequest(RemoteChangesRequest<Hash>),
 		/// Remote changes reponse.
 		RemoteChangesResponse(RemoteChangesResponse<Number, Hash>),
+		/// Cancel an in-flight request.
+		CancelRequest(CancelRequest),
+		/// Remote block body request.
+		RemoteBodyRequest(RemoteBodyRequest<Hash>),
+		/// Remote block body response.
+		RemoteBodyResponse(RemoteBodyResponse<Extrinsic>),
+		/// Compressed block response.
+		CompressedBlockResponse(CompressedBlockResponse),
+		/// Open a notifications protocol.
+		NotificationsOpen(NotificationsOpen),
+		/// Notifications protocol message.
+		Notification(Notification),
+		/// Finalized block announce.
+		FinalizedAnnounce(FinalizedAnnounce<Hash, Number>),
+		/// Consensus message signed by an authority.
+		AuthorityConsensus(Hash, ConsensusMessage, bool), // topic, encoded SignedConsensusMessage, broadcast
+		/// Ping, to be answered with a `Pong` carrying the same nonce.
+		Ping(u64),
+		/// Answer to a `Ping`.
+		Pong(u64),
+		/// Consensus message of an engine, checked by its gossip validator.
+		EngineConsensus(ConsensusEngineId, Hash, ConsensusMessage), // engine, topic, opaque Vec<u8>
 		/// Chain-specific message
 		#[codec(index = "255")]
 		ChainSpecific(Vec<u8>),
 	}
 
+	impl<Header, Hash, Number, Extrinsic> Message<Header, Hash, Number, Extrinsic> {
+		/// Name of the message variant, used to break down traffic statistics.
+		pub fn kind(&self) -> &'static str {
+			match *self {
+				Message::Status(_) => "Status",
+				Message::BlockRequest(_) => "BlockRequest",
+				Message::BlockResponse(_) => "BlockResponse",
+				Message::BlockAnnounce(_) => "BlockAnnounce",
+				Message::Transactions(_) => "Transactions",
+				Message::Consensus(..) => "Consensus",
+				Message::RemoteCallRequest(_) => "RemoteCallRequest",
+				Message::RemoteCallResponse(_) => "RemoteCallResponse",
+				Message::RemoteReadRequest(_) => "RemoteReadRequest",
+				Message::RemoteReadResponse(_) => "RemoteReadResponse",
+				Message::RemoteHeaderRequest(_) => "RemoteHeaderRequest",
+				Message::RemoteHeaderResponse(_) => "RemoteHeaderResponse",
+				Message::RemoteChangesRequest(_) => "RemoteChangesRequest",
+				Message::RemoteChangesResponse(_) => "RemoteChangesResponse",
+				Message::CancelRequest(_) => "CancelRequest",
+				Message::RemoteBodyRequest(_) => "RemoteBodyRequest",
+				Message::RemoteBodyResponse(_) => "RemoteBodyResponse",
+				Message::CompressedBlockResponse(_) => "CompressedBlockResponse",
+				Message::NotificationsOpen(_) => "NotificationsOpen",
+				Message::Notification(_) => "Notification",
+				Message::FinalizedAnnounce(_) => "FinalizedAnnounce",
+				Message::AuthorityConsensus(..) => "AuthorityConsensus",
+				Message::Ping(_) => "Ping",
+				Message::Pong(_) => "Pong",
+				Message::EngineConsensus(..) => "EngineConsensus",
+				Message::ChainSpecific(_) => "ChainSpecific",
+			}
+		}
+	}
+
 	/// Status sent on connection.
-	#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+	#[derive(Debug, PartialEq, Eq, Clone, Encode)]
 	pub struct Status<Hash, Number> {
 		/// Protocol version.
 		pub version: u32,
@@ -209,6 +388,30 @@ pub mod generic {
 		pub genesis_hash: Hash,
 		/// Chain-specific status.
 		pub chain_status: Vec<u8>,
+		/// Supported optional features.
+		pub features: Features,
+		/// Hard fork of the chain the peer follows, if any.
+		pub fork_id: Option<Vec<u8>>,
+		/// Name and version of the peer's implementation, if it tells.
+		pub client_version: Option<Vec<u8>>,
+	}
+
+	// fields are only ever appended to the status, older peers don't send the
+	// ones added after them.
+	impl<Hash: Decode, Number: Decode> Decode for Status<Hash, Number> {
+		fn decode<I: Input>(input: &mut I) -> Option<Self> {
+			Some(Status {
+				version: Decode::decode(input)?,
+				roles: Decode::decode(input)?,
+				best_number: Decode::decode(input)?,
+				best_hash: Decode::decode(input)?,
+				genesis_hash: Decode::decode(input)?,
+				chain_status: Decode::decode(input)?,
+				features: Decode::decode(input).unwrap_or_else(Features::empty),
+				fork_id: Decode::decode(input).unwrap_or(None),
+				client_version: Decode::decode(input).unwrap_or(None),
+			})
+		}
 	}
 
 	/// Request block data from a peer.
@@ -244,6 +447,15 @@ pub mod generic {
 		pub header: H,
 	}
 
+	/// Announce a block finalized by the sender.
+	#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+	pub struct FinalizedAnnounce<Hash, Number> {
+		/// Hash of the finalized block.
+		pub hash: Hash,
+		/// Number of the finalized block.
+		pub number: Number,
+	}
+
 	#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
 	/// Remote call request.
 	pub struct RemoteCallRequest<H> {
@@ -321,4 +533,92 @@ pub mod generic {
 		/// Missing changes tries roots proof.
 		pub roots_proof: Vec<Vec<u8>>,
 	}
+
+	#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+	/// Remote block body request.
+	pub struct RemoteBodyRequest<H> {
+		/// Unique request id.
+		pub id: RequestId,
+		/// Hash of the block to request the body for.
+		pub block: H,
+	}
+
+	#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+	/// Remote block body response.
+	pub struct RemoteBodyResponse<Extrinsic> {
+		/// Id of a request this response was made for.
+		pub id: RequestId,
+		/// Block body. None if the block is unknown.
+		pub body: Option<Vec<Extrinsic>>,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use codec::{Encode, Decode};
+	use config::Roles;
+	use primitives::ed25519;
+	use super::{Features, SignedConsensusMessage};
+	use super::generic::Status;
+
+	#[derive(Encode)]
+	struct LegacyStatus {
+		version: u32,
+		roles: Roles,
+		best_number: u64,
+		best_hash: u64,
+		genesis_hash: u64,
+		chain_status: Vec<u8>,
+	}
+
+	#[test]
+	fn status_without_features_is_decoded() {
+		let legacy = LegacyStatus {
+			version: 3,
+			roles: Roles::FULL,
+			best_number: 10,
+			best_hash: 1,
+			genesis_hash: 2,
+			chain_status: vec![4, 2],
+		};
+		let status: Status<u64, u64> = Decode::decode(&mut &legacy.encode()[..]).unwrap();
+		assert_eq!(status.chain_status, vec![4, 2]);
+		assert_eq!(status.features, Features::empty());
+		assert_eq!(status.fork_id, None);
+		assert_eq!(status.client_version, None);
+	}
+
+	#[test]
+	fn unknown_features_are_ignored() {
+		let status = Status::<u64, u64> {
+			version: 3,
+			roles: Roles::FULL,
+			best_number: 10,
+			best_hash: 1,
+			genesis_hash: 2,
+			chain_status: Vec::new(),
+			features: Features::BATCHED_JUSTIFICATIONS,
+			fork_id: None,
+			client_version: None,
+		};
+		let mut encoded = status.encode();
+		// highest byte of the features, followed by the fork id and client version.
+		let len = encoded.len();
+		encoded[len - 3] = 0x80;
+		let decoded: Status<u64, u64> = Decode::decode(&mut &encoded[..]).unwrap();
+		assert_eq!(decoded.features, Features::BATCHED_JUSTIFICATIONS);
+	}
+
+	#[test]
+	fn signed_consensus_message_is_bound_to_its_topic() {
+		let key = ed25519::Pair::from_seed(&[7; 32]);
+		let signed = SignedConsensusMessage::sign(&1u64, vec![1, 2, 3], &key);
+		assert_eq!(signed.signer, key.public());
+		assert!(signed.verify(&1u64));
+		assert!(!signed.verify(&2u64));
+
+		let mut forged = signed.clone();
+		forged.message = vec![1, 2, 4];
+		assert!(!forged.verify(&1u64));
+	}
 }
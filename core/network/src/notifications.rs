@@ -0,0 +1,266 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Notifications protocols registered by higher-level components, multiplexed
+//! over the substrate protocol.
+//!
+//! Both sides open a protocol by sending its name and a handshake. Once a peer
+//! opened a protocol we registered, notifications can flow in both directions
+//! until the peer disconnects.
+
+use std::collections::{HashMap, HashSet};
+use futures::sync::mpsc;
+use network_libp2p::NodeIndex;
+use runtime_primitives::traits::Block as BlockT;
+use message::{NotificationsOpen, Notification, generic::Message};
+use protocol::Context;
+use error;
+
+/// Maximum number of protocols a peer may open, registered by us or not.
+const MAX_PEER_PROTOCOLS: usize = 32;
+
+/// Name of a notifications protocol.
+pub type ProtocolName = Vec<u8>;
+
+/// Event of a notifications protocol, reported to the component that registered it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum NotificationEvent {
+	/// A peer opened the protocol.
+	Opened {
+		/// The peer.
+		who: NodeIndex,
+		/// Handshake sent by the peer.
+		handshake: Vec<u8>,
+	},
+	/// A peer sent a notification.
+	Notification {
+		/// The peer.
+		who: NodeIndex,
+		/// Payload of the notification.
+		data: Vec<u8>,
+	},
+	/// A peer that opened the protocol disconnected.
+	Closed {
+		/// The peer.
+		who: NodeIndex,
+	},
+}
+
+struct Registration {
+	handshake: Vec<u8>,
+	sink: mpsc::UnboundedSender<NotificationEvent>,
+}
+
+/// Notifications protocols handler.
+pub struct Notifications {
+	protocols: HashMap<ProtocolName, Registration>,
+	// Connected peers supporting notifications, with the handshakes of the protocols they opened.
+	peers: HashMap<NodeIndex, HashMap<ProtocolName, Vec<u8>>>,
+}
+
+impl Notifications {
+	/// Create a new instance.
+	pub fn new() -> Self {
+		Notifications {
+			protocols: HashMap::new(),
+			peers: HashMap::new(),
+		}
+	}
+
+	/// Register a protocol and open it with all peers. Peers that already opened
+	/// it are reported on the returned stream straight away.
+	pub fn register<B: BlockT>(
+		&mut self,
+		protocol: &mut Context<B>,
+		name: ProtocolName,
+		handshake: Vec<u8>,
+	) -> error::Result<mpsc::UnboundedReceiver<NotificationEvent>> {
+		// the name is taken over once the stream of the previous registration is dropped.
+		if self.protocols.get(&name).map_or(false, |r| !r.sink.is_closed()) {
+			return Err(error::ErrorKind::DuplicateNotificationsProtocol(name).into());
+		}
+
+		let (sink, stream) = mpsc::unbounded();
+		for (who, opened) in self.peers.iter() {
+			if let Some(remote_handshake) = opened.get(&name) {
+				let _ = sink.unbounded_send(NotificationEvent::Opened { who: *who, handshake: remote_handshake.clone() });
+			}
+			protocol.send_message(*who, open_message(&name, &handshake));
+		}
+		trace!(target: "sync", "Registered notifications protocol {}", String::from_utf8_lossy(&name));
+		self.protocols.insert(name, Registration { handshake, sink });
+		Ok(stream)
+	}
+
+	/// Send a notification to a peer. Returns `false` if the protocol isn't open with the peer.
+	pub fn send<B: BlockT>(&mut self, protocol: &mut Context<B>, who: NodeIndex, name: &[u8], data: Vec<u8>) -> bool {
+		if !self.protocols.contains_key(name) || !self.peers.get(&who).map_or(false, |opened| opened.contains_key(name)) {
+			return false;
+		}
+		protocol.send_message(who, Message::Notification(Notification { protocol: name.to_vec(), data }));
+		true
+	}
+
+	/// Handle new connected peer supporting notifications.
+	pub fn new_peer<B: BlockT>(&mut self, protocol: &mut Context<B>, who: NodeIndex) {
+		for (name, registration) in self.protocols.iter() {
+			protocol.send_message(who, open_message(name, &registration.handshake));
+		}
+		self.peers.insert(who, HashMap::new());
+	}
+
+	/// Call when a peer has been disconnected.
+	pub fn peer_disconnected(&mut self, who: NodeIndex) {
+		let opened = match self.peers.remove(&who) {
+			Some(opened) => opened,
+			None => return,
+		};
+		for name in opened.keys() {
+			self.notify(name, NotificationEvent::Closed { who });
+		}
+	}
+
+	/// Handle a protocol opened by a peer.
+	pub fn on_open(&mut self, who: NodeIndex, open: NotificationsOpen) {
+		{
+			let opened = match self.peers.get_mut(&who) {
+				Some(opened) => opened,
+				None => {
+					trace!(target: "sync", "Ignoring notifications protocol opened by {}", who);
+					return;
+				},
+			};
+			if opened.contains_key(&open.protocol) || opened.len() >= MAX_PEER_PROTOCOLS {
+				trace!(target: "sync", "Ignoring notifications protocol {} opened by {}",
+					String::from_utf8_lossy(&open.protocol), who);
+				return;
+			}
+			opened.insert(open.protocol.clone(), open.handshake.clone());
+		}
+		self.notify(&open.protocol, NotificationEvent::Opened { who, handshake: open.handshake });
+	}
+
+	/// Handle a notification sent by a peer.
+	pub fn on_notification(&mut self, who: NodeIndex, notification: Notification) {
+		if !self.peers.get(&who).map_or(false, |opened| opened.contains_key(&notification.protocol)) {
+			trace!(target: "sync", "Ignoring notification of a protocol {} didn't open", who);
+			return;
+		}
+		self.notify(&notification.protocol, NotificationEvent::Notification { who, data: notification.data });
+	}
+
+	/// Closes all notification streams.
+	pub fn abort(&mut self) {
+		self.protocols.clear();
+		self.peers.clear();
+	}
+
+	fn notify(&mut self, name: &[u8], event: NotificationEvent) {
+		let closed = match self.protocols.get(name) {
+			Some(registration) => registration.sink.unbounded_send(event).is_err(),
+			None => return,
+		};
+		if closed {
+			trace!(target: "sync", "Unregistering notifications protocol {}", String::from_utf8_lossy(name));
+			self.protocols.remove(name);
+		}
+	}
+}
+
+fn open_message<B: BlockT>(name: &[u8], handshake: &[u8]) -> ::message::Message<B> {
+	Message::NotificationsOpen(NotificationsOpen { protocol: name.to_vec(), handshake: handshake.to_vec() })
+}
+
+#[cfg(test)]
+mod tests {
+	use futures::{Future, Stream};
+	use network_libp2p::{NodeIndex, Severity, ReputationChange};
+	use message::{self, generic::Message, NotificationsOpen, Notification};
+	use protocol::{Context, PeerInfo};
+	use test_client::{self, runtime::Block};
+	use super::*;
+
+	type TestClient = ::client::Client<test_client::Backend, test_client::Executor, Block, test_client::runtime::RuntimeApi>;
+
+	struct TestContext {
+		client: TestClient,
+		sent: Vec<(NodeIndex, message::Message<Block>)>,
+	}
+
+	impl TestContext {
+		fn new() -> Self {
+			TestContext { client: test_client::new(), sent: Vec::new() }
+		}
+	}
+
+	impl Context<Block> for TestContext {
+		fn client(&self) -> &::chain::Client<Block> {
+			&self.client
+		}
+
+		fn report_peer(&mut self, _who: NodeIndex, _reason: Severity) {}
+
+		fn change_reputation(&mut self, _who: NodeIndex, _change: ReputationChange) {}
+
+		fn peer_info(&self, _peer: NodeIndex) -> Option<PeerInfo<Block>> {
+			None
+		}
+
+		fn send_message(&mut self, who: NodeIndex, data: message::Message<Block>) {
+			self.sent.push((who, data));
+		}
+
+		fn cancel_requests(&mut self, _who: NodeIndex, _filter: &Fn(&message::BlockRequest<Block>) -> bool) {}
+
+		fn is_request_deferred(&self, _who: NodeIndex, _filter: &Fn(&message::BlockRequest<Block>) -> bool) -> bool {
+			false
+		}
+	}
+
+	fn open(name: &[u8], handshake: &[u8]) -> NotificationsOpen {
+		NotificationsOpen { protocol: name.to_vec(), handshake: handshake.to_vec() }
+	}
+
+	#[test]
+	fn notifications_are_routed_to_the_registered_protocol() {
+		let mut context = TestContext::new();
+		let mut notifications = Notifications::new();
+		notifications.new_peer(&mut context, 1);
+		notifications.new_peer(&mut context, 2);
+		notifications.on_open(1, open(b"fin", b"hello"));
+
+		let events = notifications.register(&mut context, b"fin".to_vec(), b"hi".to_vec()).unwrap();
+		assert!(notifications.register(&mut context, b"fin".to_vec(), Vec::new()).is_err());
+		assert_eq!(context.sent.len(), 2);
+		assert!(context.sent.iter().all(|&(_, ref m)| *m == Message::NotificationsOpen(open(b"fin", b"hi"))));
+
+		assert!(notifications.send(&mut context, 1, b"fin", vec![1]));
+		assert!(!notifications.send(&mut context, 2, b"fin", vec![1]));
+		assert!(!notifications.send(&mut context, 1, b"other", vec![1]));
+
+		notifications.on_notification(1, Notification { protocol: b"fin".to_vec(), data: vec![2] });
+		notifications.on_notification(2, Notification { protocol: b"fin".to_vec(), data: vec![3] });
+		notifications.peer_disconnected(1);
+		notifications.abort();
+
+		let events = events.collect().wait().unwrap();
+		assert_eq!(events, vec![
+			NotificationEvent::Opened { who: 1, handshake: b"hello".to_vec() },
+			NotificationEvent::Notification { who: 1, data: vec![2] },
+			NotificationEvent::Closed { who: 1 },
+		]);
+	}
+}
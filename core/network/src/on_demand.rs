@@ -26,12 +26,14 @@ use linked_hash_map::Entry;
 use parking_lot::Mutex;
 use client::{error::{Error as ClientError, ErrorKind as ClientErrorKind}};
 use client::light::fetcher::{Fetcher, FetchChecker, RemoteHeaderRequest,
-	RemoteCallRequest, RemoteReadRequest, RemoteChangesRequest, ChangesProof};
+	RemoteCallRequest, RemoteReadRequest, RemoteChangesRequest, RemoteBodyRequest, ChangesProof};
 use io::SyncIo;
 use message;
 use network_libp2p::{Severity, NodeIndex};
 use config::Roles;
+use protocol::{MIN_VERSION, REMOTE_BODY_VERSION};
 use service;
+use simulation;
 use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, NumberFor};
 
 /// Remote request timeout.
@@ -42,7 +44,7 @@ const RETRY_COUNT: usize = 1;
 /// On-demand service API.
 pub trait OnDemandService<Block: BlockT>: Send + Sync {
 	/// When new node is connected.
-	fn on_connect(&self, peer: NodeIndex, role: Roles, best_number: NumberFor<Block>);
+	fn on_connect(&self, peer: NodeIndex, role: Roles, protocol_version: u32, best_number: NumberFor<Block>);
 
 	/// When block is announced by the peer.
 	fn on_block_announce(&self, peer: NodeIndex, best_number: NumberFor<Block>);
@@ -74,6 +76,9 @@ pub trait OnDemandService<Block: BlockT>: Send + Sync {
 		peer: NodeIndex,
 		response: message::RemoteChangesResponse<NumberFor<Block>, Block::Hash>
 	);
+
+	/// When body response is received from remote node.
+	fn on_remote_body_response(&self, io: &mut SyncIo, peer: NodeIndex, response: message::RemoteBodyResponse<Block>);
 }
 
 /// On-demand requests service. Dispatches requests to appropriate peers.
@@ -95,6 +100,7 @@ struct OnDemandCore<B: BlockT, E: service::ExecuteInContext<B>> {
 	active_peers: LinkedHashMap<NodeIndex, Request<B>>,
 	idle_peers: VecDeque<NodeIndex>,
 	best_blocks: HashMap<NodeIndex, NumberFor<B>>,
+	protocol_versions: HashMap<NodeIndex, u32>,
 }
 
 struct Request<Block: BlockT> {
@@ -109,12 +115,15 @@ enum RequestData<Block: BlockT> {
 	RemoteRead(RemoteReadRequest<Block::Header>, Sender<Result<Option<Vec<u8>>, ClientError>>),
 	RemoteCall(RemoteCallRequest<Block::Header>, Sender<Result<Vec<u8>, ClientError>>),
 	RemoteChanges(RemoteChangesRequest<Block::Header>, Sender<Result<Vec<(NumberFor<Block>, u32)>, ClientError>>),
+	RemoteBody(RemoteBodyRequest<Block::Header>, Sender<Result<Vec<Block::Extrinsic>, ClientError>>),
 }
 
 enum Accept<Block: BlockT> {
 	Ok,
 	CheckFailed(ClientError, RequestData<Block>),
 	Unexpected(RequestData<Block>),
+	/// The peer doesn't have what was requested; retried with another peer.
+	Unavailable(RequestData<Block>),
 }
 
 impl<T> Future for RemoteResponse<T> {
@@ -147,6 +156,7 @@ impl<B: BlockT, E> OnDemand<B, E> where
 				active_peers: LinkedHashMap::new(),
 				idle_peers: VecDeque::new(),
 				best_blocks: HashMap::new(),
+				protocol_versions: HashMap::new(),
 			})
 		}
 	}
@@ -197,6 +207,16 @@ impl<B: BlockT, E> OnDemand<B, E> where
 
 				(retry_count, Some(retry_request_data))
 			},
+			Accept::Unavailable(retry_request_data) => {
+				trace!(target: "sync", "Remote {} not available from peer {}", rtype, peer);
+				if retry_count > 0 {
+					(retry_count - 1, Some(retry_request_data))
+				} else {
+					trace!(target: "sync", "Failed to get remote {} response for given number of retries", rtype);
+					retry_request_data.fail(ClientErrorKind::RemoteFetchFailed.into());
+					(0, None)
+				}
+			},
 		};
 
 		if let Some(request_data) = retry_request_data {
@@ -212,13 +232,13 @@ impl<B, E> OnDemandService<B> for OnDemand<B, E> where
 	E: service::ExecuteInContext<B>,
 	B::Header: HeaderT,
 {
-	fn on_connect(&self, peer: NodeIndex, role: Roles, best_number: NumberFor<B>) {
+	fn on_connect(&self, peer: NodeIndex, role: Roles, protocol_version: u32, best_number: NumberFor<B>) {
 		if !role.intersects(Roles::FULL | Roles::AUTHORITY) {
 			return;
 		}
 
 		let mut core = self.core.lock();
-		core.add_peer(peer, best_number);
+		core.add_peer(peer, protocol_version, best_number);
 		core.dispatch();
 	}
 
@@ -303,6 +323,24 @@ impl<B, E> OnDemandService<B> for OnDemand<B, E> where
 			data @ _ => Accept::Unexpected(data),
 		})
 	}
+
+	fn on_remote_body_response(&self, io: &mut SyncIo, peer: NodeIndex, response: message::RemoteBodyResponse<B>) {
+		self.accept_response("body", io, peer, response.id, |request| match request.data {
+			// peers that don't have the block, such as light or pruned nodes, answer with no body.
+			RequestData::RemoteBody(request, sender) => match response.body {
+				None => Accept::Unavailable(RequestData::RemoteBody(request, sender)),
+				Some(body) => match self.checker.check_body_proof(&request, body) {
+					Ok(response) => {
+						// we do not bother if receiver has been dropped already
+						let _ = sender.send(Ok(response));
+						Accept::Ok
+					},
+					Err(error) => Accept::CheckFailed(error, RequestData::RemoteBody(request, sender)),
+				},
+			},
+			data @ _ => Accept::Unexpected(data),
+		})
+	}
 }
 
 impl<B, E> Fetcher<B> for OnDemand<B, E> where
@@ -314,6 +352,7 @@ impl<B, E> Fetcher<B> for OnDemand<B, E> where
 	type RemoteReadResult = RemoteResponse<Option<Vec<u8>>>;
 	type RemoteCallResult = RemoteResponse<Vec<u8>>;
 	type RemoteChangesResult = RemoteResponse<Vec<(NumberFor<B>, u32)>>;
+	type RemoteBodyResult = RemoteResponse<Vec<B::Extrinsic>>;
 
 	fn remote_header(&self, request: RemoteHeaderRequest<B::Header>) -> Self::RemoteHeaderResult {
 		let (sender, receiver) = channel();
@@ -338,6 +377,12 @@ impl<B, E> Fetcher<B> for OnDemand<B, E> where
 		self.schedule_request(request.retry_count.clone(), RequestData::RemoteChanges(request, sender),
 			RemoteResponse { receiver })
 	}
+
+	fn remote_body(&self, request: RemoteBodyRequest<B::Header>) -> Self::RemoteBodyResult {
+		let (sender, receiver) = channel();
+		self.schedule_request(request.retry_count.clone(), RequestData::RemoteBody(request, sender),
+			RemoteResponse { receiver })
+	}
 }
 
 impl<B, E> OnDemandCore<B, E> where
@@ -345,9 +390,10 @@ impl<B, E> OnDemandCore<B, E> where
 	E: service::ExecuteInContext<B>,
 	B::Header: HeaderT,
 {
-	pub fn add_peer(&mut self, peer: NodeIndex, best_number: NumberFor<B>) {
+	pub fn add_peer(&mut self, peer: NodeIndex, protocol_version: u32, best_number: NumberFor<B>) {
 		self.idle_peers.push_back(peer);
 		self.best_blocks.insert(peer, best_number);
+		self.protocol_versions.insert(peer, protocol_version);
 	}
 
 	pub fn update_peer(&mut self, peer: NodeIndex, best_number: NumberFor<B>) {
@@ -356,6 +402,7 @@ impl<B, E> OnDemandCore<B, E> where
 
 	pub fn remove_peer(&mut self, peer: NodeIndex) {
 		self.best_blocks.remove(&peer);
+		self.protocol_versions.remove(&peer);
 
 		if let Some(request) = self.active_peers.remove(&peer) {
 			self.pending_requests.push_front(request);
@@ -368,7 +415,7 @@ impl<B, E> OnDemandCore<B, E> where
 	}
 
 	pub fn maintain_peers(&mut self) -> Vec<NodeIndex> {
-		let now = Instant::now();
+		let now = simulation::now();
 		let mut bad_peers = Vec::new();
 		loop {
 			match self.active_peers.front() {
@@ -388,7 +435,7 @@ impl<B, E> OnDemandCore<B, E> where
 
 		self.pending_requests.push_back(Request {
 			id: request_id,
-			timestamp: Instant::now(),
+			timestamp: simulation::now(),
 			retry_count,
 			data,
 		});
@@ -435,7 +482,9 @@ impl<B, E> OnDemandCore<B, E> where
 					.expect("entries are inserted into best_blocks when peer is connected;
 						entries are removed from best_blocks when peer is disconnected;
 						peer is in idle_peers and thus connected; qed");
+				let peer_protocol_version = self.protocol_versions.get(&peer).cloned().unwrap_or(MIN_VERSION);
 				request.required_block() <= *peer_best_block
+					&& request.required_protocol_version() <= peer_protocol_version
 			};
 
 			if !can_be_processed_by_peer {
@@ -455,7 +504,7 @@ impl<B, E> OnDemandCore<B, E> where
 			last_peer = self.idle_peers.back().cloned();
 
 			let mut request = self.pending_requests.pop_front().expect("checked in loop condition; qed");
-			request.timestamp = Instant::now();
+			request.timestamp = simulation::now();
 			trace!(target: "sync", "Dispatching remote request {} to peer {}", request.id, peer);
 
 			service.execute_in_context(|ctx| ctx.send_message(peer, request.message()));
@@ -473,6 +522,14 @@ impl<Block: BlockT> Request<Block> {
 			RequestData::RemoteRead(ref data, _) => *data.header.number(),
 			RequestData::RemoteCall(ref data, _) => *data.header.number(),
 			RequestData::RemoteChanges(ref data, _) => data.max_block.0,
+			RequestData::RemoteBody(ref data, _) => *data.header.number(),
+		}
+	}
+
+	pub fn required_protocol_version(&self) -> u32 {
+		match self.data {
+			RequestData::RemoteBody(_, _) => REMOTE_BODY_VERSION,
+			_ => MIN_VERSION,
 		}
 	}
 
@@ -505,6 +562,11 @@ impl<Block: BlockT> Request<Block> {
 					max: data.max_block.1.clone(),
 					key: data.key.clone(),
 				}),
+			RequestData::RemoteBody(ref data, _) =>
+				message::generic::Message::RemoteBodyRequest(message::RemoteBodyRequest {
+					id: self.id,
+					block: data.header.hash(),
+				}),
 		}
 	}
 }
@@ -517,6 +579,7 @@ impl<Block: BlockT> RequestData<Block> {
 			RequestData::RemoteCall(_, sender) => { let _ = sender.send(Err(error)); },
 			RequestData::RemoteRead(_, sender) => { let _ = sender.send(Err(error)); },
 			RequestData::RemoteChanges(_, sender) => { let _ = sender.send(Err(error)); },
+			RequestData::RemoteBody(_, sender) => { let _ = sender.send(Err(error)); },
 		}
 	}
 }
@@ -531,14 +594,15 @@ pub mod tests {
 	use runtime_primitives::traits::NumberFor;
 	use client::{error::{ErrorKind as ClientErrorKind, Result as ClientResult}};
 	use client::light::fetcher::{Fetcher, FetchChecker, RemoteHeaderRequest,
-		RemoteCallRequest, RemoteReadRequest, RemoteChangesRequest, ChangesProof};
+		RemoteCallRequest, RemoteReadRequest, RemoteChangesRequest, RemoteBodyRequest, ChangesProof};
 	use config::Roles;
 	use message;
 	use network_libp2p::NodeIndex;
 	use service::ExecuteInContext;
 	use test::TestIo;
+	use protocol::{CURRENT_VERSION, REMOTE_BODY_VERSION};
 	use super::{REQUEST_TIMEOUT, OnDemand, OnDemandService};
-	use test_client::runtime::{changes_trie_config, Block, Header};
+	use test_client::runtime::{changes_trie_config, Block, Extrinsic, Header};
 
 	pub struct DummyExecutor;
 	struct DummyFetchChecker { ok: bool }
@@ -580,6 +644,13 @@ pub mod tests {
 				false => Err(ClientErrorKind::Backend("Test error".into()).into()),
 			}
 		}
+
+		fn check_body_proof(&self, _: &RemoteBodyRequest<Header>, body: Vec<Extrinsic>) -> ClientResult<Vec<Extrinsic>> {
+			match self.ok {
+				true => Ok(body),
+				false => Err(ClientErrorKind::Backend("Test error".into()).into()),
+			}
+		}
 	}
 
 	fn dummy(ok: bool) -> (Arc<DummyExecutor>, Arc<OnDemand<Block, DummyExecutor>>) {
@@ -614,9 +685,9 @@ pub mod tests {
 	#[test]
 	fn knows_about_peers_roles() {
 		let (_, on_demand) = dummy(true);
-		on_demand.on_connect(0, Roles::LIGHT, 1000);
-		on_demand.on_connect(1, Roles::FULL, 2000);
-		on_demand.on_connect(2, Roles::AUTHORITY, 3000);
+		on_demand.on_connect(0, Roles::LIGHT, CURRENT_VERSION, 1000);
+		on_demand.on_connect(1, Roles::FULL, CURRENT_VERSION, 2000);
+		on_demand.on_connect(2, Roles::AUTHORITY, CURRENT_VERSION, 3000);
 		assert_eq!(vec![1, 2], on_demand.core.lock().idle_peers.iter().cloned().collect::<Vec<_>>());
 		assert_eq!(on_demand.core.lock().best_blocks.get(&1), Some(&2000));
 		assert_eq!(on_demand.core.lock().best_blocks.get(&2), Some(&3000));
@@ -625,7 +696,7 @@ pub mod tests {
 	#[test]
 	fn disconnects_from_idle_peer() {
 		let (_, on_demand) = dummy(true);
-		on_demand.on_connect(0, Roles::FULL, 100);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 100);
 		assert_eq!(1, total_peers(&*on_demand));
 		assert!(!on_demand.core.lock().best_blocks.is_empty());
 
@@ -640,8 +711,8 @@ pub mod tests {
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
 
-		on_demand.on_connect(0, Roles::FULL, 1000);
-		on_demand.on_connect(1, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
+		on_demand.on_connect(1, Roles::FULL, CURRENT_VERSION, 1000);
 		assert_eq!(vec![0, 1], on_demand.core.lock().idle_peers.iter().cloned().collect::<Vec<_>>());
 		assert!(on_demand.core.lock().active_peers.is_empty());
 
@@ -667,7 +738,7 @@ pub mod tests {
 		let (_x, on_demand) = dummy(true);
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
-		on_demand.on_connect(0, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
 
 		on_demand.remote_call(RemoteCallRequest {
 			block: Default::default(),
@@ -694,7 +765,7 @@ pub mod tests {
 			retry_count: Some(1),
 		});
 
-		on_demand.on_connect(0, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
 		receive_call_response(&*on_demand, &mut network, 0, 0);
 		assert!(network.to_disconnect.contains(&0));
 		assert_eq!(on_demand.core.lock().pending_requests.len(), 1);
@@ -705,7 +776,7 @@ pub mod tests {
 		let (_x, on_demand) = dummy(true);
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
-		on_demand.on_connect(0, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
 
 		receive_call_response(&*on_demand, &mut network, 0, 0);
 		assert!(network.to_disconnect.contains(&0));
@@ -716,7 +787,7 @@ pub mod tests {
 		let (_x, on_demand) = dummy(false);
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
-		on_demand.on_connect(0, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
 
 		on_demand.remote_call(RemoteCallRequest {
 			block: Default::default(),
@@ -743,7 +814,7 @@ pub mod tests {
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
 		for i in 0..retry_count+1 {
-			on_demand.on_connect(i, Roles::FULL, 1000);
+			on_demand.on_connect(i, Roles::FULL, CURRENT_VERSION, 1000);
 		}
 
 		let sync = Arc::new((Mutex::new(0), Mutex::new(0), Condvar::new()));
@@ -782,7 +853,7 @@ pub mod tests {
 		let (_x, on_demand) = dummy(true);
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
-		on_demand.on_connect(0, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
 
 		let response = on_demand.remote_call(RemoteCallRequest {
 			block: Default::default(),
@@ -805,7 +876,7 @@ pub mod tests {
 		let (_x, on_demand) = dummy(true);
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
-		on_demand.on_connect(0, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
 
 		let response = on_demand.remote_read(RemoteReadRequest {
 			header: dummy_header(),
@@ -830,7 +901,7 @@ pub mod tests {
 		let (_x, on_demand) = dummy(true);
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
-		on_demand.on_connect(0, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
 
 		let response = on_demand.remote_header(RemoteHeaderRequest {
 			cht_root: Default::default(),
@@ -865,7 +936,7 @@ pub mod tests {
 		let (_x, on_demand) = dummy(true);
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
-		on_demand.on_connect(0, Roles::FULL, 1000);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
 
 		let response = on_demand.remote_changes(RemoteChangesRequest {
 			changes_trie_config: changes_trie_config(),
@@ -897,7 +968,7 @@ pub mod tests {
 		let queue = RwLock::new(VecDeque::new());
 		let mut network = TestIo::new(&queue, None);
 
-		on_demand.on_connect(1, Roles::FULL, 100);
+		on_demand.on_connect(1, Roles::FULL, CURRENT_VERSION, 100);
 
 		on_demand.remote_header(RemoteHeaderRequest {
 			cht_root: Default::default(),
@@ -915,7 +986,7 @@ pub mod tests {
 			retry_count: None,
 		});
 
-		on_demand.on_connect(2, Roles::FULL, 150);
+		on_demand.on_connect(2, Roles::FULL, CURRENT_VERSION, 150);
 
 		assert_eq!(vec![1, 2], on_demand.core.lock().idle_peers.iter().cloned().collect::<Vec<_>>());
 		assert_eq!(on_demand.core.lock().pending_requests.len(), 3);
@@ -960,9 +1031,9 @@ pub mod tests {
 			retry_count: None,
 		});
 
-		on_demand.on_connect(1, Roles::FULL, 200);
-		on_demand.on_connect(2, Roles::FULL, 200);
-		on_demand.on_connect(3, Roles::FULL, 250);
+		on_demand.on_connect(1, Roles::FULL, CURRENT_VERSION, 200);
+		on_demand.on_connect(2, Roles::FULL, CURRENT_VERSION, 200);
+		on_demand.on_connect(3, Roles::FULL, CURRENT_VERSION, 250);
 
 		assert_eq!(vec![1, 2], on_demand.core.lock().idle_peers.iter().cloned().collect::<Vec<_>>());
 		assert_eq!(on_demand.core.lock().pending_requests.len(), 1);
@@ -985,9 +1056,91 @@ pub mod tests {
 			retry_count: None,
 		});
 
-		on_demand.on_connect(1, Roles::FULL, 250);
+		on_demand.on_connect(1, Roles::FULL, CURRENT_VERSION, 250);
 
 		assert!(on_demand.core.lock().idle_peers.iter().cloned().collect::<Vec<_>>().is_empty());
 		assert_eq!(on_demand.core.lock().pending_requests.len(), 1);
 	}
+
+	#[test]
+	fn receives_remote_body_response() {
+		let (_x, on_demand) = dummy(true);
+		let queue = RwLock::new(VecDeque::new());
+		let mut network = TestIo::new(&queue, None);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
+
+		let response = on_demand.remote_body(RemoteBodyRequest {
+			header: dummy_header(),
+			retry_count: None,
+		});
+		let thread = ::std::thread::spawn(move || {
+			let result = response.wait().unwrap();
+			assert_eq!(result, Vec::new());
+		});
+
+		on_demand.on_remote_body_response(&mut network, 0, message::generic::RemoteBodyResponse {
+			id: 0,
+			body: Some(Vec::new()),
+		});
+		thread.join().unwrap();
+	}
+
+	#[test]
+	fn retries_with_another_peer_on_missing_body() {
+		let (_x, on_demand) = dummy(true);
+		let queue = RwLock::new(VecDeque::new());
+		let mut network = TestIo::new(&queue, None);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
+		on_demand.on_connect(1, Roles::FULL, CURRENT_VERSION, 1000);
+
+		on_demand.remote_body(RemoteBodyRequest {
+			header: dummy_header(),
+			retry_count: Some(1),
+		});
+		on_demand.on_remote_body_response(&mut network, 0, message::generic::RemoteBodyResponse {
+			id: 0,
+			body: None,
+		});
+		assert!(network.to_disconnect.is_empty());
+		assert_eq!(total_peers(&*on_demand), 2);
+		assert_eq!(vec![1], on_demand.core.lock().active_peers.keys().cloned().collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn fails_body_request_when_no_peer_has_it() {
+		let (_x, on_demand) = dummy(true);
+		let queue = RwLock::new(VecDeque::new());
+		let mut network = TestIo::new(&queue, None);
+		on_demand.on_connect(0, Roles::FULL, CURRENT_VERSION, 1000);
+
+		let response = on_demand.remote_body(RemoteBodyRequest {
+			header: dummy_header(),
+			retry_count: Some(1),
+		});
+		for id in 0..2 {
+			on_demand.on_remote_body_response(&mut network, 0, message::generic::RemoteBodyResponse {
+				id,
+				body: None,
+			});
+		}
+		assert!(network.to_disconnect.is_empty());
+		assert!(response.wait().is_err());
+	}
+
+	#[test]
+	fn does_not_send_body_request_to_peer_with_old_protocol_version() {
+		let (_x, on_demand) = dummy(true);
+		on_demand.on_connect(1, Roles::FULL, REMOTE_BODY_VERSION - 1, 1000);
+
+		on_demand.remote_body(RemoteBodyRequest {
+			header: dummy_header(),
+			retry_count: None,
+		});
+		assert_eq!(vec![1], on_demand.core.lock().idle_peers.iter().cloned().collect::<Vec<_>>());
+		assert_eq!(on_demand.core.lock().pending_requests.len(), 1);
+
+		on_demand.on_connect(2, Roles::FULL, REMOTE_BODY_VERSION, 1000);
+		assert_eq!(vec![1], on_demand.core.lock().idle_peers.iter().cloned().collect::<Vec<_>>());
+		assert_eq!(on_demand.core.lock().pending_requests.len(), 0);
+	}
 }
@@ -0,0 +1,272 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Outbound messages held back while a peer is clogged, so that a burst of
+//! low-priority traffic doesn't delay consensus messages.
+//!
+//! Consensus messages are always sent straight away. Other messages are queued
+//! per class once the network reports the peer as clogged, and released on the
+//! next tick. Each class releases a number of messages per round in proportion
+//! to its weight, so that sync messages are favoured over transactions without
+//! starving them, and peers are served in turns. Sync messages are never dropped,
+//! since our requests and responses would be lost with them.
+
+use std::collections::{HashMap, VecDeque};
+use network_libp2p::NodeIndex;
+use runtime_primitives::traits::Block as BlockT;
+use message::{Message, generic::Message as GenericMessage};
+
+/// Number of queued classes: all priorities but `Priority::Consensus`.
+const QUEUED_CLASSES: usize = 2;
+/// Number of messages each queued class releases per round.
+const CLASS_WEIGHTS: [usize; QUEUED_CLASSES] = [4, 1];
+
+/// Priority class of an outbound message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Priority {
+	/// Consensus messages and handshakes, never queued.
+	Consensus,
+	/// Block announcements, requests and responses. Queued, but never dropped.
+	Sync,
+	/// Transactions gossip.
+	Transactions,
+}
+
+impl Priority {
+	/// Priority of a message.
+	pub(crate) fn of<B: BlockT>(message: &Message<B>) -> Self {
+		match *message {
+			GenericMessage::Status(_) |
+			GenericMessage::Consensus(..) |
+			GenericMessage::AuthorityConsensus(..) |
+			GenericMessage::EngineConsensus(..) |
+			GenericMessage::FinalizedAnnounce(_) |
+			GenericMessage::Ping(_) |
+			GenericMessage::Pong(_) |
+			GenericMessage::CancelRequest(_) |
+			GenericMessage::NotificationsOpen(_) |
+			GenericMessage::Notification(_) => Priority::Consensus,
+			GenericMessage::Transactions(_) => Priority::Transactions,
+			_ => Priority::Sync,
+		}
+	}
+
+	// Index of the queue of the class, if queued at all.
+	fn queue_index(&self) -> Option<usize> {
+		match *self {
+			Priority::Consensus => None,
+			Priority::Sync => Some(0),
+			Priority::Transactions => Some(1),
+		}
+	}
+
+	// Whether messages of the class are dropped once too many are queued.
+	fn is_droppable(&self) -> bool {
+		*self == Priority::Transactions
+	}
+}
+
+/// A queued message, with its kind.
+type QueuedMessage = (&'static str, Vec<u8>);
+
+#[derive(Default)]
+struct PeerQueue {
+	classes: [VecDeque<QueuedMessage>; QUEUED_CLASSES],
+	clogged: bool,
+}
+
+impl PeerQueue {
+	fn is_empty(&self) -> bool {
+		self.classes.iter().all(|class| class.is_empty())
+	}
+
+	// Release up to the weight of each class, and at most `max` messages in total.
+	fn release_round(&mut self, who: NodeIndex, max: usize, released: &mut Vec<(NodeIndex, &'static str, Vec<u8>)>) -> usize {
+		let mut count = 0;
+		for (class, weight) in self.classes.iter_mut().zip(CLASS_WEIGHTS.iter()) {
+			for _ in 0..*weight {
+				if count >= max {
+					return count;
+				}
+				match class.pop_front() {
+					Some((kind, data)) => {
+						released.push((who, kind, data));
+						count += 1;
+					},
+					None => break,
+				}
+			}
+		}
+		count
+	}
+}
+
+/// Outbound messages queued per peer and priority.
+pub(crate) struct OutboundQueue {
+	peers: HashMap<NodeIndex, PeerQueue>,
+	max_queued: usize,
+}
+
+impl OutboundQueue {
+	/// Create a new queue holding at most `max_queued` messages of each droppable
+	/// class per peer.
+	pub(crate) fn new(max_queued: usize) -> Self {
+		OutboundQueue {
+			peers: HashMap::new(),
+			max_queued,
+		}
+	}
+
+	/// Queue an encoded message of the given kind, unless it can be sent right away in
+	/// which case it is returned. Droppable messages beyond the limit are dropped.
+	pub(crate) fn push(&mut self, who: NodeIndex, priority: Priority, kind: &'static str, data: Vec<u8>) -> Option<Vec<u8>> {
+		let index = match priority.queue_index() {
+			Some(index) => index,
+			None => return Some(data),
+		};
+		let max_queued = self.max_queued;
+		let peer = match self.peers.get_mut(&who) {
+			Some(peer) => peer,
+			None => return Some(data),
+		};
+		// messages of a class are sent in order, so they queue behind held back ones.
+		let queue = &mut peer.classes[index];
+		if !peer.clogged && queue.is_empty() {
+			return Some(data);
+		}
+		if priority.is_droppable() && queue.len() >= max_queued {
+			trace!(target: "sync", "Too many queued {:?} messages to {}, dropping", priority, who);
+			return None;
+		}
+		queue.push_back((kind, data));
+		None
+	}
+
+	/// The network reported the peer as clogged: hold back its low-priority messages.
+	pub(crate) fn set_clogged(&mut self, who: NodeIndex) {
+		self.peers.entry(who).or_insert_with(Default::default).clogged = true;
+	}
+
+	/// Release the queued messages, up to `max_per_peer` for each peer, with their kind.
+	/// Peers release a round of messages in turns.
+	pub(crate) fn drain(&mut self, max_per_peer: usize) -> Vec<(NodeIndex, &'static str, Vec<u8>)> {
+		let mut drained = Vec::new();
+		let mut released: HashMap<NodeIndex, usize> = HashMap::new();
+		for peer in self.peers.values_mut() {
+			peer.clogged = false;
+		}
+		loop {
+			let mut progress = false;
+			for (who, peer) in self.peers.iter_mut() {
+				let count = released.entry(*who).or_insert(0);
+				if *count >= max_per_peer {
+					continue;
+				}
+				let round = peer.release_round(*who, max_per_peer - *count, &mut drained);
+				*count += round;
+				progress |= round > 0;
+			}
+			if !progress {
+				break;
+			}
+		}
+		self.peers.retain(|_, peer| !peer.is_empty());
+		drained
+	}
+
+	/// Drop the queued messages of a peer.
+	pub(crate) fn remove_peer(&mut self, who: NodeIndex) {
+		self.peers.remove(&who);
+	}
+
+	/// Drop all queued messages.
+	pub(crate) fn clear(&mut self) {
+		self.peers.clear();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use network_libp2p::NodeIndex;
+	use super::{OutboundQueue, Priority};
+
+	fn data(drained: Vec<(NodeIndex, &'static str, Vec<u8>)>) -> Vec<(NodeIndex, u8)> {
+		drained.into_iter().map(|(who, _, data)| (who, data[0])).collect()
+	}
+
+	#[test]
+	fn consensus_messages_skip_clogged_peers_queue() {
+		let mut queue = OutboundQueue::new(16);
+		assert_eq!(queue.push(1, Priority::Sync, "sync", vec![1]), Some(vec![1]));
+
+		queue.set_clogged(1);
+		assert_eq!(queue.push(1, Priority::Transactions, "tx", vec![2]), None);
+		assert_eq!(queue.push(1, Priority::Sync, "sync", vec![3]), None);
+		assert_eq!(queue.push(1, Priority::Consensus, "consensus", vec![4]), Some(vec![4]));
+		assert_eq!(queue.push(2, Priority::Transactions, "tx", vec![5]), Some(vec![5]));
+
+		assert_eq!(queue.drain(16), vec![(1, "sync", vec![3]), (1, "tx", vec![2])]);
+		assert_eq!(queue.push(1, Priority::Transactions, "tx", vec![6]), Some(vec![6]));
+	}
+
+	#[test]
+	fn transactions_are_not_starved() {
+		let mut queue = OutboundQueue::new(16);
+		queue.set_clogged(1);
+		for i in 0..8 {
+			queue.push(1, Priority::Sync, "sync", vec![i]);
+			queue.push(1, Priority::Transactions, "tx", vec![100 + i]);
+		}
+
+		let drained: Vec<_> = data(queue.drain(6)).into_iter().map(|(_, data)| data).collect();
+		assert_eq!(drained, vec![0, 1, 2, 3, 100, 4]);
+
+		// the peer is no longer clogged, but messages queue behind the remaining ones.
+		assert_eq!(queue.push(1, Priority::Sync, "sync", vec![8]), None);
+		assert_eq!(queue.drain(64).len(), 3 + 7 + 1);
+		assert_eq!(queue.push(1, Priority::Sync, "sync", vec![9]), Some(vec![9]));
+	}
+
+	#[test]
+	fn only_transactions_are_dropped() {
+		let mut queue = OutboundQueue::new(2);
+		queue.set_clogged(1);
+		for i in 0..4 {
+			assert_eq!(queue.push(1, Priority::Sync, "sync", vec![i]), None);
+			assert_eq!(queue.push(1, Priority::Transactions, "tx", vec![100 + i]), None);
+		}
+
+		let drained: Vec<_> = data(queue.drain(64)).into_iter().map(|(_, data)| data).collect();
+		assert_eq!(drained, vec![0, 1, 2, 3, 100, 101]);
+	}
+
+	#[test]
+	fn peers_are_drained_in_turns() {
+		let mut queue = OutboundQueue::new(16);
+		queue.set_clogged(1);
+		queue.set_clogged(2);
+		for i in 0..6 {
+			queue.push(1, Priority::Sync, "sync", vec![i]);
+		}
+		queue.push(2, Priority::Sync, "sync", vec![10]);
+		queue.push(2, Priority::Transactions, "tx", vec![11]);
+
+		let mut drained = data(queue.drain(64));
+		// the order of the peers within a round is unspecified.
+		drained[..6].sort();
+		assert_eq!(drained, vec![(1, 0), (1, 1), (1, 2), (1, 3), (2, 10), (2, 11), (1, 4), (1, 5)]);
+	}
+}
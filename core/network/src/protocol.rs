@@ -14,36 +14,77 @@
 // You should have received a copy of the GNU General Public License
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::collections::{HashMap, HashSet, BTreeMap};
+use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet, VecDeque};
 use std::cmp;
 use std::io::Cursor;
 use std::sync::Arc;
-use std::time;
-use parking_lot::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{thread, time};
+use futures::sync::{mpsc, oneshot};
+use parking_lot::{Mutex, RwLock};
+use linked_hash_map::LinkedHashMap;
 use rustc_hex::ToHex;
 use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, NumberFor, As, Zero};
-use runtime_primitives::generic::BlockId;
+use runtime_primitives::generic::{BlockId, SignedBlock};
 use primitives::storage::StorageKey;
-use network_libp2p::{NodeIndex, Severity};
+use network_libp2p::{NodeIndex, Severity, ReputationChange};
 use codec::{Encode, Decode};
-use consensus::import_queue::ImportQueue;
+use consensus::BlockOrigin;
+use consensus::import_queue::{ImportQueue, IncomingBlock};
 use message::{self, Message};
 use message::generic::Message as GenericMessage;
-use consensus_gossip::ConsensusGossip;
+use consensus_gossip::{ConsensusGossip, TopicMetrics};
+use notifications::{Notifications, NotificationEvent, ProtocolName};
+use block_requests::BlockRequestQueue;
+use outbound::{OutboundQueue, Priority};
+use lru_set::LruHashSet;
 use specialization::NetworkSpecialization;
-use sync::{ChainSync, Status as SyncStatus, SyncState};
+use sync::{ChainSync, Metrics as SyncMetrics, Status as SyncStatus};
+use extra_requests::ExtraRequestInfo;
+use blocks::DownloadStats;
 use service::{TransactionPool, ExHashT};
-use config::{ProtocolConfig, Roles};
+use config::{GossipQuota, LightAnnouncement, ProtocolConfig, RequestQuota, Roles, TransactionQuota};
 use chain::Client;
 use client::light::fetcher::ChangesProof;
 use on_demand::OnDemandService;
 use io::SyncIo;
+use simulation;
 use error;
 
-const REQUEST_TIMEOUT_SEC: u64 = 40;
+/// Time a peer has to answer a request or complete the handshake.
+const REQUEST_TIMEOUT: time::Duration = time::Duration::from_secs(40);
+/// Interval between pings to a peer.
+const PING_INTERVAL: time::Duration = time::Duration::from_secs(10);
+/// Time a peer has to answer a ping before it is disconnected.
+const PING_TIMEOUT: time::Duration = time::Duration::from_secs(20);
 
 /// Current protocol version.
-pub (crate) const CURRENT_VERSION: u32 = 1;
+pub (crate) const CURRENT_VERSION: u32 = 3;
+/// Lowest version we support.
+pub (crate) const MIN_VERSION: u32 = 1;
+/// First version that understands `CancelRequest` messages.
+pub (crate) const CANCEL_REQUEST_VERSION: u32 = 2;
+/// First version that understands `RemoteBodyRequest` messages.
+pub (crate) const REMOTE_BODY_VERSION: u32 = 3;
+/// Optional features we advertise in our `Status`.
+pub (crate) fn supported_features() -> message::Features {
+	message::Features::BATCHED_JUSTIFICATIONS |
+		message::Features::COMPRESSED_RESPONSES |
+		message::Features::NOTIFICATIONS |
+		message::Features::FINALIZED_ANNOUNCEMENTS |
+		message::Features::AUTHORITY_MESSAGES |
+		message::Features::PING |
+		message::Features::GOSSIP_ENGINES
+}
+
+/// Maximum number of held back messages released to a peer on each tick.
+const MAX_RELEASED_MESSAGES: usize = 1024;
+/// Maximum length of the client version a peer can tell us.
+const MAX_CLIENT_VERSION_LEN: usize = 128;
+/// Maximum number of cancelled request ids remembered per peer.
+const MAX_CANCELLED_REQUESTS: usize = 64;
+/// Maximum number of transaction hashes remembered per peer as known to it.
+const MAX_KNOWN_EXTRINSICS: usize = 4096;
 
 // Maximum allowed entries in `BlockResponse`
 const MAX_BLOCK_DATA_RESPONSE: u32 = 128;
@@ -51,6 +92,18 @@ const MAX_BLOCK_DATA_RESPONSE: u32 = 128;
 /// for at least `LIGHT_MAXIMAL_BLOCKS_DIFFERENCE` blocks, we consider it unuseful
 /// and disconnect to free connection slot.
 const LIGHT_MAXIMAL_BLOCKS_DIFFERENCE: u64 = 8192;
+/// When the chain is frozen, one in this many connected peers is dropped to make
+/// room for new ones.
+const CHAIN_FREEZE_ROTATION_FRACTION: usize = 4;
+/// Number of authority sets remembered to check the signers of authority messages.
+const MAX_CACHED_AUTHORITY_SETS: usize = 8;
+/// Reputation change of a peer sending a consensus engine message its validator discards.
+const DISCARDED_ENGINE_MESSAGE_REPUTATION_CHANGE: i32 = -10;
+/// Number of recently authored blocks remembered to be republished to new peers.
+const MAX_AUTHORED_BLOCKS: usize = 16;
+/// New peers whose best block is at most this many blocks behind ours are
+/// announced our recently authored blocks right away.
+const REPUBLISH_MAX_DISTANCE: u64 = 16;
 
 // Lock must always be taken in order declared here.
 pub struct Protocol<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> {
@@ -60,11 +113,44 @@ pub struct Protocol<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> {
 	sync: Arc<RwLock<ChainSync<B>>>,
 	specialization: RwLock<S>,
 	consensus_gossip: RwLock<ConsensusGossip<B>>,
+	notifications: RwLock<Notifications>,
+	// Encoded ids of the authorities at recent best blocks.
+	authorities: Mutex<LinkedHashMap<B::Hash, Vec<Vec<u8>>>>,
 	context_data: ContextData<B, H>,
 	// Connected peers pending Status message.
 	handshaking_peers: RwLock<HashMap<NodeIndex, time::Instant>>,
 	transaction_pool: Arc<TransactionPool<H, B>>,
+	chain_progress: RwLock<ChainProgress<B>>,
+	// Headers of recently authored blocks, oldest first.
+	authored_blocks: RwLock<VecDeque<B::Header>>,
+	// Block requests waiting for a worker thread.
+	block_requests: Arc<BlockRequestQueue<B>>,
+	// Whether we were doing a major sync at the last tick.
+	was_major_syncing: AtomicBool,
+	// When to send the ready extrinsics again to the peers that already know them.
+	extrinsics_rebroadcast: Mutex<HashMap<H, time::Instant>>,
+	// Extrinsics recently sent to a peer, and the propagation counters.
+	propagation: Mutex<(LruHashSet<H>, PropagationMetrics)>,
+	// Whether new extrinsics wait to be propagated at the next tick.
+	propagation_requested: Arc<AtomicBool>,
+}
+
+impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Drop for Protocol<B, S, H> {
+	fn drop(&mut self) {
+		self.block_requests.stop();
+	}
+}
+
+/// Last observed progress of the chain, used to detect that we are stuck with stale peers.
+struct ChainProgress<B: BlockT> {
+	/// Best block seen from our peers at the last check.
+	best_seen_block: Option<NumberFor<B>>,
+	/// When the best seen block last advanced or a block was last announced to us.
+	last_progress: time::Instant,
+	/// Number of peers replaced because the chain appeared frozen.
+	rotated_peers: u64,
 }
+
 /// Syncing status and statistics
 #[derive(Clone)]
 pub struct ProtocolStatus<B: BlockT> {
@@ -74,43 +160,291 @@ pub struct ProtocolStatus<B: BlockT> {
 	pub num_peers: usize,
 	/// Total number of active peers.
 	pub num_active_peers: usize,
+	/// Total number of peers replaced because the chain appeared frozen.
+	pub chain_freeze_rotations: u64,
 }
 
 /// Peer information
 struct Peer<B: BlockT, H: ExHashT> {
 	/// Protocol version
 	protocol_version: u32,
+	/// Optional features supported by both sides.
+	features: message::Features,
+	/// Name and version of the peer's implementation, if it told us.
+	client_version: Option<String>,
 	/// Roles
 	roles: Roles,
 	/// Peer best block hash
 	best_hash: B::Hash,
 	/// Peer best block number
 	best_number: <B::Header as HeaderT>::Number,
-	/// Pending block request if any
-	block_request: Option<message::BlockRequest<B>>,
-	/// Pending block request timestamp
-	block_request_timestamp: Option<time::Instant>,
-	/// Pending block justification request if any
-	justification_request: Option<message::BlockRequest<B>>,
-	/// Pending block justification request timestamp
-	justification_request_timestamp: Option<time::Instant>,
-	/// Holds a set of transactions known to this peer.
-	known_extrinsics: HashSet<H>,
+	/// In-flight requests by id, with the time by which they must be answered.
+	requests: HashMap<message::RequestId, (message::BlockRequest<B>, time::Instant)>,
+	/// Requests we cancelled; responses the peer sends for them anyway are ignored.
+	cancelled_requests: BTreeSet<message::RequestId>,
+	/// Holds the most recent transactions the peer sent us or we sent it, so that they
+	/// aren't sent again.
+	known_extrinsics: LruHashSet<H>,
+	/// Transactions we sent this peer or skipped because it knew them, so that each is
+	/// accounted for only once in the propagation metrics.
+	accounted_extrinsics: LruHashSet<H>,
 	/// Holds a set of blocks known to this peer.
 	known_blocks: HashSet<B::Hash>,
 	/// Request counter,
 	next_request_id: message::RequestId,
+	/// Our requests waiting for the peer to answer previous ones.
+	deferred_requests: VecDeque<message::BlockRequest<B>>,
+	/// Block requests served to this peer in the current quota window.
+	served: ServedRequests,
+	/// Block requests beyond the quota, served in a later window.
+	delayed_requests: VecDeque<message::BlockRequest<B>>,
+	/// Gossip and announcement messages received from this peer in the current quota window.
+	gossip: ReceivedGossip,
+	/// Extrinsics sent to this peer in the current quota window.
+	sent_extrinsics: SentExtrinsics,
+	/// Nonce and send time of our ping waiting for an answer.
+	pending_ping: Option<(u64, time::Instant)>,
+	/// When we last pinged the peer.
+	last_ping: Option<time::Instant>,
+	/// Round-trip time measured with pings, smoothed.
+	latency: Option<time::Duration>,
 }
 
-impl<B: BlockT, H: ExHashT> Peer<B, H> {
-	fn min_request_timestamp(&self) -> Option<&time::Instant> {
-		match (self.block_request_timestamp, self.justification_request_timestamp) {
-			(Some(t1), Some(t2)) if t1 < t2 => self.block_request_timestamp.as_ref(),
-			(Some(_), Some(_)) => self.justification_request_timestamp.as_ref(),
-			(Some(_), None) => self.block_request_timestamp.as_ref(),
-			(None, Some(_)) => self.justification_request_timestamp.as_ref(),
-			_ => None,
+/// Requests served to a peer since the start of the current quota window.
+struct ServedRequests {
+	window_start: time::Instant,
+	requests: u32,
+	bytes: usize,
+}
+
+impl ServedRequests {
+	fn new(now: time::Instant) -> Self {
+		ServedRequests {
+			window_start: now,
+			requests: 0,
+			bytes: 0,
+		}
+	}
+
+	/// Counts a request if the quota allows serving it now.
+	fn admit(&mut self, quota: &RequestQuota, now: time::Instant) -> bool {
+		if now - self.window_start >= quota.window {
+			*self = ServedRequests::new(now);
+		}
+		if self.requests >= quota.max_requests || self.bytes >= quota.max_bytes {
+			return false;
 		}
+		self.requests += 1;
+		true
+	}
+}
+
+/// Extrinsics sent to a peer since the start of the current quota window.
+struct SentExtrinsics {
+	window_start: time::Instant,
+	bytes: usize,
+}
+
+impl SentExtrinsics {
+	fn new(now: time::Instant) -> Self {
+		SentExtrinsics {
+			window_start: now,
+			bytes: 0,
+		}
+	}
+
+	/// Counts an extrinsic of `size` bytes if the quota allows sending it now. The first
+	/// extrinsic of a window is always sent, whatever its size.
+	fn admit(&mut self, quota: &TransactionQuota, size: usize, now: time::Instant) -> bool {
+		if now - self.window_start >= quota.window {
+			*self = SentExtrinsics::new(now);
+		}
+		if self.bytes > 0 && self.bytes + size > quota.max_bytes {
+			return false;
+		}
+		self.bytes += size;
+		true
+	}
+}
+
+/// Gossip received from a peer since the start of the current quota window.
+struct ReceivedGossip {
+	window_start: time::Instant,
+	messages: u32,
+	bytes: usize,
+	// Whether messages were dropped in the current window.
+	exceeded: bool,
+	// Number of consecutive windows the quota was exceeded in, the current one included.
+	exceeded_windows: u32,
+}
+
+/// Outcome of counting a gossip message against the quota.
+#[derive(Debug, PartialEq, Eq)]
+enum GossipAdmission {
+	/// The message is within the quota.
+	Admitted,
+	/// The message is beyond the quota, which was already exceeded in the window.
+	Dropped,
+	/// The message is the first beyond the quota in the window, which was exceeded in
+	/// that many consecutive windows.
+	Exceeded(u32),
+}
+
+impl ReceivedGossip {
+	fn new(now: time::Instant) -> Self {
+		ReceivedGossip {
+			window_start: now,
+			messages: 0,
+			bytes: 0,
+			exceeded: false,
+			exceeded_windows: 0,
+		}
+	}
+
+	/// Counts a received message of `size` bytes.
+	fn admit(&mut self, quota: &GossipQuota, size: usize, now: time::Instant) -> GossipAdmission {
+		let elapsed = now - self.window_start;
+		if elapsed >= quota.window {
+			// The count of exceeded windows is reset by a window within the quota, including
+			// the ones that passed without messages.
+			let exceeded_windows = if self.exceeded && elapsed < 2 * quota.window {
+				self.exceeded_windows
+			} else {
+				0
+			};
+			*self = ReceivedGossip::new(now);
+			self.exceeded_windows = exceeded_windows;
+		}
+		if self.messages < quota.max_messages && self.bytes < quota.max_bytes {
+			self.messages += 1;
+			self.bytes += size;
+			return GossipAdmission::Admitted;
+		}
+		if self.exceeded {
+			return GossipAdmission::Dropped;
+		}
+		self.exceeded = true;
+		self.exceeded_windows += 1;
+		GossipAdmission::Exceeded(self.exceeded_windows)
+	}
+}
+
+/// Updates the rebroadcast schedule of the extrinsics of the pool, given by hash, and returns the
+/// ones due for a rebroadcast. Extrinsics no longer in the pool are forgotten; the rebroadcast of
+/// new and due ones is scheduled at `next`.
+fn schedule_rebroadcast<H: ::std::hash::Hash + Eq + Clone>(
+	schedule: &mut HashMap<H, time::Instant>,
+	ready: impl Iterator<Item = H>,
+	now: time::Instant,
+	next: impl Fn() -> time::Instant,
+) -> Vec<H> {
+	let mut due = Vec::new();
+	let mut rescheduled = HashMap::with_capacity(schedule.len());
+	for hash in ready {
+		let at = match schedule.get(&hash) {
+			Some(at) if *at <= now => {
+				due.push(hash.clone());
+				next()
+			},
+			Some(at) => *at,
+			None => next(),
+		};
+		rescheduled.insert(hash, at);
+	}
+	*schedule = rescheduled;
+	due
+}
+
+/// Picks the peers to propagate extrinsics to, given the connected ones with whether they are
+/// light and whether they miss some of the extrinsics.
+///
+/// Light peers don't keep a transaction pool, so they are never picked. The reserved peers
+/// missing extrinsics are all picked, and so is a random subset of the others, whose size is
+/// the square root of the number of full peers.
+fn select_propagation_targets(
+	peers: Vec<(NodeIndex, bool, bool)>,
+	is_reserved: impl Fn(NodeIndex) -> bool,
+) -> Vec<NodeIndex> {
+	let full_peers = peers.iter().filter(|(_, light, _)| !light).count();
+	let (mut targets, mut others): (Vec<NodeIndex>, Vec<NodeIndex>) = peers.into_iter()
+		.filter(|(_, light, missing_extrinsics)| !light && *missing_extrinsics)
+		.map(|(who, _, _)| who)
+		.partition(|who| is_reserved(*who));
+	simulation::shuffle(&mut others);
+	let num_others = (full_peers as f64).sqrt().ceil() as usize;
+	targets.extend(others.into_iter().take(num_others));
+	targets
+}
+
+/// Batches extrinsics, given with their encoded size, in as few messages of at most
+/// `max_message_bytes` as possible while keeping their order. An extrinsic larger than the
+/// limit is sent alone.
+fn batch_extrinsics<E>(extrinsics: Vec<(E, usize)>, max_message_bytes: usize) -> Vec<Vec<E>> {
+	let mut batches: Vec<Vec<E>> = Vec::new();
+	let mut batch_bytes = 0;
+	for (extrinsic, size) in extrinsics {
+		match batches.last_mut() {
+			Some(ref mut batch) if batch_bytes + size <= max_message_bytes => batch.push(extrinsic),
+			_ => {
+				batches.push(vec![extrinsic]);
+				batch_bytes = 0;
+			},
+		}
+		batch_bytes += size;
+	}
+	batches
+}
+
+/// Compresses a block response, unless compression doesn't make it smaller.
+fn compress_block_response<B: BlockT>(response: &message::BlockResponse<B>) -> Option<message::CompressedBlockResponse> {
+	let encoded = response.encode();
+	match snap::Encoder::new().compress_vec(&encoded) {
+		Ok(data) => if data.len() < encoded.len() {
+			trace!(target: "sync", "Compressed BlockResponse from {} to {} bytes", encoded.len(), data.len());
+			return Some(message::CompressedBlockResponse { id: response.id, data });
+		},
+		Err(e) => debug!(target: "sync", "Failed to compress BlockResponse: {}", e),
+	}
+	None
+}
+
+/// Decompresses a block response. Returns the response and its uncompressed size.
+///
+/// Like uncompressed responses, responses of more than one block larger than `max_bytes`
+/// are rejected, while a single block may be larger. How much a response decompresses to
+/// is bounded by the size of the packet, as snappy expands data by a small factor at most.
+fn decompress_block_response<B: BlockT>(
+	compressed: message::CompressedBlockResponse,
+	max_bytes: usize,
+) -> Result<(message::BlockResponse<B>, usize), String> {
+	let size = snap::decompress_len(&compressed.data)
+		.map_err(|e| format!("Invalid compressed block response: {}", e))?;
+	let data = snap::Decoder::new().decompress_vec(&compressed.data)
+		.map_err(|e| format!("Invalid compressed block response: {}", e))?;
+	let response: message::BlockResponse<B> = Decode::decode(&mut &data[..])
+		.ok_or_else(|| "Compressed block response with invalid format".to_string())?;
+	if response.id != compressed.id {
+		return Err(format!("Compressed block response {} contains response {}", compressed.id, response.id));
+	}
+	if size > max_bytes && response.blocks.len() > 1 {
+		return Err(format!("Compressed block response of {} bytes exceeds the limit", size));
+	}
+	Ok((response, size))
+}
+
+impl<B: BlockT, H: ExHashT> Peer<B, H> {
+	/// Ids of the in-flight requests whose deadline has passed.
+	fn expired_requests(&self, now: time::Instant) -> Vec<message::RequestId> {
+		let mut expired = self.requests.iter()
+			.filter(|&(_, &(_, deadline))| deadline < now)
+			.map(|(id, _)| *id)
+			.collect::<Vec<_>>();
+		expired.sort();
+		expired
+	}
+
+	fn has_block_request(&self) -> bool {
+		self.requests.values().any(|(r, _)| r.fields != message::BlockAttributes::JUSTIFICATION)
 	}
 }
 
@@ -121,12 +455,81 @@ pub struct PeerInfo<B: BlockT> {
 	pub roles: Roles,
 	/// Protocol version
 	pub protocol_version: u32,
+	/// Optional features supported by both sides.
+	pub features: message::Features,
+	/// Name and version of the peer's implementation, if it told us.
+	pub client_version: Option<String>,
+	/// Round-trip time to the peer, if measured.
+	pub latency: Option<time::Duration>,
 	/// Peer best block hash
 	pub best_hash: B::Hash,
 	/// Peer best block number
 	pub best_number: <B::Header as HeaderT>::Number,
 }
 
+/// Number of messages and bytes exchanged with peers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MessageCounters {
+	/// Number of messages received.
+	pub messages_received: u64,
+	/// Number of bytes received.
+	pub bytes_received: u64,
+	/// Number of messages sent.
+	pub messages_sent: u64,
+	/// Number of bytes sent.
+	pub bytes_sent: u64,
+}
+
+/// Extrinsic propagation counters, accumulated since the node started.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PropagationMetrics {
+	/// Number of extrinsics sent to at least one peer. Only the last 4096 announced extrinsics
+	/// are remembered, so one propagated again after that is counted again.
+	pub extrinsics_announced: u64,
+	/// Number of (peer, extrinsic) pairs where the extrinsic wasn't sent because the peer
+	/// already knew it.
+	pub duplicates_suppressed: u64,
+}
+
+/// Traffic broken down by message kind, as returned by `Message::kind`.
+pub type MessageStats = BTreeMap<&'static str, MessageCounters>;
+
+/// Traffic statistics of the protocol.
+#[derive(Debug, Default, Clone)]
+pub struct TrafficStats {
+	/// Traffic with all peers since the start, disconnected ones included.
+	pub total: MessageStats,
+	/// Traffic with each connected peer.
+	pub peers: BTreeMap<NodeIndex, MessageStats>,
+}
+
+impl TrafficStats {
+	fn note_received(&mut self, who: NodeIndex, kind: &'static str, bytes: usize) {
+		let note = |stats: &mut MessageStats| {
+			let counters = stats.entry(kind).or_insert_with(Default::default);
+			counters.messages_received += 1;
+			counters.bytes_received += bytes as u64;
+		};
+		note(&mut self.total);
+		// only peers connected to the protocol are tracked individually.
+		if let Some(stats) = self.peers.get_mut(&who) {
+			note(stats);
+		}
+	}
+
+	fn note_sent(&mut self, who: NodeIndex, kind: &'static str, bytes: usize) {
+		let note = |stats: &mut MessageStats| {
+			let counters = stats.entry(kind).or_insert_with(Default::default);
+			counters.messages_sent += 1;
+			counters.bytes_sent += bytes as u64;
+		};
+		note(&mut self.total);
+		if let Some(stats) = self.peers.get_mut(&who) {
+			note(stats);
+		}
+	}
+}
+
 /// Context for a network-specific handler.
 pub trait Context<B: BlockT> {
 	/// Get a reference to the client.
@@ -135,11 +538,23 @@ pub trait Context<B: BlockT> {
 	/// Point out that a peer has been malign or irresponsible or appeared lazy.
 	fn report_peer(&mut self, who: NodeIndex, reason: Severity);
 
+	/// Raise or lower the reputation of a peer. Peers whose reputation drops
+	/// too low are disconnected, and banned for a while.
+	fn change_reputation(&mut self, who: NodeIndex, change: ReputationChange);
+
 	/// Get peer info.
 	fn peer_info(&self, peer: NodeIndex) -> Option<PeerInfo<B>>;
 
 	/// Send a message to a peer.
 	fn send_message(&mut self, who: NodeIndex, data: ::message::Message<B>);
+
+	/// Drop the in-flight block requests to a peer that match the filter and
+	/// tell the peer it can stop serving them. Late responses are ignored.
+	fn cancel_requests(&mut self, who: NodeIndex, filter: &Fn(&message::BlockRequest<B>) -> bool);
+
+	/// Whether a block request to a peer matching the filter is deferred until
+	/// the peer answers the previous ones.
+	fn is_request_deferred(&self, who: NodeIndex, filter: &Fn(&message::BlockRequest<B>) -> bool) -> bool;
 }
 
 /// Protocol context.
@@ -158,7 +573,7 @@ impl<'a, B: BlockT + 'a, H: 'a + ExHashT> ProtocolContext<'a, B, H> {
 
 	/// Send a message to a peer.
 	pub fn send_message(&mut self, who: NodeIndex, message: Message<B>) {
-		send_message(&self.context_data.peers, self.io, who, message)
+		send_message(self.context_data, self.io, who, message)
 	}
 
 	/// Point out that a peer has been malign or irresponsible or appeared lazy.
@@ -166,12 +581,61 @@ impl<'a, B: BlockT + 'a, H: 'a + ExHashT> ProtocolContext<'a, B, H> {
 		self.io.report_peer(who, reason);
 	}
 
+	/// Raise or lower the reputation of a peer.
+	pub fn change_reputation(&mut self, who: NodeIndex, change: ReputationChange) {
+		self.io.change_reputation(who, change);
+	}
+
+	/// Drop in-flight block requests to a peer and tell the peer about it.
+	pub fn cancel_requests(&mut self, who: NodeIndex, filter: &Fn(&message::BlockRequest<B>) -> bool) {
+		let (ids, notify) = {
+			let mut peers = self.context_data.peers.write();
+			let peer = match peers.get_mut(&who) {
+				Some(peer) => peer,
+				None => return,
+			};
+			// deferred requests were never sent, they are simply forgotten.
+			peer.deferred_requests.retain(|request| !filter(request));
+			let ids: Vec<_> = peer.requests.iter()
+				.filter(|&(_, &(ref request, _))| filter(request))
+				.map(|(id, _)| *id)
+				.collect();
+			for id in &ids {
+				peer.requests.remove(id);
+				peer.cancelled_requests.insert(*id);
+			}
+			// peers that honour the cancellation never respond, forget the oldest ids.
+			while peer.cancelled_requests.len() > MAX_CANCELLED_REQUESTS {
+				let oldest = *peer.cancelled_requests.iter().next().expect("set is not empty; qed");
+				peer.cancelled_requests.remove(&oldest);
+			}
+			(ids, peer.protocol_version >= CANCEL_REQUEST_VERSION)
+		};
+
+		for id in ids {
+			trace!(target: "sync", "Cancelling request {} to {}", id, who);
+			if notify {
+				self.send_message(who, GenericMessage::CancelRequest(message::CancelRequest { id }));
+			}
+		}
+		send_deferred_requests(self.context_data, self.io, who);
+	}
+
+	/// Whether a block request to a peer matching the filter waits to be sent.
+	pub fn is_request_deferred(&self, who: NodeIndex, filter: &Fn(&message::BlockRequest<B>) -> bool) -> bool {
+		self.context_data.peers.read().get(&who)
+			.map_or(false, |peer| peer.deferred_requests.iter().any(|request| filter(request)))
+	}
+
 	/// Get peer info.
 	pub fn peer_info(&self, peer: NodeIndex) -> Option<PeerInfo<B>> {
 		self.context_data.peers.read().get(&peer).map(|p| {
 			PeerInfo {
 				roles: p.roles,
 				protocol_version: p.protocol_version,
+				features: p.features,
+				client_version: p.client_version.clone(),
+				latency: p.latency,
 				best_hash: p.best_hash,
 				best_number: p.best_number,
 			}
@@ -188,10 +652,22 @@ impl<'a, B: BlockT + 'a, H: ExHashT + 'a> Context<B> for ProtocolContext<'a, B,
 		ProtocolContext::report_peer(self, who, reason);
 	}
 
+	fn change_reputation(&mut self, who: NodeIndex, change: ReputationChange) {
+		ProtocolContext::change_reputation(self, who, change);
+	}
+
 	fn peer_info(&self, who: NodeIndex) -> Option<PeerInfo<B>> {
 		ProtocolContext::peer_info(self, who)
 	}
 
+	fn cancel_requests(&mut self, who: NodeIndex, filter: &Fn(&message::BlockRequest<B>) -> bool) {
+		ProtocolContext::cancel_requests(self, who, filter);
+	}
+
+	fn is_request_deferred(&self, who: NodeIndex, filter: &Fn(&message::BlockRequest<B>) -> bool) -> bool {
+		ProtocolContext::is_request_deferred(self, who, filter)
+	}
+
 	fn client(&self) -> &Client<B> {
 		&*self.context_data.chain
 	}
@@ -200,8 +676,14 @@ impl<'a, B: BlockT + 'a, H: ExHashT + 'a> Context<B> for ProtocolContext<'a, B,
 /// Data necessary to create a context.
 pub(crate) struct ContextData<B: BlockT, H: ExHashT> {
 	// All connected peers
-	peers: RwLock<HashMap<NodeIndex, Peer<B, H>>>,
+	peers: RwLock<BTreeMap<NodeIndex, Peer<B, H>>>,
 	pub chain: Arc<Client<B>>,
+	// Maximum number of our block requests in flight to a single peer.
+	max_requests_in_flight: usize,
+	// Traffic statistics. Never held while acquiring another lock.
+	traffic: Mutex<TrafficStats>,
+	// Messages held back for clogged peers. Never held while acquiring another lock.
+	outbound: Mutex<OutboundQueue>,
 }
 
 impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
@@ -217,24 +699,75 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		where I: ImportQueue<B>
 	{
 		let info = chain.info()?;
-		let sync = ChainSync::new(config.roles, &info, import_queue);
+		let sync = ChainSync::new(&config, &*chain, &info, import_queue);
 		let protocol = Protocol {
-			config: config,
 			context_data: ContextData {
-				peers: RwLock::new(HashMap::new()),
+				peers: RwLock::new(BTreeMap::new()),
 				chain,
+				max_requests_in_flight: config.max_requests_in_flight,
+				traffic: Mutex::new(TrafficStats::default()),
+				outbound: Mutex::new(OutboundQueue::new(config.max_queued_outbound_messages)),
 			},
 			on_demand,
 			genesis_hash: info.chain.genesis_hash,
 			sync: Arc::new(RwLock::new(sync)),
 			specialization: RwLock::new(specialization),
-			consensus_gossip: RwLock::new(ConsensusGossip::new()),
+			consensus_gossip: RwLock::new(ConsensusGossip::with_seen_messages(
+				config.max_seen_gossip_messages,
+				config.seen_gossip_messages_ttl,
+			)),
+			notifications: RwLock::new(Notifications::new()),
+			authorities: Mutex::new(LinkedHashMap::new()),
 			handshaking_peers: RwLock::new(HashMap::new()),
 			transaction_pool: transaction_pool,
+			chain_progress: RwLock::new(ChainProgress {
+				best_seen_block: None,
+				last_progress: simulation::now(),
+				rotated_peers: 0,
+			}),
+			authored_blocks: RwLock::new(VecDeque::new()),
+			block_requests: Arc::new(BlockRequestQueue::new(config.max_queued_block_requests)),
+			was_major_syncing: AtomicBool::new(false),
+			extrinsics_rebroadcast: Mutex::new(HashMap::new()),
+			propagation: Mutex::new((LruHashSet::new(MAX_KNOWN_EXTRINSICS), PropagationMetrics::default())),
+			propagation_requested: Arc::new(AtomicBool::new(false)),
+			config: config,
 		};
 		Ok(protocol)
 	}
 
+	/// Start the threads serving block requests. They send the responses with
+	/// an I/O handle provided by `with_io`, and stop with the protocol.
+	pub(crate) fn start_block_request_workers(
+		protocol: &Arc<Self>,
+		with_io: Arc<Fn(&mut FnMut(&mut SyncIo)) + Send + Sync>,
+	) -> ::std::io::Result<()> where B: 'static {
+		if protocol.config.block_request_workers == 0 {
+			return Ok(());
+		}
+		for index in 0 .. protocol.config.block_request_workers {
+			let queue = protocol.block_requests.clone();
+			let protocol = Arc::downgrade(protocol);
+			let with_io = with_io.clone();
+			thread::Builder::new().name(format!("block-requests-{}", index)).spawn(move || {
+				while let Some((who, request)) = queue.pop() {
+					let protocol = match protocol.upgrade() {
+						Some(protocol) => protocol,
+						None => break,
+					};
+					let mut request = Some(request);
+					with_io(&mut |io| {
+						if let Some(request) = request.take() {
+							protocol.serve_block_request(io, who, request);
+						}
+					});
+				}
+			})?;
+		}
+		protocol.block_requests.start();
+		Ok(())
+	}
+
 	pub(crate) fn context_data(&self) -> &ContextData<B, H> {
 		&self.context_data
 	}
@@ -243,6 +776,32 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		&self.sync
 	}
 
+	/// Returns the requests in flight with the given peer, ordered by id.
+	#[cfg(test)]
+	pub(crate) fn requests_in_flight(&self, who: NodeIndex) -> Vec<message::BlockRequest<B>> {
+		let peers = self.context_data.peers.read();
+		let mut requests = peers.get(&who)
+			.map(|peer| peer.requests.values().map(|(request, _)| request.clone()).collect::<Vec<_>>())
+			.unwrap_or_default();
+		requests.sort_by_key(|request| request.id);
+		requests
+	}
+
+	/// Returns the traffic statistics of the protocol.
+	pub fn traffic_stats(&self) -> TrafficStats {
+		self.context_data.traffic.lock().clone()
+	}
+
+	/// Returns the gossip message counters of the most recently active topics.
+	pub fn gossip_metrics(&self) -> Vec<(B::Hash, TopicMetrics)> {
+		self.consensus_gossip.read().metrics()
+	}
+
+	/// Returns the extrinsic propagation counters.
+	pub fn propagation_metrics(&self) -> PropagationMetrics {
+		self.propagation.lock().1
+	}
+
 	pub(crate) fn consensus_gossip<'a>(&'a self) -> &'a RwLock<ConsensusGossip<B>> {
 		&self.consensus_gossip
 	}
@@ -254,10 +813,26 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		ProtocolStatus {
 			sync: sync.status(),
 			num_peers: peers.values().count(),
-			num_active_peers: peers.values().filter(|p| p.block_request.is_some()).count(),
+			num_active_peers: peers.values().filter(|p| p.has_block_request()).count(),
+			chain_freeze_rotations: self.chain_progress.read().rotated_peers,
 		}
 	}
 
+	/// Returns the occupancy of the block download window.
+	pub fn download_stats(&self) -> DownloadStats<NumberFor<B>> {
+		self.sync.read().download_stats()
+	}
+
+	/// Returns sync metrics.
+	pub fn sync_metrics(&self) -> SyncMetrics {
+		self.sync.read().metrics()
+	}
+
+	/// Returns the state of the tracked justification requests.
+	pub fn justification_requests(&self) -> Vec<ExtraRequestInfo<B>> {
+		self.sync.read().justification_requests()
+	}
+
 	pub fn peers(&self) -> Vec<(NodeIndex, PeerInfo<B>)> {
 		self.context_data.peers.read().iter().map(|(idx, p)| {
 			(
@@ -265,6 +840,9 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				PeerInfo {
 					roles: p.roles,
 					protocol_version: p.protocol_version,
+					features: p.features,
+					client_version: p.client_version.clone(),
+					latency: p.latency,
 					best_hash: p.best_hash,
 					best_number: p.best_number,
 				}
@@ -275,58 +853,26 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 	fn handle_response(&self, io: &mut SyncIo, who: NodeIndex, response: &message::BlockResponse<B>) -> Option<message::BlockRequest<B>> {
 		let mut peers = self.context_data.peers.write();
 		let request = if let Some(ref mut peer) = peers.get_mut(&who) {
-			match (peer.block_request.take(), peer.justification_request.take()) {
-				(Some(block_request), Some(justification_request)) => {
-					if block_request.id == response.id {
-						peer.block_request_timestamp = None;
-						peer.justification_request = Some(justification_request);
-						block_request
-					} else if justification_request.id == response.id {
-						peer.justification_request_timestamp = None;
-						peer.block_request = Some(block_request);
-						justification_request
-					} else {
-						peer.justification_request_timestamp = None;
-						peer.block_request_timestamp = None;
-						trace!(target: "sync", "Ignoring mismatched response packet from {} (expected {} or {} got {})",
-							who,
-							block_request.id,
-							justification_request.id,
-							response.id,
-						);
-						return None;
-					}
-				},
-				(Some(block_request), None) => {
-					if block_request.id == response.id {
-						peer.block_request_timestamp = None;
-						block_request
-					} else {
-						peer.block_request_timestamp = None;
-						trace!(target: "sync", "Ignoring mismatched response packet from {} (expected {} got {})",
-							who,
-							block_request.id,
-							response.id,
-						);
-						return None;
-					}
-				},
-				(None, Some(justification_request)) => {
-					if justification_request.id == response.id {
-						peer.justification_request_timestamp = None;
-						justification_request
-					} else {
-						peer.justification_request_timestamp = None;
-						trace!(target: "sync", "Ignoring mismatched response packet from {} (expected {} got {})",
-							who,
-							justification_request.id,
-							response.id,
-						);
-						return None;
+			if peer.cancelled_requests.remove(&response.id) {
+				trace!(target: "sync", "Ignoring response to cancelled request {} from {}", response.id, who);
+				return None;
+			}
+			match peer.requests.remove(&response.id) {
+				Some((request, _)) => request,
+				None => {
+					trace!(target: "sync", "Ignoring mismatched response packet from {} (expected one of {:?} got {})",
+						who,
+						peer.requests.keys().collect::<Vec<_>>(),
+						response.id,
+					);
+					// ids are issued in order: a response to an id never issued is made up, while one
+					// to an earlier id is a duplicate or answers a cancellation we forgot about. Peers
+					// that don't know about cancellations answer every request, so they aren't blamed.
+					if response.id >= peer.next_request_id {
+						io.report_peer(who, Severity::Bad("Response to a request that was never sent"));
+					} else if peer.protocol_version >= CANCEL_REQUEST_VERSION {
+						io.report_peer(who, Severity::Useless("Response to a request that is not in flight"));
 					}
-				},
-				(None, None) => {
-					io.report_peer(who, Severity::Bad("Unexpected response packet received from peer"));
 					return None;
 				},
 			}
@@ -339,6 +885,7 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 	}
 
 	pub fn handle_packet(&self, io: &mut SyncIo, who: NodeIndex, mut data: &[u8]) {
+		let size = data.len();
 		let message: Message<B> = match Decode::decode(&mut data) {
 			Some(m) => m,
 			None => {
@@ -347,16 +894,27 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				return;
 			}
 		};
+		self.context_data.traffic.lock().note_received(who, message.kind(), size);
+
+		if is_gossip(&message) && !self.admit_gossip(io, who, size) {
+			return;
+		}
 
 		match message {
 			GenericMessage::Status(s) => self.on_status_message(io, who, s),
 			GenericMessage::BlockRequest(r) => self.on_block_request(io, who, r),
-			GenericMessage::BlockResponse(r) => {
-				if let Some(request) = self.handle_response(io, who, &r) {
-					self.on_block_response(io, who, request, r);
+			GenericMessage::BlockResponse(r) => self.on_block_response_packet(io, who, r, size),
+			GenericMessage::CompressedBlockResponse(r) => {
+				match decompress_block_response(r, self.config.max_accepted_block_response_bytes) {
+					Ok((response, size)) => self.on_block_response_packet(io, who, response, size),
+					Err(reason) => {
+						trace!(target: "sync", "Invalid compressed block response from {}: {}", who, reason);
+						io.report_peer(who, Severity::Bad(&reason));
+					},
 				}
 			},
 			GenericMessage::BlockAnnounce(announce) => self.on_block_announce(io, who, announce),
+			GenericMessage::FinalizedAnnounce(announce) => self.on_finalized_announce(who, announce),
 			GenericMessage::Transactions(m) => self.on_extrinsics(io, who, m),
 			GenericMessage::RemoteCallRequest(request) => self.on_remote_call_request(io, who, request),
 			GenericMessage::RemoteCallResponse(response) => self.on_remote_call_response(io, who, response),
@@ -366,15 +924,54 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 			GenericMessage::RemoteHeaderResponse(response) => self.on_remote_header_response(io, who, response),
 			GenericMessage::RemoteChangesRequest(request) => self.on_remote_changes_request(io, who, request),
 			GenericMessage::RemoteChangesResponse(response) => self.on_remote_changes_response(io, who, response),
+			GenericMessage::RemoteBodyRequest(request) => self.on_remote_body_request(io, who, request),
+			GenericMessage::RemoteBodyResponse(response) => self.on_remote_body_response(io, who, response),
+			GenericMessage::CancelRequest(cancel) => {
+				// requests are answered as soon as they arrive, unless delayed by the quota.
+				trace!(target: "sync", "Request {} cancelled by {}", cancel.id, who);
+				if let Some(peer) = self.context_data.peers.write().get_mut(&who) {
+					peer.delayed_requests.retain(|request| request.id != cancel.id);
+				}
+				self.block_requests.cancel(who, cancel.id);
+			},
 			GenericMessage::Consensus(topic, msg, broadcast) => {
 				self.consensus_gossip.write().on_incoming(&mut ProtocolContext::new(&self.context_data, io), who, topic, msg, broadcast);
 			},
+			GenericMessage::Ping(nonce) => self.send_message(io, who, GenericMessage::Pong(nonce)),
+			GenericMessage::Pong(nonce) => self.on_pong(who, nonce),
+			GenericMessage::AuthorityConsensus(topic, msg, broadcast) => self.on_authority_consensus(io, who, topic, msg, broadcast),
+			GenericMessage::EngineConsensus(engine_id, topic, msg) => self.on_engine_consensus(io, who, engine_id, topic, msg),
+			GenericMessage::NotificationsOpen(open) => self.notifications.write().on_open(who, open),
+			GenericMessage::Notification(notification) => self.notifications.write().on_notification(who, notification),
 			other => self.specialization.write().on_message(&mut ProtocolContext::new(&self.context_data, io), who, &mut Some(other)),
 		}
 	}
 
+	// Counts a gossip message against the quota of the peer, and penalizes the peer if it
+	// exceeds it repeatedly. Returns false if the message must be dropped.
+	fn admit_gossip(&self, io: &mut SyncIo, who: NodeIndex, size: usize) -> bool {
+		let quota = &self.config.gossip_quota;
+		let exceeded_windows = match self.context_data.peers.write().get_mut(&who) {
+			Some(peer) => match peer.gossip.admit(quota, size, simulation::now()) {
+				GossipAdmission::Admitted => return true,
+				GossipAdmission::Dropped => return false,
+				GossipAdmission::Exceeded(windows) => windows,
+			},
+			// Handshaking peers can't gossip yet.
+			None => return true,
+		};
+
+		trace!(target: "sync", "{} exceeded the gossip quota in {} consecutive windows", who, exceeded_windows);
+		if exceeded_windows >= quota.max_exceeded_windows {
+			io.report_peer(who, Severity::Useless("Peer flooded us with gossip"));
+		} else if exceeded_windows > 1 {
+			io.change_reputation(who, ReputationChange::new(quota.reputation_change, "Peer exceeded the gossip quota"));
+		}
+		false
+	}
+
 	pub fn send_message(&self, io: &mut SyncIo, who: NodeIndex, message: Message<B>) {
-		send_message::<B, H>(&self.context_data.peers, io, who, message)
+		send_message::<B, H>(&self.context_data, io, who, message)
 	}
 
 	pub fn gossip_consensus_message(&self, io: &mut SyncIo, topic: B::Hash, message: Vec<u8>, broadcast: bool) {
@@ -384,10 +981,127 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		});
 	}
 
+	/// Send a message signed by an authority to the peers, which check the signature and
+	/// that the signer is an authority before relaying it.
+	pub fn gossip_authority_message(&self, io: &mut SyncIo, topic: B::Hash, message: message::SignedConsensusMessage, broadcast: bool) {
+		let gossip = self.consensus_gossip();
+		self.with_spec(io, move |_s, context|{
+			gossip.write().multicast_authenticated(context, topic, message, broadcast);
+		});
+	}
+
+	/// Send a message of a consensus engine to all peers understanding engine messages,
+	/// without checking it with the validator of the engine.
+	pub fn gossip_engine_message(&self, io: &mut SyncIo, engine_id: message::ConsensusEngineId, topic: B::Hash, message: Vec<u8>) {
+		let gossip = self.consensus_gossip();
+		self.with_spec(io, move |_s, context|{
+			gossip.write().multicast_engine(context, engine_id, topic, message);
+		});
+	}
+
+	fn on_engine_consensus(&self, io: &mut SyncIo, who: NodeIndex, engine_id: message::ConsensusEngineId, topic: B::Hash, data: Vec<u8>) {
+		// the validator runs without the gossip locked, and not for messages already seen.
+		let validator = {
+			let gossip = self.consensus_gossip.read();
+			if gossip.is_known_engine_message(engine_id, topic, &data) {
+				trace!(target: "gossip", "Ignored already known message from {} in {}", who, topic);
+				return;
+			}
+			match gossip.validator(engine_id) {
+				Some(validator) => validator,
+				None => {
+					trace!(target: "gossip", "Ignored message of unknown engine {:?} from {}", engine_id, who);
+					return;
+				},
+			}
+		};
+		let validation = validator.validate(&topic, &data);
+		let result = {
+			let mut context = ProtocolContext::new(&self.context_data, io);
+			self.consensus_gossip.write().on_incoming_engine(&mut context, who, engine_id, topic, data, validation)
+		};
+		if let Err(reason) = result {
+			io.change_reputation(who, ReputationChange::new(DISCARDED_ENGINE_MESSAGE_REPUTATION_CHANGE, reason));
+		}
+	}
+
+	fn on_authority_consensus(&self, io: &mut SyncIo, who: NodeIndex, topic: B::Hash, data: Vec<u8>, broadcast: bool) {
+		// copies of known messages were checked already.
+		if self.consensus_gossip.read().is_known_message(topic, &data, true) {
+			trace!(target: "gossip", "Ignored already known authority message from {} in {}", who, topic);
+			return;
+		}
+		let signed = match message::SignedConsensusMessage::decode(&mut &data[..]) {
+			Some(signed) => signed,
+			None => {
+				io.report_peer(who, Severity::Bad("Peer sent an invalid authority message"));
+				return;
+			},
+		};
+		if !signed.verify(&topic) {
+			trace!(target: "gossip", "Forged authority message from {} in {}", who, topic);
+			io.report_peer(who, Severity::Bad("Peer sent an authority message with a bad signature"));
+			return;
+		}
+		// the authority set may have changed after the peer got the message, so it isn't
+		// held against the peer.
+		if !self.is_authority(&signed.signer) {
+			trace!(target: "gossip", "Authority message from {} in {} signed by non-authority {}", who, topic, signed.signer);
+			return;
+		}
+		let mut context = ProtocolContext::new(&self.context_data, io);
+		self.consensus_gossip.write().on_incoming_authenticated(&mut context, who, topic, data, broadcast);
+	}
+
+	// Whether the key belongs to an authority at our best block. The topic is chosen by the
+	// sender, so signers aren't checked at the block it names: keys removed from the set
+	// would be accepted forever by naming an old block.
+	fn is_authority(&self, key: &::primitives::ed25519::Public) -> bool {
+		let best_hash = match self.context_data.chain.info() {
+			Ok(info) => info.chain.best_hash,
+			Err(_) => return false,
+		};
+		let key = key.encode();
+
+		let mut cached = self.authorities.lock();
+		if let Some(authorities) = cached.get_refresh(&best_hash) {
+			return authorities.contains(&key);
+		}
+		let authorities: Vec<Vec<u8>> = match self.context_data.chain.authorities(&BlockId::Hash(best_hash)) {
+			Ok(authorities) => authorities.iter().map(|authority| authority.encode()).collect(),
+			Err(e) => {
+				debug!(target: "gossip", "Error reading authorities at {}: {:?}", best_hash, e);
+				return false;
+			},
+		};
+		let is_authority = authorities.contains(&key);
+		cached.insert(best_hash, authorities);
+		while cached.len() > MAX_CACHED_AUTHORITY_SETS {
+			cached.pop_front();
+		}
+		is_authority
+	}
+
+	pub fn register_notifications_protocol(
+		&self,
+		io: &mut SyncIo,
+		name: ProtocolName,
+		handshake: Vec<u8>,
+	) -> error::Result<mpsc::UnboundedReceiver<NotificationEvent>> {
+		let mut context = ProtocolContext::new(&self.context_data, io);
+		self.notifications.write().register(&mut context, name, handshake)
+	}
+
+	pub fn send_notification(&self, io: &mut SyncIo, who: NodeIndex, name: &[u8], data: Vec<u8>) -> bool {
+		let mut context = ProtocolContext::new(&self.context_data, io);
+		self.notifications.write().send(&mut context, who, name, data)
+	}
+
 	/// Called when a new peer is connected
 	pub fn on_peer_connected(&self, io: &mut SyncIo, who: NodeIndex) {
 		trace!(target: "sync", "Connected {}: {}", who, io.peer_debug_info(who));
-		self.handshaking_peers.write().insert(who, time::Instant::now());
+		self.handshaking_peers.write().insert(who, simulation::now());
+		self.context_data.traffic.lock().peers.insert(who, MessageStats::new());
 		self.send_status(io, who);
 	}
 
@@ -405,13 +1119,23 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 			handshaking_peers.remove(&peer);
 			peers.remove(&peer).is_some()
 		};
+		self.context_data.traffic.lock().peers.remove(&peer);
+		self.context_data.outbound.lock().remove_peer(peer);
 		if removed {
 			let mut context = ProtocolContext::new(&self.context_data, io);
 			self.consensus_gossip.write().peer_disconnected(&mut context, peer);
+			self.notifications.write().peer_disconnected(peer);
 			sync.peer_disconnected(&mut context, peer);
 			spec.on_disconnect(&mut context, peer);
 			self.on_demand.as_ref().map(|s| s.on_disconnect(peer));
+			self.block_requests.remove_peer(peer);
 		}
+
+		// the peer may have been the last one holding back downloaded blocks.
+		let ready = sync.take_ready_blocks();
+		drop(spec);
+		drop(sync);
+		self.import_ready_blocks(ready);
 	}
 
 	/// Called as a back-pressure mechanism if the networking detects that the peer cannot process
@@ -422,12 +1146,18 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		who: NodeIndex,
 		clogging_messages: impl ExactSizeIterator<Item = &'a [u8]>
 	) {
-		// We don't do anything but print some diagnostics for now.
-		if let Some(peer) = self.context_data.peers.read().get(&who) {
+		// low-priority messages wait for the next tick, consensus messages still go through.
+		self.context_data.outbound.lock().set_clogged(who);
+
+		if let Some(peer) = self.context_data.peers.write().get_mut(&who) {
 			debug!(target: "sync", "Clogged peer {} (protocol_version: {:?}; roles: {:?}; \
 				known_extrinsics: {:?}; known_blocks: {:?}; best_hash: {:?}; best_number: {:?})",
 				who, peer.protocol_version, peer.roles, peer.known_extrinsics, peer.known_blocks,
 				peer.best_hash, peer.best_number);
+			// A ping stuck behind the clogging messages would time out, and wouldn't measure
+			// the latency anyway: it is forgotten, and the next one waits for a full interval.
+			peer.pending_ping = None;
+			peer.last_ping = Some(simulation::now());
 		} else {
 			debug!(target: "sync", "Peer clogged before being properly connected");
 		}
@@ -452,22 +1182,175 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 			request.max,
 		);
 
-		let mut blocks = Vec::new();
-		let mut id = match request.from {
-			message::FromBlock::Hash(h) => BlockId::Hash(h),
-			message::FromBlock::Number(n) => BlockId::Number(n),
+		// requests are served in order, later ones wait behind delayed ones.
+		let quota = &self.config.block_request_quota;
+		let delay = match self.context_data.peers.write().get_mut(&peer) {
+			Some(p) => !p.delayed_requests.is_empty() || !p.served.admit(quota, simulation::now()),
+			None => false,
+		};
+		if !delay {
+			self.dispatch_block_request(io, peer, request);
+			return;
+		}
+
+		trace!(target: "sync", "Delaying BlockRequest {} from {}, quota exceeded", request.id, peer);
+		let flooding = match self.context_data.peers.write().get_mut(&peer) {
+			Some(p) => {
+				p.delayed_requests.push_back(request);
+				p.delayed_requests.len() > quota.max_delayed
+			},
+			None => false,
+		};
+		if flooding {
+			io.report_peer(peer, Severity::Useless("Peer sent too many block requests"));
+		}
+	}
+
+	// Ping the peers supporting it whose latency is due to be measured again.
+	fn ping_peers(&self, io: &mut SyncIo) {
+		let now = simulation::now();
+		let mut pings = Vec::new();
+		{
+			let mut peers = self.context_data.peers.write();
+			for (who, peer) in peers.iter_mut() {
+				let due = peer.pending_ping.is_none() && peer.last_ping.map_or(true, |last| now - last >= PING_INTERVAL);
+				if due && peer.features.contains(message::Features::PING) {
+					let nonce = simulation::random();
+					peer.pending_ping = Some((nonce, now));
+					peer.last_ping = Some(now);
+					pings.push((*who, nonce));
+				}
+			}
+		}
+		for (who, nonce) in pings {
+			self.send_message(io, who, GenericMessage::Ping(nonce));
+		}
+	}
+
+	fn on_pong(&self, who: NodeIndex, nonce: u64) {
+		let latency = {
+			let mut peers = self.context_data.peers.write();
+			let peer = match peers.get_mut(&who) {
+				Some(peer) => peer,
+				None => return,
+			};
+			let pending_ping = peer.pending_ping;
+			let sent = match pending_ping {
+				Some((expected, sent)) if expected == nonce => sent,
+				_ => {
+					trace!(target: "sync", "Unexpected pong from {}", who);
+					return;
+				},
+			};
+			let rtt = simulation::now() - sent;
+			let latency = peer.latency.map_or(rtt, |latency| (latency * 7 + rtt) / 8);
+			peer.pending_ping = None;
+			peer.latency = Some(latency);
+			latency
 		};
+		trace!(target: "sync", "Latency to {}: {:?}", who, latency);
+		self.sync.write().note_latency(who, latency);
+	}
+
+	// Send the messages held back while peers were clogged.
+	fn release_outbound_messages(&self, io: &mut SyncIo) {
+		let released = self.context_data.outbound.lock().drain(MAX_RELEASED_MESSAGES);
+		for (who, kind, data) in released {
+			self.context_data.traffic.lock().note_sent(who, kind, data.len());
+			io.send(who, data);
+		}
+	}
+
+	// Serve the delayed block requests the quota of their peer now allows.
+	fn serve_delayed_block_requests(&self, io: &mut SyncIo) {
+		let now = simulation::now();
+		let quota = &self.config.block_request_quota;
+		let mut ready = Vec::new();
+		for (who, peer) in self.context_data.peers.write().iter_mut() {
+			while !peer.delayed_requests.is_empty() && peer.served.admit(quota, now) {
+				let request = peer.delayed_requests.pop_front().expect("checked to be non-empty above; qed");
+				ready.push((*who, request));
+			}
+		}
+		for (who, request) in ready {
+			self.dispatch_block_request(io, who, request);
+		}
+	}
+
+	// Hand a block request over to the worker threads, or serve it right away
+	// if there are none.
+	fn dispatch_block_request(&self, io: &mut SyncIo, peer: NodeIndex, request: message::BlockRequest<B>) {
+		if !self.block_requests.is_started() {
+			self.serve_block_request(io, peer, request);
+			return;
+		}
+		if self.block_requests.queued(peer) >= self.config.max_queued_requests_per_peer {
+			// answer right away, so that the peer asks someone else rather than waiting
+			// for its request to time out.
+			trace!(target: "sync", "Too many queued block requests from {}, refusing {}", peer, request.id);
+			let response = message::generic::BlockResponse { id: request.id, blocks: Vec::new() };
+			self.send_message(io, peer, GenericMessage::BlockResponse(response));
+			return;
+		}
+		if let Err(request) = self.block_requests.push(peer, request) {
+			// an empty response would tell the peer we don't have the blocks, so the
+			// request waits for a later tick instead.
+			let mut peers = self.context_data.peers.write();
+			match peers.get_mut(&peer) {
+				Some(ref mut p) if p.delayed_requests.len() < self.config.block_request_quota.max_delayed => {
+					trace!(target: "sync", "Too many queued block requests, delaying {} from {}", request.id, peer);
+					p.delayed_requests.push_front(request);
+				},
+				_ => trace!(target: "sync", "Too many queued block requests, dropping {} from {}", request.id, peer),
+			}
+		}
+	}
+
+	fn serve_block_request(&self, io: &mut SyncIo, peer: NodeIndex, request: message::BlockRequest<B>) {
 		let max = cmp::min(request.max.unwrap_or(u32::max_value()), MAX_BLOCK_DATA_RESPONSE) as usize;
 		let get_header = request.fields.contains(message::BlockAttributes::HEADER);
-		let get_body = request.fields.contains(message::BlockAttributes::BODY);
+		// light nodes don't store bodies, reading one would fetch it from the network.
+		let get_body = request.fields.contains(message::BlockAttributes::BODY)
+			&& self.config.roles & Roles::LIGHT != Roles::LIGHT;
 		let get_justification = request.fields.contains(message::BlockAttributes::JUSTIFICATION);
-		while let Some(header) = self.context_data.chain.header(&id).unwrap_or(None) {
-			if blocks.len() >= max {
-				break;
-			}
-			let number = header.number().clone();
+		let headers = match request.direction {
+			message::Direction::Ascending => {
+				let mut headers = Vec::new();
+				let mut id = match request.from {
+					message::FromBlock::Hash(h) => BlockId::Hash(h),
+					message::FromBlock::Number(n) => BlockId::Number(n),
+				};
+				while headers.len() < max {
+					let header = match self.context_data.chain.header(&id).unwrap_or(None) {
+						Some(header) => header,
+						None => break,
+					};
+					let is_last = request.to == Some(header.hash());
+					id = BlockId::Number(*header.number() + As::sa(1));
+					headers.push(header);
+					if is_last {
+						break;
+					}
+				}
+				headers
+			},
+			message::Direction::Descending => {
+				// ancestors are read by following parent hashes, which the chain
+				// can serve without resolving each block through the number index.
+				let start = match request.from {
+					message::FromBlock::Hash(h) => Some(h),
+					message::FromBlock::Number(n) => self.context_data.chain.block_hash(n).unwrap_or(None),
+				};
+				start
+					.and_then(|hash| self.context_data.chain.header_ancestry(hash, max).ok())
+					.unwrap_or_default()
+			},
+		};
+
+		let mut blocks = Vec::with_capacity(headers.len());
+		let mut response_bytes = 0;
+		for header in headers {
 			let hash = header.hash();
-			let parent_hash = header.parent_hash().clone();
 			let justification = if get_justification { self.context_data.chain.justification(&BlockId::Hash(hash)).unwrap_or(None) } else { None };
 			let block_data = message::generic::BlockData {
 				hash: hash,
@@ -477,25 +1360,52 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				message_queue: None,
 				justification,
 			};
+			// the requester continues from the last block it receives.
+			let block_bytes = block_data.using_encoded(|data| data.len());
+			if response_bytes + block_bytes > self.config.max_block_response_bytes && !blocks.is_empty() {
+				trace!(target: "sync", "Truncating BlockResponse to {} blocks ({} bytes)", blocks.len(), response_bytes);
+				break;
+			}
+			response_bytes += block_bytes;
 			blocks.push(block_data);
-			match request.direction {
-				message::Direction::Ascending => id = BlockId::Number(number + As::sa(1)),
-				message::Direction::Descending => {
-					if number == As::sa(0) {
-						break;
-					}
-					id = BlockId::Hash(parent_hash)
-				}
+			if request.to == Some(hash) {
+				break;
 			}
 		}
+		if let Some(p) = self.context_data.peers.write().get_mut(&peer) {
+			p.served.bytes += response_bytes;
+		}
 		let response = message::generic::BlockResponse {
 			id: request.id,
 			blocks: blocks,
 		};
 		trace!(target: "sync", "Sending BlockResponse with {} blocks", response.blocks.len());
+		let compress = self.context_data.peers.read().get(&peer)
+			.map_or(false, |p| p.features.contains(message::Features::COMPRESSED_RESPONSES));
+		if compress {
+			if let Some(compressed) = compress_block_response(&response) {
+				self.send_message(io, peer, GenericMessage::CompressedBlockResponse(compressed));
+				return;
+			}
+		}
 		self.send_message(io, peer, GenericMessage::BlockResponse(response))
 	}
 
+	fn on_block_response_packet(&self, io: &mut SyncIo, who: NodeIndex, response: message::BlockResponse<B>, size: usize) {
+		if size > self.config.max_accepted_block_response_bytes && response.blocks.len() > 1 {
+			trace!(target: "sync", "Block response of {} bytes from {} exceeds the limit of {} bytes",
+				size, who, self.config.max_accepted_block_response_bytes);
+			io.report_peer(who, Severity::Bad("Peer sent an oversized block response"));
+			return;
+		}
+		let request = self.handle_response(io, who, &response);
+		send_deferred_requests(&self.context_data, io, who);
+		if let Some(request) = request {
+			self.sync.write().note_block_response(size);
+			self.on_block_response(io, who, request, response);
+		}
+	}
+
 	fn on_block_response(&self, io: &mut SyncIo, peer: NodeIndex, request: message::BlockRequest<B>, response: message::BlockResponse<B>) {
 		let blocks_range = match (
 				response.blocks.first().and_then(|b| b.header.as_ref().map(|h| h.number())),
@@ -508,16 +1418,25 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		trace!(target: "sync", "BlockResponse {} from {} with {} blocks {}",
 			response.id, peer, response.blocks.len(), blocks_range);
 
-		// TODO [andre]: move this logic to the import queue so that
-		// justifications are imported asynchronously (#1482)
 		if request.fields == message::BlockAttributes::JUSTIFICATION {
-			let mut sync = self.sync.write();
-			sync.on_block_justification_data(
-				&mut ProtocolContext::new(&self.context_data, io),
-				peer,
-				request,
-				response,
-			);
+			// the import queue reports the results back to sync, which needs sync.write();
+			// queue the justifications after releasing it.
+			let justifications = {
+				let mut sync = self.sync.write();
+				sync.on_block_justification_data(
+					&mut ProtocolContext::new(&self.context_data, io),
+					peer,
+					request,
+					response,
+				)
+			};
+
+			if !justifications.is_empty() {
+				let import_queue = self.sync.read().import_queue();
+				for ((hash, number), justification) in justifications {
+					import_queue.import_justification(peer, hash, number, justification);
+				}
+			}
 		} else {
 			// import_queue.import_blocks also acquires sync.write();
 			// Break the cycle by doing these separately from the outside;
@@ -526,10 +1445,16 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				sync.on_block_data(&mut ProtocolContext::new(&self.context_data, io), peer, request, response)
 			};
 
-			if let Some((origin, new_blocks)) = new_blocks {
-				let import_queue = self.sync.read().import_queue();
-				import_queue.import_blocks(origin, new_blocks);
-			}
+			self.import_ready_blocks(new_blocks);
+		}
+	}
+
+	// import_queue.import_blocks also acquires sync.write(), so this must be
+	// called without holding the sync lock.
+	fn import_ready_blocks(&self, blocks: Option<(BlockOrigin, Vec<IncomingBlock<B>>)>) {
+		if let Some((origin, new_blocks)) = blocks {
+			let import_queue = self.sync.read().import_queue();
+			import_queue.import_blocks(origin, new_blocks);
 		}
 	}
 
@@ -537,22 +1462,63 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 	pub fn tick(&self, io: &mut SyncIo) {
 		self.consensus_gossip.write().collect_garbage(|_| true);
 		self.maintain_peers(io);
-		self.sync.write().tick(&mut ProtocolContext::new(&self.context_data, io));
+		self.detect_chain_freeze(io);
+		self.serve_delayed_block_requests(io);
+		self.release_outbound_messages(io);
+		self.ping_peers(io);
+		let ready = {
+			let mut sync = self.sync.write();
+			sync.tick(&mut ProtocolContext::new(&self.context_data, io));
+			sync.take_ready_blocks()
+		};
+		self.import_ready_blocks(ready);
 		self.on_demand.as_ref().map(|s| s.maintain_peers(io));
+		self.on_major_sync_finished();
+		if self.propagation_requested.load(Ordering::Relaxed) {
+			self.propagate_extrinsics(io);
+		}
+	}
+
+	/// Propagate the extrinsics at the next tick, so that the ones imported in the
+	/// meantime are sent in the same messages.
+	pub fn request_propagation(&self) {
+		self.propagation_requested.store(true, Ordering::Relaxed);
+	}
+
+	// Transactions are neither accepted nor propagated during a major sync. Once it finishes,
+	// the ones of the pool are checked against the new best block in the background, then
+	// propagated at the next tick.
+	fn on_major_sync_finished(&self) {
+		let major_syncing = self.sync.read().status().is_major_syncing();
+		if !self.was_major_syncing.swap(major_syncing, Ordering::Relaxed) || major_syncing {
+			return;
+		}
+		debug!(target: "sync", "Major sync finished, revalidating the transaction pool");
+		let propagation_requested = self.propagation_requested.clone();
+		self.transaction_pool.on_major_sync_finished(Box::new(move || {
+			propagation_requested.store(true, Ordering::Relaxed);
+		}));
 	}
 
 	fn maintain_peers(&self, io: &mut SyncIo) {
-		let tick = time::Instant::now();
+		let tick = simulation::now();
 		let mut aborting = Vec::new();
 		{
 			let peers = self.context_data.peers.read();
 			let handshaking_peers = self.handshaking_peers.read();
-			for (who, timestamp) in peers.iter()
-				.filter_map(|(id, peer)| peer.min_request_timestamp().map(|r| (id, r)))
-				.chain(handshaking_peers.iter())
-			{
-				if (tick - *timestamp).as_secs() > REQUEST_TIMEOUT_SEC {
-					trace!(target: "sync", "Timeout {}", who);
+			for (who, peer) in peers.iter() {
+				let expired = peer.expired_requests(tick);
+				if !expired.is_empty() {
+					trace!(target: "sync", "Timeout {}, requests {:?} not answered in time", who, expired);
+					aborting.push(*who);
+				} else if peer.pending_ping.map_or(false, |(_, sent)| tick - sent > PING_TIMEOUT) {
+					trace!(target: "sync", "Timeout {}, ping not answered in time", who);
+					aborting.push(*who);
+				}
+			}
+			for (who, timestamp) in handshaking_peers.iter() {
+				if tick - *timestamp > REQUEST_TIMEOUT {
+					trace!(target: "sync", "Handshake timeout {}", who);
 					aborting.push(*who);
 				}
 			}
@@ -564,12 +1530,71 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		}
 	}
 
+	/// Replace some of the peers if the chain hasn't progressed for too long.
+	///
+	/// Reserved peers are kept, and the others with the lowest reputation are disconnected
+	/// without being blamed, so that the network layer dials new ones in their place.
+	/// Peers are not penalized because a frozen chain isn't necessarily their fault.
+	fn detect_chain_freeze(&self, io: &mut SyncIo) {
+		let timeout = match self.config.chain_freeze_timeout {
+			Some(timeout) => timeout,
+			None => return,
+		};
+
+		let now = simulation::now();
+		let best_seen_block = self.sync.read().status().best_seen_block;
+		let num_peers = self.context_data.peers.read().len();
+		let mut progress = self.chain_progress.write();
+		if best_seen_block != progress.best_seen_block {
+			progress.best_seen_block = best_seen_block;
+			progress.last_progress = now;
+			return;
+		}
+
+		if num_peers == 0 {
+			// there is no one to blame while we are not connected.
+			progress.last_progress = now;
+			return;
+		}
+
+		if now - progress.last_progress < timeout {
+			return;
+		}
+
+		progress.last_progress = now;
+		let mut peers: Vec<_> = self.context_data.peers.read().keys()
+			.filter(|who| !io.is_reserved_peer(**who))
+			.map(|who| (io.peer_reputation(*who).unwrap_or(0), *who))
+			.collect();
+		if peers.is_empty() {
+			return;
+		}
+
+		let rotated = cmp::max(1, peers.len() / CHAIN_FREEZE_ROTATION_FRACTION);
+		warn!(
+			"Chain appears frozen: no progress for {} seconds with {} peers, best seen block: {:?}. Replacing {} peers.",
+			timeout.as_secs(),
+			peers.len(),
+			best_seen_block,
+			rotated
+		);
+		peers.sort();
+		for (_, who) in peers.into_iter().take(rotated) {
+			trace!(target: "sync", "Rotating out {} from a frozen chain", who);
+			io.disconnect_peer(who);
+		}
+		progress.rotated_peers += rotated as u64;
+	}
+
 	#[allow(dead_code)]
 	pub fn peer_info(&self, peer: NodeIndex) -> Option<PeerInfo<B>> {
 		self.context_data.peers.read().get(&peer).map(|p| {
 			PeerInfo {
 				roles: p.roles,
 				protocol_version: p.protocol_version,
+				features: p.features,
+				client_version: p.client_version.clone(),
+				latency: p.latency,
 				best_hash: p.best_hash,
 				best_number: p.best_number,
 			}
@@ -591,10 +1616,16 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				io.report_peer(who, Severity::Bad(&format!("Peer is on different chain (our genesis: {} theirs: {})", self.genesis_hash, status.genesis_hash)));
 				return;
 			}
-			if status.version != CURRENT_VERSION {
+			if status.version < MIN_VERSION || status.version > CURRENT_VERSION {
 				io.report_peer(who, Severity::Bad(&format!("Peer using unsupported protocol version {}", status.version)));
 				return;
 			}
+			// peers predating fork ids can't tell, they are only rejected when we follow a fork.
+			if status.fork_id != self.config.fork_id {
+				io.report_peer(who, Severity::Bad(&format!("Peer is on a different fork (ours: {:?} theirs: {:?})",
+					self.config.fork_id, status.fork_id)));
+				return;
+			}
 			if self.config.roles & Roles::LIGHT == Roles::LIGHT {
 				let self_best_block = self.context_data.chain.info().ok()
 					.and_then(|info| info.best_queued_number)
@@ -608,33 +1639,114 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 
 			let peer = Peer {
 				protocol_version: status.version,
+				features: status.features & supported_features(),
+				client_version: status.client_version.as_ref()
+					.filter(|version| version.len() <= MAX_CLIENT_VERSION_LEN)
+					.map(|version| String::from_utf8_lossy(version).into_owned()),
 				roles: status.roles,
 				best_hash: status.best_hash,
 				best_number: status.best_number,
-				block_request: None,
-				block_request_timestamp: None,
-				justification_request: None,
-				justification_request_timestamp: None,
-				known_extrinsics: HashSet::new(),
+				requests: HashMap::new(),
+				cancelled_requests: BTreeSet::new(),
+				known_extrinsics: LruHashSet::new(MAX_KNOWN_EXTRINSICS),
+				accounted_extrinsics: LruHashSet::new(MAX_KNOWN_EXTRINSICS),
 				known_blocks: HashSet::new(),
 				next_request_id: 0,
+				deferred_requests: VecDeque::new(),
+				served: ServedRequests::new(simulation::now()),
+				gossip: ReceivedGossip::new(simulation::now()),
+				sent_extrinsics: SentExtrinsics::new(simulation::now()),
+				delayed_requests: VecDeque::new(),
+				pending_ping: None,
+				last_ping: None,
+				latency: None,
 			};
 			peers.insert(who.clone(), peer);
 			handshaking_peers.remove(&who);
 			debug!(target: "sync", "Connected {} {}", who, io.peer_debug_info(who));
 		}
 
-		let mut context = ProtocolContext::new(&self.context_data, io);
-		self.on_demand.as_ref().map(|s| s.on_connect(who, status.roles, status.best_number));
-		self.sync.write().new_peer(&mut context, who);
-		self.consensus_gossip.write().new_peer(&mut context, who, status.roles);
-		self.specialization.write().on_connect(&mut context, who, status);
+		let peer_best_number = status.best_number;
+		let notifications = (status.features & supported_features()).contains(message::Features::NOTIFICATIONS);
+		{
+			let mut context = ProtocolContext::new(&self.context_data, io);
+			self.on_demand.as_ref().map(|s| s.on_connect(who, status.roles, status.version, status.best_number));
+			self.sync.write().new_peer(&mut context, who);
+			self.consensus_gossip.write().new_peer(&mut context, who, status.roles);
+			if notifications {
+				self.notifications.write().new_peer(&mut context, who);
+			}
+			self.specialization.write().on_connect(&mut context, who, status);
+		}
+
+		self.republish_authored_blocks(io, who, peer_best_number);
+	}
+
+	/// Announce our recently authored blocks to a newly connected peer that is
+	/// slightly behind us, instead of waiting for its sync to request them.
+	fn republish_authored_blocks(&self, io: &mut SyncIo, who: NodeIndex, peer_best_number: NumberFor<B>) {
+		let our_best_number = match self.context_data.chain.info() {
+			Ok(info) => info.chain.best_number,
+			Err(_) => return,
+		};
+		if peer_best_number >= our_best_number ||
+			our_best_number - peer_best_number > As::sa(REPUBLISH_MAX_DISTANCE)
+		{
+			return;
+		}
+
+		// Blocks retracted by a reorganisation since we authored them aren't worth announcing.
+		let chain = &self.context_data.chain;
+		let authored_blocks = self.authored_blocks.read().iter()
+			.filter(|header| *header.number() > peer_best_number)
+			.filter(|header| match chain.block_hash(*header.number()) {
+				Ok(Some(hash)) => hash == header.hash(),
+				_ => false,
+			})
+			.cloned()
+			.collect::<Vec<_>>();
+		if authored_blocks.is_empty() {
+			return;
+		}
+
+		let mut peers = self.context_data.peers.write();
+		let peer = match peers.get_mut(&who) {
+			Some(peer) => peer,
+			None => return,
+		};
+		if peer.roles & Roles::LIGHT == Roles::LIGHT {
+			return;
+		}
+
+		for header in authored_blocks {
+			let hash = header.hash();
+			if peer.known_blocks.insert(hash) {
+				trace!(target: "sync", "Republishing authored block {:?} to {}", hash, who);
+				self.send_message(io, who, GenericMessage::BlockAnnounce(message::BlockAnnounce {
+					header
+				}));
+			}
+		}
+	}
+
+	/// Remember a block we authored, to republish it to peers connecting shortly
+	/// after it was announced.
+	pub fn on_block_authored(&self, header: &B::Header) {
+		if self.config.roles & Roles::LIGHT == Roles::LIGHT {
+			return;
+		}
+
+		let mut authored_blocks = self.authored_blocks.write();
+		authored_blocks.push_back(header.clone());
+		while authored_blocks.len() > MAX_AUTHORED_BLOCKS {
+			authored_blocks.pop_front();
+		}
 	}
 
 	/// Called when peer sends us new extrinsics
 	fn on_extrinsics(&self, _io: &mut SyncIo, who: NodeIndex, extrinsics: message::Transactions<B::Extrinsic>) {
-		// Accept extrinsics only when fully synced
-		if self.sync.read().status().state != SyncState::Idle {
+		// Accept extrinsics only when fully synced; our view of their validity is behind.
+		if self.sync.read().status().is_major_syncing() {
 			trace!(target: "sync", "{} Ignoring extrinsics while syncing", who);
 			return;
 		}
@@ -652,34 +1764,105 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 	}
 
 	/// Called when we propagate ready extrinsics to peers.
+	///
+	/// Extrinsics are sent to the reserved full peers and to a random subset of the square root of
+	/// the number of the other full peers, which gossip them further. Peers left out receive them
+	/// on a later round if they still don't know them.
 	pub fn propagate_extrinsics(&self, io: &mut SyncIo) {
 		debug!(target: "sync", "Propagating extrinsics");
+		self.propagation_requested.store(false, Ordering::Relaxed);
 
-		// Accept transactions only when fully synced
-		if self.sync.read().status().state != SyncState::Idle {
+		// Propagate transactions only when fully synced; they are likely stale otherwise.
+		if self.sync.read().status().is_major_syncing() {
 			return;
 		}
 
 		let extrinsics = self.transaction_pool.transactions();
 
+		// Extrinsics still ready when their rebroadcast is due weren't included in a block, and
+		// may have been lost by the peers; they are forgotten as known so that they are sent again.
+		let due = match self.config.transaction_rebroadcast {
+			Some(interval) => {
+				let now = simulation::now();
+				schedule_rebroadcast(
+					&mut self.extrinsics_rebroadcast.lock(),
+					extrinsics.iter().map(|(hash, _)| hash.clone()),
+					now,
+					|| now + interval + simulation::jitter(interval / 2),
+				)
+			},
+			None => Vec::new(),
+		};
+
+		let candidates = {
+			let mut peers = self.context_data.peers.write();
+			if !due.is_empty() {
+				trace!(target: "sync", "Rebroadcasting {} extrinsics", due.len());
+				for peer in peers.values_mut() {
+					for hash in due.iter() {
+						peer.known_extrinsics.remove(hash);
+					}
+				}
+			}
+			peers.iter().map(|(who, peer)| (
+				*who,
+				peer.roles & Roles::LIGHT == Roles::LIGHT,
+				extrinsics.iter().any(|(hash, _)| !peer.known_extrinsics.contains(hash)),
+			)).collect::<Vec<_>>()
+		};
+		// The network service is asked about reserved peers without holding the peers lock.
+		let targets = select_propagation_targets(candidates, |who| io.is_reserved_peer(who));
+
 		let mut propagated_to = HashMap::new();
 		let mut peers = self.context_data.peers.write();
-		for (who, ref mut peer) in peers.iter_mut() {
-			let (hashes, to_send): (Vec<_>, Vec<_>) = extrinsics
-				.iter()
-				.filter(|&(ref hash, _)| peer.known_extrinsics.insert(hash.clone()))
-				.cloned()
-				.unzip();
-
-			if !to_send.is_empty() {
-				let node_id = io.peer_id(*who).map(|id| id.to_base58());
-				if let Some(id) = node_id {
-					for hash in hashes {
-						propagated_to.entry(hash).or_insert_with(Vec::new).push(id.clone());
+		let quota = &self.config.transaction_quota;
+		let now = simulation::now();
+		let sizes: Vec<usize> = extrinsics.iter()
+			.map(|(_, extrinsic)| extrinsic.using_encoded(|data| data.len()))
+			.collect();
+		let mut propagation = self.propagation.lock();
+		let (ref mut announced, ref mut metrics) = *propagation;
+		for who in targets.iter() {
+			let peer = match peers.get_mut(who) {
+				Some(peer) => peer,
+				None => continue,
+			};
+
+			let mut hashes = Vec::new();
+			let mut to_send = Vec::new();
+			for ((hash, extrinsic), size) in extrinsics.iter().zip(sizes.iter()) {
+				if peer.known_extrinsics.contains(hash) {
+					if peer.accounted_extrinsics.insert(hash.clone()) {
+						metrics.duplicates_suppressed += 1;
 					}
+					continue;
+				}
+				// Once the quota of the window is used up, the remaining extrinsics stay unknown
+				// to the peer, so that the next propagations send them in the order of the pool.
+				if !peer.sent_extrinsics.admit(quota, *size, now) {
+					break;
 				}
-				trace!(target: "sync", "Sending {} transactions to {}", to_send.len(), who);
-				self.send_message(io, *who, GenericMessage::Transactions(to_send));
+				peer.known_extrinsics.insert(hash.clone());
+				peer.accounted_extrinsics.insert(hash.clone());
+				if announced.insert(hash.clone()) {
+					metrics.extrinsics_announced += 1;
+				}
+				to_send.push((extrinsic.clone(), *size));
+				hashes.push(hash.clone());
+			}
+
+			if hashes.is_empty() {
+				continue;
+			}
+			let node_id = io.peer_id(*who).map(|id| id.to_base58());
+			if let Some(id) = node_id {
+				for hash in hashes {
+					propagated_to.entry(hash).or_insert_with(Vec::new).push(id.clone());
+				}
+			}
+			for batch in batch_extrinsics(to_send, quota.max_message_bytes) {
+				trace!(target: "sync", "Sending {} transactions to {}", batch.len(), who);
+				self.send_message(io, *who, GenericMessage::Transactions(batch));
 			}
 		}
 		self.transaction_pool.on_broadcasted(propagated_to);
@@ -701,9 +1884,13 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				return;
 			}
 		};
+		let announce_to_light = self.is_announced_to_light_peers(&hash);
 		let mut peers = self.context_data.peers.write();
 		let hash = header.hash();
 		for (who, ref mut peer) in peers.iter_mut() {
+			if peer.roles & Roles::LIGHT == Roles::LIGHT && !announce_to_light {
+				continue;
+			}
 			trace!(target: "sync", "Reannouncing block {:?} to {}", hash, who);
 			peer.known_blocks.insert(hash);
 			self.send_message(io, *who, GenericMessage::BlockAnnounce(message::BlockAnnounce {
@@ -722,6 +1909,9 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				best_number: info.chain.best_number,
 				best_hash: info.chain.best_hash,
 				chain_status: self.specialization.read().status(),
+				features: supported_features(),
+				fork_id: self.config.fork_id.clone(),
+				client_version: self.config.client_version.as_ref().map(|version| version.as_bytes().to_vec()),
 			};
 			self.send_message(io, who, GenericMessage::Status(status))
 		}
@@ -733,11 +1923,15 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		let mut peers = self.context_data.peers.write();
 		let mut handshaking_peers = self.handshaking_peers.write();
 		let mut consensus_gossip = self.consensus_gossip.write();
+		let mut notifications = self.notifications.write();
 		sync.clear();
 		spec.on_abort();
 		peers.clear();
 		handshaking_peers.clear();
+		self.context_data.traffic.lock().peers.clear();
+		self.context_data.outbound.lock().clear();
 		consensus_gossip.abort();
+		notifications.abort();
 	}
 
 	pub fn stop(&self) {
@@ -749,6 +1943,35 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		self.abort();
 	}
 
+	/// Fetch a single block from the network. Blocks that are already in our
+	/// chain are returned without asking any peer, unless we are a light node
+	/// which doesn't store bodies.
+	pub fn fetch_block(&self, io: &mut SyncIo, hash: B::Hash, fields: message::BlockAttributes) -> oneshot::Receiver<SignedBlock<B>> {
+		let (sender, receiver) = oneshot::channel();
+		let id = BlockId::Hash(hash);
+		let chain = &self.context_data.chain;
+		let local = if self.config.roles & Roles::LIGHT == Roles::LIGHT {
+			None
+		} else {
+			match (chain.header(&id), chain.body(&id)) {
+				(Ok(Some(header)), Ok(Some(body))) => Some((header, body)),
+				_ => None,
+			}
+		};
+		if let Some((header, body)) = local {
+			let justification = if fields.contains(message::BlockAttributes::JUSTIFICATION) {
+				chain.justification(&id).unwrap_or(None)
+			} else {
+				None
+			};
+			let _ = sender.send(SignedBlock { block: B::new(header, body), justification });
+			return receiver;
+		}
+
+		self.sync.write().fetch_block(hash, fields, sender, &mut ProtocolContext::new(&self.context_data, io));
+		receiver
+	}
+
 	pub fn on_block_announce(&self, io: &mut SyncIo, who: NodeIndex, announce: message::BlockAnnounce<B::Header>) {
 		let header = announce.header;
 		let hash = header.hash();
@@ -758,6 +1981,7 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				peer.known_blocks.insert(hash.clone());
 			}
 		}
+		self.chain_progress.write().last_progress = simulation::now();
 		self.on_demand.as_ref().map(|s| s.on_block_announce(who, *header.number()));
 		self.sync.write().on_block_announce(&mut ProtocolContext::new(&self.context_data, io), who, hash, &header);
 	}
@@ -776,9 +2000,13 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		}
 
 		// send out block announcements
+		let announce_to_light = self.is_announced_to_light_peers(&hash);
 		let mut peers = self.context_data.peers.write();
 
 		for (who, ref mut peer) in peers.iter_mut() {
+			if peer.roles & Roles::LIGHT == Roles::LIGHT && !announce_to_light {
+				continue;
+			}
 			if peer.known_blocks.insert(hash.clone()) {
 				trace!(target: "sync", "Announcing block {:?} to {}", hash, who);
 				self.send_message(io, *who, GenericMessage::BlockAnnounce(message::BlockAnnounce {
@@ -788,8 +2016,48 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		}
 	}
 
-	pub fn on_block_finalized(&self, _io: &mut SyncIo, hash: B::Hash, header: &B::Header) {
-		self.sync.write().block_finalized(&hash, *header.number());
+	/// Whether the given imported block is announced to light peers right away.
+	fn is_announced_to_light_peers(&self, hash: &B::Hash) -> bool {
+		match self.config.light_announcement {
+			LightAnnouncement::All => true,
+			LightAnnouncement::Best => self.context_data.chain.info()
+				.map(|info| info.chain.best_hash == *hash)
+				.unwrap_or(false),
+			LightAnnouncement::Finalized => false,
+		}
+	}
+
+	pub fn on_chain_reorg(&self, io: &mut SyncIo, retracted: &[B::Hash], header: &B::Header) {
+		self.sync.write().on_chain_reorg(&mut ProtocolContext::new(&self.context_data, io), retracted, header);
+	}
+
+	pub fn on_block_finalized(&self, io: &mut SyncIo, hash: B::Hash, header: &B::Header) {
+		self.sync.write().block_finalized(&hash, *header.number(), &mut ProtocolContext::new(&self.context_data, io));
+
+		// light peers waiting for finality are announced the finalized block
+		let announce_to_light = self.config.light_announcement == LightAnnouncement::Finalized
+			&& self.config.roles & Roles::LIGHT != Roles::LIGHT;
+
+		let mut peers = self.context_data.peers.write();
+		for (who, ref mut peer) in peers.iter_mut() {
+			if peer.features.contains(message::Features::FINALIZED_ANNOUNCEMENTS) {
+				self.send_message(io, *who, GenericMessage::FinalizedAnnounce(message::generic::FinalizedAnnounce {
+					hash: hash.clone(),
+					number: *header.number(),
+				}));
+			}
+			if announce_to_light && peer.roles & Roles::LIGHT == Roles::LIGHT && peer.known_blocks.insert(hash.clone()) {
+				trace!(target: "sync", "Announcing finalized block {:?} to {}", hash, who);
+				self.send_message(io, *who, GenericMessage::BlockAnnounce(message::BlockAnnounce {
+					header: header.clone()
+				}));
+			}
+		}
+	}
+
+	fn on_finalized_announce(&self, who: NodeIndex, announce: message::FinalizedAnnounce<B>) {
+		trace!(target: "sync", "Finalized announce from {}: {} (#{})", who, announce.hash, announce.number);
+		self.sync.write().on_finalized_announce(who, announce.hash, announce.number);
 	}
 
 	fn on_remote_call_request(&self, io: &mut SyncIo, who: NodeIndex, request: message::RemoteCallRequest<B::Hash>) {
@@ -833,6 +2101,31 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 		self.on_demand.as_ref().map(|s| s.on_remote_read_response(io, who, response));
 	}
 
+	fn on_remote_body_request(&self, io: &mut SyncIo, who: NodeIndex, request: message::RemoteBodyRequest<B::Hash>) {
+		trace!(target: "sync", "Remote body request {} from {} ({})", request.id, who, request.block);
+		// light nodes would have to fetch the body from the network themselves
+		let body = if self.config.roles & Roles::LIGHT == Roles::LIGHT {
+			None
+		} else {
+			match self.context_data.chain.body(&BlockId::Hash(request.block)) {
+				Ok(body) => body,
+				Err(error) => {
+					trace!(target: "sync", "Remote body request {} from {} ({}) failed with: {}",
+						request.id, who, request.block, error);
+					None
+				},
+			}
+		};
+		self.send_message(io, who, GenericMessage::RemoteBodyResponse(message::generic::RemoteBodyResponse {
+			id: request.id, body,
+		}));
+	}
+
+	fn on_remote_body_response(&self, io: &mut SyncIo, who: NodeIndex, response: message::RemoteBodyResponse<B>) {
+		trace!(target: "sync", "Remote body response {} from {}", response.id, who);
+		self.on_demand.as_ref().map(|s| s.on_remote_body_response(io, who, response));
+	}
+
 	fn on_remote_header_request(&self, io: &mut SyncIo, who: NodeIndex, request: message::RemoteHeaderRequest<NumberFor<B>>) {
 		trace!(target: "sync", "Remote header proof request {} from {} ({})",
 			request.id, who, request.block);
@@ -844,7 +2137,7 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 				(Default::default(), Default::default())
 			},
 		};
- 		self.send_message(io, who, GenericMessage::RemoteHeaderResponse(message::RemoteHeaderResponse {
+		self.send_message(io, who, GenericMessage::RemoteHeaderResponse(message::RemoteHeaderResponse {
 			id: request.id, header, proof,
 		}));
 	}
@@ -895,26 +2188,58 @@ impl<B: BlockT, S: NetworkSpecialization<B>, H: ExHashT> Protocol<B, S, H> {
 	}
 }
 
-fn send_message<B: BlockT, H: ExHashT>(peers: &RwLock<HashMap<NodeIndex, Peer<B, H>>>, io: &mut SyncIo, who: NodeIndex, mut message: Message<B>) {
+// Messages counted against the gossip quota of the peer sending them.
+fn is_gossip<B: BlockT>(message: &Message<B>) -> bool {
+	match *message {
+		GenericMessage::Consensus(..) |
+		GenericMessage::AuthorityConsensus(..) |
+		GenericMessage::EngineConsensus(..) |
+		GenericMessage::Transactions(_) |
+		GenericMessage::BlockAnnounce(_) |
+		GenericMessage::FinalizedAnnounce(_) => true,
+		_ => false,
+	}
+}
+
+fn send_message<B: BlockT, H: ExHashT>(context_data: &ContextData<B, H>, io: &mut SyncIo, who: NodeIndex, mut message: Message<B>) {
 	match message {
 		GenericMessage::BlockRequest(ref mut r) => {
-			let mut peers = peers.write();
+			let mut peers = context_data.peers.write();
 			if let Some(ref mut peer) = peers.get_mut(&who) {
+				if peer.requests.len() >= context_data.max_requests_in_flight {
+					trace!(target: "sync", "Deferring request to {}, {} requests in flight", who, peer.requests.len());
+					peer.deferred_requests.push_back(r.clone());
+					return;
+				}
 				r.id = peer.next_request_id;
 				peer.next_request_id = peer.next_request_id + 1;
-
-				if r.fields == message::BlockAttributes::JUSTIFICATION {
-					peer.justification_request = Some(r.clone());
-					peer.justification_request_timestamp = Some(time::Instant::now());
-				} else {
-					peer.block_request = Some(r.clone());
-					peer.block_request_timestamp = Some(time::Instant::now());
-				}
+				peer.requests.insert(r.id, (r.clone(), simulation::now() + REQUEST_TIMEOUT));
 			}
 		},
 		_ => (),
 	}
-	io.send(who, message.encode());
+	let encoded = message.encode();
+	// queued messages are counted once released, dropped ones not at all.
+	let encoded = context_data.outbound.lock().push(who, Priority::of(&message), message.kind(), encoded);
+	if let Some(encoded) = encoded {
+		context_data.traffic.lock().note_sent(who, message.kind(), encoded.len());
+		io.send(who, encoded);
+	}
+}
+
+// Send the deferred requests the peer has room for.
+fn send_deferred_requests<B: BlockT, H: ExHashT>(context_data: &ContextData<B, H>, io: &mut SyncIo, who: NodeIndex) {
+	loop {
+		let request = match context_data.peers.write().get_mut(&who) {
+			Some(ref mut peer) if peer.requests.len() < context_data.max_requests_in_flight =>
+				peer.deferred_requests.pop_front(),
+			_ => None,
+		};
+		match request {
+			Some(request) => send_message(context_data, io, who, GenericMessage::BlockRequest(request)),
+			None => break,
+		}
+	}
 }
 
 /// Construct a simple protocol that is composed of several sub protocols.
@@ -1015,3 +2340,306 @@ macro_rules! construct_simple_protocol {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use std::time::{Duration, Instant};
+	use codec::Encode;
+	use config::{GossipQuota, RequestQuota, TransactionQuota};
+	use message;
+	use test_client::runtime::{Block, Hash};
+	use super::{
+		batch_extrinsics, compress_block_response, decompress_block_response, schedule_rebroadcast,
+		select_propagation_targets, GossipAdmission, MessageStats, ReceivedGossip, SentExtrinsics,
+		ServedRequests, TrafficStats,
+	};
+
+	fn quota() -> GossipQuota {
+		GossipQuota {
+			window: Duration::from_secs(1),
+			max_messages: 2,
+			max_bytes: 100,
+			reputation_change: -10,
+			max_exceeded_windows: 3,
+		}
+	}
+
+	#[test]
+	fn gossip_beyond_the_quota_is_dropped_until_the_next_window() {
+		let quota = quota();
+		let start = Instant::now();
+		let mut gossip = ReceivedGossip::new(start);
+
+		assert_eq!(gossip.admit(&quota, 10, start), GossipAdmission::Admitted);
+		assert_eq!(gossip.admit(&quota, 10, start), GossipAdmission::Admitted);
+		assert_eq!(gossip.admit(&quota, 10, start), GossipAdmission::Exceeded(1));
+		assert_eq!(gossip.admit(&quota, 10, start), GossipAdmission::Dropped);
+
+		let next_window = start + quota.window;
+		assert_eq!(gossip.admit(&quota, 10, next_window), GossipAdmission::Admitted);
+	}
+
+	#[test]
+	fn gossip_quota_counts_bytes() {
+		let quota = quota();
+		let start = Instant::now();
+		let mut gossip = ReceivedGossip::new(start);
+
+		// the message crossing the limit is admitted, the next one isn't.
+		assert_eq!(gossip.admit(&quota, 150, start), GossipAdmission::Admitted);
+		assert_eq!(gossip.admit(&quota, 1, start), GossipAdmission::Exceeded(1));
+	}
+
+	#[test]
+	fn consecutive_exceeded_gossip_windows_are_counted() {
+		let quota = quota();
+		let start = Instant::now();
+		let mut gossip = ReceivedGossip::new(start);
+		let flood = |gossip: &mut ReceivedGossip, at: Instant| {
+			(0..quota.max_messages).for_each(|_| { gossip.admit(&quota, 1, at); });
+			gossip.admit(&quota, 1, at)
+		};
+
+		assert_eq!(flood(&mut gossip, start), GossipAdmission::Exceeded(1));
+		assert_eq!(flood(&mut gossip, start + quota.window), GossipAdmission::Exceeded(2));
+		assert_eq!(flood(&mut gossip, start + quota.window * 2), GossipAdmission::Exceeded(3));
+
+		// a window within the quota resets the count.
+		assert_eq!(gossip.admit(&quota, 1, start + quota.window * 3), GossipAdmission::Admitted);
+		assert_eq!(flood(&mut gossip, start + quota.window * 4), GossipAdmission::Exceeded(1));
+
+		// and so does a window without any message.
+		assert_eq!(flood(&mut gossip, start + quota.window * 6), GossipAdmission::Exceeded(1));
+	}
+
+	fn transaction_quota() -> TransactionQuota {
+		TransactionQuota {
+			window: Duration::from_secs(5),
+			max_bytes: 100,
+			max_message_bytes: 40,
+		}
+	}
+
+	#[test]
+	fn extrinsics_beyond_the_quota_are_carried_over_to_the_next_window() {
+		let quota = transaction_quota();
+		let start = Instant::now();
+		let mut sent = SentExtrinsics::new(start);
+
+		assert!(sent.admit(&quota, 60, start));
+		assert!(!sent.admit(&quota, 60, start));
+		// smaller ones don't overtake the refused one, which is carried over with the others.
+		let next_window = start + quota.window;
+		assert!(sent.admit(&quota, 60, next_window));
+		assert!(sent.admit(&quota, 40, next_window));
+		assert!(!sent.admit(&quota, 1, next_window));
+	}
+
+	#[test]
+	fn an_extrinsic_larger_than_the_quota_is_sent_alone_in_its_window() {
+		let quota = transaction_quota();
+		let start = Instant::now();
+		let mut sent = SentExtrinsics::new(start);
+
+		assert!(sent.admit(&quota, 500, start));
+		assert!(!sent.admit(&quota, 1, start));
+		assert!(sent.admit(&quota, 1, start + quota.window));
+	}
+
+	#[test]
+	fn extrinsics_are_split_in_messages_of_limited_size() {
+		let max_message_bytes = transaction_quota().max_message_bytes;
+		let batches = batch_extrinsics(vec![(1, 20), (2, 20), (3, 30), (4, 10), (5, 1)], max_message_bytes);
+		assert_eq!(batches, vec![vec![1, 2], vec![3, 4], vec![5]]);
+
+		assert!(batch_extrinsics(Vec::<(u8, usize)>::new(), max_message_bytes).is_empty());
+	}
+
+	#[test]
+	fn an_extrinsic_larger_than_a_message_is_sent_alone() {
+		let max_message_bytes = transaction_quota().max_message_bytes;
+		let batches = batch_extrinsics(vec![(1, 10), (2, 100), (3, 10)], max_message_bytes);
+		assert_eq!(batches, vec![vec![1], vec![2], vec![3]]);
+
+		let batches = batch_extrinsics(vec![(1, 100), (2, 100)], max_message_bytes);
+		assert_eq!(batches, vec![vec![1], vec![2]]);
+	}
+
+	#[test]
+	fn extrinsics_are_propagated_to_reserved_peers_and_a_square_root_subset() {
+		// peers 0 to 15 are full, 0 and 1 are reserved, and 15 already knows the extrinsics.
+		let mut peers = (0..16).map(|who| (who, false, who != 15)).collect::<Vec<_>>();
+		// light peers are never picked, nor counted.
+		peers.extend((16..20).map(|who| (who, true, true)));
+
+		let targets = select_propagation_targets(peers, |who| who < 2);
+
+		// the reserved peers, and the square root of the 16 full peers among the others.
+		assert_eq!(targets.len(), 2 + 4);
+		assert_eq!(&targets[..2], &[0, 1]);
+		assert!(targets[2..].iter().all(|who| *who >= 2 && *who < 15));
+	}
+
+	#[test]
+	fn reserved_peers_knowing_the_extrinsics_are_skipped() {
+		let peers = vec![(0, false, false), (1, true, true)];
+		assert!(select_propagation_targets(peers, |_| true).is_empty());
+	}
+
+	#[test]
+	fn ready_extrinsics_are_rebroadcast_once_due() {
+		let start = Instant::now();
+		let interval = Duration::from_secs(60);
+		let mut schedule = HashMap::new();
+
+		// new extrinsics are scheduled, not rebroadcast.
+		let due = schedule_rebroadcast(&mut schedule, vec![1, 2].into_iter(), start, || start + interval);
+		assert!(due.is_empty());
+		assert_eq!(schedule.len(), 2);
+
+		let before = start + interval - Duration::from_secs(1);
+		assert!(schedule_rebroadcast(&mut schedule, vec![1, 2].into_iter(), before, || before + interval).is_empty());
+
+		// extrinsic 2 was included in a block and is forgotten, 3 is new.
+		let at = start + interval;
+		let due = schedule_rebroadcast(&mut schedule, vec![1, 3].into_iter(), at, || at + interval);
+		assert_eq!(due, vec![1]);
+		assert_eq!(schedule.get(&1), Some(&(at + interval)));
+		assert_eq!(schedule.get(&3), Some(&(at + interval)));
+		assert!(!schedule.contains_key(&2));
+	}
+
+	fn block_response(id: message::RequestId) -> message::BlockResponse<Block> {
+		let blocks = (0..10u64).map(|n| message::generic::BlockData {
+			hash: Hash::from_low_u64_be(n),
+			header: None,
+			body: None,
+			receipt: None,
+			message_queue: None,
+			justification: Some(vec![0; 100]),
+		}).collect();
+		message::generic::BlockResponse { id, blocks }
+	}
+
+	#[test]
+	fn compressed_block_responses_are_decompressed() {
+		let response = block_response(7);
+		let compressed = compress_block_response(&response).unwrap();
+		assert!(compressed.data.len() < response.encode().len());
+
+		let (decompressed, size) = decompress_block_response::<Block>(compressed, 1024 * 1024).unwrap();
+		assert_eq!(decompressed, response);
+		assert_eq!(size, response.encode().len());
+	}
+
+	#[test]
+	fn incompressible_block_responses_are_sent_uncompressed() {
+		let response = message::generic::BlockResponse { id: 7, blocks: Vec::new() };
+		assert!(compress_block_response::<Block>(&response).is_none());
+	}
+
+	#[test]
+	fn oversized_compressed_block_responses_are_rejected() {
+		let response = block_response(7);
+		let compressed = compress_block_response(&response).unwrap();
+		let size = response.encode().len();
+
+		assert!(decompress_block_response::<Block>(compressed.clone(), size - 1).is_err());
+		assert!(decompress_block_response::<Block>(compressed, size).is_ok());
+
+		// a single block is accepted whatever its size.
+		let mut response = block_response(7);
+		response.blocks.truncate(1);
+		response.blocks[0].justification = Some(vec![0; 1024]);
+		let compressed = compress_block_response(&response).unwrap();
+		let (decompressed, size) = decompress_block_response::<Block>(compressed, 100).unwrap();
+		assert_eq!(decompressed, response);
+		assert!(size > 100);
+	}
+
+	#[test]
+	fn compressed_block_responses_with_another_id_are_rejected() {
+		let mut compressed = compress_block_response(&block_response(7)).unwrap();
+		compressed.id = 8;
+		assert!(decompress_block_response::<Block>(compressed, 1024 * 1024).is_err());
+	}
+
+	#[test]
+	fn invalid_compressed_block_responses_are_rejected() {
+		let compressed = message::CompressedBlockResponse { id: 7, data: vec![0xff; 16] };
+		assert!(decompress_block_response::<Block>(compressed, 1024 * 1024).is_err());
+	}
+
+	fn request_quota() -> RequestQuota {
+		RequestQuota {
+			window: Duration::from_secs(10),
+			max_requests: 2,
+			max_bytes: 1000,
+			max_delayed: 1,
+		}
+	}
+
+	#[test]
+	fn block_requests_beyond_the_quota_wait_for_the_next_window() {
+		let quota = request_quota();
+		let start = Instant::now();
+		let mut served = ServedRequests::new(start);
+
+		assert!(served.admit(&quota, start));
+		assert!(served.admit(&quota, start));
+		assert!(!served.admit(&quota, start));
+		assert!(!served.admit(&quota, start + quota.window - Duration::from_millis(1)));
+
+		assert!(served.admit(&quota, start + quota.window));
+		assert_eq!(served.requests, 1);
+	}
+
+	#[test]
+	fn block_request_quota_counts_served_bytes() {
+		let quota = request_quota();
+		let start = Instant::now();
+		let mut served = ServedRequests::new(start);
+
+		assert!(served.admit(&quota, start));
+		served.bytes += quota.max_bytes;
+		assert!(!served.admit(&quota, start));
+
+		assert!(served.admit(&quota, start + quota.window));
+		assert_eq!(served.bytes, 0);
+	}
+
+	#[test]
+	fn traffic_is_counted_per_message_kind_and_per_peer() {
+		let mut traffic = TrafficStats::default();
+		traffic.peers.insert(1, MessageStats::new());
+
+		traffic.note_received(1, "Status", 10);
+		traffic.note_received(1, "BlockResponse", 100);
+		traffic.note_received(1, "BlockResponse", 50);
+		traffic.note_sent(1, "BlockRequest", 20);
+
+		let peer = &traffic.peers[&1];
+		assert_eq!(peer["Status"].messages_received, 1);
+		assert_eq!(peer["BlockResponse"].messages_received, 2);
+		assert_eq!(peer["BlockResponse"].bytes_received, 150);
+		assert_eq!(peer["BlockResponse"].messages_sent, 0);
+		assert_eq!(peer["BlockRequest"].messages_sent, 1);
+		assert_eq!(peer["BlockRequest"].bytes_sent, 20);
+		assert_eq!(traffic.total, *peer);
+	}
+
+	#[test]
+	fn traffic_of_untracked_peers_is_only_counted_in_total() {
+		let mut traffic = TrafficStats::default();
+		traffic.peers.insert(1, MessageStats::new());
+
+		traffic.note_received(2, "Status", 10);
+		traffic.note_sent(2, "Status", 10);
+
+		assert!(traffic.peers[&1].is_empty());
+		assert!(!traffic.peers.contains_key(&2));
+		assert_eq!(traffic.total["Status"].messages_received, 1);
+		assert_eq!(traffic.total["Status"].messages_sent, 1);
+	}
+}
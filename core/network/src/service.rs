@@ -18,26 +18,39 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::{io, thread};
 use std::time::Duration;
-use futures::{self, Future, Stream, stream, sync::oneshot};
+use futures::{self, Future, Stream, stream, sync::{mpsc, oneshot}};
 use parking_lot::{Mutex, RwLock};
-use network_libp2p::{ProtocolId, PeerId, NetworkConfiguration, NodeIndex, ErrorKind, Severity};
+use network_libp2p::{ProtocolId, PeerId, NetworkConfiguration, NodeIndex, ErrorKind, Severity, Multiaddr};
+use network_libp2p::{ConnectionMetrics, IpFilterList, IpNetwork};
+use network_libp2p::ReputationChange;
 use network_libp2p::{start_service, Service as NetworkService, ServiceEvent as NetworkServiceEvent};
 use network_libp2p::{RegisteredProtocol, parse_str_addr, Protocol as Libp2pProtocol};
-use io::NetSyncIo;
+use io::{NetSyncIo, SyncIo};
 use consensus::import_queue::{ImportQueue, Link};
-use consensus_gossip::ConsensusGossip;
-use protocol::{self, Protocol, ProtocolContext, Context, ProtocolStatus, PeerInfo};
+use consensus_gossip::{ConsensusEngineId, ConsensusGossip, TopicMetrics, Validator};
+use notifications::{NotificationEvent, ProtocolName};
+use protocol::{self, Protocol, ProtocolContext, Context, ProtocolStatus, PeerInfo, TrafficStats, PropagationMetrics};
 use config::Params;
 use error::Error;
 use specialization::NetworkSpecialization;
 use runtime_primitives::traits::{Block as BlockT, NumberFor};
-use sync::ChainSync;
+use runtime_primitives::generic::SignedBlock;
+use message::{BlockAttributes, SignedConsensusMessage};
+use primitives::ed25519;
+use sync::{ChainSync, Metrics as SyncMetrics};
+use extra_requests::ExtraRequestInfo;
+use blocks::DownloadStats;
 use std::sync::Weak;
 use tokio::{runtime::Runtime, timer::Interval};
 
 /// Type that represents fetch completion future.
 pub type FetchFuture = oneshot::Receiver<Vec<u8>>;
 
+/// Future that resolves to a block requested with `Service::fetch_block`.
+/// It is canceled if no connected peer can provide the block, or if no peer
+/// could be asked for it within a timeout.
+pub type BlockFetchFuture<B> = oneshot::Receiver<SignedBlock<B>>;
+
 const TICK_TIMEOUT: Duration = Duration::from_millis(1000);
 const PROPAGATE_TIMEOUT: Duration = Duration::from_millis(5000);
 
@@ -45,6 +58,12 @@ const PROPAGATE_TIMEOUT: Duration = Duration::from_millis(5000);
 pub trait SyncProvider<B: BlockT>: Send + Sync {
 	/// Get sync status
 	fn status(&self) -> ProtocolStatus<B>;
+	/// Get the occupancy of the block download window
+	fn download_stats(&self) -> DownloadStats<NumberFor<B>>;
+	/// Get sync metric counters
+	fn sync_metrics(&self) -> SyncMetrics;
+	/// Get the state of the tracked justification requests
+	fn justification_requests(&self) -> Vec<ExtraRequestInfo<B>>;
 	/// Get currently connected peers
 	fn peers(&self) -> Vec<(NodeIndex, Option<PeerId>, PeerInfo<B>)>;
 }
@@ -61,6 +80,9 @@ pub trait TransactionPool<H: ExHashT, B: BlockT>: Send + Sync {
 	fn import(&self, transaction: &B::Extrinsic) -> Option<H>;
 	/// Notify the pool about transactions broadcast.
 	fn on_broadcasted(&self, propagations: HashMap<H, Vec<String>>);
+	/// Notify the pool that we caught up with the chain after a major sync, so that it checks
+	/// its transactions again, calling `revalidated` once done. Must not block.
+	fn on_major_sync_finished(&self, revalidated: Box<Fn() + Send>);
 }
 
 /// Service able to execute closure in the network context.
@@ -91,11 +113,25 @@ impl<B: BlockT, E: ExecuteInContext<B>> NetworkLink<B, E> {
 
 impl<B: BlockT, E: ExecuteInContext<B>> Link<B> for NetworkLink<B, E> {
 	fn block_imported(&self, hash: &B::Hash, number: NumberFor<B>) {
-		self.with_sync(|sync, _| sync.block_imported(&hash, number))
+		if let (Some(sync), Some(service)) = (self.sync.upgrade(), self.context.upgrade()) {
+			service.execute_in_context(move |_| {
+				// the import queue may report the result back through `with_sync`,
+				// so import a buffered justification after releasing the lock.
+				let buffered = sync.write().block_imported(&hash, number);
+				if let Some((who, justification)) = buffered {
+					let import_queue = sync.read().import_queue();
+					import_queue.import_justification(who, *hash, number, justification);
+				}
+			});
+		}
 	}
 
-	fn request_justification(&self, hash: &B::Hash, number: NumberFor<B>) {
-		self.with_sync(|sync, protocol| sync.request_justification(hash, number, protocol))
+	fn justification_imported(&self, who: NodeIndex, hash: &B::Hash, number: NumberFor<B>, success: bool) {
+		self.with_sync(|sync, protocol| sync.justification_import_result(who, hash, number, success, protocol))
+	}
+
+	fn request_justification(&self, hash: &B::Hash, number: NumberFor<B>, mandatory: bool) {
+		self.with_sync(|sync, protocol| sync.request_justification(hash, number, mandatory, protocol))
 	}
 
 	fn maintain_sync(&self) {
@@ -151,7 +187,7 @@ impl<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT> Service<B, S,
 			params.transaction_pool,
 			params.specialization,
 		)?);
-		let versions = [(protocol::CURRENT_VERSION as u8)];
+		let versions: Vec<u8> = (protocol::MIN_VERSION..=protocol::CURRENT_VERSION).rev().map(|v| v as u8).collect();
 		let registered = RegisteredProtocol::new(protocol_id, &versions[..]);
 		let (thread, network) = start_thread(params.network_config, handler.clone(), registered)?;
 
@@ -185,19 +221,71 @@ impl<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT> Service<B, S,
 		self.network.lock().average_upload_per_sec()
 	}
 
+	/// Returns the counters of the connections opened by the network.
+	pub fn connection_metrics(&self) -> ConnectionMetrics {
+		self.network.lock().connection_metrics()
+	}
+
+	/// Returns the number of messages and bytes exchanged with peers, per message kind.
+	pub fn traffic_stats(&self) -> TrafficStats {
+		self.handler.traffic_stats()
+	}
+
+	/// Returns the gossip message counters of the most recently active topics.
+	pub fn gossip_metrics(&self) -> Vec<(B::Hash, TopicMetrics)> {
+		self.handler.gossip_metrics()
+	}
+
+	/// Returns the extrinsic propagation counters.
+	pub fn propagation_metrics(&self) -> PropagationMetrics {
+		self.handler.propagation_metrics()
+	}
+
+	/// Returns the addresses the network is listening on.
+	///
+	/// When configured to listen on port `0`, this reports the ports that were actually bound.
+	pub fn listen_addresses(&self) -> Vec<Multiaddr> {
+		self.network.lock().listeners().cloned().collect()
+	}
+
+	/// Returns the identity of this node on the network.
+	pub fn local_peer_id(&self) -> PeerId {
+		self.network.lock().peer_id().clone()
+	}
+
+	/// Returns the addresses of this node that are reported to the network.
+	///
+	/// Addresses observed by other peers are only included once enough peers confirmed them.
+	pub fn external_addresses(&self) -> Vec<Multiaddr> {
+		self.network.lock().external_addresses()
+	}
+
 	/// Called when a new block is imported by the client.
 	pub fn on_block_imported(&self, hash: B::Hash, header: &B::Header) {
 		self.handler.on_block_imported(&mut NetSyncIo::new(&self.network, self.protocol_id), hash, header)
 	}
 
+	/// Called when a block authored by this node is imported by the client, in
+	/// addition to `on_block_imported`.
+	pub fn on_block_authored(&self, header: &B::Header) {
+		self.handler.on_block_authored(header)
+	}
+
+	/// Called when the client's best chain switched to another fork, with the
+	/// blocks removed from the best chain (newest first) and the new best header.
+	pub fn on_chain_reorg(&self, retracted: &[B::Hash], header: &B::Header) {
+		self.handler.on_chain_reorg(&mut NetSyncIo::new(&self.network, self.protocol_id), retracted, header)
+	}
+
 	/// Called when a new block is finalized by the client.
 	pub fn on_block_finalized(&self, hash: B::Hash, header: &B::Header) {
 		self.handler.on_block_finalized(&mut NetSyncIo::new(&self.network, self.protocol_id), hash, header)
 	}
 
-	/// Called when new transactons are imported by the client.
+	/// Called when new transactons are imported by the client. They are propagated
+	/// at the next tick of the protocol, batched with the ones imported meanwhile.
 	pub fn trigger_repropagate(&self) {
-		self.handler.propagate_extrinsics(&mut NetSyncIo::new(&self.network, self.protocol_id));
+		self.handler.request_propagation();
 	}
 
 	/// Make sure an important block is propagated to peers.
@@ -208,6 +296,13 @@ impl<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT> Service<B, S,
 		self.handler.announce_block(&mut NetSyncIo::new(&self.network, self.protocol_id), hash);
 	}
 
+	/// Request a specific block from the network, e.g. one referenced by a
+	/// consensus message that we haven't seen announced. Header and body are
+	/// always requested, other `attributes` are added to the request.
+	pub fn fetch_block(&self, hash: B::Hash, attributes: BlockAttributes) -> BlockFetchFuture<B> {
+		self.handler.fetch_block(&mut NetSyncIo::new(&self.network, self.protocol_id), hash, attributes)
+	}
+
 	/// Send a consensus message through the gossip
 	pub fn gossip_consensus_message(&self, topic: B::Hash, message: Vec<u8>, broadcast: bool) {
 		self.handler.gossip_consensus_message(
@@ -217,6 +312,69 @@ impl<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT> Service<B, S,
 			broadcast,
 		)
 	}
+
+	/// Sign a consensus message with an authority key and send it through the
+	/// gossip, to the peers able to check it.
+	pub fn gossip_authority_message(&self, topic: B::Hash, message: Vec<u8>, broadcast: bool, key: &ed25519::Pair) {
+		let signed = SignedConsensusMessage::sign(&topic, message, key);
+		self.handler.gossip_authority_message(
+			&mut NetSyncIo::new(&self.network, self.protocol_id),
+			topic,
+			signed,
+			broadcast,
+		)
+	}
+
+	/// Register the gossip validator of a consensus engine. Messages of the engine received
+	/// from peers are checked by it before being kept or propagated.
+	pub fn register_gossip_validator(&self, engine_id: ConsensusEngineId, validator: Arc<dyn Validator<B>>) {
+		self.consensus_gossip().write().register_validator(engine_id, validator);
+	}
+
+	/// Send a message of a consensus engine through the gossip, to the peers understanding
+	/// messages of engines.
+	pub fn gossip_engine_message(&self, engine_id: ConsensusEngineId, topic: B::Hash, message: Vec<u8>) {
+		self.handler.gossip_engine_message(
+			&mut NetSyncIo::new(&self.network, self.protocol_id),
+			engine_id,
+			topic,
+			message,
+		)
+	}
+
+	/// Register a notifications protocol, opened with every peer supporting
+	/// notifications with the given handshake. Events of the peers that opened
+	/// the protocol on their side are reported on the returned stream.
+	pub fn register_notifications_protocol(
+		&self,
+		name: ProtocolName,
+		handshake: Vec<u8>,
+	) -> Result<mpsc::UnboundedReceiver<NotificationEvent>, Error> {
+		self.handler.register_notifications_protocol(
+			&mut NetSyncIo::new(&self.network, self.protocol_id),
+			name,
+			handshake,
+		)
+	}
+
+	/// Send a notification of a registered protocol to a peer. Returns `false` if
+	/// the peer hasn't opened the protocol.
+	pub fn send_notification(&self, who: NodeIndex, name: &[u8], data: Vec<u8>) -> bool {
+		self.handler.send_notification(&mut NetSyncIo::new(&self.network, self.protocol_id), who, name, data)
+	}
+
+	/// Raise or lower the reputation of a peer, e.g. after it sent a useful or
+	/// an invalid transaction. Peers whose reputation drops too low are
+	/// disconnected, and banned for a while.
+	pub fn report_peer(&self, who: NodeIndex, change: ReputationChange) {
+		NetSyncIo::new(&self.network, self.protocol_id).change_reputation(who, change)
+	}
+
+	/// Current reputation of a connected peer.
+	pub fn peer_reputation(&self, who: NodeIndex) -> Option<i32> {
+		self.network.lock().peer_reputation(who)
+	}
+
 	/// Execute a closure with the chain-specific network specialization.
 	pub fn with_spec<F, U>(&self, f: F) -> U
 		where F: FnOnce(&mut S, &mut Context<B>) -> U
@@ -260,6 +418,18 @@ impl<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT> SyncProvider<
 		self.handler.status()
 	}
 
+	fn download_stats(&self) -> DownloadStats<NumberFor<B>> {
+		self.handler.download_stats()
+	}
+
+	fn sync_metrics(&self) -> SyncMetrics {
+		self.handler.sync_metrics()
+	}
+
+	fn justification_requests(&self) -> Vec<ExtraRequestInfo<B>> {
+		self.handler.justification_requests()
+	}
+
 	fn peers(&self) -> Vec<(NodeIndex, Option<PeerId>, PeerInfo<B>)> {
 		let peers = self.handler.peers();
 		let network = self.network.lock();
@@ -275,10 +445,35 @@ pub trait ManageNetwork: Send + Sync {
 	fn accept_unreserved_peers(&self);
 	/// Set to deny unreserved peers to connect
 	fn deny_unreserved_peers(&self);
+	/// Returns true if only reserved peers are allowed to connect
+	fn is_reserved_only(&self) -> bool;
 	/// Remove reservation for the peer
 	fn remove_reserved_peer(&self, peer: PeerId);
 	/// Add reserved peer
 	fn add_reserved_peer(&self, peer: String) -> Result<(), String>;
+	/// Add an address the peer is reachable at
+	fn add_known_address(&self, peer: PeerId, addr: Multiaddr);
+	/// Start connecting to the peer, if we aren't connected already
+	fn dial_peer(&self, peer: PeerId);
+	/// Disconnect the peer, for the given reason
+	fn disconnect_peer(&self, peer: PeerId, reason: &str);
+	/// Returns true if the nodes of the local network are discovered through mDNS
+	fn is_mdns_enabled(&self) -> bool;
+	/// Start or stop discovering the nodes of the local network through mDNS
+	fn set_mdns_enabled(&self, enabled: bool) -> Result<(), String>;
+	/// Returns the maximum number of incoming and outgoing non-reserved peers
+	fn peer_slots(&self) -> (u32, u32);
+	/// Change the maximum number of incoming and outgoing non-reserved peers, disconnecting
+	/// the excess peers or connecting to more
+	fn set_peer_slots(&self, in_peers: u32, out_peers: u32);
+	/// Returns the networks of the IP allow or deny list
+	fn ip_filter(&self, list: IpFilterList) -> Vec<IpNetwork>;
+	/// Add a network in CIDR notation to the IP allow or deny list, disconnecting the peers
+	/// that are no longer allowed
+	fn add_ip_filter(&self, list: IpFilterList, network: String) -> Result<(), String>;
+	/// Remove a network in CIDR notation from the IP allow or deny list. Returns false if it
+	/// wasn't part of the list
+	fn remove_ip_filter(&self, list: IpFilterList, network: String) -> Result<bool, String>;
 	/// Returns a user-friendly identifier of our node.
 	fn node_id(&self) -> Option<String>;
 }
@@ -292,6 +487,10 @@ impl<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT> ManageNetwork
 		self.network.lock().deny_unreserved_peers();
 	}
 
+	fn is_reserved_only(&self) -> bool {
+		self.network.lock().is_reserved_only()
+	}
+
 	fn remove_reserved_peer(&self, peer: PeerId) {
 		self.network.lock().remove_reserved_peer(peer);
 	}
@@ -302,6 +501,49 @@ impl<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT> ManageNetwork
 		Ok(())
 	}
 
+	fn add_known_address(&self, peer: PeerId, addr: Multiaddr) {
+		self.network.lock().add_known_address(peer, addr);
+	}
+
+	fn dial_peer(&self, peer: PeerId) {
+		self.network.lock().dial_peer(peer);
+	}
+
+	fn disconnect_peer(&self, peer: PeerId, reason: &str) {
+		self.network.lock().disconnect_peer(&peer, reason);
+	}
+
+	fn is_mdns_enabled(&self) -> bool {
+		self.network.lock().is_mdns_enabled()
+	}
+
+	fn set_mdns_enabled(&self, enabled: bool) -> Result<(), String> {
+		self.network.lock().set_mdns_enabled(enabled).map_err(|e| format!("{:?}", e))
+	}
+
+	fn peer_slots(&self) -> (u32, u32) {
+		self.network.lock().peer_slots()
+	}
+
+	fn set_peer_slots(&self, in_peers: u32, out_peers: u32) {
+		self.network.lock().set_peer_slots(in_peers, out_peers);
+	}
+
+	fn ip_filter(&self, list: IpFilterList) -> Vec<IpNetwork> {
+		self.network.lock().ip_filter(list)
+	}
+
+	fn add_ip_filter(&self, list: IpFilterList, network: String) -> Result<(), String> {
+		let network = network.parse::<IpNetwork>()?;
+		self.network.lock().add_ip_filter(list, network);
+		Ok(())
+	}
+
+	fn remove_ip_filter(&self, list: IpFilterList, network: String) -> Result<bool, String> {
+		let network = network.parse::<IpNetwork>()?;
+		Ok(self.network.lock().remove_ip_filter(list, &network))
+	}
+
 	fn node_id(&self) -> Option<String> {
 		let network = self.network.lock();
 		let ret = network
@@ -337,6 +579,13 @@ fn start_thread<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT>(
 		},
 	};
 
+	// block requests are served by worker threads, sending through the network service.
+	let with_io = {
+		let service = service.clone();
+		Arc::new(move |f: &mut FnMut(&mut SyncIo)| f(&mut NetSyncIo::new(&service, protocol_id)))
+	};
+	Protocol::start_block_request_workers(&protocol, with_io)?;
+
 	let (close_tx, close_rx) = oneshot::channel();
 	let service_clone = service.clone();
 	let mut runtime = Runtime::new()?;
@@ -412,7 +661,7 @@ fn run_thread<B: BlockT + 'static, S: NetworkSpecialization<B>, H: ExHashT>(
 				}
 			}
 			NetworkServiceEvent::OpenedCustomProtocol { node_index, version, .. } => {
-				debug_assert_eq!(version, protocol::CURRENT_VERSION as u8);
+				debug_assert!(version >= protocol::MIN_VERSION as u8 && version <= protocol::CURRENT_VERSION as u8);
 				protocol.on_peer_connected(&mut net_sync, node_index);
 			}
 			NetworkServiceEvent::ClosedCustomProtocol { node_index, .. } => {
@@ -0,0 +1,195 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Deterministic simulation support.
+//!
+//! The network protocol reads the current time and picks random peers through
+//! this module. Normally these map directly onto `Instant::now` and
+//! `rand::thread_rng`. While a `Simulation` is entered on the current thread,
+//! time only moves when the simulation is advanced and all randomness comes
+//! from a generator seeded by the simulation, so a run can be replayed exactly
+//! from its seed.
+//!
+//! A simulation covers the protocol state machine only: sync, extra requests,
+//! gossip, on-demand requests, pings and extrinsic propagation. Code that picks
+//! among the entries of a hash map sorts them first.
+//!
+//! The import queue is not simulated. The simulation state is thread-local, so
+//! the worker threads serving block requests and the threads of the basic import
+//! queue read the real clock and random generator, and the order in which their
+//! results reach the protocol is not reproducible. Runs are only reproducible
+//! without serving workers and with blocks imported synchronously on the
+//! simulation thread, as the test network does, so races between block import
+//! and sync can't be replayed from a seed. The libp2p layer below the protocol
+//! isn't simulated either.
+
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+use rand::{self, Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+use rand::distributions::{Distribution, Standard};
+
+struct State {
+	epoch: Instant,
+	elapsed: Duration,
+	rng: StdRng,
+}
+
+thread_local! {
+	static SIMULATION: RefCell<Option<State>> = RefCell::new(None);
+}
+
+/// Guard for a deterministic simulation running on the current thread.
+/// The simulation ends when the guard is dropped.
+pub struct Simulation {
+	seed: u64,
+	// simulation state is thread-local, so the guard must stay on its thread.
+	_not_send: PhantomData<*const ()>,
+}
+
+impl Simulation {
+	/// Enter a simulation driven by the given seed on the current thread.
+	///
+	/// Panics if a simulation is already running on this thread.
+	pub fn enter(seed: u64) -> Self {
+		SIMULATION.with(|s| {
+			let mut s = s.borrow_mut();
+			assert!(s.is_none(), "Simulations can not be nested");
+			*s = Some(State {
+				epoch: Instant::now(),
+				elapsed: Duration::from_secs(0),
+				rng: StdRng::seed_from_u64(seed),
+			});
+		});
+
+		Simulation { seed, _not_send: PhantomData }
+	}
+
+	/// The seed this simulation was entered with.
+	pub fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	/// Move the simulated clock forward.
+	pub fn advance(&self, by: Duration) {
+		advance(by);
+	}
+}
+
+impl Drop for Simulation {
+	fn drop(&mut self) {
+		SIMULATION.with(|s| *s.borrow_mut() = None);
+	}
+}
+
+/// Whether a simulation is running on the current thread.
+pub fn is_active() -> bool {
+	SIMULATION.with(|s| s.borrow().is_some())
+}
+
+/// Move the simulated clock forward. Does nothing outside of a simulation.
+pub fn advance(by: Duration) {
+	SIMULATION.with(|s| if let Some(ref mut state) = *s.borrow_mut() {
+		state.elapsed += by;
+	});
+}
+
+/// The current time, simulated if a simulation is running.
+pub fn now() -> Instant {
+	SIMULATION.with(|s| match *s.borrow() {
+		Some(ref state) => state.epoch + state.elapsed,
+		None => Instant::now(),
+	})
+}
+
+/// Shuffle the given items, deterministically if a simulation is running.
+pub fn shuffle<T>(items: &mut [T]) {
+	SIMULATION.with(|s| match *s.borrow_mut() {
+		Some(ref mut state) => items.shuffle(&mut state.rng),
+		None => items.shuffle(&mut rand::thread_rng()),
+	})
+}
+
+/// A random value, deterministic if a simulation is running.
+pub fn random<T>() -> T where Standard: Distribution<T> {
+	SIMULATION.with(|s| match *s.borrow_mut() {
+		Some(ref mut state) => state.rng.gen(),
+		None => rand::random(),
+	})
+}
+
+/// A random duration below `max`, deterministic if a simulation is running.
+pub fn jitter(max: Duration) -> Duration {
+	let max_millis = max.as_secs() * 1000 + u64::from(max.subsec_millis());
+	if max_millis == 0 {
+		return Duration::from_secs(0);
+	}
+	let millis = SIMULATION.with(|s| match *s.borrow_mut() {
+		Some(ref mut state) => state.rng.gen_range(0, max_millis),
+		None => rand::thread_rng().gen_range(0, max_millis),
+	});
+	Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn shuffled(seed: u64) -> Vec<u32> {
+		let _simulation = Simulation::enter(seed);
+		let mut items: Vec<u32> = (0..32).collect();
+		shuffle(&mut items);
+		items
+	}
+
+	#[test]
+	fn shuffle_is_reproducible_from_seed() {
+		assert_eq!(shuffled(7), shuffled(7));
+		assert_ne!(shuffled(7), shuffled(8));
+	}
+
+	#[test]
+	fn random_values_are_reproducible_from_seed() {
+		let values = |seed| {
+			let _simulation = Simulation::enter(seed);
+			(0..4).map(|_| random::<u64>()).collect::<Vec<_>>()
+		};
+		assert_eq!(values(7), values(7));
+		assert_ne!(values(7), values(8));
+	}
+
+	#[test]
+	fn jitter_is_bounded_and_reproducible() {
+		let jitters = |seed| {
+			let _simulation = Simulation::enter(seed);
+			(0..16).map(|_| jitter(Duration::from_secs(3))).collect::<Vec<_>>()
+		};
+		assert_eq!(jitters(7), jitters(7));
+		assert!(jitters(7).iter().all(|j| *j < Duration::from_secs(3)));
+		assert_eq!(jitter(Duration::from_secs(0)), Duration::from_secs(0));
+	}
+
+	#[test]
+	fn clock_only_moves_when_advanced() {
+		let simulation = Simulation::enter(0);
+		let start = now();
+		assert_eq!(now(), start);
+		simulation.advance(Duration::from_secs(5));
+		assert_eq!(now() - start, Duration::from_secs(5));
+		drop(simulation);
+		assert!(!is_active());
+	}
+}
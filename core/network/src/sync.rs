@@ -17,222 +17,289 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use protocol::Context;
+use codec::{Encode, Decode};
+use futures::sync::oneshot;
+use protocol::{Context, CANCEL_REQUEST_VERSION};
 use network_libp2p::{Severity, NodeIndex};
 use client::{BlockStatus, ClientInfo};
 use consensus::BlockOrigin;
 use consensus::import_queue::{ImportQueue, IncomingBlock};
 use client::error::Error as ClientError;
-use blocks::BlockCollection;
+use blocks::{BlockCollection, BlockData, CrossCheck, DownloadStats, ForkBlocks, ForkInsertError};
 use runtime_primitives::Justification;
 use runtime_primitives::traits::{Block as BlockT, Header as HeaderT, As, NumberFor, Zero};
-use runtime_primitives::generic::BlockId;
+use runtime_primitives::generic::{BlockId, SignedBlock};
 use message::{self, generic::Message as GenericMessage};
-use config::Roles;
+use config::{DownloadWindow, ProtocolConfig, Roles};
+use simulation;
+use extra_requests::{ExtraRequests, ExtraRequestInfo, JustificationsEssence};
 
 // Maximum blocks to request in a single packet.
 const MAX_BLOCKS_TO_REQUEST: usize = 128;
+// Maximum blocks to request in a single packet from peers that can't cancel requests.
+const MAX_BLOCKS_TO_REQUEST_LEGACY: usize = 32;
 // Maximum blocks to store in the import queue.
 const MAX_IMPORTING_BLOCKS: usize = 2048;
 // Number of blocks in the queue that prevents ancestry search.
 const MAJOR_SYNC_BLOCKS: usize = 5;
-// Time to wait before trying to get a justification from the same peer.
-const JUSTIFICATION_RETRY_WAIT: Duration = Duration::from_secs(10);
 // Number of recently announced blocks to track for each peer.
 const ANNOUNCE_HISTORY_SIZE: usize = 64;
 // Max number of blocks to download for unknown forks.
 // TODO: this should take finality into account. See https://github.com/paritytech/substrate/issues/1606
 const MAX_UNKNOWN_FORK_DOWNLOAD_LEN: u32 = 32;
-
-struct PeerSync<B: BlockT> {
+// Max number of consecutive ranges to download when looking for the parent of an unknown fork.
+const MAX_UNKNOWN_FORK_RANGES: u32 = 8;
+// Max number of announced stale forks waiting to be downloaded.
+const MAX_PENDING_FORKS: usize = 64;
+// Max number of justifications held back until their block is imported.
+const MAX_BUFFERED_JUSTIFICATIONS: usize = 64;
+// Auxiliary storage key of the mandatory justification requests, kept across restarts.
+pub(crate) const MANDATORY_JUSTIFICATIONS_KEY: &[u8] = b"sync_mandatory_justifications";
+
+// Width of the latency buckets peers are ranked by, in milliseconds.
+const LATENCY_BUCKET_MS: u64 = 100;
+// Latency assumed for peers not measured yet, in milliseconds.
+const DEFAULT_LATENCY_MS: u64 = 300;
+// Time after which a block fetch that is not in flight to any peer is cancelled.
+const BLOCK_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub(crate) struct PeerSync<B: BlockT> {
 	pub common_number: NumberFor<B>,
 	pub best_hash: B::Hash,
 	pub best_number: NumberFor<B>,
 	pub state: PeerSyncState<B>,
 	pub recently_announced: VecDeque<B::Hash>,
+	pub finalized_number: NumberFor<B>,
+	pub latency: Option<Duration>,
+	pub roles: Roles,
+	pub capabilities: PeerCapabilities,
+}
+
+impl<B: BlockT> PeerSync<B> {
+	/// Latency bucket of the peer, lower is better. Peers not measured yet are
+	/// ranked as if they had an average latency.
+	pub(crate) fn latency_rank(&self) -> u64 {
+		let latency = self.latency.unwrap_or(Duration::from_millis(DEFAULT_LATENCY_MS));
+		(latency.as_secs() * 1000 + latency.subsec_millis() as u64) / LATENCY_BUCKET_MS
+	}
 }
 
+/// Requests a peer can serve, depending on the protocol version and features it negotiated.
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-enum PeerSyncState<B: BlockT> {
+pub(crate) struct PeerCapabilities {
+	/// Maximum number of blocks to request in a single packet.
+	pub max_blocks_per_request: usize,
+	/// Whether extra data (e.g. justifications) of consecutive blocks can be
+	/// requested in a single packet.
+	pub extra_ranges: bool,
+}
+
+impl PeerCapabilities {
+	/// Capabilities of a peer using the given protocol version and features. Peers
+	/// that don't understand `CancelRequest` messages keep serving requests we no
+	/// longer need, so requests to them are kept small. Extra data is only requested
+	/// for several blocks at a time from peers advertising batched justifications.
+	pub(crate) fn negotiate(version: u32, features: message::Features) -> Self {
+		let max_blocks_per_request = if version >= CANCEL_REQUEST_VERSION {
+			MAX_BLOCKS_TO_REQUEST
+		} else {
+			MAX_BLOCKS_TO_REQUEST_LEGACY
+		};
+		PeerCapabilities {
+			max_blocks_per_request,
+			extra_ranges: features.contains(message::Features::BATCHED_JUSTIFICATIONS),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub(crate) enum PeerSyncState<B: BlockT> {
 	AncestorSearch(NumberFor<B>),
 	Available,
 	DownloadingNew(NumberFor<B>),
 	DownloadingStale(B::Hash),
-	DownloadingJustification(B::Hash),
 }
 
-/// Pending justification request for the given block (hash and number).
-type PendingJustification<B> = (<B as BlockT>::Hash, NumberFor<B>);
-
-/// Manages pending block justification requests.
-struct PendingJustifications<B: BlockT> {
-	justifications: HashSet<PendingJustification<B>>,
-	pending_requests: VecDeque<PendingJustification<B>>,
-	peer_requests: HashMap<NodeIndex, PendingJustification<B>>,
-	previous_requests: HashMap<PendingJustification<B>, Vec<(NodeIndex, Instant)>>,
+/// An announced stale fork waiting for one of its announcers to become available.
+struct PendingFork<B: BlockT> {
+	number: NumberFor<B>,
+	parent_known: bool,
+	weight: Option<u64>,
+	peers: HashSet<NodeIndex>,
 }
 
-impl<B: BlockT> PendingJustifications<B> {
-	fn new() -> PendingJustifications<B> {
-		PendingJustifications {
-			justifications: HashSet::new(),
-			pending_requests: VecDeque::new(),
-			peer_requests: HashMap::new(),
-			previous_requests: HashMap::new(),
-		}
+impl<B: BlockT> PendingFork<B> {
+	// Forks are downloaded in descending order of their weight, then number.
+	fn priority(&self) -> (Option<u64>, NumberFor<B>) {
+		(self.weight, self.number)
 	}
+}
 
-	/// Dispatches all possible pending requests to the given peers. Peers are
-	/// filtered according to the current known best block (i.e. we won't send a
-	/// justification request for block #10 to a peer at block #2), and we also
-	/// throttle requests to the same peer if a previous justification request
-	/// yielded no results.
-	fn dispatch(&mut self, peers: &mut HashMap<NodeIndex, PeerSync<B>>, protocol: &mut Context<B>) {
-		if self.pending_requests.is_empty() {
-			return;
-		}
-
-		// clean up previous failed requests so we can retry again
-		for (_, requests) in self.previous_requests.iter_mut() {
-			requests.retain(|(_, instant)| instant.elapsed() < JUSTIFICATION_RETRY_WAIT);
-		}
-
-		let mut available_peers = peers.iter().filter_map(|(peer, sync)| {
-			// don't request to any peers that already have pending requests or are unavailable
-			if sync.state != PeerSyncState::Available || self.peer_requests.contains_key(&peer) {
-				None
-			} else {
-				Some((*peer, sync.best_number))
-			}
-		}).collect::<VecDeque<_>>();
-
-		let mut last_peer = available_peers.back().map(|p| p.0);
-		let mut unhandled_requests = VecDeque::new();
-
-		loop {
-			let (peer, peer_best_number) = match available_peers.pop_front() {
-				Some(p) => p,
-				_ => break,
-			};
-
-			// only ask peers that have synced past the block number that we're
-			// asking the justification for and to whom we haven't already made
-			// the same request recently
-			let peer_eligible = {
-				let request = match self.pending_requests.front() {
-					Some(r) => r.clone(),
-					_ => break,
-				};
-
-				peer_best_number >= request.1 &&
-					!self.previous_requests
-						 .get(&request)
-						 .map(|requests| requests.iter().any(|i| i.0 == peer))
-						 .unwrap_or(false)
-			};
+/// A block explicitly requested by another subsystem.
+struct BlockFetch<B: BlockT> {
+	fields: message::BlockAttributes,
+	// Peer the block is currently requested from, if any.
+	peer: Option<NodeIndex>,
+	// Attributes of the request in flight, `fields` may have grown since it was sent.
+	requested_fields: message::BlockAttributes,
+	// Peers that already failed to provide the block.
+	tried: HashSet<NodeIndex>,
+	// When the block was first requested.
+	queued: Instant,
+	senders: Vec<oneshot::Sender<SignedBlock<B>>>,
+}
 
-			if !peer_eligible {
-				available_peers.push_back((peer, peer_best_number));
+/// Manages pending requests for single blocks made through `fetch_block`.
+struct PendingBlockFetches<B: BlockT> {
+	fetches: HashMap<B::Hash, BlockFetch<B>>,
+}
 
-				// we tried all peers and none can answer this request
-				if Some(peer) == last_peer {
-					last_peer = available_peers.back().map(|p| p.0);
+impl<B: BlockT> PendingBlockFetches<B> {
+	fn new() -> Self {
+		PendingBlockFetches {
+			fetches: HashMap::new(),
+		}
+	}
 
-					let request = self.pending_requests.pop_front()
-						.expect("verified to be Some in the beginning of the loop; qed");
+	/// Queue a fetch for the given block (without dispatching it).
+	fn queue(&mut self, hash: B::Hash, fields: message::BlockAttributes, sender: oneshot::Sender<SignedBlock<B>>) {
+		let fetch = self.fetches.entry(hash).or_insert_with(|| BlockFetch {
+			fields: message::BlockAttributes::empty(),
+			peer: None,
+			requested_fields: message::BlockAttributes::empty(),
+			tried: HashSet::new(),
+			queued: Instant::now(),
+			senders: Vec::new(),
+		});
+		fetch.fields |= fields | message::BlockAttributes::HEADER | message::BlockAttributes::BODY;
+		fetch.senders.push(sender);
+	}
 
-					unhandled_requests.push_back(request);
-				}
+	/// Returns the hash of the fetch the given request to a peer was sent for, if any.
+	/// Stale fork downloads and justification requests can start from the same block,
+	/// so the request must have the exact shape of the fetch request in flight.
+	fn fetch_for(&self, who: NodeIndex, request: &message::BlockRequest<B>) -> Option<B::Hash> {
+		let hash = match request.from {
+			message::FromBlock::Hash(hash) => hash,
+			message::FromBlock::Number(_) => return None,
+		};
+		self.fetches.get(&hash)
+			.filter(|fetch| fetch.peer == Some(who))
+			.filter(|fetch| *request == fetch_request::<B>(hash, fetch.requested_fields, request.id))
+			.map(|_| hash)
+	}
 
-				continue;
+	/// Drop fetches nobody is waiting for anymore and cancel the ones that are not
+	/// in flight and were requested more than `BLOCK_FETCH_TIMEOUT` before `now`.
+	fn collect_garbage(&mut self, now: Instant) {
+		self.fetches.retain(|hash, fetch| {
+			fetch.senders.retain(|sender| !sender.is_canceled());
+			if fetch.peer.is_none() && now.duration_since(fetch.queued) >= BLOCK_FETCH_TIMEOUT {
+				debug!(target: "sync", "Timed out fetching block {}", hash);
+				return false;
 			}
+			!fetch.senders.is_empty()
+		});
+	}
 
-			last_peer = available_peers.back().map(|p| p.0);
-
-			let request = self.pending_requests.pop_front()
-				.expect("verified to be Some in the beginning of the loop; qed");
-
-			self.peer_requests.insert(peer, request);
-
-			peers.get_mut(&peer)
-				.expect("peer was is taken from available_peers; available_peers is a subset of peers; qed")
-				.state = PeerSyncState::DownloadingJustification(request.0);
-
-			trace!(target: "sync", "Requesting justification for block #{} from {}", request.0, peer);
-			let request = message::generic::BlockRequest {
-				id: 0,
-				fields: message::BlockAttributes::JUSTIFICATION,
-				from: message::FromBlock::Hash(request.0),
-				to: None,
-				direction: message::Direction::Ascending,
-				max: Some(1),
+	/// Send requests for all fetches that are not in flight. Peers that announced
+	/// the block or have it as their best block are asked first. Fetches that
+	/// every connected peer failed to answer or that no peer could be asked for in
+	/// time are dropped, which cancels them.
+	fn dispatch(&mut self, peers: &HashMap<NodeIndex, PeerSync<B>>, metrics: &mut Metrics, protocol: &mut Context<B>) {
+		self.collect_garbage(Instant::now());
+
+		let mut busy: HashSet<NodeIndex> = self.fetches.values().filter_map(|fetch| fetch.peer).collect();
+		let mut failed = Vec::new();
+		for (hash, fetch) in self.fetches.iter_mut().filter(|&(_, ref fetch)| fetch.peer.is_none()) {
+			// peers known to have the block sort first
+			let mut candidates: Vec<_> = peers.iter()
+				.filter(|&(who, _)| !busy.contains(who) && !fetch.tried.contains(who))
+				.filter(|&(_, peer)| servable_attributes(fetch.fields, peer.roles) == fetch.fields)
+				.map(|(who, peer)| {
+					let has_block = peer.best_hash == *hash || peer.recently_announced.contains(hash);
+					(!has_block, *who)
+				})
+				.collect();
+			candidates.sort();
+
+			let who = match candidates.first() {
+				Some((_, who)) => *who,
+				None => {
+					if !peers.is_empty() && peers.keys().all(|who| fetch.tried.contains(who)) {
+						failed.push(*hash);
+					}
+					continue;
+				},
 			};
 
-			protocol.send_message(peer, GenericMessage::BlockRequest(request));
+			trace!(target: "sync", "Requesting block {} from {}", hash, who);
+			busy.insert(who);
+			fetch.peer = Some(who);
+			fetch.requested_fields = fetch.fields;
+			fetch.tried.insert(who);
+			send_block_request(protocol, metrics, who, fetch_request::<B>(*hash, fetch.fields, 0));
 		}
 
-		self.pending_requests.append(&mut unhandled_requests);
+		for hash in failed {
+			debug!(target: "sync", "No peer could provide block {}", hash);
+			self.fetches.remove(&hash);
+		}
 	}
 
-	/// Queue a justification request (without dispatching it).
-	fn queue_request(&mut self, justification: &PendingJustification<B>) {
-		if !self.justifications.insert(*justification) {
-			return;
+	/// Processes the response to a fetch request. Completes the fetch if the
+	/// peer provided the block, otherwise it will be retried with another peer.
+	fn on_response(&mut self, who: NodeIndex, hash: B::Hash, blocks: Vec<message::BlockData<B>>) {
+		let block = blocks.into_iter().next().and_then(|data| match (data.header, data.body) {
+			(Some(header), Some(body)) => Some(SignedBlock {
+				block: B::new(header, body),
+				justification: data.justification,
+			}),
+			_ => None,
+		});
+
+		match block {
+			Some(block) => if let Some(fetch) = self.fetches.remove(&hash) {
+				for sender in fetch.senders {
+					let _ = sender.send(block.clone());
+				}
+			},
+			None => {
+				trace!(target: "sync", "Peer {} did not provide block {}", who, hash);
+				if let Some(fetch) = self.fetches.get_mut(&hash) {
+					fetch.peer = None;
+				}
+			},
 		}
-		self.pending_requests.push_back(*justification);
 	}
 
-	/// Retry any pending request if a peer disconnected.
+	/// Retry any fetch that was in flight to a peer that disconnected.
 	fn peer_disconnected(&mut self, who: NodeIndex) {
-		if let Some(request) = self.peer_requests.remove(&who) {
-			self.pending_requests.push_front(request);
+		for fetch in self.fetches.values_mut().filter(|fetch| fetch.peer == Some(who)) {
+			fetch.peer = None;
 		}
 	}
+}
 
-	/// Processes the response for the request previously sent to the given
-	/// peer. Queues a retry in case the import fails or the given justification
-	/// was `None`.
-	fn on_response(
-		&mut self,
-		who: NodeIndex,
-		justification: Option<Justification>,
-		protocol: &mut Context<B>,
-		import_queue: &ImportQueue<B>,
-	) {
-		// we assume that the request maps to the given response, this is
-		// currently enforced by the outer network protocol before passing on
-		// messages to chain sync.
-		if let Some(request) = self.peer_requests.remove(&who) {
-			if let Some(justification) = justification {
-				if import_queue.import_justification(request.0, request.1, justification) {
-					self.justifications.remove(&request);
-					self.previous_requests.remove(&request);
-					return;
-				} else {
-					protocol.report_peer(
-						who,
-						Severity::Bad(&format!("Invalid justification provided for #{}", request.0)),
-					);
-				}
-			} else {
-				self.previous_requests
-					.entry(request)
-					.or_insert(Vec::new())
-					.push((who, Instant::now()));
-			}
-
-			self.pending_requests.push_front(request);
-		}
+/// Request for a single block made through `fetch_block`.
+fn fetch_request<B: BlockT>(hash: B::Hash, fields: message::BlockAttributes, id: message::RequestId) -> message::BlockRequest<B> {
+	message::generic::BlockRequest {
+		id,
+		fields,
+		from: message::FromBlock::Hash(hash),
+		to: None,
+		direction: message::Direction::Ascending,
+		max: Some(1),
 	}
+}
 
-	/// Removes any pending justification requests for blocks lower than the
-	/// given best finalized.
-	fn collect_garbage(&mut self, best_finalized: NumberFor<B>) {
-		self.justifications.retain(|(_, n)| *n > best_finalized);
-		self.pending_requests.retain(|(_, n)| *n > best_finalized);
-		self.peer_requests.retain(|_, (_, n)| *n > best_finalized);
-		self.previous_requests.retain(|(_, n), _| *n > best_finalized);
-	}
+/// The pending fork to drop to make room for a new one: the lowest ranked, if it ranks
+/// below the new fork. Forks ranked equally are kept, as they were announced first.
+fn fork_to_evict<B: BlockT>(forks: &HashMap<B::Hash, PendingFork<B>>, new: &PendingFork<B>) -> Option<B::Hash> {
+	forks.iter()
+		.min_by_key(|(_, fork)| fork.priority())
+		.filter(|(_, fork)| fork.priority() < new.priority())
+		.map(|(hash, _)| *hash)
 }
 
 /// Relay chain sync strategy.
@@ -240,11 +307,26 @@ pub struct ChainSync<B: BlockT> {
 	genesis_hash: B::Hash,
 	peers: HashMap<NodeIndex, PeerSync<B>>,
 	blocks: BlockCollection<B>,
+	// Bounds of the number of ranges in `blocks`, depending on the number of peers.
+	download_window: DownloadWindow,
+	// Partially downloaded unknown forks, assembled from descending ranges.
+	fork_blocks: ForkBlocks<B>,
+	// Announced stale forks not yet downloaded, by fork head.
+	pending_forks: HashMap<B::Hash, PendingFork<B>>,
 	best_queued_number: NumberFor<B>,
 	best_queued_hash: B::Hash,
+	best_finalized_number: NumberFor<B>,
+	best_finalized_hash: B::Hash,
 	required_block_attributes: message::BlockAttributes,
 	import_queue: Arc<ImportQueue<B>>,
-	justifications: PendingJustifications<B>,
+	justifications: ExtraRequests<B, JustificationsEssence>,
+	// Justifications for blocks still in the import queue, by block hash.
+	buffered_justifications: HashMap<B::Hash, (NodeIndex, NumberFor<B>, Justification)>,
+	block_fetches: PendingBlockFetches<B>,
+	justifications_only: bool,
+	// Next unfinalized local block to scan for a missing justification, when only syncing justifications.
+	justification_scan_next: Option<NumberFor<B>>,
+	metrics: Metrics,
 }
 
 /// Reported sync state.
@@ -263,6 +345,30 @@ pub struct Status<B: BlockT> {
 	pub state: SyncState,
 	/// Target sync block number.
 	pub best_seen_block: Option<NumberFor<B>>,
+	/// Number of the last block we know to be finalized.
+	pub best_finalized_number: NumberFor<B>,
+	/// Encoded size of the downloaded blocks waiting to be imported.
+	pub queued_block_bytes: usize,
+}
+
+/// Sync metric counters, accumulated since the node started.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Metrics {
+	/// Number of block requests sent to peers.
+	pub requests_sent: u64,
+	/// Number of block responses received from peers.
+	pub responses_received: u64,
+	/// Total encoded size of the received block responses.
+	pub bytes_downloaded: u64,
+	/// Number of block requests sent while searching for a common ancestor.
+	pub ancestor_search_steps: u64,
+	/// Number of times a peer was reported for misbehaving or being useless.
+	pub peers_penalized: u64,
+	/// Number of justification requests repeated after a failed attempt.
+	pub justification_retries: u64,
+	/// Number of block responses that differ from the blocks provided by other
+	/// peers for the same numbers, when cross-checking block data.
+	pub divergent_responses: u64,
 }
 
 impl<B: BlockT> Status<B> {
@@ -277,29 +383,85 @@ impl<B: BlockT> Status<B> {
 }
 
 impl<B: BlockT> ChainSync<B> {
-	/// Create a new instance.
-	pub(crate) fn new(role: Roles, info: &ClientInfo<B>, import_queue: Arc<ImportQueue<B>>) -> Self {
+	/// Create a new instance. Mandatory justification requests that were
+	/// outstanding when the node stopped are queued again.
+	pub(crate) fn new(
+		config: &ProtocolConfig,
+		chain: &::chain::Client<B>,
+		info: &ClientInfo<B>,
+		import_queue: Arc<ImportQueue<B>>,
+	) -> Self {
 		let mut required_block_attributes = message::BlockAttributes::HEADER | message::BlockAttributes::JUSTIFICATION;
-		if role.intersects(Roles::FULL | Roles::AUTHORITY) {
+		if config.roles.intersects(Roles::FULL | Roles::AUTHORITY) {
 			required_block_attributes |= message::BlockAttributes::BODY;
 		}
 
-		ChainSync {
+		let mut sync = ChainSync {
 			genesis_hash: info.chain.genesis_hash,
 			peers: HashMap::new(),
-			blocks: BlockCollection::new(),
+			blocks: {
+				let mut blocks = BlockCollection::with_max_queued_bytes(config.max_queued_block_bytes);
+				blocks.set_cross_check(config.cross_check_blocks);
+				blocks
+			},
+			download_window: config.download_window.clone(),
+			fork_blocks: ForkBlocks::new(),
+			pending_forks: HashMap::new(),
 			best_queued_hash: info.best_queued_hash.unwrap_or(info.chain.best_hash),
 			best_queued_number: info.best_queued_number.unwrap_or(info.chain.best_number),
-			justifications: PendingJustifications::new(),
+			best_finalized_hash: info.chain.finalized_hash,
+			best_finalized_number: info.chain.finalized_number,
+			justifications: ExtraRequests::new(
+				JustificationsEssence,
+				config.justification_retry.clone(),
+				config.max_pending_justifications,
+			),
+			buffered_justifications: HashMap::new(),
+			block_fetches: PendingBlockFetches::new(),
 			required_block_attributes,
 			import_queue,
+			justifications_only: config.justifications_only,
+			justification_scan_next: if config.justifications_only {
+				Some(info.chain.finalized_number + As::sa(1))
+			} else {
+				None
+			},
+			metrics: Metrics::default(),
+		};
+
+		for (hash, number) in load_mandatory_justifications(chain) {
+			// the justification may have been imported right before stopping.
+			match chain.justification(&BlockId::Hash(hash)) {
+				Ok(Some(_)) => continue,
+				_ => sync.justifications.queue_request(&(hash, number), true),
+			}
 		}
+		// the loaded requests are already persisted.
+		sync.justifications.take_mandatory_changes();
+
+		sync
 	}
 
 	fn best_seen_block(&self) -> Option<NumberFor<B>> {
 		self.peers.values().max_by_key(|p| p.best_number).map(|p| p.best_number)
 	}
 
+	/// Returns the state of the tracked justification requests.
+	pub(crate) fn justification_requests(&self) -> Vec<ExtraRequestInfo<B>> {
+		self.justifications.requests_info()
+	}
+
+	/// Returns sync metrics.
+	pub(crate) fn metrics(&self) -> Metrics {
+		self.metrics.clone()
+	}
+
+	/// Note a block response of the given encoded size received from a peer.
+	pub(crate) fn note_block_response(&mut self, bytes: usize) {
+		self.metrics.responses_received += 1;
+		self.metrics.bytes_downloaded += bytes as u64;
+	}
+
 	/// Returns import queue reference.
 	pub(crate) fn import_queue(&self) -> Arc<ImportQueue<B>> {
 		self.import_queue.clone()
@@ -315,22 +477,46 @@ impl<B: BlockT> ChainSync<B> {
 		Status {
 			state: state,
 			best_seen_block: best_seen,
+			best_finalized_number: self.best_finalized_number,
+			queued_block_bytes: self.blocks.queued_bytes(),
 		}
 	}
 
+	/// Returns the occupancy of the block download window. Walks the downloaded
+	/// ranges, unlike `status`.
+	pub(crate) fn download_stats(&self) -> DownloadStats<NumberFor<B>> {
+		self.blocks.stats(self.best_queued_number + As::sa(1))
+	}
+
 	/// Handle new connected peer.
 	pub(crate) fn new_peer(&mut self, protocol: &mut Context<B>, who: NodeIndex) {
 		if let Some(info) = protocol.peer_info(who) {
+			if self.justifications_only {
+				// we never download from peers, they are only asked for justifications.
+				debug!(target:"sync", "New peer with best hash {} ({}), justification sync only.", info.best_hash, info.best_number);
+				self.peers.insert(who, PeerSync {
+					common_number: ::std::cmp::min(self.best_queued_number, info.best_number),
+					best_hash: info.best_hash,
+					best_number: info.best_number,
+					state: PeerSyncState::Available,
+					recently_announced: Default::default(),
+					finalized_number: As::sa(0),
+					latency: None,
+					roles: info.roles,
+					capabilities: PeerCapabilities::negotiate(info.protocol_version, info.features),
+				});
+				return;
+			}
 			match (block_status(&*protocol.client(), &*self.import_queue, info.best_hash), info.best_number) {
 				(Err(e), _) => {
 					debug!(target:"sync", "Error reading blockchain: {:?}", e);
-					protocol.report_peer(who, Severity::Useless(&format!("Error legimimately reading blockchain status: {:?}", e)));
+					report_peer(protocol, &mut self.metrics, who, Severity::Useless(&format!("Error legimimately reading blockchain status: {:?}", e)));
 				},
 				(Ok(BlockStatus::KnownBad), _) => {
-					protocol.report_peer(who, Severity::Bad(&format!("New peer with known bad best block {} ({}).", info.best_hash, info.best_number)));
+					report_peer(protocol, &mut self.metrics, who, Severity::Bad(&format!("New peer with known bad best block {} ({}).", info.best_hash, info.best_number)));
 				},
 				(Ok(BlockStatus::Unknown), b) if b.is_zero() => {
-					protocol.report_peer(who, Severity::Bad(&format!("New peer with unknown genesis hash {} ({}).", info.best_hash, info.best_number)));
+					report_peer(protocol, &mut self.metrics, who, Severity::Bad(&format!("New peer with unknown genesis hash {} ({}).", info.best_hash, info.best_number)));
 				},
 				(Ok(BlockStatus::Unknown), _) if self.import_queue.status().importing_count > MAJOR_SYNC_BLOCKS => {
 					// when actively syncing the common point moves too fast.
@@ -341,6 +527,10 @@ impl<B: BlockT> ChainSync<B> {
 						best_number: info.best_number,
 						state: PeerSyncState::Available,
 						recently_announced: Default::default(),
+						finalized_number: As::sa(0),
+						latency: None,
+						roles: info.roles,
+						capabilities: PeerCapabilities::negotiate(info.protocol_version, info.features),
 					});
 				}
 				(Ok(BlockStatus::Unknown), _) => {
@@ -354,8 +544,12 @@ impl<B: BlockT> ChainSync<B> {
 							best_number: info.best_number,
 							state: PeerSyncState::AncestorSearch(common_best),
 							recently_announced: Default::default(),
+							finalized_number: As::sa(0),
+							latency: None,
+							roles: info.roles,
+							capabilities: PeerCapabilities::negotiate(info.protocol_version, info.features),
 						});
-						Self::request_ancestry(protocol, who, common_best)
+						Self::request_ancestry(protocol, &mut self.metrics, who, common_best)
 					} else {
 						// We are at genesis, just start downloading
 						debug!(target:"sync", "New peer with best hash {} ({}).", info.best_hash, info.best_number);
@@ -365,6 +559,10 @@ impl<B: BlockT> ChainSync<B> {
 							best_number: info.best_number,
 							state: PeerSyncState::Available,
 							recently_announced: Default::default(),
+							finalized_number: As::sa(0),
+							latency: None,
+							roles: info.roles,
+							capabilities: PeerCapabilities::negotiate(info.protocol_version, info.features),
 						});
 						self.download_new(protocol, who)
 					}
@@ -377,6 +575,10 @@ impl<B: BlockT> ChainSync<B> {
 						best_number: info.best_number,
 						state: PeerSyncState::Available,
 						recently_announced: Default::default(),
+						finalized_number: As::sa(0),
+						latency: None,
+						roles: info.roles,
+						capabilities: PeerCapabilities::negotiate(info.protocol_version, info.features),
 					});
 				}
 			}
@@ -391,41 +593,125 @@ impl<B: BlockT> ChainSync<B> {
 		request: message::BlockRequest<B>,
 		response: message::BlockResponse<B>
 	) -> Option<(BlockOrigin, Vec<IncomingBlock<B>>)> {
+		if let Some(hash) = self.block_fetches.fetch_for(who, &request) {
+			if let Err(msg) = validate_block_response(&request, &response.blocks) {
+				trace!(target: "sync", "Invalid block fetch response from {}: {}", who, msg);
+				report_peer(protocol, &mut self.metrics, who, Severity::Bad(&msg));
+				return None;
+			}
+			self.block_fetches.on_response(who, hash, response.blocks);
+			self.block_fetches.dispatch(&self.peers, &mut self.metrics, protocol);
+			return None;
+		}
+
 		let new_blocks: Vec<IncomingBlock<B>> = if let Some(ref mut peer) = self.peers.get_mut(&who) {
 			let mut blocks = response.blocks;
 			if request.direction == message::Direction::Descending {
 				trace!(target: "sync", "Reversing incoming block list");
 				blocks.reverse();
 			}
+			if let Err(msg) = validate_block_response(&request, &blocks) {
+				trace!(target: "sync", "Invalid block response from {}: {}", who, msg);
+				report_peer(protocol, &mut self.metrics, who, Severity::Bad(&msg));
+				return None;
+			}
 			match peer.state {
 				PeerSyncState::DownloadingNew(start_block) => {
 					self.blocks.clear_peer_download(who);
 					peer.state = PeerSyncState::Available;
-					self.blocks.insert(start_block, blocks, who);
+					// blocks sent without the requested body are downloaded again,
+					// the rest of the response is kept.
+					let missing_bodies = if request.fields.contains(message::BlockAttributes::BODY) {
+						blocks.iter()
+							.enumerate()
+							.filter(|&(_, block)| block.body.is_none())
+							.map(|(i, _)| start_block + As::sa(i as u64))
+							.collect::<Vec<_>>()
+					} else {
+						Vec::new()
+					};
+					match self.blocks.insert(start_block, blocks, who) {
+						Some(CrossCheck::Diverged(divergence)) => {
+							warn!(target: "sync", "Block #{} from {} differs from the one provided by {:?}, downloading again",
+								divergence.number, who, divergence.peers);
+							self.metrics.divergent_responses += 1;
+						},
+						Some(CrossCheck::Resolved { number, wrong }) => {
+							for wrong_peer in wrong {
+								let msg = format!("Block #{} differs from the one provided by {}", number, who);
+								report_peer(protocol, &mut self.metrics, wrong_peer, Severity::Useless(&msg));
+							}
+						},
+						None => {},
+					}
+					for number in missing_bodies {
+						trace!(target: "sync", "Block #{} from {} is missing requested body", number, who);
+						self.blocks.mark_missing(number .. number + As::sa(1));
+					}
+					// `drain_ready_blocks` can't be called while the peer is borrowed.
 					self.blocks
 						.drain(self.best_queued_number + As::sa(1))
 						.into_iter()
-						.map(|block_data| {
-							IncomingBlock {
-								hash: block_data.block.hash,
-								header: block_data.block.header,
-								body: block_data.block.body,
-								justification: block_data.block.justification,
-								origin: block_data.origin,
+						.map(BlockData::into_incoming)
+						.collect()
+				},
+				PeerSyncState::DownloadingStale(head) if request.direction == message::Direction::Descending => {
+					match self.fork_blocks.insert(blocks, who) {
+						Ok(()) => (),
+						Err(ForkInsertError::HashMismatch(hash)) => {
+							peer.state = PeerSyncState::Available;
+							self.fork_blocks.remove_peer(who);
+							let msg = format!("Block {} of unknown fork {} doesn't match its header", hash, head);
+							report_peer(protocol, &mut self.metrics, who, Severity::Bad(&msg));
+							return None;
+						},
+						Err(ForkInsertError::Full) => {
+							trace!(target: "sync", "Too many blocks of unknown forks downloaded, giving up {} from {}", head, who);
+							peer.state = PeerSyncState::Available;
+							self.fork_blocks.remove_peer(who);
+							return None;
+						},
+					}
+					match self.fork_blocks.lowest_ancestor(who, &head) {
+						Some((number, parent_hash)) => {
+							let parent_known = block_status(&*protocol.client(), &*self.import_queue, parent_hash)
+								.ok()
+								.map_or(false, |s| s != BlockStatus::Unknown);
+							if parent_known {
+								trace!(target: "sync", "Downloaded unknown fork {} from {} down to #{}", head, who, number);
+								peer.state = PeerSyncState::Available;
+								self.fork_blocks.drain(who, &head)
+									.into_iter()
+									.map(BlockData::into_incoming)
+									.collect()
+							} else if number <= self.best_finalized_number + As::sa(1) {
+								trace!(target: "sync", "Unknown fork {} from {} conflicts with our finalized block #{}", head, who, self.best_finalized_number);
+								peer.state = PeerSyncState::Available;
+								self.fork_blocks.drain(who, &head);
+								Vec::new()
+							} else if number + As::sa((MAX_UNKNOWN_FORK_DOWNLOAD_LEN * MAX_UNKNOWN_FORK_RANGES) as u64) < self.best_queued_number {
+								trace!(target: "sync", "Unknown fork {} from {} is too deep, giving up at #{}", head, who, number);
+								peer.state = PeerSyncState::Available;
+								self.fork_blocks.drain(who, &head);
+								Vec::new()
+							} else {
+								trace!(target: "sync", "Requesting more of the unknown fork {} from {} below #{}", head, who, number);
+								let fields = servable_attributes(self.required_block_attributes, peer.roles);
+								Self::request_fork_range(protocol, &mut self.metrics, who, parent_hash, fields);
+								return None;
 							}
-						}).collect()
+						},
+						None => {
+							peer.state = PeerSyncState::Available;
+							Vec::new()
+						},
+					}
 				},
 				PeerSyncState::DownloadingStale(_) => {
 					peer.state = PeerSyncState::Available;
-					blocks.into_iter().map(|b| {
-						IncomingBlock {
-							hash: b.hash,
-							header: b.header,
-							body: b.body,
-							justification: b.justification,
-							origin: Some(who),
-						}
-					}).collect()
+					blocks.into_iter()
+						.map(|block| BlockData::new(block, Some(who)).into_incoming())
+						.collect()
 				},
 				PeerSyncState::AncestorSearch(n) => {
 					match blocks.get(0) {
@@ -440,85 +726,119 @@ impl<B: BlockT> ChainSync<B> {
 									trace!(target:"sync", "Found common ancestor for peer {}: {} ({})", who, block.hash, n);
 									vec![]
 								},
+								Ok(our_best) if n > As::sa(0) && n <= self.best_finalized_number => {
+									// finalized blocks can not be reverted, so there is no point in searching any further
+									trace!(target:"sync", "Ancestry search: peer {} forked at or before our finalized block #{}: theirs: {} ({}), ours: {:?}", who, self.best_finalized_number, block.hash, n, our_best);
+									report_peer(protocol, &mut self.metrics, who, Severity::Bad("Ancestry search: peer is on a fork conflicting with our finalized chain"));
+									return None;
+								},
 								Ok(our_best) if n > As::sa(0) => {
 									trace!(target:"sync", "Ancestry block mismatch for peer {}: theirs: {} ({}), ours: {:?}", who, block.hash, n, our_best);
 									let n = n - As::sa(1);
 									peer.state = PeerSyncState::AncestorSearch(n);
-									Self::request_ancestry(protocol, who, n);
+									Self::request_ancestry(protocol, &mut self.metrics, who, n);
 									return None;
 								},
 								Ok(_) => { // genesis mismatch
 									trace!(target:"sync", "Ancestry search: genesis mismatch for peer {}", who);
-									protocol.report_peer(who, Severity::Bad("Ancestry search: genesis mismatch for peer"));
+									report_peer(protocol, &mut self.metrics, who, Severity::Bad("Ancestry search: genesis mismatch for peer"));
 									return None;
 								},
 								Err(e) => {
-									protocol.report_peer(who, Severity::Useless(&format!("Error answering legitimate blockchain query: {:?}", e)));
+									report_peer(protocol, &mut self.metrics, who, Severity::Useless(&format!("Error answering legitimate blockchain query: {:?}", e)));
 									return None;
 								}
 							}
 						},
 						None => {
 							trace!(target:"sync", "Invalid response when searching for ancestor from {}", who);
-							protocol.report_peer(who, Severity::Bad("Invalid response when searching for ancestor"));
+							report_peer(protocol, &mut self.metrics, who, Severity::Bad("Invalid response when searching for ancestor"));
 							return None;
 						}
 					}
 				},
-				PeerSyncState::Available | PeerSyncState::DownloadingJustification(..) => Vec::new(),
+				PeerSyncState::Available => Vec::new(),
 			}
 		} else {
 			Vec::new()
 		};
+		// blocks completed earlier may have become ready without the help of this response.
+		let new_blocks = if new_blocks.is_empty() { self.drain_ready_blocks() } else { new_blocks };
+
+		let origin = self.queue_blocks(&new_blocks);
+		self.maintain_sync(protocol);
+		Some((origin, new_blocks))
+	}
 
-		let is_recent = new_blocks
-			.first()
-			.map(|block| self.peers.iter().any(|(_, peer)| peer.recently_announced.contains(&block.hash)))
-			.unwrap_or(false);
-		let origin = if is_recent { BlockOrigin::NetworkBroadcast } else { BlockOrigin::NetworkInitialSync };
+	/// Take the downloaded blocks that can now be imported in order, if any.
+	///
+	/// Blocks are mostly handed over as a result of `on_block_data`, but a range may
+	/// also become ready when the peers holding it back go away. This must be called
+	/// after any such event, so that the import queue doesn't wait for the next response.
+	pub(crate) fn take_ready_blocks(&mut self) -> Option<(BlockOrigin, Vec<IncomingBlock<B>>)> {
+		let blocks = self.drain_ready_blocks();
+		if blocks.is_empty() {
+			return None;
+		}
+		let origin = self.queue_blocks(&blocks);
+		Some((origin, blocks))
+	}
+
+	// Downloaded blocks directly following the best queued block.
+	fn drain_ready_blocks(&mut self) -> Vec<IncomingBlock<B>> {
+		self.blocks
+			.drain(self.best_queued_number + As::sa(1))
+			.into_iter()
+			.map(BlockData::into_incoming)
+			.collect()
+	}
 
-		if let Some((hash, number)) = new_blocks.last()
+	// Note the blocks handed over to the import queue as queued, and return their origin.
+	fn queue_blocks(&mut self, blocks: &[IncomingBlock<B>]) -> BlockOrigin {
+		let origin = {
+			let peers = &self.peers;
+			import_origin(blocks, |hash| peers.values().any(|peer| peer.recently_announced.contains(hash)))
+		};
+		if let Some((hash, number)) = blocks.last()
 			.and_then(|b| b.header.as_ref().map(|h| (b.hash.clone(), *h.number())))
 		{
-			trace!(target:"sync", "Accepted {} blocks ({:?}) with origin {:?}", new_blocks.len(), hash, origin);
+			trace!(target:"sync", "Accepted {} blocks ({:?}) with origin {:?}", blocks.len(), hash, origin);
 			self.block_queued(&hash, number);
 		}
-		self.maintain_sync(protocol);
-		Some((origin, new_blocks))
+		origin
 	}
 
-	/// Handle new justification data.
+	/// Handle new justification data. Returns the justifications to import; the
+	/// result of each import must be reported with `justification_import_result`.
 	pub(crate) fn on_block_justification_data(
 		&mut self,
 		protocol: &mut Context<B>,
 		who: NodeIndex,
-		_request: message::BlockRequest<B>,
+		request: message::BlockRequest<B>,
 		response: message::BlockResponse<B>,
-	) {
-		if let Some(ref mut peer) = self.peers.get_mut(&who) {
-			if let PeerSyncState::DownloadingJustification(hash) = peer.state {
-				peer.state = PeerSyncState::Available;
-
-				// we only request one justification at a time
-				match response.blocks.into_iter().next() {
-					Some(response) => {
-						if hash != response.hash {
+	) -> Vec<((B::Hash, NumberFor<B>), Justification)> {
+		let mut import = Vec::new();
+		if self.peers.contains_key(&who) && self.justifications.peer_request(who).is_some() {
+			if let message::FromBlock::Hash(hash) = request.from {
+				// the response starts at the block the request was sent for, even if it
+				// has been garbage collected since. Later blocks may differ if the peer's
+				// best chain does and are then simply missing.
+				match response.blocks.first().map(|block| block.hash) {
+					Some(first) => {
+						if hash != first {
 							let msg = format!(
 								"Invalid block justification provided: requested: {:?} got: {:?}",
 								hash,
-								response.hash,
+								first,
 							);
 
-							protocol.report_peer(who, Severity::Bad(&msg));
-							return;
+							report_peer(protocol, &mut self.metrics, who, Severity::Bad(&msg));
+							return import;
 						}
 
-						self.justifications.on_response(
-							who,
-							response.justification,
-							protocol,
-							&*self.import_queue,
-						);
+						let justifications = self.justifications.on_response(who, response.blocks);
+						self.on_justification_requests_changed(protocol);
+						import = self.buffer_queued_justifications(protocol, who, justifications);
 					},
 					None => {
 						let msg = format!(
@@ -526,46 +846,198 @@ impl<B: BlockT> ChainSync<B> {
 							hash,
 						);
 
-						protocol.report_peer(who, Severity::Useless(&msg));
-						return;
+						report_peer(protocol, &mut self.metrics, who, Severity::Useless(&msg));
+						return import;
 					},
 				}
 			}
 		}
 
 		self.maintain_sync(protocol);
+		import
+	}
+
+	/// Hold back justifications for blocks that are still in the import queue,
+	/// importing them would fail. They are returned by `block_imported` once
+	/// the block is imported. Returns the justifications to import right away.
+	fn buffer_queued_justifications(
+		&mut self,
+		protocol: &mut Context<B>,
+		who: NodeIndex,
+		justifications: Vec<((B::Hash, NumberFor<B>), Justification)>,
+	) -> Vec<((B::Hash, NumberFor<B>), Justification)> {
+		let mut import = Vec::with_capacity(justifications.len());
+		for ((hash, number), justification) in justifications {
+			let queued = block_status(&*protocol.client(), &*self.import_queue, hash)
+				.map_or(false, |status| status == BlockStatus::Queued);
+			if queued && self.buffered_justifications.len() < MAX_BUFFERED_JUSTIFICATIONS {
+				trace!(target: "sync", "Buffering justification for queued block {} ({}) from {}", number, hash, who);
+				self.buffered_justifications.insert(hash, (who, number, justification));
+			} else {
+				import.push(((hash, number), justification));
+			}
+		}
+		import
+	}
+
+	/// Notify about the result of importing a justification provided by the given peer.
+	pub fn justification_import_result(
+		&mut self,
+		who: NodeIndex,
+		hash: &B::Hash,
+		number: NumberFor<B>,
+		success: bool,
+		protocol: &mut Context<B>,
+	) {
+		trace!(target: "sync", "Justification import of block {} ({}) from {}: {}", number, hash, who, if success { "ok" } else { "failed" });
+		self.justifications.on_import_result(who, &(*hash, number), success, protocol, &mut self.metrics);
+		self.on_justification_requests_changed(protocol);
+		self.justifications.dispatch(&self.peers, &mut self.metrics, protocol);
 	}
 
 	/// Maintain the sync process (download new blocks, fetch justifications).
 	pub fn maintain_sync(&mut self, protocol: &mut Context<B>) {
-		let peers: Vec<NodeIndex> = self.peers.keys().map(|p| *p).collect();
+		let mut peers: Vec<NodeIndex> = self.peers.keys().map(|p| *p).collect();
+		peers.sort();
+		simulation::shuffle(&mut peers);
+		// peers with a similar latency are asked in random order.
+		peers.sort_by_key(|who| self.peers[who].latency_rank());
 		for peer in peers {
 			self.download_new(protocol, peer);
 		}
-		self.justifications.dispatch(&mut self.peers, protocol);
+		self.download_pending_forks(protocol);
+		self.scan_unfinalized_justifications(protocol);
+		self.justifications.dispatch(&self.peers, &mut self.metrics, protocol);
+		self.block_fetches.dispatch(&self.peers, &mut self.metrics, protocol);
 	}
 
 	/// Called periodically to perform any time-based actions.
 	pub fn tick(&mut self, protocol: &mut Context<B>) {
-		self.justifications.dispatch(&mut self.peers, protocol);
+		self.scan_unfinalized_justifications(protocol);
+		self.justifications.on_timeouts(protocol, &mut self.metrics);
+		self.on_justification_requests_changed(protocol);
+		self.justifications.dispatch(&self.peers, &mut self.metrics, protocol);
+		self.block_fetches.dispatch(&self.peers, &mut self.metrics, protocol);
+	}
+
+	/// Notify the import queue about justifications that are no longer
+	/// requested after exhausting their attempt budget or being dropped.
+	fn report_unavailable_justifications(&mut self) {
+		for (hash, number) in self.justifications.take_unavailable() {
+			debug!(target: "sync", "Justification for block {} ({}) is unavailable", number, hash);
+			self.import_queue.justification_unavailable(hash, number);
+		}
+	}
+
+	/// Report the justification requests given up and persist the mandatory
+	/// requests if they changed, so that they are requested again after a restart.
+	fn on_justification_requests_changed(&mut self, protocol: &mut Context<B>) {
+		self.report_unavailable_justifications();
+
+		if let Some(requests) = self.justifications.take_mandatory_changes() {
+			let encoded = requests.encode();
+			if let Err(e) = protocol.client().insert_aux(&[(MANDATORY_JUSTIFICATIONS_KEY, &encoded[..])], &[]) {
+				warn!(target: "sync", "Error persisting justification requests: {:?}", e);
+			}
+		}
+	}
+
+	/// When only syncing justifications, queue a justification request for each
+	/// local block above our last finalized block that a peer finalized. The scan
+	/// resumes where it stopped once peers announce further finalized blocks.
+	fn scan_unfinalized_justifications(&mut self, protocol: &mut Context<B>) {
+		let from = match self.justification_scan_next {
+			Some(from) => from,
+			None => return,
+		};
+		let peers_finalized = match self.peers.values().map(|peer| peer.finalized_number).max() {
+			Some(number) if number >= from => number,
+			_ => return,
+		};
+
+		let best_number = match protocol.client().info() {
+			Ok(info) => info.chain.best_number,
+			Err(e) => {
+				debug!(target:"sync", "Error reading blockchain: {:?}", e);
+				return;
+			}
+		};
+
+		let to = ::std::cmp::min(peers_finalized, best_number);
+		let mut number = from;
+		while number <= to {
+			match protocol.client().block_hash(number) {
+				Ok(Some(hash)) => self.justifications.queue_request(&(hash, number), false),
+				_ => break,
+			}
+			number = number + As::sa(1);
+		}
+		self.justification_scan_next = Some(number);
+
+		if number > from {
+			trace!(target: "sync", "Queued justification requests for blocks {} to {}", from, number - As::sa(1));
+		}
 	}
 
 	/// Request a justification for the given block.
 	///
 	/// Queues a new justification request and tries to dispatch all pending requests.
-	pub fn request_justification(&mut self, hash: &B::Hash, number: NumberFor<B>, protocol: &mut Context<B>) {
-		self.justifications.queue_request(&(*hash, number));
-		self.justifications.dispatch(&mut self.peers, protocol);
+	/// Mandatory requests are needed to make finality progress: they take precedence
+	/// over optional ones and are kept even once the block is finalized. If too many
+	/// justifications are pending a request may be dropped, which is reported to the
+	/// import queue as unavailable for mandatory requests.
+	pub fn request_justification(
+		&mut self,
+		hash: &B::Hash,
+		number: NumberFor<B>,
+		mandatory: bool,
+		protocol: &mut Context<B>,
+	) {
+		self.justifications.queue_request(&(*hash, number), mandatory);
+		self.justifications.dispatch(&self.peers, &mut self.metrics, protocol);
+		self.on_justification_requests_changed(protocol);
 	}
 
-	/// Notify about successful import of the given block.
-	pub fn block_imported(&mut self, hash: &B::Hash, number: NumberFor<B>) {
+	/// Fetch a single block from the network.
+	///
+	/// Queues a new block fetch and tries to dispatch all pending fetches. The
+	/// block is sent through `sender` once a peer provides it.
+	pub(crate) fn fetch_block(
+		&mut self,
+		hash: B::Hash,
+		fields: message::BlockAttributes,
+		sender: oneshot::Sender<SignedBlock<B>>,
+		protocol: &mut Context<B>,
+	) {
+		self.block_fetches.queue(hash, fields, sender);
+		self.block_fetches.dispatch(&self.peers, &mut self.metrics, protocol);
+	}
+
+	/// Notify about successful import of the given block. Returns a buffered
+	/// justification for the block and the peer that provided it, if any; the
+	/// result of its import must be reported with `justification_import_result`.
+	pub fn block_imported(&mut self, hash: &B::Hash, number: NumberFor<B>) -> Option<(NodeIndex, Justification)> {
 		trace!(target: "sync", "Block imported successfully {} ({})", number, hash);
+		self.buffered_justifications.remove(hash).map(|(who, _, justification)| (who, justification))
 	}
 
 	/// Notify about finalization of the given block.
-	pub fn block_finalized(&mut self, _hash: &B::Hash, number: NumberFor<B>) {
-		self.justifications.collect_garbage(number);
+	pub fn block_finalized(&mut self, hash: &B::Hash, number: NumberFor<B>, protocol: &mut Context<B>) {
+		if number > self.best_finalized_number {
+			self.best_finalized_number = number;
+			self.best_finalized_hash = *hash;
+		}
+		self.pending_forks.retain(|_, fork| fork.number > number);
+		self.buffered_justifications.retain(|_, (_, n, _)| *n > number);
+		{
+			let client = protocol.client();
+			let best_finalized = self.best_finalized_number;
+			self.justifications.collect_garbage(best_finalized, |&(hash, number)|
+				is_on_finalized_chain(client, best_finalized, hash, number)
+			);
+		}
+		self.on_justification_requests_changed(protocol);
+		self.fork_blocks.prune(number);
 	}
 
 	fn block_queued(&mut self, hash: &B::Hash, number: NumberFor<B>) {
@@ -593,6 +1065,57 @@ impl<B: BlockT> ChainSync<B> {
 		self.block_queued(&hash, best_header.number().clone())
 	}
 
+	/// Handle a switch of our best chain to another fork. `retracted` are the
+	/// blocks removed from the best chain, newest first.
+	///
+	/// Rewinds the best queued block to the new best block and moves peer common
+	/// blocks back to the fork point, so downloads continue on the new chain.
+	pub(crate) fn on_chain_reorg(&mut self, protocol: &mut Context<B>, retracted: &[B::Hash], best_header: &B::Header) {
+		let best_number = *best_header.number();
+		let fork_number = match retracted.last().map(|hash| protocol.client().header(&BlockId::Hash(*hash))) {
+			Some(Ok(Some(header))) => *header.number() - As::sa(1),
+			Some(Err(e)) => {
+				debug!(target:"sync", "Error reading blockchain: {:?}", e);
+				best_number
+			},
+			_ => best_number,
+		};
+		let fork_number = ::std::cmp::min(fork_number, best_number);
+
+		// the queued blocks only build on the retracted fork if they go past the fork point,
+		// and aren't the blocks that made the new chain the best one.
+		let queued_on_new_chain = self.best_queued_number <= best_number &&
+			protocol.client().block_hash(self.best_queued_number).ok() == Some(Some(self.best_queued_hash));
+		let rewind = self.best_queued_number > fork_number && !queued_on_new_chain;
+
+		debug!(target:"sync", "Reorg to {} ({}), forked at {}, previously queued {} ({}){}",
+			best_number, best_header.hash(), fork_number, self.best_queued_number, self.best_queued_hash,
+			if rewind { ", rewinding" } else { "" });
+
+		if rewind || self.best_queued_number < best_number {
+			self.best_queued_hash = best_header.hash();
+			self.best_queued_number = best_number;
+		}
+
+		if rewind {
+			// downloaded blocks may build on the retracted fork.
+			self.blocks.clear();
+		}
+		for (who, peer) in self.peers.iter_mut() {
+			if peer.common_number > fork_number {
+				peer.common_number = fork_number;
+			}
+			if rewind {
+				if let PeerSyncState::DownloadingNew(_) = peer.state {
+					Self::cancel_downloads(&self.block_fetches, protocol, *who);
+					peer.state = PeerSyncState::Available;
+				}
+			}
+		}
+
+		self.maintain_sync(protocol);
+	}
+
 	/// Handle new block announcement.
 	pub(crate) fn on_block_announce(&mut self, protocol: &mut Context<B>, who: NodeIndex, hash: B::Hash, header: &B::Header) {
 		let number = *header.number();
@@ -624,16 +1147,21 @@ impl<B: BlockT> ChainSync<B> {
 			return;
 		}
 
+		if self.justifications_only {
+			return;
+		}
+
 		if !(known || self.is_already_downloading(&hash)) {
 			let stale = number <= self.best_queued_number;
 			if stale {
-				if !(known_parent || self.is_already_downloading(header.parent_hash())) {
+				let parent_known = known_parent || self.is_already_downloading(header.parent_hash());
+				if !parent_known {
 					trace!(target: "sync", "Considering new unknown stale block announced from {}: {} {:?}", who, hash, header);
-					self.download_unknown_stale(protocol, who, &hash);
 				} else {
 					trace!(target: "sync", "Considering new stale block announced from {}: {} {:?}", who, hash, header);
-					self.download_stale(protocol, who, &hash);
 				}
+				self.queue_fork(who, hash, header, parent_known);
+				self.download_pending_forks(protocol);
 			} else {
 				trace!(target: "sync", "Considering new block announced from {}: {} {:?}", who, hash, header);
 				self.download_new(protocol, who);
@@ -643,6 +1171,92 @@ impl<B: BlockT> ChainSync<B> {
 		}
 	}
 
+	/// Note the round-trip time to a peer, measured by the protocol.
+	pub(crate) fn note_latency(&mut self, who: NodeIndex, latency: Duration) {
+		if let Some(peer) = self.peers.get_mut(&who) {
+			peer.latency = Some(latency);
+		}
+	}
+
+	/// Handle a block finalized by a peer. Forks the peer announced up to that
+	/// height are no longer downloaded from it: it finalized at most one of them
+	/// and regular sync gets that one.
+	pub(crate) fn on_finalized_announce(&mut self, who: NodeIndex, hash: B::Hash, number: NumberFor<B>) {
+		match self.peers.get_mut(&who) {
+			Some(ref mut peer) if number > peer.finalized_number => peer.finalized_number = number,
+			_ => return,
+		}
+		for (fork_hash, fork) in self.pending_forks.iter_mut() {
+			if fork.number <= number && *fork_hash != hash {
+				fork.peers.remove(&who);
+			}
+		}
+		self.pending_forks.retain(|_, fork| !fork.peers.is_empty());
+	}
+
+	// Remember a stale fork announced by the given peer, to be downloaded by priority.
+	fn queue_fork(&mut self, who: NodeIndex, hash: B::Hash, header: &B::Header, parent_known: bool) {
+		if !self.pending_forks.contains_key(&hash) {
+			let fork = PendingFork {
+				number: *header.number(),
+				parent_known,
+				weight: self.import_queue.fork_weight(header),
+				peers: HashSet::new(),
+			};
+			if self.pending_forks.len() >= MAX_PENDING_FORKS {
+				match fork_to_evict(&self.pending_forks, &fork) {
+					Some(lowest) => {
+						trace!(target: "sync", "Too many pending forks, dropping {}", lowest);
+						self.pending_forks.remove(&lowest);
+					},
+					None => {
+						trace!(target: "sync", "Too many pending forks, ignoring {}", hash);
+						return;
+					},
+				}
+			}
+			self.pending_forks.insert(hash, fork);
+		}
+		if let Some(fork) = self.pending_forks.get_mut(&hash) {
+			fork.parent_known |= parent_known;
+			fork.peers.insert(who);
+		}
+	}
+
+	// Start downloading pending forks, most likely to become best first, from available peers
+	// that announced them.
+	fn download_pending_forks(&mut self, protocol: &mut Context<B>) {
+		let mut forks: Vec<_> = self.pending_forks.iter()
+			.map(|(hash, fork)| (fork.priority(), *hash))
+			.collect();
+		forks.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.as_ref().cmp(a.1.as_ref())));
+		for (_, hash) in forks {
+			if self.is_known(protocol, &hash) || self.is_already_downloading(&hash) {
+				self.pending_forks.remove(&hash);
+				continue;
+			}
+			let who = {
+				let peers = &self.peers;
+				self.pending_forks[&hash].peers.iter()
+					.filter(|who| peers.get(*who).map_or(false, |p| p.state == PeerSyncState::Available))
+					.min()
+					.cloned()
+			};
+			let who = match who {
+				Some(who) => who,
+				None => continue,
+			};
+			if let Some(fork) = self.pending_forks.remove(&hash) {
+				trace!(target: "sync", "Downloading fork {} (#{}, weight {:?}) from {}", hash, fork.number, fork.weight, who);
+				if fork.parent_known {
+					self.download_stale(protocol, who, &hash);
+				} else {
+					self.download_unknown_stale(protocol, who, &hash);
+				}
+			}
+		}
+	}
+
 	fn is_already_downloading(&self, hash: &B::Hash) -> bool {
 		self.peers.iter().any(|(_, p)| p.state == PeerSyncState::DownloadingStale(*hash))
 	}
@@ -654,8 +1268,14 @@ impl<B: BlockT> ChainSync<B> {
 	/// Handle disconnected peer.
 	pub(crate) fn peer_disconnected(&mut self, protocol: &mut Context<B>, who: NodeIndex) {
 		self.blocks.clear_peer_download(who);
+		self.fork_blocks.remove_peer(who);
+		for fork in self.pending_forks.values_mut() {
+			fork.peers.remove(&who);
+		}
+		self.pending_forks.retain(|_, fork| !fork.peers.is_empty());
 		self.peers.remove(&who);
 		self.justifications.peer_disconnected(who);
+		self.block_fetches.peer_disconnected(who);
 		self.maintain_sync(protocol);
 	}
 
@@ -663,16 +1283,36 @@ impl<B: BlockT> ChainSync<B> {
 	pub(crate) fn restart(&mut self, protocol: &mut Context<B>) {
 		self.import_queue.clear();
 		self.blocks.clear();
+		self.fork_blocks.clear();
+		self.pending_forks.clear();
+		// the queued blocks are gone, so are the justifications waiting for them.
+		for (hash, (_, number, _)) in self.buffered_justifications.drain() {
+			self.justifications.cancel_import(&(hash, number));
+		}
 		match protocol.client().info() {
 			Ok(info) => {
 				self.best_queued_hash = info.best_queued_hash.unwrap_or(info.chain.best_hash);
 				self.best_queued_number = info.best_queued_number.unwrap_or(info.chain.best_number);
-				debug!(target:"sync", "Restarted with {} ({})", self.best_queued_number, self.best_queued_hash);
+				self.best_finalized_hash = info.chain.finalized_hash;
+				self.best_finalized_number = info.chain.finalized_number;
+				debug!(target:"sync", "Restarted with {} ({}), finalized {} ({})", self.best_queued_number, self.best_queued_hash, self.best_finalized_number, self.best_finalized_hash);
 			},
 			Err(e) => {
 				debug!(target:"sync", "Error reading blockchain: {:?}", e);
 				self.best_queued_hash = self.genesis_hash;
 				self.best_queued_number = As::sa(0);
+				self.best_finalized_hash = self.genesis_hash;
+				self.best_finalized_number = As::sa(0);
+			}
+		}
+		self.justification_scan_next = if self.justifications_only {
+			Some(self.best_finalized_number + As::sa(1))
+		} else {
+			None
+		};
+		for (who, peer) in self.peers.iter() {
+			if peer.state != PeerSyncState::Available {
+				Self::cancel_downloads(&self.block_fetches, protocol, *who);
 			}
 		}
 		let ids: Vec<NodeIndex> = self.peers.drain().map(|(id, _)| id).collect();
@@ -684,6 +1324,8 @@ impl<B: BlockT> ChainSync<B> {
 	/// Clear all sync data.
 	pub(crate) fn clear(&mut self) {
 		self.blocks.clear();
+		self.fork_blocks.clear();
+		self.pending_forks.clear();
 		self.peers.clear();
 	}
 
@@ -692,16 +1334,23 @@ impl<B: BlockT> ChainSync<B> {
 		if let Some(ref mut peer) = self.peers.get_mut(&who) {
 			match peer.state {
 				PeerSyncState::Available => {
+					let fields = match block_download_attributes(self.required_block_attributes, peer.roles) {
+						Some(fields) => fields,
+						None => {
+							trace!(target: "sync", "Peer {} can not serve stale block {}", who, hash);
+							return;
+						},
+					};
 					let request = message::generic::BlockRequest {
 						id: 0,
-						fields: self.required_block_attributes.clone(),
+						fields,
 						from: message::FromBlock::Hash(*hash),
 						to: None,
 						direction: message::Direction::Ascending,
 						max: Some(1),
 					};
 					peer.state = PeerSyncState::DownloadingStale(*hash);
-					protocol.send_message(who, GenericMessage::BlockRequest(request));
+					send_block_request(protocol, &mut self.metrics, who, request);
 				},
 				_ => (),
 			}
@@ -713,46 +1362,77 @@ impl<B: BlockT> ChainSync<B> {
 		if let Some(ref mut peer) = self.peers.get_mut(&who) {
 			match peer.state {
 				PeerSyncState::Available => {
-					let request = message::generic::BlockRequest {
-						id: 0,
-						fields: self.required_block_attributes.clone(),
-						from: message::FromBlock::Hash(*hash),
-						to: None,
-						direction: message::Direction::Descending,
-						max: Some(MAX_UNKNOWN_FORK_DOWNLOAD_LEN),
+					let fields = match block_download_attributes(self.required_block_attributes, peer.roles) {
+						Some(fields) => fields,
+						None => {
+							trace!(target: "sync", "Peer {} can not serve unknown fork {}", who, hash);
+							return;
+						},
 					};
+					// continue below what has already been downloaded of this fork, if anything.
+					let from = self.fork_blocks.lowest_ancestor(who, hash).map_or(*hash, |(_, parent_hash)| parent_hash);
 					peer.state = PeerSyncState::DownloadingStale(*hash);
-					protocol.send_message(who, GenericMessage::BlockRequest(request));
+					Self::request_fork_range(protocol, &mut self.metrics, who, from, fields);
 				},
 				_ => (),
 			}
 		}
 	}
 
+	// Request a range of an unknown fork, descending from the given block.
+	fn request_fork_range(protocol: &mut Context<B>, metrics: &mut Metrics, who: NodeIndex, hash: B::Hash, fields: message::BlockAttributes) {
+		let request = message::generic::BlockRequest {
+			id: 0,
+			fields,
+			from: message::FromBlock::Hash(hash),
+			to: None,
+			direction: message::Direction::Descending,
+			max: Some(MAX_UNKNOWN_FORK_DOWNLOAD_LEN),
+		};
+		send_block_request(protocol, metrics, who, request);
+	}
+
 	// Issue a request for a peer to download new blocks, if any are available
 	fn download_new(&mut self, protocol: &mut Context<B>, who: NodeIndex) {
+		if self.justifications_only {
+			return;
+		}
+		self.blocks.set_max_ranges(self.download_window.ranges(self.peers.len()));
 		if let Some(ref mut peer) = self.peers.get_mut(&who) {
 			let import_status = self.import_queue.status();
 			// when there are too many blocks in the queue => do not try to download new blocks
-			if import_status.importing_count > MAX_IMPORTING_BLOCKS {
+			if import_status.importing_count > MAX_IMPORTING_BLOCKS || import_status.backpressure {
 				trace!(target: "sync", "Too many blocks in the queue.");
 				return;
 			}
 			match peer.state {
 				PeerSyncState::Available => {
+					let fields = match block_download_attributes(self.required_block_attributes, peer.roles) {
+						Some(fields) => fields,
+						None => {
+							trace!(target: "sync", "Peer {} can not serve the blocks we need", who);
+							return;
+						},
+					};
 					trace!(target: "sync", "Considering new block download from {}, common block is {}, best is {:?}", who, peer.common_number, peer.best_number);
-					if let Some(range) = self.blocks.needed_blocks(who, MAX_BLOCKS_TO_REQUEST, peer.best_number, peer.common_number) {
+					if let Some(range) = self.blocks.needed_blocks(who, peer.capabilities.max_blocks_per_request, peer.best_number, peer.common_number) {
 						trace!(target: "sync", "Requesting blocks from {}, ({} to {})", who, range.start, range.end);
+						// we only know the hash of the last block in range if it is the peer's best.
+						let to = if range.end == peer.best_number + As::sa(1) {
+							Some(peer.best_hash)
+						} else {
+							None
+						};
 						let request = message::generic::BlockRequest {
 							id: 0,
-							fields: self.required_block_attributes.clone(),
+							fields,
 							from: message::FromBlock::Number(range.start),
-							to: None,
+							to,
 							direction: message::Direction::Ascending,
 							max: Some((range.end - range.start).as_() as u32),
 						};
 						peer.state = PeerSyncState::DownloadingNew(range.start);
-						protocol.send_message(who, GenericMessage::BlockRequest(request));
+						send_block_request(protocol, &mut self.metrics, who, request);
 					} else {
 						trace!(target: "sync", "Nothing to request");
 					}
@@ -762,7 +1442,16 @@ impl<B: BlockT> ChainSync<B> {
 		}
 	}
 
-	fn request_ancestry(protocol: &mut Context<B>, who: NodeIndex, block: NumberFor<B>) {
+	// Cancel the block downloads in flight to the given peer. Justification requests
+	// and block fetches are tracked separately and stay valid.
+	fn cancel_downloads(block_fetches: &PendingBlockFetches<B>, protocol: &mut Context<B>, who: NodeIndex) {
+		protocol.cancel_requests(who, &|request| {
+			request.fields != message::BlockAttributes::JUSTIFICATION && block_fetches.fetch_for(who, request).is_none()
+		});
+	}
+
+	fn request_ancestry(protocol: &mut Context<B>, metrics: &mut Metrics, who: NodeIndex, block: NumberFor<B>) {
+		metrics.ancestor_search_steps += 1;
 		trace!(target: "sync", "Requesting ancestry block #{} from {}", block, who);
 		let request = message::generic::BlockRequest {
 			id: 0,
@@ -772,7 +1461,168 @@ impl<B: BlockT> ChainSync<B> {
 			direction: message::Direction::Ascending,
 			max: Some(1),
 		};
-		protocol.send_message(who, GenericMessage::BlockRequest(request));
+		send_block_request(protocol, metrics, who, request);
+	}
+}
+
+// Send a block request to a peer, counting it in the sync metrics.
+pub(crate) fn send_block_request<B: BlockT>(
+	protocol: &mut Context<B>,
+	metrics: &mut Metrics,
+	who: NodeIndex,
+	request: message::BlockRequest<B>,
+) {
+	metrics.requests_sent += 1;
+	protocol.send_message(who, GenericMessage::BlockRequest(request));
+}
+
+// Report a peer, counting it in the sync metrics.
+pub(crate) fn report_peer<B: BlockT>(protocol: &mut Context<B>, metrics: &mut Metrics, who: NodeIndex, reason: Severity) {
+	metrics.peers_penalized += 1;
+	protocol.report_peer(who, reason);
+}
+
+/// Block attributes out of `fields` that a peer with the given roles can serve.
+/// Light peers don't keep block bodies.
+fn servable_attributes(fields: message::BlockAttributes, roles: Roles) -> message::BlockAttributes {
+	if roles.intersects(Roles::FULL | Roles::AUTHORITY) {
+		fields
+	} else {
+		fields - message::BlockAttributes::BODY
+	}
+}
+
+/// Origin of blocks handed over to the import queue: blocks starting with one a peer recently
+/// announced are imported as a broadcast, the others as part of the initial sync.
+fn import_origin<B: BlockT>(
+	blocks: &[IncomingBlock<B>],
+	recently_announced: impl Fn(&B::Hash) -> bool,
+) -> BlockOrigin {
+	match blocks.first() {
+		Some(block) if recently_announced(&block.hash) => BlockOrigin::NetworkBroadcast,
+		_ => BlockOrigin::NetworkInitialSync,
+	}
+}
+
+/// Mandatory justification requests persisted before the last stop.
+fn load_mandatory_justifications<B: BlockT>(chain: &::chain::Client<B>) -> Vec<(B::Hash, NumberFor<B>)> {
+	match chain.get_aux(MANDATORY_JUSTIFICATIONS_KEY) {
+		Ok(Some(raw)) => Decode::decode(&mut &raw[..]).unwrap_or_else(|| {
+			warn!(target: "sync", "Persisted justification requests kept in invalid format");
+			Vec::new()
+		}),
+		Ok(None) => Vec::new(),
+		Err(e) => {
+			warn!(target: "sync", "Error loading persisted justification requests: {:?}", e);
+			Vec::new()
+		},
+	}
+}
+
+/// Whether the given block is an ancestor or a descendant of the best finalized
+/// block. Blocks that can't be looked up are assumed to be.
+fn is_on_finalized_chain<B: BlockT>(
+	chain: &::chain::Client<B>,
+	best_finalized: NumberFor<B>,
+	mut hash: B::Hash,
+	mut number: NumberFor<B>,
+) -> bool {
+	// the canonical chain always includes the finalized blocks, so walk back a fork
+	// until it joins the canonical chain and check that it does above the finalized block.
+	loop {
+		match chain.block_hash(number) {
+			Ok(Some(canonical)) if canonical == hash => return true,
+			Ok(_) if number <= best_finalized => return false,
+			Ok(_) => {},
+			Err(_) => return true,
+		}
+		match chain.header(&BlockId::Hash(hash)) {
+			Ok(Some(header)) => {
+				hash = *header.parent_hash();
+				number = number - As::sa(1);
+			},
+			_ => return true,
+		}
+	}
+}
+
+/// Attributes to request when downloading blocks for import from a peer with
+/// the given roles, or `None` if the peer can not serve all of them.
+fn block_download_attributes(
+	required: message::BlockAttributes,
+	roles: Roles,
+) -> Option<message::BlockAttributes> {
+	let fields = servable_attributes(required, roles);
+	if fields.contains(required & (message::BlockAttributes::HEADER | message::BlockAttributes::BODY)) {
+		Some(fields)
+	} else {
+		None
+	}
+}
+
+/// Check that the blocks of a response match what was asked for in `request`.
+/// Blocks are expected in ascending order, i.e. already reversed for descending
+/// requests. The response must not exceed `max` or go past `to`, must start at
+/// `from`, must form a linked chain and must only carry requested attributes.
+fn validate_block_response<B: BlockT>(
+	request: &message::BlockRequest<B>,
+	blocks: &[message::BlockData<B>],
+) -> Result<(), String> {
+	if let Some(max) = request.max {
+		if blocks.len() > max as usize {
+			return Err(format!("Received {} blocks, requested at most {}", blocks.len(), max));
+		}
+	}
+
+	for block in blocks {
+		match block.header {
+			Some(ref header) if header.hash() != block.hash =>
+				return Err(format!("Block {} has header with different hash {}", block.hash, header.hash())),
+			None if request.fields.contains(message::BlockAttributes::HEADER) =>
+				return Err(format!("Block {} is missing requested header", block.hash)),
+			_ => {},
+		}
+		if block.body.is_some() && !request.fields.contains(message::BlockAttributes::BODY) {
+			return Err(format!("Block {} has unrequested body", block.hash));
+		}
+		if block.justification.is_some() && !request.fields.contains(message::BlockAttributes::JUSTIFICATION) {
+			return Err(format!("Block {} has unrequested justification", block.hash));
+		}
+	}
+
+	for pair in blocks.windows(2) {
+		if let (&Some(ref parent), &Some(ref child)) = (&pair[0].header, &pair[1].header) {
+			if *child.parent_hash() != pair[0].hash || *child.number() != *parent.number() + As::sa(1) {
+				return Err(format!("Block {} does not follow {}", pair[1].hash, pair[0].hash));
+			}
+		}
+	}
+
+	// the first block in request order, and how many blocks follow it.
+	let (first, after_to) = match request.direction {
+		message::Direction::Ascending => (
+			blocks.first(),
+			request.to.and_then(|to| blocks.iter().position(|b| b.hash == to)).map(|pos| blocks.len() - pos - 1),
+		),
+		message::Direction::Descending => (
+			blocks.last(),
+			request.to.and_then(|to| blocks.iter().position(|b| b.hash == to)),
+		),
+	};
+
+	if let Some(first) = first {
+		let starts_at_from = match request.from {
+			message::FromBlock::Hash(ref hash) => first.hash == *hash,
+			message::FromBlock::Number(ref number) => first.header.as_ref().map_or(true, |h| h.number() == number),
+		};
+		if !starts_at_from {
+			return Err(format!("Response does not start at requested block {:?}", request.from));
+		}
+	}
+
+	match after_to {
+		Some(n) if n > 0 => Err(format!("Received {} blocks past requested end {:?}", n, request.to)),
+		_ => Ok(()),
 	}
 }
 
@@ -788,3 +1638,110 @@ fn block_status<B: BlockT>(
 
 	chain.block_status(&BlockId::Hash(hash))
 }
+
+#[cfg(test)]
+mod tests {
+	use consensus::BlockOrigin;
+	use consensus::import_queue::IncomingBlock;
+	use futures::{Async, Future};
+	use futures::sync::oneshot;
+	use message;
+	use test_client::runtime::{Block, Hash};
+	use std::collections::{HashMap, HashSet};
+	use std::time::Duration;
+	use super::{fetch_request, fork_to_evict, import_origin, PendingBlockFetches, PendingFork, BLOCK_FETCH_TIMEOUT};
+
+	fn block(hash: u64) -> IncomingBlock<Block> {
+		IncomingBlock {
+			hash: Hash::from_low_u64_be(hash),
+			header: None,
+			body: None,
+			justification: None,
+			origin: None,
+			size: 0,
+		}
+	}
+
+	#[test]
+	fn blocks_starting_with_an_announced_one_are_a_broadcast() {
+		let announced = |hash: &Hash| *hash == Hash::from_low_u64_be(1);
+
+		assert_eq!(import_origin(&[block(1), block(2)], announced), BlockOrigin::NetworkBroadcast);
+		assert_eq!(import_origin(&[block(2), block(1)], announced), BlockOrigin::NetworkInitialSync);
+		let no_blocks: &[IncomingBlock<Block>] = &[];
+		assert_eq!(import_origin(no_blocks, announced), BlockOrigin::NetworkInitialSync);
+	}
+
+	#[test]
+	fn only_requests_of_the_fetch_in_flight_belong_to_it() {
+		let hash = Hash::from_low_u64_be(1);
+		let mut fetches = PendingBlockFetches::<Block>::new();
+		let (sender, _receiver) = oneshot::channel();
+		fetches.queue(hash, message::BlockAttributes::empty(), sender);
+		{
+			let fetch = fetches.fetches.get_mut(&hash).unwrap();
+			fetch.peer = Some(1);
+			fetch.requested_fields = fetch.fields;
+		}
+		let fields = fetches.fetches[&hash].fields;
+
+		assert_eq!(fetches.fetch_for(1, &fetch_request::<Block>(hash, fields, 7)), Some(hash));
+		assert_eq!(fetches.fetch_for(2, &fetch_request::<Block>(hash, fields, 7)), None);
+		assert_eq!(fetches.fetch_for(1, &fetch_request::<Block>(Hash::from_low_u64_be(2), fields, 7)), None);
+
+		// a stale fork download from the same block.
+		let mut stale = fetch_request::<Block>(hash, fields, 8);
+		stale.direction = message::Direction::Descending;
+		stale.max = Some(16);
+		assert_eq!(fetches.fetch_for(1, &stale), None);
+
+		// a justification request for the same block.
+		let justification = fetch_request::<Block>(hash, message::BlockAttributes::JUSTIFICATION, 9);
+		assert_eq!(fetches.fetch_for(1, &justification), None);
+
+		// attributes requested once the fetch was sent don't change its request.
+		let (sender, _receiver) = oneshot::channel();
+		fetches.queue(hash, message::BlockAttributes::JUSTIFICATION, sender);
+		assert_eq!(fetches.fetch_for(1, &fetch_request::<Block>(hash, fields, 7)), Some(hash));
+	}
+
+	#[test]
+	fn fetches_without_a_peer_time_out() {
+		let hash = Hash::from_low_u64_be(1);
+		let in_flight = Hash::from_low_u64_be(2);
+		let mut fetches = PendingBlockFetches::<Block>::new();
+		let (sender, mut receiver) = oneshot::channel();
+		fetches.queue(hash, message::BlockAttributes::empty(), sender);
+		let (sender, mut in_flight_receiver) = oneshot::channel();
+		fetches.queue(in_flight, message::BlockAttributes::empty(), sender);
+		fetches.fetches.get_mut(&in_flight).unwrap().peer = Some(1);
+		let queued = fetches.fetches[&hash].queued;
+
+		fetches.collect_garbage(queued + BLOCK_FETCH_TIMEOUT - Duration::from_secs(1));
+		assert_eq!(receiver.poll(), Ok(Async::NotReady));
+
+		fetches.collect_garbage(queued + BLOCK_FETCH_TIMEOUT);
+		assert_eq!(receiver.poll(), Err(oneshot::Canceled));
+		// the fetch in flight is only retried or dropped once its peer fails it.
+		assert_eq!(in_flight_receiver.poll(), Ok(Async::NotReady));
+	}
+
+	fn fork(number: u64, weight: Option<u64>) -> PendingFork<Block> {
+		PendingFork { number, parent_known: false, weight, peers: HashSet::new() }
+	}
+
+	#[test]
+	fn only_forks_ranked_below_a_new_one_are_evicted() {
+		let forks: HashMap<_, _> = vec![
+			(Hash::from_low_u64_be(1), fork(10, Some(5))),
+			(Hash::from_low_u64_be(2), fork(12, None)),
+			(Hash::from_low_u64_be(3), fork(8, Some(1))),
+		].into_iter().collect();
+
+		// forks without a weight rank last, then by number.
+		assert_eq!(fork_to_evict(&forks, &fork(1, Some(0))), Some(Hash::from_low_u64_be(2)));
+		assert_eq!(fork_to_evict(&forks, &fork(13, None)), Some(Hash::from_low_u64_be(2)));
+		assert_eq!(fork_to_evict(&forks, &fork(12, None)), None);
+		assert_eq!(fork_to_evict(&forks, &fork(11, None)), None);
+	}
+}
@@ -79,7 +79,8 @@ fn prepare_good_block() -> (client::Client<test_client::Backend, test_client::Ex
 		header,
 		body: None,
 		justification,
-		origin: Some(0)
+		origin: Some(0),
+		size: 0,
 	})
 }
 
@@ -169,3 +170,217 @@ fn async_import_queue_drops() {
 		drop(queue);
 	}
 }
+
+struct ImportedLink(::std::sync::mpsc::Sender<(Hash, NumberFor<Block>)>);
+
+impl Link<Block> for ImportedLink {
+	fn block_imported(&self, hash: &Hash, number: NumberFor<Block>) {
+		let _ = self.0.send((*hash, number));
+	}
+}
+
+fn incoming_block(block: Block) -> IncomingBlock<Block> {
+	IncomingBlock {
+		hash: block.header.hash(),
+		header: Some(block.header),
+		body: Some(block.extrinsics),
+		justification: None,
+		origin: Some(0),
+		size: 0,
+	}
+}
+
+#[test]
+fn async_import_queue_verifies_blocks_ahead_and_imports_them_in_order() {
+	let source = test_client::new();
+	let blocks = (0..8).map(|_| {
+		let block = source.new_block().unwrap().bake().unwrap();
+		source.import(BlockOrigin::File, block.clone()).unwrap();
+		incoming_block(block)
+	}).collect::<Vec<_>>();
+
+	let client = Arc::new(test_client::new());
+	let verifier = Arc::new(PassThroughVerifier(false));
+	let queue = BasicQueue::with_verification_workers(verifier, client.clone(), None, 4);
+	let (tx, rx) = ::std::sync::mpsc::channel();
+	queue.start(ImportedLink(tx)).unwrap();
+	queue.import_blocks(BlockOrigin::NetworkInitialSync, blocks);
+
+	let imported = (0..8).map(|_| rx.recv().unwrap().1).collect::<Vec<_>>();
+	assert_eq!(imported, (1..9).collect::<Vec<_>>());
+	assert_eq!(client.info().unwrap().chain.best_number, 8);
+}
+
+// Accepts the blocks whose parent is imported.
+struct ParentCheckingVerifier(Arc<TestClient>);
+
+impl Verifier<Block> for ParentCheckingVerifier {
+	fn verify(
+		&self,
+		origin: BlockOrigin,
+		header: <Block as BlockT>::Header,
+		justification: Option<Justification>,
+		body: Option<Vec<<Block as BlockT>::Extrinsic>>
+	) -> Result<(ImportBlock<Block>, Option<Vec<AuthorityIdFor<Block>>>), String> {
+		match self.0.header(&BlockId::Hash(header.parent_hash)) {
+			Ok(Some(_)) => PassThroughVerifier(false).verify(origin, header, justification, body),
+			_ => Err("Unknown parent".into()),
+		}
+	}
+}
+
+#[test]
+fn async_import_queue_verifies_ahead_only_blocks_with_imported_parents() {
+	let source = test_client::new();
+	let blocks = (0..4).map(|_| {
+		let block = source.new_block().unwrap().bake().unwrap();
+		source.import(BlockOrigin::File, block.clone()).unwrap();
+		incoming_block(block)
+	}).collect::<Vec<_>>();
+
+	let client = Arc::new(test_client::new());
+	let verifier = Arc::new(ParentCheckingVerifier(client.clone()));
+	let queue = BasicQueue::with_verification_workers(verifier, client.clone(), None, 4);
+	let (tx, rx) = ::std::sync::mpsc::channel();
+	queue.start(ImportedLink(tx)).unwrap();
+	queue.import_blocks(BlockOrigin::NetworkInitialSync, blocks[..2].to_vec());
+	queue.import_blocks(BlockOrigin::NetworkInitialSync, blocks[2..].to_vec());
+
+	let imported = (0..4).map(|_| rx.recv().unwrap().1).collect::<Vec<_>>();
+	assert_eq!(imported, (1..5).collect::<Vec<_>>());
+	assert_eq!(client.info().unwrap().chain.best_number, 4);
+}
+
+#[test]
+fn async_import_queue_imports_broadcast_blocks_first() {
+	let source = test_client::new();
+	let synced = (0..3).map(|_| {
+		let block = source.new_block().unwrap().bake().unwrap();
+		source.import(BlockOrigin::File, block.clone()).unwrap();
+		incoming_block(block)
+	}).collect::<Vec<_>>();
+	let synced_head = synced[2].clone();
+
+	// a competing block at the head of another fork.
+	let mut builder = test_client::new().new_block().unwrap();
+	let transfer = Transfer {
+		from: Keyring::Alice.to_raw_public().into(),
+		to: Keyring::Bob.to_raw_public().into(),
+		amount: 1,
+		nonce: 0,
+	};
+	let signature = Keyring::Alice.sign(&transfer.encode()).into();
+	builder.push(Extrinsic::Transfer(transfer, signature)).unwrap();
+	let broadcast = incoming_block(builder.bake().unwrap());
+	let broadcast_hash = broadcast.hash;
+
+	let verifier = Arc::new(PassThroughVerifier(false));
+	let queue = BasicQueue::new(verifier, Arc::new(test_client::new()), None);
+	queue.import_blocks(BlockOrigin::NetworkInitialSync, synced[..2].to_vec());
+	queue.import_blocks(BlockOrigin::NetworkBroadcast, vec![broadcast]);
+	// building on queued blocks, so not imported ahead of them.
+	queue.import_blocks(BlockOrigin::NetworkBroadcast, vec![synced_head.clone()]);
+	let (tx, rx) = ::std::sync::mpsc::channel();
+	queue.start(ImportedLink(tx)).unwrap();
+
+	let imported = (0..4).map(|_| rx.recv().unwrap().0).collect::<Vec<_>>();
+	assert_eq!(imported, vec![broadcast_hash, synced[0].hash, synced[1].hash, synced_head.hash]);
+}
+
+#[test]
+fn async_import_queue_imports_chained_broadcast_blocks_after_their_parent() {
+	let source = test_client::new();
+	let synced = (0..2).map(|_| {
+		let block = source.new_block().unwrap().bake().unwrap();
+		source.import(BlockOrigin::File, block.clone()).unwrap();
+		incoming_block(block)
+	}).collect::<Vec<_>>();
+
+	// two blocks at the head of another fork, the second building on the first.
+	let fork = test_client::new();
+	let mut builder = fork.new_block().unwrap();
+	let transfer = Transfer {
+		from: Keyring::Alice.to_raw_public().into(),
+		to: Keyring::Bob.to_raw_public().into(),
+		amount: 1,
+		nonce: 0,
+	};
+	let signature = Keyring::Alice.sign(&transfer.encode()).into();
+	builder.push(Extrinsic::Transfer(transfer, signature)).unwrap();
+	let fork_block = builder.bake().unwrap();
+	fork.import(BlockOrigin::File, fork_block.clone()).unwrap();
+	let fork_child = incoming_block(fork.new_block().unwrap().bake().unwrap());
+	let fork_block = incoming_block(fork_block);
+
+	let verifier = Arc::new(PassThroughVerifier(false));
+	let queue = BasicQueue::new(verifier, Arc::new(test_client::new()), None);
+	queue.import_blocks(BlockOrigin::NetworkInitialSync, vec![synced[0].clone()]);
+	queue.import_blocks(BlockOrigin::NetworkBroadcast, vec![fork_block.clone()]);
+	queue.import_blocks(BlockOrigin::NetworkInitialSync, vec![synced[1].clone()]);
+	queue.import_blocks(BlockOrigin::NetworkBroadcast, vec![fork_child.clone()]);
+	let (tx, rx) = ::std::sync::mpsc::channel();
+	queue.start(ImportedLink(tx)).unwrap();
+
+	let imported = (0..4).map(|_| rx.recv().unwrap().0).collect::<Vec<_>>();
+	assert_eq!(imported, vec![fork_block.hash, fork_child.hash, synced[0].hash, synced[1].hash]);
+}
+
+#[test]
+fn async_import_queue_notifies_single_block_imports() {
+	let (_, hash, number, block) = prepare_good_block();
+	let client = Arc::new(test_client::new());
+	let verifier = Arc::new(PassThroughVerifier(false));
+	let queue = BasicQueue::new(verifier, client.clone(), None);
+	let (tx, rx) = ::std::sync::mpsc::channel();
+	queue.start(ImportedLink(tx)).unwrap();
+
+	assert_eq!(queue.import_block(BlockOrigin::Own, block), Ok(BlockImportResult::ImportedUnknown(hash, number)));
+	assert_eq!(rx.recv().unwrap(), (hash, number));
+	assert!(!queue.is_importing(&hash));
+	assert_eq!(client.info().unwrap().chain.best_number, 1);
+}
+
+#[test]
+fn async_import_queue_reports_queued_blocks() {
+	let (_, _, _, mut block) = prepare_good_block();
+	block.size = 100;
+	let verifier = Arc::new(PassThroughVerifier(true));
+	let queue = BasicQueue::new(verifier, Arc::new(test_client::new()), None);
+	queue.import_blocks(BlockOrigin::NetworkInitialSync, vec![block.clone(), block]);
+
+	let status = queue.status();
+	assert_eq!(status.importing_count, 1);
+	assert_eq!(status.verification_backlog, 2);
+	assert_eq!(status.queued_bytes, 200);
+	assert!(!status.backpressure);
+
+	queue.clear();
+	let status = queue.status();
+	assert_eq!(status.verification_backlog, 0);
+	assert_eq!(status.queued_bytes, 0);
+}
+
+struct JustificationLink(::std::sync::mpsc::Sender<(NodeIndex, Hash, bool)>);
+
+impl Link<Block> for JustificationLink {
+	fn justification_imported(&self, who: NodeIndex, hash: &Hash, _number: NumberFor<Block>, success: bool) {
+		let _ = self.0.send((who, *hash, success));
+	}
+}
+
+#[test]
+fn async_import_queue_reports_justification_import_results() {
+	let (client, hash, number, _) = prepare_good_block();
+	let client = Arc::new(client);
+	let verifier = Arc::new(PassThroughVerifier(false));
+	let queue = BasicQueue::new(verifier, client.clone(), Some(Arc::new(ForceFinalized(client.clone()))));
+	let (tx, rx) = ::std::sync::mpsc::channel();
+	queue.start(JustificationLink(tx)).unwrap();
+
+	queue.import_justification(3, Default::default(), 2, Vec::new());
+	queue.import_justification(4, hash, number, Vec::new());
+
+	assert_eq!(rx.recv().unwrap(), (3, Default::default(), false));
+	assert_eq!(rx.recv().unwrap(), (4, hash, true));
+	assert_eq!(client.justification(&BlockId::Number(1)).unwrap(), Some(Vec::new()));
+}
@@ -0,0 +1,61 @@
+// Copyright 2017-2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+use codec::Encode;
+use message::SignedConsensusMessage;
+use message::generic::Message as GenericMessage;
+use super::*;
+
+fn authority_message(topic: Hash, data: Vec<u8>, key: Keyring) -> (Vec<u8>, TestPacket) {
+	let signed = SignedConsensusMessage::sign(&topic, data, &key.pair()).encode();
+	let message: ::message::Message<Block> = GenericMessage::AuthorityConsensus(topic, signed.clone(), false);
+	(signed, TestPacket { data: message.encode(), recipient: 0 })
+}
+
+#[test]
+fn authority_messages_are_checked_against_the_authority_set() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(0).push_authorities_change_block(vec![Keyring::Dave.to_raw_public().into()]);
+	net.sync();
+	let topic = H256::random();
+
+	// Alice is an authority at genesis, but not at our best block.
+	let (signed, packet) = authority_message(topic, vec![1], Keyring::Alice);
+	assert!(net.peer(0).receive_message(1, packet).is_empty());
+	assert!(!net.peer(0).consensus_gossip().read().is_known_message(topic, &signed, true));
+
+	// naming a block where Alice was an authority doesn't help either.
+	let genesis = net.peer(0).client().info().unwrap().chain.genesis_hash;
+	let (signed, packet) = authority_message(genesis, vec![1], Keyring::Alice);
+	assert!(net.peer(0).receive_message(1, packet).is_empty());
+	assert!(!net.peer(0).consensus_gossip().read().is_known_message(genesis, &signed, true));
+
+	let (signed, packet) = authority_message(topic, vec![2], Keyring::Dave);
+	assert!(net.peer(0).receive_message(1, packet).is_empty());
+	assert!(net.peer(0).consensus_gossip().read().is_known_message(topic, &signed, true));
+
+	// copies of a known message aren't checked again.
+	let (_, packet) = authority_message(topic, vec![2], Keyring::Dave);
+	assert!(net.peer(0).receive_message(1, packet).is_empty());
+
+	// a forged signature gets the peer disconnected.
+	let mut forged = SignedConsensusMessage::sign(&topic, vec![3], &Keyring::Dave.pair());
+	forged.message = vec![4];
+	let message: ::message::Message<Block> = GenericMessage::AuthorityConsensus(topic, forged.encode(), false);
+	let disconnected = net.peer(0).receive_message(1, TestPacket { data: message.encode(), recipient: 0 });
+	assert!(disconnected.contains(&1));
+}
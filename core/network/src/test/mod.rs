@@ -20,9 +20,13 @@
 mod sync;
 #[cfg(test)]
 mod block_import;
+#[cfg(test)]
+mod gossip;
+pub mod simulated;
 
 use std::collections::{VecDeque, HashSet, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
 
 use parking_lot::RwLock;
 use client;
@@ -34,23 +38,31 @@ use runtime_primitives::traits::{AuthorityIdFor, Block as BlockT, Digest, Digest
 use io::SyncIo;
 use protocol::{Context, Protocol, ProtocolContext};
 use config::ProtocolConfig;
-use service::{NetworkLink, TransactionPool};
-use network_libp2p::{NodeIndex, PeerId, Severity};
+use service::{BlockFetchFuture, NetworkLink, TransactionPool};
+use message::BlockAttributes;
+use network_libp2p::{NodeIndex, PeerId, ReputationChange, DISCONNECT_THRESHOLD};
 use keyring::Keyring;
 use codec::Encode;
 use consensus::{BlockOrigin, ImportBlock, JustificationImport, ForkChoiceStrategy, Error as ConsensusError, ErrorKind as ConsensusErrorKind};
-use consensus::import_queue::{import_many_blocks, ImportQueue, ImportQueueStatus, IncomingBlock};
-use consensus::import_queue::{Link, SharedBlockImport, SharedJustificationImport, Verifier};
+use consensus::import_queue::{import_many_blocks, import_single_block, import_justification, ImportQueue, ImportQueueStatus, IncomingBlock};
+use consensus::import_queue::{BlockImportError, BlockImportResult};
+use consensus::import_queue::{Link, Origin, SharedBlockImport, SharedJustificationImport, Verifier};
 use specialization::NetworkSpecialization;
 use consensus_gossip::ConsensusGossip;
 use service::ExecuteInContext;
+use simulation;
 use test_client;
 
 pub use test_client::runtime::{Block, Hash, Transfer, Extrinsic};
 pub use test_client::TestClient;
 
+/// Simulated time that passes with every sync step, matching the service tick.
+const SIMULATED_TICK: Duration = Duration::from_millis(1000);
+
 #[cfg(any(test, feature = "test-helpers"))]
 use std::cell::RefCell;
+#[cfg(any(test, feature = "test-helpers"))]
+use std::rc::Rc;
 
 #[cfg(any(test, feature = "test-helpers"))]
 struct ImportCB<B: BlockT>(RefCell<Option<Box<dyn Fn(BlockOrigin, Vec<IncomingBlock<B>>) -> bool>>>);
@@ -76,6 +88,31 @@ unsafe impl<B: BlockT> Send for ImportCB<B> {}
 #[cfg(any(test, feature = "test-helpers"))]
 unsafe impl<B: BlockT> Sync for ImportCB<B> {}
 
+#[cfg(any(test, feature = "test-helpers"))]
+struct JustificationCB<B: BlockT>(RefCell<Option<Box<dyn Fn(Origin, B::Hash, NumberFor<B>, bool)>>>);
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl<B: BlockT> JustificationCB<B> {
+	fn new() -> Self {
+		JustificationCB(RefCell::new(None))
+	}
+	fn set<F>(&self, cb: Box<F>)
+		where F: 'static + Fn(Origin, B::Hash, NumberFor<B>, bool)
+	{
+		*self.0.borrow_mut() = Some(cb);
+	}
+	fn call(&self, who: Origin, hash: B::Hash, number: NumberFor<B>, success: bool) {
+		if let Some(cb) = self.0.borrow().as_ref() {
+			cb(who, hash, number, success);
+		}
+	}
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+unsafe impl<B: BlockT> Send for JustificationCB<B> {}
+#[cfg(any(test, feature = "test-helpers"))]
+unsafe impl<B: BlockT> Sync for JustificationCB<B> {}
+
 
 #[cfg(any(test, feature = "test-helpers"))]
 /// A Verifier that accepts all blocks and passes them on with the configured
@@ -106,6 +143,10 @@ impl<B: BlockT> Verifier<B> for PassThroughVerifier {
 			fork_choice: ForkChoiceStrategy::LongestChain,
 		}, new_authorities))
 	}
+
+	fn is_parent_independent(&self) -> bool {
+		true
+	}
 }
 
 /// A link implementation that does nothing.
@@ -119,6 +160,7 @@ impl<B: BlockT> Link<B> for NoopLink { }
 pub struct SyncImportQueue<B: BlockT, V: Verifier<B>> {
 	verifier: Arc<V>,
 	link: ImportCB<B>,
+	justification_link: JustificationCB<B>,
 	block_import: SharedBlockImport<B>,
 	justification_import: Option<SharedJustificationImport<B>>,
 }
@@ -131,6 +173,7 @@ impl<B: 'static + BlockT, V: 'static + Verifier<B>> SyncImportQueue<B, V> {
 		let queue = SyncImportQueue {
 			verifier,
 			link: ImportCB::new(),
+			justification_link: JustificationCB::new(),
 			block_import,
 			justification_import,
 		};
@@ -161,16 +204,21 @@ impl<B: 'static + BlockT, V: 'static + Verifier<B>> ImportQueue<B> for SyncImpor
 	) -> Result<(), std::io::Error> {
 		let v = self.verifier.clone();
 		let import_handle = self.block_import.clone();
+		let link = Rc::new(link);
+		let blocks_link = link.clone();
 		self.link.set(Box::new(move |origin, new_blocks| {
 			let verifier = v.clone();
 			import_many_blocks(
 				&*import_handle,
-				&link,
+				&*blocks_link,
 				None,
 				(origin, new_blocks),
 				verifier,
 			)
 		}));
+		self.justification_link.set(Box::new(move |who, hash, number, success| {
+			link.justification_imported(who, &hash, number, success)
+		}));
 		Ok(())
 	}
 	fn clear(&self) { }
@@ -181,6 +229,9 @@ impl<B: 'static + BlockT, V: 'static + Verifier<B>> ImportQueue<B> for SyncImpor
 		ImportQueueStatus {
 			importing_count: 0,
 			best_importing_number: Zero::zero(),
+			queued_bytes: 0,
+			verification_backlog: 0,
+			backpressure: false,
 		}
 	}
 
@@ -192,15 +243,33 @@ impl<B: 'static + BlockT, V: 'static + Verifier<B>> ImportQueue<B> for SyncImpor
 		self.link.call(origin, blocks);
 	}
 
+	fn import_block(
+		&self,
+		origin: BlockOrigin,
+		block: IncomingBlock<B>,
+	) -> Result<BlockImportResult<B::Hash, NumberFor<B>>, BlockImportError> {
+		import_single_block(&*self.block_import, origin, block, self.verifier.clone())
+	}
+
 	fn import_justification(
 		&self,
+		who: Origin,
 		hash: B::Hash,
 		number: NumberFor<B>,
 		justification: Justification,
-	) -> bool {
-		self.justification_import.as_ref().map(|justification_import| {
-			justification_import.import_justification(hash, number, justification).is_ok()
-		}).unwrap_or(false)
+	) {
+		let success = import_justification(self.justification_import.as_ref(), hash, number, justification);
+		self.justification_link.call(who, hash, number, success);
+	}
+
+	fn justification_unavailable(&self, hash: B::Hash, number: NumberFor<B>) {
+		if let Some(justification_import) = self.justification_import.as_ref() {
+			justification_import.on_justification_unavailable(hash, number);
+		}
+	}
+
+	fn fork_weight(&self, header: &B::Header) -> Option<u64> {
+		self.verifier.fork_weight(header)
 	}
 }
 
@@ -262,7 +331,13 @@ impl<'p> Drop for TestIo<'p> {
 }
 
 impl<'p> SyncIo for TestIo<'p> {
-	fn report_peer(&mut self, who: NodeIndex, _reason: Severity) {
+	fn change_reputation(&mut self, who: NodeIndex, change: ReputationChange) {
+		if change.value <= DISCONNECT_THRESHOLD {
+			self.to_disconnect.insert(who);
+		}
+	}
+
+	fn disconnect_peer(&mut self, who: NodeIndex) {
 		self.to_disconnect.insert(who);
 	}
 
@@ -390,6 +465,13 @@ impl<V: 'static + Verifier<Block>, D> Peer<V, D> {
 		self.sync.abort();
 	}
 
+	/// Restart the chain sync state machine, dropping its in-flight downloads.
+	fn restart_chain_sync(&self) {
+		let mut io = TestIo::new(&self.queue, None);
+		let mut context = ProtocolContext::new(self.sync.context_data(), &mut io);
+		self.sync.sync().write().restart(&mut context);
+	}
+
 	fn flush(&self) {
 	}
 
@@ -399,16 +481,21 @@ impl<V: 'static + Verifier<Block>, D> Peer<V, D> {
 		self.sync.gossip_consensus_message(&mut TestIo::new(&self.queue, None), topic, data, broadcast);
 	}
 
+	/// Fetch a block from the network.
+	pub fn fetch_block(&self, hash: Hash, attributes: BlockAttributes) -> BlockFetchFuture<Block> {
+		self.sync.fetch_block(&mut TestIo::new(&self.queue, None), hash, attributes)
+	}
+
 	/// Announce a block to peers.
 	pub fn announce_block(&self, block: Hash) {
 		self.sync.announce_block(&mut TestIo::new(&self.queue, None), block);
 	}
 
-	/// Request a justification for the given block.
+	/// Request a mandatory justification for the given block.
 	#[cfg(test)]
 	fn request_justification(&self, hash: &::primitives::H256, number: NumberFor<Block>) {
 		self.executor.execute_in_context(|context| {
-			self.sync.sync().write().request_justification(hash, number, context);
+			self.sync.sync().write().request_justification(hash, number, true, context);
 		})
 	}
 
@@ -442,6 +529,7 @@ impl<V: 'static + Verifier<Block>, D> Peer<V, D> {
 					header: Some(header),
 					body: Some(block.extrinsics),
 					justification: None,
+					size: 0,
 				},
 			]);
 		}
@@ -508,6 +596,10 @@ impl TransactionPool<Hash, Block> for EmptyTransactionPool {
 	}
 
 	fn on_broadcasted(&self, _: HashMap<Hash, Vec<String>>) {}
+
+	fn on_major_sync_finished(&self, revalidated: Box<Fn() + Send>) {
+		revalidated();
+	}
 }
 
 pub trait TestNetFactory: Sized {
@@ -597,10 +689,16 @@ pub trait TestNetFactory: Sized {
 		self.set_started(true);
 	}
 
-	/// Do one step of routing.
+	/// Do one step of routing. Under a simulation peers are visited in an
+	/// order picked by the simulation, otherwise in index order.
 	fn route(&mut self) {
 		self.mut_peers(move |peers| {
-			for peer in 0..peers.len() {
+			let mut order: Vec<usize> = (0..peers.len()).collect();
+			if simulation::is_active() {
+				simulation::shuffle(&mut order);
+			}
+
+			for peer in order {
 				let packet = peers[peer].pending_message();
 				if let Some(packet) = packet {
 					let disconnecting = {
@@ -632,6 +730,7 @@ pub trait TestNetFactory: Sized {
 	/// Do a step of synchronization.
 	fn sync_step(&mut self) {
 		self.route();
+		simulation::advance(SIMULATED_TICK);
 
 		self.mut_peers(|peers| {
 			for peer in peers {
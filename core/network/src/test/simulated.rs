@@ -0,0 +1,291 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Deterministic network simulation for sync tests.
+//!
+//! `SimulatedNet` runs a `TestNet` inside a `Simulation`: packets are delivered
+//! after a scripted latency of their link, time only moves when the network is
+//! stepped, and peers can be scripted to misbehave. Runs with the same seed and
+//! script always produce the same result.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use codec::{Decode, Encode};
+use message::{self, generic::Message as GenericMessage};
+use network_libp2p::NodeIndex;
+use simulation::{self, Simulation};
+use super::{Peer, PassThroughVerifier, TestNet, TestNetFactory, TestPacket, SIMULATED_TICK};
+
+/// Latency of links without a scripted one.
+const DEFAULT_LATENCY: Duration = Duration::from_millis(100);
+
+/// How a simulated peer answers block requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Behaviour {
+	/// Follows the protocol.
+	Honest,
+	/// Never answers block requests.
+	Silent,
+	/// Answers block requests without any blocks.
+	EmptyResponses,
+	/// Answers block requests with undecodable data.
+	Malformed,
+}
+
+/// A packet on its way to the recipient.
+struct InFlight {
+	deliver_at: Instant,
+	// breaks ties between packets delivered at the same time in sending order.
+	seq: u64,
+	from: NodeIndex,
+	packet: TestPacket,
+}
+
+/// A test network driven by simulated time.
+pub struct SimulatedNet {
+	net: TestNet,
+	latencies: BTreeMap<(NodeIndex, NodeIndex), Duration>,
+	behaviours: BTreeMap<NodeIndex, Behaviour>,
+	disconnected: BTreeSet<(NodeIndex, NodeIndex)>,
+	in_flight: Vec<InFlight>,
+	next_seq: u64,
+	delivered: Vec<(NodeIndex, NodeIndex)>,
+	// must be dropped after the network, which reads the simulated clock.
+	_simulation: Simulation,
+}
+
+impl SimulatedNet {
+	/// Create a network of `n` peers simulated with the given seed.
+	///
+	/// Panics if another simulation is running on this thread.
+	pub fn new(seed: u64, n: usize) -> Self {
+		let simulation = Simulation::enter(seed);
+		SimulatedNet {
+			net: TestNet::new(n),
+			latencies: BTreeMap::new(),
+			behaviours: BTreeMap::new(),
+			disconnected: BTreeSet::new(),
+			in_flight: Vec::new(),
+			next_seq: 0,
+			delivered: Vec::new(),
+			_simulation: simulation,
+		}
+	}
+
+	/// Get a reference to a peer.
+	pub fn peer(&self, i: usize) -> &Peer<PassThroughVerifier, ()> {
+		self.net.peer(i)
+	}
+
+	/// Set the latency of packets sent in both directions between two peers.
+	pub fn set_latency(&mut self, a: NodeIndex, b: NodeIndex, latency: Duration) {
+		self.latencies.insert((a, b), latency);
+		self.latencies.insert((b, a), latency);
+	}
+
+	/// Script how a peer answers block requests.
+	pub fn set_behaviour(&mut self, who: NodeIndex, behaviour: Behaviour) {
+		self.behaviours.insert(who, behaviour);
+	}
+
+	/// Whether two peers have disconnected from each other.
+	pub fn is_disconnected(&self, a: NodeIndex, b: NodeIndex) -> bool {
+		self.disconnected.contains(&(a, b)) || self.disconnected.contains(&(b, a))
+	}
+
+	/// Sender and recipient of every packet delivered so far, in delivery order.
+	pub fn delivered(&self) -> &[(NodeIndex, NodeIndex)] {
+		&self.delivered
+	}
+
+	/// Collect the packets sent by all peers, deliver the ones that are due and
+	/// advance the clock by a tick.
+	pub fn step(&mut self) {
+		self.net.start();
+		self.collect_sent();
+		self.deliver_due();
+
+		simulation::advance(SIMULATED_TICK);
+		for peer in self.net.peers() {
+			peer.sync_step();
+		}
+	}
+
+	/// Step until no packets are pending or `max_steps` is reached. Returns the
+	/// number of steps taken.
+	pub fn run(&mut self, max_steps: u32) -> u32 {
+		let mut steps = 0;
+		while steps < max_steps && !self.done() {
+			self.step();
+			steps += 1;
+		}
+		steps
+	}
+
+	/// Whether no packets are queued or in flight.
+	pub fn done(&self) -> bool {
+		self.in_flight.is_empty() && self.net.done()
+	}
+
+	fn collect_sent(&mut self) {
+		let now = simulation::now();
+		for from in 0..self.net.peers().len() as NodeIndex {
+			while let Some(packet) = self.net.peers()[from].pending_message() {
+				if self.is_disconnected(from, packet.recipient) {
+					continue;
+				}
+
+				let packet = match self.misbehave(from, packet) {
+					Some(packet) => packet,
+					None => continue,
+				};
+				let latency = self.latencies.get(&(from, packet.recipient)).cloned().unwrap_or(DEFAULT_LATENCY);
+				self.in_flight.push(InFlight { deliver_at: now + latency, seq: self.next_seq, from, packet });
+				self.next_seq += 1;
+			}
+		}
+	}
+
+	fn deliver_due(&mut self) {
+		let now = simulation::now();
+		let (mut due, in_flight): (Vec<_>, Vec<_>) = self.in_flight.drain(..)
+			.partition(|p| p.deliver_at <= now);
+		self.in_flight = in_flight;
+		due.sort_by_key(|p| (p.deliver_at, p.seq));
+
+		for InFlight { from, packet, .. } in due {
+			let recipient = packet.recipient;
+			if self.is_disconnected(from, recipient) {
+				continue;
+			}
+
+			self.delivered.push((from, recipient));
+			let to_disconnect = self.net.peers()[recipient].receive_message(from, packet);
+			for who in to_disconnect {
+				trace!(target: "sync", "--- {} disconnects {} ---", recipient, who);
+				self.disconnected.insert((recipient, who));
+				self.net.peers()[recipient].on_disconnect(who);
+				self.net.peers()[who].on_disconnect(recipient);
+			}
+		}
+	}
+
+	/// Apply the scripted behaviour of the sender to a packet. Returns `None`
+	/// if the packet is dropped.
+	fn misbehave(&self, from: NodeIndex, packet: TestPacket) -> Option<TestPacket> {
+		let behaviour = self.behaviours.get(&from).cloned().unwrap_or(Behaviour::Honest);
+		if behaviour == Behaviour::Honest {
+			return Some(packet);
+		}
+
+		let response = match <message::Message<super::Block> as Decode>::decode(&mut &packet.data[..]) {
+			Some(GenericMessage::BlockResponse(response)) => response,
+			_ => return Some(packet),
+		};
+
+		let data = match behaviour {
+			Behaviour::Honest => unreachable!("handled above; qed"),
+			Behaviour::Silent => return None,
+			Behaviour::EmptyResponses => {
+				let empty: message::Message<super::Block> = GenericMessage::BlockResponse(
+					message::generic::BlockResponse { id: response.id, blocks: Vec::new() }
+				);
+				empty.encode()
+			},
+			Behaviour::Malformed => vec![0xff; 4],
+		};
+		Some(TestPacket { data, recipient: packet.recipient })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use client::backend::Backend;
+	use client::blockchain::HeaderBackend as BlockchainHeaderBackend;
+	use super::super::Hash;
+
+	fn best_hashes(net: &SimulatedNet, n: usize) -> Vec<Hash> {
+		(0..n).map(|i| net.peer(i).client().info().unwrap().chain.best_hash).collect()
+	}
+
+	#[test]
+	fn many_peers_with_latencies_sync_deterministically() {
+		let _ = ::env_logger::try_init();
+		const PEERS: usize = 24;
+
+		fn run(seed: u64) -> (u32, Vec<Hash>) {
+			let mut net = SimulatedNet::new(seed, PEERS);
+			net.peer(1).push_blocks(40, false);
+			net.peer(2).push_blocks(30, true);
+			net.peer(3).push_blocks(20, false);
+			for i in 0..PEERS {
+				for j in (i + 1)..PEERS {
+					let millis = ((i * 7 + j * 13) % 20) as u64 * 150;
+					net.set_latency(i as NodeIndex, j as NodeIndex, Duration::from_millis(millis));
+				}
+			}
+			let steps = net.run(1000);
+			assert!(net.done());
+			(steps, best_hashes(&net, PEERS))
+		}
+
+		let (steps, best) = run(3);
+		assert_eq!(run(3), (steps, best.clone()));
+		assert!(best.iter().all(|hash| *hash == best[1]));
+	}
+
+	#[test]
+	fn different_seeds_pick_different_gossip_targets() {
+		let _ = ::env_logger::try_init();
+		const PEERS: usize = 16;
+
+		fn run(seed: u64) -> Vec<(NodeIndex, NodeIndex)> {
+			let mut net = SimulatedNet::new(seed, PEERS);
+			net.run(100);
+			let start = net.delivered().len();
+			// non-broadcast messages are sent to a random subset of the peers.
+			net.peer(0).gossip_message(Hash::from_low_u64_be(1), vec![1, 2, 3], false);
+			net.run(100);
+			assert!(net.done());
+			net.delivered()[start..].to_vec()
+		}
+
+		let trace = run(0);
+		assert!(!trace.is_empty());
+		assert_eq!(run(0), trace);
+		assert!((1..8).any(|seed| run(seed) != trace));
+	}
+
+	#[test]
+	fn byzantine_peers_do_not_prevent_sync() {
+		let _ = ::env_logger::try_init();
+		let mut net = SimulatedNet::new(0, 5);
+		for i in 1..5 {
+			net.peer(i).push_blocks(20, false);
+		}
+		net.set_behaviour(1, Behaviour::Silent);
+		net.set_behaviour(2, Behaviour::EmptyResponses);
+		net.set_behaviour(3, Behaviour::Malformed);
+		// the honest peer is the last one to be heard from.
+		net.set_latency(0, 4, Duration::from_secs(2));
+		net.run(1000);
+
+		assert!(net.done());
+		assert!(net.peer(0).client().backend().blockchain().equals_to(net.peer(4).client().backend().blockchain()));
+	}
+}
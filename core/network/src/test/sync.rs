@@ -16,8 +16,11 @@
 
 use client::backend::Backend;
 use client::blockchain::HeaderBackend as BlockchainHeaderBackend;
-use config::Roles;
+use config::{LightAnnouncement, Roles};
 use consensus::BlockOrigin;
+use codec::Decode;
+use futures::Future;
+use message::generic::{BlockData, BlockResponse, Message as GenericMessage};
 use sync::SyncState;
 use super::*;
 
@@ -33,6 +36,33 @@ fn sync_from_two_peers_works() {
 	assert_eq!(status.sync.state, SyncState::Idle);
 }
 
+#[test]
+fn throwaway_nodes_join_a_running_network_and_sync() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(4);
+	net.peer(0).push_blocks(20, false);
+	net.sync();
+	for i in 1..4 {
+		assert!(net.peer(i).client.backend().blockchain().equals_to(net.peer(0).client.backend().blockchain()));
+	}
+
+	// a fresh in-memory node starts from genesis and catches up with everyone.
+	net.add_peer(&ProtocolConfig::default());
+	assert_eq!(net.peer(4).client.info().unwrap().chain.best_number, 0);
+	net.peer(4).start();
+	for i in 0..4 {
+		net.peer(i).on_connect(4);
+		net.peer(4).on_connect(i);
+	}
+	net.peer(2).push_blocks(5, false);
+	net.sync();
+
+	for i in 0..4 {
+		assert!(net.peer(i).client.backend().blockchain().equals_to(net.peer(4).client.backend().blockchain()));
+	}
+	assert_eq!(net.peer(4).client.info().unwrap().chain.best_number, 25);
+}
+
 #[test]
 fn sync_from_two_peers_with_ancestry_search_works() {
 	let _ = ::env_logger::try_init();
@@ -55,6 +85,65 @@ fn sync_long_chain_works() {
 	assert!(net.peer(0).client.backend().blockchain().equals_to(net.peer(1).client.backend().blockchain()));
 }
 
+#[test]
+fn sync_metrics_are_counted() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(1).push_blocks(10, false);
+	net.sync();
+	let metrics = net.peer(0).sync.sync_metrics();
+	assert!(metrics.requests_sent > 0);
+	assert!(metrics.responses_received > 0);
+	assert!(metrics.bytes_downloaded > 0);
+	assert_eq!(metrics.peers_penalized, 0);
+}
+
+#[test]
+fn traffic_stats_are_counted() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(1).push_blocks(10, false);
+	net.sync();
+
+	let traffic = net.peer(0).sync.traffic_stats();
+	assert_eq!(traffic.total["Status"].messages_received, 1);
+	assert!(traffic.total["BlockRequest"].messages_sent > 0);
+	assert!(traffic.peers[&1]["BlockRequest"].bytes_sent > 0);
+	let responses = |kind: &str| traffic.peers[&1].get(kind).map_or(0, |c| c.bytes_received);
+	assert!(responses("BlockResponse") + responses("CompressedBlockResponse") > 0);
+
+	// disconnected peers are only kept in the total.
+	net.peer(0).on_disconnect(1);
+	let traffic = net.peer(0).sync.traffic_stats();
+	assert!(traffic.peers.is_empty());
+	assert_eq!(traffic.total["Status"].messages_received, 1);
+}
+
+#[test]
+fn oversized_compressed_responses_of_a_single_block_are_accepted() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(0);
+	let mut config = ProtocolConfig::default();
+	config.max_accepted_block_response_bytes = 1024;
+	net.add_peer(&config);
+	// serve a single block per response.
+	let mut config = ProtocolConfig::default();
+	config.max_block_response_bytes = 1;
+	net.add_peer(&config);
+
+	// blocks that are much larger than the limit but compress well.
+	net.peer(1).generate_blocks(3, BlockOrigin::File, |mut builder| {
+		builder.push(Extrinsic::StorageChange(vec![1], Some(vec![42; 16 * 1024]))).unwrap();
+		builder.bake().unwrap()
+	});
+	net.sync();
+
+	assert!(net.peer(0).client.backend().blockchain().equals_to(net.peer(1).client.backend().blockchain()));
+	let traffic = net.peer(0).sync.traffic_stats();
+	assert!(traffic.peers[&1]["CompressedBlockResponse"].messages_received > 0);
+	assert_eq!(net.peer(0).sync.sync_metrics().peers_penalized, 0);
+}
+
 #[test]
 fn sync_no_common_longer_chain_fails() {
 	let _ = ::env_logger::try_init();
@@ -88,6 +177,31 @@ fn sync_justifications() {
 	assert_eq!(net.peer(1).client().justification(&BlockId::Number(10)).unwrap(), Some(Vec::new()));
 }
 
+#[test]
+fn justifications_for_consecutive_blocks_are_requested_in_batches() {
+	let _ = ::env_logger::try_init();
+	let mut net = JustificationTestNet::new(2);
+	net.peer(0).push_blocks(20, false);
+	net.sync();
+
+	for n in 1..21 {
+		net.peer(0).client().finalize_block(BlockId::Number(n), Some(Vec::new()), true).unwrap();
+	}
+
+	let requests_sent = net.peer(1).sync.sync_metrics().requests_sent;
+	for n in 11..21 {
+		let header = net.peer(1).client().header(&BlockId::Number(n)).unwrap().unwrap();
+		net.peer(1).request_justification(&header.hash().into(), n);
+	}
+	net.sync();
+
+	for n in 11..21 {
+		assert_eq!(net.peer(1).client().justification(&BlockId::Number(n)).unwrap(), Some(Vec::new()));
+	}
+	// the first request is sent on its own, the other nine are queued while it is in flight.
+	assert_eq!(net.peer(1).sync.sync_metrics().requests_sent - requests_sent, 2);
+}
+
 #[test]
 fn sync_after_fork_works() {
 	let _ = ::env_logger::try_init();
@@ -179,6 +293,109 @@ fn blocks_are_not_announced_by_light_nodes() {
 	assert_eq!(net.peer(2).client.backend().blockchain().info().unwrap().best_number, 0);
 }
 
+#[test]
+fn light_peers_are_only_announced_finalized_blocks() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(0);
+
+	// full peer0 announces to light peer1 on finality only, full peer2 hears of all blocks.
+	let mut finalized_config = ProtocolConfig::default();
+	finalized_config.light_announcement = LightAnnouncement::Finalized;
+	let mut light_config = ProtocolConfig::default();
+	light_config.roles = Roles::LIGHT;
+	net.add_peer(&finalized_config);
+	net.add_peer(&light_config);
+	net.add_peer(&ProtocolConfig::default());
+	net.sync();
+
+	net.peer(0).push_blocks(1, false);
+	let header = net.peer(0).client().header(&BlockId::Number(1)).unwrap().unwrap();
+	net.peer(0).with_io(|io| net.peer(0).sync.on_block_imported(io, header.hash(), &header));
+	net.sync();
+	assert_eq!(net.peer(1).client.backend().blockchain().info().unwrap().best_number, 0);
+	assert_eq!(net.peer(2).client.backend().blockchain().info().unwrap().best_number, 1);
+
+	net.peer(0).client().finalize_block(BlockId::Number(1), None, true).unwrap();
+	net.peer(0).send_finality_notifications();
+	net.sync();
+	assert_eq!(net.peer(1).client.backend().blockchain().info().unwrap().best_number, 1);
+}
+
+#[test]
+fn block_bodies_are_not_requested_from_light_peers() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(0);
+
+	// full peer0 and light peer2 are both connected to light peer1
+	let mut light_config = ProtocolConfig::default();
+	light_config.roles = Roles::LIGHT;
+	net.add_peer(&ProtocolConfig::default());
+	net.add_peer(&light_config);
+	net.add_peer(&light_config);
+
+	net.peer(1).push_blocks(3, false);
+	net.peer(0).start();
+	net.peer(1).start();
+	net.peer(2).start();
+	net.peer(0).on_connect(1);
+	net.peer(2).on_connect(1);
+
+	while !net.done() {
+		net.sync_step();
+	}
+
+	// the light peer can not serve bodies to the full peer, but serves headers
+	// to the other light peer.
+	assert_eq!(net.peer(0).client.backend().blockchain().info().unwrap().best_number, 0);
+	assert_eq!(net.peer(2).client.backend().blockchain().info().unwrap().best_number, 3);
+}
+
+#[test]
+fn light_nodes_do_not_serve_block_bodies() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(0);
+	let mut light_config = ProtocolConfig::default();
+	light_config.roles = Roles::LIGHT;
+	net.add_peer(&light_config);
+	net.peer(0).push_blocks(3, false);
+	let info = net.peer(0).client().info().unwrap().chain;
+
+	net.peer(0).on_connect(1);
+	let status = GenericMessage::Status(::message::generic::Status {
+		version: ::protocol::CURRENT_VERSION,
+		roles: Roles::FULL,
+		best_number: info.best_number,
+		best_hash: info.best_hash,
+		genesis_hash: info.genesis_hash,
+		chain_status: Vec::new(),
+		features: ::message::Features::empty(),
+		fork_id: None,
+		client_version: None,
+	});
+	net.peer(0).receive_message(1, TestPacket { data: status.encode(), recipient: 0 });
+
+	// reading the bodies would make a light node fetch them from its own peers.
+	let request = GenericMessage::BlockRequest(::message::generic::BlockRequest {
+		id: 7,
+		fields: BlockAttributes::HEADER | BlockAttributes::BODY,
+		from: ::message::FromBlock::Number(1),
+		to: None,
+		direction: ::message::Direction::Ascending,
+		max: Some(3),
+	});
+	net.peer(0).receive_message(1, TestPacket { data: request.encode(), recipient: 0 });
+
+	let response = loop {
+		let packet = net.peer(0).pending_message().expect("peer 0 answers the block request");
+		if let Some(GenericMessage::BlockResponse(response)) = <::message::Message<Block> as Decode>::decode(&mut &packet.data[..]) {
+			break response;
+		}
+	};
+	assert_eq!(response.id, 7);
+	assert_eq!(response.blocks.len(), 3);
+	assert!(response.blocks.iter().all(|block| block.header.is_some() && block.body.is_none()));
+}
+
 #[test]
 fn can_sync_small_non_best_forks() {
 	let _ = ::env_logger::try_init();
@@ -217,3 +434,620 @@ fn can_sync_small_non_best_forks() {
 	assert!(net.peer(0).client().header(&BlockId::Hash(small_hash)).unwrap().is_some());
 	assert!(net.peer(1).client().header(&BlockId::Hash(small_hash)).unwrap().is_some());
 }
+
+#[test]
+fn can_sync_long_non_best_forks() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.sync_step();
+	net.peer(0).push_blocks(10, false);
+	net.peer(1).push_blocks(10, false);
+
+	// fork longer than a single descending range on peer 0.
+	net.peer(0).push_blocks_at(BlockId::Number(10), 40, true);
+	let fork_hash = net.peer(0).client().info().unwrap().chain.best_hash;
+	net.peer(0).push_blocks_at(BlockId::Number(10), 50, false);
+	assert_eq!(net.peer(0).client().info().unwrap().chain.best_number, 60);
+
+	net.peer(1).push_blocks(50, false);
+	assert_eq!(net.peer(1).client().info().unwrap().chain.best_number, 60);
+
+	net.sync();
+	assert!(net.peer(1).client().header(&BlockId::Hash(fork_hash)).unwrap().is_none());
+
+	net.peer(0).announce_block(fork_hash);
+	net.sync();
+
+	// peer 1 downloads the fork in several ranges and imports it.
+	assert!(net.peer(1).client().header(&BlockId::Hash(fork_hash)).unwrap().is_some());
+}
+
+#[test]
+fn best_pending_fork_is_downloaded_first() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(0).push_blocks(40, false);
+	net.peer(1).push_blocks(30, false);
+
+	// two stale forks on peer 1, the longer one is announced last.
+	net.peer(1).push_blocks_at(BlockId::Number(30), 2, true);
+	let short_hash = net.peer(1).client().info().unwrap().chain.best_hash;
+	net.peer(1).generate_blocks_at(BlockId::Number(30), 3, BlockOrigin::File, |mut builder| {
+		builder.push(Extrinsic::AuthoritiesChange(Vec::new())).unwrap();
+		builder.bake().unwrap()
+	});
+	let long_hash = net.peer(1).client().info().unwrap().chain.best_hash;
+	net.peer(1).push_blocks_at(BlockId::Number(30), 10, false);
+	assert_eq!(net.peer(1).client().info().unwrap().chain.best_number, 40);
+	net.sync();
+
+	// peer 0 is busy downloading a new block when both forks are announced.
+	net.peer(1).push_blocks(1, false);
+	let new_hash = net.peer(1).client().info().unwrap().chain.best_hash;
+	net.peer(1).announce_block(new_hash);
+	net.peer(1).announce_block(short_hash);
+	net.peer(1).announce_block(long_hash);
+	for _ in 0..4 {
+		net.route();
+	}
+
+	let first_fork_request = net.peer(0).queue.read().iter().filter_map(|packet| {
+		match <::message::Message<Block> as Decode>::decode(&mut &packet.data[..]) {
+			Some(GenericMessage::BlockRequest(request)) => Some(request.from),
+			_ => None,
+		}
+	}).next();
+	assert_eq!(first_fork_request, Some(::message::FromBlock::Hash(long_hash)));
+
+	net.sync();
+	assert!(net.peer(0).client().header(&BlockId::Hash(short_hash)).unwrap().is_some());
+	assert!(net.peer(0).client().header(&BlockId::Hash(long_hash)).unwrap().is_some());
+}
+
+#[test]
+fn forks_below_the_finalized_block_of_a_peer_are_not_downloaded_from_it() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(0).push_blocks(30, false);
+	net.peer(1).push_blocks(30, false);
+
+	// a stale fork on peer 1, which then finalizes a block above it.
+	net.peer(1).push_blocks_at(BlockId::Number(30), 2, true);
+	let fork_hash = net.peer(1).client().info().unwrap().chain.best_hash;
+	net.peer(1).push_blocks_at(BlockId::Number(30), 10, false);
+	net.sync();
+
+	// peer 0 is busy downloading a new block when the fork is announced.
+	net.peer(1).push_blocks(1, false);
+	let new_hash = net.peer(1).client().info().unwrap().chain.best_hash;
+	net.peer(1).announce_block(new_hash);
+	net.peer(1).announce_block(fork_hash);
+	net.peer(1).client().finalize_block(BlockId::Number(35), None, true).unwrap();
+	net.peer(1).send_finality_notifications();
+
+	net.sync();
+	assert!(net.peer(0).client().header(&BlockId::Hash(new_hash)).unwrap().is_some());
+	assert!(net.peer(0).client().header(&BlockId::Hash(fork_hash)).unwrap().is_none());
+}
+
+#[test]
+fn justification_only_sync_fetches_missing_justifications() {
+	let _ = ::env_logger::try_init();
+	let mut net = JustificationTestNet::new(0);
+
+	let mut justifications_only_config = ProtocolConfig::default();
+	justifications_only_config.justifications_only = true;
+	net.add_peer(&ProtocolConfig::default());
+	net.add_peer(&justifications_only_config);
+
+	// both peers already have the same chain
+	net.peer(0).push_blocks(20, false);
+	net.peer(1).push_blocks(20, false);
+
+	net.peer(0).client().finalize_block(BlockId::Number(10), Some(Vec::new()), true).unwrap();
+	assert_eq!(net.peer(1).client().justification(&BlockId::Number(10)).unwrap(), None);
+
+	// nothing is requested until a peer tells us it finalized the blocks.
+	net.sync();
+	assert_eq!(net.peer(1).client().justification(&BlockId::Number(10)).unwrap(), None);
+	assert_eq!(net.peer(1).client().info().unwrap().chain.finalized_number, 0);
+
+	net.peer(0).send_finality_notifications();
+	net.sync();
+
+	assert_eq!(net.peer(1).client().justification(&BlockId::Number(10)).unwrap(), Some(Vec::new()));
+	assert_eq!(net.peer(1).client().info().unwrap().chain.finalized_number, 10);
+}
+
+#[test]
+fn simulated_sync_is_reproducible_from_seed() {
+	let _ = ::env_logger::try_init();
+
+	fn run(seed: u64) -> (u32, Vec<Hash>) {
+		let _simulation = ::simulation::Simulation::enter(seed);
+		let mut net = TestNet::new(4);
+		net.peer(1).push_blocks(30, false);
+		net.peer(2).push_blocks(20, true);
+		net.peer(3).push_blocks(10, false);
+		let steps = net.sync();
+		let best = net.peers().iter()
+			.map(|p| p.client().info().unwrap().chain.best_hash)
+			.collect();
+		(steps, best)
+	}
+
+	for seed in 0..5 {
+		assert_eq!(run(seed), run(seed));
+	}
+}
+
+#[test]
+fn justification_and_blocks_are_requested_concurrently() {
+	let _ = ::env_logger::try_init();
+	let mut net = JustificationTestNet::new(2);
+	net.peer(0).push_blocks(10, false);
+	net.peer(1).push_blocks(10, false);
+	net.peer(0).client().finalize_block(BlockId::Number(10), Some(Vec::new()), true).unwrap();
+
+	// peer 1 needs both a justification and new blocks from its only peer.
+	net.peer(0).push_blocks(20, false);
+	net.start();
+
+	// hand peer 0's status to peer 1, which starts searching for a common ancestor.
+	let status = net.peer(0).pending_message().expect("peer 0 sends its status to peer 1");
+	assert!(net.peer(1).receive_message(0, status).is_empty());
+	let header = net.peer(1).client().header(&BlockId::Number(10)).unwrap().unwrap();
+	net.peer(1).request_justification(&header.hash().into(), 10);
+
+	// before either request is answered, both are in flight with peer 0.
+	let justifications = net.peer(1).sync.sync().read().justification_requests();
+	assert_eq!(justifications.len(), 1);
+	assert_eq!(justifications[0].requested_from.map(|(who, _)| who), Some(0));
+	let requests = net.peer(1).sync.requests_in_flight(0);
+	assert_eq!(requests.len(), 2);
+	assert!(requests.iter().any(|r| r.fields == BlockAttributes::JUSTIFICATION));
+	assert!(requests.iter().any(|r| r.fields.contains(BlockAttributes::HEADER)));
+
+	net.sync();
+
+	assert_eq!(net.peer(1).client().justification(&BlockId::Number(10)).unwrap(), Some(Vec::new()));
+	assert_eq!(net.peer(1).client().info().unwrap().chain.best_number, 30);
+}
+
+#[test]
+fn ancestry_search_stops_at_finalized_block() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(0).push_blocks(5, false);
+	net.peer(1).push_blocks(5, false);
+
+	// peer 1 forks off at block #5, below the block peer 0 finalizes.
+	net.peer(0).push_blocks(10, false);
+	net.peer(1).push_blocks(10, true);
+	net.peer(0).client().finalize_block(BlockId::Number(10), None, true).unwrap();
+	net.peer(0).send_finality_notifications();
+	assert_eq!(net.peer(0).sync.status().sync.best_finalized_number, 10);
+
+	net.sync();
+
+	assert!(net.peer(0).sync.peers().is_empty());
+}
+
+#[test]
+fn unlinked_block_response_is_rejected() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(1).push_blocks(10, false);
+	net.start();
+	net.route();
+
+	// intercept the block request peer 0 sends to peer 1
+	let request = loop {
+		let packet = net.peer(0).pending_message().expect("peer 0 requests blocks from peer 1");
+		if let Some(GenericMessage::BlockRequest(request)) = <::message::Message<Block> as Decode>::decode(&mut &packet.data[..]) {
+			break request;
+		}
+	};
+
+	// answer with peer 1's chain, but leave a gap in it
+	let mut blocks: Vec<_> = (1..11).map(|n| {
+		let header = net.peer(1).client().header(&BlockId::Number(n)).unwrap().unwrap();
+		BlockData {
+			hash: header.hash(),
+			header: Some(header),
+			body: Some(Vec::new()),
+			receipt: None,
+			message_queue: None,
+			justification: None,
+		}
+	}).collect();
+	blocks.remove(4);
+
+	let response = GenericMessage::BlockResponse(BlockResponse { id: request.id, blocks });
+	let disconnected = net.peer(0).receive_message(1, TestPacket { data: response.encode(), recipient: 0 });
+	assert!(disconnected.contains(&1));
+}
+
+#[test]
+fn restart_cancels_in_flight_block_requests() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(1).push_blocks(10, false);
+	net.start();
+	net.route();
+
+	// peer 0 has requested blocks from peer 1, restart before the response arrives.
+	net.peer(0).restart_chain_sync();
+
+	let cancelled = net.peer(0).queue.read().iter().any(|packet| {
+		match <::message::Message<Block> as Decode>::decode(&mut &packet.data[..]) {
+			Some(GenericMessage::CancelRequest(_)) => packet.recipient == 1,
+			_ => false,
+		}
+	});
+	assert!(cancelled);
+
+	// the response to the cancelled request is ignored and sync carries on.
+	net.sync();
+	assert_eq!(net.peer(0).client().info().unwrap().chain.best_number, 10);
+	assert_eq!(net.peer(0).sync.peers().len(), 1);
+}
+
+/// Connects peer 0 to peer 1 speaking the given protocol version, lets peer 0 request blocks
+/// and cancel the request, then delivers the response twice and a response to an unknown id.
+fn unexpected_block_responses(version: u32) -> (HashSet<NodeIndex>, HashSet<NodeIndex>, HashSet<NodeIndex>) {
+	let net = TestNet::new(2);
+	net.peer(1).push_blocks(10, false);
+	let info = net.peer(1).client().info().unwrap().chain;
+
+	net.peer(0).on_connect(1);
+	let status = GenericMessage::Status(::message::generic::Status {
+		version,
+		roles: Roles::FULL,
+		best_number: info.best_number,
+		best_hash: info.best_hash,
+		genesis_hash: info.genesis_hash,
+		chain_status: Vec::new(),
+		features: ::message::Features::empty(),
+		fork_id: None,
+		client_version: None,
+	});
+	net.peer(0).receive_message(1, TestPacket { data: status.encode(), recipient: 0 });
+	let request = loop {
+		let packet = net.peer(0).pending_message().unwrap();
+		if let Some(GenericMessage::BlockRequest(request)) = <::message::Message<Block> as Decode>::decode(&mut &packet.data[..]) {
+			break request;
+		}
+	};
+	net.peer(0).restart_chain_sync();
+
+	let respond = |id| {
+		let response = GenericMessage::BlockResponse(BlockResponse { id, blocks: Vec::new() });
+		net.peer(0).receive_message(1, TestPacket { data: response.encode(), recipient: 0 })
+	};
+	(respond(request.id), respond(request.id), respond(request.id + 1000))
+}
+
+#[test]
+fn late_responses_are_ignored_from_peers_unaware_of_cancellations() {
+	let _ = ::env_logger::try_init();
+	let (cancelled, duplicate, unknown) = unexpected_block_responses(::protocol::CANCEL_REQUEST_VERSION - 1);
+	assert!(cancelled.is_empty());
+	assert!(duplicate.is_empty());
+	assert!(unknown.contains(&1));
+}
+
+#[test]
+fn late_responses_are_reported_from_peers_aware_of_cancellations() {
+	let _ = ::env_logger::try_init();
+	let (cancelled, duplicate, unknown) = unexpected_block_responses(::protocol::CANCEL_REQUEST_VERSION);
+	assert!(cancelled.is_empty());
+	assert!(duplicate.contains(&1));
+	assert!(unknown.contains(&1));
+}
+
+#[test]
+fn fetch_block_from_peer_works() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(0);
+
+	// peer 0 never downloads blocks by itself, so the block must come from the fetch.
+	let mut justifications_only_config = ProtocolConfig::default();
+	justifications_only_config.justifications_only = true;
+	net.add_peer(&justifications_only_config);
+	net.add_peer(&ProtocolConfig::default());
+
+	net.peer(1).push_blocks(3, false);
+	let hash = net.peer(1).client().block_hash(2).unwrap().unwrap();
+
+	let fetched = net.peer(0).fetch_block(hash, BlockAttributes::JUSTIFICATION);
+	net.sync();
+
+	let fetched = fetched.wait().unwrap();
+	assert_eq!(fetched.block.header().hash(), hash);
+	assert!(net.peer(0).client().header(&BlockId::Hash(hash)).unwrap().is_none());
+}
+
+#[test]
+fn peers_are_rotated_when_chain_is_frozen() {
+	let _ = ::env_logger::try_init();
+	let _simulation = ::simulation::Simulation::enter(0);
+	let mut net = TestNet::new(0);
+	let mut config = ProtocolConfig::default();
+	config.chain_freeze_timeout = Some(::std::time::Duration::from_secs(120));
+	net.add_peer(&config);
+	net.add_peer(&ProtocolConfig::default());
+	net.add_peer(&ProtocolConfig::default());
+	net.peer(1).push_blocks(10, false);
+	net.sync();
+
+	let tick = |net: &TestNet, peer: usize| net.peer(peer).with_io(|io| {
+		net.peer(peer).sync.tick(io);
+		io.to_disconnect.clone()
+	});
+
+	// the best seen block doesn't move, but the timeout hasn't passed yet.
+	::simulation::advance(::std::time::Duration::from_secs(60));
+	assert!(tick(&net, 0).is_empty());
+
+	::simulation::advance(::std::time::Duration::from_secs(61));
+	assert_eq!(tick(&net, 0).len(), 1);
+	assert_eq!(net.peer(0).sync.status().chain_freeze_rotations, 1);
+
+	// the freeze timer is restarted after rotating.
+	assert!(tick(&net, 0).is_empty());
+
+	// the detection is disabled by default.
+	assert!(tick(&net, 1).is_empty());
+	assert_eq!(net.peer(1).sync.status().chain_freeze_rotations, 0);
+}
+
+#[test]
+fn unanswered_pings_time_out_unless_the_peer_is_clogged() {
+	let _ = ::env_logger::try_init();
+	let _simulation = ::simulation::Simulation::enter(0);
+	let mut net = TestNet::new(3);
+	net.sync();
+
+	let tick = |net: &TestNet| net.peer(0).with_io(|io| {
+		net.peer(0).sync.tick(io);
+		io.to_disconnect.clone()
+	});
+
+	// the pings to both peers are lost on their way.
+	::simulation::advance(::std::time::Duration::from_secs(10));
+	assert!(tick(&net).is_empty());
+	net.peer(0).queue.write().clear();
+
+	// the network reports one of the peers as clogged.
+	net.peer(0).with_io(|io| net.peer(0).sync.on_clogged_peer(io, 2, ::std::iter::empty()));
+
+	::simulation::advance(::std::time::Duration::from_secs(21));
+	assert_eq!(tick(&net), vec![1].into_iter().collect());
+}
+
+#[test]
+fn unanswered_justification_request_is_retried_from_another_peer() {
+	let _ = ::env_logger::try_init();
+	let _simulation = ::simulation::Simulation::enter(0);
+	let mut net = JustificationTestNet::new(3);
+	net.peer(0).push_blocks(20, false);
+	net.sync();
+	net.peer(0).client().finalize_block(BlockId::Number(10), Some(Vec::new()), true).unwrap();
+	net.peer(2).client().finalize_block(BlockId::Number(10), Some(Vec::new()), true).unwrap();
+
+	let header = net.peer(1).client().header(&BlockId::Number(10)).unwrap().unwrap();
+	net.peer(1).request_justification(&header.hash().into(), 10);
+
+	// the request is lost on its way.
+	let unanswered = net.peer(1).pending_message().unwrap().recipient;
+
+	let tick = |net: &JustificationTestNet| net.peer(1).with_io(|io| {
+		net.peer(1).sync.tick(io);
+		io.to_disconnect.clone()
+	});
+
+	::simulation::advance(::std::time::Duration::from_secs(10));
+	assert!(tick(&net).is_empty());
+
+	// the peer is penalized and the request is sent to the other peer.
+	::simulation::advance(::std::time::Duration::from_secs(10));
+	assert_eq!(tick(&net), vec![unanswered].into_iter().collect());
+	net.sync();
+
+	assert_eq!(net.peer(1).client().justification(&BlockId::Number(10)).unwrap(), Some(Vec::new()));
+}
+
+#[test]
+fn deferred_justification_request_times_out_once_sent() {
+	let _ = ::env_logger::try_init();
+	let _simulation = ::simulation::Simulation::enter(0);
+	let mut config = ProtocolConfig::default();
+	config.max_requests_in_flight = 1;
+	let mut net = JustificationTestNet::from_config(&config);
+	net.add_peer(&config);
+	net.add_peer(&config);
+	net.peer(0).push_blocks(20, false);
+	net.sync();
+	net.peer(0).client().finalize_block(BlockId::Number(10), Some(Vec::new()), true).unwrap();
+
+	let next_request = |net: &JustificationTestNet| loop {
+		let packet = net.peer(1).pending_message()?;
+		if let Some(GenericMessage::BlockRequest(_)) = <::message::Message<Block> as Decode>::decode(&mut &packet.data[..]) {
+			return Some(packet);
+		}
+	};
+	let tick = |net: &JustificationTestNet| net.peer(1).with_io(|io| {
+		net.peer(1).sync.tick(io);
+		io.to_disconnect.clone()
+	});
+
+	// peer 1 requests a new block from peer 0, which answers it late.
+	net.peer(0).push_blocks(1, false);
+	net.peer(0).send_import_notifications();
+	while let Some(packet) = net.peer(0).pending_message() {
+		net.peer(1).receive_message(0, packet);
+	}
+	let block_request = next_request(&net).expect("peer 1 requests the announced block");
+
+	// the justification request waits for the block request to be answered.
+	let header = net.peer(1).client().header(&BlockId::Number(10)).unwrap().unwrap();
+	net.peer(1).request_justification(&header.hash().into(), 10);
+	assert!(next_request(&net).is_none());
+
+	::simulation::advance(::std::time::Duration::from_secs(25));
+	assert!(tick(&net).is_empty());
+
+	net.peer(0).receive_message(1, block_request);
+	let response = net.peer(0).pending_message().unwrap();
+	net.peer(1).receive_message(0, response);
+
+	// it is sent once the block request is answered, and lost on its way.
+	assert_eq!(next_request(&net).map(|packet| packet.recipient), Some(0));
+	net.peer(1).queue.write().clear();
+
+	::simulation::advance(::std::time::Duration::from_secs(15));
+	assert!(tick(&net).is_empty());
+
+	::simulation::advance(::std::time::Duration::from_secs(5));
+	assert_eq!(tick(&net), vec![0].into_iter().collect());
+}
+
+#[test]
+fn justification_request_is_given_up_after_attempt_budget() {
+	let _ = ::env_logger::try_init();
+	let _simulation = ::simulation::Simulation::enter(0);
+	let mut config = ProtocolConfig::default();
+	config.justification_retry.max_attempts = Some(2);
+	let mut net = TestNet::from_config(&config);
+	net.add_peer(&config);
+	net.add_peer(&config);
+	net.peer(0).push_blocks(20, false);
+	net.sync();
+
+	// no peer has the justification.
+	let header = net.peer(1).client().header(&BlockId::Number(10)).unwrap().unwrap();
+	net.peer(1).request_justification(&header.hash().into(), 10);
+	net.sync();
+	assert_eq!(net.peer(1).sync.sync().read().metrics().justification_retries, 0);
+
+	// the peer is asked again once its backoff expires, which exhausts the budget.
+	::simulation::advance(::std::time::Duration::from_secs(10));
+	net.peer(1).sync_step();
+	net.sync();
+	assert_eq!(net.peer(1).sync.sync().read().metrics().justification_retries, 1);
+
+	// the request is not sent anymore.
+	::simulation::advance(::std::time::Duration::from_secs(600));
+	net.peer(1).sync_step();
+	assert!(net.peer(1).is_done());
+	assert_eq!(net.peer(1).sync.sync().read().metrics().justification_retries, 1);
+}
+
+#[test]
+fn mandatory_justification_requests_are_persisted() {
+	let _ = ::env_logger::try_init();
+	let _simulation = ::simulation::Simulation::enter(0);
+	let mut net = JustificationTestNet::new(2);
+	net.peer(0).push_blocks(20, false);
+	net.sync();
+
+	let persisted = |net: &JustificationTestNet| {
+		let raw = ::client::backend::AuxStore::get_aux(&**net.peer(1).client(), ::sync::MANDATORY_JUSTIFICATIONS_KEY)
+			.unwrap()
+			.unwrap_or_default();
+		<Vec<(::primitives::H256, u64)>>::decode(&mut &raw[..]).unwrap_or_default()
+	};
+
+	// no peer has the justification yet, the request is kept for restarts.
+	let header = net.peer(1).client().header(&BlockId::Number(10)).unwrap().unwrap();
+	net.peer(1).request_justification(&header.hash().into(), 10);
+	net.sync();
+	assert_eq!(persisted(&net), vec![(header.hash(), 10)]);
+
+	// the request is forgotten once the justification is imported.
+	net.peer(0).client().finalize_block(BlockId::Number(10), Some(Vec::new()), true).unwrap();
+	::simulation::advance(::std::time::Duration::from_secs(600));
+	net.peer(1).sync_step();
+	net.sync();
+	assert_eq!(net.peer(1).client().justification(&BlockId::Number(10)).unwrap(), Some(Vec::new()));
+	assert!(persisted(&net).is_empty());
+}
+
+/// Starts a network of two peers and routes their handshakes, then returns the blocks the
+/// first peer announced to the second one.
+fn blocks_announced_on_connection(net: &mut TestNet) -> Vec<Hash> {
+	net.start();
+	net.route();
+	net.peer(0).queue.read().iter()
+		.filter(|packet| packet.recipient == 1)
+		.filter_map(|packet| match <message::Message<Block> as Decode>::decode(&mut &packet.data[..]) {
+			Some(GenericMessage::BlockAnnounce(announce)) => Some(announce.header.hash()),
+			_ => None,
+		})
+		.collect()
+}
+
+#[test]
+fn authored_blocks_are_republished_to_peers_slightly_behind() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	let best_header = |net: &TestNet| {
+		let best_hash = net.peer(0).client().info().unwrap().chain.best_hash;
+		net.peer(0).client().header(&BlockId::Hash(best_hash)).unwrap().unwrap()
+	};
+	net.peer(0).push_blocks(2, false);
+	let retracted = best_header(&net);
+	net.peer(0).sync.on_block_authored(&retracted);
+
+	// a longer fork retracts the authored block.
+	net.peer(0).push_blocks_at(BlockId::Number(1), 2, true);
+	let fork = net.peer(0).client().header(&BlockId::Number(2)).unwrap().unwrap();
+	let best = best_header(&net);
+	assert_ne!(fork.hash(), retracted.hash());
+	net.peer(0).sync.on_block_authored(&fork);
+	net.peer(0).sync.on_block_authored(&best);
+
+	assert_eq!(blocks_announced_on_connection(&mut net), vec![fork.hash(), best.hash()]);
+}
+
+#[test]
+fn authored_blocks_are_not_republished_to_peers_far_behind() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(2);
+	net.peer(0).push_blocks(5, false);
+	let authored = net.peer(0).client().header(&BlockId::Number(5)).unwrap().unwrap();
+	net.peer(0).sync.on_block_authored(&authored);
+	// blocks authored by others since then put us too far ahead of the peer.
+	net.peer(0).push_blocks(20, false);
+
+	assert!(blocks_announced_on_connection(&mut net).is_empty());
+}
+
+#[test]
+fn blocks_held_back_by_a_disconnected_peer_are_imported() {
+	let _ = ::env_logger::try_init();
+	let mut net = TestNet::new(0);
+	let mut config = ProtocolConfig::default();
+	config.cross_check_blocks = true;
+	net.add_peer(&config);
+	net.add_peer(&ProtocolConfig::default());
+	net.add_peer(&ProtocolConfig::default());
+	net.peer(1).push_blocks(10, false);
+	net.peer(2).push_blocks(10, false);
+
+	// peer 2 never answers, so the blocks of peer 1 wait to be cross-checked.
+	let is_block_response = |packet: &TestPacket| match <message::Message<Block> as Decode>::decode(&mut &packet.data[..]) {
+		Some(GenericMessage::BlockResponse(_)) => true,
+		_ => false,
+	};
+	net.start();
+	for _ in 0..20 {
+		net.peer(2).queue.write().retain(|packet| !is_block_response(packet));
+		net.route();
+	}
+	assert_eq!(net.peer(0).client().info().unwrap().chain.best_number, 0);
+
+	net.peer(0).on_disconnect(2);
+	assert_eq!(net.peer(0).client().info().unwrap().chain.best_number, 10);
+}
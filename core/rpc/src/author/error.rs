@@ -17,16 +17,19 @@
 //! Authoring RPC module errors.
 
 use client;
-use transaction_pool::txpool;
+use txpool_api;
 use rpc;
 
 use errors;
 
 error_chain! {
 	links {
-		Pool(txpool::error::Error, txpool::error::ErrorKind) #[doc = "Pool error"];
+		Pool(txpool_api::error::Error, txpool_api::error::ErrorKind) #[doc = "Pool error"];
 		Client(client::error::Error, client::error::ErrorKind) #[doc = "Client error"];
 	}
+	foreign_links {
+		UnsafeRpcCalled(errors::UnsafeRpcCalled) #[doc = "Unsafe RPC method called"];
+	}
 	errors {
 		/// Not implemented yet
 		Unimplemented {
@@ -43,6 +46,21 @@ error_chain! {
 			description("extrinsic verification error"),
 			display("Extrinsic verification error: {}", e.description()),
 		}
+		/// Incorrect block format.
+		BadBlockFormat {
+			description("bad block format"),
+			display("Invalid block format"),
+		}
+		/// Block import error.
+		BlockImport(e: String) {
+			description("block import error"),
+			display("Block import error: {}", e),
+		}
+		/// The import queue asks to stop feeding it blocks.
+		ImportQueueBusy {
+			description("import queue busy"),
+			display("Too many blocks waiting for import, try again later"),
+		}
 	}
 }
 
@@ -62,6 +80,22 @@ impl From<Error> for rpc::Error {
 				message: e.description().into(),
 				data: Some(format!("{:?}", e).into()),
 			},
+			Error(ErrorKind::BadBlockFormat, _) => rpc::Error {
+				code: rpc::ErrorCode::ServerError(ERROR + 3),
+				message: "Block has invalid format.".into(),
+				data: None,
+			},
+			Error(ErrorKind::BlockImport(e), _) => rpc::Error {
+				code: rpc::ErrorCode::ServerError(ERROR + 4),
+				message: "Block could not be imported.".into(),
+				data: Some(e.into()),
+			},
+			Error(ErrorKind::UnsafeRpcCalled(_), _) => errors::unsafe_rpc_called(),
+			Error(ErrorKind::ImportQueueBusy, _) => rpc::Error {
+				code: rpc::ErrorCode::ServerError(ERROR + 6),
+				message: "Too many blocks waiting for import, try again later.".into(),
+				data: None,
+			},
 			e => errors::internal(e),
 		}
 	}
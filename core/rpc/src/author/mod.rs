@@ -20,21 +20,23 @@ use std::sync::Arc;
 
 use client::{self, Client};
 use codec::{Encode, Decode};
-use transaction_pool::{
-	txpool::{
-		ChainApi as PoolChainApi,
-		BlockHash,
-		ExHash,
-		IntoPoolError,
-		Pool,
-		watcher::Status,
-	},
+use consensus::BlockOrigin;
+use consensus::import_queue::{BlockImportError, BlockImportResult, ImportQueue, IncomingBlock};
+use txpool_api::{
+	TransactionPool,
+	InPoolTransaction,
+	TransactionStatus,
+	BlockHash,
+	TxHash,
+	error::IntoPoolError,
 };
 use jsonrpc_macros::pubsub;
 use jsonrpc_pubsub::SubscriptionId;
 use primitives::{Bytes, Blake2Hasher, H256};
 use rpc::futures::{Sink, Stream, Future};
 use runtime_primitives::{generic, traits};
+use runtime_primitives::traits::{Block as BlockT, Header as HeaderT};
+use errors::UnsafeRpcCalled;
 use subscriptions::Subscriptions;
 
 pub mod error;
@@ -57,10 +59,15 @@ build_rpc_trait! {
 		#[rpc(name = "author_pendingExtrinsics")]
 		fn pending_extrinsics(&self) -> Result<Vec<Bytes>>;
 
+		/// Verify and import a SCALE-encoded sealed block, as if it was authored
+		/// by this node. Unsafe, only available if enabled on the node.
+		#[rpc(name = "author_submitBlock")]
+		fn submit_block(&self, Bytes) -> Result<BlockImportStatus>;
+
 		#[pubsub(name = "author_extrinsicUpdate")] {
 			/// Submit an extrinsic to watch.
 			#[rpc(name = "author_submitAndWatchExtrinsic")]
-			fn watch_extrinsic(&self, Self::Metadata, pubsub::Subscriber<Status<Hash, BlockHash>>, Bytes);
+			fn watch_extrinsic(&self, Self::Metadata, pubsub::Subscriber<TransactionStatus<Hash, BlockHash>>, Bytes);
 
 			/// Unsubscribe from extrinsic watching.
 			#[rpc(name = "author_unwatchExtrinsic")]
@@ -70,42 +77,65 @@ build_rpc_trait! {
 	}
 }
 
+/// Result of importing a block submitted with `author_submitBlock`.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockImportStatus {
+	/// The block is already in the chain.
+	Known,
+	/// The block has been imported.
+	Imported,
+	/// The block has been imported, but requires a justification, which is
+	/// requested from the network.
+	NeedsJustification,
+}
+
 /// Authoring API
-pub struct Author<B, E, P, RA> where P: PoolChainApi + Sync + Send + 'static {
+pub struct Author<B, E, P, RA> where P: TransactionPool + Sync + Send + 'static {
 	/// Substrate client
-	client: Arc<Client<B, E, <P as PoolChainApi>::Block, RA>>,
+	client: Arc<Client<B, E, <P as TransactionPool>::Block, RA>>,
 	/// Extrinsic pool
-	pool: Arc<Pool<P>>,
+	pool: Arc<P>,
 	/// Subscriptions manager
 	subscriptions: Subscriptions,
+	/// Block import queue
+	import_queue: Arc<ImportQueue<<P as TransactionPool>::Block>>,
+	/// Whether methods unsafe to expose publicly are enabled
+	unsafe_methods: bool,
 }
 
-impl<B, E, P, RA> Author<B, E, P, RA> where P: PoolChainApi + Sync + Send + 'static {
+impl<B, E, P, RA> Author<B, E, P, RA> where P: TransactionPool + Sync + Send + 'static {
 	/// Create new instance of Authoring API.
+	///
+	/// Blocks can only be submitted if `unsafe_methods` is set.
 	pub fn new(
-		client: Arc<Client<B, E, <P as PoolChainApi>::Block, RA>>,
-		pool: Arc<Pool<P>>,
+		client: Arc<Client<B, E, <P as TransactionPool>::Block, RA>>,
+		pool: Arc<P>,
 		subscriptions: Subscriptions,
+		import_queue: Arc<ImportQueue<<P as TransactionPool>::Block>>,
+		unsafe_methods: bool,
 	) -> Self {
 		Author {
 			client,
 			pool,
 			subscriptions,
+			import_queue,
+			unsafe_methods,
 		}
 	}
 }
 
-impl<B, E, P, RA> AuthorApi<ExHash<P>, BlockHash<P>> for Author<B, E, P, RA> where
-	B: client::backend::Backend<<P as PoolChainApi>::Block, Blake2Hasher> + Send + Sync + 'static,
-	E: client::CallExecutor<<P as PoolChainApi>::Block, Blake2Hasher> + Send + Sync + 'static,
-	P: PoolChainApi + Sync + Send + 'static,
+impl<B, E, P, RA> AuthorApi<TxHash<P>, BlockHash<P>> for Author<B, E, P, RA> where
+	B: client::backend::Backend<<P as TransactionPool>::Block, Blake2Hasher> + Send + Sync + 'static,
+	E: client::CallExecutor<<P as TransactionPool>::Block, Blake2Hasher> + Send + Sync + 'static,
+	P: TransactionPool + Sync + Send + 'static,
 	P::Block: traits::Block<Hash=H256>,
 	P::Error: 'static,
 	RA: Send + Sync + 'static
 {
 	type Metadata = ::metadata::Metadata;
 
-	fn submit_extrinsic(&self, ext: Bytes) -> Result<ExHash<P>> {
+	fn submit_extrinsic(&self, ext: Bytes) -> Result<TxHash<P>> {
 		let xt = Decode::decode(&mut &ext[..]).ok_or(error::Error::from(error::ErrorKind::BadFormat))?;
 		let best_block_hash = self.client.info()?.chain.best_hash;
 		self.pool
@@ -117,13 +147,43 @@ impl<B, E, P, RA> AuthorApi<ExHash<P>, BlockHash<P>> for Author<B, E, P, RA> whe
 	}
 
 	fn pending_extrinsics(&self) -> Result<Vec<Bytes>> {
-		Ok(self.pool.ready().map(|tx| tx.data.encode().into()).collect())
+		Ok(self.pool.ready().map(|tx| tx.data().encode().into()).collect())
+	}
+
+	fn submit_block(&self, block: Bytes) -> Result<BlockImportStatus> {
+		if !self.unsafe_methods {
+			return Err(UnsafeRpcCalled.into());
+		}
+		let import_queue = &self.import_queue;
+		if import_queue.status().backpressure {
+			return Err(error::ErrorKind::ImportQueueBusy.into());
+		}
+		let size = block.len();
+		let block = generic::SignedBlock::<P::Block>::decode(&mut &block[..])
+			.ok_or(error::Error::from(error::ErrorKind::BadBlockFormat))?;
+		let (header, body) = block.block.deconstruct();
+		let incoming = IncomingBlock {
+			hash: header.hash(),
+			header: Some(header),
+			body: Some(body),
+			justification: block.justification,
+			origin: None,
+			size,
+		};
+
+		match import_queue.import_block(BlockOrigin::Own, incoming) {
+			Ok(BlockImportResult::ImportedKnown(..)) => Ok(BlockImportStatus::Known),
+			Ok(BlockImportResult::ImportedUnknown(..)) => Ok(BlockImportStatus::Imported),
+			Ok(BlockImportResult::ImportedUnjustified(..)) => Ok(BlockImportStatus::NeedsJustification),
+			Err(BlockImportError::VerificationFailed(_, e)) => Err(error::ErrorKind::BlockImport(e).into()),
+			Err(e) => Err(error::ErrorKind::BlockImport(format!("{:?}", e)).into()),
+		}
 	}
 
-	fn watch_extrinsic(&self, _metadata: Self::Metadata, subscriber: pubsub::Subscriber<Status<ExHash<P>, BlockHash<P>>>, xt: Bytes) {
+	fn watch_extrinsic(&self, _metadata: Self::Metadata, subscriber: pubsub::Subscriber<TransactionStatus<TxHash<P>, BlockHash<P>>>, xt: Bytes) {
 		let submit = || -> Result<_> {
 			let best_block_hash = self.client.info()?.chain.best_hash;
-			let dxt = <<P as PoolChainApi>::Block as traits::Block>::Extrinsic::decode(&mut &xt[..]).ok_or(error::Error::from(error::ErrorKind::BadFormat))?;
+			let dxt = <<P as TransactionPool>::Block as traits::Block>::Extrinsic::decode(&mut &xt[..]).ok_or(error::Error::from(error::ErrorKind::BadFormat))?;
 			self.pool
 				.submit_and_watch(&generic::BlockId::hash(best_block_hash), dxt)
 				.map_err(|e| e.into_pool_error()
@@ -132,8 +192,8 @@ impl<B, E, P, RA> AuthorApi<ExHash<P>, BlockHash<P>> for Author<B, E, P, RA> whe
 				)
 		};
 
-		let watcher = match submit() {
-			Ok(watcher) => watcher,
+		let status_stream = match submit() {
+			Ok(status_stream) => status_stream,
 			Err(err) => {
 				// reject the subscriber (ignore errors - we don't care if subscriber is no longer there).
 				let _ = subscriber.reject(err.into());
@@ -144,7 +204,7 @@ impl<B, E, P, RA> AuthorApi<ExHash<P>, BlockHash<P>> for Author<B, E, P, RA> whe
 		self.subscriptions.add(subscriber, move |sink| {
 			sink
 				.sink_map_err(|e| warn!("Error sending notifications: {:?}", e))
-				.send_all(watcher.into_stream().map(Ok))
+				.send_all(status_stream.map(Ok))
 				.map(|_| ())
 		})
 	}
@@ -18,13 +18,17 @@ use super::*;
 
 use std::sync::Arc;
 use codec::Encode;
+use consensus::{ForkChoiceStrategy, ImportBlock};
+use consensus::import_queue::{BasicQueue, Verifier};
 use transaction_pool::{
 	txpool::Pool,
 	ChainApi,
 };
 use primitives::H256;
 use test_client::keyring::Keyring;
-use test_client::runtime::{Extrinsic, Transfer};
+use test_client::runtime::{Block, Extrinsic, Header, Transfer};
+use runtime_primitives::Justification;
+use runtime_primitives::traits::AuthorityIdFor;
 use test_client;
 use tokio::runtime;
 
@@ -39,14 +43,20 @@ fn uxt(sender: Keyring, nonce: u64) -> Extrinsic {
 	Extrinsic::Transfer(tx, signature)
 }
 
+fn import_queue(client: &Arc<test_client::TestClient>) -> Arc<ImportQueue<Block>> {
+	Arc::new(BasicQueue::new(Arc::new(PassThroughVerifier), client.clone(), None))
+}
+
 #[test]
 fn submit_transaction_should_not_cause_error() {
 	let runtime = runtime::Runtime::new().unwrap();
 	let client = Arc::new(test_client::new());
 	let p = Author {
 		client: client.clone(),
-		pool: Arc::new(Pool::new(Default::default(), ChainApi::new(client))),
+		pool: Arc::new(Pool::new(Default::default(), ChainApi::new(client.clone()))),
 		subscriptions: Subscriptions::new(runtime.executor()),
+		import_queue: import_queue(&client),
+		unsafe_methods: false,
 	};
 	let h: H256 = hex!("81897a4890fb7554e7f77c533a865846a11583a56a8ad5e307543188d55e64f1").into();
 
@@ -67,6 +77,8 @@ fn submit_rich_transaction_should_not_cause_error() {
 		client: client.clone(),
 		pool: Arc::new(Pool::new(Default::default(), ChainApi::new(client.clone()))),
 		subscriptions: Subscriptions::new(runtime.executor()),
+		import_queue: import_queue(&client),
+		unsafe_methods: false,
 	};
 	let h: H256 = hex!("9ec8469b5dcfe29cc274ac1d07ad73d80be57566ace0fcdbe51ebcf4b51e925b").into();
 
@@ -86,9 +98,11 @@ fn should_watch_extrinsic() {
 	let client = Arc::new(test_client::new());
 	let pool = Arc::new(Pool::new(Default::default(), ChainApi::new(client.clone())));
 	let p = Author {
-		client,
+		client: client.clone(),
 		pool: pool.clone(),
 		subscriptions: Subscriptions::new(runtime.executor()),
+		import_queue: import_queue(&client),
+		unsafe_methods: false,
 	};
 	let (subscriber, id_rx, data) = ::jsonrpc_macros::pubsub::Subscriber::new_test("test");
 
@@ -126,9 +140,11 @@ fn should_return_pending_extrinsics() {
 	let client = Arc::new(test_client::new());
 	let pool = Arc::new(Pool::new(Default::default(), ChainApi::new(client.clone())));
 	let p = Author {
-		client,
+		client: client.clone(),
 		pool: pool.clone(),
 		subscriptions: Subscriptions::new(runtime.executor()),
+		import_queue: import_queue(&client),
+		unsafe_methods: false,
 	};
 	let ex = uxt(Keyring::Alice, 0);
 	AuthorApi::submit_extrinsic(&p, ex.encode().into()).unwrap();
@@ -137,3 +153,66 @@ fn should_return_pending_extrinsics() {
 		Ok(ref expected) if *expected == vec![Bytes(ex.encode())]
 	);
 }
+
+struct PassThroughVerifier;
+
+impl Verifier<Block> for PassThroughVerifier {
+	fn verify(
+		&self,
+		origin: BlockOrigin,
+		header: Header,
+		justification: Option<Justification>,
+		body: Option<Vec<Extrinsic>>,
+	) -> ::std::result::Result<(ImportBlock<Block>, Option<Vec<AuthorityIdFor<Block>>>), String> {
+		Ok((ImportBlock {
+			origin,
+			header,
+			body,
+			finalized: false,
+			justification,
+			post_digests: vec![],
+			auxiliary: Vec::new(),
+			fork_choice: ForkChoiceStrategy::LongestChain,
+		}, None))
+	}
+}
+
+#[test]
+fn should_import_submitted_block() {
+	let runtime = runtime::Runtime::new().unwrap();
+	let client = Arc::new(test_client::new());
+	let p = Author {
+		client: client.clone(),
+		pool: Arc::new(Pool::new(Default::default(), ChainApi::new(client.clone()))),
+		subscriptions: Subscriptions::new(runtime.executor()),
+		import_queue: import_queue(&client),
+		unsafe_methods: true,
+	};
+	let block = client.new_block().unwrap().bake().unwrap();
+	let block = generic::SignedBlock { block, justification: None }.encode();
+
+	assert_matches!(p.submit_block(Bytes(block.clone())), Ok(BlockImportStatus::Imported));
+	assert_eq!(client.info().unwrap().chain.best_number, 1);
+	assert_matches!(p.submit_block(Bytes(block)), Ok(BlockImportStatus::Known));
+}
+
+#[test]
+fn should_not_import_submitted_block_if_disabled() {
+	let runtime = runtime::Runtime::new().unwrap();
+	let client = Arc::new(test_client::new());
+	let p = Author {
+		client: client.clone(),
+		pool: Arc::new(Pool::new(Default::default(), ChainApi::new(client.clone()))),
+		subscriptions: Subscriptions::new(runtime.executor()),
+		import_queue: import_queue(&client),
+		unsafe_methods: false,
+	};
+	let block = client.new_block().unwrap().bake().unwrap();
+	let block = generic::SignedBlock { block, justification: None }.encode();
+
+	assert_matches!(
+		p.submit_block(Bytes(block)),
+		Err(error::Error(error::ErrorKind::UnsafeRpcCalled(_), _))
+	);
+	assert_eq!(client.info().unwrap().chain.best_number, 0);
+}
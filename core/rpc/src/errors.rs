@@ -14,8 +14,33 @@
 // You should have received a copy of the GNU General Public License
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::{error, fmt};
 use rpc;
 
+/// Call to an RPC method that is unsafe to expose publicly, while such methods aren't enabled.
+#[derive(Debug)]
+pub struct UnsafeRpcCalled;
+
+impl fmt::Display for UnsafeRpcCalled {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "RPC method is unsafe to expose publicly and is not enabled")
+	}
+}
+
+impl error::Error for UnsafeRpcCalled {
+	fn description(&self) -> &str {
+		"unsafe rpc method called"
+	}
+}
+
+pub fn unsafe_rpc_called() -> rpc::Error {
+	rpc::Error {
+		code: rpc::ErrorCode::ServerError(2),
+		message: "Method is unsafe and not enabled on this node.".into(),
+		data: None,
+	}
+}
+
 pub fn unimplemented() -> rpc::Error {
 	rpc::Error {
 		code: rpc::ErrorCode::ServerError(1),
@@ -0,0 +1,50 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Finality RPC module errors.
+
+use rpc;
+
+use errors;
+
+error_chain! {
+	foreign_links {
+		UnsafeRpcCalled(errors::UnsafeRpcCalled) #[doc = "Unsafe RPC method called"];
+	}
+	errors {
+		/// No finality voter is running on this node.
+		NoVoter {
+			description("no finality voter"),
+			display("No finality voter is running"),
+		}
+	}
+}
+
+const ERROR: i64 = 3000;
+
+impl From<Error> for rpc::Error {
+	fn from(e: Error) -> Self {
+		match e {
+			Error(ErrorKind::UnsafeRpcCalled(_), _) => errors::unsafe_rpc_called(),
+			Error(ErrorKind::NoVoter, _) => rpc::Error {
+				code: rpc::ErrorCode::ServerError(ERROR + 2),
+				message: "No finality voter is running".into(),
+				data: None,
+			},
+			e => errors::internal(e),
+		}
+	}
+}
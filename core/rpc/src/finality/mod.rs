@@ -0,0 +1,96 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Substrate finality API, to control the finality voter of the node.
+
+pub mod error;
+
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
+use parking_lot::RwLock;
+
+use errors::UnsafeRpcCalled;
+use self::error::Result;
+
+/// Control over the finality voter run by the node.
+pub trait VoterControl: Send + Sync {
+	/// Stop voting until resumed, e.g. to hold back finality during incidents.
+	/// Block production and import are not affected.
+	fn pause(&self, reason: String);
+
+	/// Resume voting after a pause.
+	fn resume(&self);
+}
+
+/// Voter control that is filled in once the finality voter is started,
+/// which happens after the RPC servers are up.
+pub type SharedVoterControl = Arc<RwLock<Option<Arc<VoterControl>>>>;
+
+build_rpc_trait! {
+	/// Substrate finality RPC API
+	pub trait FinalityApi {
+		/// Pause the finality voter. Blocks are still produced and imported,
+		/// but this node doesn't vote on finality until resumed.
+		#[rpc(name = "finality_pauseVoter")]
+		fn pause_voter(&self, String) -> Result<()>;
+
+		/// Resume the finality voter after a pause.
+		#[rpc(name = "finality_resumeVoter")]
+		fn resume_voter(&self) -> Result<()>;
+	}
+}
+
+/// Finality API implementation
+pub struct Finality {
+	voter: SharedVoterControl,
+	unsafe_methods: bool,
+}
+
+impl Finality {
+	/// Create new instance of Finality API. The voter can only be controlled
+	/// if `unsafe_methods` is set.
+	pub fn new(voter: SharedVoterControl, unsafe_methods: bool) -> Self {
+		Finality {
+			voter,
+			unsafe_methods,
+		}
+	}
+
+	fn voter(&self) -> Result<Arc<VoterControl>> {
+		if !self.unsafe_methods {
+			return Err(UnsafeRpcCalled.into());
+		}
+
+		let voter = self.voter.read().clone();
+		voter.ok_or(error::ErrorKind::NoVoter.into())
+	}
+}
+
+impl FinalityApi for Finality {
+	fn pause_voter(&self, reason: String) -> Result<()> {
+		info!("Pausing finality voter: {}", reason);
+		self.voter()?.pause(reason);
+		Ok(())
+	}
+
+	fn resume_voter(&self) -> Result<()> {
+		info!("Resuming finality voter");
+		self.voter()?.resume();
+		Ok(())
+	}
+}
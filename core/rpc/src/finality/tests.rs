@@ -0,0 +1,62 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::*;
+
+use parking_lot::Mutex;
+
+#[derive(Default)]
+struct Voter {
+	commands: Mutex<Vec<Option<String>>>,
+}
+
+impl VoterControl for Voter {
+	fn pause(&self, reason: String) {
+		self.commands.lock().push(Some(reason));
+	}
+
+	fn resume(&self) {
+		self.commands.lock().push(None);
+	}
+}
+
+#[test]
+fn should_pause_and_resume_voter() {
+	let voter = Arc::new(Voter::default());
+	let control: SharedVoterControl = Arc::new(RwLock::new(None));
+	let api = Finality::new(control.clone(), true);
+
+	// voter not started yet.
+	assert_matches!(
+		api.pause_voter("incident".into()),
+		Err(error::Error(error::ErrorKind::NoVoter, _))
+	);
+
+	*control.write() = Some(voter.clone());
+	assert_matches!(api.pause_voter("incident".into()), Ok(()));
+	assert_matches!(api.resume_voter(), Ok(()));
+	assert_eq!(*voter.commands.lock(), vec![Some("incident".to_string()), None]);
+}
+
+#[test]
+fn should_reject_unsafe_calls() {
+	let api = Finality::new(Default::default(), false);
+
+	assert_matches!(
+		api.resume_voter(),
+		Err(error::Error(error::ErrorKind::UnsafeRpcCalled(_), _))
+	);
+}
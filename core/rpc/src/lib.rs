@@ -26,10 +26,12 @@ extern crate serde;
 extern crate serde_json;
 extern crate sr_primitives as runtime_primitives;
 extern crate sr_version as runtime_version;
+extern crate srml_metadata;
 extern crate substrate_client as client;
+extern crate substrate_consensus_common as consensus;
 extern crate substrate_network as network;
 extern crate substrate_primitives as primitives;
-extern crate substrate_transaction_pool as transaction_pool;
+extern crate substrate_transaction_pool_api as txpool_api;
 extern crate tokio;
 
 #[macro_use]
@@ -50,7 +52,7 @@ extern crate hex_literal;
 #[cfg(test)]
 extern crate substrate_test_client as test_client;
 #[cfg(test)]
-extern crate substrate_consensus_common as consensus;
+extern crate substrate_transaction_pool as transaction_pool;
 #[cfg(test)]
 extern crate rustc_hex;
 
@@ -58,10 +60,12 @@ mod errors;
 mod helpers;
 mod subscriptions;
 
+pub use errors::UnsafeRpcCalled;
 pub use subscriptions::Subscriptions;
 
 pub mod author;
 pub mod chain;
+pub mod finality;
 pub mod metadata;
 pub mod state;
 pub mod system;
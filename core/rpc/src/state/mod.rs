@@ -23,6 +23,7 @@ use std::{
 };
 
 use client::{self, Client, CallExecutor, BlockchainEvents, runtime_api::Metadata};
+use codec::Encode;
 use jsonrpc_macros::Trailing;
 use jsonrpc_macros::pubsub;
 use jsonrpc_pubsub::SubscriptionId;
@@ -83,6 +84,11 @@ build_rpc_trait! {
 		#[rpc(name = "state_queryStorage")]
 		fn query_storage(&self, Vec<StorageKey>, Hash, Trailing<Hash>) -> Result<Vec<StorageChangeSet<Hash>>>;
 
+		/// Query the blocks containing events with the given topic, starting from a block given
+		/// as the second parameter.
+		#[rpc(name = "state_queryEventTopic")]
+		fn query_event_topic(&self, Hash, Hash, Trailing<Hash>) -> Result<Vec<Hash>>;
+
 		#[pubsub(name = "state_runtimeVersion")] {
 			/// New runtime version subscription
 			#[rpc(name = "state_subscribeRuntimeVersion", alias = ["chain_subscribeRuntimeVersion", ])]
@@ -334,6 +340,20 @@ impl<B, E, Block, RA> StateApi<Block::Hash> for State<B, E, Block, RA> where
 		Ok(changes)
 	}
 
+	fn query_event_topic(
+		&self,
+		topic: Block::Hash,
+		from: Block::Hash,
+		to: Trailing<Block::Hash>
+	) -> Result<Vec<Block::Hash>> {
+		let key = event_topic_key(&topic);
+		let changes = self.query_storage(vec![key], from, to)?;
+		Ok(changes.into_iter()
+			.filter(|change_set| change_set.changes.iter().any(|(_, data)| data.is_some()))
+			.map(|change_set| change_set.block)
+			.collect())
+	}
+
 	fn subscribe_storage(
 		&self,
 		_meta: Self::Metadata,
@@ -436,6 +456,14 @@ impl<B, E, Block, RA> StateApi<Block::Hash> for State<B, E, Block, RA> where
 	}
 }
 
+/// Storage key of the `System EventTopics` map entry of the given topic.
+///
+/// The entry holds the indices of the current block's events with the topic and is cleared at
+/// the start of every block.
+fn event_topic_key(topic: &H256) -> StorageKey {
+	StorageKey(srml_metadata::storage_key("System", "EventTopics", Some(&topic.encode())).to_vec())
+}
+
 /// Splits passed range into two subranges where:
 /// - first range has at least one element in it;
 /// - second range (optionally) starts at given `middle` element.
@@ -199,6 +199,65 @@ fn should_query_storage() {
 	run_tests(Arc::new(test_client::new_with_changes_trie()));
 }
 
+#[test]
+fn should_query_event_topic() {
+	type TestClient = test_client::client::Client<
+		test_client::Backend,
+		test_client::Executor,
+		runtime::Block,
+		runtime::RuntimeApi
+	>;
+
+	fn run_tests(client: Arc<TestClient>) {
+		let core = ::tokio::runtime::Runtime::new().unwrap();
+		let api = State::new(client.clone(), Subscriptions::new(core.executor()));
+
+		let topic = H256::from_low_u64_be(1);
+		let other_topic = H256::from_low_u64_be(2);
+
+		// the test runtime has no events, so write the `System EventTopics` entries the way
+		// `deposit_event_indexed` and the clearing at the start of each block would.
+		let add_block = |changes: Vec<(H256, Option<Vec<(u64, u32)>>)>| {
+			let mut builder = client.new_block().unwrap();
+			for (topic, indices) in changes {
+				builder.push_storage_change(
+					event_topic_key(&topic).0,
+					indices.map(|indices| indices.encode()),
+				).unwrap();
+			}
+			let block = builder.bake().unwrap();
+			let hash = block.header.hash();
+			client.import(BlockOrigin::Own, block).unwrap();
+			hash
+		};
+		let genesis_hash = client.genesis_hash();
+		let block1_hash = add_block(vec![(topic, Some(vec![(1, 0)]))]);
+		let block2_hash = add_block(vec![(topic, None)]);
+		let block3_hash = add_block(vec![(topic, Some(vec![(3, 0), (3, 1)]))]);
+		let block4_hash = add_block(vec![(topic, None), (other_topic, Some(vec![(4, 0)]))]);
+
+		assert_eq!(
+			api.query_event_topic(topic, genesis_hash, None.into()).unwrap(),
+			vec![block1_hash, block3_hash],
+		);
+		assert_eq!(
+			api.query_event_topic(topic, genesis_hash, Some(block2_hash).into()).unwrap(),
+			vec![block1_hash],
+		);
+		assert_eq!(
+			api.query_event_topic(topic, block2_hash, None.into()).unwrap(),
+			vec![block3_hash],
+		);
+		assert_eq!(
+			api.query_event_topic(other_topic, genesis_hash, None.into()).unwrap(),
+			vec![block4_hash],
+		);
+	}
+
+	run_tests(Arc::new(test_client::new()));
+	run_tests(Arc::new(test_client::new_with_changes_trie()));
+}
+
 #[test]
 fn should_split_ranges() {
 	assert_eq!(split_range(1, None), (0..1, None));
@@ -22,6 +22,9 @@ use errors;
 use system::helpers::Health;
 
 error_chain! {
+	foreign_links {
+		UnsafeRpcCalled(errors::UnsafeRpcCalled) #[doc = "Unsafe RPC method called"];
+	}
 	errors {
 		/// Node is not fully functional
 		NotHealthy(h: Health) {
@@ -34,6 +37,12 @@ error_chain! {
 			description("not yet implemented"),
 			display("Method Not Implemented"),
 		}
+
+		/// Address of a peer that can't be parsed.
+		InvalidPeerAddress(addr: String) {
+			description("invalid peer address"),
+			display("Invalid peer address: {}. Expected a multiaddress ending with /p2p/<peer id>", addr),
+		}
 	}
 }
 
@@ -48,6 +57,12 @@ impl From<Error> for rpc::Error {
 				message: "node is not healthy".into(),
 				data:serde_json::to_value(h).ok(),
 			},
+			Error(ErrorKind::UnsafeRpcCalled(_), _) => errors::unsafe_rpc_called(),
+			Error(ErrorKind::InvalidPeerAddress(addr), _) => rpc::Error {
+				code: rpc::ErrorCode::InvalidParams,
+				message: format!("Invalid peer address: {}", addr),
+				data: None,
+			},
 			e => errors::internal(e),
 		}
 	}
@@ -62,12 +62,54 @@ pub struct PeerInfo<Hash, Number> {
 	pub roles: String,
 	/// Protocol version
 	pub protocol_version: u32,
+	/// Client name and version, if the peer told
+	pub client_version: Option<String>,
 	/// Peer best block hash
 	pub best_hash: Hash,
 	/// Peer best block number
 	pub best_number: Number,
 }
 
+/// State of a justification request tracked by the sync
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingJustification<Hash, Number> {
+	/// Block hash
+	pub hash: Hash,
+	/// Block number
+	pub number: Number,
+	/// Is the justification needed to make finality progress
+	pub mandatory: bool,
+	/// Is a provided justification being imported
+	pub importing: bool,
+	/// Peer the request is currently in flight with
+	pub requested_from: Option<JustificationPeerRequest>,
+	/// Peers that failed to provide the justification
+	pub failed_attempts: Vec<FailedJustificationAttempt>,
+}
+
+/// Justification request in flight with a peer
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JustificationPeerRequest {
+	/// Peer Node Index
+	pub peer: usize,
+	/// Seconds elapsed since the request was sent
+	pub elapsed_secs: u64,
+}
+
+/// Failed attempts of a peer to provide a justification
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedJustificationAttempt {
+	/// Peer Node Index
+	pub peer: usize,
+	/// Number of failed attempts
+	pub attempts: u32,
+	/// Seconds elapsed since the last attempt
+	pub elapsed_secs: u64,
+}
+
 impl fmt::Display for Health {
 	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
 		write!(fmt, "{} peers ({})", self.peers, if self.is_syncing {
@@ -100,10 +142,11 @@ mod tests {
 				peer_id: "2".into(),
 				roles: "a".into(),
 				protocol_version: 2,
+				client_version: Some("c/v3".into()),
 				best_hash: 5u32,
 				best_number: 6u32,
 			}).unwrap(),
-			r#"{"index":1,"peerId":"2","roles":"a","protocolVersion":2,"bestHash":5,"bestNumber":6}"#,
+			r#"{"index":1,"peerId":"2","roles":"a","protocolVersion":2,"clientVersion":"c/v3","bestHash":5,"bestNumber":6}"#,
 		);
 	}
 }
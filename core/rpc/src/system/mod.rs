@@ -26,8 +26,12 @@ use std::sync::Arc;
 use network;
 use runtime_primitives::traits::{self, Header as HeaderT};
 
+use errors::UnsafeRpcCalled;
 use self::error::Result;
-pub use self::helpers::{Properties, SystemInfo, Health, PeerInfo};
+pub use self::helpers::{
+	Properties, SystemInfo, Health, PeerInfo, PendingJustification, JustificationPeerRequest,
+	FailedJustificationAttempt,
+};
 
 build_rpc_trait! {
 	/// Substrate system RPC API
@@ -59,6 +63,29 @@ build_rpc_trait! {
 		/// Returns currently connected peers
 		#[rpc(name = "system_peers")]
 		fn system_peers(&self) -> Result<Vec<PeerInfo<Hash, Number>>>;
+
+		/// Returns the justification requests tracked by the sync, with the peers
+		/// they were assigned to and the failed attempts so far. Unsafe.
+		#[rpc(name = "system_pendingJustifications")]
+		fn system_pending_justifications(&self) -> Result<Vec<PendingJustification<Hash, Number>>>;
+
+		/// Returns true if the node only accepts connections of its reserved peers.
+		#[rpc(name = "system_reservedOnly")]
+		fn system_reserved_only(&self) -> Result<bool>;
+
+		/// Switches between accepting only reserved peers, disconnecting the
+		/// others, and accepting any peer. Unsafe.
+		#[rpc(name = "system_setReservedOnly")]
+		fn system_set_reserved_only(&self, reserved_only: bool) -> Result<()>;
+
+		/// Connects to a peer, given as a multiaddress ending with `/p2p/<peer id>`. The address
+		/// is remembered for later connections. Unsafe.
+		#[rpc(name = "system_dialPeer")]
+		fn system_dial_peer(&self, addr: String) -> Result<()>;
+
+		/// Disconnects a peer, given as `/p2p/<peer id>`. Unsafe.
+		#[rpc(name = "system_disconnectPeer")]
+		fn system_disconnect_peer(&self, peer: String) -> Result<()>;
 	}
 }
 
@@ -66,20 +93,27 @@ build_rpc_trait! {
 pub struct System<B: traits::Block> {
 	info: SystemInfo,
 	sync: Arc<network::SyncProvider<B>>,
+	network: Arc<network::ManageNetwork>,
 	should_have_peers: bool,
+	unsafe_methods: bool,
 }
 
 impl<B: traits::Block> System<B> {
-	/// Creates new `System` given the `SystemInfo`.
+	/// Creates new `System` given the `SystemInfo`. Diagnostics that are unsafe
+	/// to expose publicly are only available if `unsafe_methods` is set.
 	pub fn new(
 		info: SystemInfo,
 		sync: Arc<network::SyncProvider<B>>,
+		network: Arc<network::ManageNetwork>,
 		should_have_peers: bool,
+		unsafe_methods: bool,
 	) -> Self {
 		System {
 			info,
 			should_have_peers,
 			sync,
+			network,
+			unsafe_methods,
 		}
 	}
 }
@@ -116,8 +150,77 @@ impl<B: traits::Block> SystemApi<B::Hash, <B::Header as HeaderT>::Number> for Sy
 			peer_id: peer_id.map_or_else(Default::default, |p| p.to_base58()),
 			roles: format!("{:?}", p.roles),
 			protocol_version: p.protocol_version,
+			client_version: p.client_version,
 			best_hash: p.best_hash,
 			best_number: p.best_number,
 		}).collect())
 	}
+
+	fn system_pending_justifications(&self) -> Result<Vec<PendingJustification<B::Hash, <B::Header as HeaderT>::Number>>> {
+		if !self.unsafe_methods {
+			return Err(UnsafeRpcCalled.into());
+		}
+
+		Ok(self.sync.justification_requests().into_iter().map(|r| PendingJustification {
+			hash: r.hash,
+			number: r.number,
+			mandatory: r.mandatory,
+			importing: r.importing,
+			requested_from: r.requested_from.map(|(peer, elapsed)| JustificationPeerRequest {
+				peer,
+				elapsed_secs: elapsed.as_secs(),
+			}),
+			failed_attempts: r.failed_attempts.into_iter().map(|(peer, attempts, elapsed)| FailedJustificationAttempt {
+				peer,
+				attempts,
+				elapsed_secs: elapsed.as_secs(),
+			}).collect(),
+		}).collect())
+	}
+
+	fn system_reserved_only(&self) -> Result<bool> {
+		Ok(self.network.is_reserved_only())
+	}
+
+	fn system_set_reserved_only(&self, reserved_only: bool) -> Result<()> {
+		if !self.unsafe_methods {
+			return Err(UnsafeRpcCalled.into());
+		}
+
+		if reserved_only {
+			warn!("Only accepting reserved peers from now on, as requested over RPC");
+			self.network.deny_unreserved_peers();
+		} else {
+			info!("Accepting any peer from now on, as requested over RPC");
+			self.network.accept_unreserved_peers();
+		}
+		Ok(())
+	}
+
+	fn system_dial_peer(&self, addr: String) -> Result<()> {
+		if !self.unsafe_methods {
+			return Err(UnsafeRpcCalled.into());
+		}
+
+		let (peer_id, peer_addr) = network::parse_str_addr(&addr)
+			.map_err(|_| error::ErrorKind::InvalidPeerAddress(addr.clone()))?;
+		info!("Connecting to {} as requested over RPC", addr);
+		if peer_addr.iter().next().is_some() {
+			self.network.add_known_address(peer_id.clone(), peer_addr);
+		}
+		self.network.dial_peer(peer_id);
+		Ok(())
+	}
+
+	fn system_disconnect_peer(&self, peer: String) -> Result<()> {
+		if !self.unsafe_methods {
+			return Err(UnsafeRpcCalled.into());
+		}
+
+		let (peer_id, _) = network::parse_str_addr(&peer)
+			.map_err(|_| error::ErrorKind::InvalidPeerAddress(peer.clone()))?;
+		info!("Disconnecting {} as requested over RPC", peer);
+		self.network.disconnect_peer(peer_id, "requested over RPC");
+		Ok(())
+	}
 }
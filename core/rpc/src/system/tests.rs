@@ -16,8 +16,11 @@
 
 use super::*;
 
-use network::{self, SyncState, SyncStatus, ProtocolStatus, NodeIndex, PeerId, PeerInfo as NetworkPeerInfo, PublicKey};
+use network::{self, SyncMetrics, SyncState, SyncStatus, ProtocolStatus, NodeIndex, PeerId, PeerInfo as NetworkPeerInfo, PublicKey, ExtraRequestInfo, DownloadStats};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use network::config::Roles;
+use network::message::Features;
 use test_client::runtime::Block;
 
 #[derive(Default)]
@@ -33,24 +36,117 @@ impl network::SyncProvider<Block> for Status {
 			sync: SyncStatus {
 				state: if self.is_syncing { SyncState::Downloading } else { SyncState::Idle },
 				best_seen_block: None,
+				best_finalized_number: 0,
+				queued_block_bytes: 0,
 			},
 			num_peers: self.peers,
 			num_active_peers: 0,
+			chain_freeze_rotations: 0,
 		}
 	}
 
+	fn download_stats(&self) -> DownloadStats<u64> {
+		DownloadStats {
+			queued_blocks: 0,
+			downloading: Vec::new(),
+			first_missing: 1,
+		}
+	}
+
+	fn sync_metrics(&self) -> SyncMetrics {
+		Default::default()
+	}
+
+	fn justification_requests(&self) -> Vec<ExtraRequestInfo<Block>> {
+		vec![ExtraRequestInfo {
+			hash: Default::default(),
+			number: 5,
+			mandatory: true,
+			importing: false,
+			requested_from: Some((1, Duration::from_secs(3))),
+			failed_attempts: vec![(2, 1, Duration::from_secs(30))],
+		}]
+	}
+
 	fn peers(&self) -> Vec<(NodeIndex, Option<PeerId>, NetworkPeerInfo<Block>)> {
 		vec![(1, Some(PublicKey::Ed25519((0 .. 32).collect::<Vec<u8>>()).into()), NetworkPeerInfo {
 			roles: Roles::FULL,
 			protocol_version: 1,
+			features: Features::empty(),
+			client_version: Some("substrate/v1.0.0".into()),
+			latency: None,
 			best_hash: Default::default(),
 			best_number: 1
 		})]
 	}
 }
 
+#[derive(Default)]
+struct Network {
+	reserved_only: AtomicBool,
+}
+
+impl network::ManageNetwork for Network {
+	fn accept_unreserved_peers(&self) {
+		self.reserved_only.store(false, Ordering::SeqCst);
+	}
+
+	fn deny_unreserved_peers(&self) {
+		self.reserved_only.store(true, Ordering::SeqCst);
+	}
+
+	fn is_reserved_only(&self) -> bool {
+		self.reserved_only.load(Ordering::SeqCst)
+	}
+
+	fn remove_reserved_peer(&self, _peer: PeerId) {}
+
+	fn add_reserved_peer(&self, _peer: String) -> ::std::result::Result<(), String> {
+		Ok(())
+	}
+
+	fn add_known_address(&self, _peer: PeerId, _addr: network::Multiaddr) {}
+
+	fn dial_peer(&self, _peer: PeerId) {}
+
+	fn disconnect_peer(&self, _peer: PeerId, _reason: &str) {}
+
+	fn is_mdns_enabled(&self) -> bool {
+		false
+	}
+
+	fn set_mdns_enabled(&self, _enabled: bool) -> ::std::result::Result<(), String> {
+		Ok(())
+	}
+
+	fn peer_slots(&self) -> (u32, u32) {
+		(25, 75)
+	}
+
+	fn set_peer_slots(&self, _in_peers: u32, _out_peers: u32) {}
+
+	fn ip_filter(&self, _list: network::IpFilterList) -> Vec<network::IpNetwork> {
+		Vec::new()
+	}
+
+	fn add_ip_filter(&self, _list: network::IpFilterList, _network: String) -> ::std::result::Result<(), String> {
+		Ok(())
+	}
+
+	fn remove_ip_filter(&self, _list: network::IpFilterList, _network: String) -> ::std::result::Result<bool, String> {
+		Ok(false)
+	}
+
+	fn node_id(&self) -> Option<String> {
+		None
+	}
+}
 
 fn api<T: Into<Option<Status>>>(sync: T) -> System<Block> {
+	api_with_unsafe(sync, false)
+}
+
+fn api_with_unsafe<T: Into<Option<Status>>>(sync: T, unsafe_methods: bool) -> System<Block> {
 	let status = sync.into().unwrap_or_default();
 	let should_have_peers = !status.is_dev;
 	System::new(SystemInfo {
@@ -58,7 +154,7 @@ fn api<T: Into<Option<Status>>>(sync: T) -> System<Block> {
 		impl_version: "0.2.0".into(),
 		chain_name: "testchain".into(),
 		properties: Default::default(),
-	}, Arc::new(status), should_have_peers)
+	}, Arc::new(status), Arc::new(Network::default()), should_have_peers, unsafe_methods)
 }
 
 #[test]
@@ -153,8 +249,68 @@ fn system_peers() {
 			peer_id: "QmS5oyTmdjwBowwAH1D9YQnoe2HyWpVemH8qHiU5RqWPh4".into(),
 			roles: "FULL".into(),
 			protocol_version: 1,
+			client_version: Some("substrate/v1.0.0".into()),
 			best_hash: Default::default(),
 			best_number: 1u64,
 		}]
 	);
 }
+
+#[test]
+fn system_pending_justifications() {
+	assert_matches!(
+		api(None).system_pending_justifications(),
+		Err(error::Error(error::ErrorKind::UnsafeRpcCalled(_), _))
+	);
+
+	assert_eq!(
+		api_with_unsafe(None, true).system_pending_justifications().unwrap(),
+		vec![PendingJustification {
+			hash: Default::default(),
+			number: 5u64,
+			mandatory: true,
+			importing: false,
+			requested_from: Some(JustificationPeerRequest {
+				peer: 1,
+				elapsed_secs: 3,
+			}),
+			failed_attempts: vec![FailedJustificationAttempt {
+				peer: 2,
+				attempts: 1,
+				elapsed_secs: 30,
+			}],
+		}]
+	);
+}
+
+#[test]
+fn system_reserved_only() {
+	assert_eq!(api(None).system_reserved_only().unwrap(), false);
+	assert_matches!(
+		api(None).system_set_reserved_only(true),
+		Err(error::Error(error::ErrorKind::UnsafeRpcCalled(_), _))
+	);
+
+	let api = api_with_unsafe(None, true);
+	api.system_set_reserved_only(true).unwrap();
+	assert_eq!(api.system_reserved_only().unwrap(), true);
+	api.system_set_reserved_only(false).unwrap();
+	assert_eq!(api.system_reserved_only().unwrap(), false);
+}
+
+#[test]
+fn system_dial_and_disconnect_peer() {
+	let peer = format!("/ip4/127.0.0.1/tcp/30333/p2p/{}", PeerId::random().to_base58());
+	assert_matches!(
+		api(None).system_dial_peer(peer.clone()),
+		Err(error::Error(error::ErrorKind::UnsafeRpcCalled(_), _))
+	);
+
+	let api = api_with_unsafe(None, true);
+	api.system_dial_peer(peer).unwrap();
+	assert_matches!(
+		api.system_dial_peer("/ip4/127.0.0.1/tcp/30333".into()),
+		Err(error::Error(error::ErrorKind::InvalidPeerAddress(_), _))
+	);
+	api.system_disconnect_peer(format!("/p2p/{}", PeerId::random().to_base58())).unwrap();
+}
@@ -20,14 +20,17 @@ use std::{self, io::{Read, Write}};
 use futures::Future;
 
 use runtime_primitives::generic::{SignedBlock, BlockId};
-use runtime_primitives::traits::{As, Block, Header};
+use runtime_primitives::traits::{As, Block as BlockT, Header, Hash as HashT, HashFor, NumberFor};
+use primitives::{H256, Blake2Hasher, storage::{StorageKey, well_known_keys}};
 use consensus_common::import_queue::{ImportQueue, IncomingBlock, Link};
 use network::message;
 
+use client::{Client, CallExecutor, backend};
 use consensus_common::BlockOrigin;
 use components::{self, Components, ServiceFactory, FactoryFullConfiguration, FactoryBlockNumber, RuntimeGenesis};
 use new_client;
 use codec::{Decode, Encode};
+use config::PruningMode;
 use error;
 use chain_spec::ChainSpec;
 
@@ -103,7 +106,7 @@ pub fn import_blocks<F, E, R>(
 	where F: ServiceFactory, E: Future<Item=(),Error=()> + Send + 'static, R: Read,
 {
 	struct DummyLink;
-	impl<B: Block> Link<B> for DummyLink { }
+	impl<B: BlockT> Link<B> for DummyLink { }
 
 	let client = new_client::<F>(&config)?;
 	// FIXME #1134 this shouldn't need a mutable config.
@@ -142,6 +145,7 @@ pub fn import_blocks<F, E, R>(
 					body: block.body,
 					justification: block.justification,
 					origin: None,
+					size: 0,
 				}
 			]);
 		} else {
@@ -178,9 +182,255 @@ pub fn revert_chain<F>(
 	Ok(())
 }
 
+/// Verify the consistency of the stored chain.
+///
+/// Walks the best chain from genesis, or over the `last` given number of blocks, checking
+/// that headers link up by hash, that bodies match their extrinsics root, that
+/// justifications can be read and that state is available wherever the configured pruning
+/// mode keeps it. With `repair` the unfinalized blocks from the first inconsistent one
+/// onwards are reverted. Fails if any inconsistency remains or the check is interrupted
+/// before reaching the best block.
+pub fn verify_chain<F, E>(
+	config: FactoryFullConfiguration<F>,
+	exit: E,
+	last: Option<FactoryBlockNumber<F>>,
+	repair: bool,
+) -> error::Result<()>
+	where F: ServiceFactory, E: Future<Item=(),Error=()> + Send + 'static,
+{
+	let client = new_client::<F>(&config)?;
+	let info = client.info()?.chain;
+
+	// the state of finalized blocks is pruned once they are `max_blocks` behind.
+	let state_kept_from = match config.pruning {
+		PruningMode::Constrained(ref constraints) => {
+			let keep: FactoryBlockNumber<F> = As::sa(constraints.max_blocks.unwrap_or(0) as u64);
+			if info.finalized_number > keep { info.finalized_number - keep } else { As::sa(0) }
+		},
+		PruningMode::ArchiveAll | PruningMode::ArchiveCanonical => As::sa(0),
+	};
+
+	let from = match last {
+		Some(n) if n > As::sa(0) && n <= info.best_number => info.best_number - n + As::sa(1),
+		_ => As::sa(0),
+	};
+
+	let (exit_send, exit_recv) = std::sync::mpsc::channel();
+	::std::thread::spawn(move || {
+		let _ = exit.wait();
+		let _ = exit_send.send(());
+	});
+	info!("Verifying blocks from #{} to #{}", from, info.best_number);
+
+	let verification = verify_blocks(&*client, from, info.best_number, state_kept_from, || exit_recv.try_recv().is_ok())?;
+
+	let first_invalid = match (verification.first_invalid, verification.interrupted_at) {
+		(Some(first_invalid), _) => first_invalid,
+		(None, Some(interrupted_at)) =>
+			return Err(format!("Verification interrupted at #{}, no inconsistencies found before", interrupted_at).into()),
+		(None, None) => {
+			info!("No inconsistencies found up to #{}", info.best_number);
+			return Ok(());
+		},
+	};
+
+	if repair {
+		if first_invalid > info.finalized_number {
+			let reverted = client.revert(info.best_number - first_invalid + As::sa(1))?;
+			let best = client.info()?.chain;
+			info!("Reverted {} blocks. Best: #{} ({})", reverted, best.best_number, best.best_hash);
+			return Ok(());
+		}
+		warn!("Block #{} is finalized (#{}) and can't be reverted", first_invalid, info.finalized_number);
+	}
+
+	match verification.interrupted_at {
+		Some(interrupted_at) => Err(format!(
+			"Found {} inconsistent blocks, starting at #{}, before being interrupted at #{}",
+			verification.invalid_count, first_invalid, interrupted_at,
+		).into()),
+		None => Err(format!(
+			"Found {} inconsistent blocks, starting at #{}", verification.invalid_count, first_invalid,
+		).into()),
+	}
+}
+
+/// Outcome of checking a range of stored blocks.
+#[derive(Debug, PartialEq)]
+struct Verification<N> {
+	/// First inconsistent block, if any.
+	first_invalid: Option<N>,
+	/// Number of inconsistent blocks.
+	invalid_count: u64,
+	/// Block the check stopped at before reaching the end of the range, if it was interrupted.
+	interrupted_at: Option<N>,
+}
+
+/// Check the stored blocks `from` to `to` of the best chain, stopping early once
+/// `interrupted` returns true. The state is only checked from `state_kept_from` onwards.
+fn verify_blocks<B, E, Block, RA, I>(
+	client: &Client<B, E, Block, RA>,
+	from: NumberFor<Block>,
+	to: NumberFor<Block>,
+	state_kept_from: NumberFor<Block>,
+	interrupted: I,
+) -> error::Result<Verification<NumberFor<Block>>>
+	where
+	B: backend::Backend<Block, Blake2Hasher>,
+	E: CallExecutor<Block, Blake2Hasher>,
+	Block: BlockT<Hash=H256>,
+	I: Fn() -> bool,
+{
+	let mut number = from;
+	let mut parent_hash = if number > As::sa(0) {
+		client.block_hash(number - As::sa(1))?
+	} else {
+		None
+	};
+
+	let mut verification = Verification {
+		first_invalid: None,
+		invalid_count: 0,
+		interrupted_at: None,
+	};
+	loop {
+		if interrupted() {
+			verification.interrupted_at = Some(number);
+			break;
+		}
+
+		let mut problems = Vec::new();
+		let hash = client.block_hash(number)?;
+		match hash {
+			None => problems.push("missing from the block index".to_string()),
+			Some(hash) => {
+				let id = BlockId::Hash(hash);
+				match client.header(&id) {
+					Ok(Some(header)) => {
+						if header.hash() != hash {
+							problems.push(format!("header hashes to {}", header.hash()));
+						}
+						if let Some(parent_hash) = parent_hash {
+							if *header.parent_hash() != parent_hash {
+								problems.push(format!(
+									"parent hash {} does not match the previous block {}",
+									header.parent_hash(),
+									parent_hash,
+								));
+							}
+						}
+						match client.body(&id) {
+							Ok(Some(body)) => {
+								let root = HashFor::<Block>::ordered_trie_root(body.iter().map(Encode::encode));
+								if root != *header.extrinsics_root() {
+									problems.push(format!("body does not match extrinsics root {}", header.extrinsics_root()));
+								}
+							},
+							Ok(None) => problems.push("body missing".to_string()),
+							Err(e) => problems.push(format!("body unreadable: {}", e)),
+						}
+					},
+					Ok(None) => problems.push("header missing".to_string()),
+					Err(e) => problems.push(format!("header unreadable: {}", e)),
+				}
+				if let Err(e) = client.justification(&id) {
+					problems.push(format!("justification unreadable: {}", e));
+				}
+				if number >= state_kept_from {
+					match client.storage(&id, &StorageKey(well_known_keys::CODE.to_vec())) {
+						Ok(Some(_)) => {},
+						Ok(None) => problems.push("state has no runtime code".to_string()),
+						Err(e) => problems.push(format!("state unavailable: {}", e)),
+					}
+				}
+			},
+		}
+
+		if !problems.is_empty() {
+			warn!("Block #{} ({:?}) is inconsistent: {}", number, hash, problems.join(", "));
+			verification.first_invalid = verification.first_invalid.or(Some(number));
+			verification.invalid_count += 1;
+		}
+		if number.as_() % 10000 == 0 {
+			info!("#{}", number);
+		}
+		if number >= to {
+			break;
+		}
+		parent_hash = hash;
+		number += As::sa(1);
+	}
+
+	Ok(verification)
+}
+
 /// Build a chain spec json
 pub fn build_spec<G>(spec: ChainSpec<G>, raw: bool) -> error::Result<String>
 	where G: RuntimeGenesis,
 {
 	Ok(spec.to_json(raw)?)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+	use substrate_test_client::{self, TestClient};
+
+	fn client_with_blocks(count: u64) -> substrate_test_client::client::Client<
+		substrate_test_client::Backend,
+		substrate_test_client::Executor,
+		substrate_test_client::runtime::Block,
+		substrate_test_client::runtime::RuntimeApi,
+	> {
+		let client = substrate_test_client::new();
+		for _ in 0..count {
+			let block = client.new_block().unwrap().bake().unwrap();
+			client.import(BlockOrigin::Own, block).unwrap();
+		}
+		client
+	}
+
+	#[test]
+	fn consistent_chain_is_verified() {
+		let client = client_with_blocks(5);
+
+		let verification = verify_blocks(&client, 0, 5, 0, || false).unwrap();
+
+		assert_eq!(verification, Verification {
+			first_invalid: None,
+			invalid_count: 0,
+			interrupted_at: None,
+		});
+	}
+
+	#[test]
+	fn interruption_is_reported() {
+		let client = client_with_blocks(5);
+		let checked = Cell::new(0);
+
+		let verification = verify_blocks(&client, 1, 5, 0, || {
+			checked.set(checked.get() + 1);
+			checked.get() > 3
+		}).unwrap();
+
+		assert_eq!(verification, Verification {
+			first_invalid: None,
+			invalid_count: 0,
+			interrupted_at: Some(4),
+		});
+	}
+
+	#[test]
+	fn blocks_missing_from_the_index_are_inconsistent() {
+		let client = client_with_blocks(3);
+
+		let verification = verify_blocks(&client, 2, 5, 0, || false).unwrap();
+
+		assert_eq!(verification, Verification {
+			first_invalid: Some(4),
+			invalid_count: 2,
+			interrupted_at: None,
+		});
+	}
+}
@@ -90,6 +90,10 @@ struct ChainSpecFile {
 	pub protocol_id: Option<String>,
 	pub consensus_engine: Option<String>,
 	pub properties: Option<Properties>,
+	/// Identifies a hard fork of the chain sharing its genesis block. Peers
+	/// on a different fork are disconnected during the handshake.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub fork_id: Option<String>,
 }
 
 /// Arbitrary properties defined in chain spec as a JSON object
@@ -135,6 +139,10 @@ impl<G: RuntimeGenesis> ChainSpec<G> {
 		self.spec.consensus_engine.as_ref().map(String::as_str)
 	}
 
+	pub fn fork_id(&self) -> Option<&str> {
+		self.spec.fork_id.as_ref().map(String::as_str)
+	}
+
 	pub fn properties(&self) -> Properties {
 		// Return an empty JSON object if 'properties' not defined in config
 		self.spec.properties.as_ref().unwrap_or(&json::map::Map::new()).clone()
@@ -183,6 +191,7 @@ impl<G: RuntimeGenesis> ChainSpec<G> {
 			protocol_id: protocol_id.map(str::to_owned),
 			consensus_engine: consensus_engine.map(str::to_owned),
 			properties,
+			fork_id: None,
 		};
 		ChainSpec {
 			spec,
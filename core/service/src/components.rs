@@ -26,7 +26,8 @@ use {error, Service, maybe_start_server};
 use consensus_common::import_queue::ImportQueue;
 use network::{self, OnDemand};
 use substrate_executor::{NativeExecutor, NativeExecutionDispatch};
-use transaction_pool::txpool::{self, Options as TransactionPoolOptions, Pool as TransactionPool};
+use transaction_pool::txpool::Options as TransactionPoolOptions;
+use txpool_api::TransactionPool;
 use runtime_primitives::{
 	BuildStorage, traits::{Block as BlockT, Header as HeaderT, ProvideRuntimeApi}, generic::BlockId
 };
@@ -108,13 +109,13 @@ pub type ComponentClient<C> = Client<
 pub type ComponentBlock<C> = <<C as Components>::Factory as ServiceFactory>::Block;
 
 /// Extrinsic hash type for `Components`
-pub type ComponentExHash<C> = <<C as Components>::TransactionPoolApi as txpool::ChainApi>::Hash;
+pub type ComponentExHash<C> = <<C as Components>::TransactionPool as TransactionPool>::Hash;
 
 /// Extrinsic type.
 pub type ComponentExtrinsic<C> = <ComponentBlock<C> as BlockT>::Extrinsic;
 
-/// Extrinsic pool API type for `Components`.
-pub type PoolApi<C> = <C as Components>::TransactionPoolApi;
+/// Extrinsic pool type for `Components`.
+pub type ComponentTransactionPool<C> = <C as Components>::TransactionPool;
 
 /// A set of traits for the runtime genesis config.
 pub trait RuntimeGenesis: Serialize + DeserializeOwned + BuildStorage {}
@@ -127,12 +128,16 @@ pub trait StartRPC<C: Components> {
 	fn start_rpc(
 		client: Arc<ComponentClient<C>>,
 		network: Arc<network::SyncProvider<ComponentBlock<C>>>,
+		network_manager: Arc<network::ManageNetwork>,
 		should_have_peers: bool,
 		system_info: SystemInfo,
 		rpc_http: Option<SocketAddr>,
 		rpc_ws: Option<SocketAddr>,
 		task_executor: TaskExecutor,
-		transaction_pool: Arc<TransactionPool<C::TransactionPoolApi>>,
+		transaction_pool: Arc<C::TransactionPool>,
+		import_queue: Arc<ImportQueue<ComponentBlock<C>>>,
+		finality_voter: rpc::apis::finality::SharedVoterControl,
+		unsafe_methods: bool,
 	) -> error::Result<Self::ServersHandle>;
 }
 
@@ -145,12 +150,16 @@ impl<C: Components> StartRPC<Self> for C where
 	fn start_rpc(
 		client: Arc<ComponentClient<C>>,
 		network: Arc<network::SyncProvider<ComponentBlock<C>>>,
+		network_manager: Arc<network::ManageNetwork>,
 		should_have_peers: bool,
 		rpc_system_info: SystemInfo,
 		rpc_http: Option<SocketAddr>,
 		rpc_ws: Option<SocketAddr>,
 		task_executor: TaskExecutor,
-		transaction_pool: Arc<TransactionPool<C::TransactionPoolApi>>,
+		transaction_pool: Arc<C::TransactionPool>,
+		import_queue: Arc<ImportQueue<ComponentBlock<C>>>,
+		finality_voter: rpc::apis::finality::SharedVoterControl,
+		unsafe_methods: bool,
 	) -> error::Result<Self::ServersHandle> {
 		let handler = || {
 			let client = client.clone();
@@ -158,16 +167,18 @@ impl<C: Components> StartRPC<Self> for C where
 			let chain = rpc::apis::chain::Chain::new(client.clone(), subscriptions.clone());
 			let state = rpc::apis::state::State::new(client.clone(), subscriptions.clone());
 			let author = rpc::apis::author::Author::new(
-				client.clone(), transaction_pool.clone(), subscriptions
+				client.clone(), transaction_pool.clone(), subscriptions, import_queue.clone(), unsafe_methods
 			);
 			let system = rpc::apis::system::System::new(
-				rpc_system_info.clone(), network.clone(), should_have_peers
+				rpc_system_info.clone(), network.clone(), network_manager.clone(), should_have_peers, unsafe_methods
 			);
-			rpc::rpc_handler::<ComponentBlock<C>, ComponentExHash<C>, _, _, _, _>(
+			let finality = rpc::apis::finality::Finality::new(finality_voter.clone(), unsafe_methods);
+			rpc::rpc_handler::<ComponentBlock<C>, ComponentExHash<C>, _, _, _, _, _>(
 				state,
 				chain,
 				author,
 				system,
+				finality,
 			)
 		};
 
@@ -183,21 +194,21 @@ pub trait MaintainTransactionPool<C: Components> {
 	fn on_block_imported(
 		id: &BlockId<ComponentBlock<C>>,
 		client: &ComponentClient<C>,
-		transaction_pool: &TransactionPool<C::TransactionPoolApi>,
+		transaction_pool: &C::TransactionPool,
 	) -> error::Result<()>;
 }
 
-fn on_block_imported<Api, Backend, Block, Executor, PoolApi>(
+fn on_block_imported<Api, Backend, Block, Executor, Pool>(
 	id: &BlockId<Block>,
 	client: &Client<Backend, Executor, Block, Api>,
-	transaction_pool: &TransactionPool<PoolApi>,
+	transaction_pool: &Pool,
 ) -> error::Result<()> where
 	Block: BlockT<Hash = <Blake2Hasher as ::primitives::Hasher>::Out>,
 	Backend: client::backend::Backend<Block, Blake2Hasher>,
 	Client<Backend, Executor, Block, Api>: ProvideRuntimeApi,
 	<Client<Backend, Executor, Block, Api> as ProvideRuntimeApi>::Api: TaggedTransactionQueue<Block>,
 	Executor: client::CallExecutor<Block, Blake2Hasher>,
-	PoolApi: txpool::ChainApi<Hash = Block::Hash, Block = Block>,
+	Pool: TransactionPool<Block = Block>,
 {
 	// Avoid calling into runtime if there is nothing to prune from the pool anyway.
 	if transaction_pool.status().is_empty() {
@@ -207,7 +218,7 @@ fn on_block_imported<Api, Backend, Block, Executor, PoolApi>(
 	if let Some(block) = client.block(id)? {
 		let parent_id = BlockId::hash(*block.block.header().parent_hash());
 		let extrinsics = block.block.extrinsics();
-		transaction_pool.prune(id, &parent_id, extrinsics).map_err(|e| format!("{:?}", e))?;
+		transaction_pool.maintain(id, &parent_id, extrinsics).map_err(|e| format!("{:?}", e))?;
 	}
 
 	Ok(())
@@ -220,7 +231,7 @@ impl<C: Components> MaintainTransactionPool<Self> for C where
 	fn on_block_imported(
 		id: &BlockId<ComponentBlock<C>>,
 		client: &ComponentClient<C>,
-		transaction_pool: &TransactionPool<C::TransactionPoolApi>,
+		transaction_pool: &C::TransactionPool,
 	) -> error::Result<()> {
 		on_block_imported(id, client, transaction_pool)
 	}
@@ -249,10 +260,10 @@ pub trait ServiceFactory: 'static + Sized {
 	type NetworkProtocol: network::specialization::NetworkSpecialization<Self::Block>;
 	/// Chain runtime.
 	type RuntimeDispatch: NativeExecutionDispatch + Send + Sync + 'static;
-	/// Extrinsic pool backend type for the full client.
-	type FullTransactionPoolApi: txpool::ChainApi<Hash = <Self::Block as BlockT>::Hash, Block = Self::Block> + Send + 'static;
-	/// Extrinsic pool backend type for the light client.
-	type LightTransactionPoolApi: txpool::ChainApi<Hash = <Self::Block as BlockT>::Hash, Block = Self::Block> + 'static;
+	/// Extrinsic pool type for the full client.
+	type FullTransactionPool: TransactionPool<Hash = <Self::Block as BlockT>::Hash, Block = Self::Block> + 'static;
+	/// Extrinsic pool type for the light client.
+	type LightTransactionPool: TransactionPool<Hash = <Self::Block as BlockT>::Hash, Block = Self::Block> + 'static;
 	/// Genesis configuration for the runtime.
 	type Genesis: RuntimeGenesis;
 	/// Other configuration for service members.
@@ -266,13 +277,12 @@ pub trait ServiceFactory: 'static + Sized {
 	/// ImportQueue for light clients
 	type LightImportQueue: consensus_common::import_queue::ImportQueue<Self::Block> + 'static;
 
-	//TODO: replace these with a constructor trait. that TransactionPool implements. (#1242)
 	/// Extrinsic pool constructor for the full client.
 	fn build_full_transaction_pool(config: TransactionPoolOptions, client: Arc<FullClient<Self>>)
-		-> Result<TransactionPool<Self::FullTransactionPoolApi>, error::Error>;
+		-> Result<Self::FullTransactionPool, error::Error>;
 	/// Extrinsic pool constructor for the light client.
 	fn build_light_transaction_pool(config: TransactionPoolOptions, client: Arc<LightClient<Self>>)
-		-> Result<TransactionPool<Self::LightTransactionPoolApi>, error::Error>;
+		-> Result<Self::LightTransactionPool, error::Error>;
 
 	/// Build network protocol.
 	fn build_network_protocol(config: &FactoryFullConfiguration<Self>)
@@ -328,11 +338,10 @@ pub trait Components: Sized + 'static {
 	type RuntimeApi: Send + Sync;
 	/// A type that can start the RPC.
 	type RPC: StartRPC<Self>;
-	// TODO: Traitify transaction pool and allow people to implement their own. (#1242)
 	/// A type that can maintain transaction pool.
-	type TransactionPool: MaintainTransactionPool<Self>;
+	type TransactionPoolMaintainer: MaintainTransactionPool<Self>;
 	/// Extrinsic pool type.
-	type TransactionPoolApi: 'static + txpool::ChainApi<
+	type TransactionPool: 'static + txpool_api::TransactionPool<
 		Hash = <FactoryBlock<Self::Factory> as BlockT>::Hash,
 		Block = FactoryBlock<Self::Factory>
 	>;
@@ -354,7 +363,7 @@ pub trait Components: Sized + 'static {
 
 	/// Create extrinsic pool.
 	fn build_transaction_pool(config: TransactionPoolOptions, client: Arc<ComponentClient<Self>>)
-		-> Result<TransactionPool<Self::TransactionPoolApi>, error::Error>;
+		-> Result<Self::TransactionPool, error::Error>;
 
 	/// instance of import queue for clients
 	fn build_import_queue(
@@ -401,11 +410,11 @@ impl<Factory: ServiceFactory> Components for FullComponents<Factory> {
 	type Factory = Factory;
 	type Executor = FullExecutor<Factory>;
 	type Backend = FullBackend<Factory>;
-	type TransactionPoolApi = <Factory as ServiceFactory>::FullTransactionPoolApi;
+	type TransactionPool = <Factory as ServiceFactory>::FullTransactionPool;
 	type ImportQueue = Factory::FullImportQueue;
 	type RuntimeApi = Factory::RuntimeApi;
 	type RPC = Factory::FullService;
-	type TransactionPool = Factory::FullService;
+	type TransactionPoolMaintainer = Factory::FullService;
 
 	fn build_client(
 		config: &FactoryFullConfiguration<Factory>,
@@ -418,7 +427,7 @@ impl<Factory: ServiceFactory> Components for FullComponents<Factory> {
 	{
 		let db_settings = client_db::DatabaseSettings {
 			cache_size: config.database_cache_size.map(|u| u as usize),
-			path: config.database_path.as_str().into(),
+			path: database_path(config),
 			pruning: config.pruning.clone(),
 		};
 		Ok((Arc::new(client_db::new_client(
@@ -431,7 +440,7 @@ impl<Factory: ServiceFactory> Components for FullComponents<Factory> {
 	}
 
 	fn build_transaction_pool(config: TransactionPoolOptions, client: Arc<ComponentClient<Self>>)
-		-> Result<TransactionPool<Self::TransactionPoolApi>, error::Error>
+		-> Result<Self::TransactionPool, error::Error>
 	{
 		Factory::build_full_transaction_pool(config, client)
 	}
@@ -476,11 +485,11 @@ impl<Factory: ServiceFactory> Components for LightComponents<Factory> {
 	type Factory = Factory;
 	type Executor = LightExecutor<Factory>;
 	type Backend = LightBackend<Factory>;
-	type TransactionPoolApi = <Factory as ServiceFactory>::LightTransactionPoolApi;
+	type TransactionPool = <Factory as ServiceFactory>::LightTransactionPool;
 	type ImportQueue = <Factory as ServiceFactory>::LightImportQueue;
 	type RuntimeApi = Factory::RuntimeApi;
 	type RPC = Factory::LightService;
-	type TransactionPool = Factory::LightService;
+	type TransactionPoolMaintainer = Factory::LightService;
 
 	fn build_client(
 		config: &FactoryFullConfiguration<Factory>,
@@ -494,7 +503,7 @@ impl<Factory: ServiceFactory> Components for LightComponents<Factory> {
 	{
 		let db_settings = client_db::DatabaseSettings {
 			cache_size: None,
-			path: config.database_path.as_str().into(),
+			path: database_path(config),
 			pruning: config.pruning.clone(),
 		};
 		let db_storage = client_db::light::LightStorage::new(db_settings)?;
@@ -507,7 +516,7 @@ impl<Factory: ServiceFactory> Components for LightComponents<Factory> {
 	}
 
 	fn build_transaction_pool(config: TransactionPoolOptions, client: Arc<ComponentClient<Self>>)
-		-> Result<TransactionPool<Self::TransactionPoolApi>, error::Error>
+		-> Result<Self::TransactionPool, error::Error>
 	{
 		Factory::build_light_transaction_pool(config, client)
 	}
@@ -520,11 +529,21 @@ impl<Factory: ServiceFactory> Components for LightComponents<Factory> {
 	}
 }
 
+/// Database location for the given configuration, `None` if it should be kept in memory.
+fn database_path<F: ServiceFactory>(config: &FactoryFullConfiguration<F>) -> Option<::std::path::PathBuf> {
+	if config.in_memory {
+		None
+	} else {
+		Some(config.database_path.as_str().into())
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
 	use codec::Encode;
 	use consensus_common::BlockOrigin;
+	use transaction_pool::txpool;
 	use substrate_test_client::{
 		self,
 		TestClient,
@@ -535,7 +554,7 @@ mod tests {
 	#[test]
 	fn should_remove_transactions_from_the_pool() {
 		let client = Arc::new(substrate_test_client::new());
-		let pool = TransactionPool::new(Default::default(), ::transaction_pool::ChainApi::new(client.clone()));
+		let pool = txpool::Pool::new(Default::default(), ::transaction_pool::ChainApi::new(client.clone()));
 		let transaction = {
 			let transfer = Transfer {
 				amount: 5,
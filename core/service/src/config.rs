@@ -17,11 +17,15 @@
 //! Service configuration.
 
 use std::net::SocketAddr;
+use std::time::Duration;
 use transaction_pool;
 use chain_spec::ChainSpec;
 pub use client::ExecutionStrategy;
 pub use client_db::PruningMode;
-pub use network::config::{NetworkConfiguration, Roles};
+pub use network::config::{
+	NetworkConfiguration, Roles, LightAnnouncement, DownloadWindow, RequestQuota, GossipQuota,
+	TransactionQuota,
+};
 use runtime_primitives::BuildStorage;
 use serde::{Serialize, de::DeserializeOwned};
 use target_info::Target;
@@ -47,6 +51,9 @@ pub struct Configuration<C, G: Serialize + DeserializeOwned + BuildStorage> {
 	pub database_path: String,
 	/// Cache Size for internal database in MiB
 	pub database_cache_size: Option<u32>,
+	/// Keep the database and keystore purely in memory. `database_path` and
+	/// `keystore_path` are ignored and nothing survives the service.
+	pub in_memory: bool,
 	/// Pruning settings.
 	pub pruning: PruningMode,
 	/// Additional key seeds.
@@ -65,8 +72,29 @@ pub struct Configuration<C, G: Serialize + DeserializeOwned + BuildStorage> {
 	pub rpc_http: Option<SocketAddr>,
 	/// RPC over Websockets binding address. `None` if disabled.
 	pub rpc_ws: Option<SocketAddr>,
+	/// Enable RPC methods that are unsafe to expose publicly, like block submission.
+	pub rpc_unsafe: bool,
 	/// Telemetry service URL. `None` if disabled.
 	pub telemetry_url: Option<String>,
+	/// Number of threads verifying blocks ahead of their import. Blocks are verified on the
+	/// import thread if below 2.
+	pub verification_workers: usize,
+	/// Replace some of the peers if the chain doesn't progress for this long. `None` if disabled.
+	pub chain_freeze_timeout: Option<Duration>,
+	/// Never download blocks, only fetch the missing justifications of blocks we already have.
+	pub justifications_only: bool,
+	/// Which imported blocks are announced to light peers.
+	pub light_announcement: LightAnnouncement,
+	/// Download each block range from two peers and compare the blocks before importing them.
+	pub cross_check_blocks: bool,
+	/// Number of block ranges that may be downloaded ahead of the imported chain.
+	pub download_window: DownloadWindow,
+	/// Block requests served to a single peer within a time window.
+	pub block_request_quota: RequestQuota,
+	/// Gossip and announcement messages handled from a single peer within a time window.
+	pub gossip_quota: GossipQuota,
+	/// Extrinsics sent to a single peer within a time window.
+	pub transaction_quota: TransactionQuota,
 }
 
 impl<C: Default, G: Serialize + DeserializeOwned + BuildStorage> Configuration<C, G> {
@@ -84,6 +112,7 @@ impl<C: Default, G: Serialize + DeserializeOwned + BuildStorage> Configuration<C
 			keystore_path: Default::default(),
 			database_path: Default::default(),
 			database_cache_size: Default::default(),
+			in_memory: false,
 			keys: Default::default(),
 			custom: Default::default(),
 			pruning: PruningMode::default(),
@@ -91,7 +120,17 @@ impl<C: Default, G: Serialize + DeserializeOwned + BuildStorage> Configuration<C
 			api_execution_strategy: ExecutionStrategy::Both,
 			rpc_http: None,
 			rpc_ws: None,
+			rpc_unsafe: false,
 			telemetry_url: None,
+			verification_workers: 0,
+			chain_freeze_timeout: None,
+			justifications_only: false,
+			light_announcement: LightAnnouncement::All,
+			cross_check_blocks: false,
+			download_window: Default::default(),
+			block_request_quota: Default::default(),
+			gossip_quota: Default::default(),
+			transaction_quota: Default::default(),
 		};
 		configuration.network.boot_nodes = configuration.chain_spec.boot_nodes().to_vec();
 		configuration.telemetry_url = configuration.chain_spec.telemetry_url().map(str::to_owned);
@@ -34,6 +34,7 @@ extern crate substrate_client as client;
 extern crate substrate_client_db as client_db;
 extern crate parity_codec as codec;
 extern crate substrate_transaction_pool as transaction_pool;
+extern crate substrate_transaction_pool_api as txpool_api;
 extern crate substrate_rpc_servers as rpc;
 extern crate target_info;
 extern crate tokio;
@@ -61,6 +62,7 @@ pub mod chain_ops;
 use std::io;
 use std::net::SocketAddr;
 use std::collections::HashMap;
+use std::thread;
 #[doc(hidden)]
 pub use std::{ops::Deref, result::Result, sync::Arc};
 use futures::prelude::*;
@@ -83,15 +85,17 @@ pub use transaction_pool::txpool::{
 pub use client::{ExecutionStrategy, FinalityNotifications};
 
 pub use components::{ServiceFactory, FullBackend, FullExecutor, LightBackend,
-	LightExecutor, Components, PoolApi, ComponentClient,
+	LightExecutor, Components, ComponentTransactionPool, ComponentClient,
 	ComponentBlock, FullClient, LightClient, FullComponents, LightComponents,
 	CodeExecutor, NetworkService, FactoryChainSpec, FactoryBlock,
 	FactoryFullConfiguration, RuntimeGenesis, FactoryGenesis,
 	ComponentExHash, ComponentExtrinsic, FactoryExtrinsic
 };
 use components::{StartRPC, MaintainTransactionPool};
+use txpool_api::InPoolTransaction;
 #[doc(hidden)]
 pub use network::OnDemand;
+pub use rpc::apis::finality::VoterControl as FinalityVoterControl;
 
 const DEFAULT_PROTOCOL_ID: &'static str = "sup";
 
@@ -99,13 +103,14 @@ const DEFAULT_PROTOCOL_ID: &'static str = "sup";
 pub struct Service<Components: components::Components> {
 	client: Arc<ComponentClient<Components>>,
 	network: Option<Arc<components::NetworkService<Components::Factory>>>,
-	transaction_pool: Arc<TransactionPool<Components::TransactionPoolApi>>,
+	transaction_pool: Arc<Components::TransactionPool>,
 	keystore: Keystore,
 	exit: ::exit_future::Exit,
 	signal: Option<Signal>,
 	/// Configuration of this Service
 	pub config: FactoryFullConfiguration<Components::Factory>,
 	_rpc: Box<::std::any::Any + Send + Sync>,
+	finality_voter: rpc::apis::finality::SharedVoterControl,
 	_telemetry: Option<Arc<tel::Telemetry>>,
 }
 
@@ -134,7 +139,11 @@ impl<Components: components::Components> Service<Components> {
 		// Create client
 		let executor = NativeExecutor::new();
 
-		let mut keystore = Keystore::open(config.keystore_path.as_str().into())?;
+		let mut keystore = if config.in_memory {
+			Keystore::new_in_memory()
+		} else {
+			Keystore::open(config.keystore_path.as_str().into())?
+		};
 
 		// This is meant to be for testing only
 		// FIXME #1063 remove this
@@ -172,7 +181,20 @@ impl<Components: components::Components> Service<Components> {
 		 });
 
 		let network_params = network::config::Params {
-			config: network::config::ProtocolConfig { roles: config.roles },
+			config: network::config::ProtocolConfig {
+				roles: config.roles,
+				fork_id: config.chain_spec.fork_id().map(|id| id.as_bytes().to_vec()),
+				client_version: Some(config.client_id()),
+				chain_freeze_timeout: config.chain_freeze_timeout,
+				justifications_only: config.justifications_only,
+				light_announcement: config.light_announcement,
+				cross_check_blocks: config.cross_check_blocks,
+				download_window: config.download_window.clone(),
+				block_request_quota: config.block_request_quota.clone(),
+				gossip_quota: config.gossip_quota.clone(),
+				transaction_quota: config.transaction_quota.clone(),
+				..Default::default()
+			},
 			network_config: config.network.clone(),
 			chain: client.clone(),
 			on_demand: on_demand.as_ref().map(|d| d.clone() as _),
@@ -195,7 +217,7 @@ impl<Components: components::Components> Service<Components> {
 		let network = network::Service::new(
 			network_params,
 			protocol_id,
-			import_queue
+			import_queue.clone()
 		)?;
 		on_demand.map(|on_demand| on_demand.set_service_link(Arc::downgrade(&network)));
 
@@ -208,10 +230,16 @@ impl<Components: components::Components> Service<Components> {
 			let events = client.import_notification_stream()
 				.for_each(move |notification| {
 					if let Some(network) = network.upgrade() {
+						if !notification.retracted.is_empty() {
+							network.on_chain_reorg(&notification.retracted, &notification.header);
+						}
+						if notification.origin == consensus_common::BlockOrigin::Own {
+							network.on_block_authored(&notification.header);
+						}
 						network.on_block_imported(notification.hash, &notification.header);
 					}
 					if let (Some(txpool), Some(client)) = (txpool.upgrade(), wclient.upgrade()) {
-						Components::TransactionPool::on_block_imported(
+						Components::TransactionPoolMaintainer::on_block_imported(
 							&BlockId::hash(notification.hash),
 							&*client,
 							&*txpool,
@@ -294,9 +322,12 @@ impl<Components: components::Components> Service<Components> {
 			impl_version: config.impl_version.into(),
 			properties: config.chain_spec.properties(),
 		};
+		// the finality voter is set once started.
+		let finality_voter: rpc::apis::finality::SharedVoterControl = Default::default();
 		let rpc = Components::RPC::start_rpc(
-			client.clone(), network.clone(), has_bootnodes, system_info, config.rpc_http,
-			config.rpc_ws, task_executor.clone(), transaction_pool.clone(),
+			client.clone(), network.clone(), network.clone(), has_bootnodes, system_info, config.rpc_http,
+			config.rpc_ws, task_executor.clone(), transaction_pool.clone(), import_queue,
+			finality_voter.clone(), config.rpc_unsafe,
 		)?;
 
 		// Telemetry
@@ -332,6 +363,7 @@ impl<Components: components::Components> Service<Components> {
 			config,
 			exit,
 			_rpc: Box::new(rpc),
+			finality_voter,
 			_telemetry: telemetry,
 		})
 	}
@@ -366,7 +398,7 @@ impl<Components> Service<Components> where Components: components::Components {
 	}
 
 	/// Get shared extrinsic pool instance.
-	pub fn transaction_pool(&self) -> Arc<TransactionPool<Components::TransactionPoolApi>> {
+	pub fn transaction_pool(&self) -> Arc<Components::TransactionPool> {
 		self.transaction_pool.clone()
 	}
 
@@ -379,6 +411,11 @@ impl<Components> Service<Components> where Components: components::Components {
 	pub fn on_exit(&self) -> ::exit_future::Exit {
 		self.exit.clone()
 	}
+
+	/// Set the finality voter that can be paused and resumed over RPC.
+	pub fn set_finality_voter(&self, voter: Arc<FinalityVoterControl>) {
+		*self.finality_voter.write() = Some(voter);
+	}
 }
 
 
@@ -415,7 +452,7 @@ fn maybe_start_server<T, F>(address: Option<SocketAddr>, start: F) -> Result<Opt
 /// Transaction pool adapter.
 pub struct TransactionPoolAdapter<C: Components> {
 	imports_external_transactions: bool,
-	pool: Arc<TransactionPool<C::TransactionPoolApi>>,
+	pool: Arc<C::TransactionPool>,
 	client: Arc<ComponentClient<C>>,
 }
 
@@ -436,8 +473,8 @@ impl<C: Components> network::TransactionPool<ComponentExHash<C>, ComponentBlock<
 	fn transactions(&self) -> Vec<(ComponentExHash<C>, ComponentExtrinsic<C>)> {
 		self.pool.ready()
 			.map(|t| {
-				let hash = t.hash.clone();
-				let ex: ComponentExtrinsic<C> = t.data.clone();
+				let hash = t.hash().clone();
+				let ex: ComponentExtrinsic<C> = t.data().clone();
 				(hash, ex)
 			})
 			.collect()
@@ -478,6 +515,24 @@ impl<C: Components> network::TransactionPool<ComponentExHash<C>, ComponentBlock<
 	fn on_broadcasted(&self, propagations: HashMap<ComponentExHash<C>, Vec<String>>) {
 		self.pool.on_broadcasted(propagations)
 	}
+
+	fn on_major_sync_finished(&self, revalidated: Box<Fn() + Send>) {
+		let best_block_id = match self.best_block_id() {
+			Some(best_block_id) => best_block_id,
+			None => return,
+		};
+		// validating every transaction takes a while, and must not hold up the network.
+		let pool = self.pool.clone();
+		let spawned = thread::Builder::new().name("TxPoolRevalidation".into()).spawn(move || {
+			if let Err(e) = pool.revalidate(&best_block_id) {
+				warn!("Error revalidating the transaction pool: {:?}", e);
+			}
+			revalidated();
+		});
+		if let Err(e) = spawned {
+			warn!("Failed to start revalidating the transaction pool: {:?}", e);
+		}
+	}
 }
 
 /// Constructs a service factory with the given name that implements the `ServiceFactory` trait.
@@ -496,9 +551,9 @@ impl<C: Components> network::TransactionPool<ComponentExHash<C>, ComponentBlock<
 ///         // Declare the network protocol and give an initializer.
 /// 		NetworkProtocol = NodeProtocol { |config| Ok(NodeProtocol::new()) },
 /// 		RuntimeDispatch = node_executor::Executor,
-/// 		FullTransactionPoolApi = transaction_pool::ChainApi<FullBackend<Self>, FullExecutor<Self>, Block>
+/// 		FullTransactionPool = TransactionPool<transaction_pool::ChainApi<FullBackend<Self>, FullExecutor<Self>, Block>>
 /// 			{ |config, client| Ok(TransactionPool::new(config, transaction_pool::ChainApi::new(client))) },
-/// 		LightTransactionPoolApi = transaction_pool::ChainApi<LightBackend<Self>, LightExecutor<Self>, Block>
+/// 		LightTransactionPool = TransactionPool<transaction_pool::ChainApi<LightBackend<Self>, LightExecutor<Self>, Block>>
 /// 			{ |config, client| Ok(TransactionPool::new(config, transaction_pool::ChainApi::new(client))) },
 /// 		Genesis = GenesisConfig,
 /// 		Configuration = (),
@@ -527,8 +582,8 @@ macro_rules! construct_service_factory {
 			RuntimeApi = $runtime_api:ty,
 			NetworkProtocol = $protocol:ty { $( $protocol_init:tt )* },
 			RuntimeDispatch = $dispatch:ty,
-			FullTransactionPoolApi = $full_transaction:ty { $( $full_transaction_init:tt )* },
-			LightTransactionPoolApi = $light_transaction:ty { $( $light_transaction_init:tt )* },
+			FullTransactionPool = $full_transaction:ty { $( $full_transaction_init:tt )* },
+			LightTransactionPool = $light_transaction:ty { $( $light_transaction_init:tt )* },
 			Genesis = $genesis:ty,
 			Configuration = $config:ty,
 			FullService = $full_service:ty { $( $full_service_init:tt )* },
@@ -549,8 +604,8 @@ macro_rules! construct_service_factory {
 			type RuntimeApi = $runtime_api;
 			type NetworkProtocol = $protocol;
 			type RuntimeDispatch = $dispatch;
-			type FullTransactionPoolApi = $full_transaction;
-			type LightTransactionPoolApi = $light_transaction;
+			type FullTransactionPool = $full_transaction;
+			type LightTransactionPool = $light_transaction;
 			type Genesis = $genesis;
 			type Configuration = $config;
 			type FullService = $full_service;
@@ -561,7 +616,7 @@ macro_rules! construct_service_factory {
 			fn build_full_transaction_pool(
 				config: $crate::TransactionPoolOptions,
 				client: $crate::Arc<$crate::FullClient<Self>>
-			) -> $crate::Result<$crate::TransactionPool<Self::FullTransactionPoolApi>, $crate::Error>
+			) -> $crate::Result<Self::FullTransactionPool, $crate::Error>
 			{
 				( $( $full_transaction_init )* ) (config, client)
 			}
@@ -569,7 +624,7 @@ macro_rules! construct_service_factory {
 			fn build_light_transaction_pool(
 				config: $crate::TransactionPoolOptions,
 				client: $crate::Arc<$crate::LightClient<Self>>
-			) -> $crate::Result<$crate::TransactionPool<Self::LightTransactionPoolApi>, $crate::Error>
+			) -> $crate::Result<Self::LightTransactionPool, $crate::Error>
 			{
 				( $( $light_transaction_init )* ) (config, client)
 			}
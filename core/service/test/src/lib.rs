@@ -18,7 +18,6 @@
 
 #[macro_use]
 extern crate log;
-extern crate tempdir;
 extern crate tokio;
 extern crate futures;
 extern crate env_logger;
@@ -34,7 +33,6 @@ use std::sync::Arc;
 use std::net::Ipv4Addr;
 use std::time::Duration;
 use futures::{Future, Stream};
-use tempdir::TempDir;
 use tokio::runtime::Runtime;
 use tokio::timer::Interval;
 use primitives::blake2_256;
@@ -48,7 +46,7 @@ use service::{
 	FactoryExtrinsic,
 };
 use network::{Protocol, SyncProvider, ManageNetwork};
-use network::config::{NetworkConfiguration, NonReservedPeerMode};
+use network::config::{NetworkConfiguration, NodeKeyType, NonReservedPeerMode, LightAnnouncement};
 use sr_primitives::traits::As;
 use sr_primitives::generic::BlockId;
 use consensus::{ImportBlock, BlockImport};
@@ -59,7 +57,6 @@ struct TestNet<F: ServiceFactory> {
 	full_nodes: Vec<(u32, Arc<F::FullService>)>,
 	_light_nodes: Vec<(u32, Arc<F::LightService>)>,
 	chain_spec: FactoryChainSpec<F>,
-	base_port: u16,
 	nodes: usize,
 }
 
@@ -81,36 +78,49 @@ fn node_private_key_string(index: u32) -> String {
 	format!("N{}", index)
 }
 
+/// Address other nodes can reach the given node at, on the port picked by the OS.
+fn node_address<F: ServiceFactory>(service: &F::FullService) -> String {
+	let network = service.network();
+	let mut address = network.listen_addresses().into_iter().next().expect("Test nodes always listen");
+	address.append(Protocol::P2p(network.local_peer_id().into()));
+	address.to_string()
+}
+
 fn node_config<F: ServiceFactory> (
 	index: u32,
 	spec: &FactoryChainSpec<F>,
 	role: Roles,
 	key_seed: Option<String>,
-	base_port: u16,
-	root: &TempDir,
 ) -> FactoryFullConfiguration<F>
 {
-	let root = root.path().join(format!("node-{}", index));
 	let mut keys = Vec::new();
 	if let Some(seed) = key_seed {
 		keys.push(seed);
 	}
 
 	let network_config = NetworkConfiguration {
-		config_path: Some(root.join("network").to_str().unwrap().into()),
-		net_config_path: Some(root.join("network").to_str().unwrap().into()),
+		config_path: None,
+		net_config_path: None,
 		listen_addresses: vec! [
 			iter::once(Protocol::Ip4(Ipv4Addr::new(127, 0, 0, 1)))
-				.chain(iter::once(Protocol::Tcp(base_port + index as u16)))
+				.chain(iter::once(Protocol::Tcp(0)))
 				.collect()
 		],
 		public_addresses: vec![],
+		enable_port_mapping: false,
+		enable_mdns: false,
 		boot_nodes: vec![],
 		use_secret: Some(blake2_256(node_private_key_string(index).as_bytes())),
+		node_key_type: NodeKeyType::Secp256k1,
 		in_peers: 50,
 		out_peers: 450,
+		max_connections_per_ip: 4,
+		max_connections_per_subnet: 16,
 		reserved_nodes: vec![],
 		non_reserved_mode: NonReservedPeerMode::Accept,
+		wss_certificate: None,
+		pre_shared_key_path: None,
+		multiplexer: Default::default(),
 		client_version: "network/test/0.1".to_owned(),
 		node_name: "unknown".to_owned(),
 	};
@@ -122,9 +132,10 @@ fn node_config<F: ServiceFactory> (
 		roles: role,
 		transaction_pool: Default::default(),
 		network: network_config,
-		keystore_path: root.join("key").to_str().unwrap().into(),
-		database_path: root.join("db").to_str().unwrap().into(),
+		keystore_path: Default::default(),
+		database_path: Default::default(),
 		database_cache_size: None,
+		in_memory: true,
 		pruning: Default::default(),
 		keys: keys,
 		chain_spec: (*spec).clone(),
@@ -134,12 +145,22 @@ fn node_config<F: ServiceFactory> (
 		api_execution_strategy: ExecutionStrategy::NativeWhenPossible,
 		rpc_http: None,
 		rpc_ws: None,
+		rpc_unsafe: false,
 		telemetry_url: None,
+		verification_workers: 0,
+		chain_freeze_timeout: None,
+		justifications_only: false,
+		light_announcement: LightAnnouncement::All,
+		cross_check_blocks: false,
+		download_window: Default::default(),
+		block_request_quota: Default::default(),
+		gossip_quota: Default::default(),
+		transaction_quota: Default::default(),
 	}
 }
 
 impl<F: ServiceFactory> TestNet<F> {
-	fn new(temp: &TempDir, spec: FactoryChainSpec<F>, full: u32, light: u32, authorities: Vec<String>, base_port: u16) -> TestNet<F> {
+	fn new(spec: FactoryChainSpec<F>, full: u32, light: u32, authorities: Vec<String>) -> TestNet<F> {
 		let _ = ::env_logger::try_init();
 		::fdlimit::raise_fd_limit();
 		let runtime = Runtime::new().expect("Error creating tokio runtime");
@@ -149,32 +170,30 @@ impl<F: ServiceFactory> TestNet<F> {
 			full_nodes: Default::default(),
 			_light_nodes: Default::default(),
 			chain_spec: spec.clone(),
-			base_port,
 			nodes: 0,
 		};
-		net.insert_nodes(temp, full, light, authorities);
+		net.insert_nodes(full, light, authorities);
 		net
 	}
 
-	fn insert_nodes(&mut self, temp: &TempDir, full: u32, light: u32, authorities: Vec<String>) {
+	fn insert_nodes(&mut self, full: u32, light: u32, authorities: Vec<String>) {
 		let mut nodes = self.nodes;
-		let base_port = self.base_port;
 		let spec = self.chain_spec.clone();
 		let executor = self.runtime.executor();
 		self.authority_nodes.extend(authorities.iter().enumerate().map(|(index, key)| ((index + nodes) as u32,
-			 Arc::new(F::new_full(node_config::<F>(index as u32, &spec, Roles::AUTHORITY, Some(key.clone()), base_port, &temp), executor.clone())
+			 Arc::new(F::new_full(node_config::<F>(index as u32, &spec, Roles::AUTHORITY, Some(key.clone())), executor.clone())
 					  .expect("Error creating test node service")))
 		));
 		nodes += authorities.len();
 
 		self.full_nodes.extend((nodes..nodes + full as usize).map(|index| (index as u32,
-			Arc::new(F::new_full(node_config::<F>(index as u32, &spec, Roles::FULL, None, base_port, &temp), executor.clone())
+			Arc::new(F::new_full(node_config::<F>(index as u32, &spec, Roles::FULL, None), executor.clone())
 				.expect("Error creating test node service")))
 		));
 		nodes += full as usize;
 
 		self._light_nodes.extend((nodes..nodes + light as usize).map(|index| (index as u32,
-			Arc::new(F::new_light(node_config::<F>(index as u32, &spec, Roles::LIGHT, None, base_port, &temp), executor.clone())
+			Arc::new(F::new_light(node_config::<F>(index as u32, &spec, Roles::LIGHT, None), executor.clone())
 					 .expect("Error creating test node service")))
 		));
 		nodes += light as usize;
@@ -185,11 +204,10 @@ impl<F: ServiceFactory> TestNet<F> {
 pub fn connectivity<F: ServiceFactory>(spec: FactoryChainSpec<F>) {
 	const NUM_NODES: u32 = 10;
 	{
-		let temp = TempDir::new("substrate-connectivity-test").expect("Error creating test dir");
 		let runtime = {
-			let mut network = TestNet::<F>::new(&temp, spec.clone(), NUM_NODES, 0, vec![], 30400);
+			let mut network = TestNet::<F>::new(spec.clone(), NUM_NODES, 0, vec![]);
 			info!("Checking star topology");
-			let first_address = network.full_nodes[0].1.network().node_id().expect("No node address");
+			let first_address = node_address::<F>(&network.full_nodes[0].1);
 			for (_, service) in network.full_nodes.iter().skip(1) {
 				service.network().add_reserved_peer(first_address.clone()).expect("Error adding reserved peer");
 			}
@@ -200,24 +218,18 @@ pub fn connectivity<F: ServiceFactory>(spec: FactoryChainSpec<F>) {
 		};
 
 		runtime.shutdown_on_idle().wait().expect("Error shutting down runtime");
-
-		temp.close().expect("Error removing temp dir");
 	}
 	{
-		let temp = TempDir::new("substrate-connectivity-test").expect("Error creating test dir");
-		{
-			let mut network = TestNet::<F>::new(&temp, spec, NUM_NODES, 0, vec![], 30400);
-			info!("Checking linked topology");
-			let mut address = network.full_nodes[0].1.network().node_id().expect("No node address");
-			for (_, service) in network.full_nodes.iter().skip(1) {
-				service.network().add_reserved_peer(address.clone()).expect("Error adding reserved peer");
-				address = service.network().node_id().expect("No node address");
-			}
-			network.run_until_all_full(|_index, service| {
-				service.network().status().num_peers == NUM_NODES as usize - 1
-			});
+		let mut network = TestNet::<F>::new(spec, NUM_NODES, 0, vec![]);
+		info!("Checking linked topology");
+		let mut address = node_address::<F>(&network.full_nodes[0].1);
+		for (_, service) in network.full_nodes.iter().skip(1) {
+			service.network().add_reserved_peer(address.clone()).expect("Error adding reserved peer");
+			address = node_address::<F>(service);
 		}
-		temp.close().expect("Error removing temp dir");
+		network.run_until_all_full(|_index, service| {
+			service.network().status().num_peers == NUM_NODES as usize - 1
+		});
 	}
 }
 
@@ -229,8 +241,7 @@ where
 {
 	const NUM_NODES: u32 = 10;
 	const NUM_BLOCKS: usize = 512;
-	let temp = TempDir::new("substrate-sync-test").expect("Error creating test dir");
-	let mut network = TestNet::<F>::new(&temp, spec.clone(), NUM_NODES, 0, vec![], 30500);
+	let mut network = TestNet::<F>::new(spec.clone(), NUM_NODES, 0, vec![]);
 	info!("Checking block sync");
 	let first_address = {
 		let first_service = &network.full_nodes[0].1;
@@ -241,7 +252,7 @@ where
 			let import_data = block_factory(&first_service);
 			first_service.client().import_block(import_data, None).expect("Error importing test block");
 		}
-		first_service.network().node_id().unwrap()
+		node_address::<F>(first_service)
 	};
 	info!("Running sync");
 	for (_, service) in network.full_nodes.iter().skip(1) {
@@ -265,10 +276,9 @@ pub fn consensus<F>(spec: FactoryChainSpec<F>, authorities: Vec<String>)
 {
 	const NUM_NODES: u32 = 20;
 	const NUM_BLOCKS: u64 = 200;
-	let temp = TempDir::new("substrate-conensus-test").expect("Error creating test dir");
-	let mut network = TestNet::<F>::new(&temp, spec.clone(), NUM_NODES / 2, 0, authorities, 30600);
+	let mut network = TestNet::<F>::new(spec.clone(), NUM_NODES / 2, 0, authorities);
 	info!("Checking consensus");
-	let first_address = network.authority_nodes[0].1.network().node_id().unwrap();
+	let first_address = node_address::<F>(&network.authority_nodes[0].1);
 	for (_, service) in network.full_nodes.iter() {
 		service.network().add_reserved_peer(first_address.clone()).expect("Error adding reserved peer");
 	}
@@ -279,7 +289,7 @@ pub fn consensus<F>(spec: FactoryChainSpec<F>, authorities: Vec<String>)
 		service.client().info().unwrap().chain.finalized_number >= As::sa(NUM_BLOCKS / 2)
 	});
 	info!("Adding more peers");
-	network.insert_nodes(&temp, NUM_NODES / 2, 0, vec![]);
+	network.insert_nodes(NUM_NODES / 2, 0, vec![]);
 	for (_, service) in network.full_nodes.iter() {
 		service.network().add_reserved_peer(first_address.clone()).expect("Error adding reserved peer");
 	}
@@ -203,6 +203,22 @@ pub fn ed25519_verify<P: AsRef<[u8]>>(sig: &[u8; 64], msg: &[u8], pubkey: P) ->
 	ed25519::verify(sig, msg, pubkey)
 }
 
+/// List the Ed25519 public keys held by the node keystore.
+///
+/// Returns `None` outside of an offchain execution context.
+pub fn ed25519_public_keys() -> Option<Vec<[u8; 32]>> {
+	ext::with(|ext| ext.offchain_keystore().map(|keystore| keystore.ed25519_public_keys()))
+		.expect("ed25519_public_keys cannot be called outside of an Externalities-provided environment.")
+}
+
+/// Sign `msg` with the Ed25519 key of the node keystore matching `pubkey`.
+///
+/// Returns `None` outside of an offchain execution context or if the key is unknown.
+pub fn ed25519_sign(pubkey: &[u8; 32], msg: &[u8]) -> Option<[u8; 64]> {
+	ext::with(|ext| ext.offchain_keystore().and_then(|keystore| keystore.ed25519_sign(pubkey, msg)))
+		.expect("ed25519_sign cannot be called outside of an Externalities-provided environment.")
+}
+
 /// Verify and recover a SECP256k1 ECDSA signature.
 /// - `sig` is passed in RSV format. V should be either 0/1 or 27/28.
 /// - returns `Err` if the signatue is bad, otherwise the 64-byte pubkey (doesn't include the 0x04 prefix).
@@ -96,6 +96,11 @@ extern "C" {
 	fn ext_ed25519_verify(msg_data: *const u8, msg_len: u32, sig_data: *const u8, pubkey_data: *const u8) -> u32;
 	/// Note: ext_secp256k1_ecdsa_recover returns 0 if the signature is correct, nonzero otherwise.
 	fn ext_secp256k1_ecdsa_recover(msg_data: *const u8, sig_data: *const u8, pubkey_data: *mut u8) -> u32;
+
+	/// Offchain keystore access. Host-side result allocation
+	fn ext_ed25519_public_keys(written_out: *mut u32) -> *mut u8;
+	/// Note: ext_ed25519_sign returns 0 if the signature was written, nonzero otherwise.
+	fn ext_ed25519_sign(pubkey_data: *const u8, msg_data: *const u8, msg_len: u32, sig_data: *mut u8) -> u32;
 }
 
 /// Ensures we use the right crypto when calling into native
@@ -376,6 +381,45 @@ pub fn ed25519_verify<P: AsRef<[u8]>>(sig: &[u8; 64], msg: &[u8], pubkey: P) ->
 	}
 }
 
+/// List the Ed25519 public keys held by the node keystore.
+///
+/// Returns `None` outside of an offchain execution context.
+pub fn ed25519_public_keys() -> Option<Vec<[u8; 32]>> {
+	let mut length: u32 = 0;
+	let keys = unsafe {
+		let ptr = ext_ed25519_public_keys(&mut length);
+		if length == u32::max_value() {
+			return None;
+		}
+		// the host doesn't allocate anything for no keys, so `ptr` may be null.
+		if length == 0 {
+			return Some(Vec::new());
+		}
+		// Invariants required by Vec::from_raw_parts are not formally fulfilled.
+		// We don't allocate via String/Vec<T>, but use a custom allocator instead.
+		// See #300 for more details.
+		<Vec<u8>>::from_raw_parts(ptr, length as usize, length as usize)
+	};
+	Some(keys.chunks(32).map(|chunk| {
+		let mut key = [0u8; 32];
+		key.copy_from_slice(chunk);
+		key
+	}).collect())
+}
+
+/// Sign `msg` with the Ed25519 key of the node keystore matching `pubkey`.
+///
+/// Returns `None` outside of an offchain execution context or if the key is unknown.
+pub fn ed25519_sign(pubkey: &[u8; 32], msg: &[u8]) -> Option<[u8; 64]> {
+	let mut sig = [0u8; 64];
+	match unsafe {
+		ext_ed25519_sign(pubkey.as_ptr(), msg.as_ptr(), msg.len() as u32, sig.as_mut_ptr())
+	} {
+		0 => Some(sig),
+		_ => None,
+	}
+}
+
 /// Verify and recover a SECP256k1 ECDSA signature.
 /// - `sig` is passed in RSV format. V should be either 0/1 or 27/28.
 /// - returns `None` if the signatue is bad, the 64-byte pubkey (doesn't include the 0x04 prefix).
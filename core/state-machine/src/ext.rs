@@ -20,7 +20,7 @@ use std::{error, fmt, cmp::Ord};
 use log::warn;
 use crate::backend::{Backend, Consolidate};
 use crate::changes_trie::{AnchorBlockId, Storage as ChangesTrieStorage, compute_changes_trie_root};
-use crate::{Externalities, OverlayedChanges};
+use crate::{Externalities, OffchainKeystore, OverlayedChanges};
 use hash_db::Hasher;
 use primitives::storage::well_known_keys::is_child_storage_key;
 use trie::{MemoryDB, TrieDBMut, TrieMut, default_child_trie_root, is_child_trie_key_valid};
@@ -81,6 +81,8 @@ where
 	/// `storage_changes_root` is called matters + we need to remember additional
 	/// data at this moment (block number).
 	changes_trie_transaction: Option<(u64, MemoryDB<H>, H::Out)>,
+	/// The keystore exposed to offchain execution. Never set for on-chain execution.
+	offchain_keystore: Option<&'a OffchainKeystore>,
 }
 
 impl<'a, H, B, T> Ext<'a, H, B, T>
@@ -98,9 +100,16 @@ where
 			storage_transaction: None,
 			changes_trie_storage,
 			changes_trie_transaction: None,
+			offchain_keystore: None,
 		}
 	}
 
+	/// Expose a keystore to the code executed with these externalities. Must only be used
+	/// for offchain execution.
+	pub fn set_offchain_keystore(&mut self, keystore: &'a OffchainKeystore) {
+		self.offchain_keystore = Some(keystore);
+	}
+
 	/// Get the transaction necessary to update the backend.
 	pub fn transaction(mut self) -> (B::Transaction, Option<MemoryDB<H>>) {
 		let _ = self.storage_root();
@@ -317,6 +326,10 @@ where
 		self.changes_trie_transaction = root_and_tx;
 		root
 	}
+
+	fn offchain_keystore(&self) -> Option<&OffchainKeystore> {
+		self.offchain_keystore
+	}
 }
 
 #[cfg(test)]
@@ -393,4 +406,31 @@ mod tests {
 		assert_eq!(ext.storage_changes_root(Default::default(), 99),
 			Some(hex!("bcf494e41e29a15c9ae5caa053fe3cb8b446ee3e02a254efbdec7a19235b76e4").into()));
 	}
+
+	struct SingleKeystore;
+
+	impl OffchainKeystore for SingleKeystore {
+		fn ed25519_public_keys(&self) -> Vec<[u8; 32]> {
+			vec![[1; 32]]
+		}
+
+		fn ed25519_sign(&self, public: &[u8; 32], _msg: &[u8]) -> Option<[u8; 64]> {
+			if public == &[1; 32] { Some([2; 64]) } else { None }
+		}
+	}
+
+	#[test]
+	fn offchain_keystore_is_only_available_when_set() {
+		let mut overlay = prepare_overlay_with_changes();
+		let backend = TestBackend::default();
+		let keystore = SingleKeystore;
+		let mut ext = TestExt::new(&mut overlay, &backend, None);
+		assert!(ext.offchain_keystore().is_none());
+
+		ext.set_offchain_keystore(&keystore);
+		let keystore = ext.offchain_keystore().unwrap();
+		assert_eq!(keystore.ed25519_public_keys(), vec![[1; 32]]);
+		assert_eq!(keystore.ed25519_sign(&[1; 32], b"msg").map(|s| s.to_vec()), Some(vec![2; 64]));
+		assert!(keystore.ed25519_sign(&[3; 32], b"msg").is_none());
+	}
 }
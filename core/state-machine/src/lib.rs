@@ -79,6 +79,19 @@ impl fmt::Display for ExecutionError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "Externalities Error") }
 }
 
+/// Access to the node keystore from offchain execution contexts.
+///
+/// Private keys never leave the keystore: callers may only list the public
+/// keys that are available and request signatures made with them.
+pub trait OffchainKeystore: Send + Sync {
+	/// List the public keys of all Ed25519 keys held by the keystore.
+	fn ed25519_public_keys(&self) -> Vec<[u8; 32]>;
+
+	/// Sign `msg` with the Ed25519 key matching `public`. Returns `None` if the
+	/// key is not held by the keystore.
+	fn ed25519_sign(&self, public: &[u8; 32], msg: &[u8]) -> Option<[u8; 64]>;
+}
+
 /// Externalities: pinned to specific active address.
 pub trait Externalities<H: Hasher> {
 	/// Read runtime storage.
@@ -147,6 +160,14 @@ pub trait Externalities<H: Hasher> {
 
 	/// Get the change trie root of the current storage overlay at a block wth given parent.
 	fn storage_changes_root(&mut self, parent: H::Out, parent_num: u64) -> Option<H::Out> where H::Out: Ord;
+
+	/// Get the keystore available to offchain execution contexts.
+	///
+	/// This is always `None` during on-chain execution, since signing there
+	/// would make block execution depend on local node state.
+	fn offchain_keystore(&self) -> Option<&OffchainKeystore> {
+		None
+	}
 }
 
 /// Code execution engine.
@@ -173,7 +194,8 @@ pub enum ExecutionStrategy {
 	NativeWhenPossible,
 	/// Use the given wasm module.
 	AlwaysWasm,
-	/// Run with both the wasm and the native variant (if compatible). Report any discrepency as an error.
+	/// Run with both the wasm and the native variant (if compatible). Report any discrepency
+	/// in the results or the resulting storage roots as an error.
 	Both,
 }
 
@@ -277,6 +299,34 @@ where
 	))
 }
 
+/// Execute a call in an offchain context using the given state backend and call executor.
+/// The keystore is made available to the executed code, which can list its public keys and
+/// request signatures. Changes made to the storage are discarded.
+pub fn execute_offchain<H, B, T, Exec>(
+	backend: &B,
+	changes_trie_storage: Option<&T>,
+	exec: &Exec,
+	method: &str,
+	call_data: &[u8],
+	use_native: bool,
+	keystore: &OffchainKeystore,
+) -> Result<Vec<u8>, Box<Error>>
+where
+	H: Hasher,
+	Exec: CodeExecutor<H>,
+	B: Backend<H>,
+	T: ChangesTrieStorage<H>,
+	H::Out: Ord + HeapSizeOf,
+{
+	let mut overlay = OverlayedChanges::default();
+	let mut externalities = ext::Ext::new(&mut overlay, backend, changes_trie_storage);
+	externalities.set_offchain_keystore(keystore);
+	exec.call::<_, NeverNativeValue, fn() -> NeverNativeValue>(&mut externalities, method, call_data, use_native, None)
+		.0
+		.map(NativeOrEncoded::into_encoded)
+		.map_err(|e| Box::new(e) as _)
+}
+
 /// Execute a call using the given state backend, overlayed changes, and call executor.
 /// Produces a state-backend-specific "transaction" which can be used to apply the changes
 /// to the backing store, such as the disk.
@@ -326,11 +376,14 @@ where
 	};
 	init_overlay(overlay, false)?;
 
+	// storage roots are only needed to compare native and wasm execution.
+	let compare_roots = strategy == ExecutionStrategy::Both;
+
 	let result = {
 		let orig_prospective = overlay.prospective.clone();
 
-		let (result, was_native, storage_delta, changes_delta) = {
-			let ((result, was_native), (storage_delta, changes_delta)) = {
+		let (result, was_native, storage_delta, changes_delta, native_root) = {
+			let ((result, was_native), (storage_delta, changes_delta), root) = {
 				let mut externalities = ext::Ext::new(overlay, backend, changes_trie_storage);
 				let retval = exec.call(
 					&mut externalities,
@@ -340,25 +393,26 @@ where
 					strategy != ExecutionStrategy::AlwaysWasm,
 					native_call.take(),
 				);
+				let root = if compare_roots { Some(externalities.storage_root()) } else { None };
 				let (storage_delta, changes_delta) = if compute_tx {
 					let (storage_delta, changes_delta) = externalities.transaction();
 					(Some(storage_delta), changes_delta)
 				} else {
 					(None, None)
 				};
-				(retval, (storage_delta, changes_delta))
+				(retval, (storage_delta, changes_delta), root)
 			};
-			(result, was_native, storage_delta, changes_delta)
+			(result, was_native, storage_delta, changes_delta, root)
 		};
 
 		// run wasm separately if we did run native the first time and we're meant to run both
 		let (result, storage_delta, changes_delta) = if let (true, ExecutionManager::Both(on_consensus_failure)) =
 			(was_native, manager)
 		{
-			overlay.prospective = orig_prospective.clone();
+			let native_prospective = ::std::mem::replace(&mut overlay.prospective, orig_prospective);
 
-			let (wasm_result, wasm_storage_delta, wasm_changes_delta) = {
-				let ((result, _), (storage_delta, changes_delta)) = {
+			let (wasm_result, wasm_storage_delta, wasm_changes_delta, wasm_root) = {
+				let ((result, _), (storage_delta, changes_delta), root) = {
 					let mut externalities = ext::Ext::new(overlay, backend, changes_trie_storage);
 					let retval = exec.call(
 						&mut externalities,
@@ -367,23 +421,34 @@ where
 						false,
 						native_call,
 					);
+					let root = externalities.storage_root();
 					let (storage_delta, changes_delta) = if compute_tx {
 						let (storage_delta, changes_delta) = externalities.transaction();
 						(Some(storage_delta), changes_delta)
 					} else {
 						(None, None)
 					};
-					(retval, (storage_delta, changes_delta))
+					(retval, (storage_delta, changes_delta), root)
 				};
-				(result, storage_delta, changes_delta)
+				(result, storage_delta, changes_delta, root)
 			};
 
-			if (result.is_ok() && wasm_result.is_ok()
+			let results_match = (result.is_ok() && wasm_result.is_ok()
 				&& result.as_ref().ok() == wasm_result.as_ref().ok())
-				|| result.is_err() && wasm_result.is_err() {
+				|| result.is_err() && wasm_result.is_err();
+			if results_match && native_root == Some(wasm_root) {
 				(result, storage_delta, changes_delta)
 			} else {
 				// Consensus error.
+				warn!(
+					"Native and wasm execution of {} diverged. Storage root native: {:?}, wasm: {:?}",
+					method,
+					native_root,
+					wasm_root
+				);
+				for line in native_prospective.diff(&overlay.prospective) {
+					warn!("   {}", line);
+				}
 				(on_consensus_failure(wasm_result, result), wasm_storage_delta, wasm_changes_delta)
 			}
 		} else {
@@ -663,6 +728,58 @@ mod tests {
 
 	impl Error for u8 {}
 
+	/// Returns the public keys of the offchain keystore, if there is one.
+	struct KeystoreExecutor;
+
+	impl<H: Hasher> CodeExecutor<H> for KeystoreExecutor {
+		type Error = u8;
+
+		fn call<E: Externalities<H>, R: Encode + Decode + PartialEq, NC: FnOnce() -> R>(
+			&self,
+			ext: &mut E,
+			_method: &str,
+			_data: &[u8],
+			_use_native: bool,
+			_native_call: Option<NC>,
+		) -> (Result<NativeOrEncoded<R>, Self::Error>, bool) {
+			match ext.offchain_keystore() {
+				Some(keystore) => (Ok(NativeOrEncoded::Encoded(keystore.ed25519_public_keys().concat())), false),
+				None => (Err(0), false),
+			}
+		}
+	}
+
+	struct SingleKeystore;
+
+	impl OffchainKeystore for SingleKeystore {
+		fn ed25519_public_keys(&self) -> Vec<[u8; 32]> {
+			vec![[1; 32]]
+		}
+
+		fn ed25519_sign(&self, _public: &[u8; 32], _msg: &[u8]) -> Option<[u8; 64]> {
+			None
+		}
+	}
+
+	/// Returns the same result with native and wasm, but stores a different value.
+	struct DivergingStorageExecutor;
+
+	impl<H: Hasher> CodeExecutor<H> for DivergingStorageExecutor {
+		type Error = u8;
+
+		fn call<E: Externalities<H>, R: Encode + Decode + PartialEq, NC: FnOnce() -> R>(
+			&self,
+			ext: &mut E,
+			_method: &str,
+			_data: &[u8],
+			use_native: bool,
+			_native_call: Option<NC>,
+		) -> (Result<NativeOrEncoded<R>, Self::Error>, bool) {
+			ext.place_storage(b"diverged".to_vec(), Some(vec![use_native as u8]));
+			(Ok(NativeOrEncoded::Encoded(vec![66])), use_native)
+		}
+	}
+
 	#[test]
 	fn execute_works() {
 		assert_eq!(execute(
@@ -681,6 +798,32 @@ mod tests {
 		).unwrap().0, vec![66]);
 	}
 
+	#[test]
+	fn keystore_is_only_available_to_offchain_execution() {
+		let backend = trie_backend::tests::test_trie();
+		let changes_trie_storage = InMemoryChangesTrieStorage::new();
+
+		assert_eq!(execute_offchain(
+			&backend,
+			Some(&changes_trie_storage),
+			&KeystoreExecutor,
+			"test",
+			&[],
+			true,
+			&SingleKeystore,
+		).unwrap(), vec![1; 32]);
+
+		assert!(execute(
+			&backend,
+			Some(&changes_trie_storage),
+			&mut Default::default(),
+			&KeystoreExecutor,
+			"test",
+			&[],
+			ExecutionStrategy::NativeWhenPossible,
+		).is_err());
+	}
+
 	#[test]
 	fn dual_execution_strategy_detects_consensus_failure() {
 		let mut consensus_failed = false;
@@ -707,6 +850,31 @@ mod tests {
 		assert!(consensus_failed);
 	}
 
+	#[test]
+	fn dual_execution_strategy_detects_storage_root_mismatch() {
+		let mut consensus_failed = false;
+		let mut overlay = OverlayedChanges::default();
+		let result = execute_using_consensus_failure_handler::<_, _, _, _, _, NeverNativeValue, fn() -> NeverNativeValue>(
+			&trie_backend::tests::test_trie(),
+			Some(&InMemoryChangesTrieStorage::new()),
+			&mut overlay,
+			&DivergingStorageExecutor,
+			"test",
+			&[],
+			ExecutionManager::Both(|we, _ne| {
+				consensus_failed = true;
+				we
+			}),
+			true,
+			None,
+		).unwrap();
+
+		assert_eq!(result.0.into_encoded(), vec![66]);
+		assert!(consensus_failed);
+		// wasm changes are kept.
+		assert_eq!(overlay.storage(b"diverged"), Some(Some(&[0][..])));
+	}
+
 	#[test]
 	fn prove_execution_and_proof_check_works() {
 		let executor = DummyCodeExecutor {
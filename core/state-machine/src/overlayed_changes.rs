@@ -17,10 +17,11 @@
 //! The overlayed changes to state.
 
 #[cfg(test)] use std::iter::FromIterator;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use parity_codec::Decode;
 use crate::changes_trie::{NO_EXTRINSIC_INDEX, Configuration as ChangesTrieConfig};
 use primitives::storage::well_known_keys::EXTRINSIC_INDEX;
+use primitives::hexdisplay::HexDisplay;
 
 /// The overlayed changes to state to be queried on top of the backend.
 ///
@@ -74,6 +75,57 @@ impl OverlayedChangeSet {
 		self.top.is_empty() && self.children.is_empty()
 	}
 
+	/// Describe every key changed differently in this and the other change set, one line
+	/// per key in the form `key: this value != other value`.
+	pub(crate) fn diff(&self, other: &Self) -> Vec<String> {
+		fn describe(value: Option<Option<&Vec<u8>>>) -> String {
+			match value {
+				None => "unchanged".into(),
+				Some(None) => "deleted".into(),
+				Some(Some(value)) => format!("0x{}", HexDisplay::from(value)),
+			}
+		}
+
+		fn diff_map<'a, I, J>(prefix: &str, this: I, other: J, lines: &mut Vec<String>) where
+			I: Iterator<Item=(&'a Vec<u8>, Option<&'a Vec<u8>>)>,
+			J: Iterator<Item=(&'a Vec<u8>, Option<&'a Vec<u8>>)>,
+		{
+			let this: BTreeMap<_, _> = this.collect();
+			let other: BTreeMap<_, _> = other.collect();
+			let keys: BTreeSet<_> = this.keys().chain(other.keys()).collect();
+			for key in keys {
+				let (this, other) = (this.get(key).cloned(), other.get(key).cloned());
+				if this != other {
+					lines.push(format!("{}0x{}: {} != {}", prefix, HexDisplay::from(*key), describe(this), describe(other)));
+				}
+			}
+		}
+
+		let mut lines = Vec::new();
+		diff_map(
+			"",
+			self.top.iter().map(|(k, v)| (k, v.value.as_ref())),
+			other.top.iter().map(|(k, v)| (k, v.value.as_ref())),
+			&mut lines,
+		);
+
+		let empty = HashMap::new();
+		let child_keys: BTreeSet<_> = self.children.keys().chain(other.children.keys()).collect();
+		for storage_key in child_keys {
+			let prefix = format!("child 0x{} ", HexDisplay::from(*storage_key));
+			let this = self.children.get(*storage_key).map(|c| &c.1).unwrap_or(&empty);
+			let other = other.children.get(*storage_key).map(|c| &c.1).unwrap_or(&empty);
+			diff_map(
+				&prefix,
+				this.iter().map(|(k, v)| (k, v.as_ref())),
+				other.iter().map(|(k, v)| (k, v.as_ref())),
+				&mut lines,
+			);
+		}
+
+		lines
+	}
+
 	/// Clear the change set.
 	pub fn clear(&mut self) {
 		self.top.clear();
@@ -479,4 +531,23 @@ mod tests {
 		assert_eq!(overlay.prospective,
 			Default::default());
 	}
+
+	#[test]
+	fn change_set_diff_lists_differing_keys() {
+		let mut this = OverlayedChanges::default();
+		this.set_storage(vec![1], Some(vec![10]));
+		this.set_storage(vec![2], Some(vec![20]));
+		this.set_storage(vec![3], None);
+		let mut other = OverlayedChanges::default();
+		other.set_storage(vec![1], Some(vec![10]));
+		other.set_storage(vec![2], Some(vec![21]));
+		other.set_storage(vec![4], Some(vec![40]));
+
+		assert_eq!(this.prospective.diff(&other.prospective), vec![
+			"0x02: 0x14 != 0x15".to_string(),
+			"0x03: deleted != unchanged".to_string(),
+			"0x04: unchanged != 0x28".to_string(),
+		]);
+		assert!(this.prospective.diff(&this.prospective).is_empty());
+	}
 }
@@ -18,6 +18,7 @@
 
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::sync::Arc;
 use hash_db::Hasher;
 use heapsize::HeapSizeOf;
 use trie::trie_root;
@@ -25,7 +26,7 @@ use crate::backend::InMemory;
 use crate::changes_trie::{compute_changes_trie_root, InMemoryStorage as ChangesTrieInMemoryStorage, AnchorBlockId};
 use primitives::storage::well_known_keys::{CHANGES_TRIE_CONFIG, CODE, HEAP_PAGES};
 use parity_codec::Encode;
-use super::{Externalities, OverlayedChanges};
+use super::{Externalities, OffchainKeystore, OverlayedChanges};
 
 /// Simple HashMap-based Externalities impl.
 pub struct TestExternalities<H: Hasher> where H::Out: HeapSizeOf {
@@ -33,6 +34,7 @@ pub struct TestExternalities<H: Hasher> where H::Out: HeapSizeOf {
 	changes_trie_storage: ChangesTrieInMemoryStorage<H>,
 	changes: OverlayedChanges,
 	code: Vec<u8>,
+	offchain_keystore: Option<Arc<OffchainKeystore>>,
 }
 
 impl<H: Hasher> TestExternalities<H> where H::Out: HeapSizeOf {
@@ -55,6 +57,7 @@ impl<H: Hasher> TestExternalities<H> where H::Out: HeapSizeOf {
 			changes_trie_storage: ChangesTrieInMemoryStorage::new(),
 			changes: overlay,
 			code: code.to_vec(),
+			offchain_keystore: None,
 		}
 	}
 
@@ -62,6 +65,11 @@ impl<H: Hasher> TestExternalities<H> where H::Out: HeapSizeOf {
 	pub fn insert(&mut self, k: Vec<u8>, v: Vec<u8>) -> Option<Vec<u8>> {
 		self.inner.insert(k, v)
 	}
+
+	/// Make the given keystore available, as it would be in an offchain context.
+	pub fn set_offchain_keystore(&mut self, keystore: Arc<OffchainKeystore>) {
+		self.offchain_keystore = Some(keystore);
+	}
 }
 
 impl<H: Hasher> ::std::fmt::Debug for TestExternalities<H> where H::Out: HeapSizeOf {
@@ -103,6 +111,7 @@ impl<H: Hasher> From< HashMap<Vec<u8>, Vec<u8>> > for TestExternalities<H> where
 			changes_trie_storage: ChangesTrieInMemoryStorage::new(),
 			changes: Default::default(),
 			code: Default::default(),
+			offchain_keystore: None,
 		}
 	}
 }
@@ -157,6 +166,10 @@ impl<H: Hasher> Externalities<H> for TestExternalities<H> where H::Out: Ord + He
 			&AnchorBlockId { hash: parent, number: parent_num },
 		).map(|(root, _)| root.clone())
 	}
+
+	fn offchain_keystore(&self) -> Option<&OffchainKeystore> {
+		self.offchain_keystore.as_ref().map(|k| &**k)
+	}
 }
 
 #[cfg(test)]
@@ -174,4 +187,28 @@ mod tests {
 		const ROOT: [u8; 32] = hex!("0b41e488cccbd67d1f1089592c2c235f5c5399b053f7fe9152dd4b5f279914cd");
 		assert_eq!(ext.storage_root(), H256::from(ROOT));
 	}
+
+	struct SingleKeystore;
+
+	impl OffchainKeystore for SingleKeystore {
+		fn ed25519_public_keys(&self) -> Vec<[u8; 32]> {
+			vec![[1; 32]]
+		}
+
+		fn ed25519_sign(&self, public: &[u8; 32], _msg: &[u8]) -> Option<[u8; 64]> {
+			if public == &[1; 32] { Some([2; 64]) } else { None }
+		}
+	}
+
+	#[test]
+	fn offchain_keystore_is_only_available_when_set() {
+		let mut ext = TestExternalities::<Blake2Hasher>::default();
+		assert!(ext.offchain_keystore().is_none());
+
+		ext.set_offchain_keystore(Arc::new(SingleKeystore));
+		let keystore = ext.offchain_keystore().unwrap();
+		assert_eq!(keystore.ed25519_public_keys(), vec![[1; 32]]);
+		assert!(keystore.ed25519_sign(&[1; 32], b"msg").is_some());
+		assert!(keystore.ed25519_sign(&[3; 32], b"msg").is_none());
+	}
 }
@@ -85,6 +85,7 @@ pub struct Transfer {
 pub enum Extrinsic {
 	AuthoritiesChange(Vec<Ed25519AuthorityId>),
 	Transfer(Transfer, Ed25519Signature),
+	StorageChange(Vec<u8>, Option<Vec<u8>>),
 }
 
 #[cfg(feature = "std")]
@@ -108,6 +109,7 @@ impl BlindCheckable for Extrinsic {
 					Err("bad signature")
 				}
 			},
+			Extrinsic::StorageChange(key, value) => Ok(Extrinsic::StorageChange(key, value)),
 		}
 	}
 }
@@ -195,6 +195,7 @@ fn execute_transaction_backend(utx: &Extrinsic) -> ApplyResult {
 	match utx {
 		Extrinsic::Transfer(ref transfer, _) => execute_transfer_backend(transfer),
 		Extrinsic::AuthoritiesChange(ref new_auth) => execute_new_authorities_backend(new_auth),
+		Extrinsic::StorageChange(ref key, ref value) => execute_storage_change(key, value.as_ref().map(|v| &**v)),
 	}
 }
 
@@ -230,6 +231,14 @@ fn execute_new_authorities_backend(new_authorities: &[Ed25519AuthorityId]) -> Ap
 	Ok(ApplyOutcome::Success)
 }
 
+fn execute_storage_change(key: &[u8], value: Option<&[u8]>) -> ApplyResult {
+	match value {
+		Some(value) => storage::unhashed::put_raw(key, value),
+		None => storage::unhashed::kill(key),
+	}
+	Ok(ApplyOutcome::Success)
+}
+
 #[cfg(feature = "std")]
 fn info_expect_equal_hash(given: &Hash, expected: &Hash) {
 	use primitives::hexdisplay::HexDisplay;
@@ -0,0 +1,194 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Transaction pool API.
+//!
+//! The service, RPC and block authorship only talk to the transaction pool
+//! through the `TransactionPool` trait defined here, so a node can be run with
+//! a pool implementation other than the default transaction graph.
+
+#![warn(missing_docs)]
+#![warn(unused_extern_crates)]
+
+pub mod error;
+
+use std::{
+	collections::HashMap,
+	hash::Hash,
+	sync::Arc,
+};
+
+use futures::{Stream, sync::mpsc};
+use serde::Serialize;
+use serde_derive::{Serialize, Deserialize};
+use sr_primitives::{
+	generic::BlockId,
+	traits::{Block as BlockT, Member},
+	transaction_validity::TransactionPriority,
+};
+
+/// Transaction pool status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolStatus {
+	/// Number of transactions in the ready queue.
+	pub ready: usize,
+	/// Sum of bytes of ready transaction encodings.
+	pub ready_bytes: usize,
+	/// Number of transactions in the future queue.
+	pub future: usize,
+	/// Sum of bytes of future transaction encodings.
+	pub future_bytes: usize,
+}
+
+impl PoolStatus {
+	/// Returns true if the are no transactions in the pool.
+	pub fn is_empty(&self) -> bool {
+		self.ready == 0 && self.future == 0
+	}
+}
+
+/// Cumulative transaction pool counters.
+///
+/// All values only ever grow, so rates can be computed by sampling them periodically.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PoolMetrics {
+	/// Number of extrinsics submitted to the pool (including re-submissions after pruning).
+	pub submitted: u64,
+	/// Number of submitted extrinsics that were successfully validated.
+	pub validated: u64,
+	/// Number of submitted extrinsics that were rejected as invalid or of unknown validity.
+	pub invalid: u64,
+	/// Number of transactions pruned from the pool after being included in a block.
+	pub pruned: u64,
+	/// Number of invalid extrinsics that were temporarily banned.
+	///
+	/// Extrinsics that are banned because they were included in a block are not counted.
+	pub banned: u64,
+}
+
+/// Possible transaction status events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionStatus<Hash, BlockHash> {
+	/// Extrinsic is part of the future queue.
+	Future,
+	/// Extrinsic is part of the ready queue.
+	Ready,
+	/// Extrinsic has been finalised in block with given hash.
+	Finalised(BlockHash),
+	/// Some state change (perhaps another extrinsic was included) rendered this extrinsic invalid.
+	Usurped(Hash),
+	/// The extrinsic has been broadcast to the given peers.
+	Broadcast(Vec<String>),
+	/// Extrinsic has been dropped from the pool because of the limit.
+	Dropped,
+	/// Extrinsic was detected as invalid.
+	Invalid,
+}
+
+/// Stream of status updates for a watched transaction.
+pub type TransactionStatusStream<Hash, BlockHash> =
+	Box<Stream<Item=TransactionStatus<Hash, BlockHash>, Error=()> + Send>;
+
+/// Stream of notifications about transactions imported to the pool.
+pub type ImportNotificationStream = mpsc::UnboundedReceiver<()>;
+
+/// Transaction hash type for a pool.
+pub type TxHash<P> = <P as TransactionPool>::Hash;
+/// Block hash type for a pool.
+pub type BlockHash<P> = <<P as TransactionPool>::Block as BlockT>::Hash;
+/// Extrinsic type for a pool.
+pub type ExtrinsicFor<P> = <<P as TransactionPool>::Block as BlockT>::Extrinsic;
+
+/// A transaction stored in the pool.
+pub trait InPoolTransaction {
+	/// Transaction type.
+	type Transaction;
+	/// Transaction hash type.
+	type Hash;
+
+	/// Get the raw transaction.
+	fn data(&self) -> &Self::Transaction;
+
+	/// Get the transaction hash.
+	fn hash(&self) -> &Self::Hash;
+
+	/// Get the transaction priority.
+	fn priority(&self) -> &TransactionPriority;
+}
+
+/// Transaction pool interface.
+pub trait TransactionPool: Send + Sync {
+	/// Block type.
+	type Block: BlockT;
+	/// Transaction hash type.
+	type Hash: Hash + Eq + Member + Serialize;
+	/// Transaction type stored in the pool.
+	type InPoolTransaction: InPoolTransaction<Transaction=ExtrinsicFor<Self>, Hash=TxHash<Self>>;
+	/// Error type.
+	type Error: From<error::Error> + error::IntoPoolError;
+
+	/// Import a bunch of unverified extrinsics, validated at the given block.
+	fn submit_at(
+		&self,
+		at: &BlockId<Self::Block>,
+		xts: Vec<ExtrinsicFor<Self>>,
+	) -> Result<Vec<Result<TxHash<Self>, Self::Error>>, Self::Error>;
+
+	/// Import one unverified extrinsic, validated at the given block.
+	fn submit_one(&self, at: &BlockId<Self::Block>, xt: ExtrinsicFor<Self>) -> Result<TxHash<Self>, Self::Error>;
+
+	/// Import one unverified extrinsic and return a stream of its status updates.
+	fn submit_and_watch(
+		&self,
+		at: &BlockId<Self::Block>,
+		xt: ExtrinsicFor<Self>,
+	) -> Result<TransactionStatusStream<TxHash<Self>, BlockHash<Self>>, Self::Error>;
+
+	/// Get an iterator over ready transactions, in the order they should be included.
+	fn ready(&self) -> Box<Iterator<Item=Arc<Self::InPoolTransaction>>>;
+
+	/// Remove transactions that turned out to be invalid and return them.
+	fn remove_invalid(&self, hashes: &[TxHash<Self>]) -> Vec<Arc<Self::InPoolTransaction>>;
+
+	/// Update the pool after the block `at` with the given extrinsics has been imported
+	/// on top of `parent`.
+	fn maintain(
+		&self,
+		at: &BlockId<Self::Block>,
+		parent: &BlockId<Self::Block>,
+		extrinsics: &[ExtrinsicFor<Self>],
+	) -> Result<(), Self::Error>;
+
+	/// Validate all transactions in the pool again at the given block and
+	/// remove the ones that became invalid.
+	fn revalidate(&self, at: &BlockId<Self::Block>) -> Result<(), Self::Error>;
+
+	/// Returns the pool status.
+	fn status(&self) -> PoolStatus;
+
+	/// Returns cumulative pool counters.
+	fn metrics(&self) -> PoolMetrics;
+
+	/// Return a stream of notifications about transactions imported to the pool.
+	fn import_notification_stream(&self) -> ImportNotificationStream;
+
+	/// Invoked when transactions have been broadcast to the given peers.
+	fn on_broadcasted(&self, propagated: HashMap<TxHash<Self>, Vec<String>>);
+
+	/// Returns the hash of the given extrinsic.
+	fn hash_of(&self, xt: &ExtrinsicFor<Self>) -> TxHash<Self>;
+}
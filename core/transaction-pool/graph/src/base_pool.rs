@@ -27,6 +27,7 @@ use serde::Serialize;
 use error_chain::bail;
 use log::{trace, debug, warn};
 use sr_primitives::traits::Member;
+use txpool_api::InPoolTransaction;
 use sr_primitives::transaction_validity::{
 	TransactionTag as Tag,
 	TransactionLongevity as Longevity,
@@ -37,6 +38,8 @@ use crate::error;
 use crate::future::{FutureTransactions, WaitingTransaction};
 use crate::ready::ReadyTransactions;
 
+pub use txpool_api::PoolStatus as Status;
+
 /// Successful import result.
 #[derive(Debug, PartialEq, Eq)]
 pub enum Imported<Hash, Ex> {
@@ -86,6 +89,8 @@ pub struct PruneStatus<Hash, Ex> {
 pub struct Transaction<Hash, Extrinsic> {
 	/// Raw extrinsic representing that transaction.
 	pub data: Extrinsic,
+	/// Number of bytes encoding of the transaction requires.
+	pub bytes: usize,
 	/// Transaction hash (unique)
 	pub hash: Hash,
 	/// Transaction priority (higher = better)
@@ -98,6 +103,23 @@ pub struct Transaction<Hash, Extrinsic> {
 	pub provides: Vec<Tag>,
 }
 
+impl<Hash, Extrinsic> InPoolTransaction for Transaction<Hash, Extrinsic> {
+	type Transaction = Extrinsic;
+	type Hash = Hash;
+
+	fn data(&self) -> &Extrinsic {
+		&self.data
+	}
+
+	fn hash(&self) -> &Hash {
+		&self.hash
+	}
+
+	fn priority(&self) -> &Priority {
+		&self.priority
+	}
+}
+
 /// Transaction pool.
 ///
 /// Builds a dependency graph for all transactions in the pool and returns
@@ -298,26 +320,13 @@ impl<Hash: hash::Hash + Member + Serialize, Ex: ::std::fmt::Debug> BasePool<Hash
 	pub fn status(&self) -> Status {
 		Status {
 			ready: self.ready.len(),
+			ready_bytes: self.ready.bytes(),
 			future: self.future.len(),
+			future_bytes: self.future.bytes(),
 		}
 	}
 }
 
-/// Pool status
-pub struct Status {
-	/// Number of transactions in the ready queue.
-	pub ready: usize,
-	/// Number of transactions in the future queue.
-	pub future: usize,
-}
-
-impl Status {
-	/// Returns true if the are no transactions in the pool.
-	pub fn is_empty(&self) -> bool {
-		self.ready == 0 && self.future == 0
-	}
-}
-
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -336,6 +345,7 @@ mod tests {
 		// when
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1u64,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -356,6 +366,7 @@ mod tests {
 		// when
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -364,6 +375,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -385,6 +397,7 @@ mod tests {
 		// when
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -395,6 +408,7 @@ mod tests {
 		assert_eq!(pool.ready.len(), 0);
 		pool.import(Transaction {
 			data: vec![2u8],
+			bytes: 1,
 			hash: 2,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -415,6 +429,7 @@ mod tests {
 		// when
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -423,6 +438,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![3u8],
+			bytes: 1,
 			hash: 3,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -431,6 +447,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![2u8],
+			bytes: 1,
 			hash: 2,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -439,6 +456,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![4u8],
+			bytes: 1,
 			hash: 4,
 			priority: 1_000u64,
 			valid_till: 64u64,
@@ -450,6 +468,7 @@ mod tests {
 
 		let res = pool.import(Transaction {
 			data: vec![5u8],
+			bytes: 1,
 			hash: 5,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -480,6 +499,7 @@ mod tests {
 		let mut pool = pool();
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -488,6 +508,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![3u8],
+			bytes: 1,
 			hash: 3,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -500,6 +521,7 @@ mod tests {
 		// when
 		pool.import(Transaction {
 			data: vec![2u8],
+			bytes: 1,
 			hash: 2,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -518,6 +540,7 @@ mod tests {
 		// let's close the cycle with one additional transaction
 		let res = pool.import(Transaction {
 			data: vec![4u8],
+			bytes: 1,
 			hash: 4,
 			priority: 50u64,
 			valid_till: 64u64,
@@ -545,6 +568,7 @@ mod tests {
 		let mut pool = pool();
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -553,6 +577,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![3u8],
+			bytes: 1,
 			hash: 3,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -565,6 +590,7 @@ mod tests {
 		// when
 		pool.import(Transaction {
 			data: vec![2u8],
+			bytes: 1,
 			hash: 2,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -583,6 +609,7 @@ mod tests {
 		// let's close the cycle with one additional transaction
 		let err = pool.import(Transaction {
 			data: vec![4u8],
+			bytes: 1,
 			hash: 4,
 			priority: 1u64, // lower priority than Tx(2)
 			valid_till: 64u64,
@@ -605,6 +632,7 @@ mod tests {
 		let mut pool = pool();
 		pool.import(Transaction {
 			data: vec![5u8],
+			bytes: 1,
 			hash: 5,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -613,6 +641,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -621,6 +650,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![3u8],
+			bytes: 1,
 			hash: 3,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -629,6 +659,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![2u8],
+			bytes: 1,
 			hash: 2,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -637,6 +668,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![4u8],
+			bytes: 1,
 			hash: 4,
 			priority: 1_000u64,
 			valid_till: 64u64,
@@ -646,6 +678,7 @@ mod tests {
 		// future
 		pool.import(Transaction {
 			data: vec![6u8],
+			bytes: 1,
 			hash: 6,
 			priority: 1_000u64,
 			valid_till: 64u64,
@@ -671,6 +704,7 @@ mod tests {
 		// future (waiting for 0)
 		pool.import(Transaction {
 			data: vec![5u8],
+			bytes: 1,
 			hash: 5,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -680,6 +714,7 @@ mod tests {
 		// ready
 		pool.import(Transaction {
 			data: vec![1u8],
+			bytes: 1,
 			hash: 1,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -688,6 +723,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![2u8],
+			bytes: 1,
 			hash: 2,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -696,6 +732,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![3u8],
+			bytes: 1,
 			hash: 3,
 			priority: 5u64,
 			valid_till: 64u64,
@@ -704,6 +741,7 @@ mod tests {
 		}).unwrap();
 		pool.import(Transaction {
 			data: vec![4u8],
+			bytes: 1,
 			hash: 4,
 			priority: 1_000u64,
 			valid_till: 64u64,
@@ -732,4 +770,42 @@ mod tests {
 		assert_eq!(pool.ready().count(), 3);
 	}
 
+	#[test]
+	fn should_track_bytes_of_ready_and_future_transactions() {
+		// given
+		let mut pool = pool();
+		let tx = |hash: u64, bytes: usize, priority: u64, requires: Vec<Vec<u8>>, provides: Vec<Vec<u8>>| Transaction {
+			data: vec![hash as u8],
+			bytes,
+			hash,
+			priority,
+			valid_till: 64u64,
+			requires,
+			provides,
+		};
+
+		// when
+		pool.import(tx(1, 10, 5, vec![vec![0]], vec![vec![1]])).unwrap();
+		pool.import(tx(2, 20, 5, vec![vec![5]], vec![vec![2]])).unwrap();
+		assert_eq!(pool.status().ready_bytes, 0);
+		assert_eq!(pool.status().future_bytes, 30);
+
+		// promotes the first transaction
+		pool.import(tx(3, 100, 5, vec![], vec![vec![0]])).unwrap();
+		assert_eq!(pool.status().ready_bytes, 110);
+		assert_eq!(pool.status().future_bytes, 20);
+
+		// replaces the third transaction
+		pool.import(tx(4, 200, 10, vec![], vec![vec![0]])).unwrap();
+		assert_eq!(pool.status().ready_bytes, 210);
+
+		// prunes the fourth transaction
+		pool.prune_tags(vec![vec![0]]);
+		assert_eq!(pool.status().ready_bytes, 10);
+
+		// then
+		pool.remove_invalid(&[1, 2]);
+		assert_eq!(pool.status().ready_bytes, 0);
+		assert_eq!(pool.status().future_bytes, 0);
+	}
 }
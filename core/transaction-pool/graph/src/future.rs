@@ -74,6 +74,8 @@ pub struct FutureTransactions<Hash: hash::Hash + Eq, Ex> {
 	wanted_tags: HashMap<Tag, HashSet<Hash>>,
 	/// Transactions waiting for a particular other transaction
 	waiting: HashMap<Hash, WaitingTransaction<Hash, Ex>>,
+	/// Sum of encoding lengths of the waiting transactions
+	bytes: usize,
 }
 
 impl<Hash: hash::Hash + Eq, Ex> Default for FutureTransactions<Hash, Ex> {
@@ -81,6 +83,7 @@ impl<Hash: hash::Hash + Eq, Ex> Default for FutureTransactions<Hash, Ex> {
 		FutureTransactions {
 			wanted_tags: Default::default(),
 			waiting: Default::default(),
+			bytes: 0,
 		}
 	}
 }
@@ -110,6 +113,7 @@ impl<Hash: hash::Hash + Eq + Clone, Ex> FutureTransactions<Hash, Ex> {
 		}
 
 		// Add the transaction to a by-hash waiting map
+		self.bytes += tx.transaction.bytes;
 		self.waiting.insert(tx.transaction.hash.clone(), tx);
 	}
 
@@ -141,6 +145,7 @@ impl<Hash: hash::Hash + Eq + Clone, Ex> FutureTransactions<Hash, Ex> {
 
 					if is_ready {
 						let tx = self.waiting.remove(&hash).expect(WAITING_PROOF);
+						self.bytes -= tx.transaction.bytes;
 						became_ready.push(tx);
 					}
 				}
@@ -157,6 +162,7 @@ impl<Hash: hash::Hash + Eq + Clone, Ex> FutureTransactions<Hash, Ex> {
 		let mut removed = vec![];
 		for hash in hashes {
 			if let Some(waiting_tx) = self.waiting.remove(hash) {
+				self.bytes -= waiting_tx.transaction.bytes;
 				// remove from wanted_tags as well
 				for tag in waiting_tx.missing_tags {
 					let remove = if let Some(wanted) = self.wanted_tags.get_mut(&tag) {
@@ -183,4 +189,9 @@ impl<Hash: hash::Hash + Eq + Clone, Ex> FutureTransactions<Hash, Ex> {
 	pub fn len(&self) -> usize {
 		self.waiting.len()
 	}
+
+	/// Returns sum of encoding lengths of all transactions in the Future queue.
+	pub fn bytes(&self) -> usize {
+		self.bytes
+	}
 }
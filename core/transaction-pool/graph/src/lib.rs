@@ -31,9 +31,10 @@ mod ready;
 mod rotator;
 
 pub mod base_pool;
-pub mod error;
 pub mod watcher;
 
+pub use txpool_api::error;
+
 pub use self::error::IntoPoolError;
 pub use self::base_pool::{Transaction, Status};
 pub use self::pool::{Pool, Options, ChainApi, EventStream, ExtrinsicFor, BlockHash, ExHash, NumberFor, TransactionFor};
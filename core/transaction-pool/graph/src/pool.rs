@@ -33,10 +33,12 @@ use log::debug;
 use futures::sync::mpsc;
 use parking_lot::{Mutex, RwLock};
 use sr_primitives::{
+	codec::Encode,
 	generic::BlockId,
 	traits::{self, As},
 	transaction_validity::{TransactionValidity, TransactionTag as Tag},
 };
+use txpool_api::{PoolMetrics, PoolStatus, TransactionPool, TransactionStatusStream};
 
 /// Modification notification event stream type;
 pub type EventStream = mpsc::UnboundedReceiver<()>;
@@ -88,6 +90,7 @@ pub struct Pool<B: ChainApi> {
 	>>,
 	import_notification_sinks: Mutex<Vec<mpsc::UnboundedSender<()>>>,
 	rotator: PoolRotator<ExHash<B>>,
+	metrics: Mutex<PoolMetrics>,
 }
 
 impl<B: ChainApi> Pool<B> {
@@ -103,13 +106,16 @@ impl<B: ChainApi> Pool<B> {
 			.into_iter()
 			.map(|xt| -> Result<_, B::Error> {
 				let hash = self.api.hash(&xt);
+				self.metrics.lock().submitted += 1;
 				if self.rotator.is_banned(&hash) {
 					bail!(error::Error::from(error::ErrorKind::TemporarilyBanned))
 				}
 
 				match self.api.validate_transaction(at, xt.clone())? {
 					TransactionValidity::Valid { priority, requires, provides, longevity } => {
+						self.metrics.lock().validated += 1;
 						Ok(base::Transaction {
+							bytes: xt.encode().len(),
 							data: xt,
 							hash,
 							priority,
@@ -119,9 +125,11 @@ impl<B: ChainApi> Pool<B> {
 						})
 					},
 					TransactionValidity::Invalid(e) => {
+						self.metrics.lock().invalid += 1;
 						bail!(error::Error::from(error::ErrorKind::InvalidTransaction(e)))
 					},
 					TransactionValidity::Unknown(e) => {
+						self.metrics.lock().invalid += 1;
 						self.listener.write().invalid(&hash);
 						bail!(error::Error::from(error::ErrorKind::UnknownTransactionValidity(e)))
 					},
@@ -259,9 +267,12 @@ impl<B: ChainApi> Pool<B> {
 			let header_hash = self.api.block_id_to_hash(at)?
 				.ok_or_else(|| error::ErrorKind::Msg(format!("Invalid block id: {:?}", at)).into())?;
 			let mut listener = self.listener.write();
+			let mut pruned = 0;
 			for h in hashes {
 				listener.pruned(header_hash, &h);
+				pruned += 1;
 			}
+			self.metrics.lock().pruned += pruned;
 		}
 		// perform regular cleanup of old transactions in the pool
 		// and update temporary bans.
@@ -303,6 +314,38 @@ impl<B: ChainApi> Pool<B> {
 
 		Ok(())
 	}
+
+	/// Validates all the transactions of the pool again at the given block, and removes the
+	/// invalid ones along with the stale ones. Transactions failing to validate are kept.
+	///
+	/// Useful when the transactions were validated against a state far behind the chain,
+	/// e.g. after catching up with it. This takes a while with a full pool.
+	pub fn revalidate(&self, at: &BlockId<B::Block>) -> Result<(), B::Error> {
+		let transactions = {
+			let p = self.pool.read();
+			let ready = p.ready().map(|tx| (tx.hash.clone(), tx.data.clone())).collect::<Vec<_>>();
+			ready.into_iter()
+				.chain(p.futures().map(|tx| (tx.hash.clone(), tx.data.clone())))
+				.collect::<Vec<_>>()
+		};
+
+		let mut invalid = Vec::new();
+		let mut failed = 0;
+		for (hash, xt) in transactions {
+			match self.api.validate_transaction(at, xt) {
+				Ok(TransactionValidity::Invalid(_)) => invalid.push(hash),
+				Ok(_) => (),
+				Err(e) => {
+					debug!(target: "txpool", "Error revalidating transaction {:?}: {:?}", hash, e);
+					failed += 1;
+				},
+			}
+		}
+		debug!(target: "txpool", "Revalidated the pool: {} invalid transactions, {} failed to validate", invalid.len(), failed);
+		self.remove_invalid(&invalid);
+
+		self.clear_stale(at)
+	}
 }
 
 impl<B: ChainApi> Pool<B> {
@@ -314,6 +357,7 @@ impl<B: ChainApi> Pool<B> {
 			pool: Default::default(),
 			import_notification_sinks: Default::default(),
 			rotator: Default::default(),
+			metrics: Default::default(),
 		}
 	}
 
@@ -337,6 +381,7 @@ impl<B: ChainApi> Pool<B> {
 		// temporarily ban invalid transactions
 		debug!(target: "txpool", "Banning invalid transactions: {:?}", hashes);
 		self.rotator.ban(&time::Instant::now(), hashes.iter().cloned());
+		self.metrics.lock().banned += hashes.len() as u64;
 
 		let invalid = self.pool.write().remove_invalid(hashes);
 
@@ -358,12 +403,85 @@ impl<B: ChainApi> Pool<B> {
 		self.pool.read().status()
 	}
 
+	/// Returns cumulative pool counters.
+	pub fn metrics(&self) -> PoolMetrics {
+		self.metrics.lock().clone()
+	}
+
 	/// Returns transaction hash
 	pub fn hash_of(&self, xt: &ExtrinsicFor<B>) -> ExHash<B> {
 		self.api.hash(xt)
 	}
 }
 
+impl<B: ChainApi + 'static> TransactionPool for Pool<B> {
+	type Block = B::Block;
+	type Hash = ExHash<B>;
+	type InPoolTransaction = base::Transaction<ExHash<B>, ExtrinsicFor<B>>;
+	type Error = B::Error;
+
+	fn submit_at(
+		&self,
+		at: &BlockId<B::Block>,
+		xts: Vec<ExtrinsicFor<B>>,
+	) -> Result<Vec<Result<ExHash<B>, B::Error>>, B::Error> {
+		Pool::submit_at(self, at, xts)
+	}
+
+	fn submit_one(&self, at: &BlockId<B::Block>, xt: ExtrinsicFor<B>) -> Result<ExHash<B>, B::Error> {
+		Pool::submit_one(self, at, xt)
+	}
+
+	fn submit_and_watch(
+		&self,
+		at: &BlockId<B::Block>,
+		xt: ExtrinsicFor<B>,
+	) -> Result<TransactionStatusStream<ExHash<B>, BlockHash<B>>, B::Error> {
+		Pool::submit_and_watch(self, at, xt).map(|watcher| Box::new(watcher.into_stream()) as _)
+	}
+
+	fn ready(&self) -> Box<Iterator<Item=TransactionFor<B>>> {
+		Box::new(Pool::ready(self))
+	}
+
+	fn remove_invalid(&self, hashes: &[ExHash<B>]) -> Vec<TransactionFor<B>> {
+		Pool::remove_invalid(self, hashes)
+	}
+
+	fn maintain(
+		&self,
+		at: &BlockId<B::Block>,
+		parent: &BlockId<B::Block>,
+		extrinsics: &[ExtrinsicFor<B>],
+	) -> Result<(), B::Error> {
+		Pool::prune(self, at, parent, extrinsics)
+	}
+
+	fn revalidate(&self, at: &BlockId<B::Block>) -> Result<(), B::Error> {
+		Pool::revalidate(self, at)
+	}
+
+	fn status(&self) -> PoolStatus {
+		Pool::status(self)
+	}
+
+	fn metrics(&self) -> PoolMetrics {
+		Pool::metrics(self)
+	}
+
+	fn import_notification_stream(&self) -> EventStream {
+		Pool::import_notification_stream(self)
+	}
+
+	fn on_broadcasted(&self, propagated: HashMap<ExHash<B>, Vec<String>>) {
+		Pool::on_broadcasted(self, propagated)
+	}
+
+	fn hash_of(&self, xt: &ExtrinsicFor<B>) -> ExHash<B> {
+		Pool::hash_of(self, xt)
+	}
+}
+
 fn fire_events<H, H2, Ex>(
 	listener: &mut Listener<H, H2>,
 	imported: &base::Imported<H, Ex>,
@@ -397,6 +515,7 @@ mod tests {
 	use test_runtime::{Block, Extrinsic, Transfer, H256};
 	use assert_matches::assert_matches;
 	use crate::watcher;
+	use txpool_api::InPoolTransaction;
 
 	#[derive(Debug, Default)]
 	struct TestApi;
@@ -411,6 +530,11 @@ mod tests {
 			let block_number = self.block_id_to_number(at)?.unwrap();
 			let nonce = uxt.transfer().nonce;
 
+			// transfers of nothing can't be validated past the genesis block.
+			if uxt.transfer().amount == 0 && block_number > 0 {
+				bail!("Unable to validate a transfer of nothing");
+			}
+
 			if nonce < block_number {
 				Ok(TransactionValidity::Invalid(0))
 			} else {
@@ -471,6 +595,29 @@ mod tests {
 		assert_eq!(pool.ready().map(|v| v.hash).collect::<Vec<_>>(), vec![hash]);
 	}
 
+	#[test]
+	fn should_be_usable_through_transaction_pool_trait() {
+		fn submit<P: TransactionPool>(pool: &P, at: &BlockId<P::Block>, xt: txpool_api::ExtrinsicFor<P>) -> P::Hash {
+			pool.submit_one(at, xt).unwrap()
+		}
+
+		// given
+		let pool = pool();
+
+		// when
+		let hash = submit(&pool, &BlockId::Number(0), uxt(Transfer {
+			from: H256::from_low_u64_be(1),
+			to: H256::from_low_u64_be(2),
+			amount: 5,
+			nonce: 0,
+		}));
+
+		// then
+		let ready = TransactionPool::ready(&pool).map(|tx| *tx.hash()).collect::<Vec<_>>();
+		assert_eq!(ready, vec![hash]);
+		assert_eq!(TransactionPool::status(&pool).ready, 1);
+	}
+
 	#[test]
 	fn should_reject_if_temporarily_banned() {
 		// given
@@ -586,6 +733,75 @@ mod tests {
 		assert!(pool.rotator.is_banned(&hash1));
 	}
 
+	#[test]
+	fn should_revalidate_past_failing_transactions() {
+		// given
+		let pool = pool();
+		let transfer = |amount, nonce| uxt(Transfer {
+			from: H256::from_low_u64_be(1),
+			to: H256::from_low_u64_be(2),
+			amount,
+			nonce,
+		});
+		let invalid = pool.submit_one(&BlockId::Number(0), transfer(5, 0)).unwrap();
+		let failing = pool.submit_one(&BlockId::Number(0), transfer(0, 3)).unwrap();
+		pool.submit_one(&BlockId::Number(0), transfer(5, 5)).unwrap();
+		assert_eq!(pool.status().ready, 1);
+		assert_eq!(pool.status().future, 2);
+
+		// when
+		pool.revalidate(&BlockId::Number(1)).unwrap();
+
+		// then
+		assert_eq!(pool.status().ready, 0);
+		assert_eq!(pool.status().future, 2);
+		assert!(pool.rotator.is_banned(&invalid));
+		assert!(!pool.rotator.is_banned(&failing));
+	}
+
+	#[test]
+	fn should_track_metrics() {
+		// given
+		let pool = pool();
+		let xt = uxt(Transfer {
+			from: H256::from_low_u64_be(1),
+			to: H256::from_low_u64_be(2),
+			amount: 5,
+			nonce: 0,
+		});
+		let bytes = xt.encode().len();
+		let hash1 = pool.submit_one(&BlockId::Number(0), xt).unwrap();
+		assert_eq!(pool.status().ready_bytes, bytes);
+		assert_eq!(pool.status().future_bytes, 0);
+
+		// when
+		pool.prune_tags(&BlockId::Number(1), vec![vec![0]], vec![hash1.clone()]).unwrap();
+
+		// then
+		assert_eq!(pool.status().ready_bytes, 0);
+		assert_eq!(pool.metrics(), PoolMetrics {
+			// the pruned transaction is re-submitted, but rejected as banned
+			submitted: 2,
+			validated: 1,
+			invalid: 0,
+			pruned: 1,
+			// included transactions are banned as well, but are not counted
+			banned: 0,
+		});
+
+		// when
+		let hash2 = pool.submit_one(&BlockId::Number(0), uxt(Transfer {
+			from: H256::from_low_u64_be(1),
+			to: H256::from_low_u64_be(2),
+			amount: 5,
+			nonce: 1,
+		})).unwrap();
+		pool.remove_invalid(&[hash2]);
+
+		// then
+		assert_eq!(pool.metrics().banned, 1);
+	}
+
 	mod listener {
 		use super::*;
 
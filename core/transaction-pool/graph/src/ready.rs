@@ -110,6 +110,8 @@ pub struct ReadyTransactions<Hash: hash::Hash + Eq, Ex> {
 	ready: Arc<RwLock<HashMap<Hash, ReadyTx<Hash, Ex>>>>,
 	/// Best transactions that are ready to be included to the block without any other previous transaction.
 	best: BTreeSet<TransactionRef<Hash, Ex>>,
+	/// Sum of encoding lengths of the ready transactions
+	bytes: usize,
 }
 
 impl<Hash: hash::Hash + Eq, Ex> Default for ReadyTransactions<Hash, Ex> {
@@ -119,6 +121,7 @@ impl<Hash: hash::Hash + Eq, Ex> Default for ReadyTransactions<Hash, Ex> {
 			provided_tags: Default::default(),
 			ready: Default::default(),
 			best: Default::default(),
+			bytes: 0,
 		}
 	}
 }
@@ -195,6 +198,7 @@ impl<Hash: hash::Hash + Member + Serialize, Ex> ReadyTransactions<Hash, Ex> {
 		}
 
 		// insert to Ready
+		self.bytes += transaction.transaction.bytes;
 		ready.insert(hash, ReadyTx {
 			transaction,
 			unlocks: vec![],
@@ -249,6 +253,7 @@ impl<Hash: hash::Hash + Member + Serialize, Ex> ReadyTransactions<Hash, Ex> {
 
 				// remove from best
 				self.best.remove(&tx.transaction);
+				self.bytes -= tx.transaction.transaction.bytes;
 
 				// remove all transactions that the current one unlocks
 				to_remove.append(&mut tx.unlocks);
@@ -281,6 +286,7 @@ impl<Hash: hash::Hash + Member + Serialize, Ex> ReadyTransactions<Hash, Ex> {
 			if let Some(tx) = res {
 				let unlocks = tx.unlocks;
 				let tx = tx.transaction.transaction;
+				self.bytes -= tx.bytes;
 
 				// prune previous transactions as well
 				{
@@ -379,6 +385,7 @@ impl<Hash: hash::Hash + Member + Serialize, Ex> ReadyTransactions<Hash, Ex> {
 			let tx = self.ready.write().remove(&hash).expect(HASH_READY);
 			// check if this transaction provides stuff that is not provided by the new one.
 			let (mut unlocks, tx) = (tx.unlocks, tx.transaction.transaction);
+			self.bytes -= tx.bytes;
 			{
 				let invalidated = tx.provides
 					.iter()
@@ -401,6 +408,11 @@ impl<Hash: hash::Hash + Member + Serialize, Ex> ReadyTransactions<Hash, Ex> {
 		self.ready.read().len()
 	}
 
+	/// Returns sum of encoding lengths of all transactions in this queue.
+	pub fn bytes(&self) -> usize {
+		self.bytes
+	}
+
 }
 
 pub struct BestIterator<Hash, Ex> {
@@ -476,6 +488,7 @@ mod tests {
 	fn tx(id: u8) -> Transaction<u64, Vec<u8>> {
 		Transaction {
 			data: vec![id],
+			bytes: 1,
 			hash: id as u64,
 			priority: 1,
 			valid_till: 2,
@@ -534,6 +547,7 @@ mod tests {
 		tx4.provides = vec![];
 		let tx5 = Transaction {
 			data: vec![5],
+			bytes: 1,
 			hash: 5,
 			priority: 1,
 			valid_till: u64::max_value(),	// use the max_value() here for testing.
@@ -20,27 +20,8 @@ use futures::{
 	Stream,
 	sync::mpsc,
 };
-use serde_derive::{Serialize, Deserialize};
-
-/// Possible extrinsic status events
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub enum Status<H, H2> {
-	/// Extrinsic is part of the future queue.
-	Future,
-	/// Extrinsic is part of the ready queue.
-	Ready,
-	/// Extrinsic has been finalised in block with given hash.
-	Finalised(H2),
-	/// Some state change (perhaps another extrinsic was included) rendered this extrinsic invalid.
-	Usurped(H),
-	/// The extrinsic has been broadcast to the given peers.
-	Broadcast(Vec<String>),
-	/// Extrinsic has been dropped from the pool because of the limit.
-	Dropped,
-	/// Extrinsic was detected as invalid.
-	Invalid,
-}
+
+pub use txpool_api::TransactionStatus as Status;
 
 /// Extrinsic watcher.
 ///
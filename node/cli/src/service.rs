@@ -66,9 +66,9 @@ construct_service_factory! {
 		RuntimeApi = RuntimeApi,
 		NetworkProtocol = NodeProtocol { |config| Ok(NodeProtocol::new()) },
 		RuntimeDispatch = node_executor::Executor,
-		FullTransactionPoolApi = transaction_pool::ChainApi<client::Client<FullBackend<Self>, FullExecutor<Self>, Block, RuntimeApi>, Block>
+		FullTransactionPool = TransactionPool<transaction_pool::ChainApi<client::Client<FullBackend<Self>, FullExecutor<Self>, Block, RuntimeApi>, Block>>
 			{ |config, client| Ok(TransactionPool::new(config, transaction_pool::ChainApi::new(client))) },
-		LightTransactionPoolApi = transaction_pool::ChainApi<client::Client<LightBackend<Self>, LightExecutor<Self>, Block, RuntimeApi>, Block>
+		LightTransactionPool = TransactionPool<transaction_pool::ChainApi<client::Client<LightBackend<Self>, LightExecutor<Self>, Block, RuntimeApi>, Block>>
 			{ |config, client| Ok(TransactionPool::new(config, transaction_pool::ChainApi::new(client))) },
 		Genesis = GenesisConfig,
 		Configuration = NodeConfig<Self>,
@@ -102,6 +102,9 @@ construct_service_factory! {
 					info!("Running Grandpa session as Authority {}", key.public());
 				}
 
+				// allow the voter to be paused and resumed over RPC.
+				service.set_finality_voter(Arc::new(link_half.voter_handle()));
+
 				executor.spawn(grandpa::run_grandpa(
 					grandpa::Config {
 						local_key,
@@ -143,6 +146,7 @@ construct_service_factory! {
 					client,
 					NothingExtra,
 					config.custom.inherent_data_providers.clone(),
+					config.verification_workers,
 				).map_err(Into::into)
 			}},
 		LightImportQueue = AuraImportQueue<
@@ -158,6 +162,7 @@ construct_service_factory! {
 						client,
 						NothingExtra,
 						config.custom.inherent_data_providers.clone(),
+						config.verification_workers,
 					).map_err(Into::into)
 				}
 			},
@@ -432,18 +432,21 @@ mod tests {
 			assert_eq!(System::events(), vec![
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(0),
-					event: Event::system(system::Event::ExtrinsicSuccess)
+					event: Event::system(system::Event::ExtrinsicSuccess),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(1),
-					event: Event::indices(indices::RawEvent::NewAccountIndex(bob(), 2))
+					event: Event::indices(indices::RawEvent::NewAccountIndex(bob(), 2)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(1),
 					event: Event::balances(balances::RawEvent::NewAccount(
 						hex!["d7568e5f0a7eda67a82691ff379ac4bba4f9c9b859fe779b5d46363b61ad2db9"].into(),
 						69
-					))
+					)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(1),
@@ -452,23 +455,28 @@ mod tests {
 						hex!["d7568e5f0a7eda67a82691ff379ac4bba4f9c9b859fe779b5d46363b61ad2db9"].into(),
 						69,
 						0
-					))
+					)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(1),
-					event: Event::system(system::Event::ExtrinsicSuccess)
+					event: Event::system(system::Event::ExtrinsicSuccess),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
-					event: Event::treasury(treasury::RawEvent::Spending(0))
+					event: Event::treasury(treasury::RawEvent::Spending(0)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
-					event: Event::treasury(treasury::RawEvent::Burnt(0))
+					event: Event::treasury(treasury::RawEvent::Burnt(0)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
-					event: Event::treasury(treasury::RawEvent::Rollover(0))
+					event: Event::treasury(treasury::RawEvent::Rollover(0)),
+					topics: vec![],
 				}
 			]);
 		});
@@ -487,7 +495,8 @@ mod tests {
 			assert_eq!(System::events(), vec![
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(0),
-					event: Event::system(system::Event::ExtrinsicSuccess)
+					event: Event::system(system::Event::ExtrinsicSuccess),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(1),
@@ -498,11 +507,13 @@ mod tests {
 							5,
 							0
 						)
-					)
+					),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(1),
-					event: Event::system(system::Event::ExtrinsicSuccess)
+					event: Event::system(system::Event::ExtrinsicSuccess),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(2),
@@ -513,19 +524,23 @@ mod tests {
 							15,
 							0
 						)
-					)
+					),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::ApplyExtrinsic(2),
-					event: Event::system(system::Event::ExtrinsicSuccess)
+					event: Event::system(system::Event::ExtrinsicSuccess),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
-					event: Event::session(session::RawEvent::NewSession(1))
+					event: Event::session(session::RawEvent::NewSession(1)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
-					event: Event::staking(staking::RawEvent::Reward(0))
+					event: Event::staking(staking::RawEvent::Reward(0)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
@@ -534,18 +549,22 @@ mod tests {
 						(Keyring::Two.to_raw_public().into(), 1),
 						([3u8; 32].into(), 1),
 					])),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
-					event: Event::treasury(treasury::RawEvent::Spending(0))
+					event: Event::treasury(treasury::RawEvent::Spending(0)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
-					event: Event::treasury(treasury::RawEvent::Burnt(0))
+					event: Event::treasury(treasury::RawEvent::Burnt(0)),
+					topics: vec![],
 				},
 				EventRecord {
 					phase: Phase::Finalization,
-					event: Event::treasury(treasury::RawEvent::Rollover(0))
+					event: Event::treasury(treasury::RawEvent::Rollover(0)),
+					topics: vec![],
 				}
 			]);
 		});
@@ -352,6 +352,75 @@ pub struct ModuleMetadata {
 
 type ODFnA<T> = Option<DecodeDifferent<FnEncode<&'static [T]>, Vec<T>>>;
 
+/// Returns the unhashed storage key of the storage item `item` declared by the module with
+/// storage prefix `module`.
+///
+/// For map items `key` must be the encoded map key. The actual storage location is the
+/// `twox_128` hash of the returned bytes.
+pub fn storage_key_data(module: &str, item: &str, key: Option<&[u8]>) -> Vec<u8> {
+	let key = key.unwrap_or(&[]);
+	let mut data = Vec::with_capacity(module.len() + 1 + item.len() + key.len());
+	data.extend_from_slice(module.as_bytes());
+	data.push(b' ');
+	data.extend_from_slice(item.as_bytes());
+	data.extend_from_slice(key);
+	data
+}
+
+/// Returns the storage location of the storage item `item` declared by the module with
+/// storage prefix `module`. See `storage_key_data`.
+#[cfg(feature = "std")]
+pub fn storage_key(module: &str, item: &str, key: Option<&[u8]>) -> [u8; 16] {
+	primitives::twox_128(&storage_key_data(module, item, key))
+}
+
+#[cfg(feature = "std")]
+impl StorageFunctionMetadata {
+	/// Returns the storage location of this item for the module with storage prefix `module`.
+	///
+	/// `key` must be the encoded map key for map items and `None` for plain items, otherwise
+	/// `None` is returned.
+	pub fn storage_key(&self, module: &str, key: Option<&[u8]>) -> Option<[u8; 16]> {
+		match (&self.ty, key) {
+			(StorageFunctionType::Plain(_), None) | (StorageFunctionType::Map { .. }, Some(_)) =>
+				Some(storage_key(module, self.name.as_str(), key)),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl ModuleMetadata {
+	/// Returns the storage location of the storage item named `item` of this module.
+	///
+	/// Returns `None` if the module has no such item or if `key` does not match its kind,
+	/// see `StorageFunctionMetadata::storage_key`.
+	pub fn storage_key(&self, item: &str, key: Option<&[u8]>) -> Option<[u8; 16]> {
+		let prefix = match self.prefix {
+			DecodeDifferent::Encode(ref f) => (f.0)(),
+			DecodeDifferent::Decoded(ref s) => s.as_str(),
+		};
+		let functions = match self.storage {
+			Some(DecodeDifferent::Encode(ref f)) => (f.0)(),
+			Some(DecodeDifferent::Decoded(ref v)) => &v[..],
+			None => return None,
+		};
+		functions.iter()
+			.find(|function| function.name.as_str() == item)
+			.and_then(|function| function.storage_key(prefix, key))
+	}
+}
+
+#[cfg(feature = "std")]
+impl DecodeDifferentStr {
+	fn as_str(&self) -> &str {
+		match *self {
+			DecodeDifferent::Encode(s) => s,
+			DecodeDifferent::Decoded(ref s) => s.as_str(),
+		}
+	}
+}
+
 impl Into<primitives::OpaqueMetadata> for RuntimeMetadataPrefixed {
 	fn into(self) -> primitives::OpaqueMetadata {
 		primitives::OpaqueMetadata::new(self.encode())
@@ -458,4 +458,21 @@ mod tests {
 
 		assert_eq!(expected_metadata, metadata_decoded.unwrap());
 	}
+
+	#[test]
+	fn storage_keys_can_be_computed_from_metadata() {
+		let expected = ::runtime_io::twox_128(b"TestStorage StorageMethod");
+		assert_eq!(::storage::storage_key_for("TestStorage", "StorageMethod", None), expected);
+
+		let metadata_encoded = TestRuntime::metadata().encode();
+		let metadata = RuntimeMetadataPrefixed::decode(&mut &metadata_encoded[..]).unwrap();
+		let modules = match metadata.1 {
+			RuntimeMetadata::V1(RuntimeMetadataV1 { modules: DecodeDifferent::Decoded(modules) }) => modules,
+			_ => panic!("metadata is decoded"),
+		};
+		let module = modules.iter().find(|m| m.name == DecodeDifferent::Decoded("event_module2".into())).unwrap();
+		assert_eq!(module.storage_key("StorageMethod", None), Some(expected));
+		assert_eq!(module.storage_key("StorageMethod", Some(&[1u8][..])), None);
+		assert_eq!(module.storage_key("Unknown", None), None);
+	}
 }
@@ -149,6 +149,14 @@ impl ::GenericStorage for RuntimeStorage {
 	}
 }
 
+/// Returns the storage location of the storage item `item` declared by `decl_storage!` in a
+/// module with storage prefix `module` (the name after `as` in the `decl_storage!` header).
+///
+/// For map items `key` must be the encoded map key, for plain values it must be `None`.
+pub fn storage_key_for(module: &str, item: &str, key: Option<&[u8]>) -> [u8; 16] {
+	twox_128(&::srml_metadata::storage_key_data(module, item, key))
+}
+
 /// A trait for working with macro-generated storage values under the substrate storage API.
 pub trait StorageValue<T: Codec> {
 	/// The type that get/take return.
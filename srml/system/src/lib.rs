@@ -109,11 +109,7 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		/// Deposits an event onto this block's event record.
 		pub fn deposit_event(event: T::Event) {
-			let extrinsic_index = Self::extrinsic_index();
-			let phase = extrinsic_index.map_or(Phase::Finalization, |c| Phase::ApplyExtrinsic(c));
-			let mut events = Self::events();
-			events.push(EventRecord { phase, event });
-			<Events<T>>::put(events);
+			Self::deposit_event_indexed(&[], event);
 		}
 	}
 }
@@ -128,14 +124,19 @@ pub enum Phase {
 	Finalization,
 }
 
+/// Index of an event in a block's event record.
+pub type EventIndex = u32;
+
 /// Record of an event happening.
 #[derive(Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Serialize, PartialEq, Eq, Clone, Debug))]
-pub struct EventRecord<E: Parameter + Member> {
+pub struct EventRecord<E: Parameter + Member, T> {
 	/// The phase of the block it happened in.
 	pub phase: Phase,
 	/// The event itself.
 	pub event: E,
+	/// The list of the topics this event has.
+	pub topics: Vec<T>,
 }
 
 /// Event for the system module.
@@ -219,7 +220,12 @@ decl_storage! {
 		ExtrinsicsRoot get(extrinsics_root): T::Hash;
 		Digest get(digest): T::Digest;
 
-		Events get(events): Vec<EventRecord<T::Event>>;
+		Events get(events): Vec<EventRecord<T::Event, T::Hash>>;
+		/// Indices of this block's events that have the given topic, along with the block number.
+		///
+		/// Cleared at the start of each block, so the blocks containing events with a given topic
+		/// are the blocks in which this entry was changed to a non-empty value.
+		EventTopics get(event_topics): map T::Hash => Vec<(T::BlockNumber, EventIndex)>;
 	}
 	add_extra_genesis {
 		config(changes_trie_config): Option<ChangesTrieConfiguration>;
@@ -283,6 +289,23 @@ impl<T: Trait> Module<T> {
 		storage::unhashed::get(well_known_keys::EXTRINSIC_INDEX)
 	}
 
+	/// Deposits an event onto this block's event record, indexed under the given topics.
+	///
+	/// The index of the event is recorded in `EventTopics` for each topic, which lets clients
+	/// find the blocks containing events with a topic without scanning all of the events.
+	pub fn deposit_event_indexed(topics: &[T::Hash], event: T::Event) {
+		let extrinsic_index = Self::extrinsic_index();
+		let phase = extrinsic_index.map_or(Phase::Finalization, |c| Phase::ApplyExtrinsic(c));
+		let mut events = Self::events();
+		let event_index = events.len() as EventIndex;
+		let block_number = Self::block_number();
+		for topic in topics {
+			<EventTopics<T>>::mutate(topic, |indices| indices.push((block_number, event_index)));
+		}
+		events.push(EventRecord { phase, event, topics: topics.to_vec() });
+		<Events<T>>::put(events);
+	}
+
 	/// Start the execution of a particular block.
 	pub fn initialise(number: &T::BlockNumber, parent_hash: &T::Hash, txs_root: &T::Hash) {
 		// populate environment.
@@ -292,6 +315,9 @@ impl<T: Trait> Module<T> {
 		<BlockHash<T>>::insert(*number - One::one(), parent_hash);
 		<ExtrinsicsRoot<T>>::put(txs_root);
 		<RandomSeed<T>>::put(Self::calculate_random());
+		for topic in Self::events().into_iter().flat_map(|record| record.topics) {
+			<EventTopics<T>>::remove(topic);
+		}
 		<Events<T>>::kill();
 	}
 
@@ -496,7 +522,7 @@ mod tests {
 			System::note_finished_extrinsics();
 			System::deposit_event(1u16);
 			System::finalise();
-			assert_eq!(System::events(), vec![EventRecord { phase: Phase::Finalization, event: 1u16 }]);
+			assert_eq!(System::events(), vec![EventRecord { phase: Phase::Finalization, event: 1u16, topics: vec![] }]);
 
 			System::initialise(&2, &[0u8; 32].into(), &[0u8; 32].into());
 			System::deposit_event(42u16);
@@ -506,11 +532,39 @@ mod tests {
 			System::deposit_event(3u16);
 			System::finalise();
 			assert_eq!(System::events(), vec![
-				EventRecord { phase: Phase::ApplyExtrinsic(0), event: 42u16 },
-				EventRecord { phase: Phase::ApplyExtrinsic(0), event: 100u16 },
-				EventRecord { phase: Phase::ApplyExtrinsic(1), event: 101u16 },
-				EventRecord { phase: Phase::Finalization, event: 3u16 }
+				EventRecord { phase: Phase::ApplyExtrinsic(0), event: 42u16, topics: vec![] },
+				EventRecord { phase: Phase::ApplyExtrinsic(0), event: 100u16, topics: vec![] },
+				EventRecord { phase: Phase::ApplyExtrinsic(1), event: 101u16, topics: vec![] },
+				EventRecord { phase: Phase::Finalization, event: 3u16, topics: vec![] }
 			]);
 		});
 	}
+
+	#[test]
+	fn deposit_event_topics_should_work() {
+		with_externalities(&mut new_test_ext(), || {
+			let topics: Vec<H256> = vec![[1u8; 32].into(), [2u8; 32].into()];
+
+			System::initialise(&1, &[0u8; 32].into(), &[0u8; 32].into());
+			System::deposit_event_indexed(&topics[..1], 1u16);
+			System::deposit_event(2u16);
+			System::deposit_event_indexed(&topics, 3u16);
+			System::finalise();
+
+			assert_eq!(System::events()[2], EventRecord {
+				phase: Phase::ApplyExtrinsic(0),
+				event: 3u16,
+				topics: topics.clone(),
+			});
+			assert_eq!(System::event_topics(&topics[0]), vec![(1, 0), (1, 2)]);
+			assert_eq!(System::event_topics(&topics[1]), vec![(1, 2)]);
+
+			// the index only covers the events of the current block.
+			System::initialise(&2, &[0u8; 32].into(), &[0u8; 32].into());
+			System::deposit_event_indexed(&topics[1..], 4u16);
+			System::finalise();
+			assert_eq!(System::event_topics(&topics[0]), vec![]);
+			assert_eq!(System::event_topics(&topics[1]), vec![(2, 0)]);
+		});
+	}
 }